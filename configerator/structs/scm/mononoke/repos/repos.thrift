@@ -133,6 +133,13 @@ struct RawRepoConfig {
     44: optional RawBackupRepoConfig backup_config
     // Define parameters for repo scrub/walker jobs
     45: optional RawWalkerConfig walker_config
+    // Derived data types which must be derived for the changeset a bookmark
+    // points to before the warm bookmark cache serves the bookmark there.
+    // They must be enabled in derived_data_config.
+    46: optional list<string> warm_bookmark_cache_required_derived_data,
+    // Sparse profile that clients enable by default when cloning the repo,
+    // as a path in the repo.
+    47: optional string default_sparse_profile,
 }
 
 struct RawWalkerConfig {
@@ -153,6 +160,20 @@ struct RawBackupRepoConfig {
 
 struct RawRepoClientKnobs {
   1: bool allow_short_getpack_history,
+  // Limit how much each client identity can read from the repo
+  2: optional RawReadQuota read_quota,
+}
+
+// Read quota for each client identity. A client which has read more than
+// max_bytes or max_objects within the current window has its file, tree and
+// getbundle requests rejected until the window ends. At least one of the
+// limits must be set.
+struct RawReadQuota {
+  1: optional i64 max_bytes,
+  2: optional i64 max_objects,
+  3: i64 window_secs,
+  // If false, clients over quota are only logged to scuba
+  4: bool enforced,
 }
 
 struct RawDerivedDataConfig {
@@ -192,6 +213,10 @@ struct RawBlobstoreMultiplexed {
     // The number of components that must successfully `put` a blob before the
     // multiplex as a whole claims that it successfully `put` the blob
     6: optional i64 minimum_successful_writes,
+    // The number of components that must return the same value for a `get`
+    // (or report the blob present for `is_present`) before the multiplex as
+    // a whole returns it. Defaults to 1: the first component to answer wins.
+    7: optional i64 minimum_successful_reads,
 }
 struct RawBlobstoreManifoldWithTtl {
     1: string manifold_bucket,
@@ -212,6 +237,23 @@ struct RawBlobstoreS3 {
     3: string region_name,
     4: string endpoint,
 }
+struct RawBlobstoreGcs {
+    1: string bucket,
+    // Prepended to the keys of the blobs
+    2: optional string prefix,
+    // Service account key file. If unset, $GOOGLE_APPLICATION_CREDENTIALS is
+    // used.
+    3: optional string credentials_path,
+}
+struct RawBlobstoreHttp {
+    // URL blobs are read from, in which {key} is replaced by the key of the
+    // blob
+    1: string get_url,
+    // URL blobs are written to, if different from get_url
+    2: optional string put_url,
+    // Headers sent with every request, e.g. for authentication
+    3: optional map<string, string> headers,
+}
 
 // Configuration for a single blobstore. These are intended to be defined in a
 // separate blobstore.toml config file, and then referenced by name from a
@@ -231,6 +273,8 @@ union RawBlobstoreConfig {
     9: RawBlobstoreLogging logging,
     10: RawBlobstorePack pack,
     11: RawBlobstoreS3 s3,
+    12: RawBlobstoreGcs gcs,
+    13: RawBlobstoreHttp http,
 }
 
 // A write-mostly blobstore is one that is not read from in normal operation.
@@ -0,0 +1,14 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+CREATE TABLE blobimport_checkpoints (
+  repo_id INTEGER PRIMARY KEY,
+  last_imported_rev BIGINT NOT NULL,
+  last_imported_cs_id VARBINARY(32) NOT NULL,
+  bookmarks_imported BIT NOT NULL DEFAULT 0,
+  update_timestamp BIGINT NOT NULL
+);
@@ -0,0 +1,237 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use futures::compat::Future01CompatExt;
+use mercurial_revlog::revlog::RevIdx;
+use mononoke_types::{ChangesetId, RepositoryId, Timestamp};
+use sql::queries;
+use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
+use sql_ext::SqlConnections;
+
+/// How far an import of a repository got, so that an interrupted import can resume from there
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlobimportCheckpoint {
+    /// All the revisions up to and including this one are imported
+    pub last_imported_rev: RevIdx,
+    pub last_imported_cs_id: ChangesetId,
+    /// Whether bookmarks were imported after the last imported revision
+    pub bookmarks_imported: bool,
+    pub update_timestamp: Timestamp,
+}
+
+impl BlobimportCheckpoint {
+    /// Number of revisions to skip from the start of the revlog to resume the import
+    pub fn resume_skip(&self) -> usize {
+        self.last_imported_rev.as_u32() as usize + 1
+    }
+}
+
+pub struct SqlBlobimportCheckpoints {
+    connections: SqlConnections,
+}
+
+impl SqlConstruct for SqlBlobimportCheckpoints {
+    const LABEL: &'static str = "blobimport_checkpoints";
+
+    const CREATION_QUERY: &'static str =
+        include_str!("../schemas/sqlite-blobimport_checkpoints.sql");
+
+    fn from_sql_connections(connections: SqlConnections) -> Self {
+        Self { connections }
+    }
+}
+
+impl SqlConstructFromMetadataDatabaseConfig for SqlBlobimportCheckpoints {}
+
+impl SqlBlobimportCheckpoints {
+    pub async fn load(&self, repo_id: RepositoryId) -> Result<Option<BlobimportCheckpoint>, Error> {
+        let rows = SelectCheckpoint::query(&self.connections.read_master_connection, &repo_id)
+            .compat()
+            .await?;
+
+        Ok(rows.into_iter().next().map(|row| BlobimportCheckpoint {
+            last_imported_rev: RevIdx::from(row.0 as u32),
+            last_imported_cs_id: row.1,
+            bookmarks_imported: row.2,
+            update_timestamp: row.3,
+        }))
+    }
+
+    pub async fn save(
+        &self,
+        repo_id: RepositoryId,
+        checkpoint: &BlobimportCheckpoint,
+    ) -> Result<(), Error> {
+        ReplaceCheckpoint::query(
+            &self.connections.write_connection,
+            &[(
+                &repo_id,
+                &(checkpoint.last_imported_rev.as_u32() as u64),
+                &checkpoint.last_imported_cs_id,
+                &checkpoint.bookmarks_imported,
+                &checkpoint.update_timestamp,
+            )],
+        )
+        .compat()
+        .await?;
+        Ok(())
+    }
+
+    pub async fn mark_bookmarks_imported(&self, repo_id: RepositoryId) -> Result<(), Error> {
+        MarkBookmarksImported::query(
+            &self.connections.write_connection,
+            &repo_id,
+            &Timestamp::now(),
+        )
+        .compat()
+        .await?;
+        Ok(())
+    }
+}
+
+queries! {
+    read SelectCheckpoint(
+        repo_id: RepositoryId,
+    ) -> (u64, ChangesetId, bool, Timestamp) {
+        "SELECT last_imported_rev, last_imported_cs_id, bookmarks_imported, update_timestamp
+        FROM blobimport_checkpoints WHERE repo_id={repo_id}"
+    }
+
+    write ReplaceCheckpoint(
+        values: (
+            repo_id: RepositoryId,
+            last_imported_rev: u64,
+            last_imported_cs_id: ChangesetId,
+            bookmarks_imported: bool,
+            update_timestamp: Timestamp,
+        ),
+    ) {
+        none,
+        "REPLACE INTO blobimport_checkpoints
+         (repo_id, last_imported_rev, last_imported_cs_id, bookmarks_imported, update_timestamp)
+         VALUES {values}"
+    }
+
+    write MarkBookmarksImported(
+        repo_id: RepositoryId,
+        update_timestamp: Timestamp,
+    ) {
+        none,
+        "UPDATE blobimport_checkpoints
+        SET bookmarks_imported=1, update_timestamp={update_timestamp}
+        WHERE repo_id={repo_id}"
+    }
+}
+
+/// Changesets are uploaded concurrently, so they finish out of revision order. This keeps track
+/// of the last revision before which everything is imported, which is where it is safe to
+/// resume from.
+pub(crate) struct ImportProgress {
+    next_rev: RevIdx,
+    imported: BTreeMap<RevIdx, ChangesetId>,
+    last_contiguous: Option<(RevIdx, ChangesetId)>,
+}
+
+impl ImportProgress {
+    /// `first_rev` is the first revision of this import
+    pub(crate) fn new(first_rev: RevIdx) -> Self {
+        Self {
+            next_rev: first_rev,
+            imported: BTreeMap::new(),
+            last_contiguous: None,
+        }
+    }
+
+    /// Record an imported revision. Returns whether the last contiguous revision moved.
+    pub(crate) fn record(&mut self, rev: RevIdx, cs_id: ChangesetId) -> bool {
+        self.imported.insert(rev, cs_id);
+        let mut moved = false;
+        while let Some(cs_id) = self.imported.remove(&self.next_rev) {
+            self.last_contiguous = Some((self.next_rev, cs_id));
+            self.next_rev = self.next_rev.succ();
+            moved = true;
+        }
+        moved
+    }
+
+    pub(crate) fn last_contiguous(&self) -> Option<(RevIdx, ChangesetId)> {
+        self.last_contiguous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fbinit::FacebookInit;
+    use mononoke_types_mocks::changesetid::{ONES_CSID, THREES_CSID, TWOS_CSID};
+
+    #[fbinit::test]
+    async fn test_sql_roundtrip(_fb: FacebookInit) -> Result<(), Error> {
+        let checkpoints = SqlBlobimportCheckpoints::with_sqlite_in_memory()?;
+        let repo_id = RepositoryId::new(123);
+        assert_eq!(checkpoints.load(repo_id).await?, None);
+
+        let checkpoint = BlobimportCheckpoint {
+            last_imported_rev: RevIdx::from(41u32),
+            last_imported_cs_id: ONES_CSID,
+            bookmarks_imported: false,
+            update_timestamp: Timestamp::from_timestamp_secs(1000),
+        };
+        checkpoints.save(repo_id, &checkpoint).await?;
+        let loaded = checkpoints.load(repo_id).await?;
+        assert_eq!(loaded.as_ref(), Some(&checkpoint));
+        assert_eq!(checkpoint.resume_skip(), 42);
+
+        // Other repositories have their own checkpoints
+        assert_eq!(checkpoints.load(RepositoryId::new(124)).await?, None);
+
+        checkpoints.mark_bookmarks_imported(repo_id).await?;
+        let loaded = checkpoints
+            .load(repo_id)
+            .await?
+            .expect("checkpoint is saved");
+        assert!(loaded.bookmarks_imported);
+        assert_eq!(loaded.last_imported_rev, checkpoint.last_imported_rev);
+
+        // Saving again replaces the checkpoint
+        let checkpoint = BlobimportCheckpoint {
+            last_imported_rev: RevIdx::from(50u32),
+            last_imported_cs_id: TWOS_CSID,
+            bookmarks_imported: false,
+            update_timestamp: Timestamp::from_timestamp_secs(2000),
+        };
+        checkpoints.save(repo_id, &checkpoint).await?;
+        let loaded = checkpoints.load(repo_id).await?;
+        assert_eq!(loaded.as_ref(), Some(&checkpoint));
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_progress() {
+        let mut progress = ImportProgress::new(RevIdx::from(10u32));
+        assert_eq!(progress.last_contiguous(), None);
+
+        // Revision 10 is still missing
+        assert!(!progress.record(RevIdx::from(11u32), TWOS_CSID));
+        assert_eq!(progress.last_contiguous(), None);
+
+        assert!(progress.record(RevIdx::from(10u32), ONES_CSID));
+        assert_eq!(
+            progress.last_contiguous(),
+            Some((RevIdx::from(11u32), TWOS_CSID))
+        );
+
+        assert!(!progress.record(RevIdx::from(13u32), THREES_CSID));
+        assert_eq!(
+            progress.last_contiguous(),
+            Some((RevIdx::from(11u32), TWOS_CSID))
+        );
+    }
+}
@@ -9,6 +9,7 @@
 
 mod bookmark;
 mod changeset;
+mod checkpoint;
 mod concurrency;
 
 use std::cmp;
@@ -38,11 +39,13 @@ use context::CoreContext;
 use derived_data_utils::derive_data_for_csids;
 use mercurial_revlog::{revlog::RevIdx, RevlogRepo};
 use mercurial_types::{HgChangesetId, HgNodeHash};
-use mononoke_types::{ChangesetId, RepositoryId};
+use mononoke_types::{ChangesetId, RepositoryId, Timestamp};
 use synced_commit_mapping::{SyncedCommitMapping, SyncedCommitMappingEntry};
 
 use crate::changeset::UploadChangesets;
+use crate::checkpoint::ImportProgress;
 
+pub use crate::checkpoint::{BlobimportCheckpoint, SqlBlobimportCheckpoints};
 pub use consts::HIGHEST_IMPORTED_GEN_NUM;
 
 // What to do with bookmarks when blobimporting a repo
@@ -73,6 +76,8 @@ pub struct Blobimport<'a> {
     pub small_repo_id: Option<RepositoryId>,
     pub derived_data_types: Vec<String>,
     pub origin_repo: Option<BlobRepo>,
+    // If set, progress is saved there as the import goes, so that it can be resumed
+    pub checkpoints: Option<Arc<SqlBlobimportCheckpoints>>,
 }
 
 impl<'a> Blobimport<'a> {
@@ -97,6 +102,7 @@ impl<'a> Blobimport<'a> {
             small_repo_id,
             derived_data_types,
             origin_repo,
+            checkpoints,
         } = self;
 
         // Take refs to avoid `async move` blocks capturing data data
@@ -105,6 +111,7 @@ impl<'a> Blobimport<'a> {
         let globalrevs_store = &globalrevs_store;
         let synced_commit_mapping = &synced_commit_mapping;
         let derived_data_types = &derived_data_types;
+        let checkpoints = &checkpoints;
 
         let repo_id = blobrepo.get_repoid();
 
@@ -179,6 +186,12 @@ impl<'a> Blobimport<'a> {
         let (stale_bookmarks, mononoke_bookmarks) =
             future::try_join(stale_bookmarks_fut, mononoke_bookmarks_fut).await?;
 
+        // Only imports of consecutive revisions can be checkpointed
+        let mut progress = match changeset {
+            Some(_) => None,
+            None => Some(ImportProgress::new(RevIdx::from(skip.unwrap_or(0)))),
+        };
+
         let mut max_rev_and_bcs_id = None;
         while let Some(chunk_result) = upload_changesets.next().await {
             let chunk = chunk_result?;
@@ -191,6 +204,10 @@ impl<'a> Blobimport<'a> {
                 }
             }
 
+            let revs: Vec<_> = chunk
+                .iter()
+                .map(|(rev, cs)| (*rev, cs.get_changeset_id()))
+                .collect();
             let changesets: &Vec<_> = &chunk.into_iter().map(|(_, cs)| cs).collect();
 
             let synced_commit_mapping_work = async {
@@ -253,6 +270,23 @@ impl<'a> Blobimport<'a> {
                 derivation_work,
             )
             .await?;
+
+            if let (Some(checkpoints), Some(progress)) = (checkpoints, progress.as_mut()) {
+                let mut moved = false;
+                for (rev, cs_id) in revs {
+                    moved |= progress.record(rev, cs_id);
+                }
+                if let Some((rev, cs_id)) = progress.last_contiguous().filter(|_| moved) {
+                    let checkpoint = BlobimportCheckpoint {
+                        last_imported_rev: rev,
+                        last_imported_cs_id: cs_id,
+                        bookmarks_imported: false,
+                        update_timestamp: Timestamp::now(),
+                    };
+                    checkpoints.save(repo_id, &checkpoint).await?;
+                    debug!(ctx.logger(), "checkpointed revision {}", rev.as_u32());
+                }
+            }
         }
 
         info!(
@@ -278,7 +312,11 @@ impl<'a> Blobimport<'a> {
                     bookmark::get_bookmark_prefixer(prefix),
                 )
                 .compat()
-                .await?
+                .await?;
+
+                if let Some(checkpoints) = checkpoints {
+                    checkpoints.mark_bookmarks_imported(repo_id).await?;
+                }
             }
         };
 
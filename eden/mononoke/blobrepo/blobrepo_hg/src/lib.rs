@@ -14,7 +14,9 @@ pub use crate::bonsai_generation::create_bonsai_changeset_object;
 pub use crate::repo_commit::ChangesetHandle;
 pub use changeset_fetcher::ChangesetFetcher;
 // TODO: This is exported for testing - is this the right place for it?
-pub use crate::repo_commit::{check_case_conflicts, compute_changed_files, UploadEntries};
+pub use crate::repo_commit::{
+    check_case_conflicts, compute_changed_files, find_case_conflicts, UploadEntries,
+};
 pub mod errors {
     pub use blobrepo_errors::*;
 }
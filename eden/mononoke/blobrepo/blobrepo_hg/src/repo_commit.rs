@@ -568,6 +568,73 @@ pub async fn check_case_conflicts(
         }
     };
 
+    let (added, deleted) = diff_added_and_deleted(ctx, repo, parent_root_mf, child_root_mf).await?;
+
+    // Check if there any conflicts internal to the change being landed. Past this point, the
+    // conflicts we'll report are external (i.e. they are dependent on the parent commit).
+    if let Some(conflict) = mononoke_types::check_case_conflicts(added.iter()) {
+        return Err(ErrorKind::InternalCaseConflict(conflict.0, conflict.1).into());
+    }
+
+    let candidates = case_conflict_candidates(ctx, repo, parent_root_mf, &added).await?;
+
+    let files = added
+        .iter()
+        .chain(candidates.iter().filter(|c| !deleted.contains(c)));
+
+    if let Some((child, parent)) = mononoke_types::check_case_conflicts(files) {
+        return Err(ErrorKind::ExternalCaseConflict(child, parent).into());
+    }
+
+    Ok(())
+}
+
+/// Like `check_case_conflicts`, but finds all the case conflicts the new commit introduces
+/// rather than failing on the first one. In each pair, the second path was added by the new
+/// commit, and the first is the path of the parent, or another path added by the new commit,
+/// which it conflicts with.
+pub async fn find_case_conflicts(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    child_root_mf: HgManifestId,
+    parent_root_mf: Option<HgManifestId>,
+) -> Result<Vec<(MPath, MPath)>, Error> {
+    let parent_root_mf = match parent_root_mf {
+        Some(parent_root_mf) => parent_root_mf,
+        None => {
+            let paths = child_root_mf
+                .list_leaf_entries(ctx.clone(), repo.get_blobstore())
+                .map_ok(|(path, _)| path)
+                .try_collect::<Vec<_>>()
+                .await
+                .with_context(|| "Error loading manifest")?;
+            return Ok(mononoke_types::find_case_conflicts(&paths));
+        }
+    };
+
+    let (added, deleted) = diff_added_and_deleted(ctx, repo, parent_root_mf, child_root_mf).await?;
+    let candidates = case_conflict_candidates(ctx, repo, parent_root_mf, &added).await?;
+
+    // Existing paths go first, so that they are what added paths are reported to conflict with.
+    // Conflicts between existing paths were not introduced by this commit, so are left out.
+    let added_set: HashSet<_> = added.iter().collect();
+    let files = candidates
+        .iter()
+        .filter(|c| !deleted.contains(c) && !added_set.contains(c))
+        .chain(added.iter());
+    Ok(mononoke_types::find_case_conflicts(files)
+        .into_iter()
+        .filter(|(_, path)| added_set.contains(path))
+        .collect())
+}
+
+/// Paths added and deleted by the diff between two manifests.
+async fn diff_added_and_deleted(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    parent_root_mf: HgManifestId,
+    child_root_mf: HgManifestId,
+) -> Result<(Vec<MPath>, HashSet<MPath>), Error> {
     let mut added = Vec::new();
     let mut deleted = HashSet::new();
 
@@ -588,12 +655,17 @@ pub async fn check_case_conflicts(
         };
     }
 
-    // Check if there any conflicts internal to the change being landed. Past this point, the
-    // conflicts we'll report are external (i.e. they are dependent on the parent commit).
-    if let Some(conflict) = mononoke_types::check_case_conflicts(added.iter()) {
-        return Err(ErrorKind::InternalCaseConflict(conflict.0, conflict.1).into());
-    }
+    Ok((added, deleted))
+}
 
+/// Paths of the parent's manifest which may case conflict with `added`: those which are equal to
+/// one of them, or one of their parent directories, once lower cased.
+async fn case_conflict_candidates(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    parent_root_mf: HgManifestId,
+    added: &[MPath],
+) -> Result<Vec<MPath>, Error> {
     fn lowercase_mpath(e: &MPath) -> Option<Vec<String>> {
         e.into_iter().map(MPathElement::to_lowercase_utf8).collect()
     }
@@ -610,7 +682,7 @@ pub async fn check_case_conflicts(
 
     let path_tree = Arc::new(path_tree_builder.freeze());
 
-    let candidates = bounded_traversal::bounded_traversal_stream(
+    bounded_traversal::bounded_traversal_stream(
         256,
         Some((parent_root_mf, path_tree, None)),
         |(mf_id, path_tree, path)| async move {
@@ -642,17 +714,7 @@ pub async fn check_case_conflicts(
     .try_flatten()
     .try_collect::<Vec<_>>()
     .await
-    .with_context(|| "Error scanning for conflicting paths")?;
-
-    let files = added
-        .iter()
-        .chain(candidates.iter().filter(|c| !deleted.contains(c)));
-
-    if let Some((child, parent)) = mononoke_types::check_case_conflicts(files) {
-        return Err(ErrorKind::ExternalCaseConflict(child, parent).into());
-    }
-
-    Ok(())
+    .with_context(|| "Error scanning for conflicting paths")
 }
 
 #[derive(Default)]
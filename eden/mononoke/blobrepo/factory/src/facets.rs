@@ -0,0 +1,237 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::{Context, Error, Result};
+use blobrepo::BlobRepo;
+use blobrepo_errors::*;
+use blobstore::Blobstore;
+use blobstore_factory::{make_blobstore, make_metadata_sql_factory, MetadataSqlFactory};
+use bonsai_hg_mapping::{BonsaiHgMapping, CachingBonsaiHgMapping, SqlBonsaiHgMapping};
+use bookmarks::{BookmarkUpdateLog, Bookmarks, CachedBookmarks};
+use changesets::{CachingChangesets, Changesets, SqlChangesets};
+use dbbookmarks::SqlBookmarksBuilder;
+use futures::lock::Mutex;
+use futures_watchdog::WatchdogExt;
+use metaconfig_types::Redaction;
+use mononoke_types::RepositoryId;
+use repo_blobstore::{RepoBlobstore, RepoBlobstoreArgs};
+
+use crate::{
+    get_censored_scuba_builder, get_redacted_blobs, get_volatile_pool,
+    open_blobrepo_given_datasources, BlobrepoBuilder, Caching,
+};
+
+/// The components of a repo, each constructed the first time it is asked for and shared
+/// afterwards. Asking for the whole repo reuses the storage opened for the other components.
+pub struct BlobrepoFacets<'a> {
+    builder: BlobrepoBuilder<'a>,
+    blobstore: Mutex<Option<Arc<dyn Blobstore>>>,
+    sql_factory: Mutex<Option<MetadataSqlFactory>>,
+    repo_blobstore: Mutex<Option<RepoBlobstore>>,
+    changesets: Mutex<Option<Arc<dyn Changesets>>>,
+    bookmarks: Mutex<Option<(Arc<dyn Bookmarks>, Arc<dyn BookmarkUpdateLog>)>>,
+    bonsai_hg_mapping: Mutex<Option<Arc<dyn BonsaiHgMapping>>>,
+    repo: Mutex<Option<BlobRepo>>,
+}
+
+// Callers asking for the same component concurrently wait for the first one to construct it
+async fn get_or_init<T: Clone>(
+    cell: &Mutex<Option<T>>,
+    init: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let mut value = cell.lock().await;
+    if let Some(value) = &*value {
+        return Ok(value.clone());
+    }
+    let init = init.await?;
+    *value = Some(init.clone());
+    Ok(init)
+}
+
+impl<'a> BlobrepoFacets<'a> {
+    pub(crate) fn new(builder: BlobrepoBuilder<'a>) -> Self {
+        Self {
+            builder,
+            blobstore: Mutex::new(None),
+            sql_factory: Mutex::new(None),
+            repo_blobstore: Mutex::new(None),
+            changesets: Mutex::new(None),
+            bookmarks: Mutex::new(None),
+            bonsai_hg_mapping: Mutex::new(None),
+            repo: Mutex::new(None),
+        }
+    }
+
+    pub fn repo_id(&self) -> RepositoryId {
+        self.builder.repo_config.repoid
+    }
+
+    /// The blobstore of the storage config, without the redaction, repo prefix and caches that
+    /// the repo adds on top of it
+    pub async fn blobstore(&self) -> Result<Arc<dyn Blobstore>, Error> {
+        let builder = &self.builder;
+        get_or_init(&self.blobstore, async {
+            make_blobstore(
+                builder.fb,
+                builder.storage_config.blobstore.clone(),
+                &builder.mysql_options,
+                builder.readonly_storage,
+                &builder.blobstore_options,
+                builder.logger,
+                builder.config_store,
+            )
+            .watched(builder.logger)
+            .await
+        })
+        .await
+    }
+
+    async fn sql_factory(&self) -> Result<MetadataSqlFactory, Error> {
+        let builder = &self.builder;
+        get_or_init(&self.sql_factory, async {
+            make_metadata_sql_factory(
+                builder.fb,
+                builder.storage_config.metadata.clone(),
+                builder.mysql_options.clone(),
+                builder.readonly_storage,
+                builder.logger,
+            )
+            .watched(builder.logger)
+            .await
+        })
+        .await
+    }
+
+    /// The blobstore as seen through the repo, with redaction and the repo prefix
+    pub async fn repo_blobstore(&self) -> Result<RepoBlobstore, Error> {
+        let builder = &self.builder;
+        get_or_init(&self.repo_blobstore, async {
+            let blobstore = self.blobstore().await?;
+            // Only look at the metadata database if redaction needs it
+            let redacted_blobs = match builder.redaction {
+                Redaction::Enabled => {
+                    get_redacted_blobs(&self.sql_factory().await?, builder.redaction).await?
+                }
+                Redaction::Disabled => None,
+            };
+            let censored_scuba_builder =
+                get_censored_scuba_builder(builder.fb, builder.censored_scuba_params.clone())?;
+            let args = RepoBlobstoreArgs::new(
+                blobstore,
+                redacted_blobs,
+                builder.repo_config.repoid,
+                censored_scuba_builder,
+            );
+            Ok(args.repo_blobstore_clone())
+        })
+        .await
+    }
+
+    pub async fn changesets(&self) -> Result<Arc<dyn Changesets>, Error> {
+        let builder = &self.builder;
+        get_or_init(&self.changesets, async {
+            let changesets = self
+                .sql_factory()
+                .await?
+                .open::<SqlChangesets>()
+                .await
+                .context(ErrorKind::StateOpen(StateOpenError::Changesets))?;
+            let changesets: Arc<dyn Changesets> = match builder.caching {
+                Caching::Enabled(_) => Arc::new(CachingChangesets::new(
+                    builder.fb,
+                    Arc::new(changesets),
+                    get_volatile_pool("changesets")?,
+                )),
+                Caching::CachelibOnlyBlobstore(_) | Caching::Disabled => Arc::new(changesets),
+            };
+            Ok(changesets)
+        })
+        .await
+    }
+
+    async fn bookmarks_and_log(
+        &self,
+    ) -> Result<(Arc<dyn Bookmarks>, Arc<dyn BookmarkUpdateLog>), Error> {
+        let builder = &self.builder;
+        get_or_init(&self.bookmarks, async {
+            let repoid = builder.repo_config.repoid;
+            let sql_bookmarks = Arc::new(
+                self.sql_factory()
+                    .await?
+                    .open::<SqlBookmarksBuilder>()
+                    .await
+                    .context(ErrorKind::StateOpen(StateOpenError::Bookmarks))?
+                    .with_repo_id(repoid),
+            );
+            let bookmarks: Arc<dyn Bookmarks> = match builder.repo_config.bookmarks_cache_ttl {
+                Some(ttl) => Arc::new(CachedBookmarks::new(sql_bookmarks.clone(), ttl, repoid)),
+                None => sql_bookmarks.clone(),
+            };
+            Ok((bookmarks, sql_bookmarks as Arc<dyn BookmarkUpdateLog>))
+        })
+        .await
+    }
+
+    pub async fn bookmarks(&self) -> Result<Arc<dyn Bookmarks>, Error> {
+        Ok(self.bookmarks_and_log().await?.0)
+    }
+
+    pub async fn bookmark_update_log(&self) -> Result<Arc<dyn BookmarkUpdateLog>, Error> {
+        Ok(self.bookmarks_and_log().await?.1)
+    }
+
+    pub async fn bonsai_hg_mapping(&self) -> Result<Arc<dyn BonsaiHgMapping>, Error> {
+        let builder = &self.builder;
+        get_or_init(&self.bonsai_hg_mapping, async {
+            let mapping = self
+                .sql_factory()
+                .await?
+                .open::<SqlBonsaiHgMapping>()
+                .await
+                .context(ErrorKind::StateOpen(StateOpenError::BonsaiHgMapping))?;
+            let mapping: Arc<dyn BonsaiHgMapping> = match builder.caching {
+                Caching::Enabled(_) => Arc::new(CachingBonsaiHgMapping::new(
+                    builder.fb,
+                    Arc::new(mapping),
+                    get_volatile_pool("bonsai_hg_mapping")?,
+                )),
+                Caching::CachelibOnlyBlobstore(_) | Caching::Disabled => Arc::new(mapping),
+            };
+            Ok(mapping)
+        })
+        .await
+    }
+
+    /// The whole repo, as built by `BlobrepoBuilder::build`. Its components are constructed
+    /// afresh, except for the underlying storage.
+    pub async fn repo(&self) -> Result<BlobRepo, Error> {
+        let builder = &self.builder;
+        get_or_init(&self.repo, async {
+            let (blobstore, sql_factory) =
+                futures::future::try_join(self.blobstore(), self.sql_factory()).await?;
+            open_blobrepo_given_datasources(
+                builder.fb,
+                blobstore,
+                &sql_factory,
+                &builder.repo_config,
+                builder.caching,
+                builder.redaction,
+                builder.censored_scuba_params.clone(),
+                builder.readonly_storage,
+                builder.reponame.clone(),
+                builder.blobstore_options.cachelib_options,
+                builder.logger,
+            )
+            .watched(builder.logger)
+            .await
+        })
+        .await
+    }
+}
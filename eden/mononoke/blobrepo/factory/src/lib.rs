@@ -5,6 +5,8 @@
  * GNU General Public License version 2.
  */
 
+mod facets;
+
 use anyhow::{Context, Error, Result};
 use blame::BlameRoot;
 use blobrepo::BlobRepo;
@@ -71,6 +73,7 @@ use type_map::TypeMap;
 use unodes::RootUnodeManifestId;
 use virtually_sharded_blobstore::VirtuallyShardedBlobstore;
 
+pub use crate::facets::BlobrepoFacets;
 pub use blobstore_factory::{BlobstoreOptions, PutBehaviour, ReadOnlyStorage};
 
 #[derive(Copy, Clone, PartialEq)]
@@ -89,7 +92,8 @@ pub struct BlobrepoBuilder<'a> {
     fb: FacebookInit,
     reponame: String,
     storage_config: StorageConfig,
-    mysql_options: &'a MysqlOptions,
+    // Owned, so that facets can outlive the options they were built from
+    mysql_options: MysqlOptions,
     caching: Caching,
     redaction: Redaction,
     censored_scuba_params: CensoredScubaParams,
@@ -105,7 +109,7 @@ impl<'a> BlobrepoBuilder<'a> {
         fb: FacebookInit,
         reponame: String,
         config: &RepoConfig,
-        mysql_options: &'a MysqlOptions,
+        mysql_options: &MysqlOptions,
         caching: Caching,
         censored_scuba_params: CensoredScubaParams,
         readonly_storage: ReadOnlyStorage,
@@ -117,7 +121,7 @@ impl<'a> BlobrepoBuilder<'a> {
             fb,
             reponame,
             storage_config: config.storage_config.clone(),
-            mysql_options,
+            mysql_options: mysql_options.clone(),
             caching,
             redaction: config.redaction.clone(),
             censored_scuba_params,
@@ -133,6 +137,13 @@ impl<'a> BlobrepoBuilder<'a> {
         self.redaction = redaction;
     }
 
+    /// Rather than building the whole repo, give access to its components, which are only
+    /// constructed when first asked for. Tools which only need a few of them start faster, and
+    /// don't need the storage of the others to be reachable.
+    pub fn build_facets(self) -> BlobrepoFacets<'a> {
+        BlobrepoFacets::new(self)
+    }
+
     /// remote (ie, MySQL), then it configures a full set of caches. Otherwise with local storage
     /// it's assumed to be a test configuration.
     ///
@@ -194,18 +205,7 @@ pub async fn open_blobrepo_given_datasources<'a>(
     cachelib_options: CachelibBlobstoreOptions,
     logger: &'a Logger,
 ) -> Result<BlobRepo, Error> {
-    let redacted_blobs = match redaction {
-        Redaction::Enabled => {
-            let redacted_blobs = sql_factory
-                .open::<SqlRedactedContentStore>()
-                .await?
-                .get_all_redacted_blobs()
-                .compat()
-                .await?;
-            Some(redacted_blobs)
-        }
-        Redaction::Disabled => None,
-    };
+    let redacted_blobs = get_redacted_blobs(sql_factory, redaction).await?;
 
     let filestore_config = repo_config
         .filestore
@@ -269,6 +269,24 @@ pub async fn open_blobrepo_given_datasources<'a>(
     Ok(repo)
 }
 
+async fn get_redacted_blobs(
+    sql_factory: &MetadataSqlFactory,
+    redaction: Redaction,
+) -> Result<Option<HashMap<String, RedactedMetadata>>, Error> {
+    match redaction {
+        Redaction::Enabled => {
+            let redacted_blobs = sql_factory
+                .open::<SqlRedactedContentStore>()
+                .await?
+                .get_all_redacted_blobs()
+                .compat()
+                .await?;
+            Ok(Some(redacted_blobs))
+        }
+        Redaction::Disabled => Ok(None),
+    }
+}
+
 /// A helper to build test repositories.
 #[derive(Clone)]
 pub struct TestRepoBuilder {
@@ -0,0 +1,211 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Accounting of blobstore traffic by key family. Each key is classified by its prefix (e.g.
+//! `hgchangeset.`, `content.`, `fsnode.`) and the number of reads and writes, and the bytes read
+//! and written, are counted per family, which shows which kinds of data drive blobstore growth.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use stats::prelude::*;
+
+use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use context::CoreContext;
+use mononoke_types::{BlobstoreBytes, REPO_PREFIX_REGEX};
+
+define_stats! {
+    prefix = "mononoke.blobstore.accounting";
+    gets: dynamic_timeseries("{}.gets", (family: &'static str); Rate, Sum),
+    get_misses: dynamic_timeseries("{}.get_misses", (family: &'static str); Rate, Sum),
+    get_bytes: dynamic_timeseries("{}.get_bytes", (family: &'static str); Rate, Sum),
+    presence_checks: dynamic_timeseries("{}.presence_checks", (family: &'static str); Rate, Sum),
+    puts: dynamic_timeseries("{}.puts", (family: &'static str); Rate, Sum),
+    put_bytes: dynamic_timeseries("{}.put_bytes", (family: &'static str); Rate, Sum),
+    new_puts: dynamic_timeseries("{}.new_puts", (family: &'static str); Rate, Sum),
+    new_put_bytes: dynamic_timeseries("{}.new_put_bytes", (family: &'static str); Rate, Sum),
+}
+
+/// Family of keys which don't match any of the known prefixes
+pub const OTHER_FAMILY: &str = "other";
+
+/// First component of a key, once the repo prefix is removed, and the family it is accounted to.
+/// Derived data roots are accounted with the data they point to.
+const KEY_FAMILIES: &[(&str, &str)] = &[
+    ("hgchangeset", "hgchangeset"),
+    ("hgmanifest", "hgmanifest"),
+    ("hgfilenode", "hgfilenode"),
+    ("filenode_lookup", "hgfilenode"),
+    ("changeset", "changeset"),
+    ("rawbundle2", "rawbundle2"),
+    ("content", "content"),
+    ("chunk", "content"),
+    ("content_metadata", "content_metadata"),
+    ("alias", "alias"),
+    ("fileunode", "unode"),
+    ("manifestunode", "unode"),
+    ("derived_root_unode", "unode"),
+    ("derived_root_unode_v2", "unode"),
+    ("fsnode", "fsnode"),
+    ("derived_root_fsnode", "fsnode"),
+    ("skeletonmanifest", "skeleton_manifest"),
+    ("derived_root_skeletonmanifest", "skeleton_manifest"),
+    ("deletedmanifest", "deleted_manifest"),
+    ("derived_root_deleted_manifest", "deleted_manifest"),
+    ("fastlogbatch", "fastlog"),
+    ("derived_rootfastlog", "fastlog"),
+    ("blame", "blame"),
+    ("changeset_info", "changeset_info"),
+    ("git", "git"),
+];
+
+/// Family a blobstore key is accounted to, or `OTHER_FAMILY` if it's not a known kind of key.
+/// The families are a fixed set, so that the number of counters stays bounded.
+pub fn key_family(key: &str) -> &'static str {
+    let key = match REPO_PREFIX_REGEX.find(key) {
+        Some(m) => &key[m.end()..],
+        None => key,
+    };
+    let first = key.split('.').next().unwrap_or(key);
+    KEY_FAMILIES
+        .iter()
+        .find(|(prefix, _)| *prefix == first)
+        .map_or(OTHER_FAMILY, |(_, family)| family)
+}
+
+/// A blobstore which counts reads and writes, and the bytes read and written, per key family
+#[derive(Debug)]
+pub struct AccountingBlob<B> {
+    inner: B,
+}
+
+impl<B> AccountingBlob<B> {
+    pub fn new(inner: B) -> Self {
+        Self { inner }
+    }
+
+    pub fn as_inner(&self) -> &B {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<B: Blobstore + BlobstorePutOps> Blobstore for AccountingBlob<B> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let family = key_family(key);
+        let result = self.inner.get(ctx, key).await;
+        if let Ok(value) = &result {
+            STATS::gets.add_value(1, (family,));
+            match value {
+                Some(value) => STATS::get_bytes.add_value(value.as_bytes().len() as i64, (family,)),
+                None => STATS::get_misses.add_value(1, (family,)),
+            }
+        }
+        result
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        STATS::presence_checks.add_value(1, (key_family(key),));
+        self.inner.is_present(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+fn record_put(family: &'static str, size: usize, status: OverwriteStatus) {
+    STATS::puts.add_value(1, (family,));
+    STATS::put_bytes.add_value(size as i64, (family,));
+    // Only puts of keys which didn't exist before grow the blobstore. Puts to stores which don't
+    // check whether the key existed are accounted as growth.
+    if status == OverwriteStatus::New || status == OverwriteStatus::NotChecked {
+        STATS::new_puts.add_value(1, (family,));
+        STATS::new_put_bytes.add_value(size as i64, (family,));
+    }
+}
+
+#[async_trait]
+impl<B: BlobstorePutOps> BlobstorePutOps for AccountingBlob<B> {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        let family = key_family(&key);
+        let size = value.len();
+        let status = self
+            .inner
+            .put_explicit(ctx, key, value, put_behaviour)
+            .await?;
+        record_put(family, size, status);
+        Ok(status)
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        let family = key_family(&key);
+        let size = value.len();
+        let status = self.inner.put_with_status(ctx, key, value).await?;
+        record_put(family, size, status);
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_family() {
+        assert_eq!(
+            key_family("repo0000.hgchangeset.sha1.0123456789abcdef0123456789abcdef01234567"),
+            "hgchangeset"
+        );
+        assert_eq!(key_family("repo1234.hgmanifest.sha1.abc"), "hgmanifest");
+        assert_eq!(key_family("repo0000.filenode_lookup.abc"), "hgfilenode");
+        assert_eq!(key_family("repo0000.content.blake2.abc"), "content");
+        assert_eq!(key_family("repo0000.chunk.blake2.abc"), "content");
+        assert_eq!(
+            key_family("repo0000.content_metadata.blake2.abc"),
+            "content_metadata"
+        );
+        assert_eq!(key_family("repo0000.alias.sha256.abc"), "alias");
+        assert_eq!(key_family("repo0000.changeset.blake2.abc"), "changeset");
+        assert_eq!(key_family("repo0000.derived_root_unode_v2.abc"), "unode");
+        assert_eq!(key_family("repo0000.manifestunode.blake2.abc"), "unode");
+        assert_eq!(key_family("repo0000.derived_root_fsnode.abc"), "fsnode");
+        assert_eq!(key_family("repo0000.blame.fileunode.blake2.abc"), "blame");
+        assert_eq!(key_family("repo0000.git.tree.abc"), "git");
+
+        // Keys without a repo prefix are classified too
+        assert_eq!(key_family("content.blake2.abc"), "content");
+
+        assert_eq!(key_family("repo0000.unknown.abc"), OTHER_FAMILY);
+        assert_eq!(key_family("repo0000."), OTHER_FAMILY);
+        assert_eq!(key_family(""), OTHER_FAMILY);
+        // Only the first component is looked at
+        assert_eq!(key_family("repo0000.foo.content.abc"), OTHER_FAMILY);
+    }
+}
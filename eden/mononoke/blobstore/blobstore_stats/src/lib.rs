@@ -14,7 +14,7 @@ use scuba_ext::{MononokeScubaSampleBuilder, ScubaValue};
 use time_ext::DurationExt;
 
 use blobstore::{BlobstoreGetData, OverwriteStatus};
-use context::PerfCounters;
+use context::{CoreContext, PerfCounters, REQUEST_ID};
 use metaconfig_types::BlobstoreId;
 use tunables::tunables;
 
@@ -52,7 +52,7 @@ fn add_common_values(
     scuba: &mut MononokeScubaSampleBuilder,
     pc: &PerfCounters,
     key: &str,
-    session: &str,
+    ctx: &CoreContext,
     stats: FutureStats,
     operation: OperationType,
     blobstore_id: Option<BlobstoreId>,
@@ -68,8 +68,12 @@ fn add_common_values(
         scuba.add(BLOBSTORE_ID, blobstore_id);
     }
 
+    if let Some(request_id) = ctx.request_id() {
+        scuba.add(REQUEST_ID, request_id.as_str());
+    }
+
     if stats.completion_time >= SLOW_REQUEST_THRESHOLD {
-        scuba.add(SESSION, session);
+        scuba.add(SESSION, ctx.metadata().session_id().as_str());
     }
 }
 
@@ -79,11 +83,11 @@ pub fn record_get_stats(
     stats: FutureStats,
     result: Result<&Option<BlobstoreGetData>, &Error>,
     key: &str,
-    session: &str,
+    ctx: &CoreContext,
     operation: OperationType,
     blobstore_id: Option<BlobstoreId>,
 ) {
-    add_common_values(scuba, pc, key, session, stats, operation, blobstore_id);
+    add_common_values(scuba, pc, key, ctx, stats, operation, blobstore_id);
 
     match result {
         Ok(Some(data)) => {
@@ -111,13 +115,13 @@ pub fn record_put_stats(
     stats: FutureStats,
     result: Result<&OverwriteStatus, &Error>,
     key: &str,
-    session: &str,
+    ctx: &CoreContext,
     operation: OperationType,
     size: usize,
     blobstore_id: Option<BlobstoreId>,
     write_order: Option<usize>,
 ) {
-    add_common_values(scuba, pc, key, session, stats, operation, blobstore_id);
+    add_common_values(scuba, pc, key, ctx, stats, operation, blobstore_id);
     scuba.add(SIZE, size);
 
     match result {
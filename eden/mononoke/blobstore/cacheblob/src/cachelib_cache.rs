@@ -10,8 +10,10 @@ use blobstore::{Blobstore, BlobstoreGetData, CountedBlobstore};
 use bytes::Bytes;
 use cachelib::LruCachePool;
 use context::PerfCounterType;
+use std::convert::TryInto;
 use std::fmt;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::dummy::DummyLease;
 use crate::in_process_lease::InProcessLease;
@@ -19,6 +21,11 @@ use crate::locking_cache::CacheBlobstore;
 use crate::locking_cache::CacheOps;
 
 const MAX_CACHELIB_VALUE_SIZE: u64 = 4 * 1024 * 1024;
+// Values of the presence pool: either the key is present, or it was absent from the backing store.
+// This is followed by a big endian unix time in milliseconds: when the key was seen present, or
+// when its absence stops being trusted.
+const PRESENT: u8 = b'P';
+const ABSENT: u8 = b'A';
 
 #[derive(Clone, Copy, Debug)]
 pub struct CachelibBlobstoreOptions {
@@ -26,6 +33,11 @@ pub struct CachelibBlobstoreOptions {
     pub attempt_zstd: bool,
     // Whether to wait for cache write before returning. Usually false apart from tests.
     pub lazy_cache_put: bool,
+    // If Some, keys missing from the backing store are remembered as missing for this long, so
+    // that repeated lookups of them don't go to the backing store. Writes through this cache
+    // make them present again straight away, but writes from elsewhere are only seen once it
+    // expires, so it should be short.
+    pub absent_ttl: Option<Duration>,
 }
 
 impl CachelibBlobstoreOptions {
@@ -33,14 +45,20 @@ impl CachelibBlobstoreOptions {
         Self {
             attempt_zstd: attempt_zstd.unwrap_or(true),
             lazy_cache_put: true,
+            absent_ttl: None,
         }
     }
     pub fn new_eager(attempt_zstd: Option<bool>) -> Self {
         Self {
             attempt_zstd: attempt_zstd.unwrap_or(true),
             lazy_cache_put: false,
+            absent_ttl: None,
         }
     }
+
+    pub fn with_absent_ttl(self, absent_ttl: Option<Duration>) -> Self {
+        Self { absent_ttl, ..self }
+    }
 }
 
 impl Default for CachelibBlobstoreOptions {
@@ -55,6 +73,9 @@ pub struct CachelibOps {
     blob_pool: Arc<LruCachePool>,
     presence_pool: Arc<LruCachePool>,
     options: CachelibBlobstoreOptions,
+    // Held while updating the presence pool, so that a key isn't recorded absent in between
+    // checking it wasn't written since it was looked up and setting it
+    presence_lock: Arc<Mutex<()>>,
 }
 
 impl CachelibOps {
@@ -67,8 +88,28 @@ impl CachelibOps {
             blob_pool,
             presence_pool,
             options,
+            presence_lock: Arc::new(Mutex::new(())),
         }
     }
+
+    /// The presence pool entry of `key`, split in its kind and time
+    fn get_presence(&self, key: &str) -> Option<(u8, u64)> {
+        let presence = self.presence_pool.get(key).ok()??;
+        let (&kind, time) = presence.split_first()?;
+        Some((kind, u64::from_be_bytes(time.try_into().ok()?)))
+    }
+
+    fn set_presence(&self, key: &str, kind: u8, time: u64) {
+        let mut presence = vec![kind];
+        presence.extend_from_slice(&time.to_be_bytes());
+        // A failure to set presence is considered fine, here.
+        let _ = self.presence_pool.set(key, Bytes::from(presence));
+    }
+
+    fn set_present(&self, key: &str) {
+        let _lock = self.presence_lock.lock().expect("lock poisoned");
+        self.set_presence(key, PRESENT, now_millis());
+    }
 }
 
 pub fn new_cachelib_blobstore_no_lease<T>(
@@ -120,8 +161,7 @@ impl CacheOps for CachelibOps {
     }
 
     async fn put(&self, key: &str, value: BlobstoreGetData) {
-        // A failure to set presence is considered fine, here.
-        let _ = self.presence_pool.set(key, Bytes::from(b"P".as_ref()));
+        self.set_present(key);
 
         let encode_limit = if self.options.attempt_zstd {
             Some(MAX_CACHELIB_VALUE_SIZE)
@@ -138,10 +178,8 @@ impl CacheOps for CachelibOps {
     /// otherwise (Empty or Leased states).
     async fn check_present(&self, key: &str) -> bool {
         let presence_pool = self
-            .presence_pool
-            .get(key)
-            .map(|opt| opt.is_some())
-            .unwrap_or(false);
+            .get_presence(key)
+            .map_or(false, |(kind, _)| kind == PRESENT);
         let blob_pool = self
             .blob_pool
             .get(key)
@@ -150,6 +188,46 @@ impl CacheOps for CachelibOps {
 
         presence_pool || blob_pool
     }
+
+    async fn check_absent(&self, key: &str) -> bool {
+        if self.options.absent_ttl.is_none() {
+            return false;
+        }
+        match self.get_presence(key) {
+            Some((ABSENT, expiry)) => expiry > now_millis(),
+            _ => false,
+        }
+    }
+
+    async fn put_absent(&self, key: &str, looked_up_at: SystemTime) {
+        if let Some(absent_ttl) = self.options.absent_ttl {
+            let _lock = self.presence_lock.lock().expect("lock poisoned");
+            // The key was written, or found, since the lookup which didn't find it
+            if let Some((PRESENT, seen)) = self.get_presence(key) {
+                if seen >= millis_since_epoch(looked_up_at) {
+                    return;
+                }
+            }
+            let expiry = now_millis().saturating_add(absent_ttl.as_millis() as u64);
+            self.set_presence(key, ABSENT, expiry);
+        }
+    }
+
+    async fn forget_absent(&self, key: &str) {
+        // Only called once the backing store has the key, so it is now present
+        if self.options.absent_ttl.is_some() {
+            self.set_present(key);
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    millis_since_epoch(SystemTime::now())
+}
+
+fn millis_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_millis() as u64)
 }
 
 impl fmt::Debug for CachelibOps {
@@ -17,6 +17,7 @@ use prefixblob::PrefixBlobstore;
 use redactedblobstore::{config::GET_OPERATION, RedactedBlobstore};
 use stats::prelude::*;
 use std::fmt;
+use std::time::SystemTime;
 
 define_stats! {
     prefix = "mononoke.blobstore.cacheblob";
@@ -24,6 +25,7 @@ define_stats! {
     get_hit: dynamic_timeseries("{}.get_hit", (cache_name: &'static str); Rate, Sum),
     presence_hit: dynamic_timeseries("{}.presence_hit", (cache_name: &'static str); Rate, Sum),
     presence_miss: dynamic_timeseries("{}.presence_miss", (cache_name: &'static str); Rate, Sum),
+    absent_hit: dynamic_timeseries("{}.absent_hit", (cache_name: &'static str); Rate, Sum),
 }
 
 /// Extra operations that can be performed on a cache. Other wrappers can implement this trait for
@@ -58,6 +60,9 @@ pub trait CacheBlobstoreExt: Blobstore {
 ///    but does not have a copy of the blob.
 /// 4. Known, implying that the cache has a copy of the blob for this key.
 ///
+/// Caches may also remember for a while that the backing store had no entry for a key, which is
+/// a hint rather than a state: it can be stale if the key was written through another cache.
+///
 /// When the cache engages in eviction, it demotes entries according to the following plan:
 /// Present and Leased can only demote to Empty.
 /// Known can demote to Present or Empty.
@@ -82,6 +87,20 @@ pub trait CacheOps: fmt::Debug + Send + Sync {
     /// `true` if there is definitely a value (i.e. cache entry in Present or Known state), `false`
     /// otherwise (Empty or Leased states).
     async fn check_present(&self, key: &str) -> bool;
+
+    /// Ask the cache if it recently saw that the backing store has no value for this key.
+    /// Caches which don't remember absent keys always return `false`.
+    async fn check_absent(&self, _key: &str) -> bool {
+        false
+    }
+
+    /// Tell the cache that the backing store had no value for this `key` when it was looked up at
+    /// `looked_up_at`. The key must not be recorded absent if it was written since then.
+    async fn put_absent(&self, _key: &str, _looked_up_at: SystemTime) {}
+
+    /// Tell the cache that the backing store now has a value for this `key`, so that it is no
+    /// longer reported absent. This must take effect before it returns.
+    async fn forget_absent(&self, _key: &str) {}
 }
 
 /// The operations a cache must provide to take part in the update lease protocol. This reduces the
@@ -145,6 +164,20 @@ where
         }
     }
 
+    async fn put_absent(&self, key: &str, looked_up_at: SystemTime)
+    where
+        C: 'static,
+    {
+        let key = key.to_owned();
+        cloned!(self.cache);
+        let cache_put = async move { cache.put_absent(&key, looked_up_at).await };
+        if self.lazy_cache_put {
+            tokio::spawn(cache_put);
+        } else {
+            cache_put.await;
+        }
+    }
+
     fn take_put_lease<'a>(&'a self, key: &'a str) -> BoxFuture<'a, bool> {
         async move {
             if self.lease.try_add_put_lease(key).await.map_err(|_| ()) == Ok(true) {
@@ -179,16 +212,22 @@ where
             }
             STATS::get_hit.add_value(1, (C::CACHE_NAME,));
             Ok(blob)
+        } else if self.cache.check_absent(key).await {
+            STATS::absent_hit.add_value(1, (C::CACHE_NAME,));
+            Ok(None)
         } else {
             if let Some(counter) = C::MISS_COUNTER {
                 ctx.perf_counters().increment_counter(counter);
             }
             STATS::get_miss.add_value(1, (C::CACHE_NAME,));
+            let looked_up_at = SystemTime::now();
             let blob = self.blobstore.get(ctx, key).await?;
             if let Some(ref blob) = blob {
                 let key = key.to_owned();
                 cloned!(self.cache, blob);
                 tokio::spawn(async move { cache.put(&key, blob).await });
+            } else {
+                self.put_absent(key, looked_up_at).await;
             }
             Ok(blob)
         }
@@ -203,6 +242,7 @@ where
         let can_put = self.take_put_lease(&key).await;
         if can_put {
             self.blobstore.put(ctx, key.clone(), value.clone()).await?;
+            self.cache.forget_absent(&key).await;
 
             cloned!(self.cache, self.lease);
             let cache_put = async move {
@@ -223,9 +263,17 @@ where
         if present {
             STATS::presence_hit.add_value(1, (C::CACHE_NAME,));
             Ok(true)
+        } else if self.cache.check_absent(key).await {
+            STATS::absent_hit.add_value(1, (C::CACHE_NAME,));
+            Ok(false)
         } else {
             STATS::presence_miss.add_value(1, (C::CACHE_NAME,));
-            self.blobstore.is_present(ctx, key).await
+            let looked_up_at = SystemTime::now();
+            let present = self.blobstore.is_present(ctx, key).await?;
+            if !present {
+                self.put_absent(key, looked_up_at).await;
+            }
+            Ok(present)
         }
     }
 }
@@ -0,0 +1,311 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Deduplication of blobstore puts by content hash. Large values are often written several times
+//! under different keys, e.g. the same file content in each repo of a megarepo sync. The first
+//! time a value is put, an alias key derived from the hash of its content records which key holds
+//! it. Puts of the same value under other keys then only write a small reference to that key
+//! instead of uploading the value again, and gets of such keys follow the reference.
+//!
+//! References are put under the key they stand for, so a key costs a single lookup whether it
+//! holds a value, a reference or nothing. Neither references nor aliases are known to the walker,
+//! so deduplication must not be used on stores which are garbage collected from walker keys, such
+//! as sqlblob: the target of a reference could be collected while the reference is in use.
+
+use std::str;
+
+use anyhow::{format_err, Result};
+use async_trait::async_trait;
+use stats::prelude::*;
+
+use accountingblob::{key_family, OTHER_FAMILY};
+use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use context::CoreContext;
+use mononoke_types::{hash::Context as HashContext, BlobstoreBytes};
+
+define_stats! {
+    prefix = "mononoke.blobstore.dedup";
+    deduplicated_puts: timeseries(Rate, Sum),
+    bytes_saved: timeseries(Rate, Sum),
+    aliases_recorded: timeseries(Rate, Sum),
+    reference_gets: timeseries(Rate, Sum),
+}
+
+/// Aliases are put under this prefix followed by the hash of the content, with no repo prefix so
+/// that values are deduplicated across repos.
+pub const DEDUP_ALIAS_PREFIX: &str = "dedup_alias.blake2.";
+
+/// References are values made of this followed by the key holding the value. Values of the
+/// deduplicated key families are serialized thrift structures, which never start with a zero byte.
+const REFERENCE_MAGIC: &[u8] = b"\0dedupblob reference\0";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DedupOptions {
+    /// Values smaller than this are put as usual, as looking up their alias costs more than
+    /// uploading them
+    pub min_size: usize,
+}
+
+impl DedupOptions {
+    pub fn new(min_size: usize) -> Self {
+        Self { min_size }
+    }
+}
+
+fn alias_key(value: &[u8]) -> String {
+    let mut hash_context = HashContext::new(b"dedupblob");
+    hash_context.update(value);
+    format!("{}{}", DEDUP_ALIAS_PREFIX, hash_context.finish().to_hex())
+}
+
+fn reference_value(target: &[u8]) -> BlobstoreBytes {
+    let mut reference = Vec::with_capacity(REFERENCE_MAGIC.len() + target.len());
+    reference.extend_from_slice(REFERENCE_MAGIC);
+    reference.extend_from_slice(target);
+    BlobstoreBytes::from_bytes(reference)
+}
+
+/// Key which holds the value of `key`, if `value` is a reference
+fn reference_target(key: &str, value: &BlobstoreGetData) -> Result<Option<String>> {
+    if !is_dedupable(key) {
+        return Ok(None);
+    }
+    let value = value.as_raw_bytes();
+    if !value.starts_with(REFERENCE_MAGIC) {
+        return Ok(None);
+    }
+    let target = str::from_utf8(&value[REFERENCE_MAGIC.len()..])
+        .map_err(|e| format_err!("Invalid dedup reference for {}: {}", key, e))?;
+    Ok(Some(target.to_string()))
+}
+
+/// Only keys of known families are deduplicated, as their values never change once put. Other
+/// keys, e.g. the skiplist index, may be overwritten with a different value, which would change
+/// the value of every key referring to them.
+fn is_dedupable(key: &str) -> bool {
+    key_family(key) != OTHER_FAMILY
+}
+
+/// A blobstore which avoids putting the same large value several times under different keys
+#[derive(Debug)]
+pub struct DedupBlob<B> {
+    inner: B,
+    options: DedupOptions,
+}
+
+impl<B> DedupBlob<B> {
+    pub fn new(inner: B, options: DedupOptions) -> Self {
+        Self { inner, options }
+    }
+
+    pub fn as_inner(&self) -> &B {
+        &self.inner
+    }
+}
+
+impl<B: BlobstorePutOps> DedupBlob<B> {
+    async fn put_impl<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: Option<PutBehaviour>,
+    ) -> Result<OverwriteStatus> {
+        if value.len() < self.options.min_size || !is_dedupable(&key) {
+            return self.inner_put(ctx, key, value, put_behaviour).await;
+        }
+
+        let alias = alias_key(value.as_bytes());
+        let target = match self.inner.get(ctx, &alias).await? {
+            Some(target) => target,
+            None => {
+                let status = self
+                    .inner_put(ctx, key.clone(), value, put_behaviour)
+                    .await?;
+                // Only record the alias once the value is there for others to refer to. If two
+                // keys race, the first one to be recorded is kept.
+                self.inner
+                    .put_explicit(
+                        ctx,
+                        alias,
+                        BlobstoreBytes::from_bytes(key),
+                        PutBehaviour::IfAbsent,
+                    )
+                    .await?;
+                STATS::aliases_recorded.add_value(1);
+                return Ok(status);
+            }
+        };
+
+        STATS::deduplicated_puts.add_value(1);
+        STATS::bytes_saved.add_value(value.len() as i64);
+        if target.as_raw_bytes().as_ref() == key.as_bytes() {
+            // The very same value was already put under this key
+            return Ok(OverwriteStatus::Prevented);
+        }
+        let reference = reference_value(target.as_raw_bytes());
+        self.inner_put(ctx, key, reference, put_behaviour).await
+    }
+
+    async fn inner_put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: Option<PutBehaviour>,
+    ) -> Result<OverwriteStatus> {
+        match put_behaviour {
+            Some(put_behaviour) => {
+                self.inner
+                    .put_explicit(ctx, key, value, put_behaviour)
+                    .await
+            }
+            None => self.inner.put_with_status(ctx, key, value).await,
+        }
+    }
+}
+
+#[async_trait]
+impl<B: BlobstorePutOps> Blobstore for DedupBlob<B> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let value = match self.inner.get(ctx, key).await? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match reference_target(key, &value)? {
+            Some(target) => {
+                STATS::reference_gets.add_value(1);
+                let value = self.inner.get(ctx, &target).await?.ok_or_else(|| {
+                    format_err!("Dedup reference of {} to missing key {}", key, target)
+                })?;
+                Ok(Some(value))
+            }
+            None => Ok(Some(value)),
+        }
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        self.inner.is_present(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.put_impl(ctx, key, value, None).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<B: BlobstorePutOps> BlobstorePutOps for DedupBlob<B> {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        self.put_impl(ctx, key, value, Some(put_behaviour)).await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.put_impl(ctx, key, value, None).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use borrowed::borrowed;
+    use bytes::Bytes;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use std::sync::Arc;
+
+    fn value(size: usize, fill: u8) -> BlobstoreBytes {
+        BlobstoreBytes::from_bytes(Bytes::from(vec![fill; size]))
+    }
+
+    #[fbinit::test]
+    async fn dedup_roundtrip_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::default());
+        let blob = DedupBlob::new(inner.clone(), DedupOptions::new(100));
+
+        let first = "repo0000.content.blake2.abc".to_string();
+        let second = "repo0001.content.blake2.abc".to_string();
+        blob.put(ctx, first.clone(), value(1000, 1)).await?;
+        blob.put(ctx, second.clone(), value(1000, 1)).await?;
+
+        // Only the first put uploaded the value
+        assert!(inner.is_present(ctx, &first).await?);
+        assert_eq!(
+            inner
+                .get(ctx, &second)
+                .await?
+                .map(BlobstoreGetData::into_bytes),
+            Some(reference_value(first.as_bytes()))
+        );
+
+        assert!(blob.is_present(ctx, &second).await?);
+        assert_eq!(
+            blob.get(ctx, &second)
+                .await?
+                .map(BlobstoreGetData::into_bytes),
+            Some(value(1000, 1))
+        );
+        assert!(!blob.is_present(ctx, "repo0002.content.blake2.abc").await?);
+        assert_eq!(blob.get(ctx, "repo0002.content.blake2.abc").await?, None);
+
+        // Putting the same value under the same key again is skipped
+        assert_eq!(
+            blob.put_with_status(ctx, first.clone(), value(1000, 1))
+                .await?,
+            OverwriteStatus::Prevented
+        );
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn not_deduplicated_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::default());
+        let blob = DedupBlob::new(inner.clone(), DedupOptions::new(100));
+
+        // Small values are put as usual
+        blob.put(ctx, "repo0000.content.small1".to_string(), value(10, 2))
+            .await?;
+        blob.put(ctx, "repo0000.content.small2".to_string(), value(10, 2))
+            .await?;
+        assert!(inner.is_present(ctx, "repo0000.content.small2").await?);
+
+        // So are values of keys which may be overwritten
+        blob.put(ctx, "repo0000.skiplist".to_string(), value(1000, 3))
+            .await?;
+        blob.put(ctx, "repo0001.skiplist".to_string(), value(1000, 3))
+            .await?;
+        assert!(inner.is_present(ctx, "repo0001.skiplist").await?);
+        assert!(!inner.is_present(ctx, &alias_key(&[3; 1000])).await?);
+        Ok(())
+    }
+}
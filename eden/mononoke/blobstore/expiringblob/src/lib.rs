@@ -0,0 +1,282 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use std::convert::TryInto;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bytes::{BufMut, Bytes, BytesMut};
+
+use blobstore::{
+    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreKeyParam, BlobstoreKeyRange,
+    BlobstoreKeySource, BlobstorePutOps, BlobstoreWithTtl, OverwriteStatus, PutBehaviour,
+};
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+
+// Every value is stored after a header of this magic and the big endian unix time in seconds it
+// expires at, or NEVER.
+const MAGIC: &[u8] = b"ttl\x01";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+const NEVER: u64 = 0;
+
+/// A layer over an existing blobstore that gives it TTLs, for stores without native support.
+/// Expired blobs are no longer returned, and `sweep` reclaims their space.
+///
+/// Every value is stored with a small header, so this must wrap the store from its creation:
+/// blobs written to the inner store directly can't be read through it.
+#[derive(Clone, Debug)]
+pub struct ExpiringBlob<T> {
+    inner: T,
+    default_ttl: Option<Duration>,
+}
+
+impl<T> ExpiringBlob<T> {
+    /// `default_ttl` applies to the blobs written with the regular put methods, so that a store
+    /// dedicated to ephemeral blobs can expire all of them.
+    pub fn new(inner: T, default_ttl: Option<Duration>) -> Self {
+        Self { inner, default_ttl }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub fn as_inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+fn expires_at(ttl: Option<Duration>) -> u64 {
+    match ttl {
+        // Round up, so that a blob lives at least for its ttl
+        Some(ttl) => now()
+            .saturating_add(ttl.as_secs())
+            .saturating_add((ttl.subsec_nanos() > 0) as u64),
+        None => NEVER,
+    }
+}
+
+fn is_expired(expires_at: u64) -> bool {
+    expires_at != NEVER && expires_at <= now()
+}
+
+fn encode(expires_at: u64, value: &Bytes) -> BlobstoreBytes {
+    let mut bytes = BytesMut::with_capacity(HEADER_LEN + value.len());
+    bytes.put_slice(MAGIC);
+    bytes.put_u64(expires_at);
+    bytes.put_slice(value);
+    BlobstoreBytes::from_bytes(bytes.freeze())
+}
+
+fn decode(key: &str, bytes: Bytes) -> Result<(u64, Bytes)> {
+    if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+        bail!("Blob {} was not written through ExpiringBlob", key);
+    }
+    let expires_at = u64::from_be_bytes(bytes[MAGIC.len()..HEADER_LEN].try_into()?);
+    Ok((expires_at, bytes.slice(HEADER_LEN..)))
+}
+
+impl<T: BlobstoreKeySource + BlobstorePutOps> ExpiringBlob<T> {
+    /// Overwrite the expired blobs in `range` with empty values, so that their space is
+    /// reclaimed. Returns how many blobs were reclaimed.
+    pub async fn sweep<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        range: BlobstoreKeyRange,
+    ) -> Result<usize> {
+        let mut reclaimed = 0;
+        let mut param = BlobstoreKeyParam::Start(range);
+        loop {
+            let BlobstoreEnumerationData { keys, next_token } =
+                self.inner.enumerate(ctx, &param).await?;
+            for key in keys {
+                let data = match self.inner.get(ctx, &key).await? {
+                    Some(data) => data,
+                    None => continue,
+                };
+                let (expires_at, value) = decode(&key, data.into_raw_bytes())?;
+                if is_expired(expires_at) && !value.is_empty() {
+                    self.inner
+                        .put_explicit(
+                            ctx,
+                            key,
+                            encode(expires_at, &Bytes::new()),
+                            PutBehaviour::Overwrite,
+                        )
+                        .await?;
+                    reclaimed += 1;
+                }
+            }
+            match next_token {
+                Some(token) => param = token,
+                None => return Ok(reclaimed),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Blobstore> Blobstore for ExpiringBlob<T> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let data = match self.inner.get(ctx, key).await? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let meta = data.as_meta().clone();
+        let (expires_at, value) = decode(key, data.into_raw_bytes())?;
+        if is_expired(expires_at) {
+            return Ok(None);
+        }
+        Ok(Some(BlobstoreGetData::new(
+            meta,
+            BlobstoreBytes::from_bytes(value),
+        )))
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.put_with_ttl(ctx, key, value, self.default_ttl).await
+    }
+
+    // is_present uses the default implementation, as expiry needs the header of the value
+}
+
+#[async_trait]
+impl<T: Blobstore> BlobstoreWithTtl for ExpiringBlob<T> {
+    async fn put_with_ttl<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let value = encode(expires_at(ttl), value.as_bytes());
+        self.inner.put(ctx, key, value).await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> BlobstorePutOps for ExpiringBlob<T> {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        let value = encode(expires_at(self.default_ttl), value.as_bytes());
+        self.inner
+            .put_explicit(ctx, key, value, put_behaviour)
+            .await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        let value = encode(expires_at(self.default_ttl), value.as_bytes());
+        self.inner.put_with_status(ctx, key, value).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use borrowed::borrowed;
+    use fbinit::FacebookInit;
+    use fileblob::Fileblob;
+    use memblob::Memblob;
+    use tempdir::TempDir;
+
+    #[fbinit::test]
+    async fn test_expiry(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let dir = TempDir::new("expiringblob")?;
+        let inner = Fileblob::create(dir.path(), PutBehaviour::Overwrite)?;
+        let blobstore = ExpiringBlob::new(inner.clone(), None);
+        let value = BlobstoreBytes::from_bytes("ephemeral");
+
+        blobstore
+            .put(ctx, "forever".to_string(), value.clone())
+            .await?;
+        blobstore
+            .put_with_ttl(
+                ctx,
+                "later".to_string(),
+                value.clone(),
+                Some(Duration::from_secs(3600)),
+            )
+            .await?;
+        blobstore
+            .put_with_ttl(
+                ctx,
+                "now".to_string(),
+                value.clone(),
+                Some(Duration::from_secs(0)),
+            )
+            .await?;
+
+        for key in &["forever", "later"] {
+            let fetched = blobstore.get(ctx, key).await?.map(|data| data.into_bytes());
+            assert_eq!(fetched, Some(value.clone()));
+            assert!(blobstore.is_present(ctx, key).await?);
+        }
+        assert_eq!(blobstore.get(ctx, "now").await?, None);
+        assert!(!blobstore.is_present(ctx, "now").await?);
+        // Expired, but still taking space until swept
+        assert!(inner.is_present(ctx, "now").await?);
+
+        let all = BlobstoreKeyRange::prefix("");
+        assert_eq!(blobstore.sweep(ctx, all.clone()).await?, 1);
+        let swept = inner.get(ctx, "now").await?.expect("tombstone is kept");
+        assert_eq!(swept.as_bytes().len(), HEADER_LEN);
+        assert_eq!(blobstore.get(ctx, "now").await?, None);
+        assert_eq!(blobstore.sweep(ctx, all).await?, 0);
+
+        // Blobs written without the wrapper are an error, not silently misread
+        inner
+            .put(ctx, "unwrapped".to_string(), value.clone())
+            .await?;
+        assert!(blobstore.get(ctx, "unwrapped").await.is_err());
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_default_ttl(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let blobstore = ExpiringBlob::new(Memblob::default(), Some(Duration::from_secs(0)));
+
+        blobstore
+            .put(ctx, "key".to_string(), BlobstoreBytes::from_bytes("value"))
+            .await?;
+        assert_eq!(blobstore.get(ctx, "key").await?, None);
+        Ok(())
+    }
+}
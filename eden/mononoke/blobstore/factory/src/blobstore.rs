@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use accountingblob::AccountingBlob;
 use anyhow::{bail, Context, Error};
 use blobstore::{
     Blobstore, BlobstorePutOps, DisabledBlob, ErrorKind, PutBehaviour, DEFAULT_PUT_BEHAVIOUR,
@@ -13,20 +14,27 @@ use blobstore_sync_queue::SqlBlobstoreSyncQueue;
 use cacheblob::CachelibBlobstoreOptions;
 use cached_config::ConfigStore;
 use chaosblob::{ChaosBlobstore, ChaosOptions};
+use dedupblob::{DedupBlob, DedupOptions};
 use fbinit::FacebookInit;
 use fileblob::Fileblob;
 use futures::{
     compat::Future01CompatExt,
     future::{self, BoxFuture, FutureExt},
 };
+use gcsblob::GcsBlob;
+use httpblob::HttpBlob;
 use logblob::LogBlob;
 use metaconfig_types::{
     BlobConfig, BlobstoreId, DatabaseConfig, MultiplexId, MultiplexedStoreType,
     ShardableRemoteDatabaseConfig,
 };
-use multiplexedblob::{MultiplexedBlobstore, ScrubAction, ScrubBlobstore, ScrubOptions};
+use multiplexedblob::{
+    MultiplexedBlobstore, ScrubAction, ScrubBlobstore, ScrubOptions, ScrubReport,
+};
 use packblob::{PackBlob, PackOptions};
 use readonlyblob::ReadOnlyBlobstore;
+use retryblob::{IsRetryable, RetryBlob, RetryOptions};
+use s3blob::{S3Blob, S3Options};
 use scuba_ext::MononokeScubaSampleBuilder;
 use slog::Logger;
 use sql_construct::SqlConstructFromDatabaseConfig;
@@ -49,6 +57,11 @@ pub struct BlobstoreOptions {
     pub cachelib_options: CachelibBlobstoreOptions,
     pub put_behaviour: PutBehaviour,
     pub scrub_options: Option<ScrubOptions>,
+    pub s3_options: S3Options,
+    pub accounting: bool,
+    pub dedup_options: Option<DedupOptions>,
+    pub retry_options: RetryOptions,
+    pub health_based_reads_hedge: Option<Duration>,
 }
 
 impl BlobstoreOptions {
@@ -72,6 +85,11 @@ impl BlobstoreOptions {
             put_behaviour: put_behaviour.unwrap_or(DEFAULT_PUT_BEHAVIOUR),
             // These are added via the builder methods
             scrub_options: None,
+            s3_options: S3Options::default(),
+            accounting: false,
+            dedup_options: None,
+            retry_options: RetryOptions::default(),
+            health_based_reads_hedge: None,
         }
     }
 
@@ -99,6 +117,47 @@ impl BlobstoreOptions {
             self
         }
     }
+
+    pub fn with_scrub_report(self, scrub_report: Option<Arc<ScrubReport>>) -> Self {
+        if let Some(mut scrub_options) = self.scrub_options {
+            scrub_options.scrub_report = scrub_report;
+            Self {
+                scrub_options: Some(scrub_options),
+                ..self
+            }
+        } else {
+            self
+        }
+    }
+
+    pub fn with_s3_options(self, s3_options: S3Options) -> Self {
+        Self { s3_options, ..self }
+    }
+
+    pub fn with_accounting(self, accounting: bool) -> Self {
+        Self { accounting, ..self }
+    }
+
+    pub fn with_retry_options(self, retry_options: RetryOptions) -> Self {
+        Self {
+            retry_options,
+            ..self
+        }
+    }
+
+    pub fn with_dedup_options(self, dedup_options: Option<DedupOptions>) -> Self {
+        Self {
+            dedup_options,
+            ..self
+        }
+    }
+
+    pub fn with_health_based_reads_hedge(self, health_based_reads_hedge: Option<Duration>) -> Self {
+        Self {
+            health_based_reads_hedge,
+            ..self
+        }
+    }
 }
 
 impl Default for BlobstoreOptions {
@@ -119,6 +178,14 @@ impl Default for BlobstoreOptions {
 /// needs an SQL DB for its queue, as does the MySQL blobstore.
 /// If `throttling.read_qps` or `throttling.write_qps` are Some then ThrottledBlob will be used to limit
 /// QPS to the underlying blobstore
+/// If `accounting` is set then AccountingBlob will be used to count traffic per key family
+/// If `dedup_options` is set then DedupBlob will be used to avoid putting the same value under
+/// several keys
+/// If `retry_options` allows several attempts then RetryBlob will be used to retry the operations
+/// of S3, GCS and HTTP blobstores which fail with a transient error. S3 and GCS blobstores also
+/// retry the parts of large uploads with it
+/// If `health_based_reads_hedge` is set then multiplexed blobstores read from their healthiest
+/// components first
 pub fn make_blobstore<'a>(
     fb: FacebookInit,
     blobconfig: BlobConfig,
@@ -129,6 +196,11 @@ pub fn make_blobstore<'a>(
     config_store: &'a ConfigStore,
 ) -> BoxFuture<'a, Result<Arc<dyn Blobstore>, Error>> {
     async move {
+        if blobstore_options.dedup_options.is_some() && is_garbage_collected(&blobconfig) {
+            bail!(
+                "Deduplication can't be used with sqlblob, as its garbage collection doesn't know about dedup references"
+            );
+        }
         let store = make_blobstore_put_ops(
             fb,
             blobconfig,
@@ -139,6 +211,20 @@ pub fn make_blobstore<'a>(
             config_store,
         )
         .await?;
+        // Deduplicate once for the whole store, so that all members of a multiplex agree on which
+        // keys hold references
+        let store = match blobstore_options.dedup_options {
+            Some(dedup_options) => {
+                Arc::new(DedupBlob::new(store, dedup_options)) as Arc<dyn BlobstorePutOps>
+            }
+            None => store,
+        };
+        // Only account once for the whole store, not for each member of a multiplex
+        let store = if blobstore_options.accounting {
+            Arc::new(AccountingBlob::new(store)) as Arc<dyn BlobstorePutOps>
+        } else {
+            store
+        };
         // Workaround for trait A {} trait B:A {} but Arc<dyn B> is not a Arc<dyn A>
         // See https://github.com/rust-lang/rfcs/issues/2765 if interested
         Ok(Arc::new(store) as Arc<dyn Blobstore>)
@@ -146,6 +232,29 @@ pub fn make_blobstore<'a>(
     .boxed()
 }
 
+/// Whether some of the store may be garbage collected from the keys the walker finds, which
+/// don't include dedup references and aliases.
+fn is_garbage_collected(blobconfig: &BlobConfig) -> bool {
+    use BlobConfig::*;
+
+    match blobconfig {
+        Mysql { .. } => true,
+        Disabled
+        | Files { .. }
+        | Sqlite { .. }
+        | Manifold { .. }
+        | ManifoldWithTtl { .. }
+        | S3 { .. }
+        | Gcs { .. }
+        | Http { .. } => false,
+        Multiplexed { blobstores, .. } => blobstores
+            .iter()
+            .any(|(_, _, config)| is_garbage_collected(config)),
+        Logging { blobconfig, .. } => is_garbage_collected(blobconfig),
+        Pack { blobconfig, .. } => is_garbage_collected(blobconfig),
+    }
+}
+
 pub async fn make_sql_blobstore<'a>(
     fb: FacebookInit,
     blobconfig: BlobConfig,
@@ -294,6 +403,7 @@ fn make_blobstore_put_ops<'a>(
                 scuba_sample_rate,
                 blobstores,
                 minimum_successful_writes,
+                minimum_successful_reads,
                 queue_db,
             } => {
                 has_components = true;
@@ -305,6 +415,7 @@ fn make_blobstore_put_ops<'a>(
                     scuba_sample_rate,
                     blobstores,
                     minimum_successful_writes,
+                    minimum_successful_reads,
                     mysql_options,
                     readonly_storage,
                     blobstore_options,
@@ -410,28 +521,39 @@ fn make_blobstore_put_ops<'a>(
                 keychain_group,
                 region_name,
                 endpoint,
-            } => {
-                #[cfg(fbcode_build)]
-                {
-                    ::s3blob::S3Blob::new(
-                        fb,
-                        bucket,
-                        keychain_group,
-                        region_name,
-                        endpoint,
-                        blobstore_options.put_behaviour,
-                        logger,
-                    )
-                    .await
-                    .context(ErrorKind::StateOpen)
-                    .map(|store| Arc::new(store) as Arc<dyn BlobstorePutOps>)?
-                }
-                #[cfg(not(fbcode_build))]
-                {
-                    let _ = (bucket, keychain_group, region_name, endpoint);
-                    unimplemented!("This is implemented only for fbcode_build")
-                }
-            }
+            } => S3Blob::new(
+                bucket,
+                keychain_group,
+                region_name,
+                endpoint,
+                blobstore_options.put_behaviour,
+                blobstore_options.s3_options,
+                blobstore_options.retry_options,
+            )
+            .await
+            .context(ErrorKind::StateOpen)
+            .map(|store| with_retries(store, blobstore_options, s3blob::is_retryable))?,
+            Gcs {
+                bucket,
+                prefix,
+                credentials_path,
+            } => GcsBlob::new(
+                bucket,
+                prefix,
+                credentials_path,
+                blobstore_options.put_behaviour,
+                blobstore_options.retry_options,
+            )
+            .await
+            .context(ErrorKind::StateOpen)
+            .map(|store| with_retries(store, blobstore_options, gcsblob::is_retryable))?,
+            Http {
+                get_url,
+                put_url,
+                headers,
+            } => HttpBlob::new(get_url, put_url, headers, blobstore_options.put_behaviour)
+                .context(ErrorKind::StateOpen)
+                .map(|store| with_retries(store, blobstore_options, httpblob::is_retryable))?,
         };
 
         let store = if readonly_storage.0 {
@@ -464,6 +586,23 @@ fn make_blobstore_put_ops<'a>(
     .boxed()
 }
 
+/// Retry the operations of a backend which fail with an error it classifies as retryable
+fn with_retries<B: BlobstorePutOps + 'static>(
+    store: B,
+    blobstore_options: &BlobstoreOptions,
+    is_retryable: IsRetryable,
+) -> Arc<dyn BlobstorePutOps> {
+    if blobstore_options.retry_options.has_retries() {
+        Arc::new(RetryBlob::new(
+            store,
+            blobstore_options.retry_options,
+            is_retryable,
+        ))
+    } else {
+        Arc::new(store)
+    }
+}
+
 async fn make_blobstore_multiplexed<'a>(
     fb: FacebookInit,
     multiplex_id: MultiplexId,
@@ -472,6 +611,7 @@ async fn make_blobstore_multiplexed<'a>(
     scuba_sample_rate: NonZeroU64,
     inner_config: Vec<(BlobstoreId, MultiplexedStoreType, BlobConfig)>,
     minimum_successful_writes: NonZeroUsize,
+    minimum_successful_reads: NonZeroUsize,
     mysql_options: &'a MysqlOptions,
     readonly_storage: ReadOnlyStorage,
     blobstore_options: &'a BlobstoreOptions,
@@ -549,6 +689,7 @@ async fn make_blobstore_multiplexed<'a>(
             normal_components,
             write_mostly_components,
             minimum_successful_writes,
+            minimum_successful_reads,
             Arc::new(queue),
             scuba_table.map_or(MononokeScubaSampleBuilder::with_discard(), |table| {
                 MononokeScubaSampleBuilder::new(fb, &table)
@@ -556,17 +697,25 @@ async fn make_blobstore_multiplexed<'a>(
             scuba_sample_rate,
             scrub_options.clone(),
         )) as Arc<dyn BlobstorePutOps>,
-        None => Arc::new(MultiplexedBlobstore::new(
-            multiplex_id,
-            normal_components,
-            write_mostly_components,
-            minimum_successful_writes,
-            Arc::new(queue),
-            scuba_table.map_or(MononokeScubaSampleBuilder::with_discard(), |table| {
-                MononokeScubaSampleBuilder::new(fb, &table)
-            }),
-            scuba_sample_rate,
-        )) as Arc<dyn BlobstorePutOps>,
+        None => {
+            let blobstore = MultiplexedBlobstore::new(
+                multiplex_id,
+                normal_components,
+                write_mostly_components,
+                minimum_successful_writes,
+                minimum_successful_reads,
+                Arc::new(queue),
+                scuba_table.map_or(MononokeScubaSampleBuilder::with_discard(), |table| {
+                    MononokeScubaSampleBuilder::new(fb, &table)
+                }),
+                scuba_sample_rate,
+            );
+            let blobstore = match blobstore_options.health_based_reads_hedge {
+                Some(hedge_delay) => blobstore.with_health_based_reads(hedge_delay),
+                None => blobstore,
+            };
+            Arc::new(blobstore) as Arc<dyn BlobstorePutOps>
+        }
     };
 
     Ok(blobstore)
@@ -15,8 +15,10 @@ mod sql;
 pub use ::blobstore::{PutBehaviour, DEFAULT_PUT_BEHAVIOUR};
 pub use cacheblob::CachelibBlobstoreOptions;
 pub use chaosblob::ChaosOptions;
-pub use multiplexedblob::{scrub::ScrubOptions, ScrubAction};
+pub use dedupblob::DedupOptions;
+pub use multiplexedblob::{scrub::ScrubOptions, ScrubAction, ScrubReport};
 pub use packblob::PackOptions;
+pub use retryblob::RetryOptions;
 pub use throttledblob::ThrottleOptions;
 
 pub use crate::blobstore::{make_blobstore, make_sql_blobstore, BlobstoreOptions};
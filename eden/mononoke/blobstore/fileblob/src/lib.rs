@@ -7,7 +7,6 @@
 
 #![deny(warnings)]
 
-use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::fs::create_dir_all;
 use std::path::{Path, PathBuf};
@@ -15,11 +14,12 @@ use std::time::SystemTime;
 
 use anyhow::{bail, format_err, Result};
 use async_trait::async_trait;
-use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, CONTROLS};
 
 use blobstore::{
     Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreKeyParam, BlobstoreKeySource,
     BlobstoreMetadata, BlobstorePutOps, BlobstoreWithLink, OverwriteStatus, PutBehaviour,
+    ENUMERATION_PAGE_SIZE,
 };
 use context::CoreContext;
 use mononoke_types::BlobstoreBytes;
@@ -69,6 +69,13 @@ impl Fileblob {
     }
 }
 
+// Inverse of Fileblob::path, for the files which are blobs
+fn key_from_file_name(name: &str) -> Option<String> {
+    let key = name.strip_prefix(PREFIX)?.strip_prefix('-')?;
+    let key = percent_decode_str(key).decode_utf8().ok()?;
+    Some(key.into_owned())
+}
+
 async fn ctime(file: &File) -> Option<i64> {
     let meta = file.metadata().await.ok()?;
     let ctime = meta.modified().ok()?;
@@ -203,23 +210,19 @@ impl BlobstoreKeySource for Fileblob {
     ) -> Result<BlobstoreEnumerationData> {
         match range {
             BlobstoreKeyParam::Start(ref range) => {
-                let mut enum_data = BlobstoreEnumerationData {
-                    keys: HashSet::new(),
-                    next_token: None,
-                };
-                WalkDir::new(&self.base)
-                    .into_iter()
-                    .filter_map(|v| v.ok())
-                    .for_each(|entry| {
-                        let entry = entry.path().to_str();
-                        if let Some(data) = entry {
-                            let key = data.to_string();
-                            if key < range.end_key && key > range.begin_key {
-                                enum_data.keys.insert(key);
-                            }
-                        }
-                    });
-                Ok(enum_data)
+                let mut keys = vec![];
+                for entry in WalkDir::new(&self.base).min_depth(1).max_depth(1) {
+                    let entry = entry?;
+                    if let Some(key) = entry.file_name().to_str().and_then(key_from_file_name) {
+                        keys.push(key);
+                    }
+                }
+                keys.sort();
+                Ok(BlobstoreEnumerationData::page(
+                    range,
+                    keys,
+                    ENUMERATION_PAGE_SIZE,
+                ))
             }
             _ => Err(format_err!("Fileblob does not support token, only ranges")),
         }
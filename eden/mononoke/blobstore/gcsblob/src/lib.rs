@@ -0,0 +1,438 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use anyhow::{bail, format_err, Context, Error, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::DateTime;
+use http::{
+    header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, LAST_MODIFIED, LOCATION, RANGE},
+    HeaderMap, HeaderValue, Method, Request, StatusCode,
+};
+use hyper::{client::HttpConnector, Body, Client};
+use hyper_tls::HttpsConnector;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use thiserror::Error;
+use yup_oauth2::{authenticator::Authenticator, ServiceAccountAuthenticator};
+
+use blobstore::{
+    Blobstore, BlobstoreGetData, BlobstoreMetadata, BlobstorePutOps, OverwriteStatus, PutBehaviour,
+};
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+use retryblob::{retry, RetryOptions};
+
+const API_URL: &str = "https://storage.googleapis.com/storage/v1";
+const UPLOAD_URL: &str = "https://storage.googleapis.com/upload/storage/v1";
+const SCOPES: &[&str] = &["https://www.googleapis.com/auth/devstorage.read_write"];
+
+/// Blobs larger than this are uploaded with resumable uploads, in chunks of `CHUNK_SIZE` bytes,
+/// so that a failure only requires sending the chunk again.
+const RESUMABLE_THRESHOLD: usize = 16 * 1024 * 1024;
+/// GCS requires the chunks of resumable uploads to be multiples of 256KiB.
+const CHUNK_SIZE: usize = 32 * 256 * 1024;
+
+/// The HTTP status GCS answers the chunks of a resumable upload but the last one with.
+const RESUME_INCOMPLETE: u16 = 308;
+
+/// Provides the OAuth access tokens requests are authorized with.
+#[async_trait]
+trait AccessTokenSource: Send + Sync {
+    async fn access_token(&self) -> Result<String>;
+}
+
+#[async_trait]
+impl<C> AccessTokenSource for Authenticator<C>
+where
+    C: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+{
+    async fn access_token(&self) -> Result<String> {
+        // The authenticator caches the token, and refreshes it when it expires.
+        Ok(self.token(SCOPES).await?.as_str().to_string())
+    }
+}
+
+/// A blobstore backed by a Google Cloud Storage bucket, through the GCS JSON API.
+///
+/// Puts which must not overwrite existing blobs are conditional on the object not existing
+/// (`ifGenerationMatch=0`), so GCS enforces the `PutBehaviour` atomically.
+///
+/// Retries follow the contract of `retryblob::IsRetryable`.
+#[derive(Clone)]
+pub struct GcsBlob {
+    client: Client<HttpsConnector<HttpConnector>>,
+    auth: Arc<dyn AccessTokenSource>,
+    bucket: String,
+    prefix: String,
+    put_behaviour: PutBehaviour,
+    retry_options: RetryOptions,
+}
+
+impl fmt::Debug for GcsBlob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GcsBlob")
+            .field("bucket", &self.bucket)
+            .field("prefix", &self.prefix)
+            .field("put_behaviour", &self.put_behaviour)
+            .field("retry_options", &self.retry_options)
+            .finish()
+    }
+}
+
+/// How a conditional put ended.
+enum PutOutcome {
+    Written,
+    PreconditionFailed,
+}
+
+/// Where a resumable upload is at after sending it a chunk.
+enum ChunkOutcome {
+    Done(PutOutcome),
+    /// GCS persisted that many bytes so far.
+    Incomplete(usize),
+}
+
+impl GcsBlob {
+    /// Connect to `bucket`, authenticating as the service account whose key is at
+    /// `credentials_path`, or at `$GOOGLE_APPLICATION_CREDENTIALS` if it is not set. The keys
+    /// of the blobs are prefixed with `prefix`.
+    pub async fn new(
+        bucket: String,
+        prefix: String,
+        credentials_path: Option<PathBuf>,
+        put_behaviour: PutBehaviour,
+        retry_options: RetryOptions,
+    ) -> Result<Self> {
+        let credentials_path = match credentials_path {
+            Some(path) => path,
+            None => std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS")
+                .map(PathBuf::from)
+                .ok_or_else(|| {
+                    format_err!(
+                        "no GCS credentials: set credentials_path or GOOGLE_APPLICATION_CREDENTIALS"
+                    )
+                })?,
+        };
+        let key = yup_oauth2::read_service_account_key(&credentials_path)
+            .await
+            .with_context(|| format!("failed to read GCS credentials {:?}", credentials_path))?;
+        let auth = ServiceAccountAuthenticator::builder(key).build().await?;
+
+        Ok(Self {
+            client: Client::builder().build(HttpsConnector::new()),
+            auth: Arc::new(auth),
+            bucket,
+            prefix,
+            put_behaviour,
+            retry_options,
+        })
+    }
+
+    fn object_name(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!(
+            "{}/b/{}/o/{}",
+            API_URL,
+            utf8_percent_encode(&self.bucket, NON_ALPHANUMERIC),
+            utf8_percent_encode(&self.object_name(key), NON_ALPHANUMERIC),
+        )
+    }
+
+    fn upload_url(&self, key: &str, upload_type: &str, if_absent: bool) -> String {
+        let mut url = format!(
+            "{}/b/{}/o?uploadType={}&name={}",
+            UPLOAD_URL,
+            utf8_percent_encode(&self.bucket, NON_ALPHANUMERIC),
+            upload_type,
+            utf8_percent_encode(&self.object_name(key), NON_ALPHANUMERIC),
+        );
+        if if_absent {
+            url.push_str("&ifGenerationMatch=0");
+        }
+        url
+    }
+
+    /// Send an authorized request.
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<hyper::Response<Body>> {
+        let body_len = body.len();
+        let mut request = Request::builder()
+            .method(method)
+            .uri(url)
+            .body(Body::from(body))?;
+        *request.headers_mut() = headers;
+        let token = self.auth.access_token().await?;
+        request.headers_mut().insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token))?,
+        );
+        request
+            .headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(body_len));
+        Ok(self.client.request(request).await?)
+    }
+
+    async fn put_object(&self, key: &str, value: Bytes, if_absent: bool) -> Result<PutOutcome> {
+        if value.len() > RESUMABLE_THRESHOLD {
+            return self.put_resumable(key, value, if_absent).await;
+        }
+
+        let url = self.upload_url(key, "media", if_absent);
+        let response = self
+            .send(Method::POST, &url, HeaderMap::new(), value)
+            .await?;
+        match response.status() {
+            status if status.is_success() => Ok(PutOutcome::Written),
+            StatusCode::PRECONDITION_FAILED => Ok(PutOutcome::PreconditionFailed),
+            _ => Err(response_error(response).await),
+        }
+    }
+
+    async fn put_resumable(&self, key: &str, value: Bytes, if_absent: bool) -> Result<PutOutcome> {
+        let url = self.upload_url(key, "resumable", if_absent);
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Upload-Content-Length", HeaderValue::from(value.len()));
+        let response = self.send(Method::POST, &url, headers, Bytes::new()).await?;
+        match response.status() {
+            status if status.is_success() => {}
+            StatusCode::PRECONDITION_FAILED => return Ok(PutOutcome::PreconditionFailed),
+            _ => return Err(response_error(response).await),
+        }
+        let session_url = response
+            .headers()
+            .get(LOCATION)
+            .ok_or_else(|| format_err!("GCS returned no resumable upload session for {}", key))?
+            .to_str()?
+            .to_string();
+
+        let (session_url, value) = (&session_url, &value);
+        let mut offset = 0;
+        loop {
+            let retried = &AtomicBool::new(false);
+            let send_chunk = || async move {
+                // A failed attempt may have been persisted in part: ask GCS where to resume from.
+                let offset = if retried.swap(true, Ordering::Relaxed) {
+                    match self.send_chunk(session_url, value, None).await? {
+                        ChunkOutcome::Done(outcome) => return Ok(ChunkOutcome::Done(outcome)),
+                        ChunkOutcome::Incomplete(persisted) => persisted,
+                    }
+                } else {
+                    offset
+                };
+                self.send_chunk(session_url, value, Some(offset)).await
+            };
+            match retry(&self.retry_options, is_retryable, |_, _, _| {}, send_chunk).await? {
+                ChunkOutcome::Done(outcome) => return Ok(outcome),
+                // GCS may have persisted less than the chunk: carry on after what it has.
+                ChunkOutcome::Incomplete(persisted) => offset = persisted,
+            }
+        }
+    }
+
+    /// Send the chunk of `value` starting at `offset` to a resumable upload session, or only ask
+    /// for the status of the session if `offset` is `None`.
+    async fn send_chunk(
+        &self,
+        session_url: &str,
+        value: &Bytes,
+        offset: Option<usize>,
+    ) -> Result<ChunkOutcome> {
+        let total = value.len();
+        let (range, chunk) = match offset {
+            Some(offset) => {
+                let end = (offset + CHUNK_SIZE).min(total);
+                (
+                    format!("bytes {}-{}/{}", offset, end - 1, total),
+                    value.slice(offset..end),
+                )
+            }
+            None => (format!("bytes */{}", total), Bytes::new()),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_RANGE, HeaderValue::from_str(&range)?);
+        let response = self.send(Method::PUT, session_url, headers, chunk).await?;
+        match response.status() {
+            status if status.is_success() => Ok(ChunkOutcome::Done(PutOutcome::Written)),
+            StatusCode::PRECONDITION_FAILED => {
+                Ok(ChunkOutcome::Done(PutOutcome::PreconditionFailed))
+            }
+            status if status.as_u16() == RESUME_INCOMPLETE => {
+                let persisted = match response.headers().get(RANGE) {
+                    Some(range) => persisted_bytes(range.to_str()?)?,
+                    None => 0,
+                };
+                Ok(ChunkOutcome::Incomplete(persisted))
+            }
+            _ => Err(response_error(response).await),
+        }
+    }
+}
+
+/// Parse the `Range` header of a resumable upload response, `bytes=0-<last byte persisted>`,
+/// into the number of bytes persisted.
+fn persisted_bytes(range: &str) -> Result<usize> {
+    match range
+        .strip_prefix("bytes=0-")
+        .and_then(|last| last.parse::<usize>().ok())
+    {
+        Some(last) => Ok(last + 1),
+        None => bail!("unexpected resumable upload range {:?}", range),
+    }
+}
+
+/// A request which GCS answered with an unexpected status.
+#[derive(Debug, Error)]
+#[error("GCS request failed with {status}: {body}")]
+struct ResponseError {
+    status: StatusCode,
+    body: String,
+}
+
+async fn response_error(response: hyper::Response<Body>) -> Error {
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    ResponseError {
+        status,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    }
+    .into()
+}
+
+/// Whether an operation of `GcsBlob` which failed with `error` may succeed if retried: the
+/// connection failed, or GCS throttled the request or failed to serve it.
+pub fn is_retryable(error: &Error) -> bool {
+    error.chain().any(|e| {
+        e.is::<hyper::Error>()
+            || e.downcast_ref::<ResponseError>().map_or(false, |e| {
+                e.status == StatusCode::TOO_MANY_REQUESTS || e.status.is_server_error()
+            })
+    })
+}
+
+#[async_trait]
+impl Blobstore for GcsBlob {
+    async fn get<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let url = format!("{}?alt=media", self.object_url(key));
+        let response = self
+            .send(Method::GET, &url, HeaderMap::new(), Bytes::new())
+            .await
+            .with_context(|| format!("failed to get {} from GCS", key))?;
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Ok(None),
+            _ => return Err(response_error(response).await),
+        }
+
+        let ctime = response
+            .headers()
+            .get(LAST_MODIFIED)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+            .map(|date| date.timestamp());
+        let data = hyper::body::to_bytes(response.into_body()).await?;
+        Ok(Some(BlobstoreGetData::new(
+            BlobstoreMetadata::new(ctime),
+            BlobstoreBytes::from_bytes(data),
+        )))
+    }
+
+    async fn is_present<'a>(&'a self, _ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        let url = format!("{}?fields=name", self.object_url(key));
+        let response = self
+            .send(Method::GET, &url, HeaderMap::new(), Bytes::new())
+            .await
+            .with_context(|| format!("failed to check {} in GCS", key))?;
+        match response.status() {
+            StatusCode::OK => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            _ => Err(response_error(response).await),
+        }
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for GcsBlob {
+    async fn put_explicit<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        let value = value.into_bytes();
+        let status = match put_behaviour {
+            PutBehaviour::Overwrite => {
+                self.put_object(&key, value, false).await?;
+                OverwriteStatus::NotChecked
+            }
+            PutBehaviour::IfAbsent | PutBehaviour::OverwriteAndLog => {
+                match self.put_object(&key, value.clone(), true).await? {
+                    PutOutcome::Written => OverwriteStatus::New,
+                    PutOutcome::PreconditionFailed if put_behaviour.should_overwrite() => {
+                        self.put_object(&key, value, false).await?;
+                        OverwriteStatus::Overwrote
+                    }
+                    PutOutcome::PreconditionFailed => OverwriteStatus::Prevented,
+                }
+            }
+        };
+        Ok(status)
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.put_explicit(ctx, key, value, self.put_behaviour).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_persisted_bytes() {
+        assert_eq!(persisted_bytes("bytes=0-262143").unwrap(), 262144);
+        assert!(persisted_bytes("bytes=10-20").is_err());
+    }
+}
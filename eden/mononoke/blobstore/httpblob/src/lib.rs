@@ -0,0 +1,297 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{bail, Error, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::DateTime;
+use http::{
+    header::{HeaderName, CONTENT_LENGTH, IF_NONE_MATCH, LAST_MODIFIED},
+    HeaderMap, HeaderValue, Method, Request, StatusCode,
+};
+use hyper::{client::HttpConnector, Body, Client};
+use hyper_tls::HttpsConnector;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use thiserror::Error;
+
+use blobstore::{
+    Blobstore, BlobstoreGetData, BlobstoreMetadata, BlobstorePutOps, OverwriteStatus, PutBehaviour,
+};
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+
+/// Replaced by the (percent-encoded) key of the blob in URL templates.
+const KEY_PLACEHOLDER: &str = "{key}";
+// Everything but the unreserved characters of RFC 3986, so that keys fit in a path segment.
+const KEY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A blobstore over plain HTTP(S): blobs are read with GET, checked with HEAD, and written with
+/// PUT, at URLs made from templates containing `{key}`. This works with WebDAV servers and with
+/// most artifact caches.
+///
+/// Puts which must not overwrite existing blobs send `If-None-Match: *`, so that the server
+/// refuses to overwrite them. Servers which ignore that header will overwrite them anyway.
+///
+/// Retries follow the contract of `retryblob::IsRetryable`.
+#[derive(Clone)]
+pub struct HttpBlob {
+    client: Client<HttpsConnector<HttpConnector>>,
+    get_url: String,
+    put_url: String,
+    headers: HeaderMap,
+    put_behaviour: PutBehaviour,
+}
+
+impl fmt::Debug for HttpBlob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The headers are left out: they usually hold credentials.
+        f.debug_struct("HttpBlob")
+            .field("get_url", &self.get_url)
+            .field("put_url", &self.put_url)
+            .field("put_behaviour", &self.put_behaviour)
+            .finish()
+    }
+}
+
+impl HttpBlob {
+    /// `get_url` is the template of the URLs blobs are read from, and `put_url` of the URLs they
+    /// are written to, if they differ. `headers` are sent with every request, for example to
+    /// authenticate.
+    pub fn new(
+        get_url: String,
+        put_url: Option<String>,
+        headers: BTreeMap<String, String>,
+        put_behaviour: PutBehaviour,
+    ) -> Result<Self> {
+        let put_url = put_url.unwrap_or_else(|| get_url.clone());
+        for url in &[&get_url, &put_url] {
+            if !url.contains(KEY_PLACEHOLDER) {
+                bail!("blobstore URL {} doesn't contain {}", url, KEY_PLACEHOLDER);
+            }
+        }
+        let headers = headers
+            .into_iter()
+            .map(|(name, value)| {
+                Ok((
+                    HeaderName::from_bytes(name.as_bytes())?,
+                    HeaderValue::from_str(&value)?,
+                ))
+            })
+            .collect::<Result<HeaderMap>>()?;
+
+        Ok(Self {
+            client: Client::builder().build(HttpsConnector::new()),
+            get_url,
+            put_url,
+            headers,
+            put_behaviour,
+        })
+    }
+
+    /// Send a request, with the headers of every request.
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<hyper::Response<Body>> {
+        let body_len = body.len();
+        let is_put = method == Method::PUT;
+        let mut request = Request::builder()
+            .method(method)
+            .uri(url)
+            .body(Body::from(body))?;
+        *request.headers_mut() = self.headers.clone();
+        request.headers_mut().extend(headers);
+        if is_put {
+            request
+                .headers_mut()
+                .insert(CONTENT_LENGTH, HeaderValue::from(body_len));
+        }
+        Ok(self.client.request(request).await?)
+    }
+
+    /// Write `value` at `key`. Returns false if `if_absent` is set and the key already exists.
+    async fn put_object(&self, key: &str, value: Bytes, if_absent: bool) -> Result<bool> {
+        let mut headers = HeaderMap::new();
+        if if_absent {
+            headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+        }
+        let response = self
+            .send(Method::PUT, &url_for(&self.put_url, key), headers, value)
+            .await?;
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            StatusCode::PRECONDITION_FAILED => Ok(false),
+            _ => Err(response_error(key, response).await),
+        }
+    }
+}
+
+/// Fill `template` with the percent-encoded `key`.
+fn url_for(template: &str, key: &str) -> String {
+    template.replace(
+        KEY_PLACEHOLDER,
+        &utf8_percent_encode(key, KEY_ENCODE_SET).to_string(),
+    )
+}
+
+/// A request which the server answered with an unexpected status.
+#[derive(Debug, Error)]
+#[error("HTTP request for {key} failed with {status}: {body}")]
+struct ResponseError {
+    key: String,
+    status: StatusCode,
+    body: String,
+}
+
+async fn response_error(key: &str, response: hyper::Response<Body>) -> Error {
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    ResponseError {
+        key: key.to_string(),
+        status,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    }
+    .into()
+}
+
+/// Whether an operation of `HttpBlob` which failed with `error` may succeed if retried: the
+/// connection failed, or the server throttled the request or failed to serve it.
+pub fn is_retryable(error: &Error) -> bool {
+    error.chain().any(|e| {
+        e.is::<hyper::Error>()
+            || e.downcast_ref::<ResponseError>().map_or(false, |e| {
+                e.status == StatusCode::TOO_MANY_REQUESTS || e.status.is_server_error()
+            })
+    })
+}
+
+#[async_trait]
+impl Blobstore for HttpBlob {
+    async fn get<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let response = self
+            .send(
+                Method::GET,
+                &url_for(&self.get_url, key),
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::GONE => return Ok(None),
+            status if status.is_success() => {}
+            _ => return Err(response_error(key, response).await),
+        }
+
+        let ctime = response
+            .headers()
+            .get(LAST_MODIFIED)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+            .map(|date| date.timestamp());
+        let data = hyper::body::to_bytes(response.into_body()).await?;
+        Ok(Some(BlobstoreGetData::new(
+            BlobstoreMetadata::new(ctime),
+            BlobstoreBytes::from_bytes(data),
+        )))
+    }
+
+    async fn is_present<'a>(&'a self, _ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        let response = self
+            .send(
+                Method::HEAD,
+                &url_for(&self.get_url, key),
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::GONE => Ok(false),
+            status if status.is_success() => Ok(true),
+            _ => Err(response_error(key, response).await),
+        }
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for HttpBlob {
+    async fn put_explicit<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        let value = value.into_bytes();
+        let status = match put_behaviour {
+            PutBehaviour::Overwrite => {
+                self.put_object(&key, value, false).await?;
+                OverwriteStatus::NotChecked
+            }
+            PutBehaviour::IfAbsent | PutBehaviour::OverwriteAndLog => {
+                if self.put_object(&key, value.clone(), true).await? {
+                    OverwriteStatus::New
+                } else if put_behaviour.should_overwrite() {
+                    self.put_object(&key, value, false).await?;
+                    OverwriteStatus::Overwrote
+                } else {
+                    OverwriteStatus::Prevented
+                }
+            }
+        };
+        Ok(status)
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.put_explicit(ctx, key, value, self.put_behaviour).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_url_for() {
+        assert_eq!(
+            url_for("https://cache/blobs/{key}", "repo0000.content.blake2.ab/c"),
+            "https://cache/blobs/repo0000.content.blake2.ab%2Fc"
+        );
+    }
+}
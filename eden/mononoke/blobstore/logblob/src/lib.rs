@@ -64,7 +64,7 @@ impl<B: Blobstore + BlobstorePutOps> Blobstore for LogBlob<B> {
             stats,
             result.as_ref(),
             key,
-            ctx.metadata().session_id().as_str(),
+            &ctx,
             OperationType::Get,
             None,
         );
@@ -118,7 +118,7 @@ impl<B: BlobstorePutOps> LogBlob<B> {
             stats,
             result.as_ref(),
             &key,
-            ctx.metadata().session_id().as_str(),
+            &ctx,
             OperationType::Put,
             size,
             None,
@@ -8,14 +8,15 @@
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{format_err, Result};
 use async_trait::async_trait;
 use futures::future::{BoxFuture, FutureExt};
 
 use blobstore::{
-    Blobstore, BlobstoreGetData, BlobstorePutOps, BlobstoreWithLink, OverwriteStatus, PutBehaviour,
-    DEFAULT_PUT_BEHAVIOUR,
+    Blobstore, BlobstoreGetData, BlobstorePutOps, BlobstoreWithLink, BlobstoreWithTtl,
+    OverwriteStatus, PutBehaviour, DEFAULT_PUT_BEHAVIOUR,
 };
 use context::CoreContext;
 use mononoke_types::BlobstoreBytes;
@@ -26,6 +27,8 @@ struct MemState {
     next_id: usize,
     data: HashMap<usize, BlobstoreBytes>,
     links: HashMap<String, usize>,
+    // When the blobs put with a TTL expire
+    expiry: HashMap<usize, Instant>,
 }
 
 impl MemState {
@@ -44,7 +47,8 @@ impl MemState {
                 OverwriteStatus::NotChecked
             }
             PutBehaviour::IfAbsent | PutBehaviour::OverwriteAndLog => {
-                if self.links.contains_key(&key) {
+                // Expired blobs are absent
+                if self.get(&key).is_some() {
                     if put_behaviour.should_overwrite() {
                         self.put(key, value, PutBehaviour::Overwrite);
                         OverwriteStatus::Overwrote
@@ -59,6 +63,33 @@ impl MemState {
         }
     }
 
+    fn put_with_ttl(
+        &mut self,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+        ttl: Option<Duration>,
+    ) {
+        let now = Instant::now();
+        // Drop the expired blobs, so that memory is reclaimed as blobs are written
+        let data = &mut self.data;
+        self.expiry.retain(|id, expires_at| {
+            let expired = *expires_at <= now;
+            if expired {
+                data.remove(id);
+            }
+            !expired
+        });
+
+        let status = self.put(key, value, put_behaviour);
+        match (ttl, status) {
+            (_, OverwriteStatus::Prevented) | (None, _) => {}
+            (Some(ttl), _) => {
+                self.expiry.insert(self.next_id - 1, now + ttl);
+            }
+        }
+    }
+
     fn link(&mut self, existing_key: &str, link_key: String) -> Result<()> {
         if let Some(existing_id) = self.links.get(existing_key) {
             let existing_id = *existing_id;
@@ -69,10 +100,10 @@ impl MemState {
     }
 
     fn get(&self, key: &str) -> Option<&BlobstoreBytes> {
-        if let Some(id) = self.links.get(key) {
-            self.data.get(id)
-        } else {
-            None
+        let id = self.links.get(key)?;
+        match self.expiry.get(id) {
+            Some(expires_at) if *expires_at <= Instant::now() => None,
+            _ => self.data.get(id),
         }
     }
 
@@ -179,6 +210,23 @@ impl BlobstoreWithLink for Memblob {
     }
 }
 
+#[async_trait]
+impl BlobstoreWithTtl for Memblob {
+    async fn put_with_ttl<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let state = self.state.clone();
+
+        let mut inner = state.lock().expect("lock poison");
+        inner.put_with_ttl(key, value, self.put_behaviour, ttl);
+        Ok(())
+    }
+}
+
 impl fmt::Debug for Memblob {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Memblob")
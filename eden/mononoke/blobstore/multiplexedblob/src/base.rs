@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use crate::health::BlobstoreHealth;
 use anyhow::{anyhow, Error, Result};
 use async_trait::async_trait;
 use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
@@ -13,7 +14,8 @@ use blobstore_sync_queue::OperationKey;
 use cloned::cloned;
 use context::{CoreContext, PerfCounterType, SessionClass};
 use futures::{
-    future::{join_all, select, Either as FutureEither},
+    channel::oneshot,
+    future::{join_all, select, Either as FutureEither, FutureExt},
     stream::{FuturesUnordered, StreamExt, TryStreamExt},
 };
 use futures_stats::TimedFutureExt;
@@ -62,6 +64,10 @@ pub enum ErrorKind {
     SomeMissingItem(Arc<BlobstoresReturnedNone>, Option<BlobstoreGetData>),
     #[error("Multiple failures on put: {0:?}")]
     MultiplePutFailures(Arc<BlobstoresReturnedError>),
+    #[error(
+        "Not enough blobstores agree on this item: need {0}, at most {1} agree, errors: {2:?}"
+    )]
+    ReadQuorumNotMet(usize, usize, Arc<BlobstoresReturnedError>),
 }
 
 /// This handler is called on each successful put to underlying blobstore,
@@ -80,6 +86,7 @@ pub trait MultiplexedBlobstorePutHandler: Send + Sync {
     ) -> Result<()>;
 }
 
+#[derive(Clone)]
 pub struct MultiplexedBlobstoreBase {
     multiplex_id: MultiplexId,
     /// These are the "normal" blobstores, which are read from on `get`, and written to on `put`
@@ -100,9 +107,22 @@ pub struct MultiplexedBlobstoreBase {
     /// blobstore wins the `put` race).
     /// Note that if this is bigger than the number of blobstores, we will always fail writes
     minimum_successful_writes: NonZeroUsize,
+    /// At least this many blobstores have to return the same value before we consider a `get`
+    /// successful, or return true before `is_present` does. With 1, the first blobstore to answer
+    /// wins.
+    minimum_successful_reads: NonZeroUsize,
     handler: Arc<dyn MultiplexedBlobstorePutHandler>,
     scuba: MononokeScubaSampleBuilder,
     scuba_sample_rate: NonZeroU64,
+    /// If set, reads go to the healthiest blobstores first, instead of to all of them at once.
+    health_based_reads: Option<HealthBasedReads>,
+}
+
+#[derive(Clone)]
+struct HealthBasedReads {
+    health: Arc<BlobstoreHealth>,
+    /// How long to wait for the preferred blobstores before also reading from the others
+    hedge_delay: Duration,
 }
 
 fn write_mostly_error(
@@ -126,6 +146,7 @@ impl MultiplexedBlobstoreBase {
         blobstores: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
         write_mostly_blobstores: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
         minimum_successful_writes: NonZeroUsize,
+        minimum_successful_reads: NonZeroUsize,
         handler: Arc<dyn MultiplexedBlobstorePutHandler>,
         mut scuba: MononokeScubaSampleBuilder,
         scuba_sample_rate: NonZeroU64,
@@ -137,9 +158,29 @@ impl MultiplexedBlobstoreBase {
             blobstores: blobstores.into(),
             write_mostly_blobstores: write_mostly_blobstores.into(),
             minimum_successful_writes,
+            minimum_successful_reads,
             handler,
             scuba,
             scuba_sample_rate,
+            health_based_reads: None,
+        }
+    }
+
+    /// Read from the normal blobstores in order of their observed health, rather than from
+    /// all of them at once. Only as many blobstores as are needed for a successful read are
+    /// asked straight away; the others are asked once `hedge_delay` has passed, or as soon
+    /// as one of the asked blobstores fails or does not have the blob.
+    pub fn with_health_based_reads(self, hedge_delay: Duration) -> Self {
+        let health = BlobstoreHealth::new(
+            self.multiplex_id,
+            self.blobstores.iter().map(|(id, _)| *id).collect(),
+        );
+        Self {
+            health_based_reads: Some(HealthBasedReads {
+                health: Arc::new(health),
+                hedge_delay,
+            }),
+            ..self
         }
     }
 
@@ -158,6 +199,7 @@ impl MultiplexedBlobstoreBase {
                 key,
                 OperationType::ScrubGet,
                 scuba.clone(),
+                None,
             )
             .chain(multiplexed_get(
                 ctx,
@@ -165,6 +207,7 @@ impl MultiplexedBlobstoreBase {
                 key,
                 OperationType::ScrubGet,
                 scuba,
+                None,
             )),
         )
         .await;
@@ -266,7 +309,7 @@ pub async fn inner_put(
         stats,
         result.as_ref(),
         &key,
-        ctx.metadata().session_id().as_str(),
+        ctx,
         OperationType::Put,
         size,
         Some(blobstore_id),
@@ -280,14 +323,39 @@ async fn blobstore_get<'a>(
     blobstores: Arc<[(BlobstoreId, Arc<dyn BlobstorePutOps>)]>,
     write_mostly_blobstores: Arc<[(BlobstoreId, Arc<dyn BlobstorePutOps>)]>,
     key: &'a str,
+    minimum_successful_reads: NonZeroUsize,
     scuba: MononokeScubaSampleBuilder,
+    health_based_reads: Option<HealthBasedReads>,
 ) -> Result<Option<BlobstoreGetData>, Error> {
     let is_logged = scuba.sampling().is_logged();
     let blobstores_count = blobstores.len() + write_mostly_blobstores.len();
+    let needed_reads = minimum_successful_reads.get();
+
+    // With health based reads, the blobstores beyond the first `needed_reads` wait for this
+    // gate, which opens after the hedge delay, or once `release` is sent to.
+    let (blobstores, health, mut release, gate) = match health_based_reads {
+        Some(HealthBasedReads {
+            health,
+            hedge_delay,
+        }) => {
+            let order = health.read_order(ctx.logger());
+            let mut ordered = blobstores.to_vec();
+            ordered.sort_by_key(|(id, _)| order.iter().position(|ordered_id| ordered_id == id));
+            let (release, released) = oneshot::channel::<()>();
+            let gate = select(tokio::time::delay_for(hedge_delay).boxed(), released)
+                .map(|_| ())
+                .boxed()
+                .shared();
+            (ordered.into(), Some(health), Some(release), Some(gate))
+        }
+        None => (blobstores, None, None, None),
+    };
 
     let (stats, result) = {
         async move {
             let mut errors = HashMap::new();
+            // Number of blobstores which returned each value, by content hash
+            let mut agreeing = HashMap::new();
             ctx.perf_counters()
                 .increment_counter(PerfCounterType::BlobGets);
 
@@ -297,7 +365,18 @@ async fn blobstore_get<'a>(
                 key.to_owned(),
                 OperationType::Get,
                 scuba.clone(),
+                health.clone(),
             )
+            .enumerate()
+            .map(|(index, request)| {
+                let gate = gate.clone().filter(|_| index >= needed_reads);
+                async move {
+                    if let Some(gate) = gate {
+                        gate.await;
+                    }
+                    request.await
+                }
+            })
             .collect();
             let write_mostly_requests: FuturesUnordered<_> = multiplexed_get(
                 ctx.clone(),
@@ -305,6 +384,7 @@ async fn blobstore_get<'a>(
                 key.to_owned(),
                 OperationType::Get,
                 scuba,
+                health,
             )
             .collect();
 
@@ -314,13 +394,22 @@ async fn blobstore_get<'a>(
             while let Some(result) = requests.next().await {
                 match result {
                     (_, Ok(Some(mut value))) => {
+                        if needed_reads > 1 {
+                            let mut content_hash = XxHash::with_seed(0);
+                            content_hash.write(value.as_raw_bytes());
+                            let count = agreeing.entry(content_hash.finish()).or_insert(0);
+                            *count += 1;
+                            if *count < needed_reads {
+                                continue;
+                            }
+                        }
                         if is_logged {
                             // Allow the other requests to complete so that we can record some
                             // metrics for the blobstore. This will also log metrics for write-mostly
                             // blobstores, which helps us decide whether they're good
                             tokio::spawn(requests.for_each(|_| async {}));
                         }
-                        // Return the blob that won the race
+                        // Return the blob that won the race, or reached the quorum first
                         value.remove_ctime();
                         return Ok(Some(value));
                     }
@@ -329,9 +418,21 @@ async fn blobstore_get<'a>(
                     }
                     (_, Ok(None)) => {}
                 }
+                // This blobstore could not provide the blob, so stop waiting for the hedge
+                // delay before asking the others
+                if let Some(release) = release.take() {
+                    let _ = release.send(());
+                }
             }
 
-            if errors.is_empty() {
+            if let Some(most_agreeing) = agreeing.values().max() {
+                // Some blobstores have the blob, but not enough of them agree on its value
+                Err(ErrorKind::ReadQuorumNotMet(
+                    needed_reads,
+                    *most_agreeing,
+                    Arc::new(errors),
+                ))
+            } else if errors.is_empty() {
                 // All blobstores must have returned None, as Some would have triggered a return,
                 Ok(None)
             } else if errors.len() == blobstores_count {
@@ -401,11 +502,21 @@ impl Blobstore for MultiplexedBlobstoreBase {
         let write_mostly_blobstores = self.write_mostly_blobstores.clone();
         scuba.sampled(self.scuba_sample_rate);
 
-        blobstore_get(ctx, blobstores, write_mostly_blobstores, key, scuba).await
+        blobstore_get(
+            ctx,
+            blobstores,
+            write_mostly_blobstores,
+            key,
+            self.minimum_successful_reads,
+            scuba,
+            self.health_based_reads.clone(),
+        )
+        .await
     }
 
     async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
         let blobstores_count = self.blobstores.len() + self.write_mostly_blobstores.len();
+        let needed_reads = self.minimum_successful_reads.get();
 
         let main_requests: FuturesUnordered<_> = self
             .blobstores
@@ -431,12 +542,16 @@ impl Blobstore for MultiplexedBlobstoreBase {
             let blobstores = &self.blobstores;
             async move {
                 let mut errors = HashMap::new();
+                let mut present = 0;
                 ctx.perf_counters()
                     .increment_counter(PerfCounterType::BlobPresenceChecks);
                 while let Some(result) = requests.next().await {
                     match result {
                         (_, Ok(true)) => {
-                            return Ok(true);
+                            present += 1;
+                            if present >= needed_reads {
+                                return Ok(true);
+                            }
                         }
                         (blobstore_id, Err(error)) => {
                             errors.insert(blobstore_id, error);
@@ -444,7 +559,13 @@ impl Blobstore for MultiplexedBlobstoreBase {
                         (_, Ok(false)) => {}
                     }
                 }
-                if errors.is_empty() {
+                if present > 0 {
+                    Err(ErrorKind::ReadQuorumNotMet(
+                        needed_reads,
+                        present,
+                        Arc::new(errors),
+                    ))
+                } else if errors.is_empty() {
                     Ok(false)
                 } else if errors.len() == blobstores_count {
                     Err(ErrorKind::AllFailed(Arc::new(errors)))
@@ -660,6 +781,7 @@ async fn multiplexed_get_one<'a>(
     key: &'a str,
     operation: OperationType,
     mut scuba: MononokeScubaSampleBuilder,
+    health: Option<Arc<BlobstoreHealth>>,
 ) -> (BlobstoreId, Result<Option<BlobstoreGetData>, Error>) {
     let (pc, (stats, timeout_or_res)) = {
         let pc = ctx.fork_perf_counters();
@@ -669,13 +791,16 @@ async fn multiplexed_get_one<'a>(
         (pc, ret)
     };
     let result = remap_timeout_result(timeout_or_res);
+    if let Some(health) = health {
+        health.record(blobstore_id, stats.completion_time, result.is_err());
+    }
     record_get_stats(
         &mut scuba,
         &pc,
         stats,
         result.as_ref(),
         key,
-        ctx.metadata().session_id().as_str(),
+        &ctx,
         operation,
         Some(blobstore_id),
     );
@@ -688,12 +813,13 @@ fn multiplexed_get<'fut: 'iter, 'iter>(
     key: impl Borrow<str> + Clone + 'fut,
     operation: OperationType,
     scuba: MononokeScubaSampleBuilder,
+    health: Option<Arc<BlobstoreHealth>>,
 ) -> impl Iterator<
     Item = impl Future<Output = (BlobstoreId, Result<Option<BlobstoreGetData>, Error>)> + 'fut,
 > + 'iter {
     blobstores.iter().map(move |(blobstore_id, blobstore)| {
         let ctx = ctx.borrow().clone();
-        cloned!(blobstore, blobstore_id, key, scuba);
+        cloned!(blobstore, blobstore_id, key, scuba, health);
         async move {
             multiplexed_get_one(
                 ctx,
@@ -702,6 +828,7 @@ fn multiplexed_get<'fut: 'iter, 'iter>(
                 key.borrow(),
                 operation,
                 scuba,
+                health,
             )
             .await
         }
@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use metaconfig_types::{BlobstoreId, MultiplexId};
+use slog::{info, Logger};
+
+/// Weight given to each new sample in the moving averages
+const SMOOTHING: f64 = 0.1;
+/// How much worse an erroring store looks than a merely slow one: a store
+/// failing every request is treated as this many times slower
+const ERROR_PENALTY: f64 = 10.0;
+/// The preferred store must be this many times worse than the best one
+/// before the read order changes, so that noise does not flip it back and
+/// forth
+const HYSTERESIS: f64 = 1.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct StoreHealth {
+    latency_ms: f64,
+    error_rate: f64,
+}
+
+impl StoreHealth {
+    fn score(&self) -> f64 {
+        self.latency_ms * (1.0 + ERROR_PENALTY * self.error_rate)
+    }
+}
+
+struct HealthState {
+    stores: HashMap<BlobstoreId, StoreHealth>,
+    read_order: Vec<BlobstoreId>,
+}
+
+/// Tracks the error rate and latency of each of the blobstores in a
+/// multiplex, and orders the normal blobstores so that reads go to the
+/// healthiest, fastest store first.
+pub struct BlobstoreHealth {
+    multiplex_id: MultiplexId,
+    state: Mutex<HealthState>,
+}
+
+impl BlobstoreHealth {
+    /// Start with the configured order, which is kept until there are
+    /// samples showing another store to be healthier.
+    pub fn new(multiplex_id: MultiplexId, blobstore_ids: Vec<BlobstoreId>) -> Self {
+        Self {
+            multiplex_id,
+            state: Mutex::new(HealthState {
+                stores: HashMap::new(),
+                read_order: blobstore_ids,
+            }),
+        }
+    }
+
+    /// Record the outcome of one request to a blobstore
+    pub fn record(&self, blobstore_id: BlobstoreId, latency: Duration, is_error: bool) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let error = if is_error { 1.0 } else { 0.0 };
+        let mut state = self.state.lock().expect("lock poisoned");
+        state
+            .stores
+            .entry(blobstore_id)
+            .and_modify(|health| {
+                health.latency_ms += SMOOTHING * (latency_ms - health.latency_ms);
+                health.error_rate += SMOOTHING * (error - health.error_rate);
+            })
+            .or_insert(StoreHealth {
+                latency_ms,
+                error_rate: error,
+            });
+    }
+
+    /// The order in which to read from the blobstores, best first. The order
+    /// only changes when the currently preferred store has become clearly
+    /// worse than another one, and every change is logged.
+    pub fn read_order(&self, logger: &Logger) -> Vec<BlobstoreId> {
+        let mut state = self.state.lock().expect("lock poisoned");
+        let HealthState {
+            ref stores,
+            ref mut read_order,
+        } = *state;
+
+        // Stores without samples keep their place behind those with samples
+        let score = |id: &BlobstoreId| stores.get(id).map(StoreHealth::score);
+        let mut ranked = read_order.clone();
+        ranked.sort_by(|a, b| match (score(a), score(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+
+        let should_reorder = match (
+            read_order.first().and_then(score),
+            ranked.first().and_then(score),
+        ) {
+            (Some(current), Some(best)) => current > best * HYSTERESIS,
+            (None, Some(_)) => true,
+            _ => false,
+        };
+
+        if should_reorder && ranked != *read_order {
+            info!(
+                logger,
+                "Multiplex {} read order changed from {:?} to {:?}",
+                self.multiplex_id,
+                read_order,
+                ranked
+            );
+            *read_order = ranked;
+        }
+
+        read_order.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use slog::{o, Discard};
+
+    fn ids(ids: &[u64]) -> Vec<BlobstoreId> {
+        ids.iter().map(|id| BlobstoreId::new(*id)).collect()
+    }
+
+    #[test]
+    fn test_read_order() {
+        let logger = Logger::root(Discard, o!());
+        let health = BlobstoreHealth::new(MultiplexId::new(1), ids(&[0, 1, 2]));
+        assert_eq!(health.read_order(&logger), ids(&[0, 1, 2]));
+
+        // A slightly faster store does not take over
+        health.record(BlobstoreId::new(0), Duration::from_millis(12), false);
+        health.record(BlobstoreId::new(1), Duration::from_millis(10), false);
+        assert_eq!(health.read_order(&logger), ids(&[0, 1, 2]));
+
+        // A failing store is moved back
+        for _ in 0..10 {
+            health.record(BlobstoreId::new(0), Duration::from_millis(12), true);
+        }
+        assert_eq!(health.read_order(&logger), ids(&[1, 0, 2]));
+
+        // A much faster store takes over
+        health.record(BlobstoreId::new(2), Duration::from_millis(1), false);
+        assert_eq!(health.read_order(&logger), ids(&[2, 1, 0]));
+    }
+}
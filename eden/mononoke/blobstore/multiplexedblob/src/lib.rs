@@ -8,10 +8,13 @@
 #![deny(warnings)]
 
 pub mod base;
+mod health;
 pub mod queue;
+pub mod report;
 pub mod scrub;
 
 pub use crate::queue::MultiplexedBlobstore;
+pub use crate::report::{ScrubReport, ScrubStatus};
 pub use crate::scrub::{
     LoggingScrubHandler, ScrubAction, ScrubBlobstore, ScrubHandler, ScrubOptions,
 };
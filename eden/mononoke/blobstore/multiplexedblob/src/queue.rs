@@ -14,9 +14,11 @@ use context::CoreContext;
 use metaconfig_types::{BlobstoreId, MultiplexId};
 use mononoke_types::{BlobstoreBytes, DateTime};
 use scuba_ext::MononokeScubaSampleBuilder;
+use slog::debug;
 use std::fmt;
 use std::num::{NonZeroU64, NonZeroUsize};
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct MultiplexedBlobstore {
@@ -30,6 +32,7 @@ impl MultiplexedBlobstore {
         blobstores: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
         write_mostly_blobstores: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
         minimum_successful_writes: NonZeroUsize,
+        minimum_successful_reads: NonZeroUsize,
         queue: Arc<dyn BlobstoreSyncQueue>,
         scuba: MononokeScubaSampleBuilder,
         scuba_sample_rate: NonZeroU64,
@@ -43,6 +46,7 @@ impl MultiplexedBlobstore {
                 blobstores,
                 write_mostly_blobstores,
                 minimum_successful_writes,
+                minimum_successful_reads,
                 put_handler,
                 scuba,
                 scuba_sample_rate,
@@ -50,6 +54,38 @@ impl MultiplexedBlobstore {
             queue,
         }
     }
+
+    /// Read from the healthiest blobstores first, see
+    /// `MultiplexedBlobstoreBase::with_health_based_reads`.
+    pub fn with_health_based_reads(self, hedge_delay: Duration) -> Self {
+        Self {
+            blobstore: Arc::new(
+                self.blobstore
+                    .as_ref()
+                    .clone()
+                    .with_health_based_reads(hedge_delay),
+            ),
+            ..self
+        }
+    }
+}
+
+impl MultiplexedBlobstore {
+    // Blobs which are read while waiting to be healed are healed first. This is best effort and
+    // done in the background: counting the access must neither slow down nor change the result
+    // of the read.
+    fn record_access(&self, ctx: &CoreContext, entries: Vec<BlobstoreSyncQueueEntry>) {
+        let queue = self.queue.clone();
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = queue.record_access(&ctx, &entries).await {
+                debug!(
+                    ctx.logger(),
+                    "Failed to record access to unhealed blob: {:?}", e
+                );
+            }
+        });
+    }
 }
 
 impl fmt::Debug for MultiplexedBlobstore {
@@ -102,7 +138,10 @@ impl Blobstore for MultiplexedBlobstore {
         match result {
             Ok(value) => Ok(value),
             Err(error) => {
-                if let Some(ErrorKind::AllFailed(_)) = error.downcast_ref() {
+                // Not enough agreeing blobstores is an error whatever the queue holds
+                if let Some(ErrorKind::AllFailed(_)) | Some(ErrorKind::ReadQuorumNotMet(..)) =
+                    error.downcast_ref()
+                {
                     return Err(error);
                 }
                 // This means that some underlying blobstore returned error, and
@@ -114,6 +153,7 @@ impl Blobstore for MultiplexedBlobstore {
                 if entries.is_empty() {
                     Ok(None)
                 } else {
+                    self.record_access(ctx, entries);
                     Err(error)
                 }
             }
@@ -134,13 +174,17 @@ impl Blobstore for MultiplexedBlobstore {
         match result {
             Ok(value) => Ok(value),
             Err(error) => {
-                if let Some(ErrorKind::AllFailed(_)) = error.downcast_ref() {
+                // Not enough agreeing blobstores is an error whatever the queue holds
+                if let Some(ErrorKind::AllFailed(_)) | Some(ErrorKind::ReadQuorumNotMet(..)) =
+                    error.downcast_ref()
+                {
                     return Err(error);
                 }
                 let entries = self.queue.get(&ctx, &key).await?;
                 if entries.is_empty() {
                     Ok(false)
                 } else {
+                    self.record_access(ctx, entries);
                     Err(error)
                 }
             }
@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Context, Result};
+use metaconfig_types::BlobstoreId;
+use serde_derive::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// What the scrub found, or did, for a key in one of the inner stores
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrubStatus {
+    /// The key was missing and has been copied to the store
+    Repaired,
+    /// The key was missing, and copying it to the store failed
+    RepairFailed,
+    /// The key is missing, and scrub was only reporting
+    RepairRequired,
+    /// The key is missing, but was written recently enough to be within the scrub grace period
+    GraceSkipped,
+    /// The key is missing, but the healer queue has pending entries for it
+    PendingHeal,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScrubReportEntry<'a> {
+    pub key: &'a str,
+    pub blobstore_id: BlobstoreId,
+    pub status: ScrubStatus,
+    pub ctime: Option<i64>,
+}
+
+/// Machine readable record of a scrub, one JSON object per line, so that sweeps can be audited
+/// afterwards. Lines are flushed as they are written, so that the report is complete even if the
+/// scrub is interrupted.
+pub struct ScrubReport {
+    path: PathBuf,
+    writer: Mutex<LineWriter<File>>,
+}
+
+impl ScrubReport {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("While creating scrub report {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: Mutex::new(LineWriter::new(file)),
+        })
+    }
+
+    pub fn record(&self, entry: &ScrubReportEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let mut writer = self.writer.lock().expect("lock poisoned");
+        writer
+            .write_all(&line)
+            .with_context(|| format!("While writing scrub report {}", self.path.display()))
+    }
+}
+
+impl fmt::Debug for ScrubReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScrubReport")
+            .field("path", &self.path)
+            .finish()
+    }
+}
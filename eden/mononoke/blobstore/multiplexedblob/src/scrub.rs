@@ -8,6 +8,7 @@
 use crate::{
     base::{inner_put, ErrorKind, MultiplexedBlobstoreBase},
     queue::MultiplexedBlobstore,
+    report::{ScrubReport, ScrubReportEntry, ScrubStatus},
 };
 
 use anyhow::Result;
@@ -24,6 +25,7 @@ use mononoke_types::{BlobstoreBytes, Timestamp};
 use once_cell::sync::Lazy;
 use scuba_ext::MononokeScubaSampleBuilder;
 use slog::{info, warn};
+use stats::prelude::*;
 use std::cmp::max;
 use std::collections::HashMap;
 use std::fmt;
@@ -32,6 +34,15 @@ use std::sync::{atomic::AtomicUsize, Arc};
 use std::time::Duration;
 use strum_macros::{EnumString, EnumVariantNames, IntoStaticStr};
 
+define_stats! {
+    prefix = "mononoke.blobstore.scrub";
+    repaired: dynamic_timeseries("{}.repaired", (blobstore_id: String); Rate, Sum),
+    repair_failed: dynamic_timeseries("{}.repair_failed", (blobstore_id: String); Rate, Sum),
+    repair_required: dynamic_timeseries("{}.repair_required", (blobstore_id: String); Rate, Sum),
+    grace_skipped: dynamic_timeseries("{}.grace_skipped", (blobstore_id: String); Rate, Sum),
+    pending_heal: dynamic_timeseries("{}.pending_heal", (blobstore_id: String); Rate, Sum),
+}
+
 static HEAL_MAX_BACKLOG: Lazy<Duration> =
     Lazy::new(|| Duration::from_secs(ChronoDuration::days(7).num_seconds() as u64));
 
@@ -59,6 +70,8 @@ pub struct ScrubOptions {
     pub scrub_action: ScrubAction,
     pub scrub_handler: Arc<dyn ScrubHandler>,
     pub scrub_grace: Option<Duration>,
+    /// Where to record what was found for each key, in addition to the handler
+    pub scrub_report: Option<Arc<ScrubReport>>,
 }
 
 impl Default for ScrubOptions {
@@ -67,6 +80,7 @@ impl Default for ScrubOptions {
             scrub_action: ScrubAction::ReportOnly,
             scrub_handler: Arc::new(LoggingScrubHandler::new(false)) as Arc<dyn ScrubHandler>,
             scrub_grace: None,
+            scrub_report: None,
         }
     }
 }
@@ -134,6 +148,7 @@ impl ScrubBlobstore {
         blobstores: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
         write_mostly_blobstores: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
         minimum_successful_writes: NonZeroUsize,
+        minimum_successful_reads: NonZeroUsize,
         queue: Arc<dyn BlobstoreSyncQueue>,
         scuba: MononokeScubaSampleBuilder,
         scuba_sample_rate: NonZeroU64,
@@ -144,6 +159,7 @@ impl ScrubBlobstore {
             blobstores.clone(),
             write_mostly_blobstores.clone(),
             minimum_successful_writes,
+            minimum_successful_reads,
             queue.clone(),
             scuba.clone(),
             scuba_sample_rate,
@@ -171,6 +187,33 @@ impl fmt::Debug for ScrubBlobstore {
     }
 }
 
+// Count a scrub finding in stats, and add it to the report if there is one
+fn record_scrub(
+    scrub_options: &ScrubOptions,
+    key: &str,
+    blobstore_id: BlobstoreId,
+    status: ScrubStatus,
+    meta: &BlobstoreMetadata,
+) -> Result<()> {
+    let stat_key = (blobstore_id.to_string(),);
+    match status {
+        ScrubStatus::Repaired => STATS::repaired.add_value(1, stat_key),
+        ScrubStatus::RepairFailed => STATS::repair_failed.add_value(1, stat_key),
+        ScrubStatus::RepairRequired => STATS::repair_required.add_value(1, stat_key),
+        ScrubStatus::GraceSkipped => STATS::grace_skipped.add_value(1, stat_key),
+        ScrubStatus::PendingHeal => STATS::pending_heal.add_value(1, stat_key),
+    }
+    match &scrub_options.scrub_report {
+        Some(report) => report.record(&ScrubReportEntry {
+            key,
+            blobstore_id,
+            status,
+            ctime: meta.ctime(),
+        }),
+        None => Ok(()),
+    }
+}
+
 // Would be a closure, but async closures are unstable
 async fn put_and_mark_repaired(
     ctx: &CoreContext,
@@ -180,7 +223,7 @@ async fn put_and_mark_repaired(
     store: &dyn BlobstorePutOps,
     key: &str,
     value: &BlobstoreGetData,
-    scrub_handler: &dyn ScrubHandler,
+    scrub_options: &ScrubOptions,
 ) -> Result<()> {
     let (_, res) = inner_put(
         ctx,
@@ -195,7 +238,15 @@ async fn put_and_mark_repaired(
         Some(PutBehaviour::Overwrite),
     )
     .await;
-    scrub_handler.on_repair(&ctx, id, key, res.is_ok(), value.as_meta());
+    scrub_options
+        .scrub_handler
+        .on_repair(&ctx, id, key, res.is_ok(), value.as_meta());
+    let status = if res.is_ok() {
+        ScrubStatus::Repaired
+    } else {
+        ScrubStatus::RepairFailed
+    };
+    record_scrub(scrub_options, key, id, status, value.as_meta())?;
     res.map(|_status| ())
 }
 
@@ -233,6 +284,15 @@ async fn blobstore_get(
                 match (ctime_age, scrub_options.scrub_grace) {
                     // value written recently, within the grace period, so don't attempt repair
                     (Some(ctime_age), Some(scrub_grace)) if ctime_age < scrub_grace => {
+                        for id in missing_reads.iter() {
+                            record_scrub(
+                                scrub_options,
+                                key,
+                                *id,
+                                ScrubStatus::GraceSkipped,
+                                value.as_meta(),
+                            )?;
+                        }
                         return Ok(Some(value));
                     }
                     _ => {}
@@ -254,6 +314,14 @@ async fn blobstore_get(
                             // Key is missing in the store so needs repair
                             if entries.is_empty() {
                                 needs_repair.insert(*k, s.as_ref());
+                            } else {
+                                record_scrub(
+                                    scrub_options,
+                                    key,
+                                    *k,
+                                    ScrubStatus::PendingHeal,
+                                    value.as_meta(),
+                                )?;
                             }
                         }
                         None => {}
@@ -269,6 +337,13 @@ async fn blobstore_get(
                             false,
                             value.as_meta(),
                         );
+                        record_scrub(
+                            scrub_options,
+                            key,
+                            *id,
+                            ScrubStatus::RepairRequired,
+                            value.as_meta(),
+                        )?;
                     }
                 } else {
                     // inner_put to the stores that need it.
@@ -284,7 +359,7 @@ async fn blobstore_get(
                                 store,
                                 key,
                                 &value,
-                                scrub_options.scrub_handler.as_ref(),
+                                scrub_options,
                             )
                         })
                         .collect();
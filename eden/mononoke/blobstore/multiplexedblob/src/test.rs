@@ -11,10 +11,12 @@ use std::{
     future::Future,
     pin::Pin,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use crate::base::{MultiplexedBlobstoreBase, MultiplexedBlobstorePutHandler};
 use crate::queue::MultiplexedBlobstore;
+use crate::report::ScrubReport;
 use crate::scrub::{LoggingScrubHandler, ScrubAction, ScrubBlobstore, ScrubHandler, ScrubOptions};
 use anyhow::{bail, Result};
 use async_trait::async_trait;
@@ -40,6 +42,7 @@ use nonzero_ext::nonzero;
 use readonlyblob::ReadOnlyBlobstore;
 use scuba_ext::MononokeScubaSampleBuilder;
 use sql_construct::SqlConstruct;
+use tempdir::TempDir;
 
 pub struct Tickable<T> {
     pub storage: Arc<Mutex<HashMap<String, T>>>,
@@ -243,6 +246,7 @@ async fn scrub_blobstore_fetch_none(fb: FacebookInit) -> Result<()> {
         vec![(bid0, bs0.clone()), (bid1, bs1.clone())],
         vec![],
         nonzero!(1usize),
+        nonzero!(1usize),
         queue.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -250,6 +254,7 @@ async fn scrub_blobstore_fetch_none(fb: FacebookInit) -> Result<()> {
             scrub_action: ScrubAction::ReportOnly,
             scrub_handler: Arc::new(LoggingScrubHandler::new(false)) as Arc<dyn ScrubHandler>,
             scrub_grace: None,
+            scrub_report: None,
         },
     );
 
@@ -269,6 +274,7 @@ async fn scrub_blobstore_fetch_none(fb: FacebookInit) -> Result<()> {
         id: None,
         operation_key: OperationKey::gen(),
         blob_size: None,
+        access_count: 0,
     };
     queue.add(ctx, entry).await?;
 
@@ -290,6 +296,7 @@ async fn base(fb: FacebookInit) {
         ],
         vec![],
         nonzero!(1usize),
+        nonzero!(1usize),
         log.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -439,6 +446,7 @@ async fn multiplexed(fb: FacebookInit) {
         vec![(bid0, bs0.clone()), (bid1, bs1.clone())],
         vec![],
         nonzero!(1usize),
+        nonzero!(1usize),
         queue.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -523,6 +531,7 @@ async fn multiplexed_operation_keys(fb: FacebookInit) -> Result<()> {
         ],
         vec![],
         nonzero!(1usize),
+        nonzero!(1usize),
         queue.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -576,6 +585,7 @@ async fn multiplexed_blob_size(fb: FacebookInit) -> Result<()> {
         ],
         vec![],
         nonzero!(1usize),
+        nonzero!(1usize),
         queue.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -620,6 +630,7 @@ async fn scrubbed(fb: FacebookInit) {
         vec![(bid0, bs0.clone()), (bid1, bs1.clone())],
         vec![],
         nonzero!(1usize),
+        nonzero!(1usize),
         queue.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -627,6 +638,7 @@ async fn scrubbed(fb: FacebookInit) {
             scrub_action: ScrubAction::ReportOnly,
             scrub_handler: scrub_handler.clone(),
             scrub_grace: None,
+            scrub_report: None,
         },
     );
 
@@ -688,11 +700,15 @@ async fn scrubbed(fb: FacebookInit) {
     // Now replace bs1 with an empty blobstore, and see the scrub work
     let bid1 = BlobstoreId::new(1);
     let bs1 = Arc::new(Tickable::new());
+    let report_dir = TempDir::new("scrub_report").unwrap();
+    let report_path = report_dir.path().join("report.jsonl");
+    let report = Arc::new(ScrubReport::create(&report_path).unwrap());
     let bs = ScrubBlobstore::new(
         MultiplexId::new(1),
         vec![(bid0, bs0.clone()), (bid1, bs1.clone())],
         vec![],
         nonzero!(1usize),
+        nonzero!(1usize),
         queue.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -700,6 +716,7 @@ async fn scrubbed(fb: FacebookInit) {
             scrub_action: ScrubAction::Repair,
             scrub_handler,
             scrub_grace: None,
+            scrub_report: Some(report),
         },
     );
 
@@ -763,6 +780,11 @@ async fn scrubbed(fb: FacebookInit) {
         // Now both populated.
         assert_eq!(bs0.storage.with(|s| s.get(k1).cloned()), Some(v1.clone()));
         assert_eq!(bs1.storage.with(|s| s.get(k1).cloned()), Some(v1.clone()));
+        // And the repair is in the report
+        assert_eq!(
+            std::fs::read_to_string(&report_path).unwrap(),
+            "{\"key\":\"k1\",\"blobstore_id\":1,\"status\":\"repaired\",\"ctime\":null}\n"
+        );
     }
 }
 
@@ -781,6 +803,7 @@ async fn queue_waits(fb: FacebookInit) {
         ],
         vec![],
         nonzero!(1usize),
+        nonzero!(1usize),
         log.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -881,6 +904,7 @@ async fn write_mostly_get(fb: FacebookInit) {
         vec![(BlobstoreId::new(0), main_bs.clone())],
         vec![(BlobstoreId::new(1), write_mostly_bs.clone())],
         nonzero!(1usize),
+        nonzero!(1usize),
         log.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -991,6 +1015,7 @@ async fn write_mostly_put(fb: FacebookInit) {
         vec![(BlobstoreId::new(0), main_bs.clone())],
         vec![(BlobstoreId::new(1), write_mostly_bs.clone())],
         nonzero!(1usize),
+        nonzero!(1usize),
         log.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -1170,6 +1195,7 @@ async fn needed_writes(fb: FacebookInit) {
         ],
         vec![(BlobstoreId::new(1), write_mostly_bs.clone())],
         nonzero!(2usize),
+        nonzero!(1usize),
         log.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -1298,6 +1324,7 @@ async fn needed_writes_bad_config(fb: FacebookInit) {
         ],
         vec![(BlobstoreId::new(1), write_mostly_bs.clone())],
         nonzero!(5usize),
+        nonzero!(1usize),
         log.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -1341,6 +1368,7 @@ async fn no_handlers(fb: FacebookInit) {
         ],
         vec![],
         nonzero!(1usize),
+        nonzero!(1usize),
         log.clone(),
         MononokeScubaSampleBuilder::with_discard(),
         nonzero!(1u64),
@@ -1432,3 +1460,166 @@ async fn no_handlers(fb: FacebookInit) {
         clear();
     }
 }
+
+#[fbinit::test]
+async fn needed_reads(fb: FacebookInit) {
+    let bs0 = Arc::new(Tickable::new());
+    let bs1 = Arc::new(Tickable::new());
+    let bs2 = Arc::new(Tickable::new());
+    let log = Arc::new(LogHandler::new());
+    let bs = MultiplexedBlobstoreBase::new(
+        MultiplexId::new(1),
+        vec![
+            (BlobstoreId::new(0), bs0.clone()),
+            (BlobstoreId::new(1), bs1.clone()),
+            (BlobstoreId::new(2), bs2.clone()),
+        ],
+        vec![],
+        nonzero!(1usize),
+        nonzero!(2usize),
+        log.clone(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    );
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+
+    let k = "k";
+    let v = make_value("v");
+
+    // Get waits for a second blobstore to return the same value
+    {
+        bs0.add_content(k.to_owned(), v.clone());
+        bs1.add_content(k.to_owned(), make_value("other"));
+        bs2.add_content(k.to_owned(), v.clone());
+
+        let mut get_fut = bs.get(ctx, k).map_err(|_| ()).boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs0.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs1.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs2.tick(None);
+        assert_eq!(get_fut.await.unwrap(), Some(v.clone().into()));
+
+        let mut present_fut = bs.is_present(ctx, k).map_err(|_| ()).boxed();
+        assert_eq!(
+            PollOnce::new(Pin::new(&mut present_fut)).await,
+            Poll::Pending
+        );
+        bs0.tick(None);
+        assert_eq!(
+            PollOnce::new(Pin::new(&mut present_fut)).await,
+            Poll::Pending
+        );
+        bs1.tick(None);
+        bs2.tick(None);
+        assert!(present_fut.await.unwrap());
+    }
+
+    // Fails if only one blobstore has the value
+    {
+        bs1.storage.with(|s| s.clear());
+        bs2.storage.with(|s| s.clear());
+
+        let mut get_fut = bs.get(ctx, k).map_err(|_| ()).boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs0.tick(None);
+        bs1.tick(None);
+        bs2.tick(None);
+        assert!(get_fut.await.is_err());
+
+        let mut present_fut = bs.is_present(ctx, k).map_err(|_| ()).boxed();
+        assert_eq!(
+            PollOnce::new(Pin::new(&mut present_fut)).await,
+            Poll::Pending
+        );
+        bs0.tick(None);
+        bs1.tick(None);
+        bs2.tick(None);
+        assert!(present_fut.await.is_err());
+    }
+
+    // Missing everywhere is still None
+    {
+        let mut get_fut = bs.get(ctx, "missing").map_err(|_| ()).boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs0.tick(None);
+        bs1.tick(None);
+        bs2.tick(None);
+        assert_eq!(get_fut.await.unwrap(), None);
+    }
+}
+
+#[fbinit::test]
+async fn health_based_reads(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+
+    let k = "k";
+    let v = make_value("v");
+
+    let make_blobstore = || {
+        let bs0 = Arc::new(Tickable::new());
+        let bs1 = Arc::new(Tickable::new());
+        let bs = MultiplexedBlobstoreBase::new(
+            MultiplexId::new(1),
+            vec![
+                (BlobstoreId::new(0), bs0.clone()),
+                (BlobstoreId::new(1), bs1.clone()),
+            ],
+            vec![],
+            nonzero!(1usize),
+            nonzero!(1usize),
+            Arc::new(LogHandler::new()),
+            MononokeScubaSampleBuilder::with_discard(),
+            nonzero!(1u64),
+        )
+        // Long enough that the hedge never fires during the test
+        .with_health_based_reads(Duration::from_secs(600));
+        (bs, bs0, bs1)
+    };
+
+    // Only the preferred blobstore is read from when it has the blob
+    {
+        let (bs, bs0, bs1) = make_blobstore();
+        bs0.add_content(k.to_owned(), v.clone());
+        bs1.add_content(k.to_owned(), v.clone());
+
+        let mut get_fut = bs.get(ctx, k).map_err(|_| ()).boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        // The second blobstore has not been asked, so ticking it does nothing
+        bs1.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs0.tick(None);
+        assert_eq!(get_fut.await.unwrap(), Some(v.clone().into()));
+    }
+
+    // The other blobstores are read from once the preferred one fails
+    {
+        let (bs, bs0, bs1) = make_blobstore();
+        bs1.add_content(k.to_owned(), v.clone());
+
+        let mut get_fut = bs.get(ctx, k).map_err(|_| ()).boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs1.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs0.tick(Some("bs0 failed"));
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs1.tick(None);
+        assert_eq!(get_fut.await.unwrap(), Some(v.clone().into()));
+    }
+
+    // ... or does not have the blob
+    {
+        let (bs, bs0, bs1) = make_blobstore();
+        bs1.add_content(k.to_owned(), v.clone());
+
+        let mut get_fut = bs.get(ctx, k).map_err(|_| ()).boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs0.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs1.tick(None);
+        assert_eq!(get_fut.await.unwrap(), Some(v.into()));
+    }
+}
@@ -5,10 +5,21 @@
  * GNU General Public License version 2.
  */
 
+// Zstandard blob compressed with a dictionary trained from values of the same
+// key family (e.g. all hgmanifest blobs), rather than from another version of
+// the same key. dict_key is the blobstore key the dictionary is stored under,
+// which is fetched on get() if not already cached. Dictionaries are content
+// addressed so the dictionary a key refers to never changes.
+struct ZstdFromSharedDictValue {
+    1: string dict_key,
+    2: binary zstd,
+}
+
 // Independent single data value.
 union SingleValue {
     1: binary Raw,
     2: binary Zstd,
+    3: ZstdFromSharedDictValue ZstdFromSharedDict,
 }
 
 // Represents dictionary encoded Zstandard blob. dict_key must point to a
@@ -0,0 +1,115 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Result};
+use bytes::Bytes;
+use mononoke_types::{hash::Context as HashContext, repo::REPO_PREFIX_REGEX};
+use std::io::{Cursor, Read, Write};
+
+// Trained dictionaries are put under keys starting with this, after the repo prefix if any, e.g.
+// "repo0000.zstd_dict.hgmanifest.<hash>". Like packs, they are left out of enumerations.
+pub const ZSTD_DICT_PREFIX: &str = "zstd_dict.";
+
+// Default size of a trained dictionary, as recommended by zstd
+pub const DEFAULT_DICT_SIZE: usize = 112640;
+
+// The family of a key is the kind of value it holds, e.g. "hgmanifest" or "content". Values
+// from the same family are similar enough to share a dictionary.
+pub fn key_family(key: &str) -> &str {
+    let key = match REPO_PREFIX_REGEX.find(key) {
+        Some(m) => &key[m.end()..],
+        None => key,
+    };
+    match key.find('.') {
+        Some(end) => &key[..end],
+        None => key,
+    }
+}
+
+// Dictionaries are content addressed, so that a key always refers to the same dictionary and
+// they can be cached forever.
+pub fn dict_key(prefix: &str, family: &str, dict: &[u8]) -> String {
+    let mut hash_context = HashContext::new(b"zstd_dict");
+    hash_context.update(dict);
+    format!(
+        "{}{}{}.{}",
+        prefix,
+        ZSTD_DICT_PREFIX,
+        family,
+        hash_context.finish().to_hex()
+    )
+}
+
+// Train a dictionary of at most `max_size` bytes from sample values of one family
+pub fn train(samples: &[Bytes], max_size: usize) -> Result<Bytes> {
+    if samples.is_empty() {
+        return Err(format_err!("No samples to train a dictionary from"));
+    }
+    let dict = zstd::dict::from_samples(samples, max_size)?;
+    Ok(Bytes::from(dict))
+}
+
+pub fn compress(value: &[u8], dict: &[u8], zstd_level: i32) -> Result<Vec<u8>> {
+    let mut encoder = zstd::stream::Encoder::with_dictionary(vec![], zstd_level, dict)?;
+    encoder.write_all(value)?;
+    Ok(encoder.finish()?)
+}
+
+pub fn decompress(zstd: &[u8], dict: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = zstd::stream::Decoder::with_dictionary(Cursor::new(zstd), dict)?;
+    let mut value = vec![];
+    decoder.read_to_end(&mut value)?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    #[test]
+    fn key_family_test() {
+        assert_eq!(key_family("repo0000.hgmanifest.sha1.abcd"), "hgmanifest");
+        assert_eq!(key_family("content.blake2.abcd"), "content");
+        assert_eq!(key_family("repo0123.nodots"), "nodots");
+    }
+
+    #[test]
+    fn train_and_roundtrip_test() -> Result<()> {
+        let mut rng = XorShiftRng::seed_from_u64(0); // reproducable Rng
+
+        // Small values sharing most of their content, like tree entries
+        let make_value = |rng: &mut XorShiftRng| {
+            let mut value = vec![];
+            for i in 0..20 {
+                value.extend_from_slice(format!("dir/file{}\0", i).as_bytes());
+                let mut hash = [0u8; 20];
+                rng.fill(&mut hash);
+                value.extend_from_slice(&hash);
+            }
+            Bytes::from(value)
+        };
+        let samples: Vec<_> = (0..1000).map(|_| make_value(&mut rng)).collect();
+        let dict = train(&samples, 16384)?;
+
+        let value = make_value(&mut rng);
+        let with_dict = compress(&value, &dict, 0)?;
+        let without_dict = zstd::encode_all(Cursor::new(&value), 0)?;
+        assert!(with_dict.len() < without_dict.len());
+        assert_eq!(decompress(&with_dict, &dict)?, value.to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn dict_key_test() {
+        let key = dict_key("repo0000.", "hgmanifest", b"dict");
+        assert!(key.starts_with("repo0000.zstd_dict.hgmanifest."));
+        assert_eq!(key_family(&key), "zstd_dict");
+        assert_ne!(key, dict_key("repo0000.", "hgmanifest", b"other dict"));
+    }
+}
@@ -0,0 +1,186 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::dict::ZSTD_DICT_PREFIX;
+use crate::store::{PackBlob, ENVELOPE_SUFFIX};
+
+use anyhow::{format_err, Result};
+use async_trait::async_trait;
+use blobstore::{
+    BlobstoreEnumerationData, BlobstoreKeyParam, BlobstoreKeyRange, BlobstoreKeySource,
+    BlobstoreKeyToken, BlobstorePutOps,
+};
+use context::CoreContext;
+use mononoke_types::repo::REPO_PREFIX_REGEX;
+use serde_derive::{Deserialize, Serialize};
+
+// Packs are put under prefixes starting with this, e.g. "repo0000.packed.", by convention. They
+// are not keys of their own, so they are left out of enumerations.
+pub const PACK_PREFIX: &str = "packed";
+
+// The suffix changes the order of keys (e.g. "a" < "a-" but "a.pack" > "a-.pack"), so the inner
+// store is enumerated over a wider range than asked for, and its keys filtered. Continuing needs
+// both ranges, hence the opaque token.
+#[derive(Serialize, Deserialize)]
+struct PackEnumerationToken {
+    range: BlobstoreKeyRange,
+    inner: BlobstoreKeyParam,
+}
+
+// Smallest range of inner keys which contains the inner keys of all the keys in `range`
+fn inner_range(range: &BlobstoreKeyRange) -> BlobstoreKeyRange {
+    // All the keys before the end key have their inner key before it, except its prefixes
+    let end_key = if range.end_key.is_empty() {
+        String::new()
+    } else {
+        let end_key = &range.end_key;
+        end_key
+            .char_indices()
+            .map(|(i, _)| format!("{}{}\0", &end_key[..i], ENVELOPE_SUFFIX))
+            .chain(std::iter::once(end_key.clone()))
+            .max()
+            .unwrap_or_default()
+    };
+    BlobstoreKeyRange {
+        begin_key: range.begin_key.clone(),
+        end_key,
+    }
+}
+
+// Packs and shared dictionaries are stored by packblob itself, rather than put by its users
+fn is_internal(key: &str) -> bool {
+    let unprefixed = match REPO_PREFIX_REGEX.find(key) {
+        Some(m) => &key[m.end()..],
+        None => key,
+    };
+    unprefixed.starts_with(PACK_PREFIX) || unprefixed.starts_with(ZSTD_DICT_PREFIX)
+}
+
+#[async_trait]
+impl<T: BlobstoreKeySource + BlobstorePutOps> BlobstoreKeySource for PackBlob<T> {
+    async fn enumerate<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        range: &'a BlobstoreKeyParam,
+    ) -> Result<BlobstoreEnumerationData> {
+        let (range, inner) = match range {
+            BlobstoreKeyParam::Start(range) => {
+                (range.clone(), BlobstoreKeyParam::Start(inner_range(range)))
+            }
+            BlobstoreKeyParam::Continuation(BlobstoreKeyToken::StringToken(token)) => {
+                let token: PackEnumerationToken = serde_json::from_str(token)
+                    .map_err(|e| format_err!("Invalid packblob token {:?}: {}", token, e))?;
+                (token.range, token.inner)
+            }
+        };
+
+        let inner_data = self.inner.enumerate(ctx, &inner).await?;
+        let keys = inner_data
+            .keys
+            .into_iter()
+            .filter_map(|inner_key| {
+                let key = inner_key.strip_suffix(ENVELOPE_SUFFIX)?;
+                if range.contains(key) && !is_internal(key) {
+                    Some(key.to_string())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let next_token = match inner_data.next_token {
+            Some(inner) => {
+                let token = serde_json::to_string(&PackEnumerationToken { range, inner })?;
+                Some(BlobstoreKeyParam::Continuation(
+                    BlobstoreKeyToken::StringToken(token),
+                ))
+            }
+            None => None,
+        };
+        Ok(BlobstoreEnumerationData { keys, next_token })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::PackOptions;
+    use blobstore::{Blobstore, PutBehaviour};
+    use borrowed::borrowed;
+    use bytes::Bytes;
+    use fbinit::FacebookInit;
+    use fileblob::Fileblob;
+    use mononoke_types::BlobstoreBytes;
+    use packblob_thrift::{PackedEntry, PackedValue, SingleValue};
+    use std::collections::HashSet;
+    use tempdir::TempDir;
+
+    #[test]
+    fn inner_range_test() {
+        let range = inner_range(&BlobstoreKeyRange {
+            begin_key: "a".to_string(),
+            end_key: "a-b".to_string(),
+        });
+        assert!(range.contains("a.pack"));
+        assert!(range.contains("a-.pack"));
+        assert!(!range.contains("b.pack"));
+
+        let range = inner_range(&BlobstoreKeyRange::prefix("repo0000."));
+        assert!(range.contains("repo0000.content.pack"));
+        assert!(!range.contains("repo0001.content.pack"));
+    }
+
+    #[fbinit::test]
+    async fn enumerate_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let dir = TempDir::new("packblob_enumerate")?;
+        let inner_blobstore = Fileblob::open(&dir, PutBehaviour::Overwrite)?;
+        let packblob = PackBlob::new(inner_blobstore, PackOptions::default());
+
+        let value = BlobstoreBytes::from_bytes("appleveldata");
+        packblob
+            .put(ctx, "repo0000.a".to_string(), value.clone())
+            .await?;
+        packblob
+            .put(ctx, "repo0000.a-".to_string(), value.clone())
+            .await?;
+        packblob.put(ctx, "repo0001.a".to_string(), value).await?;
+        packblob
+            .put_packed(
+                ctx,
+                vec![PackedEntry {
+                    key: "repo0000.b".to_string(),
+                    data: PackedValue::Single(SingleValue::Raw(b"packed".to_vec())),
+                }],
+                "repo0000.packed.".to_string(),
+            )
+            .await?;
+        packblob
+            .put_dict(ctx, "repo0000.", "a", Bytes::from("dict"))
+            .await?;
+
+        let data = packblob
+            .enumerate(ctx, &BlobstoreKeyRange::prefix("repo0000.").into())
+            .await?;
+        let expected: HashSet<_> = vec!["repo0000.a", "repo0000.a-", "repo0000.b"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(data.keys, expected);
+        assert_eq!(data.next_token, None);
+
+        let data = packblob
+            .enumerate(
+                ctx,
+                &BlobstoreKeyParam::from("repo0000.a".to_string().."repo0000.a-".to_string()),
+            )
+            .await?;
+        let expected: HashSet<_> = vec!["repo0000.a".to_string()].into_iter().collect();
+        assert_eq!(data.keys, expected);
+        Ok(())
+    }
+}
@@ -7,8 +7,14 @@
 
 #![deny(warnings)]
 
+mod dict;
+mod enumerate;
 mod envelope;
 mod pack;
+mod repack;
 mod store;
 
+pub use dict::{key_family, train as train_dict, DEFAULT_DICT_SIZE, ZSTD_DICT_PREFIX};
+pub use enumerate::PACK_PREFIX;
+pub use repack::{RepackOptions, RepackStats};
 pub use store::{PackBlob, PackOptions};
@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use crate::dict;
 use crate::store;
 
 use anyhow::{format_err, Error};
@@ -12,21 +13,56 @@ use ascii::AsciiString;
 use blobstore::{BlobstoreGetData, BlobstoreMetadata};
 use bytes::Bytes;
 use mononoke_types::{hash::Context as HashContext, repo::REPO_PREFIX_REGEX, BlobstoreBytes};
-use packblob_thrift::{PackedEntry, PackedFormat, PackedValue, SingleValue, ZstdFromDictValue};
-use std::{collections::HashMap, io::Cursor};
-
+use packblob_thrift::{
+    PackedEntry, PackedFormat, PackedValue, SingleValue, StorageFormat, ZstdFromDictValue,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+};
+
+// `shared_dicts` are the trained dictionaries values compressed with a shared dictionary refer
+// to, by key. They must have been fetched beforehand, see `shared_dict_keys`.
 pub fn decode_independent(
     meta: BlobstoreMetadata,
     v: SingleValue,
+    shared_dicts: &HashMap<String, Bytes>,
 ) -> Result<BlobstoreGetData, Error> {
     match v {
         SingleValue::Raw(v) => Ok(BlobstoreGetData::new(meta, BlobstoreBytes::from_bytes(v))),
         SingleValue::Zstd(v) => Ok(zstd::decode_all(Cursor::new(v))
             .map(|v| BlobstoreGetData::new(meta, BlobstoreBytes::from_bytes(v)))?),
+        SingleValue::ZstdFromSharedDict(v) => match shared_dicts.get(&v.dict_key) {
+            Some(dict) => {
+                let v = dict::decompress(&v.zstd, dict)?;
+                Ok(BlobstoreGetData::new(meta, BlobstoreBytes::from_bytes(v)))
+            }
+            None => Err(format_err!("Shared dictionary {} not loaded", v.dict_key)),
+        },
         SingleValue::UnknownField(e) => Err(format_err!("SingleValue::UnknownField {:?}", e)),
     }
 }
 
+// Keys of the shared dictionaries needed to decode values stored as `storage`
+pub fn shared_dict_keys(storage: &StorageFormat) -> HashSet<String> {
+    let single_dict_key = |single: &SingleValue| match single {
+        SingleValue::ZstdFromSharedDict(v) => Some(v.dict_key.clone()),
+        _ => None,
+    };
+    match storage {
+        StorageFormat::Single(single) => single_dict_key(single).into_iter().collect(),
+        StorageFormat::Packed(packed) => packed
+            .entries
+            .iter()
+            .filter_map(|entry| match &entry.data {
+                PackedValue::Single(single) => single_dict_key(single),
+                _ => None,
+            })
+            .collect(),
+        StorageFormat::UnknownField(_) => HashSet::new(),
+    }
+}
+
 fn decode_zstd_from_dict(
     meta: BlobstoreMetadata,
     k: &str,
@@ -51,6 +87,7 @@ pub fn decode_pack(
     pack_meta: BlobstoreMetadata,
     packed: PackedFormat,
     key: &str,
+    shared_dicts: &HashMap<String, Bytes>,
 ) -> Result<BlobstoreGetData, Error> {
     // Strip repo prefix, if any
     let key = match REPO_PREFIX_REGEX.find(key) {
@@ -63,7 +100,7 @@ pub fn decode_pack(
     for entry in packed.entries {
         let current_key = entry.key;
         let value = match entry.data {
-            PackedValue::Single(v) => Some(decode_independent(pack_meta.clone(), v)?),
+            PackedValue::Single(v) => Some(decode_independent(pack_meta.clone(), v, shared_dicts)?),
             v => {
                 remaining_entries.push(PackedEntry {
                     key: current_key.clone(),
@@ -142,6 +179,7 @@ pub fn create_packed(entries: Vec<PackedEntry>) -> Result<PackedFormat, Error> {
 mod tests {
     use super::*;
     use bytes::Bytes;
+    use packblob_thrift::ZstdFromSharedDictValue;
     use rand::{Rng, RngCore, SeedableRng};
     use rand_xorshift::XorShiftRng;
 
@@ -156,7 +194,34 @@ mod tests {
         assert!(bytes.len() < bytes_in.len());
 
         // Test the decoder
-        let decoded = decode_independent(BlobstoreMetadata::new(None), SingleValue::Zstd(bytes))?;
+        let decoded = decode_independent(
+            BlobstoreMetadata::new(None),
+            SingleValue::Zstd(bytes),
+            &HashMap::new(),
+        )?;
+        assert_eq!(decoded.as_bytes().as_bytes(), &Bytes::from(bytes_in));
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_independent_shared_dict_test() -> Result<(), Error> {
+        let dict = Bytes::from(vec![7u8; 1024]);
+        let bytes_in = vec![7u8; 4096];
+        let value = SingleValue::ZstdFromSharedDict(ZstdFromSharedDictValue {
+            dict_key: "dict".to_string(),
+            zstd: dict::compress(&bytes_in, &dict, 0)?,
+        });
+
+        // The dictionary must have been loaded
+        assert!(
+            decode_independent(BlobstoreMetadata::new(None), value.clone(), &HashMap::new())
+                .is_err()
+        );
+
+        let mut dicts = HashMap::new();
+        dicts.insert("dict".to_string(), dict);
+        let decoded = decode_independent(BlobstoreMetadata::new(None), value, &dicts)?;
         assert_eq!(decoded.as_bytes().as_bytes(), &Bytes::from(bytes_in));
 
         Ok(())
@@ -244,7 +309,12 @@ mod tests {
 
         // Test reads roundtrip back to the raw form
         for i in 0..20 {
-            let value = decode_pack(BlobstoreMetadata::new(None), packed.clone(), &i.to_string())?;
+            let value = decode_pack(
+                BlobstoreMetadata::new(None),
+                packed.clone(),
+                &i.to_string(),
+                &HashMap::new(),
+            )?;
             assert_eq!(value.as_bytes().as_bytes().to_vec(), raw_data[i]);
         }
 
@@ -276,11 +346,21 @@ mod tests {
         );
 
         // See if we can get the data back
-        let value1 = decode_pack(BlobstoreMetadata::new(None), packed.clone(), "1")?;
+        let value1 = decode_pack(
+            BlobstoreMetadata::new(None),
+            packed.clone(),
+            "1",
+            &HashMap::new(),
+        )?;
         assert_eq!(value1.as_bytes().len(), 1024);
 
         // See if we get error for unknown key
-        let missing = decode_pack(BlobstoreMetadata::new(None), packed, "missing");
+        let missing = decode_pack(
+            BlobstoreMetadata::new(None),
+            packed,
+            "missing",
+            &HashMap::new(),
+        );
         assert!(missing.is_err());
 
         Ok(())
@@ -0,0 +1,264 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::envelope::PackEnvelope;
+use crate::pack;
+use crate::store::{compress_if_worthwhile, single_len, PackBlob, ENVELOPE_SUFFIX};
+
+use anyhow::{format_err, Context, Result};
+use blobstore::{Blobstore, BlobstoreMetadata, BlobstoreWithLink};
+use bytes::Bytes;
+use context::CoreContext;
+use mononoke_types::{repo::REPO_PREFIX_REGEX, BlobstoreBytes};
+use packblob_thrift::{
+    PackedEntry, PackedValue, StorageEnvelope, StorageFormat, ZstdFromDictValue,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    fmt,
+};
+
+#[derive(Clone, Debug)]
+pub struct RepackOptions {
+    // zstd level the repacked values are compressed at. 0 means use zstd default level.
+    pub zstd_level: i32,
+    // Packs with fewer entries than this are repacked, as are unpacked values.
+    pub min_pack_entries: usize,
+    // The new packs have at most this many entries.
+    pub max_pack_entries: usize,
+}
+
+impl Default for RepackOptions {
+    fn default() -> Self {
+        Self {
+            zstd_level: 0,
+            min_pack_entries: 2,
+            max_pack_entries: 100,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepackStats {
+    // Keys which were looked at
+    pub scanned: usize,
+    // Keys which were not in the store
+    pub missing: usize,
+    // Keys which were already in a large enough pack
+    pub skipped: usize,
+    // Keys which were moved to a new pack
+    pub repacked: usize,
+    // New packs written
+    pub packs: usize,
+    // Size of the blobs the repacked keys were stored in before
+    pub bytes_before: usize,
+    // Size of the new packs
+    pub bytes_after: usize,
+}
+
+impl fmt::Display for RepackStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "scanned {} keys: {} missing, {} already packed, {} repacked into {} packs ({} bytes before, {} bytes after)",
+            self.scanned,
+            self.missing,
+            self.skipped,
+            self.repacked,
+            self.packs,
+            self.bytes_before,
+            self.bytes_after
+        )
+    }
+}
+
+// Key as stored in a pack, without repo prefix
+fn unprefixed(key: &str) -> &str {
+    match REPO_PREFIX_REGEX.find(key) {
+        Some(m) => &key[m.end()..],
+        None => key,
+    }
+}
+
+// Encode `values` as pack entries. The first value is the dictionary the others are delta
+// compressed against, when that is smaller than compressing them independently.
+fn encode_entries(values: &[(String, Bytes)], zstd_level: i32) -> Result<Vec<PackedEntry>> {
+    let (base_key, base) = match values.first() {
+        Some(first) => first,
+        None => return Ok(vec![]),
+    };
+    let mut entries = vec![PackedEntry {
+        key: base_key.clone(),
+        data: PackedValue::Single(compress_if_worthwhile(base.clone(), zstd_level)?),
+    }];
+    for (key, value) in &values[1..] {
+        let single = compress_if_worthwhile(value.clone(), zstd_level)?;
+        let delta = zstdelta::diff(base, value)?;
+        let data = if delta.len() < single_len(&single) {
+            PackedValue::ZstdFromDict(ZstdFromDictValue {
+                dict_key: unprefixed(base_key).to_string(),
+                zstd: delta,
+            })
+        } else {
+            PackedValue::Single(single)
+        };
+        entries.push(PackedEntry {
+            key: key.clone(),
+            data,
+        });
+    }
+    Ok(entries)
+}
+
+impl<T: Blobstore + BlobstoreWithLink> PackBlob<T> {
+    // Move the values of `keys` which are unpacked or in packs smaller than
+    // `options.min_pack_entries` into new packs, put under `prefix`.
+    //
+    // Each new pack is decoded in memory and checked against the values it replaces before it
+    // is written, and is fully written before the keys are linked to it, so readers see either
+    // the old or the new storage of a key. The old blobs are not removed: on ref counted stores
+    // they go away once the last key linked to them is relinked, otherwise it is up to the
+    // store's garbage collection.
+    pub async fn repack<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        keys: Vec<String>,
+        prefix: String,
+        options: &'a RepackOptions,
+    ) -> Result<RepackStats> {
+        let mut stats = RepackStats::default();
+        let mut seen_packs = HashSet::new();
+        let mut values = vec![];
+
+        for key in keys {
+            stats.scanned += 1;
+            let inner_key = [key.as_str(), ENVELOPE_SUFFIX].concat();
+            let inner_get_data = match self.inner.get(ctx, &inner_key).await? {
+                Some(inner_get_data) => inner_get_data,
+                None => {
+                    stats.missing += 1;
+                    continue;
+                }
+            };
+            let inner_len = inner_get_data.as_bytes().len();
+            let meta = inner_get_data.as_meta().clone();
+            let envelope: PackEnvelope = inner_get_data.into_bytes().try_into()?;
+            let shared_dicts = self.get_shared_dicts(ctx, &envelope.0.storage).await?;
+            let value = match envelope.0.storage {
+                StorageFormat::Single(single) => {
+                    stats.bytes_before += inner_len;
+                    pack::decode_independent(meta, single, &shared_dicts)?
+                }
+                StorageFormat::Packed(packed) => {
+                    if packed.entries.len() >= options.min_pack_entries {
+                        stats.skipped += 1;
+                        continue;
+                    }
+                    // Several keys of a small pack share its blob
+                    if seen_packs.insert(packed.key.clone()) {
+                        stats.bytes_before += inner_len;
+                    }
+                    pack::decode_pack(meta, packed, &key, &shared_dicts)?
+                }
+                StorageFormat::UnknownField(e) => {
+                    return Err(format_err!("StorageFormat::UnknownField {:?}", e));
+                }
+            };
+            values.push((key, value.into_bytes().into_bytes()));
+        }
+
+        for chunk in values.chunks(options.max_pack_entries.max(1)) {
+            let entries = encode_entries(chunk, options.zstd_level)?;
+
+            // Check the new pack before it replaces anything
+            let packed = pack::create_packed(entries.clone())?;
+            for (key, value) in chunk {
+                // Repacked values are compressed independently of shared dictionaries
+                let decoded = pack::decode_pack(
+                    BlobstoreMetadata::new(None),
+                    packed.clone(),
+                    key,
+                    &HashMap::new(),
+                )
+                .with_context(|| format!("While verifying repacked {:?}", key))?;
+                if decoded.as_bytes().as_bytes() != value {
+                    return Err(format_err!("Repacked value of {:?} doesn't match", key));
+                }
+            }
+            let pack_bytes: BlobstoreBytes = PackEnvelope(StorageEnvelope {
+                storage: StorageFormat::Packed(packed),
+            })
+            .into();
+
+            self.put_packed(ctx, entries, prefix.clone()).await?;
+            stats.repacked += chunk.len();
+            stats.packs += 1;
+            stats.bytes_after += pack_bytes.len();
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::PackOptions;
+    use borrowed::borrowed;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+
+    #[fbinit::test]
+    async fn repack_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner_blobstore = Memblob::default();
+        let packblob = PackBlob::new(inner_blobstore.clone(), PackOptions::default());
+
+        // Similar values, stored independently and uncompressed
+        let mut keys = vec![];
+        let mut values = vec![];
+        for i in 0..5 {
+            let key = format!("repo0000.app_key{}", i);
+            let mut value = vec![7u8; 4096];
+            value[i] = i as u8;
+            let value = BlobstoreBytes::from_bytes(value);
+            packblob.put(ctx, key.clone(), value.clone()).await?;
+            keys.push(key);
+            values.push(value);
+        }
+        keys.push("repo0000.missing".to_string());
+
+        let options = RepackOptions {
+            zstd_level: 0,
+            min_pack_entries: 2,
+            max_pack_entries: 3,
+        };
+        let stats = packblob
+            .repack(ctx, keys.clone(), "repo0000.packed.".to_string(), &options)
+            .await?;
+        assert_eq!(stats.scanned, 6);
+        assert_eq!(stats.missing, 1);
+        assert_eq!(stats.repacked, 5);
+        assert_eq!(stats.packs, 2);
+        assert!(stats.bytes_after < stats.bytes_before);
+
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let fetched = packblob.get(ctx, key).await?;
+            assert_eq!(Some(value.clone()), fetched.map(|v| v.into_bytes()));
+        }
+
+        // The new packs are large enough to be left alone
+        let stats = packblob
+            .repack(ctx, keys, "repo0000.packed.".to_string(), &options)
+            .await?;
+        assert_eq!(stats.skipped, 5);
+        assert_eq!(stats.repacked, 0);
+        Ok(())
+    }
+}
@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use crate::dict;
 use crate::envelope::PackEnvelope;
 use crate::pack;
 
@@ -17,37 +18,116 @@ use bytes::Bytes;
 use context::CoreContext;
 use futures::stream::{FuturesUnordered, TryStreamExt};
 use mononoke_types::BlobstoreBytes;
-use packblob_thrift::{PackedEntry, SingleValue, StorageEnvelope, StorageFormat};
-use std::{convert::TryInto, io::Cursor};
+use packblob_thrift::{
+    PackedEntry, SingleValue, StorageEnvelope, StorageFormat, ZstdFromSharedDictValue,
+};
+use std::{collections::HashMap, convert::TryInto, io::Cursor, sync::Mutex};
 
 #[derive(Clone, Debug, Default)]
 pub struct PackOptions {
     // If Some, this is used as zstd compression level on put.
     // Some(0) means use zstd default level.
     put_compress_level: Option<i32>,
+    // Values smaller than this are put uncompressed, as compressing them saves little.
+    put_compress_min_size: usize,
+    // If set, values which look already compressed (e.g. gzip or png) are put uncompressed.
+    put_skip_compressed: bool,
+    // Key of the trained dictionary to compress values with on put, by key family.
+    put_zstd_dicts: HashMap<String, String>,
 }
 
 impl PackOptions {
     pub fn new(put_compress_level: Option<i32>) -> Self {
-        Self { put_compress_level }
+        Self {
+            put_compress_level,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_put_compress_min_size(self, put_compress_min_size: usize) -> Self {
+        Self {
+            put_compress_min_size,
+            ..self
+        }
+    }
+
+    pub fn with_put_skip_compressed(self, put_skip_compressed: bool) -> Self {
+        Self {
+            put_skip_compressed,
+            ..self
+        }
+    }
+
+    pub fn with_put_zstd_dicts(self, put_zstd_dicts: HashMap<String, String>) -> Self {
+        Self {
+            put_zstd_dicts,
+            ..self
+        }
+    }
+
+    pub fn put_compress_level(&self) -> Option<i32> {
+        self.put_compress_level
+    }
+
+    // The zstd level to compress `value` at on put, if it should be
+    fn put_compress_level_for(&self, value: &[u8]) -> Option<i32> {
+        if value.len() < self.put_compress_min_size
+            || (self.put_skip_compressed && looks_compressed(value))
+        {
+            return None;
+        }
+        self.put_compress_level
+    }
+
+    // The key of the dictionary to compress the value of `key` with on put, if any
+    fn put_zstd_dict_for(&self, key: &str) -> Option<&str> {
+        self.put_zstd_dicts
+            .get(dict::key_family(key))
+            .map(String::as_str)
     }
 }
 
+// Magic numbers of common compressed formats, which zstd won't shrink further
+const COMPRESSED_MAGICS: &[&[u8]] = &[
+    b"\x28\xb5\x2f\xfd",   // zstd
+    b"\x1f\x8b",           // gzip
+    b"BZh",                // bzip2
+    b"\xfd7zXZ\x00",       // xz
+    b"\x04\x22\x4d\x18",   // lz4
+    b"PK\x03\x04",         // zip, jar and office documents
+    b"7z\xbc\xaf\x27\x1c", // 7z
+    b"\x89PNG",            // png
+    b"\xff\xd8\xff",       // jpeg
+    b"GIF8",               // gif
+];
+
+fn looks_compressed(value: &[u8]) -> bool {
+    COMPRESSED_MAGICS
+        .iter()
+        .any(|magic| value.starts_with(magic))
+}
+
 /// A layer over an existing blobstore that uses thrift blob wrappers to allow packing and compression
 #[derive(Debug)]
 pub struct PackBlob<T> {
-    inner: T,
+    pub(crate) inner: T,
     options: PackOptions,
+    // Shared dictionaries never change once put, so are kept for the life of the store
+    dicts: Mutex<HashMap<String, Bytes>>,
 }
 
 impl<T> PackBlob<T> {
     pub fn new(inner: T, options: PackOptions) -> Self {
-        Self { inner, options }
+        Self {
+            inner,
+            options,
+            dicts: Mutex::new(HashMap::new()),
+        }
     }
 }
 
 // If compressed version is smaller, use it, otherwise return raw
-fn compress_if_worthwhile(value: Bytes, zstd_level: i32) -> Result<SingleValue> {
+pub(crate) fn compress_if_worthwhile(value: Bytes, zstd_level: i32) -> Result<SingleValue> {
     let cursor = Cursor::new(value.clone());
     let compressed = zstd::encode_all(cursor, zstd_level)?;
     if compressed.len() < value.len() {
@@ -57,6 +137,14 @@ fn compress_if_worthwhile(value: Bytes, zstd_level: i32) -> Result<SingleValue>
     }
 }
 
+pub(crate) fn single_len(single: &SingleValue) -> usize {
+    match single {
+        SingleValue::Raw(v) | SingleValue::Zstd(v) => v.len(),
+        SingleValue::ZstdFromSharedDict(v) => v.zstd.len(),
+        SingleValue::UnknownField(_) => usize::MAX,
+    }
+}
+
 // differentiate keys just in case packblob is run in an existing unpacked store
 pub const ENVELOPE_SUFFIX: &str = ".pack";
 
@@ -81,11 +169,15 @@ impl<T: Blobstore + BlobstorePutOps> Blobstore for PackBlob<T> {
 
         let meta = inner_get_data.as_meta().clone();
         let envelope: PackEnvelope = inner_get_data.into_bytes().try_into()?;
+        let shared_dicts = self
+            .get_shared_dicts(ctx, &envelope.0.storage)
+            .await
+            .with_context(|| format!("While getting shared dictionaries for {:?}", key))?;
 
         let get_data = match envelope.0.storage {
-            StorageFormat::Single(single) => pack::decode_independent(meta, single)
+            StorageFormat::Single(single) => pack::decode_independent(meta, single, &shared_dicts)
                 .with_context(|| format!("While decoding independent {:?}", key))?,
-            StorageFormat::Packed(packed) => pack::decode_pack(meta, packed, key)
+            StorageFormat::Packed(packed) => pack::decode_pack(meta, packed, key, &shared_dicts)
                 .with_context(|| format!("While decoding pack for {:?}", key))?,
             StorageFormat::UnknownField(e) => {
                 return Err(format_err!("StorageFormat::UnknownField {:?}", e));
@@ -112,7 +204,86 @@ impl<T: Blobstore + BlobstorePutOps> Blobstore for PackBlob<T> {
     }
 }
 
+impl<T: Blobstore> PackBlob<T> {
+    // Get the shared dictionaries needed to decode values stored as `storage`
+    pub(crate) async fn get_shared_dicts<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        storage: &'a StorageFormat,
+    ) -> Result<HashMap<String, Bytes>> {
+        let mut shared_dicts = HashMap::new();
+        for dict_key in pack::shared_dict_keys(storage) {
+            let dict = self.get_dict(ctx, &dict_key).await?;
+            shared_dicts.insert(dict_key, dict);
+        }
+        Ok(shared_dicts)
+    }
+
+    async fn get_dict<'a>(&'a self, ctx: &'a CoreContext, dict_key: &'a str) -> Result<Bytes> {
+        let cached = self
+            .dicts
+            .lock()
+            .expect("lock poisoned")
+            .get(dict_key)
+            .cloned();
+        if let Some(dict) = cached {
+            return Ok(dict);
+        }
+
+        let inner_key = [dict_key, ENVELOPE_SUFFIX].concat();
+        let inner_get_data = self
+            .inner
+            .get(ctx, &inner_key)
+            .await?
+            .ok_or_else(|| format_err!("Shared dictionary {:?} is missing", dict_key))?;
+        let meta = inner_get_data.as_meta().clone();
+        let envelope: PackEnvelope = inner_get_data.into_bytes().try_into()?;
+        // Dictionaries are stored raw, never compressed with another dictionary
+        let dict = match envelope.0.storage {
+            StorageFormat::Single(single) => {
+                pack::decode_independent(meta, single, &HashMap::new())?
+            }
+            _ => {
+                return Err(format_err!(
+                    "Shared dictionary {:?} is not an independent value",
+                    dict_key
+                ));
+            }
+        };
+        let dict = dict.into_bytes().into_bytes();
+
+        self.dicts
+            .lock()
+            .expect("lock poisoned")
+            .insert(dict_key.to_string(), dict.clone());
+        Ok(dict)
+    }
+}
+
 impl<T: BlobstorePutOps> PackBlob<T> {
+    // Put a dictionary trained for values of key `family`, returning its key. Values of the
+    // family are compressed with it once the key is passed in `PackOptions::with_put_zstd_dicts`.
+    pub async fn put_dict<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        prefix: &'a str,
+        family: &'a str,
+        dict: Bytes,
+    ) -> Result<String> {
+        let dict_key = dict::dict_key(prefix, family, &dict);
+        let envelope = PackEnvelope(StorageEnvelope {
+            storage: StorageFormat::Single(SingleValue::Raw(dict.to_vec())),
+        });
+        self.inner
+            .put(
+                ctx,
+                [dict_key.as_str(), ENVELOPE_SUFFIX].concat(),
+                envelope.into(),
+            )
+            .await?;
+        Ok(dict_key)
+    }
+
     async fn put_impl<'a>(
         &'a self,
         ctx: &'a CoreContext,
@@ -120,15 +291,31 @@ impl<T: BlobstorePutOps> PackBlob<T> {
         value: BlobstoreBytes,
         put_behaviour: Option<PutBehaviour>,
     ) -> Result<OverwriteStatus> {
-        key.push_str(ENVELOPE_SUFFIX);
-
         let value = value.into_bytes();
 
-        let single = if let Some(zstd_level) = self.options.put_compress_level {
-            compress_if_worthwhile(value, zstd_level)
-        } else {
-            Ok(SingleValue::Raw(value.to_vec()))
-        }?;
+        let single = match self.options.put_compress_level_for(&value) {
+            Some(zstd_level) => {
+                let single = compress_if_worthwhile(value.clone(), zstd_level)?;
+                match self.options.put_zstd_dict_for(&key) {
+                    Some(dict_key) => {
+                        let dict = self.get_dict(ctx, dict_key).await?;
+                        let zstd = dict::compress(&value, &dict, zstd_level)?;
+                        if zstd.len() < single_len(&single) {
+                            SingleValue::ZstdFromSharedDict(ZstdFromSharedDictValue {
+                                dict_key: dict_key.to_string(),
+                                zstd,
+                            })
+                        } else {
+                            single
+                        }
+                    }
+                    None => single,
+                }
+            }
+            None => SingleValue::Raw(value.to_vec()),
+        };
+
+        key.push_str(ENVELOPE_SUFFIX);
 
         // Wrap in thrift encoding
         let envelope: PackEnvelope = PackEnvelope(StorageEnvelope {
@@ -274,6 +461,91 @@ mod tests {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn compress_policy_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let innerblob = Arc::new(Memblob::default());
+        let packblob = PackBlob::new(
+            innerblob.clone(),
+            PackOptions::new(Some(0))
+                .with_put_compress_min_size(1024)
+                .with_put_skip_compressed(true),
+        );
+
+        let small = vec![7u8; 1023];
+        let mut gzipped = vec![7u8; 65535];
+        gzipped[..2].copy_from_slice(b"\x1f\x8b");
+        let large = vec![7u8; 65535];
+        for (key, bytes_in, compressed) in vec![
+            ("repo0000.small", small, false),
+            ("repo0000.gzipped", gzipped, false),
+            ("repo0000.large", large, true),
+        ] {
+            let len = bytes_in.len();
+            let value = BlobstoreBytes::from_bytes(bytes_in);
+            let inner_key = roundtrip(ctx, innerblob.clone(), &packblob, key, value).await?;
+            let inner_value = innerblob.get(ctx, &inner_key).await?;
+            assert_eq!(
+                inner_value.unwrap().into_bytes().len() < len,
+                compressed,
+                "checking {}",
+                key
+            );
+        }
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn shared_dict_roundtrip_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let innerblob = Arc::new(Memblob::default());
+
+        // Incompressible on its own, but the dictionary holds most of it
+        let mut rng = XorShiftRng::seed_from_u64(0); // reproducable Rng
+        let mut dict = vec![0u8; 4096];
+        rng.fill_bytes(&mut dict);
+        let mut bytes_in = dict.clone();
+        bytes_in[0] = !bytes_in[0];
+        let len = bytes_in.len();
+
+        let dict_key = PackBlob::new(innerblob.clone(), PackOptions::default())
+            .put_dict(ctx, "repo0000.", "hgmanifest", Bytes::from(dict))
+            .await?;
+        let mut dicts = HashMap::new();
+        dicts.insert("hgmanifest".to_string(), dict_key.clone());
+        let packblob = PackBlob::new(
+            innerblob.clone(),
+            PackOptions::new(Some(0)).with_put_zstd_dicts(dicts),
+        );
+
+        for (key, with_dict) in vec![
+            ("repo0000.hgmanifest.sha1.abcd", true),
+            ("repo0000.content.blake2.abcd", false),
+        ] {
+            let value = BlobstoreBytes::from_bytes(bytes_in.clone());
+            let inner_key = roundtrip(ctx, innerblob.clone(), &packblob, key, value).await?;
+            let inner_value = innerblob.get(ctx, &inner_key).await?;
+            assert_eq!(
+                inner_value.unwrap().into_bytes().len() < len,
+                with_dict,
+                "checking {}",
+                key
+            );
+        }
+
+        // A new store loads the dictionary on get
+        let packblob = PackBlob::new(innerblob.clone(), PackOptions::default());
+        let fetched = packblob.get(ctx, "repo0000.hgmanifest.sha1.abcd").await?;
+        assert_eq!(
+            fetched.map(|v| v.into_bytes()),
+            Some(BlobstoreBytes::from_bytes(bytes_in))
+        );
+        assert!(packblob.dicts.lock().unwrap().contains_key(&dict_key));
+        Ok(())
+    }
+
     async fn roundtrip(
         ctx: &CoreContext,
         inner_blobstore: Arc<Memblob>,
@@ -7,13 +7,17 @@
 
 #![deny(warnings)]
 
+use std::time::Duration;
+
 use anyhow::Result;
 use async_trait::async_trait;
 use inlinable_string::InlinableString;
 
 use context::CoreContext;
 
-use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use blobstore::{
+    Blobstore, BlobstoreGetData, BlobstorePutOps, BlobstoreWithTtl, OverwriteStatus, PutBehaviour,
+};
 use mononoke_types::BlobstoreBytes;
 
 /// A layer over an existing blobstore that prepends a fixed string to each get and put.
@@ -99,6 +103,21 @@ impl<T: BlobstorePutOps> BlobstorePutOps for PrefixBlobstore<T> {
     }
 }
 
+#[async_trait]
+impl<T: BlobstoreWithTtl> BlobstoreWithTtl for PrefixBlobstore<T> {
+    async fn put_with_ttl<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        self.blobstore
+            .put_with_ttl(ctx, self.prepend(key), value, ttl)
+            .await
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
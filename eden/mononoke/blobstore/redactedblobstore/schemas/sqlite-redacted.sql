@@ -11,6 +11,7 @@ CREATE TABLE  `censored_contents` (
 	`task` VARCHAR(64) NOT NULL,
 	`add_timestamp` BIGINT(20) NOT NULL,
 	`log_only` BIT DEFAULT NULL,
+	`reason` VARCHAR(255) DEFAULT NULL,
 	UNIQUE(`content_key`)
 );
 
@@ -24,7 +24,7 @@ use std::{ops::Deref, sync::Arc};
 use tunables::tunables;
 
 pub use crate::errors::ErrorKind;
-pub use crate::store::{RedactedMetadata, SqlRedactedContentStore};
+pub use crate::store::{RedactedEntry, RedactedMetadata, SqlRedactedContentStore};
 
 pub mod config {
     pub const GET_OPERATION: &str = "GET";
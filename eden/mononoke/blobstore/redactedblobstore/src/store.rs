@@ -8,7 +8,7 @@
 #![deny(warnings)]
 use anyhow::Error;
 use futures_ext::{BoxFuture, FutureExt};
-use futures_old::future::Future;
+use futures_old::future::{self, Future};
 use mononoke_types::Timestamp;
 use sql::{queries, Connection};
 use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
@@ -24,6 +24,21 @@ pub struct SqlRedactedContentStore {
 queries! {
 
     write InsertRedactedBlobs(
+        values: (content_key: String, task: String, add_timestamp: Timestamp, log_only: bool, reason: Option<String>)
+    ) {
+        none,
+        mysql(
+            "INSERT INTO censored_contents(content_key, task, add_timestamp, log_only, reason) VALUES {values}
+            ON DUPLICATE KEY UPDATE task = VALUES(task), add_timestamp = VALUES(add_timestamp), log_ONLY = VALUES(log_only), reason = VALUES(reason)
+            "
+        )
+        sqlite(
+            "REPLACE INTO censored_contents(content_key, task, add_timestamp, log_only, reason) VALUES {values}"
+        )
+    }
+
+    // Used on tables created before the reason column was added
+    write InsertRedactedBlobsWithoutReason(
         values: (content_key: String, task: String, add_timestamp: Timestamp, log_only: bool)
     ) {
         none,
@@ -42,6 +57,46 @@ queries! {
         FROM censored_contents"
     }
 
+    read GetRedactedEntries() -> (String, String, Timestamp, Option<bool>, Option<String>) {
+        "SELECT content_key, task, add_timestamp, log_only, reason
+        FROM censored_contents
+        ORDER BY content_key"
+    }
+
+    read GetRedactedEntriesForTask(task: String) -> (String, String, Timestamp, Option<bool>, Option<String>) {
+        "SELECT content_key, task, add_timestamp, log_only, reason
+        FROM censored_contents
+        WHERE task = {task}
+        ORDER BY content_key"
+    }
+
+    read GetRedactedEntriesForKeys(>list content_keys: String) -> (String, String, Timestamp, Option<bool>, Option<String>) {
+        "SELECT content_key, task, add_timestamp, log_only, reason
+        FROM censored_contents
+        WHERE content_key IN {content_keys}
+        ORDER BY content_key"
+    }
+
+    read GetRedactedEntriesWithoutReason() -> (String, String, Timestamp, Option<bool>, Option<String>) {
+        "SELECT content_key, task, add_timestamp, log_only, NULL
+        FROM censored_contents
+        ORDER BY content_key"
+    }
+
+    read GetRedactedEntriesForTaskWithoutReason(task: String) -> (String, String, Timestamp, Option<bool>, Option<String>) {
+        "SELECT content_key, task, add_timestamp, log_only, NULL
+        FROM censored_contents
+        WHERE task = {task}
+        ORDER BY content_key"
+    }
+
+    read GetRedactedEntriesForKeysWithoutReason(>list content_keys: String) -> (String, String, Timestamp, Option<bool>, Option<String>) {
+        "SELECT content_key, task, add_timestamp, log_only, NULL
+        FROM censored_contents
+        WHERE content_key IN {content_keys}
+        ORDER BY content_key"
+    }
+
     write DeleteRedactedBlobs(>list content_keys: String) {
         none,
         "DELETE FROM censored_contents
@@ -70,6 +125,47 @@ pub struct RedactedMetadata {
     pub log_only: bool,
 }
 
+/// A row of the redaction table, with everything operators need to audit it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RedactedEntry {
+    pub content_key: String,
+    pub task: String,
+    pub add_timestamp: Timestamp,
+    pub log_only: bool,
+    /// Free form explanation of the redaction, for entries which were given one
+    pub reason: Option<String>,
+}
+
+impl RedactedEntry {
+    fn from_row(
+        (content_key, task, add_timestamp, log_only, reason): (
+            String,
+            String,
+            Timestamp,
+            Option<bool>,
+            Option<String>,
+        ),
+    ) -> Self {
+        Self {
+            content_key,
+            task,
+            add_timestamp,
+            log_only: log_only.unwrap_or(false),
+            reason,
+        }
+    }
+}
+
+/// Whether `err` comes from a table which doesn't have the reason column yet. Such tables keep
+/// working, without reasons.
+fn is_missing_reason_column(err: &Error) -> bool {
+    let msg = format!("{:#}", err);
+    msg.contains("reason")
+        && (msg.contains("Unknown column")
+            || msg.contains("no such column")
+            || msg.contains("has no column named"))
+}
+
 impl SqlRedactedContentStore {
     pub fn get_all_redacted_blobs(&self) -> BoxFuture<HashMap<String, RedactedMetadata>, Error> {
         GetAllRedactedBlobs::query(&self.read_connection)
@@ -94,19 +190,84 @@ impl SqlRedactedContentStore {
         task: &String,
         add_timestamp: &Timestamp,
         log_only: bool,
+        reason: &Option<String>,
     ) -> impl Future<Item = (), Error = Error> {
         let log_only = &log_only;
         let redacted_inserts: Vec<_> = content_keys
             .iter()
-            .map(move |key| (key, task, add_timestamp, log_only))
+            .map(move |key| (key, task, add_timestamp, log_only, reason))
+            .collect();
+
+        let write_connection = self.write_connection.clone();
+        let legacy_inserts: Vec<_> = content_keys
+            .iter()
+            .map(|key| (key.clone(), task.clone(), *add_timestamp, *log_only))
             .collect();
 
         InsertRedactedBlobs::query(&self.write_connection, &redacted_inserts[..])
             .map_err(Error::from)
+            .or_else(move |err| {
+                if !is_missing_reason_column(&err) {
+                    return future::err(err).boxify();
+                }
+                let legacy_inserts: Vec<_> = legacy_inserts
+                    .iter()
+                    .map(|(key, task, add_timestamp, log_only)| {
+                        (key, task, add_timestamp, log_only)
+                    })
+                    .collect();
+                InsertRedactedBlobsWithoutReason::query(&write_connection, &legacy_inserts[..])
+                    .map_err(Error::from)
+                    .boxify()
+            })
             .map(|_| ())
             .boxify()
     }
 
+    /// All the redaction entries, or only those of `task`, ordered by key
+    pub fn list_redacted_entries(
+        &self,
+        task: Option<String>,
+    ) -> BoxFuture<Vec<RedactedEntry>, Error> {
+        let read_connection = self.read_connection.clone();
+        let rows = match &task {
+            Some(task) => GetRedactedEntriesForTask::query(&self.read_connection, task).boxify(),
+            None => GetRedactedEntries::query(&self.read_connection).boxify(),
+        };
+        rows.or_else(move |err| {
+            if !is_missing_reason_column(&err) {
+                return future::err(err).boxify();
+            }
+            match task {
+                Some(task) => {
+                    GetRedactedEntriesForTaskWithoutReason::query(&read_connection, &task).boxify()
+                }
+                None => GetRedactedEntriesWithoutReason::query(&read_connection).boxify(),
+            }
+        })
+        .map(|rows| rows.into_iter().map(RedactedEntry::from_row).collect())
+        .boxify()
+    }
+
+    /// The redaction entries of those of `content_keys` which are redacted
+    pub fn get_redacted_entries(
+        &self,
+        content_keys: &[String],
+    ) -> BoxFuture<Vec<RedactedEntry>, Error> {
+        let read_connection = self.read_connection.clone();
+        let content_keys = content_keys.to_vec();
+        GetRedactedEntriesForKeys::query(&self.read_connection, &content_keys[..])
+            .or_else(move |err| {
+                if !is_missing_reason_column(&err) {
+                    return future::err(err).boxify();
+                }
+                GetRedactedEntriesForKeysWithoutReason::query(&read_connection, &content_keys[..])
+                    .boxify()
+            })
+            .map(|rows| rows.into_iter().map(RedactedEntry::from_row).collect())
+            .boxify()
+    }
+
     pub fn delete_redacted_blobs(&self, content_keys: &[String]) -> BoxFuture<(), Error> {
         DeleteRedactedBlobs::query(&self.write_connection, &content_keys[..])
             .map_err(Error::from)
@@ -134,12 +295,12 @@ mod test {
         let store = SqlRedactedContentStore::with_sqlite_in_memory().unwrap();
 
         store
-            .insert_redacted_blobs(&redacted_keys1, &task1, &Timestamp::now(), false)
+            .insert_redacted_blobs(&redacted_keys1, &task1, &Timestamp::now(), false, &None)
             .compat()
             .await
             .expect("insert failed");
         store
-            .insert_redacted_blobs(&redacted_keys2, &task2, &Timestamp::now(), true)
+            .insert_redacted_blobs(&redacted_keys2, &task2, &Timestamp::now(), true, &None)
             .compat()
             .await
             .expect("insert failed");
@@ -156,7 +317,7 @@ mod test {
         assert!(res.get(&key_d).unwrap().log_only);
 
         store
-            .insert_redacted_blobs(&redacted_keys1, &task1, &Timestamp::now(), true)
+            .insert_redacted_blobs(&redacted_keys1, &task1, &Timestamp::now(), true, &None)
             .compat()
             .await
             .expect("insert failed");
@@ -183,4 +344,129 @@ mod test {
         assert_eq!(res.contains_key(&key_d), true);
         assert_eq!(res.len(), 2);
     }
+
+    #[fbinit::test]
+    async fn test_redacted_entries(_fb: fbinit::FacebookInit) {
+        let key_a = "aaaaaaaaaaaaaaaaaaaa".to_string();
+        let key_b = "bbbbbbbbbbbbbbbbbbbb".to_string();
+        let key_c = "cccccccccccccccccccc".to_string();
+        let task1 = "task1".to_string();
+        let task2 = "task2".to_string();
+        let reason = Some("leaked credentials".to_string());
+        let timestamp = Timestamp::from_timestamp_secs(1_600_000_000);
+
+        let store = SqlRedactedContentStore::with_sqlite_in_memory().unwrap();
+
+        store
+            .insert_redacted_blobs(
+                &vec![key_b.clone(), key_a.clone()],
+                &task1,
+                &timestamp,
+                false,
+                &reason,
+            )
+            .compat()
+            .await
+            .expect("insert failed");
+        store
+            .insert_redacted_blobs(&vec![key_c.clone()], &task2, &timestamp, true, &None)
+            .compat()
+            .await
+            .expect("insert failed");
+
+        let entries = store
+            .list_redacted_entries(None)
+            .compat()
+            .await
+            .expect("select failed");
+        let keys: Vec<_> = entries.iter().map(|e| e.content_key.clone()).collect();
+        assert_eq!(keys, vec![key_a.clone(), key_b.clone(), key_c.clone()]);
+        assert_eq!(
+            entries[0],
+            RedactedEntry {
+                content_key: key_a.clone(),
+                task: task1.clone(),
+                add_timestamp: timestamp,
+                log_only: false,
+                reason: reason.clone(),
+            }
+        );
+        assert!(entries[2].log_only);
+        assert_eq!(entries[2].reason, None);
+
+        let entries = store
+            .list_redacted_entries(Some(task2.clone()))
+            .compat()
+            .await
+            .expect("select failed");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content_key, key_c);
+
+        let entries = store
+            .get_redacted_entries(&[key_b.clone(), "missing".to_string()])
+            .compat()
+            .await
+            .expect("select failed");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content_key, key_b);
+        assert_eq!(entries[0].reason, reason);
+    }
+
+    #[fbinit::test]
+    async fn test_redacted_entries_without_reason(_fb: fbinit::FacebookInit) {
+        let key_a = "aaaaaaaaaaaaaaaaaaaa".to_string();
+        let task = "task".to_string();
+        let timestamp = Timestamp::from_timestamp_secs(1_600_000_000);
+
+        // A table created before the reason column was added
+        let conn = sql_ext::open_sqlite_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE `censored_contents` (
+                `id` INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                `content_key` VARCHAR(255) NOT NULL,
+                `task` VARCHAR(64) NOT NULL,
+                `add_timestamp` BIGINT(20) NOT NULL,
+                `log_only` BIT DEFAULT NULL,
+                UNIQUE(`content_key`)
+            );",
+        )
+        .unwrap();
+        let store = SqlRedactedContentStore::from_sql_connections(SqlConnections::new_single(
+            Connection::with_sqlite(conn),
+        ));
+
+        store
+            .insert_redacted_blobs(
+                &vec![key_a.clone()],
+                &task,
+                &timestamp,
+                false,
+                &Some("leaked credentials".to_string()),
+            )
+            .compat()
+            .await
+            .expect("insert failed");
+
+        let entries = store
+            .list_redacted_entries(Some(task.clone()))
+            .compat()
+            .await
+            .expect("select failed");
+        assert_eq!(
+            entries,
+            vec![RedactedEntry {
+                content_key: key_a.clone(),
+                task,
+                add_timestamp: timestamp,
+                log_only: false,
+                reason: None,
+            }]
+        );
+        let entries = store
+            .get_redacted_entries(&[key_a])
+            .compat()
+            .await
+            .expect("select failed");
+        assert_eq!(entries.len(), 1);
+    }
 }
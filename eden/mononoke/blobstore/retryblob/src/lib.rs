@@ -0,0 +1,379 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use slog::debug;
+use stats::prelude::*;
+use thiserror::Error;
+use tokio::time::delay_for;
+
+use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use context::{CoreContext, PerfCounterType};
+use mononoke_types::BlobstoreBytes;
+
+define_stats! {
+    prefix = "mononoke.blobstore.retry";
+    retries: timeseries(Rate, Sum),
+    gave_up: timeseries(Rate, Sum),
+}
+
+/// Tells whether an error returned by a blobstore may go away if the operation is retried. Each
+/// backend knows which of its errors are transient (dropped connections, throttling, ...).
+///
+/// Backends exporting one don't retry whole operations themselves, leaving that to `RetryBlob`,
+/// so that retries aren't multiplied across layers. They may still `retry` the requests making
+/// up a single operation, such as the parts of a multipart upload, so that a failed part
+/// doesn't require uploading the whole blob again.
+pub type IsRetryable = fn(&Error) -> bool;
+
+/// Marks the errors of operations which were retried until they ran out of attempts, so that the
+/// layers above them don't retry them again.
+#[derive(Debug, Error)]
+#[error("gave up after {0} attempts")]
+pub struct RetriesExhausted(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryOptions {
+    /// Attempts made for each operation before giving up on retryable errors. 1 disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry. It doubles for each retry after that.
+    pub base_delay: Duration,
+    /// Retries are never delayed by more than this.
+    pub max_delay: Duration,
+}
+
+impl RetryOptions {
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+            max_delay,
+        }
+    }
+
+    pub fn has_retries(&self) -> bool {
+        self.max_attempts > 1
+    }
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self::new(5, Duration::from_millis(100), Duration::from_secs(10))
+    }
+}
+
+/// Run `f` until it succeeds, fails with an error `is_retryable` rejects, or runs out of the
+/// attempts allowed by `options`, with exponential backoff. `on_retry` is called with each error
+/// about to be retried, the attempt which failed and the delay before the next one.
+///
+/// Backends use this for the parts of operations which send several requests, such as multipart
+/// uploads, so that a failure only requires sending the failed request again.
+pub async fn retry<R, F, Fut>(
+    options: &RetryOptions,
+    is_retryable: IsRetryable,
+    on_retry: impl Fn(&Error, u32, Duration),
+    f: F,
+) -> Result<R>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<R>>,
+{
+    let mut delay = options.base_delay;
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Err(e) if is_retryable(&e) && !e.is::<RetriesExhausted>() => {
+                if attempt >= options.max_attempts {
+                    STATS::gave_up.add_value(1);
+                    return Err(e.context(RetriesExhausted(attempt)));
+                }
+                on_retry(&e, attempt, delay);
+                STATS::retries.add_value(1);
+                delay_for(delay).await;
+                delay = (delay * 2).min(options.max_delay);
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// A layer over an existing blobstore that retries the operations which fail with an error the
+/// blobstore classifies as retryable, with exponential backoff.
+///
+/// Puts which must not overwrite existing blobs may report `OverwriteStatus::Prevented` when
+/// retried if a failed attempt did write the blob.
+pub struct RetryBlob<T> {
+    inner: T,
+    options: RetryOptions,
+    is_retryable: IsRetryable,
+}
+
+impl<T: fmt::Debug> fmt::Debug for RetryBlob<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RetryBlob")
+            .field("inner", &self.inner)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+impl<T> RetryBlob<T> {
+    pub fn new(inner: T, options: RetryOptions, is_retryable: IsRetryable) -> Self {
+        Self {
+            inner,
+            options,
+            is_retryable,
+        }
+    }
+
+    pub fn as_inner(&self) -> &T {
+        &self.inner
+    }
+
+    async fn retry<R, F, Fut>(&self, ctx: &CoreContext, op: &str, key: &str, f: F) -> Result<R>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let on_retry = |e: &Error, attempt, delay: Duration| {
+            debug!(
+                ctx.logger(),
+                "Blobstore {} of {} failed (attempt {} of {}), retrying: {:?}",
+                op,
+                key,
+                attempt,
+                self.options.max_attempts,
+                e
+            );
+            ctx.perf_counters()
+                .increment_counter(PerfCounterType::BlobstoreRetries);
+            ctx.perf_counters().add_to_counter(
+                PerfCounterType::BlobstoreRetrySumDelay,
+                delay.as_millis() as i64,
+            );
+        };
+        retry(&self.options, self.is_retryable, on_retry, f).await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> Blobstore for RetryBlob<T> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        self.retry(ctx, "get", key, || self.inner.get(ctx, key))
+            .await
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        self.retry(ctx, "is_present", key, || self.inner.is_present(ctx, key))
+            .await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.retry(ctx, "put", &key, || {
+            self.inner.put(ctx, key.clone(), value.clone())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> BlobstorePutOps for RetryBlob<T> {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        self.retry(ctx, "put", &key, || {
+            self.inner
+                .put_explicit(ctx, key.clone(), value.clone(), put_behaviour)
+        })
+        .await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.retry(ctx, "put", &key, || {
+            self.inner.put_with_status(ctx, key.clone(), value.clone())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::format_err;
+    use borrowed::borrowed;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("flaky")]
+    struct Flaky;
+
+    fn is_flaky(e: &Error) -> bool {
+        e.is::<Flaky>()
+    }
+
+    /// Fails the first `failures` operations, with a retryable error if `retryable` is set
+    #[derive(Debug)]
+    struct FailingBlob {
+        inner: Memblob,
+        failures: AtomicU32,
+        retryable: bool,
+    }
+
+    impl FailingBlob {
+        fn new(failures: u32, retryable: bool) -> Self {
+            Self {
+                inner: Memblob::default(),
+                failures: AtomicU32::new(failures),
+                retryable,
+            }
+        }
+
+        fn fail(&self) -> Result<()> {
+            let failures = self.failures.load(Ordering::SeqCst);
+            if failures == 0 {
+                return Ok(());
+            }
+            self.failures.store(failures - 1, Ordering::SeqCst);
+            if self.retryable {
+                Err(Flaky.into())
+            } else {
+                Err(format_err!("broken"))
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Blobstore for FailingBlob {
+        async fn get<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: &'a str,
+        ) -> Result<Option<BlobstoreGetData>> {
+            self.fail()?;
+            self.inner.get(ctx, key).await
+        }
+
+        async fn put<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: String,
+            value: BlobstoreBytes,
+        ) -> Result<()> {
+            self.put_with_status(ctx, key, value).await?;
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl BlobstorePutOps for FailingBlob {
+        async fn put_explicit<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: String,
+            value: BlobstoreBytes,
+            put_behaviour: PutBehaviour,
+        ) -> Result<OverwriteStatus> {
+            self.fail()?;
+            self.inner
+                .put_explicit(ctx, key, value, put_behaviour)
+                .await
+        }
+
+        async fn put_with_status<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: String,
+            value: BlobstoreBytes,
+        ) -> Result<OverwriteStatus> {
+            self.fail()?;
+            self.inner.put_with_status(ctx, key, value).await
+        }
+    }
+
+    fn options(max_attempts: u32) -> RetryOptions {
+        RetryOptions::new(
+            max_attempts,
+            Duration::from_millis(1),
+            Duration::from_millis(2),
+        )
+    }
+
+    #[fbinit::test]
+    async fn test_retry(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let value = BlobstoreBytes::from_bytes("value");
+
+        let blob = RetryBlob::new(FailingBlob::new(2, true), options(3), is_flaky);
+        blob.put(ctx, "key".to_string(), value.clone()).await?;
+        assert_eq!(
+            blob.get(ctx, "key")
+                .await?
+                .map(BlobstoreGetData::into_bytes),
+            Some(value)
+        );
+        // Failing once more than allowed returns the last error
+        let blob = RetryBlob::new(FailingBlob::new(3, true), options(3), is_flaky);
+        assert!(blob.get(ctx, "key").await.unwrap_err().is::<Flaky>());
+
+        // Errors which ran out of attempts in a layer below aren't retried again
+        let blob = RetryBlob::new(
+            RetryBlob::new(FailingBlob::new(4, true), options(2), is_flaky),
+            options(3),
+            is_flaky,
+        );
+        assert!(blob
+            .get(ctx, "key")
+            .await
+            .unwrap_err()
+            .is::<RetriesExhausted>());
+        assert_eq!(
+            blob.as_inner().as_inner().failures.load(Ordering::SeqCst),
+            2
+        );
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_not_retryable(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+
+        let blob = RetryBlob::new(FailingBlob::new(1, false), options(3), is_flaky);
+        assert!(blob.get(ctx, "key").await.is_err());
+        assert_eq!(blob.get(ctx, "key").await?, None);
+        Ok(())
+    }
+}
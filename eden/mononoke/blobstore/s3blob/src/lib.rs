@@ -0,0 +1,446 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use std::fmt;
+use std::future::Future;
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::{format_err, Context, Error, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::DateTime;
+use futures::{
+    future,
+    stream::{self, StreamExt, TryStreamExt},
+};
+use rusoto_core::{request::HttpDispatchError, ByteStream, HttpClient, Region, RusotoError};
+use rusoto_credential::{ChainProvider, ProfileProvider};
+use rusoto_s3::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
+    CompletedPart, CreateMultipartUploadRequest, GetObjectError, GetObjectRequest, HeadObjectError,
+    HeadObjectRequest, PutObjectRequest, S3Client, UploadPartRequest, S3,
+};
+use thiserror::Error;
+use tokio::sync::Semaphore;
+
+use blobstore::{
+    Blobstore, BlobstoreGetData, BlobstoreMetadata, BlobstorePutOps, OverwriteStatus, PutBehaviour,
+};
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+use retryblob::{retry, RetryOptions};
+
+/// S3 rejects multipart uploads with parts smaller than this, except for the last one.
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug)]
+pub struct S3Options {
+    /// Blobs larger than this are uploaded in parts.
+    pub multipart_threshold: usize,
+    /// Size of the parts of a multipart upload.
+    pub multipart_part_size: usize,
+    /// Requests in flight at the same time, which bounds the connections kept open.
+    pub max_concurrent_requests: usize,
+}
+
+impl Default for S3Options {
+    fn default() -> Self {
+        Self {
+            multipart_threshold: 64 * 1024 * 1024,
+            multipart_part_size: 16 * 1024 * 1024,
+            max_concurrent_requests: 64,
+        }
+    }
+}
+
+/// A blobstore backed by a bucket of an S3-compatible object store.
+///
+/// All the requests share one HTTP client, which keeps the connections to the endpoint open
+/// between requests. Retries follow the contract of `retryblob::IsRetryable`.
+#[derive(Clone)]
+pub struct S3Blob {
+    client: S3Client,
+    bucket: String,
+    endpoint: String,
+    put_behaviour: PutBehaviour,
+    options: S3Options,
+    retry_options: RetryOptions,
+    requests: Arc<Semaphore>,
+}
+
+impl fmt::Debug for S3Blob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("S3Blob")
+            .field("bucket", &self.bucket)
+            .field("endpoint", &self.endpoint)
+            .field("put_behaviour", &self.put_behaviour)
+            .field("options", &self.options)
+            .field("retry_options", &self.retry_options)
+            .finish()
+    }
+}
+
+impl S3Blob {
+    /// Connect to `bucket` at `endpoint`. The credentials are read from the `keychain_group`
+    /// profile of the AWS credentials file, or found the usual AWS ways (environment variables,
+    /// default profile, instance metadata) if `keychain_group` is empty.
+    pub async fn new(
+        bucket: String,
+        keychain_group: String,
+        region_name: String,
+        endpoint: String,
+        put_behaviour: PutBehaviour,
+        options: S3Options,
+        retry_options: RetryOptions,
+    ) -> Result<Self> {
+        let credentials = if keychain_group.is_empty() {
+            ChainProvider::new()
+        } else {
+            let mut profile = ProfileProvider::new()?;
+            profile.set_profile(keychain_group);
+            ChainProvider::with_profile_provider(profile)
+        };
+        let region = Region::Custom {
+            name: region_name,
+            endpoint: endpoint.clone(),
+        };
+        let client = S3Client::new_with(HttpClient::new()?, credentials, region);
+        let options = S3Options {
+            multipart_part_size: options.multipart_part_size.max(MIN_PART_SIZE),
+            ..options
+        };
+
+        Ok(Self {
+            client,
+            bucket,
+            endpoint,
+            put_behaviour,
+            requests: Arc::new(Semaphore::new(options.max_concurrent_requests.max(1))),
+            options,
+            retry_options,
+        })
+    }
+
+    /// Send the request made by `request`, once there are few enough requests in flight.
+    async fn send<T, E, F, Fut>(&self, request: F) -> Result<T, RusotoError<E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, RusotoError<E>>>,
+    {
+        let _permit = self.requests.acquire().await;
+        request().await
+    }
+
+    async fn put_object(&self, key: &str, value: Bytes) -> Result<()> {
+        if value.len() > self.options.multipart_threshold {
+            return self.put_multipart(key, value).await;
+        }
+
+        self.send(|| {
+            self.client.put_object(PutObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.to_string(),
+                content_length: Some(value.len() as i64),
+                body: Some(ByteStream::from(value.to_vec())),
+                ..Default::default()
+            })
+        })
+        .await
+        .map_err(request_error)
+        .with_context(|| format!("failed to put {} to S3", key))?;
+        Ok(())
+    }
+
+    async fn put_multipart(&self, key: &str, value: Bytes) -> Result<()> {
+        let upload_id = self
+            .send(|| {
+                self.client
+                    .create_multipart_upload(CreateMultipartUploadRequest {
+                        bucket: self.bucket.clone(),
+                        key: key.to_string(),
+                        ..Default::default()
+                    })
+            })
+            .await
+            .map_err(request_error)
+            .with_context(|| format!("failed to start multipart upload of {} to S3", key))?
+            .upload_id
+            .ok_or_else(|| format_err!("S3 returned no upload id for {}", key))?;
+
+        match self.upload_parts(key, &upload_id, &value).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // Parts of uploads which are never completed or aborted are stored (and billed)
+                // until a lifecycle rule removes them.
+                let _ = self
+                    .send(|| {
+                        self.client
+                            .abort_multipart_upload(AbortMultipartUploadRequest {
+                                bucket: self.bucket.clone(),
+                                key: key.to_string(),
+                                upload_id: upload_id.clone(),
+                                ..Default::default()
+                            })
+                    })
+                    .await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn upload_parts(&self, key: &str, upload_id: &str, value: &Bytes) -> Result<()> {
+        let parts: Vec<CompletedPart> = stream::iter(
+            part_ranges(value.len(), self.options.multipart_part_size)
+                .into_iter()
+                .enumerate(),
+        )
+        .map(|(i, range)| {
+            // Part numbers start at 1.
+            let part_number = i as i64 + 1;
+            let part = value.slice(range);
+            async move {
+                let part = &part;
+                let upload_part = || async move {
+                    self.send(|| {
+                        self.client.upload_part(UploadPartRequest {
+                            bucket: self.bucket.clone(),
+                            key: key.to_string(),
+                            upload_id: upload_id.to_string(),
+                            part_number,
+                            content_length: Some(part.len() as i64),
+                            body: Some(ByteStream::from(part.to_vec())),
+                            ..Default::default()
+                        })
+                    })
+                    .await
+                    .map_err(request_error)
+                    .with_context(|| format!("failed to upload part {} of {}", part_number, key))
+                };
+                let e_tag = retry(&self.retry_options, is_retryable, |_, _, _| {}, upload_part)
+                    .await?
+                    .e_tag;
+                Ok(CompletedPart {
+                    e_tag,
+                    part_number: Some(part_number),
+                })
+            }
+        })
+        .buffered(self.options.max_concurrent_requests.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+
+        self.send(|| {
+            self.client
+                .complete_multipart_upload(CompleteMultipartUploadRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_string(),
+                    upload_id: upload_id.to_string(),
+                    multipart_upload: Some(CompletedMultipartUpload {
+                        parts: Some(parts.clone()),
+                    }),
+                    ..Default::default()
+                })
+        })
+        .await
+        .map_err(request_error)
+        .with_context(|| format!("failed to complete multipart upload of {} to S3", key))?;
+        Ok(())
+    }
+}
+
+/// Marks the errors of requests which may succeed if sent again.
+#[derive(Debug, Error)]
+#[error("transient S3 error")]
+struct TransientError;
+
+/// Whether an operation of `S3Blob` which failed with `error` may succeed if retried.
+pub fn is_retryable(error: &Error) -> bool {
+    error.downcast_ref::<TransientError>().is_some()
+}
+
+/// Convert the error of a request, marking it if it is transient.
+fn request_error<E>(err: RusotoError<E>) -> Error
+where
+    RusotoError<E>: std::error::Error + Send + Sync + 'static,
+{
+    let transient = is_transient(&err);
+    let err = Error::new(err);
+    if transient {
+        err.context(TransientError)
+    } else {
+        err
+    }
+}
+
+/// Whether a request which failed with `err` may succeed if sent again.
+fn is_transient<E>(err: &RusotoError<E>) -> bool {
+    match err {
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(response) => {
+            let status = response.status.as_u16();
+            // 429 and 503 are what S3-compatible stores throttle with.
+            status == 429 || status >= 500
+        }
+        _ => false,
+    }
+}
+
+/// Whether a HEAD request failed because the object doesn't exist. S3 doesn't send a body with
+/// the responses to HEAD requests, so the error isn't always parsed as `NoSuchKey`.
+fn is_not_found(err: &RusotoError<HeadObjectError>) -> bool {
+    match err {
+        RusotoError::Service(HeadObjectError::NoSuchKey(_)) => true,
+        RusotoError::Unknown(response) => response.status.as_u16() == 404,
+        _ => false,
+    }
+}
+
+/// Split `len` bytes in parts of `part_size` bytes, the last one being smaller if needed.
+fn part_ranges(len: usize, part_size: usize) -> Vec<Range<usize>> {
+    (0..len)
+        .step_by(part_size)
+        .map(|start| start..(start + part_size).min(len))
+        .collect()
+}
+
+#[async_trait]
+impl Blobstore for S3Blob {
+    async fn get<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let res = self
+            .send(|| async move {
+                let output = self
+                    .client
+                    .get_object(GetObjectRequest {
+                        bucket: self.bucket.clone(),
+                        key: key.to_string(),
+                        ..Default::default()
+                    })
+                    .await?;
+                // Reading the body is part of the request, so that a connection dropped while
+                // reading it is a transient error too.
+                let data = match output.body {
+                    Some(body) => {
+                        let capacity = output.content_length.unwrap_or(0).max(0) as usize;
+                        body.map_err(|e| {
+                            RusotoError::HttpDispatch(HttpDispatchError::new(e.to_string()))
+                        })
+                        .try_fold(Vec::with_capacity(capacity), |mut data, chunk| {
+                            data.extend_from_slice(&chunk);
+                            future::ok(data)
+                        })
+                        .await?
+                    }
+                    None => Vec::new(),
+                };
+                Ok::<_, RusotoError<GetObjectError>>((output.last_modified, data))
+            })
+            .await;
+
+        match res {
+            Ok((last_modified, data)) => {
+                let ctime = last_modified
+                    .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+                    .map(|date| date.timestamp());
+                Ok(Some(BlobstoreGetData::new(
+                    BlobstoreMetadata::new(ctime),
+                    BlobstoreBytes::from_bytes(data),
+                )))
+            }
+            Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => Ok(None),
+            Err(e) => {
+                Err(request_error(e)).with_context(|| format!("failed to get {} from S3", key))
+            }
+        }
+    }
+
+    async fn is_present<'a>(&'a self, _ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        let res = self
+            .send(|| {
+                self.client.head_object(HeadObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_string(),
+                    ..Default::default()
+                })
+            })
+            .await;
+
+        match res {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => {
+                Err(request_error(e)).with_context(|| format!("failed to check {} in S3", key))
+            }
+        }
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for S3Blob {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        // S3 has no conditional puts, so checking whether the key exists is racy: two concurrent
+        // puts of a new key may both write it.
+        let status = match put_behaviour {
+            PutBehaviour::Overwrite => OverwriteStatus::NotChecked,
+            PutBehaviour::IfAbsent | PutBehaviour::OverwriteAndLog => {
+                if !self.is_present(ctx, &key).await? {
+                    OverwriteStatus::New
+                } else if put_behaviour.should_overwrite() {
+                    OverwriteStatus::Overwrote
+                } else {
+                    return Ok(OverwriteStatus::Prevented);
+                }
+            }
+        };
+
+        self.put_object(&key, value.into_bytes()).await?;
+        Ok(status)
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.put_explicit(ctx, key, value, self.put_behaviour).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_part_ranges() {
+        assert_eq!(part_ranges(0, 10), Vec::<Range<usize>>::new());
+        assert_eq!(part_ranges(10, 10), vec![0..10]);
+        assert_eq!(part_ranges(25, 10), vec![0..10, 10..20, 20..25]);
+    }
+}
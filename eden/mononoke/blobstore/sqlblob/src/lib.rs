@@ -25,8 +25,9 @@ use crate::store::{ChunkSqlStore, ChunkingMethod, DataSqlStore};
 use anyhow::{bail, format_err, Error, Result};
 use async_trait::async_trait;
 use blobstore::{
-    Blobstore, BlobstoreGetData, BlobstoreMetadata, BlobstorePutOps, BlobstoreWithLink,
-    CountedBlobstore, OverwriteStatus, PutBehaviour,
+    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreKeyParam, BlobstoreKeySource,
+    BlobstoreMetadata, BlobstorePutOps, BlobstoreWithLink, CountedBlobstore, OverwriteStatus,
+    PutBehaviour, ENUMERATION_PAGE_SIZE,
 };
 use bytes::BytesMut;
 use cached_config::{ConfigHandle, ConfigStore, TestSource};
@@ -458,6 +459,37 @@ impl Sqlblob {
         self.chunk_store.set_initial_generation(shard_num).await
     }
 
+    /// Chunks last seen in a generation below the returned one are garbage, see
+    /// `delete_unreachable_chunks`
+    pub fn sweep_below_generation(&self, unreachable_generations: u64) -> u64 {
+        self.chunk_store
+            .sweep_below_generation(unreachable_generations)
+    }
+
+    pub async fn get_unreachable_chunk_ids(
+        &self,
+        shard_num: usize,
+        below_generation: u64,
+        limit: u64,
+    ) -> Result<Vec<String>> {
+        self.chunk_store
+            .get_unreachable_chunk_ids(shard_num, below_generation, limit)
+            .await
+    }
+
+    /// Deletes chunks returned by `get_unreachable_chunk_ids`, and the keys that point to them.
+    /// This is only safe once every reachable key has been marked in the mark generation.
+    pub async fn delete_unreachable_chunks(
+        &self,
+        shard_num: usize,
+        ids: &[String],
+        below_generation: u64,
+    ) -> Result<u64> {
+        self.chunk_store
+            .delete_unreachable_chunks(shard_num, ids, below_generation)
+            .await
+    }
+
     pub async fn get_chunk_generations(&self, key: &str) -> Result<Vec<Option<u64>>> {
         let chunked = self.data_store.get(key).await?;
         if let Some(chunked) = chunked {
@@ -664,6 +696,46 @@ impl BlobstoreWithLink for Sqlblob {
     }
 }
 
+#[async_trait]
+impl BlobstoreKeySource for Sqlblob {
+    async fn enumerate<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        range: &'a BlobstoreKeyParam,
+    ) -> Result<BlobstoreEnumerationData> {
+        match range {
+            BlobstoreKeyParam::Start(ref range) => {
+                let end_key = if range.end_key.is_empty() {
+                    None
+                } else {
+                    Some(range.end_key.as_str())
+                };
+                // Keys are spread over all the shards, so the first page of the range is among
+                // the first page of keys of every shard. One more key than a page is fetched, so
+                // that a shard with more keys is noticed.
+                let shard_keys: FuturesUnordered<_> = (0..self.data_store.shard_count())
+                    .map(|shard_num| {
+                        self.data_store.get_keys_in_range(
+                            shard_num,
+                            &range.begin_key,
+                            end_key,
+                            ENUMERATION_PAGE_SIZE + 1,
+                        )
+                    })
+                    .collect();
+                let mut keys: Vec<String> = shard_keys.try_concat().await?;
+                keys.sort();
+                Ok(BlobstoreEnumerationData::page(
+                    range,
+                    keys,
+                    ENUMERATION_PAGE_SIZE,
+                ))
+            }
+            _ => Err(format_err!("Sqlblob does not support token, only ranges")),
+        }
+    }
+}
+
 pub fn set_test_generations(
     source: &TestSource,
     put_generation: i64,
@@ -5,7 +5,14 @@
  * GNU General Public License version 2.
  */
 
-use std::{collections::HashMap, hash::Hasher, num::NonZeroUsize, sync::Arc};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    hash::Hasher,
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{format_err, Error};
 use bytes::BytesMut;
@@ -16,6 +23,7 @@ use futures::{
     stream::{self, Stream},
 };
 use sql::{queries, Connection};
+use sql_ext::ShardHashing;
 use twox_hash::XxHash32;
 use xdb_gc_structs::XdbGc;
 
@@ -148,6 +156,57 @@ queries! {
         "SELECT id FROM data"
     }
 
+    read GetKeysInRange(begin_key: &str, end_key: &str, limit: u64) -> (Vec<u8>) {
+        "SELECT id FROM data
+         WHERE id >= {begin_key} AND id < {end_key}
+         ORDER BY id
+         LIMIT {limit}"
+    }
+
+    read GetKeysFrom(begin_key: &str, limit: u64) -> (Vec<u8>) {
+        "SELECT id FROM data
+         WHERE id >= {begin_key}
+         ORDER BY id
+         LIMIT {limit}"
+    }
+
+    read GetUnreachableChunkIds(below_generation: u64, limit: u64) -> (Vec<u8>) {
+        "SELECT id FROM chunk_generation
+         WHERE last_seen_generation < {below_generation}
+         LIMIT {limit}"
+    }
+
+    // Rechecks the generations of the chunks selected for deletion, locking them so that they
+    // can't be written or marked until the deletion is committed.
+    read SelectUnreachableGenerations(below_generation: u64, >list ids: String) -> (Vec<u8>) {
+        mysql("SELECT id FROM chunk_generation
+               WHERE id IN {ids} AND last_seen_generation < {below_generation}
+               FOR UPDATE")
+        sqlite("SELECT id FROM chunk_generation
+                WHERE id IN {ids} AND last_seen_generation < {below_generation}")
+    }
+
+    write DeleteChunks(>list ids: String) {
+        none,
+        "DELETE FROM chunk WHERE id IN {ids}"
+    }
+
+    write DeleteGenerations(>list ids: String) {
+        none,
+        "DELETE FROM chunk_generation WHERE id IN {ids}"
+    }
+
+    write DeleteDataForChunks(>list chunk_ids: String) {
+        none,
+        "DELETE FROM data WHERE chunk_id IN {chunk_ids}"
+    }
+
+    write DeleteDataForChunksCreatedBefore(created_before: i64, >list chunk_ids: String) {
+        none,
+        "DELETE FROM data
+         WHERE chunk_id IN {chunk_ids} AND creation_time < {created_before}"
+    }
+
     read GetGenerationSizes() -> (Option<u64>, u64) {
         "SELECT chunk_generation.last_seen_generation, CAST(SUM(LENGTH(chunk.value)) AS UNSIGNED)
         FROM chunk LEFT JOIN chunk_generation ON chunk.id = chunk_generation.id
@@ -234,7 +293,7 @@ impl DataSqlStore {
         .compat()
         .await?;
         if res.affected_rows() == 0 {
-            UpdateData::query(
+            let res = UpdateData::query(
                 &self.write_connection[shard_id],
                 &key,
                 &ctime,
@@ -244,6 +303,20 @@ impl DataSqlStore {
             )
             .compat()
             .await?;
+            // A GC sweep may have deleted the row between the insert and the update
+            if res.affected_rows() == 0
+                && SelectIsDataPresent::query(&self.write_connection[shard_id], &key)
+                    .compat()
+                    .await?
+                    .is_empty()
+            {
+                InsertData::query(
+                    &self.write_connection[shard_id],
+                    &[(&key, &ctime, &chunk_id, &chunk_count, &chunking_method)],
+                )
+                .compat()
+                .await?;
+            }
         }
         Ok(())
     }
@@ -281,10 +354,41 @@ impl DataSqlStore {
             .try_flatten_stream()
     }
 
+    /// The first `limit` keys of the shard from `begin_key` up to `end_key`, or to the end of
+    /// the shard if it's None, in order
+    pub(crate) async fn get_keys_in_range(
+        &self,
+        shard_num: usize,
+        begin_key: &str,
+        end_key: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, Error> {
+        let connection = &self.read_master_connection[shard_num];
+        let limit = limit as u64;
+        let rows = match end_key {
+            Some(end_key) => {
+                GetKeysInRange::query(connection, &begin_key, &end_key, &limit)
+                    .compat()
+                    .await?
+            }
+            None => {
+                GetKeysFrom::query(connection, &begin_key, &limit)
+                    .compat()
+                    .await?
+            }
+        };
+        Ok(rows
+            .into_iter()
+            .map(|(id,)| String::from_utf8_lossy(&id).to_string())
+            .collect())
+    }
+
+    pub(crate) fn shard_count(&self) -> usize {
+        self.shard_count.get()
+    }
+
     fn shard(&self, key: &str) -> usize {
-        let mut hasher = XxHash32::with_seed(0);
-        hasher.write(key.as_bytes());
-        (hasher.finish() % self.shard_count.get() as u64) as usize
+        ShardHashing::Modulo.shard_id(key.as_bytes(), self.shard_count.get())
     }
 }
 
@@ -466,6 +570,96 @@ impl ChunkSqlStore {
         Ok(())
     }
 
+    /// Chunks last seen in a generation below the returned one have not been marked in the last
+    /// `unreachable_generations` mark generations, and are at or below the delete generation.
+    pub(crate) fn sweep_below_generation(&self, unreachable_generations: u64) -> u64 {
+        let gc_generations = self.gc_generations.get();
+        let mark_generation = gc_generations.mark_generation as u64;
+        let delete_generation = gc_generations.delete_generation as u64;
+        std::cmp::min(
+            (mark_generation + 1).saturating_sub(unreachable_generations),
+            delete_generation + 1,
+        )
+    }
+
+    pub(crate) async fn get_unreachable_chunk_ids(
+        &self,
+        shard_num: usize,
+        below_generation: u64,
+        limit: u64,
+    ) -> Result<Vec<String>, Error> {
+        let rows = GetUnreachableChunkIds::query(
+            &self.read_master_connection[shard_num],
+            &below_generation,
+            &limit,
+        )
+        .compat()
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(id,)| String::from_utf8_lossy(&id).to_string())
+            .collect())
+    }
+
+    /// Deletes the chunks with the given ids along with their generations, unless they have been
+    /// written or marked since, and the data rows which point to them. Returns the number of
+    /// chunks deleted.
+    ///
+    /// The generations are rechecked, and the chunks, their generations and the data rows in the
+    /// same shard are deleted, in one transaction. Data rows in the other shards can't be part of
+    /// it, and are deleted afterwards if they were created before the sweep started: puts write
+    /// their chunks before choosing the creation time of their data row, so a blob put again
+    /// once its chunks are deleted is kept.
+    pub(crate) async fn delete_unreachable_chunks(
+        &self,
+        shard_num: usize,
+        ids: &[String],
+        below_generation: u64,
+    ) -> Result<u64, Error> {
+        let sweep_start: i64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs()
+            .try_into()?;
+
+        self.delay.delay(shard_num).await;
+        let txn = self.write_connection[shard_num]
+            .start_transaction()
+            .compat()
+            .await?;
+        let (txn, rows) =
+            SelectUnreachableGenerations::query_with_transaction(txn, &below_generation, ids)
+                .compat()
+                .await?;
+        let ids: Vec<_> = rows
+            .into_iter()
+            .map(|(id,)| String::from_utf8_lossy(&id).to_string())
+            .collect();
+        if ids.is_empty() {
+            txn.rollback().compat().await?;
+            return Ok(0);
+        }
+        let (txn, _) = DeleteDataForChunks::query_with_transaction(txn, &ids[..])
+            .compat()
+            .await?;
+        let (txn, deleted) = DeleteChunks::query_with_transaction(txn, &ids[..])
+            .compat()
+            .await?;
+        let (txn, _) = DeleteGenerations::query_with_transaction(txn, &ids[..])
+            .compat()
+            .await?;
+        txn.commit().compat().await?;
+
+        for (data_shard, connection) in self.write_connection.iter().enumerate() {
+            if data_shard != shard_num {
+                self.delay.delay(data_shard).await;
+                DeleteDataForChunksCreatedBefore::query(connection, &sweep_start, &ids[..])
+                    .compat()
+                    .await?;
+            }
+        }
+        Ok(deleted.affected_rows())
+    }
+
     fn shard(&self, key: &str, chunk_id: u32, _chunking_method: ChunkingMethod) -> usize {
         let mut hasher = XxHash32::with_seed(0);
         hasher.write(key.as_bytes());
@@ -293,3 +293,76 @@ async fn generations(fb: FacebookInit) -> Result<()> {
     assert_eq!(generations, vec![Some(10)], "key2 generation not updated");
     Ok(())
 }
+
+#[fbinit::test]
+async fn sweep(fb: FacebookInit) -> Result<()> {
+    let (test_source, config_store) = get_test_config_store();
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+
+    let bs = Arc::new(Sqlblob::with_sqlite_in_memory(
+        DEFAULT_PUT_BEHAVIOUR,
+        &config_store,
+    )?);
+
+    let mut keys = Vec::new();
+    for _ in 0..3 {
+        let suffix: String = thread_rng().sample_iter(&Alphanumeric).take(10).collect();
+        let key = format!("manifoldblob_test_{}", suffix);
+        let mut bytes_in = [0u8; 64];
+        thread_rng().fill_bytes(&mut bytes_in);
+        let blobstore_bytes = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(&bytes_in));
+        bs.put(ctx, key.clone(), blobstore_bytes.clone()).await?;
+        bs.as_inner().set_generation(&key).await?;
+        keys.push((key, blobstore_bytes));
+    }
+
+    // Only the first key is reached by the next mark
+    set_test_generations(test_source.as_ref(), 5, 4, 3, INITIAL_VERSION + 1);
+    tokio::time::delay_for(UPDATE_WAIT_TIME).await;
+    bs.as_inner().set_generation(&keys[0].0).await?;
+
+    // Generation 2 has only been unreachable for two generations, and the delete generation
+    // caps how far the sweep goes
+    assert_eq!(bs.as_inner().sweep_below_generation(3), 2);
+    assert_eq!(bs.as_inner().sweep_below_generation(1), 4);
+
+    let below_generation = bs.as_inner().sweep_below_generation(2);
+    let mut unreachable = Vec::new();
+    for shard_num in 0..SQLITE_SHARD_NUM.get() {
+        let ids = bs
+            .as_inner()
+            .get_unreachable_chunk_ids(shard_num, below_generation, 100)
+            .await?;
+        unreachable.push((shard_num, ids));
+    }
+    assert_eq!(
+        unreachable.iter().map(|(_, ids)| ids.len()).sum::<usize>(),
+        2
+    );
+
+    // A chunk written again after being selected is kept
+    bs.put(ctx, keys[2].0.clone(), keys[2].1.clone()).await?;
+
+    // Data rows are only deleted from other shards if they were created before the sweep, and
+    // creation times are in seconds
+    tokio::time::delay_for(Duration::from_secs(1)).await;
+
+    let mut deleted = 0;
+    for (shard_num, ids) in unreachable {
+        if !ids.is_empty() {
+            deleted += bs
+                .as_inner()
+                .delete_unreachable_chunks(shard_num, &ids, below_generation)
+                .await?;
+        }
+    }
+    assert_eq!(deleted, 1);
+
+    assert!(bs.get(ctx, &keys[0].0).await?.is_some());
+    // The swept key is gone rather than pointing at missing chunks
+    assert!(bs.get(ctx, &keys[1].0).await?.is_none());
+    assert!(!bs.is_present(ctx, &keys[1].0).await?);
+    assert!(bs.get(ctx, &keys[2].0).await?.is_some());
+    Ok(())
+}
@@ -14,8 +14,8 @@ use stats::prelude::*;
 use context::CoreContext;
 
 use crate::{
-    Blobstore, BlobstoreBytes, BlobstoreGetData, BlobstorePutOps, BlobstoreWithLink,
-    OverwriteStatus, PutBehaviour,
+    Blobstore, BlobstoreBytes, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreKeyParam,
+    BlobstoreKeySource, BlobstorePutOps, BlobstoreWithLink, OverwriteStatus, PutBehaviour,
 };
 
 define_stats_struct! {
@@ -36,6 +36,9 @@ define_stats_struct! {
     link: timeseries(Rate, Sum),
     link_ok: timeseries(Rate, Sum),
     link_err: timeseries(Rate, Sum),
+    enumerate: timeseries(Rate, Sum),
+    enumerate_ok: timeseries(Rate, Sum),
+    enumerate_err: timeseries(Rate, Sum),
 }
 
 #[derive(Debug)]
@@ -175,6 +178,23 @@ impl<T: BlobstoreWithLink> BlobstoreWithLink for CountedBlobstore<T> {
     }
 }
 
+#[async_trait]
+impl<T: BlobstoreKeySource> BlobstoreKeySource for CountedBlobstore<T> {
+    async fn enumerate<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        range: &'a BlobstoreKeyParam,
+    ) -> Result<BlobstoreEnumerationData> {
+        self.stats.enumerate.add_value(1);
+        let res = self.blobstore.enumerate(ctx, range).await;
+        match res {
+            Ok(_) => self.stats.enumerate_ok.add_value(1),
+            Err(_) => self.stats.enumerate_err.add_value(1),
+        }
+        res
+    }
+}
+
 impl<T: Blobstore> Deref for CountedBlobstore<T> {
     type Target = T;
 
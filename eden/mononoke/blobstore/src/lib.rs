@@ -22,6 +22,7 @@ use std::collections::HashSet;
 use std::fmt;
 use std::io::Cursor;
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
+use std::time::Duration;
 use strum_macros::{AsRefStr, Display, EnumIter, EnumString, IntoStaticStr};
 use thiserror::Error;
 
@@ -401,8 +402,26 @@ pub trait BlobstoreWithLink: Blobstore {
     ) -> Result<()>;
 }
 
+/// Mixin trait for blobstores that can forget blobs after a time to live, for blobs which are
+/// only needed for a while, such as temporary uploads. Stores without native support can get it
+/// from `expiringblob::ExpiringBlob`.
+#[async_trait]
+#[auto_impl(Arc, Box)]
+pub trait BlobstoreWithTtl: Blobstore {
+    /// Like `Blobstore::put`, but once `ttl` has elapsed `get` may return None for `key`.
+    /// With a `ttl` of None the blob is kept like any other.
+    async fn put_with_ttl<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Option<Duration>,
+    ) -> Result<()>;
+}
+
 /// BlobstoreKeySource Interface
-/// Abstract for use with populate_healer
+/// Lists the keys of a blobstore, for use with populate_healer, the walker, scrub and GC tooling.
+/// Keys are returned a page at a time: callers enumerate `next_token` until it is `None`.
 #[async_trait]
 pub trait BlobstoreKeySource: Blobstore {
     async fn enumerate<'a>(
@@ -412,13 +431,49 @@ pub trait BlobstoreKeySource: Blobstore {
     ) -> Result<BlobstoreEnumerationData>;
 }
 
+/// Number of keys the local blobstores return per enumeration page
+pub const ENUMERATION_PAGE_SIZE: usize = 10_000;
+
+/// Range of keys to enumerate. `begin_key` is inclusive and `end_key` exclusive, as in manifold.
+/// An empty `end_key` means the range is unbounded.
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct BlobstoreKeyRange {
-    // Should match manifold inclusiveness rules, please check and document.
     pub begin_key: String,
     pub end_key: String,
 }
 
+impl BlobstoreKeyRange {
+    /// The range of all the keys starting with `prefix`
+    pub fn prefix(prefix: &str) -> Self {
+        // The end is the prefix with its last char incremented, dropping the chars which can't be
+        let mut end_key = prefix.to_string();
+        while let Some(last) = end_key.pop() {
+            let next = (last as u32 + 1..=char::MAX as u32).find_map(std::char::from_u32);
+            if let Some(next) = next {
+                end_key.push(next);
+                break;
+            }
+        }
+        Self {
+            begin_key: prefix.to_string(),
+            end_key,
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        key >= self.begin_key.as_str() && (self.end_key.is_empty() || key < self.end_key.as_str())
+    }
+
+    /// The rest of this range after `key`, to resume an enumeration which stopped at `key`
+    pub fn after(&self, key: &str) -> Self {
+        Self {
+            // The smallest string greater than key
+            begin_key: format!("{}\0", key),
+            end_key: self.end_key.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub enum BlobstoreKeyToken {
     // For fileblob and manifold
@@ -468,6 +523,12 @@ impl From<RangeFull> for BlobstoreKeyParam {
     }
 }
 
+impl From<BlobstoreKeyRange> for BlobstoreKeyParam {
+    fn from(range: BlobstoreKeyRange) -> Self {
+        BlobstoreKeyParam::Start(range)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BlobstoreEnumerationData {
     pub keys: HashSet<String>,
@@ -475,6 +536,25 @@ pub struct BlobstoreEnumerationData {
     pub next_token: Option<BlobstoreKeyParam>,
 }
 
+impl BlobstoreEnumerationData {
+    /// The first page of `keys` in `range`, for stores which can list their keys in order.
+    /// `keys` must be sorted, and hold more than `page_size` keys if there are more in the store.
+    pub fn page(range: &BlobstoreKeyRange, mut keys: Vec<String>, page_size: usize) -> Self {
+        keys.retain(|key| range.contains(key));
+        let next_token = if keys.len() > page_size {
+            keys.truncate(page_size);
+            keys.last()
+                .map(|last| BlobstoreKeyParam::Start(range.after(last)))
+        } else {
+            None
+        };
+        Self {
+            keys: keys.into_iter().collect(),
+            next_token,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum LoadableError {
     #[error("Blobstore error")]
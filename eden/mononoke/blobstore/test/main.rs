@@ -11,6 +11,7 @@
 #![feature(never_type)]
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Error;
 use borrowed::borrowed;
@@ -19,7 +20,10 @@ use fbinit::FacebookInit;
 use strum::IntoEnumIterator;
 use tempdir::TempDir;
 
-use blobstore::{Blobstore, BlobstorePutOps, BlobstoreWithLink, OverwriteStatus, PutBehaviour};
+use blobstore::{
+    Blobstore, BlobstoreEnumerationData, BlobstoreKeyParam, BlobstoreKeyRange, BlobstoreKeySource,
+    BlobstorePutOps, BlobstoreWithLink, BlobstoreWithTtl, OverwriteStatus, PutBehaviour,
+};
 use context::CoreContext;
 use fileblob::Fileblob;
 use memblob::Memblob;
@@ -147,6 +151,157 @@ async fn missing<B: Blobstore>(fb: FacebookInit, blobstore: B) -> Result<(), Err
     Ok(())
 }
 
+// All the keys in range, which must fit in a page
+async fn enumerate_all<B: BlobstoreKeySource>(
+    ctx: &CoreContext,
+    blobstore: &B,
+    range: BlobstoreKeyParam,
+) -> Result<Vec<String>, Error> {
+    let data = blobstore.enumerate(ctx, &range).await?;
+    assert_eq!(data.next_token, None);
+    let mut keys: Vec<_> = data.keys.into_iter().collect();
+    keys.sort();
+    Ok(keys)
+}
+
+async fn enumerate<B: BlobstoreKeySource>(fb: FacebookInit, blobstore: B) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+
+    let keys = vec![
+        "repo0000.a",
+        "repo0000.b with space#",
+        "repo0000.c",
+        "repo0001.a",
+        "repo00010.a",
+    ];
+    let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"appleveldata"));
+    for key in &keys {
+        blobstore.put(ctx, key.to_string(), value.clone()).await?;
+    }
+
+    assert_eq!(
+        enumerate_all(ctx, &blobstore, BlobstoreKeyParam::from(..)).await?,
+        keys
+    );
+    assert_eq!(
+        enumerate_all(
+            ctx,
+            &blobstore,
+            BlobstoreKeyRange::prefix("repo0000.").into()
+        )
+        .await?,
+        &keys[..3]
+    );
+    assert_eq!(
+        enumerate_all(
+            ctx,
+            &blobstore,
+            BlobstoreKeyRange::prefix("repo0001").into()
+        )
+        .await?,
+        &keys[3..]
+    );
+    // The begin key is included, and the end key excluded
+    assert_eq!(
+        enumerate_all(
+            ctx,
+            &blobstore,
+            BlobstoreKeyParam::from("repo0000.c".to_string().."repo0001.a".to_string())
+        )
+        .await?,
+        &keys[2..3]
+    );
+    assert_eq!(
+        enumerate_all(
+            ctx,
+            &blobstore,
+            BlobstoreKeyParam::from("repo0001.a".to_string()..)
+        )
+        .await?,
+        &keys[3..]
+    );
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_fileblob_enumerate(fb: FacebookInit) -> Result<(), Error> {
+    let dir = TempDir::new("fileblob_test")?;
+    enumerate(fb, Fileblob::open(&dir, PutBehaviour::Overwrite)?).await
+}
+
+#[fbinit::test]
+async fn test_sqlblob_enumerate(fb: FacebookInit) -> Result<(), Error> {
+    let blobstore =
+        Sqlblob::with_sqlite_in_memory(PutBehaviour::Overwrite, &(get_test_config_store().1))?;
+    enumerate(fb, blobstore).await
+}
+
+#[test]
+fn test_enumeration_page() {
+    let range = BlobstoreKeyRange::prefix("k");
+    let keys = vec!["j", "k1", "k2", "k3", "l"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+    let page = BlobstoreEnumerationData::page(&range, keys, 2);
+    let mut page_keys: Vec<_> = page.keys.into_iter().collect();
+    page_keys.sort();
+    assert_eq!(page_keys, vec!["k1", "k2"]);
+    match page.next_token {
+        Some(BlobstoreKeyParam::Start(next)) => {
+            assert!(!next.contains("k2"));
+            assert!(next.contains("k3"));
+            assert!(!next.contains("l"));
+        }
+        other => panic!("unexpected next token {:?}", other),
+    }
+}
+
+#[fbinit::test]
+async fn test_memblob_ttl(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+    let blobstore = Memblob::new(PutBehaviour::IfAbsent);
+    let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"ephemeral"));
+
+    blobstore
+        .put_with_ttl(ctx, "forever".to_string(), value.clone(), None)
+        .await?;
+    blobstore
+        .put_with_ttl(
+            ctx,
+            "later".to_string(),
+            value.clone(),
+            Some(Duration::from_secs(3600)),
+        )
+        .await?;
+    blobstore
+        .put_with_ttl(
+            ctx,
+            "now".to_string(),
+            value.clone(),
+            Some(Duration::from_secs(0)),
+        )
+        .await?;
+
+    for key in &["forever", "later"] {
+        let fetched = blobstore.get(ctx, key).await?.map(|data| data.into_bytes());
+        assert_eq!(fetched, Some(value.clone()));
+    }
+    assert!(blobstore.get(ctx, "now").await?.is_none());
+    assert!(!blobstore.is_present(ctx, "now").await?);
+
+    // An expired blob is absent, so it can be written again
+    let status = blobstore
+        .put_with_status(ctx, "now".to_string(), value.clone())
+        .await?;
+    assert_eq!(status, OverwriteStatus::New);
+    assert!(blobstore.is_present(ctx, "now").await?);
+    Ok(())
+}
+
 macro_rules! blobstore_test_impl {
     ($mod_name: ident => {
         state: $state: expr,
@@ -349,3 +504,48 @@ async fn cache_blob_tests(fb: FacebookInit, expect_zstd: bool) -> Result<(), Err
 
     Ok(())
 }
+
+#[cfg(fbcode_build)]
+#[fbinit::test]
+async fn test_cache_blob_absent(fb: FacebookInit) -> Result<(), Error> {
+    let options = cacheblob::CachelibBlobstoreOptions::new_eager(None)
+        .with_absent_ttl(Some(std::time::Duration::from_secs(3600)));
+
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+    create_cache(fb)?;
+    let blob_pool = Arc::new(cachelib::get_or_create_pool(
+        "blob_pool_absent",
+        20 * 1024 * 1024,
+    )?);
+    let presence_pool = Arc::new(cachelib::get_or_create_pool(
+        "presence_pool_absent",
+        20 * 1024 * 1024,
+    )?);
+
+    let inner = Arc::new(Memblob::new(PutBehaviour::Overwrite));
+    let cache_blob =
+        cacheblob::new_cachelib_blobstore(inner.clone(), blob_pool, presence_pool, options);
+
+    let key = "absent_key";
+    let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"data"));
+    assert!(cache_blob.get(ctx, key).await?.is_none());
+
+    // Written behind the cache's back, so still reported absent
+    inner.put(ctx, key.to_owned(), value.clone()).await?;
+    assert!(cache_blob.get(ctx, key).await?.is_none());
+    assert!(!cache_blob.is_present(ctx, key).await?);
+
+    // Written through the cache, so present straight away
+    cache_blob.put(ctx, key.to_owned(), value.clone()).await?;
+    assert!(cache_blob.is_present(ctx, key).await?);
+    assert_eq!(
+        cache_blob
+            .get(ctx, key)
+            .await?
+            .map(|bytes| bytes.into_bytes()),
+        Some(value)
+    );
+
+    Ok(())
+}
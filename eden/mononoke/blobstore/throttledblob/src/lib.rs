@@ -18,12 +18,12 @@ use nonzero_ext::nonzero;
 use std::{
     convert::TryInto,
     fmt,
-    num::{NonZeroU32, NonZeroUsize},
+    num::{NonZeroU32, NonZeroU8, NonZeroUsize},
     time::Duration,
 };
 
 use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
-use context::CoreContext;
+use context::{CoreContext, SessionClass};
 use mononoke_types::BlobstoreBytes;
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -35,6 +35,10 @@ pub struct ThrottleOptions {
     pub read_burst_bytes: Option<NonZeroUsize>,
     pub write_burst_bytes: Option<NonZeroUsize>,
     pub bytes_min_count: Option<NonZeroUsize>,
+    // If Some, this percentage of each qps and bytes/s limit is set aside for background sessions,
+    // and the rest for user waiting sessions, so that batch jobs can't starve interactive traffic.
+    // Otherwise all sessions share the limits.
+    pub background_percent: Option<NonZeroU8>,
 }
 
 impl ThrottleOptions {
@@ -59,13 +63,22 @@ pub const DEFAULT_BYTES_MIN_COUNT: usize = 1_000;
 // Default is set high as we'd rather throttle than error unless specified
 pub const DEFAULT_BURST_BYTES_S: usize = 100_000_000;
 
+type Limiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock>;
+
+// The limiters a session is throttled by
+struct Limiters {
+    read_qps_limiter: Option<Limiter>,
+    write_qps_limiter: Option<Limiter>,
+    read_bytes_limiter: Option<Limiter>,
+    write_bytes_limiter: Option<Limiter>,
+}
+
 /// A Blobstore that rate limits the number of read and write operations.
 pub struct ThrottledBlob<T: fmt::Debug> {
     blobstore: T,
-    read_qps_limiter: Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    write_qps_limiter: Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    read_bytes_limiter: Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    write_bytes_limiter: Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    limiters: Limiters,
+    // If Some, background sessions are throttled by these instead of `limiters`
+    background_limiters: Option<Limiters>,
     bytes_min_count: usize,
     /// The options fields are used for Debug. They are not consulted at runtime.
     options: ThrottleOptions,
@@ -77,19 +90,28 @@ fn jitter() -> Jitter {
     Jitter::up_to(JITTER_MAX)
 }
 
-impl<T: fmt::Debug + Send + Sync> ThrottledBlob<T> {
-    pub async fn new(blobstore: T, options: ThrottleOptions) -> Self {
-        let qps_limiter =
-            |qps: Option<NonZeroU32>| qps.map(|qps| RateLimiter::direct(Quota::per_second(qps)));
-        let read_qps_limiter = qps_limiter(options.read_qps);
-        let write_qps_limiter = qps_limiter(options.write_qps);
+// `percent` of `limit`, but at least 1
+fn share(limit: usize, percent: u8) -> usize {
+    (limit.saturating_mul(percent as usize) / 100).max(1)
+}
 
-        let bytes_min_count = options
-            .bytes_min_count
-            .map_or(DEFAULT_BYTES_MIN_COUNT, |v| v.get());
+impl Limiters {
+    // Limiters for `percent` of the limits in `options`. Bursts are not scaled down, so that
+    // the largest blob that can be read or written is the same for everyone.
+    fn new(options: &ThrottleOptions, bytes_min_count: usize, percent: u8) -> Self {
+        let qps_limiter = |qps: Option<NonZeroU32>| {
+            qps.map(|qps| {
+                let qps = share(qps.get() as usize, percent)
+                    .try_into()
+                    .unwrap_or(u32::MAX);
+                RateLimiter::direct(Quota::per_second(
+                    NonZeroU32::new(qps).unwrap_or(nonzero!(1u32)),
+                ))
+            })
+        };
         let bytes_limiter = |bytes_s: Option<NonZeroUsize>, burst_bytes_s: Option<NonZeroUsize>| {
             bytes_s.map(|bytes_s| {
-                let count_s = bytes_to_count(bytes_min_count, bytes_s.get());
+                let count_s = bytes_to_count(bytes_min_count, share(bytes_s.get(), percent));
                 RateLimiter::direct(Quota::per_second(count_s).allow_burst(
                     burst_bytes_s.map_or_else(
                         || bytes_to_count(bytes_min_count, DEFAULT_BURST_BYTES_S),
@@ -98,20 +120,49 @@ impl<T: fmt::Debug + Send + Sync> ThrottledBlob<T> {
                 ))
             })
         };
-        let read_bytes_limiter = bytes_limiter(options.read_bytes, options.read_burst_bytes);
-        let write_bytes_limiter = bytes_limiter(options.write_bytes, options.write_burst_bytes);
+
+        Self {
+            read_qps_limiter: qps_limiter(options.read_qps),
+            write_qps_limiter: qps_limiter(options.write_qps),
+            read_bytes_limiter: bytes_limiter(options.read_bytes, options.read_burst_bytes),
+            write_bytes_limiter: bytes_limiter(options.write_bytes, options.write_burst_bytes),
+        }
+    }
+}
+
+impl<T: fmt::Debug + Send + Sync> ThrottledBlob<T> {
+    pub async fn new(blobstore: T, options: ThrottleOptions) -> Self {
+        let bytes_min_count = options
+            .bytes_min_count
+            .map_or(DEFAULT_BYTES_MIN_COUNT, |v| v.get());
+        let (limiters, background_limiters) = match options.background_percent {
+            Some(background_percent) => {
+                let background_percent = background_percent.get().min(99);
+                (
+                    Limiters::new(&options, bytes_min_count, 100 - background_percent),
+                    Some(Limiters::new(&options, bytes_min_count, background_percent)),
+                )
+            }
+            None => (Limiters::new(&options, bytes_min_count, 100), None),
+        };
 
         Self {
             blobstore,
-            read_qps_limiter,
-            write_qps_limiter,
-            read_bytes_limiter,
-            write_bytes_limiter,
+            limiters,
+            background_limiters,
             bytes_min_count,
             options,
         }
     }
 
+    // The limiters the session of `ctx` is throttled by
+    fn limiters(&self, ctx: &CoreContext) -> &Limiters {
+        match (ctx.session().session_class(), &self.background_limiters) {
+            (SessionClass::Background, Some(background_limiters)) => background_limiters,
+            _ => &self.limiters,
+        }
+    }
+
     // Convert from number of bytes to the count to request from until_n_ready
     fn count_n(&self, num_bytes: usize) -> NonZeroU32 {
         bytes_to_count(self.bytes_min_count, num_bytes)
@@ -126,17 +177,18 @@ impl<T: Blobstore> Blobstore for ThrottledBlob<T> {
         ctx: &'a CoreContext,
         key: &'a str,
     ) -> Result<Option<BlobstoreGetData>> {
-        if let Some(limiter) = self.read_qps_limiter.as_ref() {
+        let limiters = self.limiters(ctx);
+        if let Some(limiter) = limiters.read_qps_limiter.as_ref() {
             limiter.until_ready_with_jitter(jitter()).await;
         }
-        if let Some(limiter) = self.read_bytes_limiter.as_ref() {
+        if let Some(limiter) = limiters.read_bytes_limiter.as_ref() {
             // Only know we'll use some bytes. Access one count so we throttle if already over the limit
             limiter.until_ready_with_jitter(jitter()).await;
         }
 
         let get_data = self.blobstore.get(ctx, key).await?;
 
-        if let Some(limiter) = self.read_bytes_limiter.as_ref() {
+        if let Some(limiter) = limiters.read_bytes_limiter.as_ref() {
             // Now we know the size, request rest of the quota
             if let Some(data) = get_data.as_ref() {
                 let count_n = self.count_n(data.as_bytes().len());
@@ -157,10 +209,11 @@ impl<T: Blobstore> Blobstore for ThrottledBlob<T> {
         key: String,
         value: BlobstoreBytes,
     ) -> Result<()> {
-        if let Some(limiter) = self.write_qps_limiter.as_ref() {
+        let limiters = self.limiters(ctx);
+        if let Some(limiter) = limiters.write_qps_limiter.as_ref() {
             limiter.until_ready_with_jitter(jitter()).await;
         }
-        if let Some(limiter) = self.write_bytes_limiter.as_ref() {
+        if let Some(limiter) = limiters.write_bytes_limiter.as_ref() {
             limiter
                 .until_n_ready_with_jitter(self.count_n(value.len()), jitter())
                 .await?;
@@ -169,12 +222,13 @@ impl<T: Blobstore> Blobstore for ThrottledBlob<T> {
     }
 
     async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
-        if let Some(limiter) = self.read_qps_limiter.as_ref() {
+        let limiters = self.limiters(ctx);
+        if let Some(limiter) = limiters.read_qps_limiter.as_ref() {
             limiter.until_ready_with_jitter(jitter()).await;
         }
         // TODO(ahornby) would need to enhance Blobstore::is_present() to know how many bytes it transferred.
         // Some stores fetch just a flag, some fetch all the data then throw it away.
-        if let Some(limiter) = self.read_bytes_limiter.as_ref() {
+        if let Some(limiter) = limiters.read_bytes_limiter.as_ref() {
             limiter.until_ready_with_jitter(jitter()).await;
         }
         self.blobstore.is_present(ctx, key).await
@@ -190,10 +244,11 @@ impl<T: BlobstorePutOps> BlobstorePutOps for ThrottledBlob<T> {
         value: BlobstoreBytes,
         put_behaviour: PutBehaviour,
     ) -> Result<OverwriteStatus> {
-        if let Some(limiter) = self.write_qps_limiter.as_ref() {
+        let limiters = self.limiters(ctx);
+        if let Some(limiter) = limiters.write_qps_limiter.as_ref() {
             limiter.until_ready_with_jitter(jitter()).await;
         }
-        if let Some(limiter) = self.write_bytes_limiter.as_ref() {
+        if let Some(limiter) = limiters.write_bytes_limiter.as_ref() {
             limiter
                 .until_n_ready_with_jitter(self.count_n(value.len()), jitter())
                 .await?;
@@ -209,10 +264,11 @@ impl<T: BlobstorePutOps> BlobstorePutOps for ThrottledBlob<T> {
         key: String,
         value: BlobstoreBytes,
     ) -> Result<OverwriteStatus> {
-        if let Some(limiter) = self.write_qps_limiter.as_ref() {
+        let limiters = self.limiters(ctx);
+        if let Some(limiter) = limiters.write_qps_limiter.as_ref() {
             limiter.until_ready_with_jitter(jitter()).await;
         }
-        if let Some(limiter) = self.write_bytes_limiter.as_ref() {
+        if let Some(limiter) = limiters.write_bytes_limiter.as_ref() {
             limiter
                 .until_n_ready_with_jitter(self.count_n(value.len()), jitter())
                 .await?;
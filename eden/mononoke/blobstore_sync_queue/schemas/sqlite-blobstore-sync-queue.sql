@@ -13,5 +13,12 @@ CREATE TABLE `blobstore_sync_queue` (
   `multiplex_id` INTEGER NOT NULL,
   `original_timestamp` BIGINT NOT NULL DEFAULT 0,
   `operation_key` BINARY(16) NOT NULL DEFAULT X'00000000000000000000000000000000',
-  'blob_size' BIGINT
+  `blob_size` BIGINT
+);
+
+CREATE TABLE `blobstore_sync_queue_access` (
+  `multiplex_id` INTEGER NOT NULL,
+  `blobstore_key` varchar NOT NULL,
+  `access_count` BIGINT NOT NULL DEFAULT 0,
+  PRIMARY KEY (`multiplex_id`, `blobstore_key`)
 );
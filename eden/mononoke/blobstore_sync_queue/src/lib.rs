@@ -29,6 +29,7 @@ use sql::{queries, Connection};
 pub use sql_construct::SqlConstruct;
 pub use sql_ext::SqlConnections;
 use stats::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::iter::IntoIterator;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -38,6 +39,7 @@ define_stats! {
     adds: timeseries(Rate, Sum),
     iters: timeseries(Rate, Sum),
     dels: timeseries(Rate, Sum),
+    accesses: timeseries(Rate, Sum),
 }
 
 // Identifier for given blobstore operation to faciliate correlating same operation
@@ -94,6 +96,9 @@ pub struct BlobstoreSyncQueueEntry {
     pub id: Option<u64>,
     pub operation_key: OperationKey,
     pub blob_size: Option<u64>,
+    /// How many times the blob was read while waiting to be healed. Only `iter_by_access_count`
+    /// fetches it, other reads leave it at 0.
+    pub access_count: u64,
 }
 
 impl BlobstoreSyncQueueEntry {
@@ -113,10 +118,19 @@ impl BlobstoreSyncQueueEntry {
             operation_key,
             blob_size,
             id: None,
+            access_count: 0,
         }
     }
 }
 
+/// How much the healer has left to do for a multiplex
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlobstoreSyncQueueDepth {
+    pub entries: u64,
+    /// When the oldest entry was added, if the queue is not empty
+    pub oldest: Option<DateTime>,
+}
+
 #[async_trait]
 #[auto_impl(Arc, Box)]
 pub trait BlobstoreSyncQueue: Send + Sync {
@@ -151,6 +165,38 @@ pub trait BlobstoreSyncQueue: Send + Sync {
         limit: usize,
     ) -> Result<Vec<BlobstoreSyncQueueEntry>, Error>;
 
+    /// Same as `iter`, except that group (1) holds the entries of the blobs which were read most
+    /// while waiting to be healed, rather than any entries. This is more expensive for the
+    /// database, as it has to look at all the entries older than `older_than`.
+    async fn iter_by_access_count<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key_like: Option<&'a str>,
+        multiplex_id: MultiplexId,
+        older_than: DateTime,
+        limit: usize,
+    ) -> Result<Vec<BlobstoreSyncQueueEntry>, Error> {
+        self.iter(ctx, key_like, multiplex_id, older_than, limit)
+            .await
+    }
+
+    /// Note that the blob of `entries` was read before it was healed, so that it is healed
+    /// sooner. The counts are kept apart from the queue entries, in the
+    /// `blobstore_sync_queue_access` table, until the blob is healed.
+    async fn record_access<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        _entries: &'a [BlobstoreSyncQueueEntry],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn depth<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        multiplex_id: MultiplexId,
+    ) -> Result<BlobstoreSyncQueueDepth, Error>;
+
     async fn del<'a>(
         &'a self,
         ctx: &'a CoreContext,
@@ -188,6 +234,32 @@ queries! {
          VALUES {values}"
     }
 
+    write AddAccessCounts(values: (
+        multiplex_id: MultiplexId,
+        blobstore_key: String,
+        access_count: u64,
+    )) {
+        none,
+        mysql("INSERT INTO blobstore_sync_queue_access (multiplex_id, blobstore_key, access_count)
+               VALUES {values}
+               ON DUPLICATE KEY UPDATE access_count = access_count + VALUES(access_count)")
+        sqlite("INSERT INTO blobstore_sync_queue_access (multiplex_id, blobstore_key, access_count)
+                VALUES {values}
+                ON CONFLICT (multiplex_id, blobstore_key) DO UPDATE SET
+                  access_count = access_count + excluded.access_count")
+    }
+
+    write DeleteHealedAccessCounts(multiplex_id: MultiplexId, >list keys: String) {
+        none,
+        "DELETE FROM blobstore_sync_queue_access
+         WHERE multiplex_id = {multiplex_id} AND blobstore_key IN {keys} AND NOT EXISTS (
+               SELECT 1
+               FROM blobstore_sync_queue
+               WHERE blobstore_sync_queue.multiplex_id = blobstore_sync_queue_access.multiplex_id
+               AND blobstore_sync_queue.blobstore_key = blobstore_sync_queue_access.blobstore_key
+         )"
+    }
+
     write DeleteEntries(>list ids: u64) {
         none,
         "DELETE FROM blobstore_sync_queue WHERE id in {ids}"
@@ -248,6 +320,64 @@ queries! {
          FROM blobstore_sync_queue
          WHERE blobstore_key = {key}"
     }
+
+    read GetRangeOfEntriesByAccessCount(multiplex_id: MultiplexId, older_than: Timestamp, limit: usize) -> (
+        String,
+        BlobstoreId,
+        MultiplexId,
+        Timestamp,
+        OperationKey,
+        u64,
+        Option<u64>,
+        u64,
+    ) {
+        "SELECT blobstore_key, blobstore_id, multiplex_id, add_timestamp, blobstore_sync_queue.operation_key, id, blob_size, b.access_count
+         FROM blobstore_sync_queue
+         JOIN (
+               SELECT q.operation_key, MAX(COALESCE(a.access_count, 0)) AS access_count
+               FROM blobstore_sync_queue q
+               LEFT JOIN blobstore_sync_queue_access a
+               ON a.multiplex_id = q.multiplex_id AND a.blobstore_key = q.blobstore_key
+               WHERE q.add_timestamp <= {older_than} AND q.multiplex_id = {multiplex_id}
+               GROUP BY q.operation_key
+               ORDER BY access_count DESC
+               LIMIT {limit}
+         ) b
+         ON blobstore_sync_queue.operation_key = b.operation_key AND multiplex_id = {multiplex_id}
+         "
+    }
+
+    read GetRangeOfEntriesLikeByAccessCount(blobstore_key_like: String, multiplex_id: MultiplexId, older_than: Timestamp, limit: usize) -> (
+        String,
+        BlobstoreId,
+        MultiplexId,
+        Timestamp,
+        OperationKey,
+        u64,
+        Option<u64>,
+        u64,
+    ) {
+        "SELECT blobstore_key, blobstore_id, multiplex_id, add_timestamp, blobstore_sync_queue.operation_key, id, blob_size, b.access_count
+         FROM blobstore_sync_queue
+         JOIN (
+               SELECT q.operation_key, MAX(COALESCE(a.access_count, 0)) AS access_count
+               FROM blobstore_sync_queue q
+               LEFT JOIN blobstore_sync_queue_access a
+               ON a.multiplex_id = q.multiplex_id AND a.blobstore_key = q.blobstore_key
+               WHERE q.blobstore_key LIKE {blobstore_key_like} AND q.add_timestamp <= {older_than} AND q.multiplex_id = {multiplex_id}
+               GROUP BY q.operation_key
+               ORDER BY access_count DESC
+               LIMIT {limit}
+         ) b
+         ON blobstore_sync_queue.operation_key = b.operation_key AND multiplex_id = {multiplex_id}
+         "
+    }
+
+    read GetQueueDepth(multiplex_id: MultiplexId) -> (u64, Option<Timestamp>) {
+        "SELECT COUNT(*), MIN(add_timestamp)
+         FROM blobstore_sync_queue
+         WHERE multiplex_id = {multiplex_id}"
+    }
 }
 
 impl SqlConstruct for SqlBlobstoreSyncQueue {
@@ -345,6 +475,66 @@ async fn insert_entries(
     Ok(())
 }
 
+type EntryRow = (
+    String,
+    BlobstoreId,
+    MultiplexId,
+    Timestamp,
+    OperationKey,
+    u64,
+    Option<u64>,
+);
+
+fn entry_from_row(row: EntryRow) -> BlobstoreSyncQueueEntry {
+    let (blobstore_key, blobstore_id, multiplex_id, timestamp, operation_key, id, blob_size) = row;
+    BlobstoreSyncQueueEntry {
+        blobstore_key,
+        blobstore_id,
+        multiplex_id,
+        timestamp: timestamp.into(),
+        operation_key,
+        id: Some(id),
+        blob_size,
+        access_count: 0,
+    }
+}
+
+type EntryRowWithAccessCount = (
+    String,
+    BlobstoreId,
+    MultiplexId,
+    Timestamp,
+    OperationKey,
+    u64,
+    Option<u64>,
+    u64,
+);
+
+fn entry_from_row_with_access_count(row: EntryRowWithAccessCount) -> BlobstoreSyncQueueEntry {
+    let (
+        blobstore_key,
+        blobstore_id,
+        multiplex_id,
+        timestamp,
+        operation_key,
+        id,
+        blob_size,
+        access_count,
+    ) = row;
+    BlobstoreSyncQueueEntry {
+        access_count,
+        ..entry_from_row((
+            blobstore_key,
+            blobstore_id,
+            multiplex_id,
+            timestamp,
+            operation_key,
+            id,
+            blob_size,
+        ))
+    }
+}
+
 #[async_trait]
 impl BlobstoreSyncQueue for SqlBlobstoreSyncQueue {
     async fn add_many<'a>(
@@ -410,34 +600,86 @@ impl BlobstoreSyncQueue for SqlBlobstoreSyncQueue {
             }
         }?;
 
+        Ok(rows.into_iter().map(entry_from_row).collect())
+    }
+
+    async fn iter_by_access_count<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key_like: Option<&'a str>,
+        multiplex_id: MultiplexId,
+        older_than: DateTime,
+        limit: usize,
+    ) -> Result<Vec<BlobstoreSyncQueueEntry>, Error> {
+        STATS::iters.add_value(1);
+        let rows = match key_like {
+            Some(sql_like) => {
+                GetRangeOfEntriesLikeByAccessCount::query(
+                    &self.read_master_connection,
+                    &sql_like.to_owned(),
+                    &multiplex_id,
+                    &older_than.into(),
+                    &limit,
+                )
+                .compat()
+                .await
+            }
+            None => {
+                GetRangeOfEntriesByAccessCount::query(
+                    &self.read_master_connection,
+                    &multiplex_id,
+                    &older_than.into(),
+                    &limit,
+                )
+                .compat()
+                .await
+            }
+        }?;
+
         Ok(rows
             .into_iter()
-            .map(
-                |
-                    (
-                        blobstore_key,
-                        blobstore_id,
-                        multiplex_id,
-                        timestamp,
-                        operation_key,
-                        id,
-                        blob_size,
-                    ),
-                | {
-                    BlobstoreSyncQueueEntry {
-                        blobstore_key,
-                        blobstore_id,
-                        multiplex_id,
-                        timestamp: timestamp.into(),
-                        operation_key,
-                        id: Some(id),
-                        blob_size,
-                    }
-                },
-            )
+            .map(entry_from_row_with_access_count)
             .collect())
     }
 
+    async fn record_access<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        entries: &'a [BlobstoreSyncQueueEntry],
+    ) -> Result<(), Error> {
+        let keys: HashSet<_> = entries
+            .iter()
+            .map(|entry| (entry.multiplex_id, entry.blobstore_key.clone()))
+            .collect();
+        if keys.is_empty() {
+            return Ok(());
+        }
+        STATS::accesses.add_value(1);
+        let values: Vec<_> = keys
+            .iter()
+            .map(|(multiplex_id, blobstore_key)| (multiplex_id, blobstore_key, &1u64))
+            .collect();
+        AddAccessCounts::query(&self.write_connection, &values[..])
+            .compat()
+            .await?;
+        Ok(())
+    }
+
+    async fn depth<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        multiplex_id: MultiplexId,
+    ) -> Result<BlobstoreSyncQueueDepth, Error> {
+        let rows = GetQueueDepth::query(&self.read_connection, &multiplex_id)
+            .compat()
+            .await?;
+        let (entries, oldest) = rows.into_iter().next().unwrap_or((0, None));
+        Ok(BlobstoreSyncQueueDepth {
+            entries,
+            oldest: oldest.map(DateTime::from),
+        })
+    }
+
     async fn del<'a>(
         &'a self,
         _ctx: &'a CoreContext,
@@ -458,6 +700,26 @@ impl BlobstoreSyncQueue for SqlBlobstoreSyncQueue {
                 .await?;
             STATS::dels.add_value(deletion_result.affected_rows() as i64);
         }
+
+        // Forget the access counts of the blobs which are fully healed. Access counts only
+        // exist to order healing, and the table may not have been created for this queue, so
+        // failing to clean them up must not fail the deletion.
+        let mut healed_keys: HashMap<MultiplexId, HashSet<String>> = HashMap::new();
+        for entry in entries {
+            healed_keys
+                .entry(entry.multiplex_id)
+                .or_default()
+                .insert(entry.blobstore_key.clone());
+        }
+        for (multiplex_id, keys) in healed_keys {
+            let keys: Vec<_> = keys.into_iter().collect();
+            for chunk in keys.chunks(10_000) {
+                let _ =
+                    DeleteHealedAccessCounts::query(&self.write_connection, &multiplex_id, chunk)
+                        .compat()
+                        .await;
+            }
+        }
         Ok(())
     }
 
@@ -470,31 +732,6 @@ impl BlobstoreSyncQueue for SqlBlobstoreSyncQueue {
             .compat()
             .await
             .with_context(|| ErrorKind::BlobKeyError(key.to_owned()))?;
-        Ok(rows
-            .into_iter()
-            .map(
-                |
-                    (
-                        blobstore_key,
-                        blobstore_id,
-                        multiplex_id,
-                        timestamp,
-                        operation_key,
-                        id,
-                        blob_size,
-                    ),
-                | {
-                    BlobstoreSyncQueueEntry {
-                        blobstore_key,
-                        blobstore_id,
-                        multiplex_id,
-                        timestamp: timestamp.into(),
-                        operation_key,
-                        id: Some(id),
-                        blob_size,
-                    }
-                },
-            )
-            .collect())
+        Ok(rows.into_iter().map(entry_from_row).collect())
     }
 }
@@ -104,3 +104,54 @@ async fn test_simple(fb: FacebookInit) -> Result<(), Error> {
     assert_eq!(entries.len(), 0);
     Ok(())
 }
+
+#[fbinit::test]
+async fn test_access_count(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let queue = SqlBlobstoreSyncQueue::with_sqlite_in_memory().unwrap();
+    let bs0 = BlobstoreId::new(0);
+    let mp = MultiplexId::new(1);
+
+    let t0 = DateTime::from_rfc3339("2018-11-29T12:00:00.00Z").unwrap();
+    let t1 = DateTime::from_rfc3339("2018-11-29T12:01:00.00Z").unwrap();
+    let cold =
+        BlobstoreSyncQueueEntry::new("cold".to_string(), bs0, mp, t0, OperationKey::gen(), None);
+    let hot =
+        BlobstoreSyncQueueEntry::new("hot".to_string(), bs0, mp, t1, OperationKey::gen(), None);
+    queue.add_many(&ctx, vec![cold, hot.clone()]).await?;
+
+    // Accesses are counted per blob, whether or not the entries were read from the queue
+    queue.record_access(&ctx, &[hot]).await?;
+    let hot_entries = queue.get(&ctx, "hot").await?;
+    queue.record_access(&ctx, &hot_entries).await?;
+
+    let entries = queue.iter_by_access_count(&ctx, None, mp, t1, 1).await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].blobstore_key, "hot");
+    assert_eq!(entries[0].access_count, 2);
+    let entries = queue
+        .iter_by_access_count(&ctx, Some("c%"), mp, t1, 1)
+        .await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].blobstore_key, "cold");
+    assert_eq!(entries[0].access_count, 0);
+
+    let depth = queue.depth(&ctx, mp).await?;
+    assert_eq!(depth.entries, 2);
+    assert_eq!(depth.oldest, Some(t0));
+    let depth = queue.depth(&ctx, MultiplexId::new(2)).await?;
+    assert_eq!(depth.entries, 0);
+    assert_eq!(depth.oldest, None);
+
+    // Healed blobs start from scratch if they are queued again
+    queue.del(&ctx, &hot_entries).await?;
+    let hot =
+        BlobstoreSyncQueueEntry::new("hot".to_string(), bs0, mp, t1, OperationKey::gen(), None);
+    queue.add(&ctx, hot).await?;
+    let entries = queue
+        .iter_by_access_count(&ctx, Some("h%"), mp, t1, 1)
+        .await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].access_count, 0);
+    Ok(())
+}
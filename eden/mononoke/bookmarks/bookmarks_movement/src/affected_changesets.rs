@@ -32,6 +32,7 @@ use tunables::tunables;
 
 use crate::hook_running::run_hooks;
 use crate::restrictions::{BookmarkKind, BookmarkMoveAuthorization};
+use crate::verdict::{BookmarkMoveCheck, BookmarkMoveVerdict};
 use crate::BookmarkMovementError;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -293,6 +294,85 @@ impl AffectedChangesets {
         Ok(())
     }
 
+    /// Check all applicable restrictions on the affected changesets, recording
+    /// the outcome of each of them in `verdict` instead of stopping at the
+    /// first failure.
+    pub(crate) async fn validate_restrictions(
+        &mut self,
+        ctx: &CoreContext,
+        repo: &BlobRepo,
+        lca_hint: &Arc<dyn LeastCommonAncestorsHint>,
+        pushrebase_params: &PushrebaseParams,
+        bookmark_attrs: &BookmarkAttrs,
+        hook_manager: &HookManager,
+        bookmark: &BookmarkName,
+        pushvars: Option<&HashMap<String, Bytes>>,
+        reason: BookmarkUpdateReason,
+        kind: BookmarkKind,
+        auth: &BookmarkMoveAuthorization<'_>,
+        additional_changesets: AdditionalChangesets,
+        cross_repo_push_source: CrossRepoPushSource,
+        verdict: &mut BookmarkMoveVerdict,
+    ) {
+        let res = self
+            .check_extras(
+                ctx,
+                repo,
+                lca_hint,
+                bookmark_attrs,
+                bookmark,
+                kind,
+                additional_changesets,
+            )
+            .await;
+        verdict.record(BookmarkMoveCheck::Extras, res);
+
+        let res = self
+            .check_case_conflicts(
+                ctx,
+                repo,
+                lca_hint,
+                pushrebase_params,
+                bookmark_attrs,
+                bookmark,
+                kind,
+                additional_changesets,
+            )
+            .await;
+        verdict.record(BookmarkMoveCheck::CaseConflicts, res);
+
+        let res = self
+            .check_hooks(
+                ctx,
+                repo,
+                lca_hint,
+                bookmark_attrs,
+                hook_manager,
+                bookmark,
+                pushvars,
+                reason,
+                kind,
+                auth,
+                additional_changesets,
+                cross_repo_push_source,
+            )
+            .await;
+        verdict.record(BookmarkMoveCheck::Hooks, res);
+
+        let res = self
+            .check_service_write_restrictions(
+                ctx,
+                repo,
+                lca_hint,
+                bookmark_attrs,
+                bookmark,
+                auth,
+                additional_changesets,
+            )
+            .await;
+        verdict.record(BookmarkMoveCheck::ServiceWriteRestrictions, res);
+    }
+
     async fn check_extras(
         &mut self,
         ctx: &CoreContext,
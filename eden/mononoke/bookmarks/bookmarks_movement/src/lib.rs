@@ -25,6 +25,7 @@ mod pushrebase_onto;
 mod repo_lock;
 mod restrictions;
 mod update;
+mod verdict;
 
 pub use hooks::{CrossRepoPushSource, HookRejection};
 pub use pushrebase::PushrebaseOutcome;
@@ -35,6 +36,7 @@ pub use crate::delete::DeleteBookmarkOp;
 pub use crate::hook_running::run_hooks;
 pub use crate::pushrebase_onto::{get_pushrebase_hooks, PushrebaseOntoBookmarkOp};
 pub use crate::update::{BookmarkUpdatePolicy, BookmarkUpdateTargets, UpdateBookmarkOp};
+pub use crate::verdict::{BookmarkMoveCheck, BookmarkMoveVerdict};
 
 /// An error encountered during an attempt to move a bookmark.
 #[derive(Debug, Error)]
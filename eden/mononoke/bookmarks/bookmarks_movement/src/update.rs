@@ -27,6 +27,7 @@ use crate::affected_changesets::{
 };
 use crate::repo_lock::check_repo_lock;
 use crate::restrictions::{BookmarkKind, BookmarkKindRestrictions, BookmarkMoveAuthorization};
+use crate::verdict::{BookmarkMoveCheck, BookmarkMoveVerdict};
 use crate::BookmarkMovementError;
 
 /// The old and new changeset during a bookmark update.
@@ -166,6 +167,93 @@ impl<'op> UpdateBookmarkOp<'op> {
         self
     }
 
+    /// Run the checks that would be applied to this bookmark update, without
+    /// updating the bookmark.
+    pub async fn validate(
+        mut self,
+        ctx: &'op CoreContext,
+        repo: &'op BlobRepo,
+        lca_hint: &'op Arc<dyn LeastCommonAncestorsHint>,
+        infinitepush_params: &'op InfinitepushParams,
+        pushrebase_params: &'op PushrebaseParams,
+        bookmark_attrs: &'op BookmarkAttrs,
+        hook_manager: &'op HookManager,
+        repo_read_write_fetcher: &'op RepoReadWriteFetcher,
+    ) -> BookmarkMoveVerdict {
+        let mut verdict = BookmarkMoveVerdict::default();
+
+        // The other checks depend on the kind of the bookmark.
+        let kind = match self
+            .kind_restrictions
+            .check_kind(infinitepush_params, self.bookmark)
+        {
+            Ok(kind) => kind,
+            Err(err) => {
+                verdict.record(BookmarkMoveCheck::Kind, Err(err));
+                return verdict;
+            }
+        };
+        verdict.record(BookmarkMoveCheck::Kind, Ok(()));
+
+        let res = self
+            .auth
+            .check_authorized(ctx, bookmark_attrs, self.bookmark, kind);
+        verdict.record(BookmarkMoveCheck::Authorization, res);
+
+        let res = self
+            .update_policy
+            .check_update_permitted(
+                ctx,
+                repo,
+                lca_hint.as_ref(),
+                bookmark_attrs,
+                &self.bookmark,
+                &self.targets,
+            )
+            .await;
+        verdict.record(BookmarkMoveCheck::FastForward, res);
+
+        self.affected_changesets
+            .validate_restrictions(
+                ctx,
+                repo,
+                lca_hint,
+                pushrebase_params,
+                bookmark_attrs,
+                hook_manager,
+                self.bookmark,
+                self.pushvars,
+                self.reason,
+                kind,
+                &self.auth,
+                AdditionalChangesets::Range {
+                    head: self.targets.new,
+                    base: self.targets.old,
+                },
+                self.cross_repo_push_source,
+                &mut verdict,
+            )
+            .await;
+
+        if kind == BookmarkKind::Public {
+            let res = crate::globalrev_mapping::check_globalrevs_push(
+                ctx,
+                repo,
+                lca_hint.as_ref(),
+                pushrebase_params,
+                self.bookmark,
+                self.targets.new,
+            )
+            .await;
+            verdict.record(BookmarkMoveCheck::Globalrevs, res);
+        }
+
+        let res = check_repo_lock(repo_read_write_fetcher, kind, self.pushvars).await;
+        verdict.record(BookmarkMoveCheck::RepoLock, res);
+
+        verdict
+    }
+
     pub async fn run(
         mut self,
         ctx: &'op CoreContext,
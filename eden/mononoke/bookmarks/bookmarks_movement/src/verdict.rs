@@ -0,0 +1,87 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use hooks::HookRejection;
+
+use crate::BookmarkMovementError;
+
+/// A check the server applies to bookmark moves.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BookmarkMoveCheck {
+    /// The bookmark name is valid for its kind (scratch or public).
+    Kind,
+
+    /// The user or service is allowed to move the bookmark.
+    Authorization,
+
+    /// The move is a fast-forward, if the request or the bookmark config
+    /// requires it.
+    FastForward,
+
+    /// The affected changesets don't set disallowed extras.
+    Extras,
+
+    /// The affected changesets don't introduce case conflicts.
+    CaseConflicts,
+
+    /// The hooks for the bookmark accept the affected changesets.
+    Hooks,
+
+    /// The affected changesets only modify paths the service may write to.
+    ServiceWriteRestrictions,
+
+    /// The target is an ancestor of the Globalrevs publishing bookmark, if the
+    /// repo uses Globalrevs.
+    Globalrevs,
+
+    /// The repo is not locked.
+    RepoLock,
+}
+
+/// The outcome of running the checks for a proposed bookmark move, without
+/// moving the bookmark.
+///
+/// Unlike an actual move, which stops at the first failed check, all checks
+/// that apply are run, so that every reason the move would be rejected is
+/// reported at once.
+#[derive(Debug, Default)]
+pub struct BookmarkMoveVerdict {
+    /// The checks that were run, in order, with their outcome.
+    pub checks: Vec<(BookmarkMoveCheck, Result<(), BookmarkMovementError>)>,
+}
+
+impl BookmarkMoveVerdict {
+    pub(crate) fn record(
+        &mut self,
+        check: BookmarkMoveCheck,
+        result: Result<(), BookmarkMovementError>,
+    ) {
+        self.checks.push((check, result));
+    }
+
+    /// Whether the server would accept the move.
+    pub fn is_allowed(&self) -> bool {
+        self.checks.iter().all(|(_, result)| result.is_ok())
+    }
+
+    /// The checks that would reject the move.
+    pub fn rejections(&self) -> impl Iterator<Item = (BookmarkMoveCheck, &BookmarkMovementError)> {
+        self.checks
+            .iter()
+            .filter_map(|(check, result)| result.as_ref().err().map(|err| (*check, err)))
+    }
+
+    /// The hooks that would reject the move.
+    pub fn hook_rejections(&self) -> impl Iterator<Item = &HookRejection> {
+        self.rejections()
+            .filter_map(|(_, err)| match err {
+                BookmarkMovementError::HookFailure(rejections) => Some(rejections.iter()),
+                _ => None,
+            })
+            .flatten()
+    }
+}
@@ -133,6 +133,16 @@ mod test {
         txn.update_scratch(&scratch_name, TWOS_CSID, ONES_CSID)?;
         assert!(txn.commit().await?);
 
+        // Using 'delete_scratch' to delete a publishing bookmark should fail.
+        let mut txn = store.create_transaction(ctx.clone());
+        txn.delete_scratch(&publishing_name, TWOS_CSID)?;
+        assert!(!txn.commit().await?);
+
+        // Using 'delete_scratch' to delete a scratch bookmark should succeed.
+        let mut txn = store.create_transaction(ctx.clone());
+        txn.delete_scratch(&scratch_name, TWOS_CSID)?;
+        assert!(txn.commit().await?);
+
         Ok(())
     }
 
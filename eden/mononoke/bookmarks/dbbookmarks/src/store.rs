@@ -9,9 +9,10 @@
 
 use anyhow::{Error, Result};
 use bookmarks::{
-    Bookmark, BookmarkKind, BookmarkName, BookmarkPagination, BookmarkPrefix, BookmarkTransaction,
-    BookmarkUpdateLog, BookmarkUpdateLogEntry, BookmarkUpdateReason, Bookmarks, Freshness,
-    RawBundleReplayData,
+    subscribe_to_update_log, Bookmark, BookmarkKind, BookmarkMovement, BookmarkName,
+    BookmarkPagination, BookmarkPrefix, BookmarkSubscriptionOptions, BookmarkSubscriptionStart,
+    BookmarkTransaction, BookmarkUpdateLog, BookmarkUpdateLogEntry, BookmarkUpdateReason,
+    Bookmarks, Freshness, RawBundleReplayData,
 };
 use context::{CoreContext, PerfCounterType};
 use futures::compat::Future01CompatExt;
@@ -22,6 +23,7 @@ use mononoke_types::{ChangesetId, RepositoryId};
 use sql::queries;
 use sql_ext::SqlConnections;
 use stats::prelude::*;
+use std::sync::Arc;
 
 use crate::transaction::SqlBookmarksTransaction;
 
@@ -381,6 +383,15 @@ impl Bookmarks for SqlBookmarks {
             self.repo_id.clone(),
         ))
     }
+
+    fn subscribe(
+        &self,
+        ctx: CoreContext,
+        start: BookmarkSubscriptionStart,
+        options: BookmarkSubscriptionOptions,
+    ) -> BoxStream<'static, Result<BookmarkMovement>> {
+        subscribe_to_update_log(Arc::new(self.clone()), ctx, start, options)
+    }
 }
 
 impl BookmarkUpdateLog for SqlBookmarks {
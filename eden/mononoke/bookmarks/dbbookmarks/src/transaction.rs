@@ -18,13 +18,15 @@ use futures::future::{self, BoxFuture, FutureExt};
 use mononoke_types::Timestamp;
 use mononoke_types::{ChangesetId, RepositoryId};
 use sql::{queries, Connection, Transaction as SqlTransaction};
+use sql_ext::{transaction_with_retries_if, TransactionRetryOptions};
 use stats::prelude::*;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use crate::store::SelectBookmark;
 
-const MAX_BOOKMARK_TRANSACTION_ATTEMPT_COUNT: usize = 5;
+const MAX_BOOKMARK_TRANSACTION_ATTEMPT_COUNT: u32 = 5;
 
 define_stats! {
     prefix = "mononoke.dbbookmarks";
@@ -76,12 +78,18 @@ queries! {
            AND name = {name}"
     }
 
-    write DeleteBookmarkIf(repo_id: RepositoryId, name: BookmarkName, changeset_id: ChangesetId) {
+    write DeleteBookmarkIf(
+        repo_id: RepositoryId,
+        name: BookmarkName,
+        changeset_id: ChangesetId,
+        >list kinds: BookmarkKind
+    ) {
         none,
         "DELETE FROM bookmarks
          WHERE repo_id = {repo_id}
            AND name = {name}
-           AND changeset_id = {changeset_id}"
+           AND changeset_id = {changeset_id}
+           AND hg_kind IN {kinds}"
     }
 
     read FindMaxBookmarkLogId() -> (Option<u64>) {
@@ -161,11 +169,12 @@ struct SqlBookmarksTransactionPayload {
     /// Operations to force-delete a bookmark.
     force_deletes: HashSet<BookmarkName>,
 
-    /// Operations to delete a bookmark with an old id.
-    deletes: HashMap<BookmarkName, ChangesetId>,
+    /// Operations to delete a bookmark with an old id, provided it has a
+    /// matching kind.
+    deletes: HashMap<BookmarkName, (ChangesetId, &'static [BookmarkKind])>,
 
-    /// Log entries to log. Scratch updates and creates are not included in
-    /// the log.
+    /// Log entries to log. Scratch updates, creates and deletes are not
+    /// included in the log.
     log: HashMap<BookmarkName, NewUpdateLogEntry>,
 }
 
@@ -317,11 +326,16 @@ impl SqlBookmarksTransactionPayload {
         &self,
         mut txn: SqlTransaction,
     ) -> Result<SqlTransaction, BookmarkTransactionError> {
-        for (bookmark, old_cs_id) in self.deletes.iter() {
-            let (txn_, result) =
-                DeleteBookmarkIf::query_with_transaction(txn, &self.repo_id, bookmark, old_cs_id)
-                    .compat()
-                    .await?;
+        for (bookmark, &(ref old_cs_id, kinds)) in self.deletes.iter() {
+            let (txn_, result) = DeleteBookmarkIf::query_with_transaction(
+                txn,
+                &self.repo_id,
+                bookmark,
+                old_cs_id,
+                kinds,
+            )
+            .compat()
+            .await?;
             txn = txn_;
             if result.affected_rows() != 1 {
                 return Err(BookmarkTransactionError::LogicError);
@@ -446,7 +460,9 @@ impl BookmarkTransaction for SqlBookmarksTransaction {
         bundle_replay: Option<&dyn BundleReplay>,
     ) -> Result<()> {
         self.check_not_seen(bookmark)?;
-        self.payload.deletes.insert(bookmark.clone(), old_cs);
+        self.payload
+            .deletes
+            .insert(bookmark.clone(), (old_cs, BookmarkKind::ALL));
         self.payload.log.insert(
             bookmark.clone(),
             NewUpdateLogEntry::new(Some(old_cs), None, reason, bundle_replay)?,
@@ -492,6 +508,15 @@ impl BookmarkTransaction for SqlBookmarksTransaction {
         Ok(())
     }
 
+    fn delete_scratch(&mut self, bookmark: &BookmarkName, old_cs: ChangesetId) -> Result<()> {
+        self.check_not_seen(bookmark)?;
+        self.payload
+            .deletes
+            .insert(bookmark.clone(), (old_cs, &[BookmarkKind::Scratch]));
+        // Scratch bookmark updates are not logged.
+        Ok(())
+    }
+
     fn create_publishing(
         &mut self,
         bookmark: &BookmarkName,
@@ -531,41 +556,39 @@ impl BookmarkTransaction for SqlBookmarksTransaction {
             .increment_counter(PerfCounterType::SqlWrites);
 
         async move {
-            let mut attempt = 0;
-            let result = loop {
-                attempt += 1;
-
-                let mut txn = write_connection.start_transaction().compat().await?;
-
-                txn = match txn_hook(ctx.clone(), txn).await {
-                    Ok(txn) => txn,
-                    Err(BookmarkTransactionError::RetryableError(_))
-                        if attempt < MAX_BOOKMARK_TRANSACTION_ATTEMPT_COUNT =>
-                    {
-                        continue;
-                    }
-                    err => break err,
-                };
-
-                match payload.attempt_write(txn).await {
-                    Err(BookmarkTransactionError::RetryableError(_))
-                        if attempt < MAX_BOOKMARK_TRANSACTION_ATTEMPT_COUNT =>
-                    {
-                        continue;
-                    }
-                    result => break result,
-                }
+            let options = TransactionRetryOptions {
+                max_attempts: MAX_BOOKMARK_TRANSACTION_ATTEMPT_COUNT,
+                ..Default::default()
             };
-
-            match result {
-                Ok(txn) => {
+            let attempts = &AtomicUsize::new(0);
+            let (ctx, payload, txn_hook) = (&ctx, &payload, &txn_hook);
+            let result = transaction_with_retries_if(
+                &write_connection,
+                &options,
+                |e| {
+                    matches!(
+                        e.downcast_ref::<BookmarkTransactionError>(),
+                        Some(BookmarkTransactionError::RetryableError(_))
+                    )
+                },
+                move |txn| async move {
+                    attempts.fetch_add(1, Ordering::Relaxed);
+                    let txn = txn_hook(ctx.clone(), txn).await?;
+                    let txn = payload.attempt_write(txn).await?;
+                    Ok((txn, ()))
+                },
+            )
+            .await;
+            let attempt = attempts.load(Ordering::Relaxed);
+
+            match result.map_err(|e| e.downcast::<BookmarkTransactionError>()) {
+                Ok(()) => {
                     STATS::bookmarks_update_log_insert_success.add_value(1);
                     STATS::bookmarks_update_log_insert_success_attempt_count
                         .add_value(attempt as i64);
-                    txn.commit().compat().await?;
                     Ok(true)
                 }
-                Err(BookmarkTransactionError::LogicError) => {
+                Err(Ok(BookmarkTransactionError::LogicError)) => {
                     // Logic error signifies that the transaction was rolled
                     // back, which likely means that bookmark has moved since
                     // our pushrebase finished. We need to retry the pushrebase
@@ -575,7 +598,7 @@ impl BookmarkTransaction for SqlBookmarksTransaction {
                     STATS::bookmarks_insert_logic_error_attempt_count.add_value(attempt as i64);
                     Ok(false)
                 }
-                Err(BookmarkTransactionError::RetryableError(err)) => {
+                Err(Ok(BookmarkTransactionError::RetryableError(err))) => {
                     // Attempt count for `RetryableError` should always be equal
                     // to the MAX_BOOKMARK_TRANSACTION_ATTEMPT_COUNT, and hitting
                     // this error here basically means that this number of attempts
@@ -584,9 +607,10 @@ impl BookmarkTransaction for SqlBookmarksTransaction {
                     STATS::bookmarks_insert_retryable_error_attempt_count.add_value(attempt as i64);
                     Err(err)
                 }
-                Err(BookmarkTransactionError::Other(err)) => {
+                Err(Ok(BookmarkTransactionError::Other(err))) | Err(Err(err)) => {
                     // `Other` error captures what we consider an "infrastructure"
-                    // error, e.g. xdb went down during this transaction.
+                    // error, e.g. xdb went down during this transaction. Transient
+                    // ones were already retried.
                     // Attempt count > 1 means the before we hit this error,
                     // we hit `RetryableError` a attempt count - 1 times.
                     STATS::bookmarks_insert_other_error.add_value(1);
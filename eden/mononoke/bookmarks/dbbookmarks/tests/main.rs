@@ -11,8 +11,10 @@
 
 use anyhow::{Error, Result};
 use bookmarks::{
-    Bookmark, BookmarkKind, BookmarkName, BookmarkPagination, BookmarkPrefix, BookmarkUpdateLog,
-    BookmarkUpdateLogEntry, BookmarkUpdateReason, Bookmarks, Freshness, RawBundleReplayData,
+    Bookmark, BookmarkKind, BookmarkName, BookmarkPagination, BookmarkPrefix,
+    BookmarkSubscriptionOptions, BookmarkSubscriptionStart, BookmarkSubscriptionToken,
+    BookmarkUpdateLog, BookmarkUpdateLogEntry, BookmarkUpdateReason, Bookmarks, Freshness,
+    RawBundleReplayData,
 };
 use context::CoreContext;
 use dbbookmarks::SqlBookmarksBuilder;
@@ -27,6 +29,7 @@ use mononoke_types_mocks::repo::{REPO_ONE, REPO_TWO, REPO_ZERO};
 use sql::mysql_async::{prelude::ConvIr, Value};
 use sql_construct::SqlConstruct;
 use std::collections::HashMap;
+use std::time::Duration;
 
 fn create_bookmark_name(book: &str) -> BookmarkName {
     BookmarkName::new(book.to_string()).unwrap()
@@ -443,6 +446,41 @@ fn test_scratch_update_bookmark(fb: FacebookInit) {
     })
 }
 
+#[fbinit::test]
+fn test_scratch_delete_bookmark(fb: FacebookInit) {
+    async_unit::tokio_unit_test(async move {
+        let ctx = CoreContext::test_mock(fb);
+        let bookmarks = SqlBookmarksBuilder::with_sqlite_in_memory()
+            .unwrap()
+            .with_repo_id(REPO_ZERO);
+        let name_1 = create_bookmark_name("book");
+
+        let mut txn = bookmarks.create_transaction(ctx.clone());
+        txn.create_scratch(&name_1, ONES_CSID).unwrap();
+        assert!(txn.commit().await.unwrap());
+
+        // The bookmark has moved since, so it is not deleted
+        let mut txn = bookmarks.create_transaction(ctx.clone());
+        txn.delete_scratch(&name_1, TWOS_CSID).unwrap();
+        assert!(!txn.commit().await.unwrap());
+
+        let mut txn = bookmarks.create_transaction(ctx.clone());
+        txn.delete_scratch(&name_1, ONES_CSID).unwrap();
+        assert!(txn.commit().await.unwrap());
+
+        assert_eq!(bookmarks.get(ctx.clone(), &name_1).await.unwrap(), None);
+
+        compare_log_entries(
+            bookmarks
+                .read_next_bookmark_log_entries(ctx.clone(), 0, 1, Freshness::MostRecent)
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap(),
+            vec![],
+        );
+    })
+}
+
 #[fbinit::test]
 fn test_update_non_existent_bookmark(fb: FacebookInit) {
     async_unit::tokio_unit_test(async move {
@@ -1241,6 +1279,74 @@ fn test_get_largest_log_id(fb: FacebookInit) {
     })
 }
 
+#[fbinit::test]
+fn test_subscribe(fb: FacebookInit) {
+    async_unit::tokio_unit_test(async move {
+        let ctx = CoreContext::test_mock(fb);
+        let bookmarks = SqlBookmarksBuilder::with_sqlite_in_memory()
+            .unwrap()
+            .with_repo_id(REPO_ZERO);
+        let name_1 = create_bookmark_name("book");
+        let name_2 = create_bookmark_name("book2");
+        let options = BookmarkSubscriptionOptions {
+            poll_interval: Duration::from_millis(10),
+            batch_size: 1,
+            freshness: Freshness::MostRecent,
+        };
+
+        let mut txn = bookmarks.create_transaction(ctx.clone());
+        txn.force_set(&name_1, ONES_CSID, BookmarkUpdateReason::TestMove, None)
+            .unwrap();
+        txn.force_set(&name_2, TWOS_CSID, BookmarkUpdateReason::TestMove, None)
+            .unwrap();
+        txn.commit().await.unwrap();
+
+        // The backlog is streamed in order, batch after batch
+        let start = BookmarkSubscriptionStart::Resume(BookmarkSubscriptionToken::from_log_id(0));
+        let mut movements = bookmarks.subscribe(ctx.clone(), start, options);
+        let movement = movements.try_next().await.unwrap().unwrap();
+        assert_eq!(movement.entry.bookmark_name, name_1);
+        assert_eq!(movement.entry.to_changeset_id, Some(ONES_CSID));
+        assert_eq!(movement.token.log_id(), 1);
+        let movement = movements.try_next().await.unwrap().unwrap();
+        assert_eq!(movement.entry.bookmark_name, name_2);
+        assert_eq!(movement.token.log_id(), 2);
+
+        // Caught up, the stream waits for the next movement
+        let mut txn = bookmarks.create_transaction(ctx.clone());
+        txn.update(
+            &name_1,
+            THREES_CSID,
+            ONES_CSID,
+            BookmarkUpdateReason::TestMove,
+            None,
+        )
+        .unwrap();
+        txn.commit().await.unwrap();
+        let movement = movements.try_next().await.unwrap().unwrap();
+        assert_eq!(movement.entry.from_changeset_id, Some(ONES_CSID));
+        assert_eq!(movement.entry.to_changeset_id, Some(THREES_CSID));
+
+        // Tokens can be persisted, and resumed from
+        let token: BookmarkSubscriptionToken = movement.token.to_string().parse().unwrap();
+        assert_eq!(token, movement.token);
+        let mut txn = bookmarks.create_transaction(ctx.clone());
+        txn.force_delete(&name_2, BookmarkUpdateReason::TestMove, None)
+            .unwrap();
+        txn.commit().await.unwrap();
+        let mut movements = bookmarks.subscribe(
+            ctx.clone(),
+            BookmarkSubscriptionStart::Resume(token),
+            options,
+        );
+        let movement = movements.try_next().await.unwrap().unwrap();
+        assert_eq!(movement.entry.bookmark_name, name_2);
+        assert_eq!(movement.entry.to_changeset_id, None);
+        assert_eq!(movement.token.log_id(), 4);
+        assert!("not a token".parse::<BookmarkSubscriptionToken>().is_err());
+    })
+}
+
 #[fbinit::test]
 fn test_creating_publishing_bookmarks(fb: FacebookInit) {
     async_unit::tokio_unit_test(async move {
@@ -20,6 +20,9 @@ use mononoke_types::{ChangesetId, RepositoryId};
 use stats::prelude::*;
 
 use crate::log::{BookmarkUpdateReason, BundleReplay};
+use crate::subscription::{
+    BookmarkMovement, BookmarkSubscriptionOptions, BookmarkSubscriptionStart,
+};
 use crate::transaction::{BookmarkTransaction, BookmarkTransactionHook};
 use crate::Bookmarks;
 
@@ -298,6 +301,15 @@ impl Bookmarks for CachedBookmarks {
         let mut cache = self.cache.lock().expect("lock poisoned");
         *cache = None;
     }
+
+    fn subscribe(
+        &self,
+        ctx: CoreContext,
+        start: BookmarkSubscriptionStart,
+        options: BookmarkSubscriptionOptions,
+    ) -> BoxStream<'static, Result<BookmarkMovement>> {
+        self.bookmarks.subscribe(ctx, start, options)
+    }
 }
 
 impl BookmarkTransaction for CachedBookmarksTransaction {
@@ -376,6 +388,11 @@ impl BookmarkTransaction for CachedBookmarksTransaction {
         self.transaction.create_scratch(bookmark, new_cs)
     }
 
+    fn delete_scratch(&mut self, bookmark: &BookmarkName, old_cs: ChangesetId) -> Result<()> {
+        // Scratch bookmarks aren't stored in the cache.
+        self.transaction.delete_scratch(bookmark, old_cs)
+    }
+
     fn create_publishing(
         &mut self,
         bookmark: &BookmarkName,
@@ -601,6 +618,10 @@ mod tests {
             Ok(())
         }
 
+        fn delete_scratch(&mut self, _bookmark: &BookmarkName, _old_cs: ChangesetId) -> Result<()> {
+            Ok(())
+        }
+
         fn create_publishing(
             &mut self,
             _bookmark: &BookmarkName,
@@ -8,14 +8,15 @@
 #![deny(warnings)]
 #![feature(never_type)]
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use context::CoreContext;
 use futures::future::BoxFuture;
-use futures::stream::BoxStream;
+use futures::stream::{self, BoxStream, StreamExt};
 use mononoke_types::ChangesetId;
 
 mod cache;
 mod log;
+mod subscription;
 mod transaction;
 
 pub use bookmarks_types::{
@@ -27,6 +28,10 @@ pub use log::{
     BookmarkUpdateLog, BookmarkUpdateLogEntry, BookmarkUpdateReason, BundleReplay,
     RawBundleReplayData,
 };
+pub use subscription::{
+    subscribe_to_update_log, BookmarkMovement, BookmarkSubscriptionOptions,
+    BookmarkSubscriptionStart, BookmarkSubscriptionToken,
+};
 pub use transaction::{BookmarkTransaction, BookmarkTransactionError, BookmarkTransactionHook};
 
 pub trait Bookmarks: Send + Sync + 'static {
@@ -71,4 +76,18 @@ pub trait Bookmarks: Send + Sync + 'static {
     fn drop_caches(&self) {
         // No-op by default.
     }
+
+    /// Stream the movements of all bookmarks, as recorded in the bookmark update log, from
+    /// `start`. Each movement comes with a token to resume the subscription after it.
+    ///
+    /// The stream never ends: once it has caught up with the log, it waits for new movements.
+    fn subscribe(
+        &self,
+        _ctx: CoreContext,
+        _start: BookmarkSubscriptionStart,
+        _options: BookmarkSubscriptionOptions,
+    ) -> BoxStream<'static, Result<BookmarkMovement>> {
+        let err = anyhow!("These bookmarks have no update log to subscribe to");
+        stream::once(async { Err(err) }).boxed()
+    }
 }
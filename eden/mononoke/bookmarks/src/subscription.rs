@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Error, Result};
+use bookmarks_types::Freshness;
+use context::CoreContext;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+
+use crate::log::{BookmarkUpdateLog, BookmarkUpdateLogEntry};
+
+/// Position of a subscriber in the bookmark update log. Subscribers which persist the token of
+/// the last movement they processed can resume from there, e.g. after a restart.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct BookmarkSubscriptionToken(u64);
+
+impl BookmarkSubscriptionToken {
+    /// Token of the position just after the log entry with this id
+    pub fn from_log_id(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub fn log_id(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for BookmarkSubscriptionToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for BookmarkSubscriptionToken {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let id = s
+            .parse()
+            .with_context(|| format!("Invalid bookmark subscription token {:?}", s))?;
+        Ok(Self(id))
+    }
+}
+
+/// Where a subscription starts
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BookmarkSubscriptionStart {
+    /// Only the movements made after the stream is first polled
+    Latest,
+    /// The movements after the one this token was given with
+    Resume(BookmarkSubscriptionToken),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BookmarkSubscriptionOptions {
+    /// How long to wait before looking for new movements again, once the subscriber is caught up
+    pub poll_interval: Duration,
+    /// Maximum number of movements read from the log at once
+    pub batch_size: u64,
+    /// Reading replicas is cheaper, at the cost of seeing movements a bit later
+    pub freshness: Freshness,
+}
+
+impl Default for BookmarkSubscriptionOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            batch_size: 100,
+            freshness: Freshness::MaybeStale,
+        }
+    }
+}
+
+/// A bookmark movement, and the token to resume the subscription after it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BookmarkMovement {
+    pub entry: BookmarkUpdateLogEntry,
+    pub token: BookmarkSubscriptionToken,
+}
+
+/// Stream the movements of bookmarks from `log`, in the order they were made. The stream never
+/// ends: once it has caught up with the log, it waits for new movements.
+pub fn subscribe_to_update_log(
+    log: Arc<dyn BookmarkUpdateLog>,
+    ctx: CoreContext,
+    start: BookmarkSubscriptionStart,
+    options: BookmarkSubscriptionOptions,
+) -> BoxStream<'static, Result<BookmarkMovement>> {
+    let first_token = async move {
+        let token = match start {
+            BookmarkSubscriptionStart::Latest => {
+                let id = log
+                    .get_largest_log_id(ctx.clone(), options.freshness)
+                    .await?;
+                BookmarkSubscriptionToken(id.unwrap_or(0))
+            }
+            BookmarkSubscriptionStart::Resume(token) => token,
+        };
+        Ok::<_, Error>((log, ctx, token))
+    };
+
+    stream::once(first_token)
+        .map_ok(move |(log, ctx, token)| {
+            stream::try_unfold((token, false), move |(token, wait)| {
+                let log = log.clone();
+                let ctx = ctx.clone();
+                async move {
+                    if wait {
+                        tokio::time::delay_for(options.poll_interval).await;
+                    }
+                    let entries: Vec<_> = log
+                        .read_next_bookmark_log_entries(
+                            ctx,
+                            token.log_id(),
+                            options.batch_size,
+                            options.freshness,
+                        )
+                        .try_collect()
+                        .await?;
+                    let next_token = entries
+                        .last()
+                        .map_or(token, |entry| BookmarkSubscriptionToken(entry.id as u64));
+                    // Only wait once caught up, so that a backlog is streamed as fast as it can
+                    let caught_up = (entries.len() as u64) < options.batch_size;
+                    Ok::<_, Error>(Some((entries, (next_token, caught_up))))
+                }
+            })
+            .map_ok(|entries| {
+                stream::iter(entries.into_iter().map(|entry| {
+                    Ok(BookmarkMovement {
+                        token: BookmarkSubscriptionToken(entry.id as u64),
+                        entry,
+                    })
+                }))
+            })
+            .try_flatten()
+        })
+        .try_flatten()
+        .boxed()
+}
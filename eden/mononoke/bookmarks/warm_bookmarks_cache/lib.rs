@@ -21,6 +21,7 @@ use cloned::cloned;
 use context::CoreContext;
 use deleted_files_manifest::RootDeletedManifestId;
 use derived_data::BonsaiDerivable;
+use derived_data_filenodes::FilenodesOnlyPublic;
 use fsnodes::RootFsnodeId;
 use futures::{
     channel::oneshot,
@@ -82,6 +83,7 @@ pub struct WarmBookmarksCacheBuilder<'a> {
     ctx: &'a CoreContext,
     repo: &'a BlobRepo,
     warmers: Vec<Warmer>,
+    derived_data_types: HashSet<String>,
 }
 
 impl<'a> WarmBookmarksCacheBuilder<'a> {
@@ -90,6 +92,7 @@ impl<'a> WarmBookmarksCacheBuilder<'a> {
             ctx,
             repo,
             warmers: vec![],
+            derived_data_types: HashSet::new(),
         }
     }
 
@@ -97,6 +100,8 @@ impl<'a> WarmBookmarksCacheBuilder<'a> {
         self.add_derived_data_warmers(&self.repo.get_derived_data_config().enabled.types)
     }
 
+    /// Don't serve bookmarks until the given derived data types are derived. Types which already
+    /// have a warmer are skipped.
     pub fn add_derived_data_warmers<'name, Name>(
         &mut self,
         types: impl IntoIterator<Item = &'name Name>,
@@ -104,7 +109,7 @@ impl<'a> WarmBookmarksCacheBuilder<'a> {
     where
         Name: 'name + AsRef<str> + ?Sized,
     {
-        let types = types.into_iter().map(AsRef::as_ref).collect::<HashSet<_>>();
+        let mut types = types.into_iter().map(AsRef::as_ref).collect::<HashSet<_>>();
 
         let config = &self.repo.get_derived_data_config();
         for ty in types.iter() {
@@ -112,6 +117,8 @@ impl<'a> WarmBookmarksCacheBuilder<'a> {
                 return Err(anyhow!("{} is not enabled for {}", ty, self.repo.name()));
             }
         }
+        let derived_data_types = &mut self.derived_data_types;
+        types.retain(|ty| derived_data_types.insert(ty.to_string()));
 
         if types.contains(MappedHgChangesetId::NAME) {
             self.warmers
@@ -146,6 +153,10 @@ impl<'a> WarmBookmarksCacheBuilder<'a> {
                     &self.ctx,
                 ));
         }
+        if types.contains(FilenodesOnlyPublic::NAME) {
+            self.warmers
+                .push(create_derived_data_warmer::<FilenodesOnlyPublic>(&self.ctx));
+        }
 
         Ok(())
     }
@@ -787,6 +798,27 @@ mod tests {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_builder_derived_data_warmers(fb: FacebookInit) -> Result<(), Error> {
+        let repo = linear::getrepo(fb).await;
+        let ctx = CoreContext::test_mock(fb);
+
+        let mut builder = WarmBookmarksCacheBuilder::new(&ctx, &repo);
+        builder.add_derived_data_warmers(Some(RootUnodeManifestId::NAME))?;
+        // Types which are already warmed are skipped
+        builder
+            .add_derived_data_warmers(&[RootUnodeManifestId::NAME, FilenodesOnlyPublic::NAME])?;
+        assert_eq!(builder.warmers.len(), 2);
+
+        // Bookmarks are only served once all the types are derived
+        let master_cs_id = resolve_cs_id(&ctx, &repo, "master").await?;
+        RootUnodeManifestId::derive(&ctx, &repo, master_cs_id).await?;
+        FilenodesOnlyPublic::derive(&ctx, &repo, master_cs_id).await?;
+        assert!(is_warm(&ctx, &repo, &master_cs_id, &builder.warmers).await);
+
+        Ok(())
+    }
+
     #[fbinit::test]
     async fn test_find_derived(fb: FacebookInit) -> Result<(), Error> {
         let repo = linear::getrepo(fb).await;
@@ -17,8 +17,8 @@ use std::ffi::{OsStr, OsString};
 use std::future::Future;
 use std::io;
 use std::iter::FromIterator;
-use std::num::{NonZeroU32, NonZeroUsize};
-use std::path::PathBuf;
+use std::num::{NonZeroU32, NonZeroU8, NonZeroUsize};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -31,24 +31,25 @@ use maybe_owned::MaybeOwned;
 use once_cell::sync::OnceCell;
 use panichandler::{self, Fate};
 use scribe_ext::Scribe;
-use scuba_ext::MononokeScubaSampleBuilder;
+use scuba_ext::{MononokeScubaSampleBuilder, ScubaSinkOptions, ScubaSinkSpec};
 use slog::{debug, info, o, warn, Drain, Level, Logger, Never, SendSyncRefUnwindSafeDrain};
 use slog_glog_fmt::{kv_categorizer::FacebookCategorizer, kv_defaults::FacebookKV, GlogFormat};
 use slog_term::TermDecorator;
 use std::panic::{RefUnwindSafe, UnwindSafe};
 
 use blobrepo::BlobRepo;
-use blobrepo_factory::{BlobrepoBuilder, Caching, ReadOnlyStorage};
+use blobrepo_factory::{BlobrepoBuilder, BlobrepoFacets, Caching, ReadOnlyStorage};
 use blobstore_factory::{
-    BlobstoreOptions, CachelibBlobstoreOptions, ChaosOptions, PackOptions, PutBehaviour,
-    ScrubAction, ThrottleOptions, DEFAULT_PUT_BEHAVIOUR,
+    BlobstoreOptions, CachelibBlobstoreOptions, ChaosOptions, DedupOptions, PackOptions,
+    PutBehaviour, RetryOptions, ScrubAction, ScrubReport, ThrottleOptions, DEFAULT_PUT_BEHAVIOUR,
 };
-use metaconfig_parser::{RepoConfigs, StorageConfigs};
+use metaconfig_parser::{ConfigProblem, RepoConfigs, StorageConfigs};
 use metaconfig_types::{BlobConfig, CommonConfig, Redaction, RepoConfig};
 use mononoke_types::RepositoryId;
 use observability::{DynamicLevelDrain, ObservabilityContext};
+use repo_read_write_status::{RepoReadWriteFetcher, SqlRepoReadWriteStatus};
 use slog_ext::make_tag_filter_drain;
-use sql_construct::SqlConstructFromMetadataDatabaseConfig;
+use sql_construct::{facebook::FbSqlConstruct, SqlConstructFromMetadataDatabaseConfig};
 use sql_ext::facebook::{MysqlConnectionType, MysqlOptions, PoolConfig, SharedConnectionPool};
 use strum::VariantNames;
 use tunables::init_tunables_worker;
@@ -92,15 +93,27 @@ const WRITE_BYTES_ARG: &str = "blobstore-write-bytes-s";
 const READ_BURST_BYTES_ARG: &str = "blobstore-read-burst-bytes-s";
 const WRITE_BURST_BYTES_ARG: &str = "blobstore-write-burst-bytes-s";
 const BLOBSTORE_BYTES_MIN_THROTTLE_ARG: &str = "blobstore-bytes-min-throttle";
+const BLOBSTORE_BACKGROUND_THROTTLE_ARG: &str = "blobstore-background-throttle-percent";
 const READ_CHAOS_ARG: &str = "blobstore-read-chaos-rate";
 const WRITE_CHAOS_ARG: &str = "blobstore-write-chaos-rate";
 const WRITE_ZSTD_ARG: &str = "blobstore-write-zstd-level";
+const WRITE_ZSTD_MIN_SIZE_ARG: &str = "blobstore-write-zstd-min-size";
+const WRITE_ZSTD_SKIP_COMPRESSED_ARG: &str = "blobstore-write-zstd-skip-compressed";
+const WRITE_ZSTD_DICT_ARG: &str = "blobstore-write-zstd-dict";
 const MANIFOLD_API_KEY_ARG: &str = "manifold-api-key";
 const MANIFOLD_USE_CPP_CLIENT_ARG: &str = "manifold-use-cpp-client";
 const CACHELIB_ATTEMPT_ZSTD_ARG: &str = "blobstore-cachelib-attempt-zstd";
+const CACHELIB_ABSENT_TTL_ARG: &str = "blobstore-cachelib-absent-ttl-ms";
 const BLOBSTORE_PUT_BEHAVIOUR_ARG: &str = "blobstore-put-behaviour";
+const BLOBSTORE_ACCOUNTING_ARG: &str = "blobstore-accounting";
+const BLOBSTORE_DEDUP_MIN_SIZE_ARG: &str = "blobstore-dedup-min-size";
+const BLOBSTORE_RETRY_ATTEMPTS_ARG: &str = "blobstore-retry-attempts";
+const BLOBSTORE_RETRY_BASE_DELAY_ARG: &str = "blobstore-retry-base-delay-ms";
+const BLOBSTORE_RETRY_MAX_DELAY_ARG: &str = "blobstore-retry-max-delay-ms";
+const BLOBSTORE_HEALTH_BASED_READS_HEDGE_ARG: &str = "blobstore-health-based-reads-hedge-ms";
 const BLOBSTORE_SCRUB_ACTION_ARG: &str = "blobstore-scrub-action";
 const BLOBSTORE_SCRUB_GRACE_ARG: &str = "blobstore-scrub-grace";
+const BLOBSTORE_SCRUB_REPORT_ARG: &str = "blobstore-scrub-report";
 
 // Old version took no args which means it would be no good for overriding default for a binary that defaults to true.
 const READONLY_STORAGE_OLD_ARG: &str = "readonly-storage";
@@ -743,6 +756,13 @@ impl MononokeAppBuilder {
                 .required(false)
                 .help("Minimum number of bytes ThrottledBlob can count"),
         )
+        .arg(
+            Arg::with_name(BLOBSTORE_BACKGROUND_THROTTLE_ARG)
+                .long(BLOBSTORE_BACKGROUND_THROTTLE_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Percentage of the blobstore qps and bytes/s limits set aside for background sessions, the rest being left to user waiting sessions. Default is for all sessions to share the limits."),
+        )
         .arg(
             Arg::with_name(READ_CHAOS_ARG)
                 .long(READ_CHAOS_ARG)
@@ -764,6 +784,31 @@ impl MononokeAppBuilder {
                 .required(false)
                 .help("Set the zstd compression level to be used on writes via the packed blobstore (if configured).  Default is None."),
         )
+        .arg(
+            Arg::with_name(WRITE_ZSTD_MIN_SIZE_ARG)
+                .long(WRITE_ZSTD_MIN_SIZE_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Blobs smaller than this many bytes are written uncompressed via the packed blobstore. Default is 0."),
+        )
+        .arg(
+            Arg::with_name(WRITE_ZSTD_SKIP_COMPRESSED_ARG)
+                .long(WRITE_ZSTD_SKIP_COMPRESSED_ARG)
+                .takes_value(true)
+                .possible_values(BOOL_VALUES)
+                .required(false)
+                .default_value(bool_as_str(false))
+                .help("Whether to write blobs which look already compressed (e.g. gzip or png) uncompressed via the packed blobstore"),
+        )
+        .arg(
+            Arg::with_name(WRITE_ZSTD_DICT_ARG)
+                .long(WRITE_ZSTD_DICT_ARG)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(false)
+                .help("FAMILY=DICT_KEY: compress blobs of the key family (e.g. hgmanifest) with the trained dictionary stored under DICT_KEY on writes via the packed blobstore, when that is smaller. Needs --blobstore-write-zstd-level."),
+        )
         .arg(
             Arg::with_name(MANIFOLD_API_KEY_ARG)
                 .long(MANIFOLD_API_KEY_ARG)
@@ -789,6 +834,54 @@ impl MononokeAppBuilder {
                 .default_value(bool_as_str(self.blobstore_cachelib_attempt_zstd_default))
                 .help("Whether to attempt zstd compression when blobstore is putting things into cachelib over threshold size."),
         )
+        .arg(
+            Arg::with_name(CACHELIB_ABSENT_TTL_ARG)
+                .long(CACHELIB_ABSENT_TTL_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Remember keys missing from the blobstore in cachelib for this many milliseconds, so that repeated lookups don't reach the blobstore. Default is to not remember them."),
+        )
+        .arg(
+            Arg::with_name(BLOBSTORE_ACCOUNTING_ARG)
+                .long(BLOBSTORE_ACCOUNTING_ARG)
+                .required(false)
+                .help("Count blobstore reads and writes, and their bytes, per key family (hgchangeset, content, fsnode, ...)"),
+        )
+        .arg(
+            Arg::with_name(BLOBSTORE_DEDUP_MIN_SIZE_ARG)
+                .long(BLOBSTORE_DEDUP_MIN_SIZE_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Don't upload values of at least this many bytes again when they are already stored under another key, and store a reference to that key instead. Default is to upload all values. Can't be used with sqlblob stores, whose garbage collection would delete the values that references point to."),
+        )
+        .arg(
+            Arg::with_name(BLOBSTORE_RETRY_ATTEMPTS_ARG)
+                .long(BLOBSTORE_RETRY_ATTEMPTS_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Attempts made for each S3, GCS or HTTP blobstore operation which fails with a transient error, and for each part of large S3 and GCS uploads, 1 to not retry them. Default is 5."),
+        )
+        .arg(
+            Arg::with_name(BLOBSTORE_RETRY_BASE_DELAY_ARG)
+                .long(BLOBSTORE_RETRY_BASE_DELAY_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Milliseconds to wait before the first retry of a blobstore operation, doubling for each retry after that. Default is 100."),
+        )
+        .arg(
+            Arg::with_name(BLOBSTORE_RETRY_MAX_DELAY_ARG)
+                .long(BLOBSTORE_RETRY_MAX_DELAY_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Maximum milliseconds to wait before retrying a blobstore operation. Default is 10000."),
+        )
+        .arg(
+            Arg::with_name(BLOBSTORE_HEALTH_BASED_READS_HEDGE_ARG)
+                .long(BLOBSTORE_HEALTH_BASED_READS_HEDGE_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Read from the healthiest blobstores of a multiplex first, and only read from the others after this many milliseconds, or once a preferred blobstore fails"),
+        )
         .arg(
           put_arg
         )
@@ -826,7 +919,14 @@ impl MononokeAppBuilder {
                 scrub_grace_arg = scrub_grace_arg
                     .default_value(&FORMATTED.get_or_init(|| format!("{}", default)));
             }
-            app.arg(scrub_action_arg).arg(scrub_grace_arg)
+            let scrub_report_arg = Arg::with_name(BLOBSTORE_SCRUB_REPORT_ARG)
+                .long(BLOBSTORE_SCRUB_REPORT_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("File to write a JSON line to for each key scrub finds missing from a store, with what was done about it");
+            app.arg(scrub_action_arg)
+                .arg(scrub_grace_arg)
+                .arg(scrub_report_arg)
         } else {
             app
         }
@@ -1201,6 +1301,32 @@ where
     .await
 }
 
+/// Open the lock that decides whether writes to the repo are allowed, as the server sees it.
+/// If the repo has no write lock database, only the `readonly` option in its config applies.
+pub async fn open_repo_read_write_fetcher<'a>(
+    fb: FacebookInit,
+    config_store: &ConfigStore,
+    matches: &'a MononokeMatches<'a>,
+) -> Result<RepoReadWriteFetcher, Error> {
+    let (_, config) = get_config(config_store, matches)?;
+    let sql_read_write_status = match config.write_lock_db_address {
+        Some(addr) => {
+            let mysql_options = parse_mysql_options(matches);
+            let readonly_storage = parse_readonly_storage(matches);
+            let status =
+                SqlRepoReadWriteStatus::with_xdb(fb, addr, &mysql_options, readonly_storage.0)
+                    .await?;
+            Some(status)
+        }
+        None => None,
+    };
+    Ok(RepoReadWriteFetcher::new(
+        sql_read_write_status,
+        config.readonly,
+        config.hgsql_name,
+    ))
+}
+
 pub async fn open_source_sql<'a, T>(
     fb: FacebookInit,
     config_store: &ConfigStore,
@@ -1468,6 +1594,30 @@ fn add_scuba_logging_args<'a, 'b>(app: App<'a, 'b>, has_default: bool) -> App<'a
                 .long("scuba-log-file")
                 .takes_value(true)
                 .help("A log file to write Scuba logs to (primarily useful in testing)"),
+        )
+        .arg(
+            Arg::with_name("scuba-sink")
+                .long("scuba-sink")
+                .takes_value(true)
+                .help(
+                    "Also send Scuba samples to this sink, for builds without Scuba: \
+                     file:<path> for a rotated JSON-lines file, udp:<host>:<port> for a \
+                     datagram per sample, or an http:// URL to POST samples to",
+                ),
+        )
+        .arg(
+            Arg::with_name("scuba-sink-max-file-size")
+                .long("scuba-sink-max-file-size")
+                .takes_value(true)
+                .requires("scuba-sink")
+                .help("Size in bytes at which a file scuba sink is rotated"),
+        )
+        .arg(
+            Arg::with_name("scuba-sink-max-files")
+                .long("scuba-sink-max-files")
+                .takes_value(true)
+                .requires("scuba-sink")
+                .help("Number of rotated files a file scuba sink keeps"),
         );
 
     if has_default {
@@ -1502,6 +1652,15 @@ pub fn get_scuba_sample_builder<'a>(
     if let Some(scuba_log_file) = matches.value_of("scuba-log-file") {
         scuba_logger = scuba_logger.with_log_file(scuba_log_file)?;
     }
+    if let Some(scuba_sink) = matches.value_of("scuba-sink") {
+        let defaults = ScubaSinkOptions::default();
+        let options = ScubaSinkOptions {
+            max_file_size: get_u64(matches, "scuba-sink-max-file-size", defaults.max_file_size),
+            max_files: get_usize(matches, "scuba-sink-max-files", defaults.max_files),
+        };
+        let sink = scuba_sink.parse::<ScubaSinkSpec>()?.open(options)?;
+        scuba_logger = scuba_logger.with_sink(sink);
+    }
     let scuba_logger = scuba_logger
         .with_observability_context(octx)
         .with_seq("seq");
@@ -1551,6 +1710,16 @@ pub fn load_storage_configs<'a>(
     metaconfig_parser::load_storage_configs(get_config_path(matches)?, config_store)
 }
 
+pub fn validate_configs<'a>(
+    config_store: &ConfigStore,
+    matches: &'a MononokeMatches<'a>,
+) -> Result<Vec<ConfigProblem>> {
+    Ok(metaconfig_parser::validate_configs(
+        get_config_path(matches)?,
+        config_store,
+    ))
+}
+
 pub fn get_config<'a>(
     config_store: &ConfigStore,
     matches: &'a MononokeMatches<'a>,
@@ -1602,6 +1771,27 @@ async fn open_repo_internal_with_repo_id(
     caching: Caching,
     redaction_override: Option<Redaction>,
 ) -> Result<BlobRepo, Error> {
+    let builder = repo_builder_with_repo_id(
+        fb,
+        logger,
+        repo_id,
+        matches,
+        create,
+        caching,
+        redaction_override,
+    )?;
+    builder.build().await
+}
+
+fn repo_builder_with_repo_id<'a>(
+    fb: FacebookInit,
+    logger: &'a Logger,
+    repo_id: RepositoryId,
+    matches: &MononokeMatches<'_>,
+    create: bool,
+    caching: Caching,
+    redaction_override: Option<Redaction>,
+) -> Result<BlobrepoBuilder<'a>, Error> {
     let config_store = init_config_store(fb, logger, matches)?;
     let common_config = load_common_config(config_store, &matches)?;
     let (reponame, config) = get_config_by_repoid(config_store, matches, repo_id)?;
@@ -1632,13 +1822,34 @@ async fn open_repo_internal_with_repo_id(
         common_config.censored_scuba_params,
         readonly_storage,
         blobstore_options,
-        &logger,
+        logger,
         config_store,
     );
     if let Some(redaction_override) = redaction_override {
         builder.set_redaction(redaction_override);
     }
-    builder.build().await
+    Ok(builder)
+}
+
+/// Open the components of an existing repo lazily, for tools which only need a few of them.
+/// See `BlobrepoBuilder::build_facets`.
+pub fn open_repo_facets<'a>(
+    fb: FacebookInit,
+    logger: &'a Logger,
+    matches: &MononokeMatches<'_>,
+) -> Result<BlobrepoFacets<'a>, Error> {
+    let config_store = init_config_store(fb, logger, matches)?;
+    let repo_id = get_repo_id(config_store, matches)?;
+    let builder = repo_builder_with_repo_id(
+        fb,
+        logger,
+        repo_id,
+        matches,
+        false,
+        parse_caching(matches.as_ref()),
+        None,
+    )?;
+    Ok(builder.build_facets())
 }
 
 pub async fn open_repo_with_repo_id<'a>(
@@ -1793,6 +2004,15 @@ pub fn parse_blobstore_options(matches: &MononokeMatches) -> Result<BlobstoreOpt
         .value_of(BLOBSTORE_BYTES_MIN_THROTTLE_ARG)
         .map(|v| v.parse().expect("Provided Bytes/s is not usize"));
 
+    let background_percent: Option<NonZeroU8> = matches
+        .value_of(BLOBSTORE_BACKGROUND_THROTTLE_ARG)
+        .map(|v| v.parse())
+        .transpose()
+        .context("Provided background throttle percentage is not a non-zero u8")?;
+    if background_percent.map_or(false, |percent| percent.get() >= 100) {
+        bail!("Provided background throttle percentage must be less than 100");
+    }
+
     let read_chaos: Option<NonZeroU32> = matches
         .value_of(READ_CHAOS_ARG)
         .map(|v| v.parse())
@@ -1822,6 +2042,34 @@ pub fn parse_blobstore_options(matches: &MononokeMatches) -> Result<BlobstoreOpt
         .transpose()
         .context("Provided Zstd compression level is not i32")?;
 
+    let write_zstd_min_size: Option<usize> = matches
+        .value_of(WRITE_ZSTD_MIN_SIZE_ARG)
+        .map(|v| v.parse())
+        .transpose()
+        .context("Provided Zstd minimum size is not usize")?;
+
+    let write_zstd_skip_compressed: bool = matches
+        .value_of(WRITE_ZSTD_SKIP_COMPRESSED_ARG)
+        .map(|v| v.parse())
+        .transpose()
+        .context("Provided blobstore-write-zstd-skip-compressed is not bool")?
+        .ok_or_else(|| format_err!("A default is set, should never be None"))?;
+
+    let write_zstd_dicts: HashMap<String, String> = matches
+        .values_of(WRITE_ZSTD_DICT_ARG)
+        .into_iter()
+        .flatten()
+        .map(|v| match v.splitn(2, '=').collect::<Vec<_>>()[..] {
+            [family, dict_key] if !family.is_empty() && !dict_key.is_empty() => {
+                Ok((family.to_string(), dict_key.to_string()))
+            }
+            _ => Err(format_err!(
+                "Provided blobstore-write-zstd-dict {:?} is not FAMILY=DICT_KEY",
+                v
+            )),
+        })
+        .collect::<Result<_, Error>>()?;
+
     let attempt_zstd: bool = matches
         .value_of(CACHELIB_ATTEMPT_ZSTD_ARG)
         .map(|v| v.parse())
@@ -1829,6 +2077,46 @@ pub fn parse_blobstore_options(matches: &MononokeMatches) -> Result<BlobstoreOpt
         .context("Provided blobstore-cachelib-attempt-zstd is not bool")?
         .ok_or_else(|| format_err!("A default is set, should never be None"))?;
 
+    let cachelib_absent_ttl: Option<Duration> = matches
+        .value_of(CACHELIB_ABSENT_TTL_ARG)
+        .map(|v| v.parse().map(Duration::from_millis))
+        .transpose()
+        .context("Provided blobstore-cachelib-absent-ttl-ms is not u64")?;
+
+    let health_based_reads_hedge: Option<Duration> = matches
+        .value_of(BLOBSTORE_HEALTH_BASED_READS_HEDGE_ARG)
+        .map(|v| v.parse().map(Duration::from_millis))
+        .transpose()
+        .context("Provided blobstore-health-based-reads-hedge-ms is not u64")?;
+
+    let dedup_min_size: Option<usize> = matches
+        .value_of(BLOBSTORE_DEDUP_MIN_SIZE_ARG)
+        .map(|v| v.parse())
+        .transpose()
+        .context("Provided blobstore-dedup-min-size is not usize")?;
+
+    let default_retry_options = RetryOptions::default();
+    let retry_options = RetryOptions::new(
+        matches
+            .value_of(BLOBSTORE_RETRY_ATTEMPTS_ARG)
+            .map(|v| v.parse())
+            .transpose()
+            .context("Provided blobstore-retry-attempts is not u32")?
+            .unwrap_or(default_retry_options.max_attempts),
+        matches
+            .value_of(BLOBSTORE_RETRY_BASE_DELAY_ARG)
+            .map(|v| v.parse().map(Duration::from_millis))
+            .transpose()
+            .context("Provided blobstore-retry-base-delay-ms is not u64")?
+            .unwrap_or(default_retry_options.base_delay),
+        matches
+            .value_of(BLOBSTORE_RETRY_MAX_DELAY_ARG)
+            .map(|v| v.parse().map(Duration::from_millis))
+            .transpose()
+            .context("Provided blobstore-retry-max-delay-ms is not u64")?
+            .unwrap_or(default_retry_options.max_delay),
+    );
+
     let blobstore_put_behaviour: Option<PutBehaviour> = matches
         .value_of(BLOBSTORE_PUT_BEHAVIOUR_ARG)
         .map(|v| v.parse())
@@ -1845,13 +2133,21 @@ pub fn parse_blobstore_options(matches: &MononokeMatches) -> Result<BlobstoreOpt
             read_burst_bytes,
             write_burst_bytes,
             bytes_min_count,
+            background_percent,
         },
         manifold_api_key,
         manifold_use_cpp_client,
-        PackOptions::new(write_zstd_level),
-        CachelibBlobstoreOptions::new_lazy(Some(attempt_zstd)),
+        PackOptions::new(write_zstd_level)
+            .with_put_compress_min_size(write_zstd_min_size.unwrap_or(0))
+            .with_put_skip_compressed(write_zstd_skip_compressed)
+            .with_put_zstd_dicts(write_zstd_dicts),
+        CachelibBlobstoreOptions::new_lazy(Some(attempt_zstd)).with_absent_ttl(cachelib_absent_ttl),
         blobstore_put_behaviour,
-    );
+    )
+    .with_accounting(matches.is_present(BLOBSTORE_ACCOUNTING_ARG))
+    .with_dedup_options(dedup_min_size.map(DedupOptions::new))
+    .with_retry_options(retry_options)
+    .with_health_based_reads_hedge(health_based_reads_hedge);
 
     let blobstore_options = if matches.arg_types.contains(&ArgType::Scrub) {
         let scrub_action = matches
@@ -1862,9 +2158,14 @@ pub fn parse_blobstore_options(matches: &MononokeMatches) -> Result<BlobstoreOpt
             .value_of(BLOBSTORE_SCRUB_GRACE_ARG)
             .map(u64::from_str)
             .transpose()?;
+        let scrub_report = matches
+            .value_of_os(BLOBSTORE_SCRUB_REPORT_ARG)
+            .map(|path| ScrubReport::create(Path::new(path)).map(Arc::new))
+            .transpose()?;
         blobstore_options
             .with_scrub_action(scrub_action)
             .with_scrub_grace(scrub_grace)
+            .with_scrub_report(scrub_report)
     } else {
         blobstore_options
     };
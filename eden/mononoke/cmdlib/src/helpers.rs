@@ -11,12 +11,13 @@ use anyhow::{bail, format_err, Context, Error, Result};
 use cloned::cloned;
 use fbinit::FacebookInit;
 use futures::{
+    compat::Future01CompatExt,
     future::{self, Either},
     FutureExt, StreamExt, TryFutureExt,
 };
 use futures_old::{Future as OldFuture, IntoFuture};
 use services::Fb303Service;
-use slog::{error, info, Logger};
+use slog::{error, info, warn, Logger};
 use tokio::{
     signal::unix::{signal, SignalKind},
     time,
@@ -30,7 +31,9 @@ use blobstore::Loadable;
 use bookmarks::BookmarkName;
 use context::CoreContext;
 use mercurial_types::{HgChangesetId, HgManifestId};
+use metaconfig_types::RepoReadOnly;
 use mononoke_types::ChangesetId;
+use repo_read_write_status::RepoReadWriteFetcher;
 use stats::schedule_stats_aggregation_preview;
 
 pub const ARG_SHUTDOWN_GRACE_PERIOD: &str = "shutdown-grace-period";
@@ -137,6 +140,32 @@ pub fn get_root_manifest_id(
 }
 
 /// Get a tokio `Runtime` with potentially explicitly set number of core threads
+/// Describe whether writes to a repo are locked, and why, for tools to display.
+pub fn describe_repo_lock(state: &RepoReadOnly) -> String {
+    match state {
+        RepoReadOnly::ReadWrite => "writes are allowed".to_string(),
+        RepoReadOnly::ReadOnly(reason) => format!("writes are locked: {}", reason),
+    }
+}
+
+/// Fetch the repo lock state and warn if writes are locked, so that operators running a tool
+/// against a repo during a migration or an incident can see that it is locked and why.
+pub async fn log_repo_lock_state(
+    logger: &Logger,
+    fetcher: &RepoReadWriteFetcher,
+) -> Result<RepoReadOnly, Error> {
+    let state = fetcher
+        .readonly()
+        .compat()
+        .await
+        .context("While fetching repo lock state")?;
+    match &state {
+        RepoReadOnly::ReadWrite => info!(logger, "Repo {}", describe_repo_lock(&state)),
+        RepoReadOnly::ReadOnly(_) => warn!(logger, "Repo {}", describe_repo_lock(&state)),
+    }
+    Ok(state)
+}
+
 pub fn create_runtime(
     log_thread_name_prefix: Option<&str>,
     core_threads: Option<usize>,
@@ -13,13 +13,17 @@ use anyhow::{format_err, Error};
 use fbinit::FacebookInit;
 use services::{self, Fb303Service, FbStatus};
 use slog::{info, Logger};
+use sql_ext::export_pool_stats;
 use stats::schedule_stats_aggregation_preview;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use std::time::Duration;
 
-use crate::args::MononokeMatches;
+use crate::args::{get_global_mysql_connection_pool, MononokeMatches};
+
+const POOL_STATS_INTERVAL: Duration = Duration::from_secs(10);
 
 // Re-eport AliveService for convenience so callers do not have to get the services dependency to
 // get AliveService.
@@ -55,6 +59,7 @@ impl Fb303Service for ReadyFlagService {
 
 /// `service_name` should match tupperware to avoid confusion.
 /// e.g. for mononoke/blobstore_healer, pass blobstore_healer
+/// The stats of the global MySQL connection pool are exported along with the other stats.
 pub fn start_fb303_and_stats_agg<S: Fb303Service + Sync + Send + 'static>(
     fb: FacebookInit,
     runtime: &mut tokio::runtime::Runtime,
@@ -69,6 +74,11 @@ pub fn start_fb303_and_stats_agg<S: Fb303Service + Sync + Send + 'static>(
             .map_err(|e| format_err!("Failed to start stats aggregation {:?}", e))?;
 
         runtime.spawn(scheduler);
+        runtime.spawn(export_pool_stats(
+            fb,
+            get_global_mysql_connection_pool(matches),
+            POOL_STATS_INTERVAL,
+        ));
     }
     Ok(())
 }
@@ -87,7 +87,10 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
-fn get_blobconfig(blob_config: BlobConfig, inner_blobstore_id: Option<u64>) -> Result<BlobConfig> {
+pub fn get_blobconfig(
+    blob_config: BlobConfig,
+    inner_blobstore_id: Option<u64>,
+) -> Result<BlobConfig> {
     match inner_blobstore_id {
         None => Ok(blob_config),
         Some(inner_blobstore_id) => match blob_config {
@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Error};
+use async_trait::async_trait;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use slog::{error, info, Logger};
+use tokio::{
+    fs::File,
+    io::{stdin, AsyncBufReadExt, BufReader},
+};
+
+use blobstore::Blobstore;
+use blobstore_factory::{make_blobstore, ReadOnlyStorage};
+use cmdlib::args::{self, MononokeMatches};
+use context::CoreContext;
+use fbinit::FacebookInit;
+
+use crate::error::SubcommandError;
+use crate::subcommand::{
+    concurrency_arg, input_file_arg, MononokeSubcommand, ARG_CONCURRENCY, ARG_INPUT_FILE,
+};
+
+pub const BLOBSTORE_SCRUB: &str = "blobstore-scrub";
+const ARG_STORAGE_CONFIG_NAME: &str = "storage-config-name";
+
+pub struct BlobstoreScrubSubcommand;
+
+#[async_trait]
+impl MononokeSubcommand for BlobstoreScrubSubcommand {
+    fn name(&self) -> &'static str {
+        BLOBSTORE_SCRUB
+    }
+
+    fn build_subcommand<'a, 'b>(&self) -> App<'a, 'b> {
+        SubCommand::with_name(BLOBSTORE_SCRUB)
+            .about(concat!(
+                "fetch the given keys from all the components of a storage config, ",
+                "repairing them according to --blobstore-scrub-action, ",
+                "and print the keys which are missing or failed",
+            ))
+            .arg(
+                Arg::with_name(ARG_STORAGE_CONFIG_NAME)
+                    .long(ARG_STORAGE_CONFIG_NAME)
+                    .takes_value(true)
+                    .required(true)
+                    .help("the name of the storage config to scrub"),
+            )
+            .arg(input_file_arg())
+            .arg(concurrency_arg("100"))
+    }
+
+    async fn run<'a>(
+        &self,
+        fb: FacebookInit,
+        logger: Logger,
+        matches: &'a MononokeMatches<'a>,
+        sub_m: &'a ArgMatches<'a>,
+    ) -> Result<(), SubcommandError> {
+        scrub(fb, logger, matches, sub_m).await?;
+        Ok(())
+    }
+}
+
+async fn scrub<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'a>,
+    sub_m: &'a ArgMatches<'a>,
+) -> Result<(), Error> {
+    let config_store = args::init_config_store(fb, &logger, matches)?;
+    let storage_config_name = sub_m
+        .value_of(ARG_STORAGE_CONFIG_NAME)
+        .context("No storage config name")?;
+    let storage_config = args::load_storage_configs(config_store, matches)
+        .context("Could not read storage configs")?
+        .storage
+        .remove(storage_config_name)
+        .context("Requested storage config not found")?;
+    let concurrency = sub_m
+        .value_of(ARG_CONCURRENCY)
+        .expect("concurrency has a default")
+        .parse::<usize>()?;
+
+    let mysql_options = args::parse_mysql_options(matches);
+    let blobstore_options = args::parse_blobstore_options(matches)?;
+    let blobstore = make_blobstore(
+        fb,
+        storage_config.blobstore,
+        &mysql_options,
+        ReadOnlyStorage(false),
+        &blobstore_options,
+        &logger,
+        config_store,
+    )
+    .await?;
+
+    let keys: BoxStream<'_, Result<String, Error>> = match sub_m.value_of_os(ARG_INPUT_FILE) {
+        Some(path) => BufReader::new(File::open(path).await?)
+            .lines()
+            .map_err(Error::from)
+            .boxed(),
+        None => BufReader::new(stdin()).lines().map_err(Error::from).boxed(),
+    };
+
+    let ctx = CoreContext::new_bulk_with_logger(fb, logger.clone());
+    let present = AtomicUsize::new(0);
+    let missing = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    keys.try_for_each_concurrent(concurrency, |key| {
+        let (ctx, blobstore) = (&ctx, &blobstore);
+        let (present, missing, failed) = (&present, &missing, &failed);
+        async move {
+            match blobstore.get(ctx, &key).await {
+                Ok(Some(_)) => {
+                    present.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(None) => {
+                    missing.fetch_add(1, Ordering::Relaxed);
+                    println!("missing {}", key);
+                }
+                Err(err) => {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                    error!(ctx.logger(), "Scrubbing key {} failed: {:?}", key, err);
+                    println!("error {}", key);
+                }
+            }
+            Ok(())
+        }
+    })
+    .await?;
+
+    info!(
+        logger,
+        "{} keys present, {} missing, {} failed",
+        present.load(Ordering::Relaxed),
+        missing.load(Ordering::Relaxed),
+        failed.load(Ordering::Relaxed),
+    );
+    Ok(())
+}
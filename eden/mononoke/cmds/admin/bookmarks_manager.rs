@@ -6,11 +6,14 @@
  */
 
 use anyhow::{format_err, Error};
+use async_trait::async_trait;
 use blobrepo_hg::BlobRepoHg;
 use bookmarks::Freshness;
 use clap::{App, Arg, ArgMatches, SubCommand};
 use cloned::cloned;
+use cmdlib::args::{self, MononokeMatches};
 use context::CoreContext;
+use fbinit::FacebookInit;
 use futures::TryStreamExt;
 use humantime::parse_duration;
 use mononoke_types::Timestamp;
@@ -22,6 +25,7 @@ use bookmarks::{BookmarkName, BookmarkUpdateReason};
 
 use crate::common::{fetch_bonsai_changeset, format_bookmark_log_entry};
 use crate::error::SubcommandError;
+use crate::subcommand::MononokeSubcommand;
 
 pub const BOOKMARKS: &str = "bookmarks";
 const SET_CMD: &str = "set";
@@ -141,6 +145,32 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
         .subcommand(del)
 }
 
+pub struct BookmarksSubcommand;
+
+#[async_trait]
+impl MononokeSubcommand for BookmarksSubcommand {
+    fn name(&self) -> &'static str {
+        BOOKMARKS
+    }
+
+    fn build_subcommand<'a, 'b>(&self) -> App<'a, 'b> {
+        build_subcommand()
+    }
+
+    async fn run<'a>(
+        &self,
+        fb: FacebookInit,
+        logger: Logger,
+        matches: &'a MononokeMatches<'a>,
+        sub_m: &'a ArgMatches<'a>,
+    ) -> Result<(), SubcommandError> {
+        args::init_cachelib(fb, matches);
+        let ctx = CoreContext::new_with_logger(fb, logger.clone());
+        let repo = args::open_repo(fb, &logger, matches).await?;
+        handle_command(ctx, repo, sub_m, logger).await
+    }
+}
+
 pub async fn handle_command(
     ctx: CoreContext,
     repo: BlobRepo,
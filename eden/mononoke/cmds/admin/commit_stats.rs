@@ -0,0 +1,157 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Error};
+use async_trait::async_trait;
+use bookmarks::BookmarkName;
+use chrono::{NaiveDate, Utc};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use slog::{info, Logger};
+
+use cmdlib::args::{self, MononokeMatches};
+use commit_stats::{update_commit_stats, SqlCommitStats};
+use context::CoreContext;
+use fbinit::FacebookInit;
+
+use crate::error::SubcommandError;
+use crate::subcommand::MononokeSubcommand;
+
+pub const COMMIT_STATS: &str = "commit-stats";
+const UPDATE: &str = "update";
+const SHOW: &str = "show";
+const ARG_BOOKMARK: &str = "bookmark";
+const ARG_BATCH_SIZE: &str = "batch-size";
+const ARG_SINCE: &str = "since";
+const ARG_UNTIL: &str = "until";
+const ARG_TOP_AUTHORS: &str = "top-authors";
+
+const DEFAULT_BATCH_SIZE: u64 = 1000;
+
+pub struct CommitStatsSubcommand;
+
+fn bookmark_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name(ARG_BOOKMARK)
+        .long(ARG_BOOKMARK)
+        .takes_value(true)
+        .default_value("master")
+        .help("bookmark whose landed commits are counted")
+}
+
+#[async_trait]
+impl MononokeSubcommand for CommitStatsSubcommand {
+    fn name(&self) -> &'static str {
+        COMMIT_STATS
+    }
+
+    fn build_subcommand<'a, 'b>(&self) -> App<'a, 'b> {
+        SubCommand::with_name(COMMIT_STATS)
+            .about("commit activity statistics computed from the bookmark update log")
+            .subcommand(
+                SubCommand::with_name(UPDATE)
+                    .about("count the commits which landed since the last update")
+                    .arg(bookmark_arg())
+                    .arg(
+                        Arg::with_name(ARG_BATCH_SIZE)
+                            .long(ARG_BATCH_SIZE)
+                            .takes_value(true)
+                            .required(false)
+                            .help("number of bookmark update log entries to read at once"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name(SHOW)
+                    .about("print the statistics of each day, as of the last update")
+                    .arg(bookmark_arg())
+                    .arg(
+                        Arg::with_name(ARG_SINCE)
+                            .long(ARG_SINCE)
+                            .takes_value(true)
+                            .required(true)
+                            .help("first day to show, as YYYY-MM-DD in UTC"),
+                    )
+                    .arg(
+                        Arg::with_name(ARG_UNTIL)
+                            .long(ARG_UNTIL)
+                            .takes_value(true)
+                            .required(false)
+                            .help("last day to show, as YYYY-MM-DD in UTC (default: today)"),
+                    )
+                    .arg(
+                        Arg::with_name(ARG_TOP_AUTHORS)
+                            .long(ARG_TOP_AUTHORS)
+                            .takes_value(true)
+                            .required(false)
+                            .help("also show this many authors with the most commits"),
+                    ),
+            )
+    }
+
+    async fn run<'a>(
+        &self,
+        fb: FacebookInit,
+        logger: Logger,
+        matches: &'a MononokeMatches<'a>,
+        sub_m: &'a ArgMatches<'a>,
+    ) -> Result<(), SubcommandError> {
+        let config_store = args::init_config_store(fb, &logger, matches)?;
+        let ctx = CoreContext::new_with_logger(fb, logger.clone());
+        let stats = args::open_sql::<SqlCommitStats>(fb, config_store, matches).await?;
+
+        match sub_m.subcommand() {
+            (UPDATE, Some(sub_m)) => {
+                let bookmark = get_bookmark(sub_m)?;
+                let batch_size = args::get_u64(sub_m, ARG_BATCH_SIZE, DEFAULT_BATCH_SIZE);
+                let repo = args::open_repo(fb, &logger, matches).await?;
+                let counted =
+                    update_commit_stats(&ctx, &repo, &stats, &bookmark, batch_size).await?;
+                info!(logger, "Counted {} new commits of {}", counted, bookmark);
+            }
+            (SHOW, Some(sub_m)) => {
+                let bookmark = get_bookmark(sub_m)?;
+                let since = parse_date(sub_m.value_of(ARG_SINCE).unwrap())?;
+                let until = match sub_m.value_of(ARG_UNTIL) {
+                    Some(until) => parse_date(until)?,
+                    None => Utc::now().naive_utc().date(),
+                };
+                let repo_id = args::get_repo_id(config_store, matches)?;
+
+                println!("day\tcommits\tauthors\tfile_changes");
+                for day in stats
+                    .get_daily_stats(&ctx, repo_id, &bookmark, since, until)
+                    .await?
+                {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        day.day, day.commits, day.authors, day.file_changes
+                    );
+                }
+
+                if let Some(limit) = args::get_u64_opt(sub_m, ARG_TOP_AUTHORS) {
+                    println!();
+                    println!("author\tcommits");
+                    for author in stats
+                        .get_top_authors(&ctx, repo_id, &bookmark, since, until, limit)
+                        .await?
+                    {
+                        println!("{}\t{}", author.author, author.commits);
+                    }
+                }
+            }
+            _ => return Err(SubcommandError::InvalidArgs),
+        }
+        Ok(())
+    }
+}
+
+fn get_bookmark(sub_m: &ArgMatches<'_>) -> Result<BookmarkName, Error> {
+    BookmarkName::new(sub_m.value_of(ARG_BOOKMARK).unwrap())
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, Error> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| format_err!("invalid date {:?}, expected YYYY-MM-DD: {}", date, e))
+}
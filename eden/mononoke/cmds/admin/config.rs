@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Error};
+use async_trait::async_trait;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use itertools::Itertools;
+use slog::Logger;
+
+use cmdlib::args::{self, MononokeMatches};
+use fbinit::FacebookInit;
+
+use crate::error::SubcommandError;
+use crate::subcommand::MononokeSubcommand;
+
+pub const CONFIG: &str = "config";
+const LINT: &str = "lint";
+const SHOW: &str = "show";
+const ARG_QUIET: &str = "quiet";
+const ARG_VERBOSE: &str = "verbose";
+
+pub struct ConfigSubcommand;
+
+#[async_trait]
+impl MononokeSubcommand for ConfigSubcommand {
+    fn name(&self) -> &'static str {
+        CONFIG
+    }
+
+    fn build_subcommand<'a, 'b>(&self) -> App<'a, 'b> {
+        SubCommand::with_name(CONFIG)
+            .about("inspect Mononoke server configs")
+            .subcommand(
+                SubCommand::with_name(LINT)
+                    .about("check Mononoke server configs for syntax and sanity")
+                    .arg(
+                        Arg::with_name(ARG_QUIET)
+                            .long(ARG_QUIET)
+                            .short("q")
+                            .help("only print errors"),
+                    )
+                    .arg(
+                        Arg::with_name(ARG_VERBOSE)
+                            .long(ARG_VERBOSE)
+                            .short("v")
+                            .help("dump content of configs"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name(SHOW).about("print the parsed config of the repository"),
+            )
+    }
+
+    async fn run<'a>(
+        &self,
+        fb: FacebookInit,
+        logger: Logger,
+        matches: &'a MononokeMatches<'a>,
+        sub_m: &'a ArgMatches<'a>,
+    ) -> Result<(), SubcommandError> {
+        match sub_m.subcommand() {
+            (LINT, Some(sub_m)) => lint(fb, logger, matches, sub_m)?,
+            (SHOW, Some(_)) => {
+                let config_store = args::init_config_store(fb, &logger, matches)?;
+                let (name, config) = args::get_config(config_store, matches)?;
+                println!("Repo {}:\n{:#?}", name, config);
+            }
+            _ => return Err(SubcommandError::InvalidArgs),
+        }
+        Ok(())
+    }
+}
+
+fn lint<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'a>,
+    sub_m: &'a ArgMatches<'a>,
+) -> Result<(), Error> {
+    let quiet = sub_m.is_present(ARG_QUIET);
+    let verbose = sub_m.is_present(ARG_VERBOSE);
+    let config_store = args::init_config_store(fb, &logger, matches)?;
+
+    // Most of the work is done here - this validates that the files are present,
+    // are correctly formed, and have the right fields (not too many, not too few).
+    let configs = args::load_repo_configs(config_store, matches)?;
+
+    if verbose {
+        println!("Configs:\n{:#?}", configs)
+    }
+
+    // Keep track of what repo ids we've seen
+    let mut repoids = BTreeMap::<_, Vec<_>>::new();
+    // Have we seen something suspect?
+    let mut bad = false;
+
+    for (name, config) in &configs.repos {
+        let (isbad, locality) = match (
+            config.storage_config.metadata.is_local(),
+            config.storage_config.blobstore.is_local(),
+        ) {
+            (true, true) => (false, "local"),
+            (false, false) => (false, "remote"),
+            (true, false) => (true, "MIXED - local DB, remote blobstore"),
+            (false, true) => (true, "MIXED - remote DB, local blobstore"),
+        };
+
+        bad |= isbad;
+
+        repoids
+            .entry(config.repoid)
+            .or_default()
+            .push(name.as_str());
+
+        if isbad || !quiet {
+            println!(
+                "Repo {}: {} - enabled: {:?} locality: {}",
+                config.repoid, name, config.enabled, locality
+            );
+        }
+    }
+
+    for (id, names) in repoids {
+        if names.len() > 1 {
+            eprintln!(
+                "ERROR: Repo Id {} used for repos: {}",
+                id,
+                names.into_iter().join(", ")
+            );
+            bad = true;
+        }
+    }
+
+    if bad {
+        bail!("Anomaly detected")
+    }
+    Ok(())
+}
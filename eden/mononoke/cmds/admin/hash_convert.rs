@@ -10,6 +10,7 @@ use fbinit::FacebookInit;
 use std::str::FromStr;
 
 use blobrepo_hg::BlobRepoHg;
+use bonsai_hg_mapping::BonsaiHgMapping;
 use cmdlib::args::{self, MononokeMatches};
 use context::CoreContext;
 use mercurial_types::HgChangesetId;
@@ -63,11 +64,15 @@ pub async fn subcommand_hash_convert<'a>(
     );
     args::init_cachelib(fb, &matches);
     let ctx = CoreContext::new_with_logger(fb, logger.clone());
-    let repo = args::open_repo(fb, &logger, &matches).await?;
+    // Converting from hg only needs the mapping, so don't open the whole repo for it
+    let facets = args::open_repo_facets(fb, &logger, &matches)?;
     if source == "hg" {
-        let maybebonsai = repo
+        let maybebonsai = facets
+            .bonsai_hg_mapping()
+            .await?
             .get_bonsai_from_hg(
-                ctx,
+                &ctx,
+                facets.repo_id(),
                 HgChangesetId::from_str(&source_hash)
                     .expect("source hash is not valid hg changeset id"),
             )
@@ -82,6 +87,7 @@ pub async fn subcommand_hash_convert<'a>(
         }
         Ok(())
     } else {
+        let repo = facets.repo().await?;
         let mercurial = repo
             .get_hg_from_bonsai_changeset(
                 ctx,
@@ -0,0 +1,291 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Error};
+use blobrepo::BlobRepo;
+use blobstore::Loadable;
+use bookmarks::BookmarkName;
+use bytes::Bytes;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use cmdlib::{
+    args::{self, MononokeMatches},
+    helpers,
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use futures::{
+    compat::{Future01CompatExt, Stream01CompatExt},
+    future::try_join_all,
+    stream::{StreamExt, TryStreamExt},
+};
+use hook_manager_factory::make_hook_manager;
+use hooks::{CrossRepoPushSource, HookOutcome};
+use mononoke_types::ChangesetId;
+use revset::DifferenceOfUnionsOfAncestorsNodeStream;
+use skiplist::SkiplistIndex;
+use slog::{info, Logger};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::error::SubcommandError;
+
+pub const HOOKS: &str = "hooks";
+const COMMAND_DRY_RUN: &str = "dry-run";
+const ARG_BOOKMARK: &str = "bookmark";
+const ARG_CHANGESET: &str = "changeset";
+const ARG_FROM: &str = "from";
+const ARG_TO: &str = "to";
+const ARG_LIMIT: &str = "limit";
+const ARG_DISABLE_HOOK: &str = "disable-hook";
+const ARG_PUSHVAR: &str = "pushvar";
+const ARG_PUSH_REDIRECTED: &str = "push-redirected";
+const ARG_SHOW_ACCEPTED: &str = "show-accepted";
+
+pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(HOOKS)
+        .about("commands to work with hooks")
+        .subcommand(
+            SubCommand::with_name(COMMAND_DRY_RUN)
+                .about(concat!(
+                    "run the hooks configured for a bookmark against existing changesets, ",
+                    "or the changesets of a bookmark move, and report which hooks reject them. ",
+                    "Nothing is logged or changed in the repo.",
+                ))
+                .arg(
+                    Arg::with_name(ARG_BOOKMARK)
+                        .long(ARG_BOOKMARK)
+                        .takes_value(true)
+                        .required(true)
+                        .help("bookmark the changesets would be pushed to"),
+                )
+                .arg(
+                    Arg::with_name(ARG_CHANGESET)
+                        .long(ARG_CHANGESET)
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required_unless(ARG_TO)
+                        .conflicts_with_all(&[ARG_FROM, ARG_TO])
+                        .help("{hg|bonsai} changeset id or bookmark name to run the hooks on"),
+                )
+                .arg(
+                    Arg::with_name(ARG_FROM)
+                        .long(ARG_FROM)
+                        .takes_value(true)
+                        .requires(ARG_TO)
+                        .help(concat!(
+                            "{hg|bonsai} changeset id or bookmark name the bookmark moves from, ",
+                            "all ancestors of --to are checked if not specified",
+                        )),
+                )
+                .arg(
+                    Arg::with_name(ARG_TO)
+                        .long(ARG_TO)
+                        .takes_value(true)
+                        .help("{hg|bonsai} changeset id or bookmark name the bookmark moves to"),
+                )
+                .arg(
+                    Arg::with_name(ARG_LIMIT)
+                        .long(ARG_LIMIT)
+                        .takes_value(true)
+                        .default_value("1000")
+                        .help("maximum number of changesets of a bookmark move to check"),
+                )
+                .arg(
+                    Arg::with_name(ARG_DISABLE_HOOK)
+                        .long(ARG_DISABLE_HOOK)
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("hook to leave out, as if it was disabled in the config"),
+                )
+                .arg(
+                    Arg::with_name(ARG_PUSHVAR)
+                        .long(ARG_PUSHVAR)
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("pushvar sent with the push, as NAME=VALUE, e.g. to test bypasses"),
+                )
+                .arg(
+                    Arg::with_name(ARG_PUSH_REDIRECTED)
+                        .long(ARG_PUSH_REDIRECTED)
+                        .help("run the hooks as for changesets push-redirected from a small repo"),
+                )
+                .arg(
+                    Arg::with_name(ARG_SHOW_ACCEPTED)
+                        .long(ARG_SHOW_ACCEPTED)
+                        .help("also print the hooks which accept the changesets"),
+                ),
+        )
+}
+
+pub async fn subcommand_hooks<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'_>,
+    sub_matches: &'a ArgMatches<'_>,
+) -> Result<(), SubcommandError> {
+    args::init_cachelib(fb, &matches);
+
+    let config_store = args::init_config_store(fb, &logger, matches)?;
+    let (repo_name, config) = args::get_config(config_store, matches)?;
+    let repo = args::open_repo(fb, &logger, &matches).await?;
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+
+    match sub_matches.subcommand() {
+        (COMMAND_DRY_RUN, Some(sub_m)) => {
+            let bookmark = BookmarkName::new(
+                sub_m
+                    .value_of(ARG_BOOKMARK)
+                    .ok_or_else(|| format_err!("{} is not set", ARG_BOOKMARK))?,
+            )?;
+            let changesets = match sub_m.value_of(ARG_TO) {
+                Some(to) => {
+                    let limit = args::get_usize(sub_m, ARG_LIMIT, 1000);
+                    let to = resolve(&ctx, &repo, to).await?;
+                    let from = match sub_m.value_of(ARG_FROM) {
+                        Some(from) => Some(resolve(&ctx, &repo, from).await?),
+                        None => None,
+                    };
+                    bookmark_move_changesets(&ctx, &repo, from, to, limit).await?
+                }
+                None => {
+                    try_join_all(
+                        sub_m
+                            .values_of(ARG_CHANGESET)
+                            .into_iter()
+                            .flatten()
+                            .map(|hash_or_bookmark| resolve(&ctx, &repo, hash_or_bookmark)),
+                    )
+                    .await?
+                }
+            };
+            let disabled_hooks: HashSet<String> = sub_m
+                .values_of(ARG_DISABLE_HOOK)
+                .into_iter()
+                .flatten()
+                .map(ToString::to_string)
+                .collect();
+            let pushvars = parse_pushvars(sub_m)?;
+            let cross_repo_push_source = if sub_m.is_present(ARG_PUSH_REDIRECTED) {
+                CrossRepoPushSource::PushRedirected
+            } else {
+                CrossRepoPushSource::NativeToThisRepo
+            };
+
+            let hook_manager =
+                make_hook_manager(&ctx, &repo, config, &repo_name, &disabled_hooks).await?;
+            if !hook_manager.hooks_exist_for_bookmark(&bookmark) {
+                info!(logger, "No hooks are configured for {}", bookmark);
+                return Ok(());
+            }
+
+            let bonsais = try_join_all(
+                changesets
+                    .iter()
+                    .map(|cs_id| cs_id.load(&ctx, repo.blobstore())),
+            )
+            .await?;
+            let outcomes = hook_manager
+                .evaluate_hooks_for_bookmark(
+                    &ctx,
+                    bonsais.iter(),
+                    &bookmark,
+                    pushvars.as_ref(),
+                    cross_repo_push_source,
+                )
+                .await?;
+
+            print_outcomes(
+                changesets.len(),
+                outcomes,
+                sub_m.is_present(ARG_SHOW_ACCEPTED),
+            );
+            Ok(())
+        }
+        _ => Err(SubcommandError::InvalidArgs),
+    }
+}
+
+async fn resolve(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    hash_or_bookmark: &str,
+) -> Result<ChangesetId, Error> {
+    helpers::csid_resolve(ctx.clone(), repo.clone(), hash_or_bookmark)
+        .compat()
+        .await
+}
+
+/// Changesets a bookmark move from `from` to `to` would run the hooks on
+async fn bookmark_move_changesets(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    from: Option<ChangesetId>,
+    to: ChangesetId,
+    limit: usize,
+) -> Result<Vec<ChangesetId>, Error> {
+    let mut changesets: Vec<_> = DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
+        ctx.clone(),
+        &repo.get_changeset_fetcher(),
+        Arc::new(SkiplistIndex::new()),
+        vec![to],
+        from.into_iter().collect(),
+    )
+    .compat()
+    .take(limit + 1)
+    .try_collect()
+    .await?;
+    if changesets.len() > limit {
+        changesets.truncate(limit);
+        info!(
+            ctx.logger(),
+            "The bookmark move has more than {} changesets, only checking the first {}",
+            limit,
+            limit
+        );
+    }
+    Ok(changesets)
+}
+
+fn parse_pushvars(sub_m: &ArgMatches<'_>) -> Result<Option<HashMap<String, Bytes>>, Error> {
+    let pushvars = match sub_m.values_of(ARG_PUSHVAR) {
+        Some(pushvars) => pushvars,
+        None => return Ok(None),
+    };
+    let pushvars = pushvars
+        .map(|pushvar| match pushvar.find('=') {
+            Some(idx) => Ok((
+                pushvar[..idx].to_string(),
+                Bytes::copy_from_slice(pushvar[idx + 1..].as_bytes()),
+            )),
+            None => Err(format_err!(
+                "Invalid pushvar {:?}, expected NAME=VALUE",
+                pushvar
+            )),
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(Some(pushvars))
+}
+
+fn print_outcomes(changeset_count: usize, outcomes: Vec<HookOutcome>, show_accepted: bool) {
+    let outcome_count = outcomes.len();
+    let mut rejections = 0;
+    for outcome in outcomes {
+        if outcome.is_rejection() {
+            rejections += 1;
+            println!("{}", outcome);
+        } else if show_accepted {
+            println!("{}", outcome);
+        }
+    }
+    println!(
+        "{} changesets checked, {} hook runs, {} rejections",
+        changeset_count, outcome_count, rejections
+    );
+}
@@ -14,7 +14,6 @@ use fbinit::FacebookInit;
 use std::process::ExitCode;
 
 use cmdlib::args::{self, ArgType, MononokeClapApp};
-use context::CoreContext;
 use slog::error;
 
 use crate::blobstore_fetch::subcommand_blobstore_fetch;
@@ -28,13 +27,16 @@ use crate::hash_convert::subcommand_hash_convert;
 use crate::hg_changeset::subcommand_hg_changeset;
 use crate::hg_sync::subcommand_process_hg_sync;
 use crate::mutable_counters::subcommand_mutable_counters;
-use crate::redaction::subcommand_redaction;
 use crate::skiplist_subcommand::subcommand_skiplist;
+use crate::subcommand::subcommands;
 
 mod blobstore_fetch;
+mod blobstore_scrub;
 mod bonsai_fetch;
 mod bookmarks_manager;
+mod commit_stats;
 mod common;
+mod config;
 mod content_fetch;
 mod create_bonsai;
 mod crossrepo;
@@ -45,21 +47,28 @@ mod filestore;
 mod hash_convert;
 mod hg_changeset;
 mod hg_sync;
+mod hooks_dry_run;
 mod mutable_counters;
+mod packblob_repack;
+mod packblob_train_dict;
 mod phases;
 mod pushrebase;
 mod rebase;
 mod redaction;
+mod repo_lock;
 mod rsync;
 mod skiplist_subcommand;
+mod subcommand;
 mod subcommand_blame;
 mod subcommand_deleted_manifest;
 mod subcommand_fsnodes;
+mod subcommand_segmented_changelog;
 mod subcommand_skeleton_manifests;
 mod subcommand_unodes;
+mod validate_config;
 
 fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
-    args::MononokeAppBuilder::new("Mononoke admin command line tool")
+    let app = args::MononokeAppBuilder::new("Mononoke admin command line tool")
         .with_arg_types(vec![ArgType::Scrub])
         .with_advanced_args_hidden()
         .with_source_and_target_repos()
@@ -70,13 +79,12 @@ fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
         .subcommand(bonsai_fetch::build_subcommand())
         .subcommand(create_bonsai::build_subcommand())
         .subcommand(content_fetch::build_subcommand())
-        .subcommand(bookmarks_manager::build_subcommand())
         .subcommand(hg_changeset::build_subcommand())
         .subcommand(skiplist_subcommand::build_subcommand())
         .subcommand(hash_convert::build_subcommand())
         .subcommand(hg_sync::build_subcommand())
+        .subcommand(hooks_dry_run::build_subcommand())
         .subcommand(mutable_counters::build_subcommand())
-        .subcommand(redaction::build_subcommand())
         .subcommand(filenodes::build_subcommand())
         .subcommand(phases::build_subcommand())
         .subcommand(filestore::build_subcommand())
@@ -90,6 +98,12 @@ fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
         .subcommand(rebase::build_subcommand())
         .subcommand(pushrebase::build_subcommand())
         .subcommand(subcommand_skeleton_manifests::build_subcommand())
+        .subcommand(packblob_repack::build_subcommand())
+        .subcommand(packblob_train_dict::build_subcommand());
+
+    subcommands().iter().fold(app, |app, subcommand| {
+        app.subcommand(subcommand.build_subcommand())
+    })
 }
 
 #[fbinit::main]
@@ -105,6 +119,12 @@ fn main(fb: FacebookInit) -> ExitCode {
 
     let mut runtime = args::init_runtime(&matches).expect("failed to initialize Tokio runtime");
     let res = runtime.block_on(async {
+        if let (name, Some(sub_m)) = matches.subcommand() {
+            if let Some(subcommand) = subcommands().into_iter().find(|s| s.name() == name) {
+                return subcommand.run(fb, logger, &matches, sub_m).await;
+            }
+        }
+
         match matches.subcommand() {
             (blobstore_fetch::BLOBSTORE_FETCH, Some(sub_m)) => {
                 subcommand_blobstore_fetch(fb, logger, &matches, sub_m).await
@@ -118,12 +138,6 @@ fn main(fb: FacebookInit) -> ExitCode {
             (content_fetch::CONTENT_FETCH, Some(sub_m)) => {
                 subcommand_content_fetch(fb, logger, &matches, sub_m).await
             }
-            (bookmarks_manager::BOOKMARKS, Some(sub_m)) => {
-                args::init_cachelib(fb, &matches);
-                let ctx = CoreContext::new_with_logger(fb, logger.clone());
-                let repo = args::open_repo(fb, &logger, &matches).await?;
-                bookmarks_manager::handle_command(ctx, repo, sub_m, logger.clone()).await
-            }
             (hg_changeset::HG_CHANGESET, Some(sub_m)) => {
                 subcommand_hg_changeset(fb, logger, &matches, sub_m).await
             }
@@ -139,15 +153,15 @@ fn main(fb: FacebookInit) -> ExitCode {
             (mutable_counters::MUTABLE_COUNTERS, Some(sub_m)) => {
                 subcommand_mutable_counters(fb, sub_m, &matches, logger.clone()).await
             }
-            (redaction::REDACTION, Some(sub_m)) => {
-                subcommand_redaction(fb, logger, &matches, sub_m).await
-            }
             (filenodes::FILENODES, Some(sub_m)) => {
                 subcommand_filenodes(fb, logger, &matches, sub_m).await
             }
             (filestore::FILESTORE, Some(sub_m)) => {
                 filestore::execute_command(fb, logger, &matches, sub_m).await
             }
+            (hooks_dry_run::HOOKS, Some(sub_m)) => {
+                hooks_dry_run::subcommand_hooks(fb, logger, &matches, sub_m).await
+            }
             (phases::PHASES, Some(sub_m)) => {
                 phases::subcommand_phases(fb, logger, &matches, sub_m).await
             }
@@ -187,6 +201,13 @@ fn main(fb: FacebookInit) -> ExitCode {
                 )
                 .await
             }
+            (packblob_repack::PACKBLOB_REPACK, Some(sub_m)) => {
+                packblob_repack::subcommand_packblob_repack(fb, logger, &matches, sub_m).await
+            }
+            (packblob_train_dict::PACKBLOB_TRAIN_DICT, Some(sub_m)) => {
+                packblob_train_dict::subcommand_packblob_train_dict(fb, logger, &matches, sub_m)
+                    .await
+            }
             _ => Err(SubcommandError::InvalidArgs),
         }
     });
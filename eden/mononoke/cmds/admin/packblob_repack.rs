@@ -0,0 +1,197 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Context, Result};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use fbinit::FacebookInit;
+use tokio::fs::read_to_string;
+
+use blobstore::{Blobstore, BlobstoreWithLink};
+use blobstore_factory::make_sql_blobstore;
+use cmdlib::args::{self, MononokeMatches};
+use context::CoreContext;
+use fileblob::Fileblob;
+use metaconfig_types::BlobConfig;
+use packblob::{PackBlob, RepackOptions};
+use slog::{info, Logger};
+
+use crate::blobstore_fetch::get_blobconfig;
+use crate::error::SubcommandError;
+
+pub const PACKBLOB_REPACK: &str = "packblob-repack";
+const ARG_KEYS_FILE: &str = "keys-file";
+const ARG_MIN_PACK_ENTRIES: &str = "min-pack-entries";
+const ARG_MAX_PACK_ENTRIES: &str = "max-pack-entries";
+const ARG_PACK_PREFIX: &str = "pack-prefix";
+const ARG_INNER_BLOBSTORE_ID: &str = "inner-blobstore-id";
+const ARG_NO_PREFIX: &str = "no-prefix";
+
+pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(PACKBLOB_REPACK)
+        .about(
+            "rewrites unpacked blobs and blobs in small packs of a packed blobstore into larger \
+             packs, compressed at --blobstore-write-zstd-level",
+        )
+        .arg(
+            Arg::with_name(ARG_KEYS_FILE)
+                .long(ARG_KEYS_FILE)
+                .takes_value(true)
+                .required(true)
+                .help("file with the keys to repack, one per line"),
+        )
+        .arg(
+            Arg::with_name(ARG_MIN_PACK_ENTRIES)
+                .long(ARG_MIN_PACK_ENTRIES)
+                .takes_value(true)
+                .required(false)
+                .help("repack the keys in packs with fewer entries than this"),
+        )
+        .arg(
+            Arg::with_name(ARG_MAX_PACK_ENTRIES)
+                .long(ARG_MAX_PACK_ENTRIES)
+                .takes_value(true)
+                .required(false)
+                .help("maximum number of entries in a new pack"),
+        )
+        .arg(
+            Arg::with_name(ARG_PACK_PREFIX)
+                .long(ARG_PACK_PREFIX)
+                .takes_value(true)
+                .required(false)
+                .help("prefix of the keys of the new packs (default: <repo prefix>packed.)"),
+        )
+        .arg(
+            Arg::with_name(ARG_NO_PREFIX)
+                .long(ARG_NO_PREFIX)
+                .takes_value(false)
+                .required(false)
+                .help("Don't prepend a prefix based on the repo id to the keys"),
+        )
+        .arg(
+            Arg::with_name(ARG_INNER_BLOBSTORE_ID)
+                .long(ARG_INNER_BLOBSTORE_ID)
+                .takes_value(true)
+                .required(false)
+                .help("If main blobstore in the storage config is a multiplexed one, use inner blobstore with this id")
+        )
+}
+
+async fn repack<T: Blobstore + BlobstoreWithLink>(
+    ctx: &CoreContext,
+    packblob: PackBlob<T>,
+    keys: Vec<String>,
+    prefix: String,
+    options: &RepackOptions,
+) -> Result<()> {
+    info!(
+        ctx.logger(),
+        "repacking {} keys into {}",
+        keys.len(),
+        prefix
+    );
+    let stats = packblob.repack(ctx, keys, prefix, options).await?;
+    info!(ctx.logger(), "{}", stats);
+    Ok(())
+}
+
+pub async fn subcommand_packblob_repack<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'a>,
+    sub_m: &'a ArgMatches<'a>,
+) -> Result<(), SubcommandError> {
+    let config_store = args::init_config_store(fb, &logger, matches)?;
+    let repo_id = args::get_repo_id(config_store, &matches)?;
+    let (_, config) = args::get_config(config_store, &matches)?;
+    let inner_blobstore_id = args::get_u64_opt(&sub_m, ARG_INNER_BLOBSTORE_ID);
+    let mysql_options = args::parse_mysql_options(&matches);
+    let blobstore_options = args::parse_blobstore_options(&matches)?;
+    let readonly_storage = args::parse_readonly_storage(&matches);
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+
+    let defaults = RepackOptions::default();
+    let options = RepackOptions {
+        zstd_level: blobstore_options
+            .pack_options
+            .put_compress_level()
+            .unwrap_or(defaults.zstd_level),
+        min_pack_entries: args::get_usize(&sub_m, ARG_MIN_PACK_ENTRIES, defaults.min_pack_entries),
+        max_pack_entries: args::get_usize(&sub_m, ARG_MAX_PACK_ENTRIES, defaults.max_pack_entries),
+    };
+    let prefix = match sub_m.value_of(ARG_PACK_PREFIX) {
+        Some(prefix) => prefix.to_string(),
+        None => format!("{}packed.", repo_id.prefix()),
+    };
+
+    let keys_file = sub_m.value_of(ARG_KEYS_FILE).unwrap();
+    let no_prefix = sub_m.is_present(ARG_NO_PREFIX);
+    let keys: Vec<String> = read_to_string(keys_file)
+        .await
+        .with_context(|| format!("While reading {}", keys_file))?
+        .lines()
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            if no_prefix {
+                key.to_string()
+            } else {
+                format!("{}{}", repo_id.prefix(), key)
+            }
+        })
+        .collect();
+
+    // Repacking relinks keys to the new packs, so it needs the store under the packblob itself
+    // rather than the full blobstore stack.
+    let blobconfig = match get_blobconfig(config.storage_config.blobstore, inner_blobstore_id)? {
+        BlobConfig::Pack { blobconfig } => *blobconfig,
+        other => {
+            return Err(format_err!("blobstore is not packed: {:?}", other).into());
+        }
+    };
+    let pack_options = blobstore_options.pack_options.clone();
+    match blobconfig {
+        BlobConfig::Files { path } => {
+            let inner = Fileblob::open(path.join("blobs"), blobstore_options.put_behaviour)?;
+            repack(
+                &ctx,
+                PackBlob::new(inner, pack_options),
+                keys,
+                prefix,
+                &options,
+            )
+            .await?
+        }
+        blobconfig @ BlobConfig::Sqlite { .. } | blobconfig @ BlobConfig::Mysql { .. } => {
+            let inner = make_sql_blobstore(
+                fb,
+                blobconfig,
+                &mysql_options,
+                readonly_storage,
+                &blobstore_options,
+                config_store,
+            )
+            .await?;
+            repack(
+                &ctx,
+                PackBlob::new(inner, pack_options),
+                keys,
+                prefix,
+                &options,
+            )
+            .await?
+        }
+        other => {
+            return Err(format_err!(
+                "repacking is not supported on {:?}, which can't link keys",
+                other
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
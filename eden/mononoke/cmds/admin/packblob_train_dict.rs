@@ -0,0 +1,238 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::BTreeMap;
+
+use anyhow::{format_err, Context, Result};
+use bytes::Bytes;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use fbinit::FacebookInit;
+use tokio::fs::read_to_string;
+
+use blobstore::{Blobstore, BlobstorePutOps};
+use blobstore_factory::make_sql_blobstore;
+use cmdlib::args::{self, MononokeMatches};
+use context::CoreContext;
+use fileblob::Fileblob;
+use metaconfig_types::BlobConfig;
+use packblob::{key_family, train_dict, PackBlob, DEFAULT_DICT_SIZE};
+use slog::{info, warn, Logger};
+
+use crate::blobstore_fetch::get_blobconfig;
+use crate::error::SubcommandError;
+
+pub const PACKBLOB_TRAIN_DICT: &str = "packblob-train-dict";
+const ARG_KEYS_FILE: &str = "keys-file";
+const ARG_MAX_DICT_SIZE: &str = "max-dict-size";
+const ARG_MAX_SAMPLES: &str = "max-samples";
+const ARG_MIN_SAMPLES: &str = "min-samples";
+const ARG_DICT_PREFIX: &str = "dict-prefix";
+const ARG_INNER_BLOBSTORE_ID: &str = "inner-blobstore-id";
+const ARG_NO_PREFIX: &str = "no-prefix";
+
+const DEFAULT_MAX_SAMPLES: usize = 10000;
+const DEFAULT_MIN_SAMPLES: usize = 100;
+
+pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(PACKBLOB_TRAIN_DICT)
+        .about(
+            "trains a zstd dictionary for each key family (e.g. hgmanifest) of the sample keys and \
+             stores it in a packed blobstore, printing the --blobstore-write-zstd-dict arguments \
+             that make writes use them",
+        )
+        .arg(
+            Arg::with_name(ARG_KEYS_FILE)
+                .long(ARG_KEYS_FILE)
+                .takes_value(true)
+                .required(true)
+                .help("file with the keys of the sample values, one per line"),
+        )
+        .arg(
+            Arg::with_name(ARG_MAX_DICT_SIZE)
+                .long(ARG_MAX_DICT_SIZE)
+                .takes_value(true)
+                .required(false)
+                .help("maximum size in bytes of each dictionary"),
+        )
+        .arg(
+            Arg::with_name(ARG_MAX_SAMPLES)
+                .long(ARG_MAX_SAMPLES)
+                .takes_value(true)
+                .required(false)
+                .help("maximum number of samples to train each dictionary from"),
+        )
+        .arg(
+            Arg::with_name(ARG_MIN_SAMPLES)
+                .long(ARG_MIN_SAMPLES)
+                .takes_value(true)
+                .required(false)
+                .help("don't train dictionaries for families with fewer samples than this"),
+        )
+        .arg(
+            Arg::with_name(ARG_DICT_PREFIX)
+                .long(ARG_DICT_PREFIX)
+                .takes_value(true)
+                .required(false)
+                .help("prefix of the keys of the dictionaries (default: <repo prefix>)"),
+        )
+        .arg(
+            Arg::with_name(ARG_NO_PREFIX)
+                .long(ARG_NO_PREFIX)
+                .takes_value(false)
+                .required(false)
+                .help("Don't prepend a prefix based on the repo id to the keys"),
+        )
+        .arg(
+            Arg::with_name(ARG_INNER_BLOBSTORE_ID)
+                .long(ARG_INNER_BLOBSTORE_ID)
+                .takes_value(true)
+                .required(false)
+                .help("If main blobstore in the storage config is a multiplexed one, use inner blobstore with this id")
+        )
+}
+
+struct TrainOptions {
+    max_dict_size: usize,
+    max_samples: usize,
+    min_samples: usize,
+}
+
+async fn train<T: BlobstorePutOps>(
+    ctx: &CoreContext,
+    packblob: PackBlob<T>,
+    keys: Vec<String>,
+    prefix: String,
+    options: &TrainOptions,
+) -> Result<()> {
+    // Sorted so the output is stable
+    let mut samples: BTreeMap<String, Vec<Bytes>> = BTreeMap::new();
+    for key in keys {
+        let family_samples = samples.entry(key_family(&key).to_string()).or_default();
+        if family_samples.len() >= options.max_samples {
+            continue;
+        }
+        match packblob.get(ctx, &key).await? {
+            Some(value) => family_samples.push(value.into_bytes().into_bytes()),
+            None => warn!(ctx.logger(), "sample key {} is missing", key),
+        }
+    }
+
+    for (family, family_samples) in samples {
+        if family_samples.len() < options.min_samples {
+            warn!(
+                ctx.logger(),
+                "skipping family {}: only {} samples",
+                family,
+                family_samples.len()
+            );
+            continue;
+        }
+        info!(
+            ctx.logger(),
+            "training dictionary for family {} from {} samples",
+            family,
+            family_samples.len()
+        );
+        let dict = train_dict(&family_samples, options.max_dict_size)
+            .with_context(|| format!("While training dictionary for family {}", family))?;
+        let dict_key = packblob.put_dict(ctx, &prefix, &family, dict).await?;
+        println!("--blobstore-write-zstd-dict {}={}", family, dict_key);
+    }
+    Ok(())
+}
+
+pub async fn subcommand_packblob_train_dict<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'a>,
+    sub_m: &'a ArgMatches<'a>,
+) -> Result<(), SubcommandError> {
+    let config_store = args::init_config_store(fb, &logger, matches)?;
+    let repo_id = args::get_repo_id(config_store, &matches)?;
+    let (_, config) = args::get_config(config_store, &matches)?;
+    let inner_blobstore_id = args::get_u64_opt(&sub_m, ARG_INNER_BLOBSTORE_ID);
+    let mysql_options = args::parse_mysql_options(&matches);
+    let blobstore_options = args::parse_blobstore_options(&matches)?;
+    let readonly_storage = args::parse_readonly_storage(&matches);
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+
+    let options = TrainOptions {
+        max_dict_size: args::get_usize(&sub_m, ARG_MAX_DICT_SIZE, DEFAULT_DICT_SIZE),
+        max_samples: args::get_usize(&sub_m, ARG_MAX_SAMPLES, DEFAULT_MAX_SAMPLES),
+        min_samples: args::get_usize(&sub_m, ARG_MIN_SAMPLES, DEFAULT_MIN_SAMPLES),
+    };
+    let prefix = match sub_m.value_of(ARG_DICT_PREFIX) {
+        Some(prefix) => prefix.to_string(),
+        None => repo_id.prefix(),
+    };
+
+    let keys_file = sub_m.value_of(ARG_KEYS_FILE).unwrap();
+    let no_prefix = sub_m.is_present(ARG_NO_PREFIX);
+    let keys: Vec<String> = read_to_string(keys_file)
+        .await
+        .with_context(|| format!("While reading {}", keys_file))?
+        .lines()
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            if no_prefix {
+                key.to_string()
+            } else {
+                format!("{}{}", repo_id.prefix(), key)
+            }
+        })
+        .collect();
+
+    // The dictionaries are stored by the packblob itself, so it is opened without the rest of the
+    // blobstore stack.
+    let blobconfig = match get_blobconfig(config.storage_config.blobstore, inner_blobstore_id)? {
+        BlobConfig::Pack { blobconfig } => *blobconfig,
+        other => {
+            return Err(format_err!("blobstore is not packed: {:?}", other).into());
+        }
+    };
+    let pack_options = blobstore_options.pack_options.clone();
+    match blobconfig {
+        BlobConfig::Files { path } => {
+            let inner = Fileblob::open(path.join("blobs"), blobstore_options.put_behaviour)?;
+            train(
+                &ctx,
+                PackBlob::new(inner, pack_options),
+                keys,
+                prefix,
+                &options,
+            )
+            .await?
+        }
+        blobconfig @ BlobConfig::Sqlite { .. } | blobconfig @ BlobConfig::Mysql { .. } => {
+            let inner = make_sql_blobstore(
+                fb,
+                blobconfig,
+                &mysql_options,
+                readonly_storage,
+                &blobstore_options,
+                config_store,
+            )
+            .await?;
+            train(
+                &ctx,
+                PackBlob::new(inner, pack_options),
+                keys,
+                prefix,
+                &options,
+            )
+            .await?
+        }
+        other => {
+            return Err(
+                format_err!("training dictionaries is not supported on {:?}", other).into(),
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -7,9 +7,10 @@
 
 use crate::common::get_file_nodes;
 use anyhow::{anyhow, format_err, Context, Error};
+use async_trait::async_trait;
 use blobrepo::BlobRepo;
 use blobrepo_hg::BlobRepoHg;
-use blobstore::Loadable;
+use blobstore::{Blobstore, Loadable};
 use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
 use cloned::cloned;
 use cmdlib::{
@@ -25,19 +26,28 @@ use futures::{
 };
 use manifest::ManifestOps;
 use mercurial_types::{blobs::HgBlobChangeset, HgChangesetId, MPath};
-use mononoke_types::{typed_hash::MononokeId, ContentId, Timestamp};
-use redactedblobstore::SqlRedactedContentStore;
+use mononoke_types::{typed_hash::MononokeId, ContentId, DateTime, Timestamp};
+use redactedblobstore::{RedactedEntry, SqlRedactedContentStore};
 use slog::{error, info, Logger};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 use crate::error::SubcommandError;
+use crate::subcommand::MononokeSubcommand;
 
 pub const REDACTION: &str = "redaction";
 const REDACTION_ADD: &str = "add";
 const REDACTION_REMOVE: &str = "remove";
 const REDACTION_LIST: &str = "list";
+const REDACTION_ADD_KEYS: &str = "add-keys";
+const REDACTION_REMOVE_KEYS: &str = "remove-keys";
+const REDACTION_LIST_KEYS: &str = "list-keys";
+const REDACTION_DRY_RUN: &str = "dry-run";
+const ARG_REASON: &str = "reason";
+const ARG_TASK: &str = "task";
+const ARG_COMMIT: &str = "commit";
+const ARG_KEYS_LIST: &str = "KEYS_LIST";
 const ARG_LOG_ONLY: &str = "log-only";
 const ARG_FORCE: &str = "force";
 const ARG_INPUT_FILE: &str = "input-file";
@@ -82,6 +92,7 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
                         .takes_value(false)
                         .help("redact file in log-only mode. All accesses to this file will be allowed, but they will all be logged")
                 )
+                .arg(reason_arg())
         ))
         .subcommand(add_path_parameters(
             SubCommand::with_name(REDACTION_REMOVE)
@@ -103,6 +114,80 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
                         .required(true),
                 ),
         )
+        .subcommand(add_key_parameters(
+            SubCommand::with_name(REDACTION_ADD_KEYS)
+                .about("redact blobstore keys directly, e.g. content of files which are in no commit anymore")
+                .arg(
+                    Arg::with_name(ARG_TASK)
+                        .help("Task tracking the redaction request")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(ARG_LOG_ONLY)
+                        .long(ARG_LOG_ONLY)
+                        .takes_value(false)
+                        .help("redact keys in log-only mode. All accesses to them will be allowed, but they will all be logged")
+                )
+                .arg(reason_arg()),
+        ))
+        .subcommand(add_key_parameters(
+            SubCommand::with_name(REDACTION_REMOVE_KEYS)
+                .about("remove blobstore keys from the redaction"),
+        ))
+        .subcommand(
+            SubCommand::with_name(REDACTION_LIST_KEYS)
+                .about("list all redacted blobstore keys, with the metadata of their redaction")
+                .arg(
+                    Arg::with_name(ARG_TASK)
+                        .long(ARG_TASK)
+                        .takes_value(true)
+                        .required(false)
+                        .help("only list the keys redacted under this task"),
+                ),
+        )
+        .subcommand(add_key_parameters(
+            SubCommand::with_name(REDACTION_DRY_RUN)
+                .about("show what redacting blobstore keys would affect, without redacting them")
+                .arg(
+                    Arg::with_name(ARG_COMMIT)
+                        .long(ARG_COMMIT)
+                        .takes_value(true)
+                        .required(false)
+                        .default_value(DEFAULT_MAIN_BOOKMARK)
+                        .help("hg commit hash or bookmark whose files with the redacted content are listed"),
+                ),
+        ))
+}
+
+fn reason_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name(ARG_REASON)
+        .long(ARG_REASON)
+        .takes_value(true)
+        .required(false)
+        .help("why the content is redacted, recorded alongside the task")
+}
+
+pub fn add_key_parameters<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name(ARG_INPUT_FILE)
+            .long(ARG_INPUT_FILE)
+            .help("file with a list of blobstore keys, one per line")
+            .takes_value(true)
+            .required(false),
+    )
+    .arg(
+        Arg::with_name(ARG_KEYS_LIST)
+            .help("blobstore keys, without repo prefix, e.g. content.blake2.<hash>")
+            .takes_value(true)
+            .multiple(true)
+            .required(false),
+    )
+    .group(
+        ArgGroup::with_name("input_keys")
+            .args(&[ARG_KEYS_LIST, ARG_INPUT_FILE])
+            .required(true),
+    )
 }
 
 pub fn add_path_parameters<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
@@ -157,6 +242,29 @@ async fn find_files_with_given_content_id_blobstore_keys<'a>(
 }
 
 /// Entrypoint for redaction subcommand handling
+pub struct RedactionSubcommand;
+
+#[async_trait]
+impl MononokeSubcommand for RedactionSubcommand {
+    fn name(&self) -> &'static str {
+        REDACTION
+    }
+
+    fn build_subcommand<'a, 'b>(&self) -> App<'a, 'b> {
+        build_subcommand()
+    }
+
+    async fn run<'a>(
+        &self,
+        fb: FacebookInit,
+        logger: Logger,
+        matches: &'a MononokeMatches<'a>,
+        sub_m: &'a ArgMatches<'a>,
+    ) -> Result<(), SubcommandError> {
+        subcommand_redaction(fb, logger, matches, sub_m).await
+    }
+}
+
 pub async fn subcommand_redaction<'a>(
     fb: FacebookInit,
     logger: Logger,
@@ -169,6 +277,18 @@ pub async fn subcommand_redaction<'a>(
             redaction_remove(fb, logger, matches, sub_sub_m).await
         }
         (REDACTION_LIST, Some(sub_sub_m)) => redaction_list(fb, logger, matches, sub_sub_m).await,
+        (REDACTION_ADD_KEYS, Some(sub_sub_m)) => {
+            redaction_add_keys(fb, logger, matches, sub_sub_m).await
+        }
+        (REDACTION_REMOVE_KEYS, Some(sub_sub_m)) => {
+            redaction_remove_keys(fb, logger, matches, sub_sub_m).await
+        }
+        (REDACTION_LIST_KEYS, Some(sub_sub_m)) => {
+            redaction_list_keys(fb, logger, matches, sub_sub_m).await
+        }
+        (REDACTION_DRY_RUN, Some(sub_sub_m)) => {
+            redaction_dry_run(fb, logger, matches, sub_sub_m).await
+        }
         _ => {
             eprintln!("{}", matches.usage());
             ::std::process::exit(1);
@@ -199,6 +319,35 @@ fn paths_parser(sub_m: &ArgMatches<'_>) -> Result<Vec<MPath>, Error> {
     }
 }
 
+/// Fetch the blobstore key list from the subcommand cli matches
+fn keys_parser(sub_m: &ArgMatches<'_>) -> Result<Vec<String>, Error> {
+    let keys: Vec<String> = match sub_m.values_of(ARG_KEYS_LIST) {
+        Some(values) => values.map(|s| s.to_string()).collect(),
+        None => match sub_m.value_of(ARG_INPUT_FILE) {
+            Some(inputfile) => {
+                let inputfile = File::open(inputfile)?;
+                let input_file = BufReader::new(&inputfile);
+                let mut keys = vec![];
+                for line in input_file.lines() {
+                    let line = line?;
+                    let key = line.trim();
+                    if !key.is_empty() {
+                        keys.push(key.to_string());
+                    }
+                }
+                keys
+            }
+            None => {
+                return Err(format_err!("key list is not specified"));
+            }
+        },
+    };
+    if keys.is_empty() {
+        return Err(format_err!("key list is empty"));
+    }
+    Ok(keys)
+}
+
 /// Fetch the task id and the file list from the subcommand cli matches
 fn task_and_paths_parser(sub_m: &ArgMatches<'_>) -> Result<(String, Vec<MPath>), Error> {
     let task = match sub_m.value_of("task") {
@@ -309,9 +458,10 @@ async fn redaction_add<'a, 'b>(
         .await?;
     }
 
+    let reason = sub_m.value_of(ARG_REASON).map(|reason| reason.to_string());
     let timestamp = Timestamp::now();
     redacted_blobs
-        .insert_redacted_blobs(&blobstore_keys, &task, &timestamp, log_only)
+        .insert_redacted_blobs(&blobstore_keys, &task, &timestamp, log_only, &reason)
         .compat()
         .await?;
 
@@ -381,6 +531,179 @@ async fn redaction_remove<'a>(
         .map_err(SubcommandError::Error)
 }
 
+async fn open_redacted_blobs<'a>(
+    fb: FacebookInit,
+    logger: &Logger,
+    matches: &'a MononokeMatches<'_>,
+) -> Result<SqlRedactedContentStore, Error> {
+    let config_store = args::init_config_store(fb, logger, matches)?;
+    args::open_sql::<SqlRedactedContentStore>(fb, config_store, &matches)
+        .await
+        .context("While opening SqlRedactedContentStore")
+}
+
+async fn redaction_add_keys<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'_>,
+    sub_m: &'a ArgMatches<'_>,
+) -> Result<(), SubcommandError> {
+    let task = match sub_m.value_of(ARG_TASK) {
+        Some(task) => task.to_string(),
+        None => return Err(SubcommandError::InvalidArgs),
+    };
+    let keys = keys_parser(sub_m)?;
+    let log_only = sub_m.is_present(ARG_LOG_ONLY);
+    let reason = sub_m.value_of(ARG_REASON).map(|reason| reason.to_string());
+
+    let redacted_blobs = open_redacted_blobs(fb, &logger, matches).await?;
+    redacted_blobs
+        .insert_redacted_blobs(&keys, &task, &Timestamp::now(), log_only, &reason)
+        .compat()
+        .await?;
+    info!(logger, "Redacted {} keys under {}", keys.len(), task);
+    Ok(())
+}
+
+async fn redaction_remove_keys<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'_>,
+    sub_m: &'a ArgMatches<'_>,
+) -> Result<(), SubcommandError> {
+    let keys = keys_parser(sub_m)?;
+    let redacted_blobs = open_redacted_blobs(fb, &logger, matches).await?;
+    redacted_blobs
+        .delete_redacted_blobs(&keys)
+        .compat()
+        .await
+        .map_err(SubcommandError::Error)
+}
+
+fn format_redacted_entry(entry: &RedactedEntry) -> String {
+    let log_only_msg = if entry.log_only { " (log only)" } else { "" };
+    let reason_msg = match &entry.reason {
+        Some(reason) => format!(": {}", reason),
+        None => String::new(),
+    };
+    format!(
+        "{} {:20} added {}{}{}",
+        entry.content_key,
+        entry.task,
+        DateTime::from(entry.add_timestamp),
+        log_only_msg,
+        reason_msg
+    )
+}
+
+async fn redaction_list_keys<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'_>,
+    sub_m: &'a ArgMatches<'_>,
+) -> Result<(), SubcommandError> {
+    let task = sub_m.value_of(ARG_TASK).map(|task| task.to_string());
+    let redacted_blobs = open_redacted_blobs(fb, &logger, matches).await?;
+    let entries = redacted_blobs.list_redacted_entries(task).compat().await?;
+    if entries.is_empty() {
+        info!(logger, "No keys are redacted");
+    }
+    for entry in entries {
+        info!(logger, "{}", format_redacted_entry(&entry));
+    }
+    Ok(())
+}
+
+/// Report, for keys about to be redacted, whether the blobstore has them, whether they are
+/// redacted already, and which files of a commit have them as content. Nothing is written.
+async fn redaction_dry_run<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'_>,
+    sub_m: &'a ArgMatches<'_>,
+) -> Result<(), SubcommandError> {
+    let keys = keys_parser(sub_m)?;
+    let commit = sub_m.value_of(ARG_COMMIT).unwrap_or(DEFAULT_MAIN_BOOKMARK);
+
+    args::init_cachelib(fb, &matches);
+    let blobrepo = args::open_repo(fb, &logger, &matches);
+    let redacted_blobs = open_redacted_blobs(fb, &logger, matches);
+    let (blobrepo, redacted_blobs) = try_join(blobrepo, redacted_blobs).await?;
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+
+    let redacted: HashMap<_, _> = redacted_blobs
+        .get_redacted_entries(&keys)
+        .compat()
+        .await?
+        .into_iter()
+        .map(|entry| (entry.content_key.clone(), entry))
+        .collect();
+    let present = try_join_all(
+        keys.iter()
+            .map(|key| blobrepo.blobstore().is_present(&ctx, key)),
+    )
+    .await?;
+    for (key, present) in keys.iter().zip(present) {
+        let present_msg = if present {
+            "present in blobstore"
+        } else {
+            "missing from blobstore"
+        };
+        match redacted.get(key) {
+            Some(entry) => info!(
+                logger,
+                "{}: {}, already redacted: {}",
+                key,
+                present_msg,
+                format_redacted_entry(entry)
+            ),
+            None => info!(logger, "{}: {}", key, present_msg),
+        }
+    }
+
+    info!(
+        logger,
+        "Looking for files with this content in {}...", commit
+    );
+    let cs_id = helpers::csid_resolve(ctx.clone(), blobrepo.clone(), commit)
+        .compat()
+        .await?;
+    let hg_cs = blobrepo
+        .get_hg_from_bonsai_changeset(ctx.clone(), cs_id)
+        .await?
+        .load(&ctx, blobrepo.blobstore())
+        .await?;
+    let mut files = find_files_with_given_content_id_blobstore_keys(
+        &ctx,
+        &blobrepo,
+        hg_cs,
+        keys.iter().collect(),
+    )
+    .await?;
+    if files.is_empty() {
+        info!(logger, "No files in {} would be redacted", commit);
+    } else {
+        files.sort();
+        for (path, content_id) in &files {
+            info!(
+                logger,
+                "Would redact in {}: {} {}",
+                commit,
+                path,
+                content_id.blobstore_key()
+            );
+        }
+        info!(
+            logger,
+            "{} files would be redacted in {}. Checking them out would be impossible, unless \
+            the keys are redacted with --log-only",
+            files.len(),
+            commit
+        );
+    }
+    Ok(())
+}
+
 async fn check_if_content_is_reachable_from_bookmark(
     ctx: &CoreContext,
     blobrepo: &BlobRepo,
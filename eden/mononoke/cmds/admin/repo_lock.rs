@@ -0,0 +1,114 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{bail, format_err, Error};
+use async_trait::async_trait;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use futures::compat::Future01CompatExt;
+use slog::{info, Logger};
+
+use cmdlib::{
+    args::{self, MononokeMatches},
+    helpers,
+};
+use fbinit::FacebookInit;
+
+use crate::error::SubcommandError;
+use crate::subcommand::MononokeSubcommand;
+
+pub const REPO_LOCK: &str = "repo-lock";
+const STATUS: &str = "status";
+const LOCK: &str = "lock";
+const UNLOCK: &str = "unlock";
+const ARG_REASON: &str = "reason";
+
+/// The reason is stored in a VARCHAR(255) column
+const MAX_REASON_LEN: usize = 255;
+
+pub struct RepoLockSubcommand;
+
+#[async_trait]
+impl MononokeSubcommand for RepoLockSubcommand {
+    fn name(&self) -> &'static str {
+        REPO_LOCK
+    }
+
+    fn build_subcommand<'a, 'b>(&self) -> App<'a, 'b> {
+        SubCommand::with_name(REPO_LOCK)
+            .about("inspect or change whether pushes to the repository are allowed")
+            .subcommand(
+                SubCommand::with_name(STATUS)
+                    .about("show whether writes are locked, and the reason shown to users"),
+            )
+            .subcommand(
+                SubCommand::with_name(LOCK)
+                    .about("refuse pushes to the repository")
+                    .arg(
+                        Arg::with_name(ARG_REASON)
+                            .long(ARG_REASON)
+                            .takes_value(true)
+                            .required(true)
+                            .help("message shown to users whose push is refused"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name(UNLOCK)
+                    .about("allow pushes to the repository again")
+                    .arg(
+                        Arg::with_name(ARG_REASON)
+                            .long(ARG_REASON)
+                            .takes_value(true)
+                            .required(false)
+                            .help("why the repository was unlocked, kept for reference"),
+                    ),
+            )
+    }
+
+    async fn run<'a>(
+        &self,
+        fb: FacebookInit,
+        logger: Logger,
+        matches: &'a MononokeMatches<'a>,
+        sub_m: &'a ArgMatches<'a>,
+    ) -> Result<(), SubcommandError> {
+        let config_store = args::init_config_store(fb, &logger, matches)?;
+        let fetcher = args::open_repo_read_write_fetcher(fb, config_store, matches).await?;
+
+        match sub_m.subcommand() {
+            (STATUS, Some(_)) => {
+                let state = fetcher.readonly().compat().await?;
+                println!("{}", helpers::describe_repo_lock(&state));
+            }
+            (LOCK, Some(sub_m)) => {
+                let reason = get_reason(sub_m)?
+                    .ok_or_else(|| format_err!("--{} is required", ARG_REASON))?;
+                fetcher.set_read_only(&reason).compat().await?;
+                info!(logger, "Locked writes: {}", reason);
+            }
+            (UNLOCK, Some(sub_m)) => {
+                let reason = get_reason(sub_m)?.unwrap_or_default();
+                fetcher.set_mononoke_read_write(&reason).compat().await?;
+                info!(logger, "Unlocked writes");
+                // The lock in the database does not override the repo config
+                helpers::log_repo_lock_state(&logger, &fetcher).await?;
+            }
+            _ => return Err(SubcommandError::InvalidArgs),
+        }
+        Ok(())
+    }
+}
+
+fn get_reason(sub_m: &ArgMatches<'_>) -> Result<Option<String>, Error> {
+    match sub_m.value_of(ARG_REASON) {
+        Some(reason) if reason.len() > MAX_REASON_LEN => bail!(
+            "--{} must be at most {} bytes long",
+            ARG_REASON,
+            MAX_REASON_LEN
+        ),
+        reason => Ok(reason.map(String::from)),
+    }
+}
@@ -0,0 +1,89 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Subcommands of the admin tool which are registered with it, instead of being wired into
+//! `main` by hand. The maintenance jobs which used to be separate binaries are hosted here.
+
+use async_trait::async_trait;
+use clap::{App, Arg, ArgMatches};
+use cmdlib::args::MononokeMatches;
+use fbinit::FacebookInit;
+use slog::Logger;
+
+use crate::blobstore_scrub::BlobstoreScrubSubcommand;
+use crate::bookmarks_manager::BookmarksSubcommand;
+use crate::commit_stats::CommitStatsSubcommand;
+use crate::config::ConfigSubcommand;
+use crate::error::SubcommandError;
+use crate::redaction::RedactionSubcommand;
+use crate::repo_lock::RepoLockSubcommand;
+use crate::subcommand_segmented_changelog::SegmentedChangelogSubcommand;
+use crate::validate_config::ValidateConfigSubcommand;
+
+pub const ARG_HEAD: &str = "head";
+pub const ARG_CONCURRENCY: &str = "concurrency";
+pub const ARG_INPUT_FILE: &str = "input-file";
+
+/// A subcommand of the admin tool
+#[async_trait]
+pub trait MononokeSubcommand: Send + Sync {
+    /// Name the subcommand is invoked with
+    fn name(&self) -> &'static str;
+
+    /// Arguments of the subcommand. Arguments which several subcommands take should come from
+    /// the helpers in this module, so that they are spelled the same everywhere.
+    fn build_subcommand<'a, 'b>(&self) -> App<'a, 'b>;
+
+    async fn run<'a>(
+        &self,
+        fb: FacebookInit,
+        logger: Logger,
+        matches: &'a MononokeMatches<'a>,
+        sub_m: &'a ArgMatches<'a>,
+    ) -> Result<(), SubcommandError>;
+}
+
+/// All the registered subcommands
+pub fn subcommands() -> Vec<Box<dyn MononokeSubcommand>> {
+    vec![
+        Box::new(BookmarksSubcommand),
+        Box::new(BlobstoreScrubSubcommand),
+        Box::new(CommitStatsSubcommand),
+        Box::new(ConfigSubcommand),
+        Box::new(RedactionSubcommand),
+        Box::new(RepoLockSubcommand),
+        Box::new(SegmentedChangelogSubcommand),
+        Box::new(ValidateConfigSubcommand),
+    ]
+}
+
+/// Changeset to start from, a bookmark or a commit hash
+pub fn head_arg<'a, 'b>(default: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name(ARG_HEAD)
+        .long(ARG_HEAD)
+        .takes_value(true)
+        .default_value(default)
+        .help("bookmark or commit hash to use as head")
+}
+
+/// Maximum number of operations run at once
+pub fn concurrency_arg<'a, 'b>(default: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name(ARG_CONCURRENCY)
+        .long(ARG_CONCURRENCY)
+        .takes_value(true)
+        .default_value(default)
+        .help("maximum number of operations run at once")
+}
+
+/// File to read input from, one item per line. Stdin is read if it is not given.
+pub fn input_file_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name(ARG_INPUT_FILE)
+        .long(ARG_INPUT_FILE)
+        .takes_value(true)
+        .required(false)
+        .help("file to read input from, one item per line, instead of stdin")
+}
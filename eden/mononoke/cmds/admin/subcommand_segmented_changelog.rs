@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::Arc;
+
+use anyhow::{Context, Error};
+use async_trait::async_trait;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use futures::compat::Future01CompatExt;
+use slog::{info, Logger};
+
+use blobstore_factory::{make_metadata_sql_factory, ReadOnlyStorage};
+use cmdlib::{
+    args::{self, MononokeMatches},
+    helpers,
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use metaconfig_types::MetadataDatabaseConfig;
+use segmented_changelog::SegmentedChangelogBuilder;
+use sql_ext::facebook::MyAdmin;
+use sql_ext::replication::{NoReplicaLagMonitor, ReplicaLagMonitor};
+
+use crate::error::SubcommandError;
+use crate::subcommand::{head_arg, MononokeSubcommand, ARG_HEAD};
+
+pub const SEGMENTED_CHANGELOG: &str = "segmented-changelog";
+const SEED: &str = "seed";
+const ARG_IDMAP_VERSION: &str = "idmap-version";
+
+pub struct SegmentedChangelogSubcommand;
+
+#[async_trait]
+impl MononokeSubcommand for SegmentedChangelogSubcommand {
+    fn name(&self) -> &'static str {
+        SEGMENTED_CHANGELOG
+    }
+
+    fn build_subcommand<'a, 'b>(&self) -> App<'a, 'b> {
+        SubCommand::with_name(SEGMENTED_CHANGELOG)
+            .about("manage the segmented changelog of a repository")
+            .subcommand(
+                SubCommand::with_name(SEED)
+                    .about("build a new version of segmented changelog")
+                    .arg(
+                        Arg::with_name(ARG_IDMAP_VERSION)
+                            .long(ARG_IDMAP_VERSION)
+                            .takes_value(true)
+                            .required(false)
+                            .help("what version to label the new idmap with"),
+                    )
+                    .arg(head_arg("master")),
+            )
+    }
+
+    async fn run<'a>(
+        &self,
+        fb: FacebookInit,
+        logger: Logger,
+        matches: &'a MononokeMatches<'a>,
+        sub_m: &'a ArgMatches<'a>,
+    ) -> Result<(), SubcommandError> {
+        let ctx = CoreContext::new_with_logger(fb, logger);
+        match sub_m.subcommand() {
+            (SEED, Some(sub_m)) => seed(&ctx, matches, sub_m).await?,
+            _ => return Err(SubcommandError::InvalidArgs),
+        }
+        Ok(())
+    }
+}
+
+async fn seed<'a>(
+    ctx: &CoreContext,
+    matches: &'a MononokeMatches<'a>,
+    sub_m: &'a ArgMatches<'a>,
+) -> Result<(), Error> {
+    let idmap_version = sub_m
+        .value_of(ARG_IDMAP_VERSION)
+        .map(str::parse::<u64>)
+        .transpose()
+        .context("parsing idmap version")?;
+    let config_store = args::init_config_store(ctx.fb, ctx.logger(), matches)?;
+
+    let repo = args::open_repo(ctx.fb, ctx.logger(), matches)
+        .await
+        .context("opening repo")?;
+
+    let mysql_options = args::parse_mysql_options(matches);
+    let (_, config) = args::get_config(config_store, matches)?;
+    let storage_config = config.storage_config;
+
+    let db_address = match &storage_config.metadata {
+        MetadataDatabaseConfig::Local(_) => None,
+        MetadataDatabaseConfig::Remote(remote_config) => {
+            Some(remote_config.primary.db_address.clone())
+        }
+    };
+    let replica_lag_monitor: Arc<dyn ReplicaLagMonitor> = match db_address {
+        None => Arc::new(NoReplicaLagMonitor()),
+        Some(address) => {
+            let my_admin = MyAdmin::new(ctx.fb).context("building myadmin client")?;
+            Arc::new(my_admin.single_shard_lag_monitor(address))
+        }
+    };
+
+    let sql_factory = make_metadata_sql_factory(
+        ctx.fb,
+        storage_config.metadata,
+        mysql_options,
+        ReadOnlyStorage(false),
+        ctx.logger(),
+    )
+    .await
+    .context("constructing metadata sql factory")?;
+
+    let mut builder = sql_factory
+        .open::<SegmentedChangelogBuilder>()
+        .await
+        .context("constructing segmented changelog builder")?;
+    if let Some(idmap_version) = idmap_version {
+        builder = builder.with_idmap_version(idmap_version);
+    }
+
+    let seeder = builder
+        .with_blobrepo(&repo)
+        .with_replica_lag_monitor(replica_lag_monitor)
+        .build_seeder(ctx)
+        .await
+        .context("building SegmentedChangelogSeeder")?;
+
+    let head_arg = sub_m.value_of(ARG_HEAD).expect("head has a default");
+    let head = helpers::csid_resolve(ctx.clone(), repo.clone(), head_arg)
+        .compat()
+        .await
+        .with_context(|| format!("resolving head csid for '{}'", head_arg))?;
+    info!(ctx.logger(), "using '{}' for head", head);
+
+    seeder
+        .run(ctx, head)
+        .await
+        .context("seeding segmented changelog")?;
+
+    info!(
+        ctx.logger(),
+        "successfully finished seeding SegmentedChangelog for repository '{}'",
+        repo.name(),
+    );
+    Ok(())
+}
@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{bail, Error};
+use async_trait::async_trait;
+use clap::{App, ArgMatches, SubCommand};
+use slog::{info, Logger};
+
+use cmdlib::args::{self, MononokeMatches};
+use fbinit::FacebookInit;
+
+use crate::error::SubcommandError;
+use crate::subcommand::MononokeSubcommand;
+
+pub const VALIDATE_CONFIG: &str = "validate-config";
+
+pub struct ValidateConfigSubcommand;
+
+#[async_trait]
+impl MononokeSubcommand for ValidateConfigSubcommand {
+    fn name(&self) -> &'static str {
+        VALIDATE_CONFIG
+    }
+
+    fn build_subcommand<'a, 'b>(&self) -> App<'a, 'b> {
+        SubCommand::with_name(VALIDATE_CONFIG).about(concat!(
+            "check the repo and storage configs, and the references between them, ",
+            "and print every problem found, with the file it is in",
+        ))
+    }
+
+    async fn run<'a>(
+        &self,
+        fb: FacebookInit,
+        logger: Logger,
+        matches: &'a MononokeMatches<'a>,
+        _sub_m: &'a ArgMatches<'a>,
+    ) -> Result<(), SubcommandError> {
+        validate(fb, logger, matches)?;
+        Ok(())
+    }
+}
+
+fn validate<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'a>,
+) -> Result<(), Error> {
+    let config_store = args::init_config_store(fb, &logger, matches)?;
+    let problems = args::validate_configs(config_store, matches)?;
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if !problems.is_empty() {
+        bail!("{} problems found in configs", problems.len());
+    }
+    info!(logger, "configs are valid");
+    Ok(())
+}
@@ -0,0 +1,109 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use futures::compat::Future01CompatExt;
+use mononoke_types::{ChangesetId, RepositoryId, Timestamp};
+use sql::queries;
+use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
+use sql_ext::SqlConnections;
+
+/// How far a backfill of a repository got, so that an interrupted backfill can resume from there
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackfillCheckpoint {
+    /// All the changesets up to and including this one, in generation order, are derived
+    pub last_done_cs_id: ChangesetId,
+    pub done_count: u64,
+    pub update_timestamp: Timestamp,
+}
+
+pub struct SqlBackfillCheckpoints {
+    connections: SqlConnections,
+}
+
+impl SqlConstruct for SqlBackfillCheckpoints {
+    const LABEL: &'static str = "derived_data_backfill_checkpoints";
+
+    const CREATION_QUERY: &'static str =
+        include_str!("schemas/sqlite-derived_data_backfill_checkpoints.sql");
+
+    fn from_sql_connections(connections: SqlConnections) -> Self {
+        Self { connections }
+    }
+}
+
+impl SqlConstructFromMetadataDatabaseConfig for SqlBackfillCheckpoints {}
+
+impl SqlBackfillCheckpoints {
+    pub async fn load(
+        &self,
+        repo_id: RepositoryId,
+        checkpoint_name: &str,
+    ) -> Result<Option<BackfillCheckpoint>, Error> {
+        let rows = SelectCheckpoint::query(
+            &self.connections.read_master_connection,
+            &repo_id,
+            &checkpoint_name,
+        )
+        .compat()
+        .await?;
+
+        Ok(rows.into_iter().next().map(|row| BackfillCheckpoint {
+            last_done_cs_id: row.0,
+            done_count: row.1,
+            update_timestamp: row.2,
+        }))
+    }
+
+    pub async fn save(
+        &self,
+        repo_id: RepositoryId,
+        checkpoint_name: &str,
+        checkpoint: &BackfillCheckpoint,
+    ) -> Result<(), Error> {
+        let checkpoint_name = checkpoint_name.to_string();
+        ReplaceCheckpoint::query(
+            &self.connections.write_connection,
+            &[(
+                &repo_id,
+                &checkpoint_name,
+                &checkpoint.last_done_cs_id,
+                &checkpoint.done_count,
+                &checkpoint.update_timestamp,
+            )],
+        )
+        .compat()
+        .await?;
+        Ok(())
+    }
+}
+
+queries! {
+    read SelectCheckpoint(
+        repo_id: RepositoryId,
+        checkpoint_name: &str,
+    ) -> (ChangesetId, u64, Timestamp) {
+        "SELECT last_done_cs_id, done_count, update_timestamp
+        FROM derived_data_backfill_checkpoints
+        WHERE repo_id={repo_id} AND checkpoint_name={checkpoint_name}"
+    }
+
+    write ReplaceCheckpoint(
+        values: (
+            repo_id: RepositoryId,
+            checkpoint_name: String,
+            last_done_cs_id: ChangesetId,
+            done_count: u64,
+            update_timestamp: Timestamp,
+        ),
+    ) {
+        none,
+        "REPLACE INTO derived_data_backfill_checkpoints
+         (repo_id, checkpoint_name, last_done_cs_id, done_count, update_timestamp)
+         VALUES {values}"
+    }
+}
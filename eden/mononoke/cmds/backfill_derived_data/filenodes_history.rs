@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Backfill of the filenodes of repos which were imported without them, e.g. by blobimport with
+//! the filenodes derived data type excluded, so that file history (linknodes and copy info) is
+//! available after the import.
+//!
+//! Derivation of filenodes only looks at changesets whose root filenode is missing, which is not
+//! enough when the history of some files was lost while the root filenodes were kept. Instead,
+//! filenodes are generated for every public changeset from the diff of its manifest against the
+//! manifests of its parents. Changesets are processed in generation order, and existing filenodes
+//! are never overwritten, so each filenode gets the linknode of the first changeset that
+//! introduced it.
+
+use anyhow::{anyhow, Result};
+use blobrepo::BlobRepo;
+use context::CoreContext;
+use derived_data_filenodes::generate_all_filenodes;
+use filenodes::{FilenodeResult, PreparedFilenode};
+use futures::{
+    compat::Future01CompatExt,
+    stream::{self, StreamExt, TryStreamExt},
+};
+use mononoke_types::RepoPath;
+use slog::info;
+use std::time::Instant;
+
+use crate::orchestrator::{
+    fetch_public_changesets, resume_position, save_checkpoint, BackfillCheckpointer, Progress,
+};
+
+/// Name of the checkpoint of filenodes history backfills, unless another one is given
+pub const DEFAULT_CHECKPOINT_NAME: &str = "filenodes_history";
+
+#[derive(Clone, Copy, Debug)]
+pub struct BackfillFilenodesOptions {
+    /// Number of changesets processed between checkpoints
+    pub batch_size: usize,
+    /// Number of changesets whose filenodes are generated at once
+    pub concurrency: usize,
+}
+
+/// Add the filenodes of all public changesets of the repo which are missing
+pub async fn backfill_filenodes(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    checkpointer: Option<BackfillCheckpointer<'_>>,
+    options: BackfillFilenodesOptions,
+) -> Result<()> {
+    let changesets = fetch_public_changesets(ctx, repo).await?;
+    let start = resume_position(ctx, repo, &changesets, checkpointer.as_ref()).await?;
+
+    let mut progress = Progress::new(changesets.len(), start);
+    info!(
+        ctx.logger(),
+        "backfilling filenodes of {} changesets out of {}",
+        changesets.len() - start,
+        changesets.len(),
+    );
+
+    for chunk in changesets[start..].chunks(options.batch_size) {
+        let timestamp = Instant::now();
+
+        // Filenodes are generated concurrently, but added in generation order, so that the
+        // earliest changeset introducing a filenode is its linknode
+        let mut generated = stream::iter(chunk.iter().copied())
+            .map(|cs_id| generate_all_filenodes(ctx, repo, cs_id))
+            .buffered(options.concurrency);
+        while let Some(filenodes) = generated.try_next().await? {
+            add_filenodes(ctx, repo, filenodes).await?;
+        }
+        progress.record(chunk.len());
+
+        if let (Some(checkpointer), Some(last)) = (&checkpointer, chunk.last()) {
+            save_checkpoint(repo, checkpointer, *last, progress.done()).await?;
+        }
+
+        progress.log(ctx, chunk.len(), timestamp.elapsed());
+    }
+    Ok(())
+}
+
+/// Add the filenodes of a changeset. As with derivation, the root filenode is added last, as it
+/// marks the filenodes of the changeset as present.
+async fn add_filenodes(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    filenodes: Vec<PreparedFilenode>,
+) -> Result<()> {
+    let (roots, non_roots): (Vec<_>, Vec<_>) = filenodes
+        .into_iter()
+        .partition(|filenode| filenode.path == RepoPath::RootPath);
+
+    for batch in vec![non_roots, roots] {
+        if batch.is_empty() {
+            continue;
+        }
+        let res = repo
+            .get_filenodes()
+            .add_filenodes(ctx.clone(), batch, repo.get_repoid())
+            .compat()
+            .await?;
+        if let FilenodeResult::Disabled = res {
+            return Err(anyhow!("filenodes are disabled for this repo"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint::SqlBackfillCheckpoints;
+    use blobrepo_hg::BlobRepoHg;
+    use fbinit::FacebookInit;
+    use filenodes::FilenodeRangeResult;
+    use fixtures::linear;
+    use mononoke_types::MPath;
+    use sql_construct::SqlConstruct;
+    use tests_utils::resolve_cs_id;
+
+    #[fbinit::test]
+    async fn test_backfill_filenodes(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = linear::getrepo(fb).await;
+        let master = resolve_cs_id(&ctx, &repo, "master").await?;
+        repo.get_phases()
+            .add_reachable_as_public(ctx.clone(), vec![master])
+            .await?;
+
+        let checkpoints = SqlBackfillCheckpoints::with_sqlite_in_memory()?;
+        let options = BackfillFilenodesOptions {
+            batch_size: 3,
+            concurrency: 10,
+        };
+        backfill_filenodes(
+            &ctx,
+            &repo,
+            Some(BackfillCheckpointer {
+                checkpoints: &checkpoints,
+                name: DEFAULT_CHECKPOINT_NAME.to_string(),
+            }),
+            options,
+        )
+        .await?;
+
+        let changesets = fetch_public_changesets(&ctx, &repo).await?;
+        let checkpoint = checkpoints
+            .load(repo.get_repoid(), DEFAULT_CHECKPOINT_NAME)
+            .await?
+            .expect("checkpoint is saved");
+        assert_eq!(checkpoint.last_done_cs_id, master);
+        assert_eq!(checkpoint.done_count, changesets.len() as u64);
+
+        // The file "1" is only added by the root commit, which is its linknode
+        let root = repo
+            .get_hg_from_bonsai_changeset(ctx.clone(), changesets[0])
+            .await?;
+        let path = RepoPath::FilePath(MPath::new("1")?);
+        let history = match repo
+            .get_filenodes()
+            .get_all_filenodes_maybe_stale(ctx.clone(), &path, repo.get_repoid(), None)
+            .compat()
+            .await?
+        {
+            FilenodeRangeResult::Present(history) => history,
+            _ => panic!("filenodes are present"),
+        };
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].linknode, root);
+        Ok(())
+    }
+}
@@ -52,7 +52,10 @@ use std::{
 };
 use time_ext::DurationExt;
 
+mod checkpoint;
 mod dry_run;
+mod filenodes_history;
+mod orchestrator;
 mod slice;
 mod warmup;
 
@@ -80,15 +83,21 @@ const ARG_SLICED: &str = "sliced";
 const ARG_SLICE_SIZE: &str = "slice-size";
 const ARG_BACKFILL: &str = "backfill";
 const ARG_GAP_SIZE: &str = "gap-size";
+const ARG_CONCURRENCY: &str = "concurrency";
+const ARG_CHECKPOINT_NAME: &str = "checkpoint-name";
 
 const SUBCOMMAND_BACKFILL: &str = "backfill";
 const SUBCOMMAND_BACKFILL_ALL: &str = "backfill-all";
+const SUBCOMMAND_BACKFILL_REPO: &str = "backfill-repo";
+const SUBCOMMAND_BACKFILL_FILENODES: &str = "backfill-filenodes";
 const SUBCOMMAND_TAIL: &str = "tail";
 const SUBCOMMAND_PREFETCH_COMMITS: &str = "prefetch-commits";
 const SUBCOMMAND_SINGLE: &str = "single";
 
 const DEFAULT_BATCH_SIZE_STR: &str = "4096";
 const DEFAULT_SLICE_SIZE_STR: &str = "20000";
+const DEFAULT_CONCURRENCY: usize = 100;
+const DEFAULT_CONCURRENCY_STR: &str = "100";
 
 /// Derived data types that are permitted to access redacted files. This list
 /// should be limited to those data types that need access to the content of
@@ -340,6 +349,81 @@ fn main(fb: FacebookInit) -> Result<()> {
                         .takes_value(true)
                         .help("size of gap to leave in derived data types that support gaps"),
                 ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUBCOMMAND_BACKFILL_REPO)
+                .about(concat!(
+                    "backfill derived data for all public commits of the repo in batches, ",
+                    "resuming from the last checkpoint",
+                ))
+                .arg(
+                    Arg::with_name(ARG_DERIVED_DATA_TYPE)
+                        .possible_values(POSSIBLE_DERIVED_TYPES)
+                        .required(false)
+                        .takes_value(true)
+                        .multiple(true)
+                        .help(concat!(
+                            "derived data type for which backfill will be run, ",
+                            "all enabled and backfilling types if not specified",
+                        )),
+                )
+                .arg(
+                    Arg::with_name(ARG_BATCH_SIZE)
+                        .long(ARG_BATCH_SIZE)
+                        .default_value(DEFAULT_BATCH_SIZE_STR)
+                        .help("number of changesets derived between checkpoints"),
+                )
+                .arg(
+                    Arg::with_name(ARG_CONCURRENCY)
+                        .long(ARG_CONCURRENCY)
+                        .default_value(DEFAULT_CONCURRENCY_STR)
+                        .help("maximum number of batches derived at once, across data types"),
+                )
+                .arg(
+                    Arg::with_name(ARG_PARALLEL)
+                        .long(ARG_PARALLEL)
+                        .help("derive commits within a batch in parallel"),
+                )
+                .arg(
+                    Arg::with_name(ARG_GAP_SIZE)
+                        .long(ARG_GAP_SIZE)
+                        .takes_value(true)
+                        .help("size of gap to leave in derived data types that support gaps"),
+                )
+                .arg(
+                    Arg::with_name(ARG_CHECKPOINT_NAME)
+                        .long(ARG_CHECKPOINT_NAME)
+                        .takes_value(true)
+                        .help(concat!(
+                            "name of the checkpoint to resume from and update, ",
+                            "derived from the data types if not specified",
+                        )),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUBCOMMAND_BACKFILL_FILENODES)
+                .about(concat!(
+                    "add the missing filenodes of all public commits of the repo, e.g. after an ",
+                    "import without filenodes, resuming from the last checkpoint",
+                ))
+                .arg(
+                    Arg::with_name(ARG_BATCH_SIZE)
+                        .long(ARG_BATCH_SIZE)
+                        .default_value(DEFAULT_BATCH_SIZE_STR)
+                        .help("number of changesets processed between checkpoints"),
+                )
+                .arg(
+                    Arg::with_name(ARG_CONCURRENCY)
+                        .long(ARG_CONCURRENCY)
+                        .default_value(DEFAULT_CONCURRENCY_STR)
+                        .help("number of changesets whose filenodes are generated at once"),
+                )
+                .arg(
+                    Arg::with_name(ARG_CHECKPOINT_NAME)
+                        .long(ARG_CHECKPOINT_NAME)
+                        .default_value(filenodes_history::DEFAULT_CHECKPOINT_NAME)
+                        .help("name of the checkpoint to resume from and update"),
+                ),
         );
     let matches = app.get_matches();
     let (_, logger, runtime) = args::init_mononoke(fb, &matches)?;
@@ -406,6 +490,94 @@ async fn run_subcmd<'a>(
             )
             .await
         }
+        (SUBCOMMAND_BACKFILL_REPO, Some(sub_m)) => {
+            let repo = args::open_repo_unredacted(fb, logger, matches).await?;
+            let config_store = args::init_config_store(fb, logger, matches)?;
+            let derived_data_types: BTreeSet<String> =
+                sub_m.values_of(ARG_DERIVED_DATA_TYPE).map_or_else(
+                    || {
+                        let config = repo.get_derived_data_config();
+                        config
+                            .enabled
+                            .types
+                            .union(&config.backfilling.types)
+                            .cloned()
+                            .collect()
+                    },
+                    |names| names.map(ToString::to_string).collect(),
+                );
+            info!(ctx.logger(), "derived data types: {:?}", derived_data_types);
+            let derivers = derived_data_types
+                .iter()
+                .map(|name| derived_data_utils_for_backfill(&repo, name.as_str()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let options = orchestrator::BackfillRepoOptions {
+                batch_size: sub_m
+                    .value_of(ARG_BATCH_SIZE)
+                    .expect("batch-size must be set")
+                    .parse::<usize>()?,
+                concurrency: sub_m
+                    .value_of(ARG_CONCURRENCY)
+                    .expect("concurrency must be set")
+                    .parse::<usize>()?,
+                parallel: sub_m.is_present(ARG_PARALLEL),
+                gap_size: sub_m
+                    .value_of(ARG_GAP_SIZE)
+                    .map(str::parse::<usize>)
+                    .transpose()?,
+            };
+            let checkpoints =
+                args::open_sql::<checkpoint::SqlBackfillCheckpoints>(fb, config_store, matches)
+                    .await?;
+            let name = sub_m.value_of(ARG_CHECKPOINT_NAME).map_or_else(
+                || orchestrator::default_checkpoint_name(&derivers),
+                ToString::to_string,
+            );
+
+            orchestrator::backfill_repo(
+                &ctx,
+                &repo,
+                &derivers,
+                Some(orchestrator::BackfillCheckpointer {
+                    checkpoints: &checkpoints,
+                    name,
+                }),
+                options,
+            )
+            .await
+        }
+        (SUBCOMMAND_BACKFILL_FILENODES, Some(sub_m)) => {
+            let repo = args::open_repo(fb, logger, matches).await?;
+            let config_store = args::init_config_store(fb, logger, matches)?;
+            let options = filenodes_history::BackfillFilenodesOptions {
+                batch_size: sub_m
+                    .value_of(ARG_BATCH_SIZE)
+                    .expect("batch-size must be set")
+                    .parse::<usize>()?,
+                concurrency: sub_m
+                    .value_of(ARG_CONCURRENCY)
+                    .expect("concurrency must be set")
+                    .parse::<usize>()?,
+            };
+            let checkpoints =
+                args::open_sql::<checkpoint::SqlBackfillCheckpoints>(fb, config_store, matches)
+                    .await?;
+            let name = sub_m
+                .value_of(ARG_CHECKPOINT_NAME)
+                .expect("checkpoint-name must be set")
+                .to_string();
+
+            filenodes_history::backfill_filenodes(
+                &ctx,
+                &repo,
+                Some(orchestrator::BackfillCheckpointer {
+                    checkpoints: &checkpoints,
+                    name,
+                }),
+                options,
+            )
+            .await
+        }
         (SUBCOMMAND_BACKFILL, Some(sub_m)) => {
             let derived_data_type = sub_m
                 .value_of(ARG_DERIVED_DATA_TYPE)
@@ -990,6 +1162,35 @@ async fn tail_batch_iteration(
     parallel: bool,
     gap_size: Option<usize>,
 ) -> Result<()> {
+    let size = derive_heads(
+        ctx,
+        repo,
+        derive_utils,
+        heads,
+        batch_size,
+        DEFAULT_CONCURRENCY,
+        parallel,
+        gap_size,
+    )
+    .await?;
+    if size == 0 {
+        tokio::time::delay_for(Duration::from_millis(250)).await;
+    }
+    Ok(())
+}
+
+/// Derive all the underived ancestors of `heads`, with at most `concurrency` batches being
+/// derived at once. Returns the number of derivations, i.e. changesets times data types.
+async fn derive_heads(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    derive_utils: &[Arc<dyn DerivedUtils>],
+    heads: Vec<ChangesetId>,
+    batch_size: usize,
+    concurrency: usize,
+    parallel: bool,
+    gap_size: Option<usize>,
+) -> Result<usize> {
     let derive_graph = derived_data_utils::build_derive_graph(
         ctx,
         repo,
@@ -1003,14 +1204,12 @@ async fn tail_batch_iteration(
     .await?;
 
     let size = derive_graph.size();
-    if size == 0 {
-        tokio::time::delay_for(Duration::from_millis(250)).await;
-    } else {
+    if size != 0 {
         info!(ctx.logger(), "deriving data {}", size);
         // We are using `bounded_traversal_dag` directly instead of `DeriveGraph::derive`
         // so we could use `warmup::warmup` on each node.
         bounded_traversal::bounded_traversal_dag(
-            100,
+            concurrency,
             derive_graph,
             |node| async move {
                 let deps = node.dependencies.clone();
@@ -1052,7 +1251,7 @@ async fn tail_batch_iteration(
         .ok_or_else(|| anyhow!("derive graph contains a cycle"))?;
     }
 
-    Ok(())
+    Ok(size)
 }
 
 async fn tail_one_iteration(
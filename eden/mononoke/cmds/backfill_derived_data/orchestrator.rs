@@ -0,0 +1,301 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Backfill of derived data for all the public changesets of a repository. Changesets are
+//! derived in generation order, one batch at a time, and the position is checkpointed after each
+//! batch so that an interrupted backfill resumes where it stopped.
+
+use anyhow::Result;
+use blobrepo::BlobRepo;
+use bulkops::{Direction, PublicChangesetBulkFetch};
+use changesets::ChangesetEntry;
+use context::CoreContext;
+use derived_data_utils::DerivedUtils;
+use futures::stream::TryStreamExt;
+use mononoke_types::{ChangesetId, Timestamp};
+use slog::{info, warn};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::checkpoint::{BackfillCheckpoint, SqlBackfillCheckpoints};
+use crate::{derive_heads, truncate_duration};
+
+#[derive(Clone, Copy, Debug)]
+pub struct BackfillRepoOptions {
+    /// Number of changesets derived between checkpoints
+    pub batch_size: usize,
+    /// Maximum number of batches, across data types, derived at once
+    pub concurrency: usize,
+    pub parallel: bool,
+    pub gap_size: Option<usize>,
+}
+
+/// Where the checkpoints of a backfill are kept. Backfills of different sets of data types
+/// should use different names.
+pub struct BackfillCheckpointer<'a> {
+    pub checkpoints: &'a SqlBackfillCheckpoints,
+    pub name: String,
+}
+
+/// Default name of the checkpoint of a backfill of these data types
+pub fn default_checkpoint_name(derivers: &[Arc<dyn DerivedUtils>]) -> String {
+    let mut names: Vec<_> = derivers.iter().map(|deriver| deriver.name()).collect();
+    names.sort();
+    names.join(",")
+}
+
+/// Rate and estimated time of arrival of a backfill
+pub(crate) struct Progress {
+    total: usize,
+    done: usize,
+    done_at_start: usize,
+    started: Instant,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize, done: usize) -> Self {
+        Self {
+            total,
+            done,
+            done_at_start: done,
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, count: usize) {
+        self.done += count;
+    }
+
+    pub(crate) fn done(&self) -> usize {
+        self.done
+    }
+
+    /// Changesets per second since the start of this run
+    fn speed(&self) -> f32 {
+        (self.done - self.done_at_start) as f32 / self.started.elapsed().as_secs_f32()
+    }
+
+    fn estimate(&self) -> Option<Duration> {
+        let done = self.done - self.done_at_start;
+        if done == 0 {
+            return None;
+        }
+        let remaining = self.total.saturating_sub(self.done);
+        Some(
+            self.started
+                .elapsed()
+                .mul_f32(remaining as f32 / done as f32),
+        )
+    }
+
+    /// Log the progress after a batch of `count` changesets which took `elapsed`
+    pub(crate) fn log(&self, ctx: &CoreContext, count: usize, elapsed: Duration) {
+        info!(
+            ctx.logger(),
+            "{}/{} ({} in {}) estimate:{} speed:{:.2}/s",
+            self.done,
+            self.total,
+            count,
+            humantime::format_duration(truncate_duration(elapsed)),
+            self.estimate().map_or_else(
+                || "unknown".to_string(),
+                |estimate| humantime::format_duration(truncate_duration(estimate)).to_string()
+            ),
+            self.speed(),
+        );
+    }
+}
+
+/// Public changesets of the repo, parents before children
+pub(crate) async fn fetch_public_changesets(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+) -> Result<Vec<ChangesetId>> {
+    let fetcher = PublicChangesetBulkFetch::new(
+        repo.get_repoid(),
+        repo.get_changesets_object(),
+        repo.get_phases(),
+    );
+    let mut entries: Vec<ChangesetEntry> = fetcher
+        .fetch(ctx, Direction::OldestFirst)
+        .try_collect()
+        .await?;
+    entries.sort_by_key(|entry| entry.gen);
+    Ok(entries.into_iter().map(|entry| entry.cs_id).collect())
+}
+
+/// Position in `changesets` of the first changeset after the checkpoint, or 0 if there is no
+/// checkpoint to resume from
+pub(crate) async fn resume_position(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    changesets: &[ChangesetId],
+    checkpointer: Option<&BackfillCheckpointer<'_>>,
+) -> Result<usize> {
+    let checkpoint = match checkpointer {
+        Some(checkpointer) => {
+            checkpointer
+                .checkpoints
+                .load(repo.get_repoid(), &checkpointer.name)
+                .await?
+        }
+        None => None,
+    };
+    match checkpoint {
+        Some(checkpoint) => {
+            match changesets
+                .iter()
+                .position(|cs_id| *cs_id == checkpoint.last_done_cs_id)
+            {
+                Some(position) => {
+                    info!(
+                        ctx.logger(),
+                        "resuming after {} ({} changesets done)",
+                        checkpoint.last_done_cs_id,
+                        position + 1,
+                    );
+                    Ok(position + 1)
+                }
+                None => {
+                    warn!(
+                        ctx.logger(),
+                        "checkpointed changeset {} is not public, starting from the beginning",
+                        checkpoint.last_done_cs_id,
+                    );
+                    Ok(0)
+                }
+            }
+        }
+        None => Ok(0),
+    }
+}
+
+/// Save the position of a backfill after `last_done_cs_id`
+pub(crate) async fn save_checkpoint(
+    repo: &BlobRepo,
+    checkpointer: &BackfillCheckpointer<'_>,
+    last_done_cs_id: ChangesetId,
+    done_count: usize,
+) -> Result<()> {
+    let checkpoint = BackfillCheckpoint {
+        last_done_cs_id,
+        done_count: done_count as u64,
+        update_timestamp: Timestamp::now(),
+    };
+    checkpointer
+        .checkpoints
+        .save(repo.get_repoid(), &checkpointer.name, &checkpoint)
+        .await
+}
+
+/// Derive the data types of `derivers`, and the types they depend on, for all public changesets
+/// of the repo. Changesets which become public while the backfill runs may be left to the tailer.
+pub async fn backfill_repo(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    derivers: &[Arc<dyn DerivedUtils>],
+    checkpointer: Option<BackfillCheckpointer<'_>>,
+    options: BackfillRepoOptions,
+) -> Result<()> {
+    let changesets = fetch_public_changesets(ctx, repo).await?;
+
+    let start = resume_position(ctx, repo, &changesets, checkpointer.as_ref()).await?;
+
+    let mut progress = Progress::new(changesets.len(), start);
+    info!(
+        ctx.logger(),
+        "backfilling {} changesets out of {}",
+        changesets.len() - start,
+        changesets.len(),
+    );
+
+    for chunk in changesets[start..].chunks(options.batch_size) {
+        let timestamp = Instant::now();
+        derive_heads(
+            ctx,
+            repo,
+            derivers,
+            chunk.to_vec(),
+            options.batch_size,
+            options.concurrency,
+            options.parallel,
+            options.gap_size,
+        )
+        .await?;
+        progress.record(chunk.len());
+
+        if let (Some(checkpointer), Some(last)) = (&checkpointer, chunk.last()) {
+            save_checkpoint(repo, checkpointer, *last, progress.done()).await?;
+        }
+
+        progress.log(ctx, chunk.len(), timestamp.elapsed());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use derived_data::BonsaiDerived;
+    use derived_data_utils::derived_data_utils_for_backfill;
+    use fbinit::FacebookInit;
+    use fixtures::linear;
+    use sql_construct::SqlConstruct;
+    use tests_utils::resolve_cs_id;
+    use unodes::RootUnodeManifestId;
+
+    #[fbinit::test]
+    async fn test_backfill_repo(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = linear::getrepo(fb).await;
+        let master = resolve_cs_id(&ctx, &repo, "master").await?;
+        repo.get_phases()
+            .add_reachable_as_public(ctx.clone(), vec![master])
+            .await?;
+
+        let derivers = vec![derived_data_utils_for_backfill(
+            &repo,
+            RootUnodeManifestId::NAME,
+        )?];
+        let checkpoints = SqlBackfillCheckpoints::with_sqlite_in_memory()?;
+        let name = default_checkpoint_name(&derivers);
+        let options = BackfillRepoOptions {
+            batch_size: 3,
+            concurrency: 10,
+            parallel: false,
+            gap_size: None,
+        };
+
+        assert!(!RootUnodeManifestId::is_derived(&ctx, &repo, &master).await?);
+        backfill_repo(
+            &ctx,
+            &repo,
+            &derivers,
+            Some(BackfillCheckpointer {
+                checkpoints: &checkpoints,
+                name: name.clone(),
+            }),
+            options,
+        )
+        .await?;
+        assert!(RootUnodeManifestId::is_derived(&ctx, &repo, &master).await?);
+
+        let changesets = fetch_public_changesets(&ctx, &repo).await?;
+        let checkpoint = checkpoints
+            .load(repo.get_repoid(), &name)
+            .await?
+            .expect("checkpoint is saved");
+        assert_eq!(checkpoint.last_done_cs_id, master);
+        assert_eq!(checkpoint.done_count, changesets.len() as u64);
+
+        // Other backfills have their own checkpoints
+        assert_eq!(checkpoints.load(repo.get_repoid(), "blame").await?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,15 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+CREATE TABLE derived_data_backfill_checkpoints (
+  repo_id INTEGER NOT NULL,
+  checkpoint_name VARCHAR(255) NOT NULL,
+  last_done_cs_id VARBINARY(32) NOT NULL,
+  done_count BIGINT NOT NULL,
+  update_timestamp BIGINT NOT NULL,
+  UNIQUE (repo_id, checkpoint_name)
+);
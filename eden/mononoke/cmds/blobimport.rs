@@ -9,7 +9,7 @@
 
 use anyhow::{bail, format_err, Context, Error, Result};
 use ascii::AsciiString;
-use blobimport_lib;
+use blobimport_lib::{self, SqlBlobimportCheckpoints};
 use blobrepo::BlobRepo;
 use bonsai_globalrev_mapping::SqlBonsaiGlobalrevMapping;
 use clap::Arg;
@@ -45,6 +45,8 @@ const ARG_DERIVED_DATA_TYPE: &str = "derived-data-type";
 const ARG_EXCLUDE_DERIVED_DATA_TYPE: &str = "exclude-derived-data-type";
 const ARG_FIND_ALREADY_IMPORTED_REV_ONLY: &str = "find-already-imported-rev-only";
 const BACKUP_FROM_REPO_ID: &str = "backup-from-repo-id";
+const ARG_CHECKPOINT: &str = "checkpoint";
+const ARG_RESUME: &str = "resume";
 
 fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
     args::MononokeAppBuilder::new("revlog to blob importer")
@@ -128,6 +130,23 @@ fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
             .value_name("ID")
             .help("numeric ID of backup source of truth mononoke repository (used only for backup jobs to sync bonsai changesets)"),
         )
+        .arg(
+            Arg::with_name(ARG_CHECKPOINT)
+                .long(ARG_CHECKPOINT)
+                .takes_value(false)
+                .required(false)
+                .help("Save the progress of the import in the metadata database as it goes, \
+                      so that it can be resumed with --resume if it is interrupted."),
+        )
+        .arg(
+            Arg::with_name(ARG_RESUME)
+                .long(ARG_RESUME)
+                .takes_value(false)
+                .required(false)
+                .conflicts_with_all(&["changeset", "skip"])
+                .help("Resume from the checkpoint of the previous import of this repo, if any, \
+                      rather than importing from the beginning. Implies --checkpoint."),
+        )
 }
 
 fn parse_fixed_parent_order<P: AsRef<Path>>(
@@ -230,7 +249,7 @@ async fn run_blobimport<'a>(
         Some(hash) => Some(HgNodeHash::from_str(hash)?),
     };
 
-    let skip = if !matches.is_present("skip") {
+    let mut skip = if !matches.is_present("skip") {
         None
     } else {
         Some(args::get_usize(matches, "skip", 0))
@@ -333,6 +352,32 @@ async fn run_blobimport<'a>(
     )
     .await?;
 
+    let resume = matches.is_present(ARG_RESUME);
+    let checkpoints = if resume || matches.is_present(ARG_CHECKPOINT) {
+        let checkpoints =
+            args::open_sql::<SqlBlobimportCheckpoints>(fb, config_store, matches).await?;
+        if resume {
+            match checkpoints.load(blobrepo.get_repoid()).await? {
+                Some(checkpoint) => {
+                    info!(
+                        logger,
+                        "resuming after revision {} (bookmarks imported: {})",
+                        checkpoint.last_imported_rev.as_u32(),
+                        checkpoint.bookmarks_imported,
+                    );
+                    skip = Some(checkpoint.resume_skip());
+                }
+                None => info!(
+                    logger,
+                    "no checkpoint to resume from, importing from the beginning"
+                ),
+            }
+        }
+        Some(Arc::new(checkpoints))
+    } else {
+        None
+    };
+
     let origin_repo = if matches.is_present(BACKUP_FROM_REPO_ID) {
         let repo_id = args::get_repo_id_from_value(config_store, matches, BACKUP_FROM_REPO_ID)?;
         Some(args::open_repo_with_repo_id(fb, &logger, repo_id, matches).await?)
@@ -365,6 +410,7 @@ async fn run_blobimport<'a>(
             small_repo_id,
             derived_data_types,
             origin_repo,
+            checkpoints,
         };
 
         let maybe_latest_imported_rev = if find_latest_imported_rev_only {
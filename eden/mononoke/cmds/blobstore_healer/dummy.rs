@@ -11,7 +11,7 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use blobstore::{Blobstore, BlobstoreGetData};
-use blobstore_sync_queue::{BlobstoreSyncQueue, BlobstoreSyncQueueEntry};
+use blobstore_sync_queue::{BlobstoreSyncQueue, BlobstoreSyncQueueDepth, BlobstoreSyncQueueEntry};
 use context::CoreContext;
 use metaconfig_types::MultiplexId;
 use mononoke_types::{BlobstoreBytes, DateTime};
@@ -95,6 +95,41 @@ impl<Q: BlobstoreSyncQueue> BlobstoreSyncQueue for DummyBlobstoreSyncQueue<Q> {
             .await
     }
 
+    async fn iter_by_access_count<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key_like: Option<&'a str>,
+        multiplex_id: MultiplexId,
+        older_than: DateTime,
+        limit: usize,
+    ) -> Result<Vec<BlobstoreSyncQueueEntry>> {
+        self.inner
+            .iter_by_access_count(ctx, key_like, multiplex_id, older_than, limit)
+            .await
+    }
+
+    async fn record_access<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        entries: &'a [BlobstoreSyncQueueEntry],
+    ) -> Result<()> {
+        let entries: Vec<_> = entries.iter().map(|e| format!("{:?}", e)).collect();
+        info!(
+            self.logger,
+            "I would have recorded access to {}",
+            entries.join(",\n")
+        );
+        Ok(())
+    }
+
+    async fn depth<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        multiplex_id: MultiplexId,
+    ) -> Result<BlobstoreSyncQueueDepth> {
+        self.inner.depth(ctx, multiplex_id).await
+    }
+
     async fn del<'a>(
         &'a self,
         _ctx: &'a CoreContext,
@@ -19,16 +19,18 @@ use metaconfig_types::{BlobstoreId, MultiplexId};
 use mononoke_types::{BlobstoreBytes, DateTime};
 use rand::{thread_rng, Rng};
 use slog::{debug, info, warn};
+use stats::prelude::*;
 use std::{
+    cmp::Reverse,
     collections::{HashMap, HashSet},
     future::Future,
     iter::Sum,
     ops::Add,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc,
+        Arc, Mutex,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[cfg(test)]
@@ -37,6 +39,14 @@ mod tests;
 const MIN_FETCH_FAILURE_DELAY: Duration = Duration::from_millis(1);
 const MAX_FETCH_FAILURE_DELAY: Duration = Duration::from_millis(100);
 const DEFAULT_BLOB_SIZE_BYTES: u64 = 1024;
+/// Counting the queue entries is expensive for the database, so do it at most this often
+const QUEUE_DEPTH_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+define_stats! {
+    prefix = "mononoke.blobstore_healer";
+    queue_depth: dynamic_singleton_counter("{}.queue_depth", (multiplex_id: MultiplexId)),
+    healing_lag_secs: dynamic_singleton_counter("{}.healing_lag_secs", (multiplex_id: MultiplexId)),
+}
 
 pub struct Healer {
     blobstore_sync_queue_limit: usize,
@@ -47,6 +57,8 @@ pub struct Healer {
     multiplex_id: MultiplexId,
     blobstore_key_like: Option<String>,
     drain_only: bool,
+    popular_first: bool,
+    last_depth_report: Mutex<Option<Instant>>,
 }
 
 impl Healer {
@@ -68,7 +80,49 @@ impl Healer {
             multiplex_id,
             blobstore_key_like,
             drain_only,
+            popular_first: false,
+            last_depth_report: Mutex::new(None),
+        }
+    }
+
+    /// Fetch the entries of the blobs which were read most while waiting to be healed first,
+    /// instead of entries in no particular order. Within a batch, those are always healed
+    /// first.
+    pub fn with_popular_first(mut self, popular_first: bool) -> Self {
+        self.popular_first = popular_first;
+        self
+    }
+
+    /// Update the queue depth and healing lag stats, unless they were updated less than
+    /// `QUEUE_DEPTH_REPORT_INTERVAL` ago. Those are only informative, so errors are logged rather
+    /// than stopping the healer.
+    async fn report_queue_depth(&self, ctx: &CoreContext) {
+        {
+            let mut last_depth_report = self.last_depth_report.lock().expect("lock poisoned");
+            if let Some(last) = *last_depth_report {
+                if last.elapsed() < QUEUE_DEPTH_REPORT_INTERVAL {
+                    return;
+                }
+            }
+            *last_depth_report = Some(Instant::now());
         }
+
+        let depth = match self.sync_queue.depth(ctx, self.multiplex_id).await {
+            Ok(depth) => depth,
+            Err(e) => {
+                warn!(ctx.logger(), "Failed to get queue depth: {:?}", e);
+                return;
+            }
+        };
+        let lag_secs = depth.oldest.map_or(0, |oldest| {
+            DateTime::now().timestamp_secs() - oldest.timestamp_secs()
+        });
+        info!(
+            ctx.logger(),
+            "Queue depth: {} entries, healing lag: {}s", depth.entries, lag_secs
+        );
+        STATS::queue_depth.set_value(ctx.fb, depth.entries as i64, (self.multiplex_id,));
+        STATS::healing_lag_secs.set_value(ctx.fb, lag_secs.max(0), (self.multiplex_id,));
     }
 
     async fn fetch_entries(
@@ -78,17 +132,29 @@ impl Healer {
     ) -> Result<(usize, Vec<BlobstoreSyncQueueEntry>)> {
         let mut fetch_size = self.current_fetch_size.load(Ordering::Relaxed);
         loop {
-            match self
-                .sync_queue
-                .iter(
-                    ctx,
-                    self.blobstore_key_like.as_deref(),
-                    self.multiplex_id,
-                    healing_deadline.clone(),
-                    fetch_size,
-                )
-                .await
-            {
+            let key_like = self.blobstore_key_like.as_deref();
+            let fetched = if self.popular_first {
+                self.sync_queue
+                    .iter_by_access_count(
+                        ctx,
+                        key_like,
+                        self.multiplex_id,
+                        healing_deadline.clone(),
+                        fetch_size,
+                    )
+                    .await
+            } else {
+                self.sync_queue
+                    .iter(
+                        ctx,
+                        key_like,
+                        self.multiplex_id,
+                        healing_deadline.clone(),
+                        fetch_size,
+                    )
+                    .await
+            };
+            match fetched {
                 Ok(queue_entries) => {
                     // Success. Update fetch size for next loop
                     let new_fetch_size =
@@ -133,6 +199,8 @@ impl Healer {
         let drain_only = self.drain_only;
         let multiplex_id = self.multiplex_id;
 
+        self.report_queue_depth(ctx).await;
+
         let (max_batch_size, queue_entries) =
             self.fetch_entries(ctx, healing_deadline.clone()).await?;
 
@@ -176,8 +244,9 @@ impl Healer {
             .sorted_by_key(|entry| entry.blobstore_key.clone())
             .group_by(|entry| entry.blobstore_key.clone())
             .into_iter()
+            .map(|(key, entries)| (key, entries.collect::<Vec<_>>()))
+            .sorted_by_key(|(_, entries)| healing_priority(entries))
             .filter_map(|(key, entries)| {
-                let entries: Vec<_> = entries.collect();
                 if drain_only {
                     Some((
                         async move {
@@ -261,6 +330,22 @@ impl Healer {
     }
 }
 
+/// Blobs are healed in increasing order of this: the most read first, then the smallest, so
+/// that as many reads as possible stop failing over to other blobstores as soon as possible.
+fn healing_priority(entries: &[BlobstoreSyncQueueEntry]) -> (Reverse<u64>, u64) {
+    let access_count = entries
+        .iter()
+        .map(|entry| entry.access_count)
+        .max()
+        .unwrap_or(0);
+    let blob_size = entries
+        .iter()
+        .filter_map(|entry| entry.blob_size)
+        .next()
+        .unwrap_or(DEFAULT_BLOB_SIZE_BYTES);
+    (Reverse(access_count), blob_size)
+}
+
 #[derive(Default, Debug, PartialEq)]
 struct HealStats {
     queue_add: usize,
@@ -572,6 +657,7 @@ async fn requeue_partial_heal(
             operation_key: operation_key.clone(),
             id: None,
             blob_size,
+            access_count: 0,
         })
         .collect();
     sync_queue.add_many(ctx, new_entries).await
@@ -812,3 +812,84 @@ async fn healer_heal_incomplete_batch(fb: FacebookInit) -> Result<()> {
     assert!(!complete_batch);
     Ok(())
 }
+
+#[test]
+fn healing_priority_order() {
+    let mp = MultiplexId::new(1);
+    let t0 = DateTime::now();
+    let entry = |key: &str, access_count, blob_size| BlobstoreSyncQueueEntry {
+        access_count,
+        ..BlobstoreSyncQueueEntry::new(
+            key.to_string(),
+            BlobstoreId::new(0),
+            mp,
+            t0,
+            OperationKey::gen(),
+            blob_size,
+        )
+    };
+    let groups = vec![
+        vec![entry("cold_small", 0, Some(10))],
+        vec![entry("hot_large", 3, Some(1000))],
+        vec![entry("hot_small", 0, Some(10)), entry("hot_small", 3, None)],
+        vec![entry("warm_unknown_size", 1, None)],
+    ];
+    let order: Vec<_> = groups
+        .iter()
+        .sorted_by_key(|entries| healing_priority(entries))
+        .map(|entries| entries[0].blobstore_key.as_str())
+        .collect();
+    assert_eq!(
+        order,
+        vec!["hot_small", "hot_large", "warm_unknown_size", "cold_small"]
+    );
+}
+
+#[fbinit::test]
+async fn healer_requeue_keeps_access_count(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (bids, underlying_stores, stores) = make_empty_stores(2);
+    put_value(&ctx, stores.get(&bids[0]), "specialk", "specialv").await;
+    underlying_stores.get(&bids[1]).unwrap().fail_puts();
+
+    let t0 = DateTime::from_rfc3339("2018-11-29T12:00:00.00Z")?;
+    let mp = MultiplexId::new(1);
+
+    let sync_queue = Arc::new(SqlBlobstoreSyncQueue::with_sqlite_in_memory()?);
+    let entries = vec![BlobstoreSyncQueueEntry::new(
+        "specialk".to_string(),
+        bids[0],
+        mp,
+        t0,
+        OperationKey::gen(),
+        None,
+    )];
+    sync_queue.add_many(&ctx, entries).await?;
+    let entries = sync_queue.get(&ctx, "specialk").await?;
+    sync_queue.record_access(&ctx, &entries).await?;
+
+    let healer = Healer::new(
+        1000,
+        BufferedParams {
+            buffer_size: 10,
+            weight_limit: 1_000_000_000,
+        },
+        sync_queue.clone(),
+        stores,
+        mp,
+        None,
+        false,
+    )
+    .with_popular_first(true);
+    healer.heal(&ctx, DateTime::now()).await?;
+
+    // The put failed, so the blob is back in the queue, as popular as before
+    let entries = sync_queue
+        .iter_by_access_count(&ctx, None, mp, DateTime::now(), 10)
+        .await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].access_count, 1);
+    assert_eq!(sync_queue.depth(&ctx, mp).await?.entries, 1);
+
+    Ok(())
+}
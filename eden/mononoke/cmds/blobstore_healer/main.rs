@@ -49,6 +49,7 @@ const ITER_LIMIT_ARG: &str = "iteration-limit";
 const HEAL_MIN_AGE_ARG: &str = "heal-min-age-secs";
 const HEAL_CONCURRENCY_ARG: &str = "heal-concurrency";
 const HEAL_MAX_BYTES: &str = "heal-max-bytes";
+const POPULAR_FIRST_ARG: &str = "popular-first";
 
 lazy_static! {
     /// Minimal age of entry to consider if it has to be healed
@@ -60,6 +61,7 @@ async fn maybe_schedule_healer_for_storage(
     ctx: &CoreContext,
     dry_run: bool,
     drain_only: bool,
+    popular_first: bool,
     blobstore_sync_queue_limit: usize,
     buffered_params: BufferedParams,
     storage_config: StorageConfig,
@@ -168,7 +170,8 @@ async fn maybe_schedule_healer_for_storage(
         multiplex_id,
         source_blobstore_key,
         drain_only,
-    );
+    )
+    .with_popular_first(popular_first);
 
     schedule_healing(ctx, multiplex_healer, lag_monitor, iter_limit, heal_min_age).await
 }
@@ -277,6 +280,14 @@ fn setup_app<'a, 'b>(app_name: &str) -> MononokeClapApp<'a, 'b> {
                 .required(false)
                 .help("max combined size of concurrently healed blobs \
                        (approximate, will still let individual larger blobs through)")
+        ).arg(
+            Arg::with_name(POPULAR_FIRST_ARG)
+                .long(POPULAR_FIRST_ARG)
+                .takes_value(false)
+                .required(false)
+                .help("Heal the blobs which were read most while waiting to be healed first. \
+                       Fetching them is more expensive for the queue database, and needs its \
+                       blobstore_sync_queue_access table.")
         )
 }
 
@@ -303,6 +314,7 @@ fn main(fb: FacebookInit) -> Result<()> {
     let heal_max_bytes = value_t!(matches, HEAL_MAX_BYTES, u64).unwrap_or(10_000_000_000);
     let dry_run = matches.is_present("dry-run");
     let drain_only = matches.is_present("drain-only");
+    let popular_first = matches.is_present(POPULAR_FIRST_ARG);
     if drain_only && source_blobstore_key.is_none() {
         bail!("Missing --blobstore-key-like restriction for --drain-only");
     }
@@ -329,6 +341,7 @@ fn main(fb: FacebookInit) -> Result<()> {
         &ctx,
         dry_run,
         drain_only,
+        popular_first,
         blobstore_sync_queue_limit,
         buffered_params,
         storage_config,
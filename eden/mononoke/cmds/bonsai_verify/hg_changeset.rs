@@ -0,0 +1,208 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Error, Result};
+use blobrepo::BlobRepo;
+use blobrepo_hg::BlobRepoHg;
+use blobstore::Loadable;
+use context::CoreContext;
+use futures::future::{self, TryFutureExt};
+use mercurial_derived_data::regenerate_hg_changeset;
+use mercurial_types::{blobs::HgBlobChangeset, HgChangesetId, HgNodeHash};
+use mononoke_types::ChangesetId;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+/// A field of an hg changeset that differs between the stored changeset and the one
+/// re-derived from bonsai.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FieldDiff {
+    pub field: String,
+    pub stored: String,
+    pub regenerated: String,
+}
+
+impl FieldDiff {
+    fn new(field: impl ToString, stored: impl ToString, regenerated: impl ToString) -> Self {
+        Self {
+            field: field.to_string(),
+            stored: stored.to_string(),
+            regenerated: regenerated.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: stored {:?}, regenerated {:?}",
+            self.field, self.stored, self.regenerated
+        )
+    }
+}
+
+/// Outcome of re-deriving the hg changeset for one bonsai changeset.
+#[derive(Clone, Debug)]
+pub enum HgChangesetVerifyResult {
+    Valid,
+    Invalid {
+        stored: HgChangesetId,
+        regenerated: HgChangesetId,
+        diffs: Vec<FieldDiff>,
+    },
+}
+
+/// Re-derive the hg changeset for a bonsai changeset from the stored hg changesets of its
+/// parents, and compare it with the hg changeset that the bonsai-hg mapping points to.
+///
+/// Deriving uploads manifests and filenodes, so the repo should have an in-memory blobstore
+/// override.
+pub async fn verify_hg_changeset(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    cs_id: ChangesetId,
+) -> Result<HgChangesetVerifyResult> {
+    let bcs = cs_id.load(ctx, repo.blobstore()).await?;
+    let parents: Vec<_> = bcs.parents().collect();
+
+    // Only look at the mapping, as a missing entry must not be filled in by deriving it.
+    let mut to_fetch = parents.clone();
+    to_fetch.push(cs_id);
+    let mapping: HashMap<_, _> = repo
+        .get_hg_bonsai_mapping(ctx.clone(), to_fetch)
+        .await?
+        .into_iter()
+        .map(|(hg_cs_id, cs_id)| (cs_id, hg_cs_id))
+        .collect();
+    let hg_cs_id = |cs_id: &ChangesetId| {
+        mapping
+            .get(cs_id)
+            .copied()
+            .ok_or_else(|| format_err!("no hg changeset is mapped to {}", cs_id))
+    };
+
+    let stored_id = hg_cs_id(&cs_id)?;
+    let hg_parents = future::try_join_all(parents.iter().map(|parent| {
+        let parent = hg_cs_id(parent);
+        async move { Ok::<_, Error>(parent?.load(ctx, repo.blobstore()).await?) }
+    }))
+    .await?;
+
+    let (stored, regenerated) = future::try_join(
+        stored_id.load(ctx, repo.blobstore()).map_err(Error::from),
+        regenerate_hg_changeset(repo, ctx.clone(), bcs, hg_parents),
+    )
+    .await?;
+
+    let regenerated_id = regenerated.get_changeset_id();
+    if regenerated_id == stored_id {
+        return Ok(HgChangesetVerifyResult::Valid);
+    }
+
+    Ok(HgChangesetVerifyResult::Invalid {
+        stored: stored_id,
+        regenerated: regenerated_id,
+        diffs: diff_changesets(&stored, &regenerated)?,
+    })
+}
+
+/// List the fields that differ between two hg changesets. For files and extras only the
+/// entries that differ are reported.
+fn diff_changesets(
+    stored: &HgBlobChangeset,
+    regenerated: &HgBlobChangeset,
+) -> Result<Vec<FieldDiff>> {
+    let mut diffs = Vec::new();
+
+    let (stored_parents, regenerated_parents) = (parents(stored)?, parents(regenerated)?);
+    if stored_parents != regenerated_parents {
+        diffs.push(FieldDiff::new(
+            "parents",
+            join(&stored_parents),
+            join(&regenerated_parents),
+        ));
+    }
+
+    if stored.manifestid() != regenerated.manifestid() {
+        diffs.push(FieldDiff::new(
+            "manifest",
+            stored.manifestid(),
+            regenerated.manifestid(),
+        ));
+    }
+
+    if stored.user() != regenerated.user() {
+        diffs.push(FieldDiff::new(
+            "user",
+            String::from_utf8_lossy(stored.user()),
+            String::from_utf8_lossy(regenerated.user()),
+        ));
+    }
+
+    if stored.time() != regenerated.time() {
+        diffs.push(FieldDiff::new("time", stored.time(), regenerated.time()));
+    }
+
+    let extra_keys: BTreeSet<_> = stored
+        .extra()
+        .keys()
+        .chain(regenerated.extra().keys())
+        .collect();
+    for key in extra_keys {
+        let (stored_value, regenerated_value) =
+            (stored.extra().get(key), regenerated.extra().get(key));
+        if stored_value != regenerated_value {
+            let value = |value: Option<&Vec<u8>>| match value {
+                Some(value) => String::from_utf8_lossy(value).into_owned(),
+                None => "<missing>".to_string(),
+            };
+            diffs.push(FieldDiff::new(
+                format!("extra[{}]", String::from_utf8_lossy(key)),
+                value(stored_value),
+                value(regenerated_value),
+            ));
+        }
+    }
+
+    // Report the files that are only listed on one side
+    let stored_files: BTreeSet<_> = stored.files().iter().collect();
+    let regenerated_files: BTreeSet<_> = regenerated.files().iter().collect();
+    if stored_files != regenerated_files {
+        diffs.push(FieldDiff::new(
+            "files",
+            join(stored_files.difference(&regenerated_files)),
+            join(regenerated_files.difference(&stored_files)),
+        ));
+    }
+
+    if stored.message() != regenerated.message() {
+        diffs.push(FieldDiff::new(
+            "message",
+            String::from_utf8_lossy(stored.message()),
+            String::from_utf8_lossy(regenerated.message()),
+        ));
+    }
+
+    Ok(diffs)
+}
+
+fn parents(cs: &HgBlobChangeset) -> Result<Vec<HgNodeHash>> {
+    let mut parents: Vec<_> = cs.p1().into_iter().chain(cs.p2()).collect();
+    parents.extend(cs.step_parents()?);
+    Ok(parents)
+}
+
+fn join<T: fmt::Display>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -8,14 +8,19 @@
 #![deny(warnings)]
 
 mod config;
+mod hg_changeset;
 
 use anyhow::{format_err, Error, Result};
 use blobrepo_hg::BlobRepoHg;
 use blobrepo_utils::{BonsaiMFVerify, BonsaiMFVerifyResult};
-use blobstore::Loadable;
+use blobstore::{Blobstore, Loadable};
+use cacheblob::MemWritesBlobstore;
 use clap::{Arg, ArgMatches, SubCommand};
 use cloned::cloned;
-use cmdlib::args::{self, MononokeClapApp, MononokeMatches};
+use cmdlib::{
+    args::{self, MononokeClapApp, MononokeMatches},
+    helpers,
+};
 use context::CoreContext;
 use failure_ext::DisplayChain;
 use fbinit::FacebookInit;
@@ -28,6 +33,7 @@ use futures_old::{
     future::{self as old_future, Either},
     Future, Stream,
 };
+use hg_changeset::{verify_hg_changeset, HgChangesetVerifyResult};
 use lock_ext::LockExt;
 use mercurial_derived_data::get_manifest_from_bonsai;
 use mercurial_types::HgChangesetId;
@@ -82,6 +88,30 @@ fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
                         .index(2),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("hg-changeset")
+                .about(
+                    "verify that hg changesets re-derived from bonsai match the stored ones, \
+                    reporting the fields that differ",
+                )
+                .args_from_usage(
+                    r#"
+                    --concurrency [CONCURRENCY] 'how many changesets to verify at once [default: 100]'
+                    "#,
+                )
+                .arg(
+                    Arg::with_name("start")
+                        .help("hg or bonsai changeset, or bookmark, to start the traversal from")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .help("count of changesets to verify")
+                        .required(true)
+                        .index(2),
+                ),
+        )
 }
 
 fn get_start_points<'a>(matches: &ArgMatches<'a>) -> Vec<HgChangesetId> {
@@ -105,6 +135,9 @@ fn main(fb: FacebookInit) -> Result<()> {
         ("hg-manifest", Some(sub_m)) => {
             subcommmand_hg_manifest_verify(&ctx, &logger, &matches, sub_m)
         }
+        ("hg-changeset", Some(sub_m)) => {
+            subcommand_hg_changeset_verify(&ctx, &logger, &matches, sub_m)
+        }
         (subcommand, _) => Err(format_err!("unhandled subcommand {}", subcommand)),
     }
 }
@@ -410,3 +443,103 @@ fn subcommmand_hg_manifest_verify(
     let mut runtime = args::init_runtime(&matches)?;
     runtime.block_on(run)
 }
+
+fn subcommand_hg_changeset_verify(
+    ctx: &CoreContext,
+    logger: &Logger,
+    matches: &MononokeMatches<'_>,
+    sub_m: &ArgMatches<'_>,
+) -> Result<()> {
+    args::init_cachelib(ctx.fb, &matches);
+
+    let count: usize = sub_m
+        .value_of("count")
+        .ok_or(Error::msg("required parameter `count` is not set"))
+        .and_then(|count_str| Ok(count_str.parse()?))?;
+    let start = sub_m
+        .value_of("start")
+        .ok_or(Error::msg("required parameter `start` is not set"))?;
+    let concurrency = args::get_usize(sub_m, "concurrency", 100);
+
+    let valid = &AtomicUsize::new(0);
+    let invalid = &AtomicUsize::new(0);
+    let errors = &AtomicUsize::new(0);
+
+    let run = async move {
+        // Re-deriving uploads manifests and filenodes, so keep those writes in memory.
+        let repo = args::open_repo(ctx.fb, &logger, matches)
+            .await?
+            .dangerous_override(|blobstore| -> Arc<dyn Blobstore> {
+                Arc::new(MemWritesBlobstore::new(blobstore))
+            });
+        let repo = &repo;
+        let csid = helpers::csid_resolve(ctx.clone(), repo.clone(), start)
+            .compat()
+            .await?;
+
+        AncestorsNodeStream::new(ctx.clone(), &repo.get_changeset_fetcher(), csid)
+            .compat()
+            .take(count)
+            .map(|res| async move {
+                let csid = res?;
+                let result = verify_hg_changeset(ctx, repo, csid).await;
+                Ok::<_, Error>((csid, result))
+            })
+            .buffered(concurrency)
+            .try_for_each(|(csid, result)| async move {
+                let logger = logger.new(slog::o!["changeset_id" => format!("{}", csid)]);
+                match result {
+                    Ok(HgChangesetVerifyResult::Valid) => {
+                        debug!(logger, "VALID");
+                        valid.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(HgChangesetVerifyResult::Invalid {
+                        stored,
+                        regenerated,
+                        diffs,
+                    }) => {
+                        warn!(
+                            logger, "INVALID";
+                            "stored hg changeset" => format!("{}", stored),
+                            "regenerated hg changeset" => format!("{}", regenerated),
+                        );
+                        for diff in diffs {
+                            info!(logger, "{}", diff);
+                        }
+                        invalid.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(err) => {
+                        // Keep going, so that one broken changeset does not hide the others.
+                        error!(logger, "ERROR: {}", DisplayChain::from(&err));
+                        errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Ok(())
+            })
+            .await
+    };
+
+    let mut runtime = args::init_runtime(&matches)?;
+    runtime.block_on(run)?;
+
+    let (valid, invalid, errors) = (
+        valid.load(Ordering::Acquire),
+        invalid.load(Ordering::Acquire),
+        errors.load(Ordering::Acquire),
+    );
+    info!(
+        logger,
+        "Verified {} changesets", valid + invalid + errors;
+        "errors" => errors,
+        "invalid" => invalid,
+        "valid" => valid,
+    );
+
+    // Use the same exit codes as round-trip.
+    if errors > 0 {
+        process::exit(2)
+    } else if invalid > 0 {
+        process::exit(1)
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error};
+use serde::{Deserialize, Serialize};
+
+use mononoke_types::RepositoryId;
+
+const CHECKPOINT_FILENAME: &str = "checkpoint.json";
+
+/// How far an export of a repository got. Changesets with ids below
+/// `lower_bound` have been written to part files numbered below `next_part`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ExportCheckpoint {
+    pub repo_id: i32,
+    pub lower_bound: u64,
+    pub next_part: u64,
+    pub exported_count: u64,
+}
+
+impl ExportCheckpoint {
+    pub fn new(repo_id: RepositoryId, lower_bound: u64) -> Self {
+        Self {
+            repo_id: repo_id.id(),
+            lower_bound,
+            next_part: 0,
+            exported_count: 0,
+        }
+    }
+
+    /// Load the checkpoint stored in the output directory, if there is one
+    pub fn load(output_dir: &Path, repo_id: RepositoryId) -> Result<Option<Self>, Error> {
+        let path = checkpoint_path(output_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content =
+            fs::read(&path).with_context(|| format!("reading checkpoint {}", path.display()))?;
+        let checkpoint: Self = serde_json::from_slice(&content)
+            .with_context(|| format!("parsing checkpoint {}", path.display()))?;
+        if checkpoint.repo_id != repo_id.id() {
+            bail!(
+                "checkpoint {} belongs to repo {}, not {}",
+                path.display(),
+                checkpoint.repo_id,
+                repo_id
+            );
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Atomically replace the checkpoint stored in the output directory
+    pub fn save(&self, output_dir: &Path) -> Result<(), Error> {
+        let path = checkpoint_path(output_dir);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .with_context(|| format!("writing checkpoint {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("replacing checkpoint {}", path.display()))?;
+        Ok(())
+    }
+}
+
+fn checkpoint_path(output_dir: &Path) -> PathBuf {
+    output_dir.join(CHECKPOINT_FILENAME)
+}
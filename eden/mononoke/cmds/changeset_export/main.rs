@@ -0,0 +1,237 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Exports the public changesets of a repository, together with some
+//! metadata about each of them, into JSON-lines or Parquet files, so that
+//! analytics pipelines can consume them without querying the production
+//! databases.
+//!
+//! Changesets are exported oldest first, one part file per batch. After
+//! each part is written a checkpoint is saved in the output directory, so
+//! rerunning the export resumes after the last written part and picks up
+//! any changesets that became public since.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error};
+use blobrepo::BlobRepo;
+use bulkops::{Direction, PublicChangesetBulkFetch, MAX_FETCH_STEP};
+use clap::Arg;
+use cmdlib::{
+    args::{self, MononokeMatches},
+    helpers,
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use futures::{
+    pin_mut,
+    stream::{self, StreamExt, TryStreamExt},
+};
+use mononoke_types::ChangesetId;
+use slog::info;
+
+use crate::checkpoint::ExportCheckpoint;
+use crate::record::ChangesetRecord;
+use crate::writer::{write_part, OutputFormat};
+
+mod checkpoint;
+mod record;
+mod writer;
+
+const ARG_OUTPUT_DIR: &str = "output-dir";
+const ARG_FORMAT: &str = "format";
+const ARG_BATCH_SIZE: &str = "batch-size";
+const ARG_CONCURRENCY: &str = "concurrency";
+
+const DEFAULT_BATCH_SIZE: u64 = 10000;
+const DEFAULT_CONCURRENCY: usize = 100;
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let app = args::MononokeAppBuilder::new("Export public changesets for analytics")
+        .with_advanced_args_hidden()
+        .build()
+        .arg(
+            Arg::with_name(ARG_OUTPUT_DIR)
+                .long(ARG_OUTPUT_DIR)
+                .takes_value(true)
+                .required(true)
+                .help("Directory to write part files and the export checkpoint to"),
+        )
+        .arg(
+            Arg::with_name(ARG_FORMAT)
+                .long(ARG_FORMAT)
+                .takes_value(true)
+                .possible_values(&["json", "parquet"])
+                .default_value("json")
+                .help("Format of the part files"),
+        )
+        .arg(
+            Arg::with_name(ARG_BATCH_SIZE)
+                .long(ARG_BATCH_SIZE)
+                .takes_value(true)
+                .help("How many changeset ids to scan for each part file"),
+        )
+        .arg(
+            Arg::with_name(ARG_CONCURRENCY)
+                .long(ARG_CONCURRENCY)
+                .takes_value(true)
+                .help("How many changesets to load concurrently"),
+        );
+    let matches = app.get_matches();
+
+    let logger = args::init_logging(fb, &matches)?;
+    args::init_cachelib(fb, &matches);
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+    helpers::block_execute(
+        run(ctx, &matches),
+        fb,
+        "changeset_export",
+        &logger,
+        &matches,
+        cmdlib::monitoring::AliveService,
+    )
+}
+
+async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(), Error> {
+    let output_dir = PathBuf::from(matches.value_of(ARG_OUTPUT_DIR).unwrap());
+    let format = args::get_and_parse(matches, ARG_FORMAT, OutputFormat::Json);
+    let batch_size = args::get_u64(matches, ARG_BATCH_SIZE, DEFAULT_BATCH_SIZE);
+    let concurrency = args::get_usize(matches, ARG_CONCURRENCY, DEFAULT_CONCURRENCY);
+
+    fs::create_dir_all(&output_dir)
+        .with_context(|| format!("creating {}", output_dir.display()))?;
+
+    let repo = args::open_repo(ctx.fb, ctx.logger(), matches).await?;
+    export(
+        &ctx,
+        &repo,
+        &output_dir,
+        format,
+        batch_size.min(MAX_FETCH_STEP),
+        concurrency,
+    )
+    .await
+}
+
+async fn export(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    output_dir: &Path,
+    format: OutputFormat,
+    batch_size: u64,
+    concurrency: usize,
+) -> Result<(), Error> {
+    let fetcher = PublicChangesetBulkFetch::new(
+        repo.get_repoid(),
+        repo.get_changesets_object(),
+        repo.get_phases(),
+    )
+    .with_step(batch_size)?;
+
+    let (repo_lower, repo_upper) = fetcher.get_repo_bounds().await?;
+    let checkpoint = match ExportCheckpoint::load(output_dir, repo.get_repoid())? {
+        Some(checkpoint) => {
+            info!(
+                ctx.logger(),
+                "Resuming export from id {} with {} changesets already exported",
+                checkpoint.lower_bound,
+                checkpoint.exported_count
+            );
+            checkpoint
+        }
+        None => ExportCheckpoint::new(repo.get_repoid(), repo_lower),
+    };
+
+    if checkpoint.lower_bound >= repo_upper {
+        info!(ctx.logger(), "Nothing new to export");
+        return Ok(());
+    }
+
+    let ids = fetcher.fetch_ids(
+        ctx,
+        Direction::OldestFirst,
+        Some((checkpoint.lower_bound, repo_upper)),
+    );
+    pin_mut!(ids);
+
+    let mut writer = PartWriter {
+        ctx,
+        repo,
+        output_dir,
+        format,
+        concurrency,
+        checkpoint,
+    };
+
+    // Ids come with the bounds of the batch they were fetched in. Group them
+    // back into those batches, so that each part covers a contiguous range of
+    // ids that the checkpoint can move past.
+    let mut batch: Option<((u64, u64), Vec<ChangesetId>)> = None;
+    while let Some((cs_id, bounds)) = ids.try_next().await? {
+        match &mut batch {
+            Some((batch_bounds, batch_ids)) if *batch_bounds == bounds => batch_ids.push(cs_id),
+            _ => {
+                if let Some(((_lower, upper), cs_ids)) = batch.replace((bounds, vec![cs_id])) {
+                    writer.write(upper, cs_ids).await?;
+                }
+            }
+        }
+    }
+    if let Some(((_lower, upper), cs_ids)) = batch {
+        writer.write(upper, cs_ids).await?;
+    }
+
+    info!(
+        ctx.logger(),
+        "Export complete, {} changesets exported in total", writer.checkpoint.exported_count
+    );
+    Ok(())
+}
+
+struct PartWriter<'a> {
+    ctx: &'a CoreContext,
+    repo: &'a BlobRepo,
+    output_dir: &'a Path,
+    format: OutputFormat,
+    concurrency: usize,
+    checkpoint: ExportCheckpoint,
+}
+
+impl<'a> PartWriter<'a> {
+    /// Export a batch of changesets as the next part, then move the
+    /// checkpoint past the batch.
+    async fn write(&mut self, upper: u64, cs_ids: Vec<ChangesetId>) -> Result<(), Error> {
+        let (ctx, repo) = (self.ctx, self.repo);
+        let records: Vec<ChangesetRecord> = stream::iter(cs_ids)
+            .map(|cs_id| ChangesetRecord::load(ctx, repo, cs_id))
+            .buffered(self.concurrency)
+            .try_collect()
+            .await?;
+
+        let path = write_part(
+            self.output_dir,
+            self.format,
+            self.checkpoint.next_part,
+            &records,
+        )?;
+        info!(
+            ctx.logger(),
+            "Wrote {} changesets to {}",
+            records.len(),
+            path.display()
+        );
+
+        self.checkpoint.next_part += 1;
+        self.checkpoint.exported_count += records.len() as u64;
+        self.checkpoint.lower_bound = upper;
+        self.checkpoint.save(self.output_dir)
+    }
+}
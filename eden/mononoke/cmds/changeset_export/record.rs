@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use blobrepo::BlobRepo;
+use blobstore::Loadable;
+use context::CoreContext;
+use derived_data::BonsaiDerivable;
+use futures::{future, TryStreamExt};
+use manifest::{Diff, Entry, ManifestOps};
+use mononoke_types::ChangesetId;
+use serde::Serialize;
+use unodes::RootUnodeManifestId;
+
+/// A single exported changeset
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ChangesetRecord {
+    pub changeset_id: String,
+    pub parents: Vec<String>,
+    pub author: String,
+    pub author_date: i64,
+    pub author_tz_offset: i32,
+    pub committer: Option<String>,
+    pub committer_date: Option<i64>,
+    pub message: String,
+    pub files_added: u64,
+    pub files_modified: u64,
+    pub files_deleted: u64,
+}
+
+#[derive(Default)]
+struct FileCounts {
+    added: u64,
+    modified: u64,
+    deleted: u64,
+}
+
+impl ChangesetRecord {
+    pub async fn load(
+        ctx: &CoreContext,
+        repo: &BlobRepo,
+        cs_id: ChangesetId,
+    ) -> Result<Self, Error> {
+        let bcs = cs_id.load(ctx, repo.blobstore()).await?;
+        let parents: Vec<_> = bcs.parents().collect();
+        let counts = file_counts(ctx, repo, cs_id, parents.first().copied()).await?;
+
+        Ok(Self {
+            changeset_id: cs_id.to_string(),
+            parents: parents.iter().map(ToString::to_string).collect(),
+            author: bcs.author().to_string(),
+            author_date: bcs.author_date().timestamp_secs(),
+            author_tz_offset: bcs.author_date().tz_offset_secs(),
+            committer: bcs.committer().map(ToString::to_string),
+            committer_date: bcs.committer_date().map(|date| date.timestamp_secs()),
+            message: bcs.message().to_string(),
+            files_added: counts.added,
+            files_modified: counts.modified,
+            files_deleted: counts.deleted,
+        })
+    }
+}
+
+/// Count the files changed relative to the first parent, by diffing the
+/// unode manifests. Root changesets count all of their files as added.
+async fn file_counts(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    cs_id: ChangesetId,
+    p1: Option<ChangesetId>,
+) -> Result<FileCounts, Error> {
+    let unode = RootUnodeManifestId::derive(ctx, repo, cs_id).await?;
+    let unode = unode.manifest_unode_id().clone();
+
+    match p1 {
+        Some(p1) => {
+            let parent_unode = RootUnodeManifestId::derive(ctx, repo, p1).await?;
+            parent_unode
+                .manifest_unode_id()
+                .diff(ctx.clone(), repo.get_blobstore(), unode)
+                .try_fold(FileCounts::default(), |mut counts, diff| {
+                    match diff {
+                        Diff::Added(_, Entry::Leaf(_)) => counts.added += 1,
+                        Diff::Changed(_, Entry::Leaf(_), Entry::Leaf(_)) => counts.modified += 1,
+                        Diff::Removed(_, Entry::Leaf(_)) => counts.deleted += 1,
+                        _ => {}
+                    }
+                    future::ok(counts)
+                })
+                .await
+        }
+        None => {
+            unode
+                .list_leaf_entries(ctx.clone(), repo.get_blobstore())
+                .try_fold(FileCounts::default(), |mut counts, _| {
+                    counts.added += 1;
+                    future::ok(counts)
+                })
+                .await
+        }
+    }
+}
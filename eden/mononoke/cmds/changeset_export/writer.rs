@@ -0,0 +1,167 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{format_err, Context, Error};
+use arrow::array::{
+    ArrayRef, Int32Array, Int64Array, ListBuilder, StringArray, StringBuilder, UInt64Array,
+};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+
+use crate::record::ChangesetRecord;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    Parquet,
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "jsonl",
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "parquet" => Ok(OutputFormat::Parquet),
+            _ => Err(format_err!("unknown output format: {}", s)),
+        }
+    }
+}
+
+/// Write one part file of the export. The part is written under a
+/// temporary name and renamed into place, so that readers never see
+/// partially written parts, and rewriting a part after a restart simply
+/// replaces it.
+pub fn write_part(
+    output_dir: &Path,
+    format: OutputFormat,
+    part: u64,
+    records: &[ChangesetRecord],
+) -> Result<PathBuf, Error> {
+    let path = output_dir.join(format!("part-{:06}.{}", part, format.extension()));
+    let tmp_path = path.with_extension("tmp");
+
+    let file =
+        File::create(&tmp_path).with_context(|| format!("creating {}", tmp_path.display()))?;
+    match format {
+        OutputFormat::Json => write_json(file, records),
+        OutputFormat::Parquet => write_parquet(file, records),
+    }
+    .with_context(|| format!("writing {}", tmp_path.display()))?;
+
+    fs::rename(&tmp_path, &path).with_context(|| format!("replacing {}", path.display()))?;
+    Ok(path)
+}
+
+fn write_json(file: File, records: &[ChangesetRecord]) -> Result<(), Error> {
+    let mut writer = BufWriter::new(file);
+    for record in records {
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_parquet(file: File, records: &[ChangesetRecord]) -> Result<(), Error> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("changeset_id", DataType::Utf8, false),
+        Field::new(
+            "parents",
+            DataType::List(Box::new(Field::new("item", DataType::Utf8, true))),
+            false,
+        ),
+        Field::new("author", DataType::Utf8, false),
+        Field::new("author_date", DataType::Int64, false),
+        Field::new("author_tz_offset", DataType::Int32, false),
+        Field::new("committer", DataType::Utf8, true),
+        Field::new("committer_date", DataType::Int64, true),
+        Field::new("message", DataType::Utf8, false),
+        Field::new("files_added", DataType::UInt64, false),
+        Field::new("files_modified", DataType::UInt64, false),
+        Field::new("files_deleted", DataType::UInt64, false),
+    ]));
+
+    let mut parents = ListBuilder::new(StringBuilder::new(records.len()));
+    for record in records {
+        for parent in &record.parents {
+            parents.values().append_value(parent)?;
+        }
+        parents.append(true)?;
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(
+            records
+                .iter()
+                .map(|r| r.changeset_id.as_str())
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(parents.finish()),
+        Arc::new(StringArray::from(
+            records
+                .iter()
+                .map(|r| r.author.as_str())
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(Int64Array::from(
+            records.iter().map(|r| r.author_date).collect::<Vec<_>>(),
+        )),
+        Arc::new(Int32Array::from(
+            records
+                .iter()
+                .map(|r| r.author_tz_offset)
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(StringArray::from(
+            records
+                .iter()
+                .map(|r| r.committer.as_deref())
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(Int64Array::from(
+            records.iter().map(|r| r.committer_date).collect::<Vec<_>>(),
+        )),
+        Arc::new(StringArray::from(
+            records
+                .iter()
+                .map(|r| r.message.as_str())
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(UInt64Array::from(
+            records.iter().map(|r| r.files_added).collect::<Vec<_>>(),
+        )),
+        Arc::new(UInt64Array::from(
+            records.iter().map(|r| r.files_modified).collect::<Vec<_>>(),
+        )),
+        Arc::new(UInt64Array::from(
+            records.iter().map(|r| r.files_deleted).collect::<Vec<_>>(),
+        )),
+    ];
+
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut writer = ArrowWriter::try_new(file, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
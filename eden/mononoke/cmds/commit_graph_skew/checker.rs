@@ -0,0 +1,301 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use changesets::{ChangesetEntry, Changesets};
+use context::CoreContext;
+use mononoke_types::{ChangesetId, RepositoryId};
+
+/// Source of changeset entries that the checker walks.
+#[async_trait]
+pub trait ChangesetEntries: Send + Sync {
+    async fn get_many(
+        &self,
+        ctx: &CoreContext,
+        cs_ids: Vec<ChangesetId>,
+    ) -> Result<Vec<ChangesetEntry>, Error>;
+}
+
+pub struct RepoChangesetEntries {
+    pub repo_id: RepositoryId,
+    pub changesets: Arc<dyn Changesets>,
+}
+
+#[async_trait]
+impl ChangesetEntries for RepoChangesetEntries {
+    async fn get_many(
+        &self,
+        ctx: &CoreContext,
+        cs_ids: Vec<ChangesetId>,
+    ) -> Result<Vec<ChangesetEntry>, Error> {
+        self.changesets
+            .get_many(ctx.clone(), self.repo_id, cs_ids)
+            .await
+    }
+}
+
+/// A changeset which is referenced, either as a head or as a parent, but
+/// which has no entry in the changesets table.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct MissingAncestor {
+    pub cs_id: ChangesetId,
+    /// The changeset that lists this one as its parent, or `None` if the
+    /// head itself is missing.
+    pub child: Option<ChangesetId>,
+}
+
+impl fmt::Display for MissingAncestor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.child {
+            Some(child) => write!(f, "{} (parent of {})", self.cs_id, child),
+            None => write!(f, "{} (head)", self.cs_id),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HeadCheck {
+    pub missing: Vec<MissingAncestor>,
+    /// How many changesets were loaded from the changesets table.
+    pub visited: usize,
+    /// Whether the walk stopped at the depth limit before reaching the
+    /// root or already verified changesets.
+    pub truncated: bool,
+}
+
+/// Walks the ancestors of heads and reports any that are missing from the
+/// changesets table.
+///
+/// Changesets whose ancestry was found complete are remembered for one
+/// more pass, so a head that has not moved, or has moved by a few commits,
+/// is checked by loading only the new changesets.
+pub struct SkewChecker<E> {
+    entries: E,
+    max_depth: usize,
+    batch_size: usize,
+    previously_verified: HashSet<ChangesetId>,
+    verified: HashSet<ChangesetId>,
+}
+
+impl<E: ChangesetEntries> SkewChecker<E> {
+    pub fn new(entries: E, max_depth: usize, batch_size: usize) -> Self {
+        Self {
+            entries,
+            max_depth,
+            batch_size,
+            previously_verified: HashSet::new(),
+            verified: HashSet::new(),
+        }
+    }
+
+    /// Starts a new pass over the heads. Changesets verified in the pass
+    /// before the previous one are forgotten, which keeps memory usage
+    /// proportional to the amount of new history.
+    pub fn start_pass(&mut self) {
+        self.previously_verified = std::mem::take(&mut self.verified);
+    }
+
+    fn is_verified(&self, cs_id: &ChangesetId) -> bool {
+        self.verified.contains(cs_id) || self.previously_verified.contains(cs_id)
+    }
+
+    pub async fn check_head(
+        &mut self,
+        ctx: &CoreContext,
+        head: ChangesetId,
+    ) -> Result<HeadCheck, Error> {
+        let mut check = HeadCheck::default();
+        if self.is_verified(&head) {
+            self.verified.insert(head);
+            return Ok(check);
+        }
+
+        // Each queued changeset is paired with the child that referenced it.
+        let mut queue: Vec<(ChangesetId, Option<ChangesetId>)> = vec![(head, None)];
+        let mut seen: HashSet<ChangesetId> = HashSet::new();
+        seen.insert(head);
+
+        while !queue.is_empty() {
+            if check.visited >= self.max_depth {
+                check.truncated = true;
+                break;
+            }
+            let take = queue
+                .len()
+                .min(self.batch_size)
+                .min(self.max_depth - check.visited);
+            let batch: Vec<_> = queue.drain(..take).collect();
+            let children: HashMap<_, _> = batch.iter().cloned().collect();
+
+            let entries = self
+                .entries
+                .get_many(ctx, batch.iter().map(|(cs_id, _)| *cs_id).collect())
+                .await?;
+            check.visited += entries.len();
+
+            let found: HashSet<_> = entries.iter().map(|entry| entry.cs_id).collect();
+            for (cs_id, child) in children {
+                if !found.contains(&cs_id) {
+                    check.missing.push(MissingAncestor { cs_id, child });
+                }
+            }
+
+            for entry in entries {
+                for parent in entry.parents {
+                    if self.is_verified(&parent) {
+                        self.verified.insert(parent);
+                    } else if seen.insert(parent) {
+                        queue.push((parent, Some(entry.cs_id)));
+                    }
+                }
+            }
+        }
+
+        if check.missing.is_empty() {
+            // Everything seen, apart from what is still queued at the depth
+            // limit, is known to have all its ancestors present.
+            for (cs_id, _) in queue {
+                seen.remove(&cs_id);
+            }
+            self.verified.extend(seen);
+        } else {
+            // Check again on the next pass, so the alert keeps firing until
+            // the missing changesets are repaired.
+            check.missing.sort();
+        }
+
+        Ok(check)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fbinit::FacebookInit;
+    use mononoke_types_mocks::changesetid::{
+        FIVES_CSID, FOURS_CSID, ONES_CSID, THREES_CSID, TWOS_CSID,
+    };
+    use mononoke_types_mocks::repo::REPO_ZERO;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct TestEntries {
+        entries: HashMap<ChangesetId, Vec<ChangesetId>>,
+        loaded: AtomicUsize,
+    }
+
+    impl TestEntries {
+        fn with(mut self, cs_id: ChangesetId, parents: Vec<ChangesetId>) -> Self {
+            self.entries.insert(cs_id, parents);
+            self
+        }
+    }
+
+    #[async_trait]
+    impl<'a> ChangesetEntries for &'a TestEntries {
+        async fn get_many(
+            &self,
+            _ctx: &CoreContext,
+            cs_ids: Vec<ChangesetId>,
+        ) -> Result<Vec<ChangesetEntry>, Error> {
+            self.loaded.fetch_add(cs_ids.len(), Ordering::Relaxed);
+            Ok(cs_ids
+                .into_iter()
+                .filter_map(|cs_id| {
+                    self.entries.get(&cs_id).map(|parents| ChangesetEntry {
+                        repo_id: REPO_ZERO,
+                        cs_id,
+                        parents: parents.clone(),
+                        gen: 0,
+                    })
+                })
+                .collect())
+        }
+    }
+
+    #[fbinit::test]
+    async fn test_connected_head(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let entries = TestEntries::default()
+            .with(ONES_CSID, vec![])
+            .with(TWOS_CSID, vec![ONES_CSID])
+            .with(THREES_CSID, vec![TWOS_CSID]);
+        let mut checker = SkewChecker::new(&entries, 100, 10);
+
+        checker.start_pass();
+        let check = checker.check_head(&ctx, THREES_CSID).await?;
+        assert_eq!(check.missing, vec![]);
+        assert_eq!(check.visited, 3);
+        assert!(!check.truncated);
+
+        // The next pass does not reload verified history.
+        checker.start_pass();
+        let check = checker.check_head(&ctx, THREES_CSID).await?;
+        assert_eq!(check.visited, 0);
+        assert_eq!(entries.loaded.load(Ordering::Relaxed), 3);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_missing_ancestors(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        // A merge where the history of the second parent was partially
+        // written.
+        let entries = TestEntries::default()
+            .with(ONES_CSID, vec![])
+            .with(THREES_CSID, vec![TWOS_CSID])
+            .with(FOURS_CSID, vec![ONES_CSID, THREES_CSID]);
+        let mut checker = SkewChecker::new(&entries, 100, 10);
+
+        checker.start_pass();
+        let check = checker.check_head(&ctx, FOURS_CSID).await?;
+        assert_eq!(
+            check.missing,
+            vec![MissingAncestor {
+                cs_id: TWOS_CSID,
+                child: Some(THREES_CSID),
+            }]
+        );
+
+        let check = checker.check_head(&ctx, FIVES_CSID).await?;
+        assert_eq!(
+            check.missing,
+            vec![MissingAncestor {
+                cs_id: FIVES_CSID,
+                child: None,
+            }]
+        );
+
+        // Broken history is not remembered as verified.
+        checker.start_pass();
+        let check = checker.check_head(&ctx, FOURS_CSID).await?;
+        assert_eq!(check.missing.len(), 1);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_max_depth(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let entries = TestEntries::default()
+            .with(TWOS_CSID, vec![ONES_CSID])
+            .with(THREES_CSID, vec![TWOS_CSID])
+            .with(FOURS_CSID, vec![THREES_CSID]);
+        let mut checker = SkewChecker::new(&entries, 2, 10);
+
+        checker.start_pass();
+        let check = checker.check_head(&ctx, FOURS_CSID).await?;
+        assert_eq!(check.missing, vec![]);
+        assert_eq!(check.visited, 2);
+        assert!(check.truncated);
+        Ok(())
+    }
+}
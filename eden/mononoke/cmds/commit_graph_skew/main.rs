@@ -0,0 +1,246 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Periodically checks that every public bookmark head is present in the
+//! changesets table together with all of its ancestors. A push that failed
+//! half way can leave a bookmark pointing at history that was never fully
+//! written, which otherwise only shows up once a client tries to pull it.
+//!
+//! Optionally the segmented changelog is checked too: its head must be
+//! fully connected in the changesets table, and bookmark heads that it
+//! does not know about yet are counted, to spot a stuck tailer.
+
+use std::time::Duration;
+
+use anyhow::{Context, Error};
+use blobrepo::BlobRepo;
+use blobstore_factory::{make_metadata_sql_factory, ReadOnlyStorage};
+use clap::Arg;
+use cmdlib::{
+    args::{self, MononokeMatches},
+    helpers,
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use futures::stream::TryStreamExt;
+use mononoke_types::ChangesetId;
+use segmented_changelog::SegmentedChangelogBuilder;
+use slog::{error, info, warn};
+use stats::prelude::*;
+
+use crate::checker::{RepoChangesetEntries, SkewChecker};
+use crate::sc_heads::SegmentedChangelogHeads;
+
+mod checker;
+mod sc_heads;
+
+define_stats! {
+    prefix = "mononoke.commit_graph_skew";
+    heads_checked: timeseries(Rate, Sum),
+    heads_with_missing_ancestors: timeseries(Rate, Sum),
+    missing_ancestors: timeseries(Rate, Sum),
+    heads_missing_from_segmented_changelog: timeseries(Rate, Sum),
+    check_failures: timeseries(Rate, Sum),
+}
+
+const ARG_INTERVAL: &str = "interval";
+const ARG_ONCE: &str = "once";
+const ARG_MAX_DEPTH: &str = "max-depth";
+const ARG_BATCH_SIZE: &str = "batch-size";
+const ARG_CHECK_SEGMENTED_CHANGELOG: &str = "check-segmented-changelog";
+
+const DEFAULT_INTERVAL_SECS: u64 = 300;
+const DEFAULT_MAX_DEPTH: usize = 100000;
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let app = args::MononokeAppBuilder::new(
+        "Check that public bookmark heads are fully connected in the changesets table",
+    )
+    .with_advanced_args_hidden()
+    .with_fb303_args()
+    .build()
+    .arg(
+        Arg::with_name(ARG_INTERVAL)
+            .long(ARG_INTERVAL)
+            .takes_value(true)
+            .help("Delay in seconds between checks"),
+    )
+    .arg(
+        Arg::with_name(ARG_ONCE)
+            .long(ARG_ONCE)
+            .takes_value(false)
+            .help("Check once and exit, failing if any ancestors are missing"),
+    )
+    .arg(
+        Arg::with_name(ARG_MAX_DEPTH)
+            .long(ARG_MAX_DEPTH)
+            .takes_value(true)
+            .help("How many new ancestors of each head to load before giving up on it"),
+    )
+    .arg(
+        Arg::with_name(ARG_BATCH_SIZE)
+            .long(ARG_BATCH_SIZE)
+            .takes_value(true)
+            .help("How many changesets to load from the changesets table at once"),
+    )
+    .arg(
+        Arg::with_name(ARG_CHECK_SEGMENTED_CHANGELOG)
+            .long(ARG_CHECK_SEGMENTED_CHANGELOG)
+            .takes_value(false)
+            .help("Also check the head of the segmented changelog"),
+    );
+    let matches = app.get_matches();
+
+    let logger = args::init_logging(fb, &matches)?;
+    args::init_cachelib(fb, &matches);
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+    helpers::block_execute(
+        run(ctx, &matches),
+        fb,
+        "commit_graph_skew",
+        &logger,
+        &matches,
+        cmdlib::monitoring::AliveService,
+    )
+}
+
+async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(), Error> {
+    let interval = Duration::from_secs(args::get_u64(matches, ARG_INTERVAL, DEFAULT_INTERVAL_SECS));
+    let max_depth = args::get_usize(matches, ARG_MAX_DEPTH, DEFAULT_MAX_DEPTH);
+    let batch_size = args::get_usize(matches, ARG_BATCH_SIZE, DEFAULT_BATCH_SIZE);
+
+    let repo = args::open_repo(ctx.fb, ctx.logger(), matches)
+        .await
+        .context("opening repo")?;
+
+    let segmented_changelog = if matches.is_present(ARG_CHECK_SEGMENTED_CHANGELOG) {
+        let config_store = args::init_config_store(ctx.fb, ctx.logger(), matches)?;
+        let (_, config) = args::get_config(config_store, matches)?;
+        let sql_factory = make_metadata_sql_factory(
+            ctx.fb,
+            config.storage_config.metadata,
+            args::parse_mysql_options(matches),
+            ReadOnlyStorage(true),
+            ctx.logger(),
+        )
+        .await
+        .context("constructing metadata sql factory")?;
+        let manager = sql_factory
+            .open::<SegmentedChangelogBuilder>()
+            .await
+            .context("constructing segmented changelog builder")?
+            .with_repo_id(repo.get_repoid())
+            .build_manager()
+            .context("building segmented changelog manager")?;
+        Some(SegmentedChangelogHeads::new(manager))
+    } else {
+        None
+    };
+
+    let mut checker = SkewChecker::new(
+        RepoChangesetEntries {
+            repo_id: repo.get_repoid(),
+            changesets: repo.get_changesets_object(),
+        },
+        max_depth,
+        batch_size,
+    );
+
+    if matches.is_present(ARG_ONCE) {
+        let missing = check_repo(&ctx, &repo, &mut checker, segmented_changelog.as_ref()).await?;
+        if missing > 0 {
+            return Err(Error::msg(format!(
+                "{} changesets are missing from the changesets table",
+                missing
+            )));
+        }
+        return Ok(());
+    }
+
+    loop {
+        if let Err(e) = check_repo(&ctx, &repo, &mut checker, segmented_changelog.as_ref()).await {
+            STATS::check_failures.add_value(1);
+            error!(ctx.logger(), "Commit graph skew check failed: {:?}", e);
+        }
+        tokio::time::delay_for(interval).await;
+    }
+}
+
+/// Checks all the heads once, returning the number of missing changesets.
+async fn check_repo(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    checker: &mut SkewChecker<RepoChangesetEntries>,
+    segmented_changelog: Option<&SegmentedChangelogHeads>,
+) -> Result<usize, Error> {
+    let mut heads: Vec<(String, ChangesetId)> = repo
+        .get_bonsai_publishing_bookmarks_maybe_stale(ctx.clone())
+        .map_ok(|(bookmark, cs_id)| (format!("bookmark {}", bookmark.name), cs_id))
+        .try_collect()
+        .await
+        .context("listing publishing bookmarks")?;
+
+    if let Some(segmented_changelog) = segmented_changelog {
+        let bookmark_heads = heads.iter().map(|(_, cs_id)| *cs_id).collect();
+        let (sc_head, unknown) = segmented_changelog
+            .check(ctx, bookmark_heads)
+            .await
+            .context("checking segmented changelog")?;
+        if !unknown.is_empty() {
+            info!(
+                ctx.logger(),
+                "{} bookmark heads are not in the segmented changelog yet",
+                unknown.len()
+            );
+        }
+        STATS::heads_missing_from_segmented_changelog.add_value(unknown.len() as i64);
+        if let Some(sc_head) = sc_head {
+            heads.push(("segmented changelog head".to_string(), sc_head));
+        }
+    }
+
+    checker.start_pass();
+    let mut total_missing = 0;
+    for (label, head) in heads {
+        let check = checker
+            .check_head(ctx, head)
+            .await
+            .with_context(|| format!("checking {} at {}", label, head))?;
+        STATS::heads_checked.add_value(1);
+
+        if check.truncated {
+            warn!(
+                ctx.logger(),
+                "Stopped checking {} at {} after {} ancestors", label, head, check.visited
+            );
+        }
+        if !check.missing.is_empty() {
+            STATS::heads_with_missing_ancestors.add_value(1);
+            STATS::missing_ancestors.add_value(check.missing.len() as i64);
+            total_missing += check.missing.len();
+            let missing: Vec<_> = check.missing.iter().map(|m| m.to_string()).collect();
+            error!(
+                ctx.logger(),
+                "{} at {} has {} ancestors missing from the changesets table: {}",
+                label,
+                head,
+                missing.len(),
+                missing.join(", ")
+            );
+        }
+    }
+
+    info!(
+        ctx.logger(),
+        "Commit graph skew check done, {} changesets missing", total_missing
+    );
+    Ok(total_missing)
+}
@@ -0,0 +1,39 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use context::CoreContext;
+use mononoke_types::ChangesetId;
+use segmented_changelog::SegmentedChangelogManager;
+
+pub struct SegmentedChangelogHeads {
+    manager: SegmentedChangelogManager,
+}
+
+impl SegmentedChangelogHeads {
+    pub fn new(manager: SegmentedChangelogManager) -> Self {
+        Self { manager }
+    }
+
+    /// Returns the last changeset added to the saved segmented changelog,
+    /// and which of `heads` it does not contain yet.
+    pub async fn check(
+        &self,
+        ctx: &CoreContext,
+        heads: Vec<ChangesetId>,
+    ) -> Result<(Option<ChangesetId>, Vec<ChangesetId>), Error> {
+        let (bundle, _) = self.manager.load_dag(ctx).await?;
+        let idmap = self.manager.new_idmap(bundle.idmap_version);
+        let last = idmap.get_last_entry(ctx).await?.map(|(_, cs_id)| cs_id);
+        let known = idmap.find_many_vertexes(ctx, heads.clone()).await?;
+        let unknown = heads
+            .into_iter()
+            .filter(|cs_id| !known.contains_key(cs_id))
+            .collect();
+        Ok((last, unknown))
+    }
+}
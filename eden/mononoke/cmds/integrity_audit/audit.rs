@@ -0,0 +1,283 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Error, Result};
+use blobrepo::BlobRepo;
+use blobrepo_hg::BlobRepoHg;
+use blobstore::{Blobstore, Loadable, LoadableError};
+use context::CoreContext;
+use failure_ext::DisplayChain;
+use futures::stream::{self, StreamExt};
+use lock_ext::LockExt;
+use manifest::{Entry, Manifest};
+use mercurial_types::{
+    blobs::{fetch_manifest_envelope_opt, HgBlobManifest},
+    FileType, HgBlobNode, HgChangesetId, HgFileNodeId, HgManifestId, NULL_HASH,
+};
+use mononoke_types::{ChangesetId, ContentId, FileContents, MPath, MPathElement, MononokeId};
+use serde::Serialize;
+use std::{collections::HashSet, sync::Mutex};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    /// The tree is stored, but its contents and parents don't hash to its id
+    TreeHashMismatch,
+    /// The tree is not in the blobstore
+    MissingTree,
+    /// The file node a tree points to is not in the blobstore
+    MissingFilenode,
+    /// A file content blob, or one of its chunks, is not in the blobstore
+    MissingContent,
+    /// The blob could not be fetched or parsed
+    Unreadable,
+}
+
+/// Something wrong with one of the blobs reachable from an audited changeset
+#[derive(Clone, Debug, Serialize)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub path: String,
+    pub key: String,
+    pub detail: Option<String>,
+}
+
+impl Problem {
+    fn new(kind: ProblemKind, path: Option<&MPath>, key: String, detail: Option<String>) -> Self {
+        Self {
+            kind,
+            path: MPath::display_opt(path).to_string(),
+            key,
+            detail,
+        }
+    }
+
+    fn unreadable(path: Option<&MPath>, key: String, error: &Error) -> Self {
+        Self::new(
+            ProblemKind::Unreadable,
+            path,
+            key,
+            Some(DisplayChain::from(error).to_string()),
+        )
+    }
+
+    fn from_load_error(
+        kind: ProblemKind,
+        path: Option<&MPath>,
+        key: String,
+        error: LoadableError,
+    ) -> Self {
+        match error {
+            LoadableError::Missing(_) => Self::new(kind, path, key, None),
+            LoadableError::Error(error) => Self::unreadable(path, key, &error),
+        }
+    }
+}
+
+/// Outcome of auditing one changeset. Blobs already checked for an earlier changeset of the same
+/// run are not checked again, so the counts only cover what this changeset added.
+#[derive(Clone, Debug, Serialize)]
+pub struct ChangesetAudit {
+    pub changeset_id: String,
+    pub hg_changeset_id: String,
+    pub trees_verified: usize,
+    pub filenodes_checked: usize,
+    pub content_blobs_checked: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl ChangesetAudit {
+    fn new(cs_id: ChangesetId, hg_cs_id: HgChangesetId) -> Self {
+        Self {
+            changeset_id: cs_id.to_string(),
+            hg_changeset_id: hg_cs_id.to_string(),
+            trees_verified: 0,
+            filenodes_checked: 0,
+            content_blobs_checked: 0,
+            problems: Vec::new(),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Walks the hg manifests of changesets, verifying that each tree hashes to its id, and checks
+/// that the file nodes and file contents they reference are in the blobstore.
+///
+/// All blobs are read through the repo blobstore, so with a multiplexed blobstore and a scrub
+/// action set, every read is also checked across the inner stores by the scrub.
+pub struct Auditor<'a> {
+    ctx: &'a CoreContext,
+    repo: &'a BlobRepo,
+    concurrency: usize,
+    seen_trees: Mutex<HashSet<HgManifestId>>,
+    seen_filenodes: Mutex<HashSet<HgFileNodeId>>,
+    seen_contents: Mutex<HashSet<ContentId>>,
+}
+
+impl<'a> Auditor<'a> {
+    pub fn new(ctx: &'a CoreContext, repo: &'a BlobRepo, concurrency: usize) -> Self {
+        Self {
+            ctx,
+            repo,
+            concurrency,
+            seen_trees: Mutex::new(HashSet::new()),
+            seen_filenodes: Mutex::new(HashSet::new()),
+            seen_contents: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub async fn audit_changeset(&self, cs_id: ChangesetId) -> Result<ChangesetAudit> {
+        let hg_cs_id = self
+            .repo
+            .get_hg_from_bonsai_changeset(self.ctx.clone(), cs_id)
+            .await?;
+        let hg_cs = hg_cs_id.load(self.ctx, self.repo.blobstore()).await?;
+        let mut audit = ChangesetAudit::new(cs_id, hg_cs_id);
+
+        // Verify the trees one level at a time, collecting the files they reference.
+        let mut trees = vec![(None, hg_cs.manifestid())];
+        let mut files = Vec::new();
+        while !trees.is_empty() {
+            let unseen: Vec<_> = trees
+                .drain(..)
+                .filter(|(_, mf_id)| self.seen_trees.with(|seen| seen.insert(*mf_id)))
+                .collect();
+            let verified: Vec<_> = stream::iter(unseen)
+                .map(|(path, mf_id)| async move {
+                    let res = self.verify_tree(path.as_ref(), mf_id).await;
+                    (path, res)
+                })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+
+            for (path, res) in verified {
+                audit.trees_verified += 1;
+                match res {
+                    Ok(entries) => {
+                        for (name, entry) in entries {
+                            let path = MPath::join_opt_element(path.as_ref(), &name);
+                            match entry {
+                                Entry::Tree(mf_id) => trees.push((Some(path), mf_id)),
+                                Entry::Leaf((_, filenode_id)) => files.push((path, filenode_id)),
+                            }
+                        }
+                    }
+                    Err(problem) => audit.problems.push(problem),
+                }
+            }
+        }
+
+        let unseen: Vec<_> = files
+            .into_iter()
+            .filter(|(_, filenode_id)| self.seen_filenodes.with(|seen| seen.insert(*filenode_id)))
+            .collect();
+        let checked: Vec<_> = stream::iter(unseen)
+            .map(|(path, filenode_id)| self.check_file(path, filenode_id))
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        for (content_blobs, problems) in checked {
+            audit.filenodes_checked += 1;
+            audit.content_blobs_checked += content_blobs;
+            audit.problems.extend(problems);
+        }
+
+        Ok(audit)
+    }
+
+    async fn verify_tree(
+        &self,
+        path: Option<&MPath>,
+        mf_id: HgManifestId,
+    ) -> Result<Vec<(MPathElement, Entry<HgManifestId, (FileType, HgFileNodeId)>)>, Problem> {
+        if mf_id.into_nodehash() == NULL_HASH {
+            return Ok(Vec::new());
+        }
+
+        let key = mf_id.blobstore_key();
+        let envelope =
+            match fetch_manifest_envelope_opt(self.ctx, self.repo.blobstore(), mf_id).await {
+                Ok(Some(envelope)) => envelope,
+                Ok(None) => return Err(Problem::new(ProblemKind::MissingTree, path, key, None)),
+                Err(e) => return Err(Problem::unreadable(path, key, &e)),
+            };
+
+        let (p1, p2) = envelope.parents();
+        let computed = HgBlobNode::new(envelope.contents().clone(), p1, p2).nodeid();
+        if envelope.node_id() != mf_id.into_nodehash() || computed != envelope.computed_node_id() {
+            return Err(Problem::new(
+                ProblemKind::TreeHashMismatch,
+                path,
+                key,
+                Some(format!(
+                    "stored as {} (computed {}), contents hash to {}",
+                    envelope.node_id(),
+                    envelope.computed_node_id(),
+                    computed
+                )),
+            ));
+        }
+
+        match HgBlobManifest::parse(envelope) {
+            Ok(manifest) => Ok(manifest.list().collect()),
+            Err(e) => Err(Problem::unreadable(path, key, &e)),
+        }
+    }
+
+    /// Check that a file node and all the blobs of its content are present. Returns the number
+    /// of content blobs checked.
+    async fn check_file(&self, path: MPath, filenode_id: HgFileNodeId) -> (usize, Vec<Problem>) {
+        let path = Some(&path);
+        let blobstore = self.repo.blobstore();
+
+        let envelope = match filenode_id.load(self.ctx, blobstore).await {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                let key = filenode_id.blobstore_key();
+                let problem = Problem::from_load_error(ProblemKind::MissingFilenode, path, key, e);
+                return (0, vec![problem]);
+            }
+        };
+
+        let content_id = envelope.content_id();
+        if !self.seen_contents.with(|seen| seen.insert(content_id)) {
+            return (0, Vec::new());
+        }
+
+        let chunk_keys: Vec<_> = match content_id.load(self.ctx, blobstore).await {
+            Ok(FileContents::Bytes(_)) => Vec::new(),
+            Ok(FileContents::Chunked(chunked)) => chunked
+                .iter_chunks()
+                .map(|chunk| chunk.chunk_id().blobstore_key())
+                .collect(),
+            Err(e) => {
+                let key = content_id.blobstore_key();
+                let problem = Problem::from_load_error(ProblemKind::MissingContent, path, key, e);
+                return (1, vec![problem]);
+            }
+        };
+
+        let mut problems = Vec::new();
+        for key in chunk_keys.iter() {
+            match blobstore.get(self.ctx, key).await {
+                Ok(Some(_)) => {}
+                Ok(None) => problems.push(Problem::new(
+                    ProblemKind::MissingContent,
+                    path,
+                    key.clone(),
+                    None,
+                )),
+                Err(e) => problems.push(Problem::unreadable(path, key.clone(), &e)),
+            }
+        }
+        (1 + chunk_keys.len(), problems)
+    }
+}
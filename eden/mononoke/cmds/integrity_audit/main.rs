@@ -0,0 +1,259 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+mod audit;
+mod report;
+
+use anyhow::{Context, Error, Result};
+use blobrepo::BlobRepo;
+use blobstore_factory::ScrubAction;
+use clap::Arg;
+use cmdlib::{
+    args::{self, ArgType},
+    helpers,
+};
+use context::CoreContext;
+use failure_ext::DisplayChain;
+use fbinit::FacebookInit;
+use futures::{
+    compat::{Future01CompatExt, Stream01CompatExt},
+    stream::{StreamExt, TryStreamExt},
+};
+use mononoke_types::{ChangesetId, DateTime};
+use rand::seq::SliceRandom;
+use revset::AncestorsNodeStream;
+use slog::{error, info, warn, Logger};
+use std::{path::Path, process, time::Duration};
+
+use crate::audit::Auditor;
+use crate::report::{AuditReport, Coverage};
+
+const ARG_START: &str = "start";
+const ARG_WINDOW: &str = "window";
+const ARG_SAMPLE_SIZE: &str = "sample-size";
+const ARG_CONCURRENCY: &str = "concurrency";
+const ARG_REPORT: &str = "report";
+const ARG_COVERAGE_STATE: &str = "coverage-state";
+const ARG_INTERVAL: &str = "interval";
+
+/// Totals of one audit run, for the summary and the exit code
+#[derive(Default)]
+struct RunStats {
+    valid: usize,
+    invalid: usize,
+    errors: usize,
+}
+
+struct AuditOptions<'a> {
+    start: &'a str,
+    window: usize,
+    sample_size: usize,
+    concurrency: usize,
+    report_path: &'a Path,
+    coverage_path: Option<&'a Path>,
+}
+
+/// Pick the changesets to audit among the most recent ones, preferring those that have not been
+/// audited by a previous run.
+fn sample(window: &[ChangesetId], coverage: &mut Coverage, sample_size: usize) -> Vec<ChangesetId> {
+    let mut candidates: Vec<_> = window
+        .iter()
+        .filter(|cs_id| !coverage.is_audited(cs_id))
+        .copied()
+        .collect();
+    if candidates.is_empty() {
+        coverage.clear();
+        candidates = window.to_vec();
+    }
+    candidates
+        .choose_multiple(&mut rand::thread_rng(), sample_size)
+        .copied()
+        .collect()
+}
+
+async fn run_audit(
+    ctx: &CoreContext,
+    logger: &Logger,
+    repo: &BlobRepo,
+    opts: &AuditOptions<'_>,
+) -> Result<RunStats> {
+    let start = helpers::csid_resolve(ctx.clone(), repo.clone(), opts.start)
+        .compat()
+        .await?;
+    let window: Vec<_> =
+        AncestorsNodeStream::new(ctx.clone(), &repo.get_changeset_fetcher(), start)
+            .compat()
+            .take(opts.window)
+            .try_collect()
+            .await?;
+
+    let mut coverage = match opts.coverage_path {
+        Some(path) => Coverage::load(path)?,
+        None => Coverage::default(),
+    };
+    coverage.retain_window(&window);
+    let sampled = sample(&window, &mut coverage, opts.sample_size);
+    info!(
+        logger,
+        "Auditing {} of the {} changesets from {}",
+        sampled.len(),
+        window.len(),
+        opts.start
+    );
+
+    let mut report = AuditReport::open(opts.report_path)?;
+    let auditor = Auditor::new(ctx, repo, opts.concurrency);
+    let mut stats = RunStats::default();
+    // The auditor skips the blobs it has already checked for another changeset, so audit the
+    // changesets one at a time, to avoid checking the same blobs concurrently.
+    for cs_id in sampled {
+        let logger = logger.new(slog::o!["changeset_id" => format!("{}", cs_id)]);
+        match auditor.audit_changeset(cs_id).await {
+            Ok(audit) => {
+                if audit.is_valid() {
+                    stats.valid += 1;
+                } else {
+                    warn!(logger, "INVALID: {} problems", audit.problems.len());
+                    for problem in audit.problems.iter() {
+                        info!(logger, "{:?}", problem);
+                    }
+                    stats.invalid += 1;
+                }
+                report.record(&audit)?;
+                coverage.mark_audited(&cs_id, DateTime::now().timestamp_secs());
+            }
+            Err(err) => {
+                // Not marked as audited, so a later run picks it again.
+                error!(logger, "ERROR: {}", DisplayChain::from(&err));
+                stats.errors += 1;
+            }
+        }
+    }
+
+    let audited = coverage.retain_window(&window);
+    if let Some(path) = opts.coverage_path {
+        coverage.save(path)?;
+    }
+    info!(
+        logger,
+        "Audited {} changesets", stats.valid + stats.invalid + stats.errors;
+        "errors" => stats.errors,
+        "invalid" => stats.invalid,
+        "valid" => stats.valid,
+        "coverage" => format!("{}/{}", audited, window.len()),
+    );
+    Ok(stats)
+}
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<()> {
+    let matches = args::MononokeAppBuilder::new("integrity audit")
+        .with_advanced_args_hidden()
+        .with_arg_types(vec![ArgType::Scrub])
+        .with_scrub_action_default(Some(ScrubAction::ReportOnly))
+        .build()
+        .about(
+            "Audit a sample of recent changesets: verify their hg trees, and check that the \
+            file contents they reference are present, scrubbing each read across the stores of \
+            a multiplexed blobstore. Run with --skip-caching so that reads reach the stores.",
+        )
+        .arg(
+            Arg::with_name(ARG_START)
+                .long(ARG_START)
+                .takes_value(true)
+                .required(true)
+                .help("bookmark or changeset whose ancestors are audited"),
+        )
+        .arg(
+            Arg::with_name(ARG_WINDOW)
+                .long(ARG_WINDOW)
+                .takes_value(true)
+                .required(false)
+                .help("how many of the most recent changesets to sample from [default: 10000]"),
+        )
+        .arg(
+            Arg::with_name(ARG_SAMPLE_SIZE)
+                .long(ARG_SAMPLE_SIZE)
+                .takes_value(true)
+                .required(false)
+                .help("how many changesets to audit in each run [default: 100]"),
+        )
+        .arg(
+            Arg::with_name(ARG_CONCURRENCY)
+                .long(ARG_CONCURRENCY)
+                .takes_value(true)
+                .required(false)
+                .help("how many blobs to check at once [default: 100]"),
+        )
+        .arg(
+            Arg::with_name(ARG_REPORT)
+                .long(ARG_REPORT)
+                .takes_value(true)
+                .required(true)
+                .help("file to append a JSON line to for each audited changeset"),
+        )
+        .arg(
+            Arg::with_name(ARG_COVERAGE_STATE)
+                .long(ARG_COVERAGE_STATE)
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "file recording the changesets audited so far, so that each run audits \
+                    changesets that were not audited yet",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_INTERVAL)
+                .long(ARG_INTERVAL)
+                .takes_value(true)
+                .required(false)
+                .help("keep running, starting a new audit this many seconds after the last one"),
+        )
+        .get_matches();
+
+    let (_, logger, mut runtime) =
+        args::init_mononoke(fb, &matches).context("failed to initialise mononoke")?;
+    let ctx = CoreContext::new_bulk_with_logger(fb, logger.clone());
+
+    let opts = AuditOptions {
+        start: matches
+            .value_of(ARG_START)
+            .ok_or(Error::msg("required parameter `start` is not set"))?,
+        window: args::get_usize(&matches, ARG_WINDOW, 10000),
+        sample_size: args::get_usize(&matches, ARG_SAMPLE_SIZE, 100),
+        concurrency: args::get_usize(&matches, ARG_CONCURRENCY, 100),
+        report_path: Path::new(
+            matches
+                .value_of_os(ARG_REPORT)
+                .ok_or(Error::msg("required parameter `report` is not set"))?,
+        ),
+        coverage_path: matches.value_of_os(ARG_COVERAGE_STATE).map(Path::new),
+    };
+    let interval = args::get_u64_opt(&matches, ARG_INTERVAL).map(Duration::from_secs);
+
+    let stats = runtime.block_on(async {
+        // Open the repo without redaction, redacted content must be audited too.
+        let repo = args::open_repo_unredacted(fb, &logger, &matches).await?;
+        loop {
+            let stats = run_audit(&ctx, &logger, &repo, &opts).await?;
+            match interval {
+                Some(interval) => tokio::time::delay_for(interval).await,
+                None => return Ok::<_, Error>(stats),
+            }
+        }
+    })?;
+
+    // Use the same exit codes as bonsai_verify.
+    if stats.errors > 0 {
+        process::exit(2)
+    } else if stats.invalid > 0 {
+        process::exit(1)
+    }
+    Ok(())
+}
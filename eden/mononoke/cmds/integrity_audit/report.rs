@@ -0,0 +1,93 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Context, Result};
+use mononoke_types::ChangesetId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, LineWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Audit report, one JSON object per line. The report is appended to, so that a scheduled job
+/// can keep a single report across runs, and lines are flushed as they are written.
+pub struct AuditReport {
+    path: PathBuf,
+    writer: LineWriter<File>,
+}
+
+impl AuditReport {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("While opening audit report {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: LineWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, entry: &impl Serialize) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.writer
+            .write_all(&line)
+            .with_context(|| format!("While writing audit report {}", self.path.display()))
+    }
+}
+
+/// The changesets audited by previous runs, with the time of their audit, so that each run picks
+/// changesets that have not been audited yet.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Coverage {
+    audited: BTreeMap<String, i64>,
+}
+
+impl Coverage {
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("While parsing coverage state {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => {
+                Err(e).with_context(|| format!("While reading coverage state {}", path.display()))
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // Write a new file and rename it, so that an interrupted run doesn't lose the state.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .with_context(|| format!("While writing coverage state {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("While writing coverage state {}", path.display()))
+    }
+
+    pub fn is_audited(&self, cs_id: &ChangesetId) -> bool {
+        self.audited.contains_key(&cs_id.to_string())
+    }
+
+    pub fn mark_audited(&mut self, cs_id: &ChangesetId, timestamp: i64) {
+        self.audited.insert(cs_id.to_string(), timestamp);
+    }
+
+    /// Forget the changesets that are not in the window anymore, and return how many of the
+    /// window's changesets have been audited.
+    pub fn retain_window(&mut self, window: &[ChangesetId]) -> usize {
+        let window: HashSet<_> = window.iter().map(|cs_id| cs_id.to_string()).collect();
+        self.audited.retain(|cs_id, _| window.contains(cs_id));
+        self.audited.len()
+    }
+
+    /// Start over, once every changeset of the window has been audited.
+    pub fn clear(&mut self) {
+        self.audited.clear();
+    }
+}
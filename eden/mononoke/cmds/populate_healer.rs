@@ -348,9 +348,7 @@ async fn populate_healer_queue(
     let mut state = get_resume_state(blobstore.clone(), &config).await?;
     let mut token = state.current_range.clone();
     loop {
-        let entries = blobstore
-            .enumerate(&config.ctx, &state.current_range)
-            .await?;
+        let entries = blobstore.enumerate(&config.ctx, &token).await?;
         state = state.with_current_many(token, entries.keys.len());
         if !config.dry_run {
             let src_blobstore_id = config.src_blobstore_id;
@@ -0,0 +1,244 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Expires scratch (infinitepush) bookmarks whose commit is older than the retention period.
+//!
+//! Scratch bookmarks don't record when they were last moved, so the age of a bookmark is the age
+//! of the commit it points to. Draft commits which are only reachable from expired bookmarks are
+//! reported, but stay in the blobstore, which is append-only.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::{format_err, Error};
+use blobrepo::BlobRepo;
+use blobstore::Loadable;
+use bookmarks::{BookmarkKind, BookmarkName, BookmarkPagination, BookmarkPrefix, Freshness};
+use clap::Arg;
+use cmdlib::{
+    args::{self, MononokeMatches},
+    helpers,
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use futures::{compat::Stream01CompatExt, future, stream::TryStreamExt};
+use mononoke_types::{ChangesetId, DateTime};
+use revset::DifferenceOfUnionsOfAncestorsNodeStream;
+use skiplist::SkiplistIndex;
+use slog::{info, warn};
+
+const ARG_RETENTION_DAYS: &str = "retention-days";
+const ARG_EXEMPT_USER: &str = "exempt-user";
+const ARG_DRY_RUN: &str = "dry-run";
+const ARG_CONCURRENCY: &str = "concurrency";
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+struct ScratchBookmark {
+    name: BookmarkName,
+    cs_id: ChangesetId,
+    /// Seconds since the commit the bookmark points to was made
+    age: i64,
+}
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let app = args::MononokeAppBuilder::new("Scratch bookmarks GC")
+        .with_advanced_args_hidden()
+        .build()
+        .about(concat!(
+            "Delete scratch bookmarks which point to commits older than the retention period, ",
+            "and report the draft commits only they reference.",
+        ))
+        .arg(
+            Arg::with_name(ARG_RETENTION_DAYS)
+                .long(ARG_RETENTION_DAYS)
+                .takes_value(true)
+                .required(true)
+                .help("scratch bookmarks pointing to commits older than this many days expire"),
+        )
+        .arg(
+            Arg::with_name(ARG_EXEMPT_USER)
+                .long(ARG_EXEMPT_USER)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help(concat!(
+                    "user whose scratch bookmarks never expire, i.e. bookmarks with this user ",
+                    "as one of the components of their name (e.g. scratch/<user>/feature)",
+                )),
+        )
+        .arg(
+            Arg::with_name(ARG_DRY_RUN)
+                .long(ARG_DRY_RUN)
+                .help("only report the bookmarks and commits which would expire"),
+        )
+        .arg(
+            Arg::with_name(ARG_CONCURRENCY)
+                .long(ARG_CONCURRENCY)
+                .takes_value(true)
+                .default_value("100")
+                .help("number of commits loaded at once"),
+        );
+    let matches = app.get_matches();
+
+    let logger = args::init_logging(fb, &matches)?;
+    args::init_cachelib(fb, &matches);
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+    helpers::block_execute(
+        run(ctx, &matches),
+        fb,
+        "scratch_bookmarks_gc",
+        &logger,
+        &matches,
+        cmdlib::monitoring::AliveService,
+    )
+}
+
+async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(), Error> {
+    let retention_days = args::get_i64_opt(matches, ARG_RETENTION_DAYS)
+        .ok_or_else(|| format_err!("--{} is required", ARG_RETENTION_DAYS))?;
+    let exempt_users: HashSet<&str> = matches
+        .values_of(ARG_EXEMPT_USER)
+        .into_iter()
+        .flatten()
+        .collect();
+    let dry_run = matches.is_present(ARG_DRY_RUN);
+    let concurrency = args::get_usize(matches, ARG_CONCURRENCY, 100);
+
+    let repo = args::open_repo(ctx.fb, ctx.logger(), matches).await?;
+
+    let scratch_bookmarks = list_scratch_bookmarks(&ctx, &repo, concurrency).await?;
+    let (expired, kept): (Vec<_>, Vec<_>) = scratch_bookmarks.into_iter().partition(|bookmark| {
+        bookmark.age > retention_days * SECONDS_PER_DAY && !is_exempt(&bookmark.name, &exempt_users)
+    });
+    info!(
+        ctx.logger(),
+        "{} scratch bookmarks expire, {} are kept",
+        expired.len(),
+        kept.len()
+    );
+
+    let draft_count = count_exclusive_draft_commits(&ctx, &repo, &expired, &kept).await?;
+    info!(
+        ctx.logger(),
+        "{} draft commits are only referenced by expired scratch bookmarks", draft_count
+    );
+
+    let mut deleted = 0;
+    for bookmark in &expired {
+        info!(
+            ctx.logger(),
+            "{} {} ({} days old)",
+            if dry_run { "would delete" } else { "deleting" },
+            bookmark.name,
+            bookmark.age / SECONDS_PER_DAY
+        );
+        if dry_run {
+            continue;
+        }
+        let mut txn = repo.update_bookmark_transaction(ctx.clone());
+        txn.delete_scratch(&bookmark.name, bookmark.cs_id)?;
+        if txn.commit().await? {
+            deleted += 1;
+        } else {
+            warn!(
+                ctx.logger(),
+                "{} has moved since it was listed, not deleting it", bookmark.name
+            );
+        }
+    }
+    if !dry_run {
+        info!(ctx.logger(), "deleted {} scratch bookmarks", deleted);
+    }
+    Ok(())
+}
+
+async fn list_scratch_bookmarks(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    concurrency: usize,
+) -> Result<Vec<ScratchBookmark>, Error> {
+    let now = DateTime::now().timestamp_secs();
+    repo.bookmarks()
+        .list(
+            ctx.clone(),
+            Freshness::MostRecent,
+            &BookmarkPrefix::empty(),
+            &[BookmarkKind::Scratch],
+            &BookmarkPagination::FromStart,
+            std::u64::MAX,
+        )
+        .map_ok(|(bookmark, cs_id)| async move {
+            let bonsai = cs_id.load(ctx, repo.blobstore()).await?;
+            let date = bonsai
+                .committer_date()
+                .unwrap_or_else(|| bonsai.author_date());
+            Ok::<_, Error>(ScratchBookmark {
+                name: bookmark.name,
+                cs_id,
+                age: now - date.timestamp_secs(),
+            })
+        })
+        .try_buffer_unordered(concurrency)
+        .try_collect()
+        .await
+}
+
+/// Scratch bookmarks are namespaced by user, e.g. scratch/<user>/feature, or
+/// infinitepush/backups/<user>/<host>/... for commit cloud backups
+fn is_exempt(name: &BookmarkName, exempt_users: &HashSet<&str>) -> bool {
+    name.as_str()
+        .split('/')
+        .any(|component| exempt_users.contains(component))
+}
+
+/// Number of draft commits which are ancestors of expired bookmarks, but not of kept scratch
+/// bookmarks or publishing bookmarks
+async fn count_exclusive_draft_commits(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    expired: &[ScratchBookmark],
+    kept: &[ScratchBookmark],
+) -> Result<usize, Error> {
+    if expired.is_empty() {
+        return Ok(0);
+    }
+    let publishing_heads: Vec<ChangesetId> = repo
+        .bookmarks()
+        .list(
+            ctx.clone(),
+            Freshness::MostRecent,
+            &BookmarkPrefix::empty(),
+            BookmarkKind::ALL_PUBLISHING,
+            &BookmarkPagination::FromStart,
+            std::u64::MAX,
+        )
+        .map_ok(|(_bookmark, cs_id)| cs_id)
+        .try_collect()
+        .await?;
+
+    let heads = expired.iter().map(|bookmark| bookmark.cs_id).collect();
+    let excludes = kept
+        .iter()
+        .map(|bookmark| bookmark.cs_id)
+        .chain(publishing_heads)
+        .collect();
+    let drafts = DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
+        ctx.clone(),
+        &repo.get_changeset_fetcher(),
+        Arc::new(SkiplistIndex::new()),
+        heads,
+        excludes,
+    )
+    .compat()
+    .try_fold(0, |count, _cs_id| future::ok(count + 1))
+    .await?;
+    Ok(drafts)
+}
@@ -19,6 +19,7 @@ use metaconfig_types::{BlobConfig, BlobstoreId, ShardableRemoteDatabaseConfig};
 
 mod subcommand_log_size;
 mod subcommand_mark;
+mod subcommand_sweep;
 
 const ARG_STORAGE_CONFIG_NAME: &str = "storage-config-name";
 const ARG_SCHEDULED_MAX: &str = "scheduled-max";
@@ -69,6 +70,7 @@ fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
         )
         .subcommand(subcommand_mark::build_subcommand())
         .subcommand(subcommand_log_size::build_subcommand())
+        .subcommand(subcommand_sweep::build_subcommand())
 }
 
 fn remove_wrapper_blobconfigs(mut blob_config: BlobConfig) -> BlobConfig {
@@ -204,6 +206,17 @@ fn main(fb: FacebookInit) -> Result<()> {
                 )
                 .await
             }
+            (subcommand_sweep::SWEEP, Some(sub_m)) => {
+                subcommand_sweep::subcommand_sweep(
+                    fb,
+                    logger,
+                    sub_m,
+                    max_parallelism,
+                    blobstore,
+                    shard_range,
+                )
+                .await
+            }
             _ => Err(anyhow!(matches.usage().to_string())),
         }
     })
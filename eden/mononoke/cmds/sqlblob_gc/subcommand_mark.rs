@@ -5,9 +5,15 @@
  * GNU General Public License version 2.
  */
 
-use std::{ops::Range, sync::Arc, time::Duration};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    ops::Range,
+    sync::Arc,
+    time::Duration,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use clap::{App, Arg, ArgMatches, SubCommand};
 use fbinit::FacebookInit;
 use futures::{
@@ -24,6 +30,7 @@ use sqlblob::Sqlblob;
 pub const MARK_SAFE: &str = "mark";
 const ARG_INITIAL_GENERATION_ONLY: &str = "initial-generation-only";
 const ARG_SKIP_INITIAL_GENERATION: &str = "skip-initial-generation";
+const ARG_KEYS_FILE: &str = "keys-file";
 
 const MIN_RETRY_DELAY: Duration = Duration::from_millis(10);
 const MAX_RETRY_DELAY: Duration = Duration::from_millis(100);
@@ -46,6 +53,13 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
                 .required(false)
                 .help("Only do the sweep; do not set generation on blobs with no generation set yet.")
         )
+        .arg(
+            Arg::with_name(ARG_KEYS_FILE)
+                .long(ARG_KEYS_FILE)
+                .takes_value(true)
+                .required(false)
+                .help("Only mark the keys listed in this file, one per line, instead of every key in the data table. Use the keys the walker wrote with --reachable-keys-output, so that the sweep subcommand can delete the rest.")
+        )
 }
 
 async fn handle_one_key(key: String, store: Arc<Sqlblob>) -> Result<()> {
@@ -132,19 +146,36 @@ pub async fn subcommand_mark<'a>(
         (tx, task)
     };
 
-    // Foreach shard in shard_range
-    for shard in shard_range {
-        info!(logger, "Starting sweep on data keys from shard {}", shard);
-        let res = sqlblob
-            .get_keys_from_shard(shard)
+    if let Some(keys_file) = sub_matches.value_of(ARG_KEYS_FILE) {
+        info!(logger, "Starting sweep on keys from {}", keys_file);
+        let file = File::open(keys_file)
+            .with_context(|| format!("Failed to open keys file {}", keys_file))?;
+        let keys = BufReader::new(file)
+            .lines()
+            .map(|line| line.map_err(anyhow::Error::from));
+        let res = stream::iter(keys)
             .forward(key_channel.clone().sink_err_into())
             .await;
-        // Report processing errors ahead of key errors - that way, we don't lose the error if the channel goes away because of an error
         if res.is_err() {
             std::mem::drop(key_channel);
             processor.await??;
             return res;
         }
+    } else {
+        // Foreach shard in shard_range
+        for shard in shard_range {
+            info!(logger, "Starting sweep on data keys from shard {}", shard);
+            let res = sqlblob
+                .get_keys_from_shard(shard)
+                .forward(key_channel.clone().sink_err_into())
+                .await;
+            // Report processing errors ahead of key errors - that way, we don't lose the error if the channel goes away because of an error
+            if res.is_err() {
+                std::mem::drop(key_channel);
+                processor.await??;
+                return res;
+            }
+        }
     }
 
     // Drop the spare sender so that the processor task can exit
@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::ops::Range;
+
+use anyhow::{bail, Result};
+use bytesize::ByteSize;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use fbinit::FacebookInit;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use slog::{info, Logger};
+
+use sqlblob::Sqlblob;
+
+pub const SWEEP: &str = "sweep";
+const ARG_UNREACHABLE_GENERATIONS: &str = "unreachable-generations";
+const ARG_DRY_RUN: &str = "dry-run";
+const ARG_BATCH_SIZE: &str = "batch-size";
+
+const DEFAULT_UNREACHABLE_GENERATIONS: u64 = 2;
+const DEFAULT_BATCH_SIZE: u64 = 1000;
+
+pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(SWEEP)
+        .about("delete chunks that recent marks did not reach")
+        .arg(
+            Arg::with_name(ARG_UNREACHABLE_GENERATIONS)
+                .long(ARG_UNREACHABLE_GENERATIONS)
+                .takes_value(true)
+                .required(false)
+                .help("Delete chunks not marked in this many mark generations, up to the delete generation. Default 2."),
+        )
+        .arg(
+            Arg::with_name(ARG_DRY_RUN)
+                .long(ARG_DRY_RUN)
+                .takes_value(false)
+                .required(false)
+                .help("Only report how many bytes would be reclaimed"),
+        )
+        .arg(
+            Arg::with_name(ARG_BATCH_SIZE)
+                .long(ARG_BATCH_SIZE)
+                .takes_value(true)
+                .required(false)
+                .help("Number of chunks to delete in each query. Default 1000."),
+        )
+}
+
+async fn sweep_shard(
+    logger: &Logger,
+    sqlblob: &Sqlblob,
+    shard: usize,
+    below_generation: u64,
+    batch_size: u64,
+) -> Result<u64> {
+    let mut deleted = 0;
+    loop {
+        let ids = sqlblob
+            .get_unreachable_chunk_ids(shard, below_generation, batch_size)
+            .await?;
+        if ids.is_empty() {
+            break;
+        }
+        let deleted_now = sqlblob
+            .delete_unreachable_chunks(shard, &ids, below_generation)
+            .await?;
+        deleted += deleted_now;
+        info!(
+            logger,
+            "Deleted {} chunks from shard {} ({} in total)", deleted_now, shard, deleted
+        );
+    }
+    Ok(deleted)
+}
+
+pub async fn subcommand_sweep<'a>(
+    _fb: FacebookInit,
+    logger: Logger,
+    sub_matches: &'a ArgMatches<'_>,
+    max_parallelism: usize,
+    sqlblob: Sqlblob,
+    shard_range: Range<usize>,
+) -> Result<()> {
+    let unreachable_generations = sub_matches
+        .value_of(ARG_UNREACHABLE_GENERATIONS)
+        .map_or(Ok(DEFAULT_UNREACHABLE_GENERATIONS), str::parse::<u64>)?;
+    if unreachable_generations == 0 {
+        bail!(
+            "--{} must be at least 1, or chunks marked by the current mark would be deleted",
+            ARG_UNREACHABLE_GENERATIONS
+        );
+    }
+    let batch_size = sub_matches
+        .value_of(ARG_BATCH_SIZE)
+        .map_or(Ok(DEFAULT_BATCH_SIZE), str::parse::<u64>)?;
+
+    let below_generation = sqlblob.sweep_below_generation(unreachable_generations);
+    info!(
+        logger,
+        "Sweeping chunks last seen before generation {}", below_generation
+    );
+
+    // Account for what will be reclaimed before deleting anything
+    let reclaimable = stream::iter(shard_range.clone().map(|shard| {
+        let sqlblob = &sqlblob;
+        async move {
+            let sizes = sqlblob.get_chunk_sizes_by_generation(shard).await?;
+            let reclaimable: u64 = sizes
+                .into_iter()
+                .filter_map(|(generation, size)| match generation {
+                    Some(generation) if generation < below_generation => Some(size),
+                    _ => None,
+                })
+                .sum();
+            Ok::<_, anyhow::Error>(reclaimable)
+        }
+    }))
+    .buffer_unordered(max_parallelism)
+    .try_fold(0u64, |acc, size| async move { Ok(acc + size) })
+    .await?;
+    info!(
+        logger,
+        "{} reclaimable",
+        ByteSize::b(reclaimable).to_string_as(true)
+    );
+
+    if sub_matches.is_present(ARG_DRY_RUN) {
+        return Ok(());
+    }
+
+    let deleted = stream::iter(
+        shard_range
+            .map(|shard| sweep_shard(&logger, &sqlblob, shard, below_generation, batch_size)),
+    )
+    .buffer_unordered(max_parallelism)
+    .try_fold(0u64, |acc, deleted| async move { Ok(acc + deleted) })
+    .await?;
+    info!(logger, "Completed sweep, deleted {} chunks", deleted);
+    Ok(())
+}
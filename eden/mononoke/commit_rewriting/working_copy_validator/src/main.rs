@@ -0,0 +1,268 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+/// Mononoke megarepo working copy validator
+///
+/// Walks the commits of a small repo bookmark, and checks that the working copy of each of them
+/// is equivalent to the working copy of the large repo commit it was synced to.
+use anyhow::{format_err, Error};
+use bookmarks::BookmarkName;
+use clap::Arg;
+use cmdlib::{args, helpers, monitoring};
+use cmdlib_x_repo::create_commit_syncers_from_matches;
+use context::CoreContext;
+use cross_repo_sync::CommitSyncer;
+use fbinit::FacebookInit;
+use futures::{
+    compat::Stream01CompatExt,
+    stream::{StreamExt, TryStreamExt},
+};
+use mononoke_types::ChangesetId;
+use revset::DifferenceOfUnionsOfAncestorsNodeStream;
+use skiplist::SkiplistIndex;
+use slog::{error, info};
+use stats::prelude::*;
+use std::{sync::Arc, time::Duration};
+use synced_commit_mapping::SyncedCommitMapping;
+
+mod validation;
+
+use crate::validation::{validate_commit, CommitValidation, ValidatedCommits};
+
+const ARG_BOOKMARK: &str = "bookmark";
+const ARG_LIMIT: &str = "limit";
+const ARG_CONTINUOUS: &str = "continuous";
+const ARG_SLEEP_SECS: &str = "sleep-secs";
+
+define_stats! {
+    prefix = "mononoke.working_copy_validator";
+    validated_commits: dynamic_timeseries(
+        "{}.{}.validated",
+        (large_repo_name: String, small_repo_name: String);
+        Rate, Sum
+    ),
+    divergent_commits: dynamic_timeseries(
+        "{}.{}.divergent",
+        (large_repo_name: String, small_repo_name: String);
+        Rate, Sum
+    ),
+}
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let app_name = concat!(
+        "Tool to validate that small repo commits have the same working copies ",
+        "as the large repo commits they were synced to",
+    );
+    let app = args::MononokeAppBuilder::new(app_name)
+        .with_source_and_target_repos()
+        .with_fb303_args()
+        .build()
+        .arg(
+            Arg::with_name(ARG_BOOKMARK)
+                .long(ARG_BOOKMARK)
+                .takes_value(true)
+                .default_value("master")
+                .help("small repo bookmark whose commits are validated"),
+        )
+        .arg(
+            Arg::with_name(ARG_LIMIT)
+                .long(ARG_LIMIT)
+                .takes_value(true)
+                .default_value("1000")
+                .help("maximum number of commits validated at once, newest first"),
+        )
+        .arg(
+            Arg::with_name(ARG_CONTINUOUS)
+                .long(ARG_CONTINUOUS)
+                .help("keep validating the commits the bookmark moves to"),
+        )
+        .arg(
+            Arg::with_name(ARG_SLEEP_SECS)
+                .long(ARG_SLEEP_SECS)
+                .takes_value(true)
+                .default_value("60")
+                .requires(ARG_CONTINUOUS)
+                .help("how long to wait between checks of the bookmark"),
+        );
+    let matches = app.get_matches();
+    let (_, logger, mut runtime) = args::init_mononoke(fb, &matches)?;
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+    let config_store = args::init_config_store(fb, &logger, &matches)?;
+    let source_repo_id = args::get_source_repo_id(config_store, &matches)?;
+
+    let syncers = runtime.block_on(create_commit_syncers_from_matches(&ctx, &matches))?;
+    if syncers.small_to_large.get_small_repo().get_repoid() != source_repo_id {
+        return Err(format_err!("Source repo must be a small repo!"));
+    }
+
+    let bookmark = BookmarkName::new(
+        matches
+            .value_of(ARG_BOOKMARK)
+            .ok_or_else(|| format_err!("{} is not set", ARG_BOOKMARK))?,
+    )?;
+    let limit = args::get_usize(&matches, ARG_LIMIT, 1000);
+    let sleep = if matches.is_present(ARG_CONTINUOUS) {
+        Some(Duration::from_secs(args::get_u64(
+            &matches,
+            ARG_SLEEP_SECS,
+            60,
+        )))
+    } else {
+        None
+    };
+
+    helpers::block_execute(
+        run(ctx, syncers.small_to_large, bookmark, limit, sleep),
+        fb,
+        "megarepo_working_copy_validator",
+        &logger,
+        &matches,
+        monitoring::AliveService,
+    )
+}
+
+/// Validate the commits of `bookmark`, then, if `sleep` is set, keep validating the new commits
+/// it moves to. Commits which were not synced yet are retried on the next iteration.
+async fn run<M: SyncedCommitMapping + Clone + 'static>(
+    ctx: CoreContext,
+    small_to_large: CommitSyncer<M>,
+    bookmark: BookmarkName,
+    limit: usize,
+    sleep: Option<Duration>,
+) -> Result<(), Error> {
+    let small_repo = small_to_large.get_small_repo();
+    let mut validated = ValidatedCommits::new();
+    let mut last_done = None;
+    loop {
+        let head = small_repo
+            .get_bonsai_bookmark(ctx.clone(), &bookmark)
+            .await?
+            .ok_or_else(|| format_err!("{} is not set in {}", bookmark, small_repo.name()))?;
+
+        let mut divergent_count = 0;
+        if last_done != Some(head) {
+            let commits = new_commits(&ctx, &small_to_large, head, last_done, limit).await?;
+            info!(ctx.logger(), "validating {} commits", commits.len());
+            for cs_id in commits {
+                match validate_and_report(&ctx, &small_to_large, &validated, cs_id).await? {
+                    CommitValidation::NotSynced => {
+                        info!(
+                            ctx.logger(),
+                            "{} is not synced yet, stopping there for now", cs_id
+                        );
+                        break;
+                    }
+                    CommitValidation::Equivalent {
+                        large_cs_id,
+                        version,
+                    } => {
+                        validated.insert(cs_id, (large_cs_id, version));
+                    }
+                    CommitValidation::Divergent { .. } => {
+                        divergent_count += 1;
+                    }
+                    CommitValidation::NotSyncCandidate => {}
+                }
+                last_done = Some(cs_id);
+            }
+        }
+
+        match sleep {
+            Some(sleep) => {
+                // Only the last commit is needed to validate the next ones incrementally
+                validated.retain(|cs_id, _| Some(*cs_id) == last_done);
+                tokio::time::delay_for(sleep).await;
+            }
+            None if divergent_count > 0 => {
+                return Err(format_err!(
+                    "{} commits have working copies which diverge from the large repo",
+                    divergent_count
+                ));
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Ancestors of `head` which are not ancestors of `last_done`, parents first
+async fn new_commits<M: SyncedCommitMapping + Clone + 'static>(
+    ctx: &CoreContext,
+    small_to_large: &CommitSyncer<M>,
+    head: ChangesetId,
+    last_done: Option<ChangesetId>,
+    limit: usize,
+) -> Result<Vec<ChangesetId>, Error> {
+    let mut commits: Vec<_> = DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
+        ctx.clone(),
+        &small_to_large.get_small_repo().get_changeset_fetcher(),
+        Arc::new(SkiplistIndex::new()),
+        vec![head],
+        last_done.into_iter().collect(),
+    )
+    .compat()
+    .take(limit)
+    .try_collect()
+    .await?;
+    if commits.len() == limit {
+        info!(
+            ctx.logger(),
+            "only validating the newest {} commits of {}", limit, head
+        );
+    }
+    // The stream goes from children to parents
+    commits.reverse();
+    Ok(commits)
+}
+
+async fn validate_and_report<M: SyncedCommitMapping + Clone + 'static>(
+    ctx: &CoreContext,
+    small_to_large: &CommitSyncer<M>,
+    validated: &ValidatedCommits,
+    small_cs_id: ChangesetId,
+) -> Result<CommitValidation, Error> {
+    let large_repo_name = small_to_large.get_large_repo().name();
+    let small_repo_name = small_to_large.get_small_repo().name();
+
+    let validation = validate_commit(ctx, small_to_large, validated, small_cs_id).await?;
+    match &validation {
+        CommitValidation::Equivalent { large_cs_id, .. } => {
+            STATS::validated_commits
+                .add_value(1, (large_repo_name.clone(), small_repo_name.clone()));
+            info!(
+                ctx.logger(),
+                "{} in {} is equivalent to {} in {}",
+                small_cs_id,
+                small_repo_name,
+                large_cs_id,
+                large_repo_name
+            );
+        }
+        CommitValidation::Divergent {
+            large_cs_id,
+            reason,
+        } => {
+            STATS::validated_commits
+                .add_value(1, (large_repo_name.clone(), small_repo_name.clone()));
+            STATS::divergent_commits
+                .add_value(1, (large_repo_name.clone(), small_repo_name.clone()));
+            error!(
+                ctx.logger(),
+                "{} in {} diverges from {} in {}: {}",
+                small_cs_id,
+                small_repo_name,
+                large_cs_id,
+                large_repo_name,
+                reason
+            );
+        }
+        CommitValidation::NotSynced | CommitValidation::NotSyncCandidate => {}
+    }
+    Ok(validation)
+}
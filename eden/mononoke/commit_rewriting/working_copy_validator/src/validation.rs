@@ -0,0 +1,309 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use blobrepo::BlobRepo;
+use blobstore::Loadable;
+use context::CoreContext;
+use cross_repo_sync::{
+    types::{Source, Target},
+    validation::{verify_working_copy_inner, PrefixesToVisit},
+    CommitSyncOutcome, CommitSyncer,
+};
+use derived_data::BonsaiDerived;
+use fsnodes::RootFsnodeId;
+use futures::{future, try_join, TryStreamExt};
+use manifest::{Diff, Entry, ManifestOps};
+use metaconfig_types::CommitSyncConfigVersion;
+use mononoke_types::{ChangesetId, ContentId, FileType, MPath};
+use movers::Mover;
+use ref_cast::RefCast;
+use std::collections::{BTreeSet, HashMap};
+use synced_commit_mapping::SyncedCommitMapping;
+
+/// Maximum number of divergent paths listed in a report
+const MAX_REPORTED_PATHS: usize = 10;
+
+/// Small repo commits whose working copy was found to be equivalent to the working copy of the
+/// large repo commit they were synced to, with that commit and the version of the mapping
+pub type ValidatedCommits = HashMap<ChangesetId, (ChangesetId, CommitSyncConfigVersion)>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommitValidation {
+    /// The commit was not synced to the large repo yet
+    NotSynced,
+    /// The commit is not synced to the large repo at all
+    NotSyncCandidate,
+    Equivalent {
+        large_cs_id: ChangesetId,
+        version: CommitSyncConfigVersion,
+    },
+    Divergent {
+        large_cs_id: ChangesetId,
+        reason: String,
+    },
+}
+
+/// Changes made to the files of a commit, with None for removed files
+type FileChanges = HashMap<MPath, Option<(FileType, ContentId)>>;
+
+/// Check that the working copy of a small repo commit is equivalent to the working copy of the
+/// large repo commit it was synced to.
+///
+/// If the first parent of the commit was validated already, with the same mapping version, only
+/// the manifest diffs of the two commits against their parents are compared. Otherwise the whole
+/// working copies are, and any error doing so is reported as a divergence.
+pub async fn validate_commit<M: SyncedCommitMapping + Clone + 'static>(
+    ctx: &CoreContext,
+    small_to_large: &CommitSyncer<M>,
+    validated: &ValidatedCommits,
+    small_cs_id: ChangesetId,
+) -> Result<CommitValidation, Error> {
+    use CommitSyncOutcome::*;
+
+    let (large_cs_id, version) = match small_to_large
+        .get_commit_sync_outcome(ctx, small_cs_id)
+        .await?
+    {
+        None => return Ok(CommitValidation::NotSynced),
+        Some(NotSyncCandidate) => return Ok(CommitValidation::NotSyncCandidate),
+        Some(RewrittenAs(cs_id, version)) | Some(EquivalentWorkingCopyAncestor(cs_id, version)) => {
+            (cs_id, version)
+        }
+    };
+
+    let small_repo = small_to_large.get_small_repo();
+    let large_repo = small_to_large.get_large_repo();
+    let mover = small_to_large.get_mover_by_version(&version).await?;
+    let reverse_mover = small_to_large
+        .get_reverse_mover_by_version(&version)
+        .await?;
+
+    let bonsai = small_cs_id.load(ctx, small_repo.blobstore()).await?;
+    let validated_parent = bonsai.parents().next().and_then(|parent| {
+        let (large_parent, parent_version) = validated.get(&parent)?;
+        if *parent_version == version {
+            Some((parent, *large_parent))
+        } else {
+            None
+        }
+    });
+
+    match validated_parent {
+        Some((small_parent, large_parent)) => {
+            let divergent_paths = compare_file_changes(
+                ctx,
+                (small_repo, small_parent, small_cs_id),
+                (large_repo, large_parent, large_cs_id),
+                &mover,
+                &reverse_mover,
+            )
+            .await?;
+            if divergent_paths.is_empty() {
+                return Ok(CommitValidation::Equivalent {
+                    large_cs_id,
+                    version,
+                });
+            }
+            let reported: Vec<_> = divergent_paths
+                .iter()
+                .take(MAX_REPORTED_PATHS)
+                .map(ToString::to_string)
+                .collect();
+            Ok(CommitValidation::Divergent {
+                large_cs_id,
+                reason: format!(
+                    "{} paths are changed differently, including {}",
+                    divergent_paths.len(),
+                    reported.join(", ")
+                ),
+            })
+        }
+        None => {
+            let res = verify_working_copy_inner(
+                ctx,
+                Source::ref_cast(small_repo),
+                Target::ref_cast(large_repo),
+                Source(small_cs_id),
+                Target(large_cs_id),
+                &mover,
+                &reverse_mover,
+                PrefixesToVisit::default(),
+            )
+            .await;
+            match res {
+                Ok(()) => Ok(CommitValidation::Equivalent {
+                    large_cs_id,
+                    version,
+                }),
+                Err(err) => Ok(CommitValidation::Divergent {
+                    large_cs_id,
+                    reason: format!("{:#}", err),
+                }),
+            }
+        }
+    }
+}
+
+/// Large repo paths which the large repo commit changes differently than the small repo commit,
+/// given that the parents of both commits have equivalent working copies
+async fn compare_file_changes(
+    ctx: &CoreContext,
+    (small_repo, small_parent, small_cs_id): (&BlobRepo, ChangesetId, ChangesetId),
+    (large_repo, large_parent, large_cs_id): (&BlobRepo, ChangesetId, ChangesetId),
+    mover: &Mover,
+    reverse_mover: &Mover,
+) -> Result<Vec<MPath>, Error> {
+    let (small_changes, large_changes) = try_join!(
+        list_file_changes(ctx, small_repo, small_parent, small_cs_id),
+        list_file_changes(ctx, large_repo, large_parent, large_cs_id),
+    )?;
+
+    let mut moved_small_changes = HashMap::new();
+    for (path, change) in small_changes {
+        if let Some(path) = mover(&path)? {
+            moved_small_changes.insert(path, change);
+        }
+    }
+
+    let mut divergent_paths = BTreeSet::new();
+    for (path, change) in large_changes {
+        // Paths which don't map to the small repo belong to other repos
+        if reverse_mover(&path)?.is_none() {
+            continue;
+        }
+        if moved_small_changes.remove(&path) != Some(change) {
+            divergent_paths.insert(path);
+        }
+    }
+    divergent_paths.extend(moved_small_changes.into_iter().map(|(path, _)| path));
+    Ok(divergent_paths.into_iter().collect())
+}
+
+async fn list_file_changes(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    base: ChangesetId,
+    cs_id: ChangesetId,
+) -> Result<FileChanges, Error> {
+    let (base_root, root) = try_join!(
+        RootFsnodeId::derive(ctx, repo, base),
+        RootFsnodeId::derive(ctx, repo, cs_id),
+    )?;
+    base_root
+        .fsnode_id()
+        .diff(ctx.clone(), repo.get_blobstore(), *root.fsnode_id())
+        .try_filter_map(|diff| {
+            let change = match diff {
+                Diff::Added(Some(path), Entry::Leaf(file))
+                | Diff::Changed(Some(path), _, Entry::Leaf(file)) => {
+                    Some((path, Some((*file.file_type(), *file.content_id()))))
+                }
+                Diff::Removed(Some(path), Entry::Leaf(_)) => Some((path, None)),
+                _ => None,
+            };
+            future::ok(change)
+        })
+        .try_collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cross_repo_sync::{update_mapping_with_version, CandidateSelectionHint, CommitSyncContext};
+    use cross_repo_sync_test_utils::init_small_large_repo;
+    use fbinit::FacebookInit;
+    use tests_utils::{resolve_cs_id, CreateCommitContext};
+
+    #[fbinit::test]
+    async fn test_validate_commit(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let (syncers, _) = init_small_large_repo(&ctx).await?;
+        let small_to_large = &syncers.small_to_large;
+        let small_repo = small_to_large.get_small_repo();
+        let large_repo = small_to_large.get_large_repo();
+        let version = small_to_large.get_current_version(&ctx).await?;
+
+        // Nothing was validated yet, so the whole working copies are compared
+        let small_master = resolve_cs_id(&ctx, small_repo, "master").await?;
+        let large_master = resolve_cs_id(&ctx, large_repo, "master").await?;
+        let mut validated = ValidatedCommits::new();
+        assert_eq!(
+            validate_commit(&ctx, small_to_large, &validated, small_master).await?,
+            CommitValidation::Equivalent {
+                large_cs_id: large_master,
+                version: version.clone(),
+            }
+        );
+        validated.insert(small_master, (large_master, version.clone()));
+
+        let small_cs_id = CreateCommitContext::new(&ctx, small_repo, vec![small_master])
+            .add_file("file4", "content4")
+            .delete_file("file3")
+            .commit()
+            .await?;
+        assert_eq!(
+            validate_commit(&ctx, small_to_large, &validated, small_cs_id).await?,
+            CommitValidation::NotSynced
+        );
+
+        // The parent was validated, so only the changes are compared
+        let large_cs_id = small_to_large
+            .sync_commit(
+                &ctx,
+                small_cs_id,
+                CandidateSelectionHint::Only,
+                CommitSyncContext::Tests,
+            )
+            .await?
+            .expect("commit is synced");
+        assert_eq!(
+            validate_commit(&ctx, small_to_large, &validated, small_cs_id).await?,
+            CommitValidation::Equivalent {
+                large_cs_id,
+                version: version.clone(),
+            }
+        );
+        validated.insert(small_cs_id, (large_cs_id, version.clone()));
+
+        // A large repo commit which doesn't match the small repo one
+        let small_cs_id = CreateCommitContext::new(&ctx, small_repo, vec![small_cs_id])
+            .add_file("file5", "content5")
+            .commit()
+            .await?;
+        let bad_large_cs_id = CreateCommitContext::new(&ctx, large_repo, vec![large_cs_id])
+            .add_file("prefix/file5", "other content")
+            .add_file("otherrepo/file", "content")
+            .commit()
+            .await?;
+        update_mapping_with_version(
+            &ctx,
+            vec![(small_cs_id, bad_large_cs_id)].into_iter().collect(),
+            small_to_large,
+            &version,
+        )
+        .await?;
+        match validate_commit(&ctx, small_to_large, &validated, small_cs_id).await? {
+            CommitValidation::Divergent {
+                large_cs_id,
+                reason,
+            } => {
+                assert_eq!(large_cs_id, bad_large_cs_id);
+                assert!(reason.contains("prefix/file5"), "{}", reason);
+            }
+            validation => panic!("unexpected validation outcome {:?}", validation),
+        }
+
+        // The whole working copies differ as well
+        assert!(matches!(
+            validate_commit(&ctx, small_to_large, &ValidatedCommits::new(), small_cs_id).await?,
+            CommitValidation::Divergent { .. }
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+CREATE TABLE commit_stats_daily (
+  repo_id INTEGER NOT NULL,
+  bookmark VARCHAR(512) NOT NULL,
+  day BIGINT NOT NULL,
+  commits BIGINT NOT NULL,
+  file_changes BIGINT NOT NULL,
+  PRIMARY KEY (repo_id, bookmark, day)
+);
+
+CREATE TABLE commit_stats_daily_authors (
+  repo_id INTEGER NOT NULL,
+  bookmark VARCHAR(512) NOT NULL,
+  day BIGINT NOT NULL,
+  author VARCHAR(255) NOT NULL,
+  commits BIGINT NOT NULL,
+  PRIMARY KEY (repo_id, bookmark, day, author)
+);
+
+CREATE TABLE commit_stats_progress (
+  repo_id INTEGER NOT NULL,
+  bookmark VARCHAR(512) NOT NULL,
+  last_log_id BIGINT NOT NULL,
+  bookmark_position BINARY(32) NULL,
+  PRIMARY KEY (repo_id, bookmark)
+);
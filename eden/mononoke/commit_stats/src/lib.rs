@@ -0,0 +1,447 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Commit activity statistics of a bookmark: how many commits landed on it each day, by how many
+//! authors, and how many files they changed. Scanning the history for these is expensive, so they
+//! are computed incrementally by tailing the bookmark update log, and kept in SQL.
+
+mod update;
+
+pub use update::update_commit_stats;
+
+use anyhow::Result;
+use bookmarks::BookmarkName;
+use chrono::{Duration, NaiveDate};
+use context::{CoreContext, PerfCounterType};
+use futures::compat::Future01CompatExt;
+use mononoke_types::{BonsaiChangeset, ChangesetId, RepositoryId, Timestamp};
+use sql::{queries, Connection};
+use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
+use sql_ext::{transaction_with_retries, SqlConnections, TransactionRetryOptions};
+use std::collections::BTreeMap;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Statistics of the commits which landed on a bookmark during a day
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DailyCommitStats {
+    /// The day, in UTC
+    pub day: NaiveDate,
+    pub commits: u64,
+    /// Number of distinct authors of the commits
+    pub authors: u64,
+    /// Sum of the number of files changed by each commit
+    pub file_changes: u64,
+}
+
+/// Number of commits of an author which landed on a bookmark during a range of days
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuthorCommitStats {
+    pub author: String,
+    pub commits: u64,
+}
+
+/// How far the bookmark update log was processed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CommitStatsProgress {
+    /// Id of the last processed entry
+    pub log_id: u64,
+    /// Where the bookmark pointed to after that entry. All its ancestors were counted.
+    pub bookmark_position: Option<ChangesetId>,
+}
+
+/// Statistics of commits which are not stored yet, to be added to the stored ones
+#[derive(Clone, Debug, Default)]
+pub struct CommitStatsDelta {
+    // day -> (commits, file changes)
+    daily: BTreeMap<i64, (u64, u64)>,
+    // (day, author) -> commits
+    authors: BTreeMap<(i64, String), u64>,
+}
+
+impl CommitStatsDelta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a changeset which landed on the day of `landed`
+    pub fn add_changeset(&mut self, landed: Timestamp, bcs: &BonsaiChangeset) {
+        let day = day_of_timestamp(landed);
+        let daily = self.daily.entry(day).or_default();
+        daily.0 += 1;
+        daily.1 += bcs.file_changes().count() as u64;
+        *self
+            .authors
+            .entry((day, bcs.author().to_string()))
+            .or_default() += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.daily.is_empty()
+    }
+
+    pub fn commits(&self) -> u64 {
+        self.daily.values().map(|(commits, _)| commits).sum()
+    }
+}
+
+/// Days are stored as the number of days since the epoch
+fn day_of_timestamp(ts: Timestamp) -> i64 {
+    ts.timestamp_seconds().div_euclid(SECONDS_PER_DAY)
+}
+
+fn epoch() -> NaiveDate {
+    NaiveDate::from_ymd(1970, 1, 1)
+}
+
+fn day_of_date(date: NaiveDate) -> i64 {
+    date.signed_duration_since(epoch()).num_days()
+}
+
+fn date_of_day(day: i64) -> NaiveDate {
+    epoch() + Duration::days(day)
+}
+
+queries! {
+    read SelectProgress(
+        repo_id: RepositoryId,
+        bookmark: BookmarkName
+    ) -> (u64, Option<ChangesetId>) {
+        "SELECT last_log_id, bookmark_position
+         FROM commit_stats_progress
+         WHERE repo_id = {repo_id} AND bookmark = {bookmark}"
+    }
+
+    write InsertProgress(
+        repo_id: RepositoryId,
+        bookmark: BookmarkName,
+        last_log_id: u64,
+        bookmark_position: Option<ChangesetId>
+    ) {
+        insert_or_ignore,
+        "{insert_or_ignore} INTO commit_stats_progress
+           (repo_id, bookmark, last_log_id, bookmark_position)
+         VALUES ({repo_id}, {bookmark}, {last_log_id}, {bookmark_position})"
+    }
+
+    write UpdateProgress(
+        repo_id: RepositoryId,
+        bookmark: BookmarkName,
+        last_log_id: u64,
+        bookmark_position: Option<ChangesetId>,
+        prev_log_id: u64
+    ) {
+        none,
+        "UPDATE commit_stats_progress
+         SET last_log_id = {last_log_id}, bookmark_position = {bookmark_position}
+         WHERE repo_id = {repo_id} AND bookmark = {bookmark} AND last_log_id = {prev_log_id}"
+    }
+
+    write AddDailyStats(values: (
+        repo_id: RepositoryId,
+        bookmark: BookmarkName,
+        day: i64,
+        commits: u64,
+        file_changes: u64,
+    )) {
+        none,
+        mysql("INSERT INTO commit_stats_daily (repo_id, bookmark, day, commits, file_changes)
+               VALUES {values}
+               ON DUPLICATE KEY UPDATE
+                 commits = commits + VALUES(commits),
+                 file_changes = file_changes + VALUES(file_changes)")
+        sqlite("INSERT INTO commit_stats_daily (repo_id, bookmark, day, commits, file_changes)
+                VALUES {values}
+                ON CONFLICT (repo_id, bookmark, day) DO UPDATE SET
+                  commits = commits + excluded.commits,
+                  file_changes = file_changes + excluded.file_changes")
+    }
+
+    write AddDailyAuthorStats(values: (
+        repo_id: RepositoryId,
+        bookmark: BookmarkName,
+        day: i64,
+        author: String,
+        commits: u64,
+    )) {
+        none,
+        mysql("INSERT INTO commit_stats_daily_authors (repo_id, bookmark, day, author, commits)
+               VALUES {values}
+               ON DUPLICATE KEY UPDATE commits = commits + VALUES(commits)")
+        sqlite("INSERT INTO commit_stats_daily_authors (repo_id, bookmark, day, author, commits)
+                VALUES {values}
+                ON CONFLICT (repo_id, bookmark, day, author) DO UPDATE SET
+                  commits = commits + excluded.commits")
+    }
+
+    read SelectDailyStats(
+        repo_id: RepositoryId,
+        bookmark: BookmarkName,
+        min_day: i64,
+        max_day: i64
+    ) -> (i64, u64, u64) {
+        "SELECT day, commits, file_changes
+         FROM commit_stats_daily
+         WHERE repo_id = {repo_id} AND bookmark = {bookmark}
+           AND day >= {min_day} AND day <= {max_day}
+         ORDER BY day"
+    }
+
+    read SelectDailyAuthorCounts(
+        repo_id: RepositoryId,
+        bookmark: BookmarkName,
+        min_day: i64,
+        max_day: i64
+    ) -> (i64, u64) {
+        "SELECT day, COUNT(*)
+         FROM commit_stats_daily_authors
+         WHERE repo_id = {repo_id} AND bookmark = {bookmark}
+           AND day >= {min_day} AND day <= {max_day}
+         GROUP BY day"
+    }
+
+    read SelectTopAuthors(
+        repo_id: RepositoryId,
+        bookmark: BookmarkName,
+        min_day: i64,
+        max_day: i64,
+        limit: u64
+    ) -> (String, u64) {
+        "SELECT author, SUM(commits) AS total
+         FROM commit_stats_daily_authors
+         WHERE repo_id = {repo_id} AND bookmark = {bookmark}
+           AND day >= {min_day} AND day <= {max_day}
+         GROUP BY author
+         ORDER BY total DESC, author
+         LIMIT {limit}"
+    }
+}
+
+#[derive(Clone)]
+pub struct SqlCommitStats {
+    write_connection: Connection,
+    read_connection: Connection,
+    read_master_connection: Connection,
+}
+
+impl SqlConstruct for SqlCommitStats {
+    const LABEL: &'static str = "commit_stats";
+
+    const CREATION_QUERY: &'static str = include_str!("../schemas/sqlite-commit-stats.sql");
+
+    fn from_sql_connections(connections: SqlConnections) -> Self {
+        Self {
+            write_connection: connections.write_connection,
+            read_connection: connections.read_connection,
+            read_master_connection: connections.read_master_connection,
+        }
+    }
+}
+
+impl SqlConstructFromMetadataDatabaseConfig for SqlCommitStats {}
+
+impl SqlCommitStats {
+    /// How far the bookmark update log was processed, if it ever was
+    pub async fn get_progress(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        bookmark: &BookmarkName,
+    ) -> Result<Option<CommitStatsProgress>> {
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlReadsMaster);
+        let rows = SelectProgress::query(&self.read_master_connection, &repo_id, bookmark)
+            .compat()
+            .await?;
+        Ok(rows
+            .into_iter()
+            .next()
+            .map(|(log_id, bookmark_position)| CommitStatsProgress {
+                log_id,
+                bookmark_position,
+            }))
+    }
+
+    /// Add `delta` to the stored statistics, and record that the bookmark update log was
+    /// processed up to `progress`. This happens atomically, and only if the log was processed
+    /// up to `prev_log_id` until now, so that concurrent updates cannot count commits twice.
+    /// Returns whether the statistics were updated.
+    pub async fn add(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        bookmark: &BookmarkName,
+        prev_log_id: Option<u64>,
+        progress: CommitStatsProgress,
+        delta: &CommitStatsDelta,
+    ) -> Result<bool> {
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlWrites);
+
+        // Deadlocks with a concurrent update are retried: the progress check makes sure that
+        // the retry doesn't count the commits twice.
+        let progress = &progress;
+        transaction_with_retries(
+            &self.write_connection,
+            &TransactionRetryOptions::default(),
+            move |txn| async move {
+                let (txn, result) = match prev_log_id {
+                    Some(prev_log_id) => {
+                        UpdateProgress::query_with_transaction(
+                            txn,
+                            &repo_id,
+                            bookmark,
+                            &progress.log_id,
+                            &progress.bookmark_position,
+                            &prev_log_id,
+                        )
+                        .compat()
+                        .await?
+                    }
+                    None => {
+                        InsertProgress::query_with_transaction(
+                            txn,
+                            &repo_id,
+                            bookmark,
+                            &progress.log_id,
+                            &progress.bookmark_position,
+                        )
+                        .compat()
+                        .await?
+                    }
+                };
+                if result.affected_rows() == 0 {
+                    // Nothing was written, so committing is the same as rolling back.
+                    return Ok((txn, false));
+                }
+
+                let mut txn = txn;
+                if !delta.is_empty() {
+                    let daily: Vec<_> = delta
+                        .daily
+                        .iter()
+                        .map(|(day, (commits, file_changes))| {
+                            (&repo_id, bookmark, day, commits, file_changes)
+                        })
+                        .collect();
+                    txn = AddDailyStats::query_with_transaction(txn, &daily[..])
+                        .compat()
+                        .await?
+                        .0;
+
+                    let authors: Vec<_> = delta
+                        .authors
+                        .iter()
+                        .map(|((day, author), commits)| (&repo_id, bookmark, day, author, commits))
+                        .collect();
+                    txn = AddDailyAuthorStats::query_with_transaction(txn, &authors[..])
+                        .compat()
+                        .await?
+                        .0;
+                }
+                Ok((txn, true))
+            },
+        )
+        .await
+    }
+
+    /// Statistics for each day from `since` to `until` included. Days when no commits landed
+    /// are left out.
+    pub async fn get_daily_stats(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        bookmark: &BookmarkName,
+        since: NaiveDate,
+        until: NaiveDate,
+    ) -> Result<Vec<DailyCommitStats>> {
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlReadsReplica);
+        let (min_day, max_day) = (day_of_date(since), day_of_date(until));
+        let (daily, authors) = futures::try_join!(
+            SelectDailyStats::query(
+                &self.read_connection,
+                &repo_id,
+                bookmark,
+                &min_day,
+                &max_day
+            )
+            .compat(),
+            SelectDailyAuthorCounts::query(
+                &self.read_connection,
+                &repo_id,
+                bookmark,
+                &min_day,
+                &max_day
+            )
+            .compat(),
+        )?;
+        let authors: BTreeMap<_, _> = authors.into_iter().collect();
+
+        Ok(daily
+            .into_iter()
+            .map(|(day, commits, file_changes)| DailyCommitStats {
+                day: date_of_day(day),
+                commits,
+                authors: authors.get(&day).copied().unwrap_or(0),
+                file_changes,
+            })
+            .collect())
+    }
+
+    /// The `limit` authors with the most commits from `since` to `until` included
+    pub async fn get_top_authors(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        bookmark: &BookmarkName,
+        since: NaiveDate,
+        until: NaiveDate,
+        limit: u64,
+    ) -> Result<Vec<AuthorCommitStats>> {
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlReadsReplica);
+        let rows = SelectTopAuthors::query(
+            &self.read_connection,
+            &repo_id,
+            bookmark,
+            &day_of_date(since),
+            &day_of_date(until),
+            &limit,
+        )
+        .compat()
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(author, commits)| AuthorCommitStats { author, commits })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day_conversions_test() {
+        assert_eq!(day_of_timestamp(Timestamp::from_timestamp_secs(0)), 0);
+        assert_eq!(
+            day_of_timestamp(Timestamp::from_timestamp_secs(SECONDS_PER_DAY - 1)),
+            0
+        );
+        assert_eq!(day_of_timestamp(Timestamp::from_timestamp_secs(-1)), -1);
+
+        let date = NaiveDate::from_ymd(2020, 11, 3);
+        assert_eq!(date_of_day(day_of_date(date)), date);
+        assert_eq!(
+            day_of_timestamp(Timestamp::from_timestamp_secs(1604361600)),
+            day_of_date(date)
+        );
+    }
+}
@@ -0,0 +1,129 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use blobrepo::BlobRepo;
+use blobstore::Loadable;
+use bookmarks::{BookmarkName, Freshness};
+use context::CoreContext;
+use futures::{compat::Stream01CompatExt, stream::TryStreamExt};
+use mononoke_types::{ChangesetId, Timestamp};
+use revset::DifferenceOfUnionsOfAncestorsNodeStream;
+use skiplist::SkiplistIndex;
+use slog::{debug, info};
+
+use crate::{CommitStatsDelta, CommitStatsProgress, SqlCommitStats};
+
+const LOAD_CONCURRENCY: usize = 100;
+
+/// Count the commits that landed on `bookmark` since the statistics were last updated, reading
+/// up to `batch_size` bookmark update log entries at a time. Returns the number of commits
+/// counted, which is 0 if the statistics were updated concurrently by someone else.
+///
+/// Commits landed by a bookmark move are those it made newly reachable from where the bookmark
+/// was last counted, and they are counted on the day of the move. Moving the bookmark backwards
+/// and forwards again counts the commits in between twice. When the bookmark is first created,
+/// all its ancestors are counted on the day they were authored instead, as the log does not tell
+/// when they landed.
+pub async fn update_commit_stats(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    stats: &SqlCommitStats,
+    bookmark: &BookmarkName,
+    batch_size: u64,
+) -> Result<u64> {
+    let repo_id = repo.get_repoid();
+    let mut counted = 0;
+    let progress = stats.get_progress(ctx, repo_id, bookmark).await?;
+    let mut prev_log_id = progress.map(|progress| progress.log_id);
+    let mut position = progress.and_then(|progress| progress.bookmark_position);
+    loop {
+        let entries: Vec<_> = repo
+            .read_next_bookmark_log_entries(
+                ctx.clone(),
+                prev_log_id.unwrap_or(0),
+                batch_size,
+                Freshness::MostRecent,
+            )
+            .try_collect()
+            .await?;
+        let last_log_id = match entries.last() {
+            Some(entry) => entry.id as u64,
+            None => return Ok(counted),
+        };
+
+        let mut delta = CommitStatsDelta::new();
+        let mut new_position = position;
+        for entry in entries
+            .iter()
+            .filter(|entry| &entry.bookmark_name == bookmark)
+        {
+            // Deleting the bookmark does not land anything, and the commits it pointed to must
+            // not be counted again if it is created again.
+            if let Some(to) = entry.to_changeset_id {
+                count_move(ctx, repo, new_position, to, entry.timestamp, &mut delta).await?;
+                new_position = Some(to);
+            }
+        }
+
+        let progress = CommitStatsProgress {
+            log_id: last_log_id,
+            bookmark_position: new_position,
+        };
+        if !stats
+            .add(ctx, repo_id, bookmark, prev_log_id, progress, &delta)
+            .await?
+        {
+            info!(
+                ctx.logger(),
+                "Commit stats of {} were updated concurrently, stopping", bookmark
+            );
+            return Ok(counted);
+        }
+        debug!(
+            ctx.logger(),
+            "Counted {} commits of {} up to log entry {}",
+            delta.commits(),
+            bookmark,
+            last_log_id
+        );
+        counted += delta.commits();
+        prev_log_id = Some(last_log_id);
+        position = new_position;
+    }
+}
+
+async fn count_move(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    from: Option<ChangesetId>,
+    to: ChangesetId,
+    moved: Timestamp,
+    delta: &mut CommitStatsDelta,
+) -> Result<()> {
+    let mut changesets = DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
+        ctx.clone(),
+        &repo.get_changeset_fetcher(),
+        Arc::new(SkiplistIndex::new()),
+        vec![to],
+        from.into_iter().collect(),
+    )
+    .compat()
+    .map_ok(|cs_id| async move { Ok(cs_id.load(ctx, repo.blobstore()).await?) })
+    .try_buffered(LOAD_CONCURRENCY);
+
+    while let Some(bcs) = changesets.try_next().await? {
+        let landed = match from {
+            Some(_) => moved,
+            None => Timestamp::from(bcs.author_date().clone()),
+        };
+        delta.add_changeset(landed, &bcs);
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use anyhow::Result;
+use blobrepo_factory::new_memblob_empty;
+use bookmarks::BookmarkName;
+use chrono::{NaiveDate, Utc};
+use commit_stats::{
+    update_commit_stats, AuthorCommitStats, CommitStatsDelta, CommitStatsProgress,
+    DailyCommitStats, SqlCommitStats,
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use mononoke_types::DateTime;
+use sql_construct::SqlConstruct;
+use tests_utils::{bookmark, CreateCommitContext};
+
+#[fbinit::test]
+async fn test_add_conflict(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let repo = new_memblob_empty(None)?;
+    let stats = SqlCommitStats::with_sqlite_in_memory()?;
+    let master = BookmarkName::new("master")?;
+    let repo_id = repo.get_repoid();
+    let delta = CommitStatsDelta::new();
+
+    let progress = |log_id| CommitStatsProgress {
+        log_id,
+        bookmark_position: None,
+    };
+
+    assert_eq!(stats.get_progress(&ctx, repo_id, &master).await?, None);
+    assert!(
+        stats
+            .add(&ctx, repo_id, &master, None, progress(1), &delta)
+            .await?
+    );
+    // Another updater which didn't see the first update must not count the same entries again
+    assert!(
+        !stats
+            .add(&ctx, repo_id, &master, None, progress(1), &delta)
+            .await?
+    );
+    assert!(
+        !stats
+            .add(&ctx, repo_id, &master, Some(0), progress(2), &delta)
+            .await?
+    );
+    assert!(
+        stats
+            .add(&ctx, repo_id, &master, Some(1), progress(2), &delta)
+            .await?
+    );
+    assert_eq!(
+        stats.get_progress(&ctx, repo_id, &master).await?,
+        Some(progress(2))
+    );
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_update_commit_stats(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let repo = new_memblob_empty(None)?;
+    let stats = SqlCommitStats::with_sqlite_in_memory()?;
+    let master = BookmarkName::new("master")?;
+    let repo_id = repo.get_repoid();
+
+    let authored = NaiveDate::from_ymd(2020, 1, 1);
+    let root = CreateCommitContext::new_root(&ctx, &repo)
+        .add_file("a", "a")
+        .add_file("b", "b")
+        .set_author("alice")
+        .set_author_date(DateTime::from_rfc3339("2020-01-01T10:00:00+00:00")?)
+        .commit()
+        .await?;
+    bookmark(&ctx, &repo, "master").set_to(root).await?;
+
+    let first = CreateCommitContext::new(&ctx, &repo, vec![root])
+        .add_file("a", "a2")
+        .set_author("alice")
+        .commit()
+        .await?;
+    let second = CreateCommitContext::new(&ctx, &repo, vec![first])
+        .add_file("b", "b2")
+        .delete_file("a")
+        .set_author("bob")
+        .commit()
+        .await?;
+    bookmark(&ctx, &repo, "master").set_to(second).await?;
+    // Other bookmarks are not counted
+    let other = CreateCommitContext::new(&ctx, &repo, vec![second])
+        .add_file("c", "c")
+        .commit()
+        .await?;
+    bookmark(&ctx, &repo, "other").set_to(other).await?;
+    // Force-moves don't log where the bookmark was, which must not count everything again
+    bookmark(&ctx, &repo, "master").set_to(second).await?;
+
+    // Entries are read one at a time, to go through several batches
+    assert_eq!(
+        update_commit_stats(&ctx, &repo, &stats, &master, 1).await?,
+        3
+    );
+    assert_eq!(
+        update_commit_stats(&ctx, &repo, &stats, &master, 1).await?,
+        0
+    );
+
+    let today = Utc::now().naive_utc().date();
+    assert_eq!(
+        stats
+            .get_daily_stats(&ctx, repo_id, &master, authored, today)
+            .await?,
+        vec![
+            DailyCommitStats {
+                day: authored,
+                commits: 1,
+                authors: 1,
+                file_changes: 2,
+            },
+            DailyCommitStats {
+                day: today,
+                commits: 2,
+                authors: 2,
+                file_changes: 3,
+            },
+        ]
+    );
+    assert_eq!(
+        stats
+            .get_top_authors(&ctx, repo_id, &master, authored, today, 1)
+            .await?,
+        vec![AuthorCommitStats {
+            author: "alice".to_string(),
+            commits: 2,
+        }]
+    );
+    assert_eq!(
+        stats
+            .get_daily_stats(&ctx, repo_id, &master, today, today)
+            .await?
+            .len(),
+        1
+    );
+    Ok(())
+}
@@ -0,0 +1,183 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Error, Result};
+use futures::compat::Future01CompatExt;
+use futures::future::BoxFuture;
+use sql::{queries, Connection};
+use stats::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+use tokio::time;
+
+use crate::SqlConnections;
+
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+define_stats! {
+    prefix = "mononoke.sql.health";
+    failed_checks: timeseries(Rate, Sum),
+    reconnects: timeseries(Rate, Sum),
+    failed_reconnects: timeseries(Rate, Sum),
+}
+
+queries! {
+    read Ping() -> (i64) {
+        "SELECT 1"
+    }
+}
+
+/// Opens new connections to replace the ones that stopped answering.
+pub type SqlConnector = Arc<dyn Fn() -> BoxFuture<'static, Result<SqlConnections>> + Send + Sync>;
+
+struct Inner {
+    connections: RwLock<SqlConnections>,
+    connect: SqlConnector,
+    healthy: AtomicBool,
+}
+
+/// Connections that are pinged to find out whether they still work, and replaced by new ones
+/// when they don't, so that long-running processes don't find out about dead connections
+/// halfway through a batch of queries.
+///
+/// Checks run on `check_health`, or periodically once `spawn_health_checks` is called. Clones
+/// share the connections.
+#[derive(Clone)]
+pub struct HealthCheckedConnections {
+    inner: Arc<Inner>,
+}
+
+impl HealthCheckedConnections {
+    /// Open connections with `connect`, which is called again each time they need to be
+    /// replaced.
+    pub async fn new(connect: SqlConnector) -> Result<Self> {
+        let connections = connect().await?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                connections: RwLock::new(connections),
+                connect,
+                healthy: AtomicBool::new(true),
+            }),
+        })
+    }
+
+    /// The current connections. Get them again after a failed health check to use the new
+    /// connections.
+    pub fn connections(&self) -> SqlConnections {
+        self.inner
+            .connections
+            .read()
+            .expect("lock poisoned")
+            .clone()
+    }
+
+    /// Whether the connections answered the last health check, or were replaced by ones that
+    /// did.
+    pub fn is_healthy(&self) -> bool {
+        self.inner.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Ping the connections, and replace them if any of them doesn't answer. Returns whether
+    /// the connections are healthy afterwards.
+    pub async fn check_health(&self) -> bool {
+        let healthy = match ping_all(&self.connections()).await {
+            Ok(()) => true,
+            Err(_) => {
+                STATS::failed_checks.add_value(1);
+                self.reconnect().await.is_ok()
+            }
+        };
+        self.inner.healthy.store(healthy, Ordering::Relaxed);
+        healthy
+    }
+
+    async fn reconnect(&self) -> Result<()> {
+        let res: Result<SqlConnections> = async {
+            let connections = (self.inner.connect)().await?;
+            ping_all(&connections).await?;
+            Ok(connections)
+        }
+        .await;
+
+        match res {
+            Ok(connections) => {
+                STATS::reconnects.add_value(1);
+                *self.inner.connections.write().expect("lock poisoned") = connections;
+                Ok(())
+            }
+            Err(e) => {
+                STATS::failed_reconnects.add_value(1);
+                Err(e)
+            }
+        }
+    }
+
+    /// Check the health of the connections every `interval` in the background, until all the
+    /// clones of these connections are dropped.
+    pub fn spawn_health_checks(&self, interval: Duration) {
+        let weak: Weak<Inner> = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            loop {
+                time::delay_for(interval).await;
+                match weak.upgrade() {
+                    Some(inner) => {
+                        HealthCheckedConnections { inner }.check_health().await;
+                    }
+                    None => break,
+                }
+            }
+        });
+    }
+}
+
+async fn ping(connection: &Connection) -> Result<()> {
+    time::timeout(PING_TIMEOUT, Ping::query(connection).compat())
+        .await
+        .map_err(|_| Error::msg("ping timed out"))??;
+    Ok(())
+}
+
+async fn ping_all(connections: &SqlConnections) -> Result<()> {
+    ping(&connections.write_connection).await?;
+    ping(&connections.read_connection).await?;
+    ping(&connections.read_master_connection).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::open_sqlite_in_memory;
+    use futures::future::FutureExt;
+    use std::sync::atomic::AtomicU32;
+
+    #[test]
+    fn test_health_check() -> Result<()> {
+        async_unit::tokio_unit_test(async move {
+            let opened = Arc::new(AtomicU32::new(0));
+            let connect: SqlConnector = {
+                let opened = opened.clone();
+                Arc::new(move || {
+                    opened.fetch_add(1, Ordering::SeqCst);
+                    async {
+                        let connection = Connection::with_sqlite(open_sqlite_in_memory()?);
+                        Ok(SqlConnections::new_single(connection))
+                    }
+                    .boxed()
+                })
+            };
+
+            let connections = HealthCheckedConnections::new(connect).await?;
+            assert!(connections.check_health().await);
+            assert!(connections.is_healthy());
+            // Healthy connections are kept
+            assert_eq!(opened.load(Ordering::SeqCst), 1);
+            Ok(())
+        })
+    }
+}
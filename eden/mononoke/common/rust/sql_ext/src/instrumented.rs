@@ -0,0 +1,223 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Error, Result};
+use scuba::ScubaSampleBuilder;
+use sql::{Connection, Transaction, WriteResult};
+use stats::prelude::*;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::{is_transient_error, SqlConnections};
+
+define_stats! {
+    prefix = "mononoke.sql";
+    queries: dynamic_timeseries("{}.{}.queries", (label: String, query: String); Rate, Sum),
+    errors: dynamic_timeseries("{}.{}.errors.{}", (label: String, query: String, class: String); Rate, Sum),
+    rows: dynamic_timeseries("{}.{}.rows", (label: String, query: String); Rate, Sum),
+    latency_ms: dynamic_histogram("{}.{}.latency_ms", (label: String, query: String); 10, 0, 1_000, Average, Count; P 50; P 95; P 99),
+}
+
+/// Number of rows a query returned or changed, for metrics
+pub trait QueryRows {
+    fn rows(&self) -> u64;
+}
+
+impl<T> QueryRows for Vec<T> {
+    fn rows(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl QueryRows for WriteResult {
+    fn rows(&self) -> u64 {
+        self.affected_rows()
+    }
+}
+
+impl<T: QueryRows> QueryRows for (Transaction, T) {
+    fn rows(&self) -> u64 {
+        self.1.rows()
+    }
+}
+
+/// Coarse class of a query error, so that errors worth retrying can be told apart in metrics.
+fn error_class(error: &Error) -> &'static str {
+    if is_transient_error(error) {
+        "transient"
+    } else {
+        "permanent"
+    }
+}
+
+/// Where the metrics of instrumented queries go: stats under `mononoke.sql.<label>`, and a
+/// sample per query in the scuba table, if one is set.
+#[derive(Clone)]
+pub struct QueryInstrumentation {
+    label: String,
+    scuba: Option<ScubaSampleBuilder>,
+}
+
+impl QueryInstrumentation {
+    pub fn new(label: impl ToString) -> Self {
+        Self {
+            label: label.to_string(),
+            scuba: None,
+        }
+    }
+
+    pub fn with_scuba(mut self, scuba: ScubaSampleBuilder) -> Self {
+        self.scuba = Some(scuba);
+        self
+    }
+
+    fn record(&self, query: &str, started: Instant, res: Result<u64, &Error>) {
+        let latency = started.elapsed();
+        let key = (self.label.clone(), query.to_string());
+        STATS::queries.add_value(1, key.clone());
+        STATS::latency_ms.add_value(latency.as_millis() as i64, key.clone());
+        match res {
+            Ok(rows) => STATS::rows.add_value(rows as i64, key),
+            Err(error) => {
+                STATS::errors.add_value(1, (key.0, key.1, error_class(error).to_string()))
+            }
+        }
+
+        if let Some(scuba) = &self.scuba {
+            let mut scuba = scuba.clone();
+            scuba
+                .add("label", self.label.as_str())
+                .add("query", query)
+                .add("latency_us", latency.as_micros() as i64);
+            match res {
+                Ok(rows) => {
+                    scuba.add("success", 1).add("rows", rows as i64);
+                }
+                Err(error) => {
+                    scuba
+                        .add("success", 0)
+                        .add("error_class", error_class(error))
+                        .add("error", format!("{:#}", error));
+                }
+            }
+            scuba.log();
+        }
+    }
+}
+
+/// A connection that records the latency, row count and errors of the queries run through
+/// `query`. It derefs to the connection, so queries that don't need metrics can still use it
+/// directly.
+#[derive(Clone)]
+pub struct InstrumentedConnection {
+    connection: Connection,
+    instrumentation: Option<Arc<QueryInstrumentation>>,
+}
+
+impl InstrumentedConnection {
+    pub fn new(connection: Connection, instrumentation: Option<QueryInstrumentation>) -> Self {
+        Self {
+            connection,
+            instrumentation: instrumentation.map(Arc::new),
+        }
+    }
+
+    /// Run a query on this connection, or on a transaction started from it, recording its
+    /// metrics under `name`.
+    pub async fn query<T, Fut>(&self, name: &str, query: Fut) -> Result<T>
+    where
+        T: QueryRows,
+        Fut: Future<Output = Result<T>>,
+    {
+        let instrumentation = match &self.instrumentation {
+            Some(instrumentation) => instrumentation,
+            None => return query.await,
+        };
+        let started = Instant::now();
+        let res = query.await;
+        instrumentation.record(name, started, res.as_ref().map(|value| value.rows()));
+        res
+    }
+
+    pub fn into_inner(self) -> Connection {
+        self.connection
+    }
+}
+
+impl Deref for InstrumentedConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+#[derive(Clone)]
+pub struct InstrumentedSqlConnections {
+    pub write_connection: InstrumentedConnection,
+    pub read_connection: InstrumentedConnection,
+    pub read_master_connection: InstrumentedConnection,
+}
+
+impl SqlConnections {
+    /// Wrap the connections to record metrics for the queries run through them. Without
+    /// `instrumentation`, the queries run as they would on the bare connections.
+    pub fn instrumented(
+        self,
+        instrumentation: Option<QueryInstrumentation>,
+    ) -> InstrumentedSqlConnections {
+        InstrumentedSqlConnections {
+            write_connection: InstrumentedConnection::new(
+                self.write_connection,
+                instrumentation.clone(),
+            ),
+            read_connection: InstrumentedConnection::new(
+                self.read_connection,
+                instrumentation.clone(),
+            ),
+            read_master_connection: InstrumentedConnection::new(
+                self.read_master_connection,
+                instrumentation,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::open_sqlite_in_memory;
+    use anyhow::format_err;
+
+    #[test]
+    fn test_instrumented_query() -> Result<()> {
+        async_unit::tokio_unit_test(async move {
+            let connection = Connection::with_sqlite(open_sqlite_in_memory()?);
+            let instrumentation =
+                QueryInstrumentation::new("test").with_scuba(ScubaSampleBuilder::with_discard());
+            let connections =
+                SqlConnections::new_single(connection).instrumented(Some(instrumentation));
+
+            // Results and errors are passed through unchanged
+            let rows = connections
+                .read_connection
+                .query("Select", async { Ok(vec![1, 2, 3]) })
+                .await?;
+            assert_eq!(rows, vec![1, 2, 3]);
+            let res: Result<Vec<u32>> = connections
+                .write_connection
+                .query("Insert", async {
+                    Err(format_err!("Deadlock found when trying to get lock"))
+                })
+                .await;
+            assert_eq!(error_class(&res.unwrap_err()), "transient");
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use sql::Connection;
+use stats::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::replication::ReplicaLagMonitor;
+use crate::SqlConnections;
+
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+define_stats! {
+    prefix = "mononoke.sql.lag_aware";
+    master_fallbacks: timeseries(Rate, Sum),
+}
+
+/// Connections whose reads go to the master instead of the replicas while the replicas lag
+/// more than a threshold behind it.
+///
+/// The lag is checked at most once per check interval, and reads go to the master if the lag
+/// can't be checked. Clones share the lag state and the fallback counter.
+#[derive(Clone)]
+pub struct LagAwareConnections {
+    connections: SqlConnections,
+    monitor: Arc<dyn ReplicaLagMonitor>,
+    max_lag: Duration,
+    check_interval: Duration,
+    // When the lag was last checked, and whether the replicas were lagging then
+    last_check: Arc<Mutex<Option<(Instant, bool)>>>,
+    fallbacks: Arc<AtomicU64>,
+}
+
+impl LagAwareConnections {
+    pub fn with_check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = check_interval;
+        self
+    }
+
+    pub fn write_connection(&self) -> &Connection {
+        &self.connections.write_connection
+    }
+
+    /// The connection to read with: a replica, or the master if the replicas are lagging.
+    pub async fn read_connection(&self) -> &Connection {
+        if self.replicas_lagging().await {
+            self.fallbacks.fetch_add(1, Ordering::Relaxed);
+            STATS::master_fallbacks.add_value(1);
+            &self.connections.read_master_connection
+        } else {
+            &self.connections.read_connection
+        }
+    }
+
+    pub fn read_master_connection(&self) -> &Connection {
+        &self.connections.read_master_connection
+    }
+
+    /// How many reads went to the master because the replicas were lagging.
+    pub fn fallback_count(&self) -> u64 {
+        self.fallbacks.load(Ordering::Relaxed)
+    }
+
+    async fn replicas_lagging(&self) -> bool {
+        let cached = *self.last_check.lock().expect("lock poisoned");
+        if let Some((checked_at, lagging)) = cached {
+            if checked_at.elapsed() < self.check_interval {
+                return lagging;
+            }
+        }
+
+        let lagging = match self.monitor.get_max_replica_lag().await {
+            Ok(lag) => lag.delay > self.max_lag,
+            Err(_) => true,
+        };
+        *self.last_check.lock().expect("lock poisoned") = Some((Instant::now(), lagging));
+        lagging
+    }
+
+    pub fn into_inner(self) -> SqlConnections {
+        self.connections
+    }
+}
+
+impl SqlConnections {
+    /// Read from the master instead of the replicas while `monitor` reports a lag of more than
+    /// `max_lag`.
+    pub fn with_replica_lag_monitor(
+        self,
+        monitor: Arc<dyn ReplicaLagMonitor>,
+        max_lag: Duration,
+    ) -> LagAwareConnections {
+        LagAwareConnections {
+            connections: self,
+            monitor,
+            max_lag,
+            check_interval: DEFAULT_CHECK_INTERVAL,
+            last_check: Arc::new(Mutex::new(None)),
+            fallbacks: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::open_sqlite_in_memory;
+    use crate::replication::ReplicaLag;
+    use anyhow::Result;
+    use async_trait::async_trait;
+
+    struct FixedLagMonitor(Duration);
+
+    #[async_trait]
+    impl ReplicaLagMonitor for FixedLagMonitor {
+        async fn get_replica_lag(&self) -> Result<Vec<ReplicaLag>> {
+            Ok(vec![ReplicaLag::new(self.0, None)])
+        }
+    }
+
+    fn lag_aware(lag: Duration) -> Result<LagAwareConnections> {
+        let connection = Connection::with_sqlite(open_sqlite_in_memory()?);
+        Ok(SqlConnections::new_single(connection)
+            .with_replica_lag_monitor(Arc::new(FixedLagMonitor(lag)), Duration::from_secs(5)))
+    }
+
+    #[test]
+    fn test_lag_aware_reads() -> Result<()> {
+        async_unit::tokio_unit_test(async move {
+            let connections = lag_aware(Duration::from_secs(1))?;
+            let _ = connections.read_connection().await;
+            assert_eq!(connections.fallback_count(), 0);
+
+            let connections = lag_aware(Duration::from_secs(10))?;
+            let _ = connections.read_connection().await;
+            let _ = connections.clone().read_connection().await;
+            assert_eq!(connections.fallback_count(), 2);
+            Ok(())
+        })
+    }
+}
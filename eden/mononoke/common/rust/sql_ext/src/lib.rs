@@ -5,14 +5,37 @@
  * GNU General Public License version 2.
  */
 
+mod health;
+mod instrumented;
+mod lag_aware;
 #[cfg(not(fbcode_build))]
 mod oss;
+mod pool_stats;
+mod read_after_write;
 pub mod replication;
+mod sharding;
 mod sqlite;
+mod transaction;
 
 use sql::{Connection, Transaction};
 
-pub use sqlite::{open_existing_sqlite_path, open_sqlite_in_memory, open_sqlite_path};
+pub use health::{HealthCheckedConnections, SqlConnector};
+pub use instrumented::{
+    InstrumentedConnection, InstrumentedSqlConnections, QueryInstrumentation, QueryRows,
+};
+pub use lag_aware::LagAwareConnections;
+pub use pool_stats::export_pool_stats;
+pub use read_after_write::ReadAfterWriteConnections;
+pub use sharding::{ShardHashing, ShardRouter};
+pub use sqlite::{
+    open_existing_sqlite_path, open_existing_sqlite_path_with_options,
+    open_shared_sqlite_in_memory, open_sqlite_in_memory, open_sqlite_path,
+    open_sqlite_path_with_options, SqliteOptions, SqliteSynchronous,
+};
+pub use transaction::{
+    is_transient_error, transaction_with_retries, transaction_with_retries_if,
+    TransactionRetryOptions,
+};
 
 #[derive(Clone)]
 pub struct SqlConnections {
@@ -74,6 +97,7 @@ pub mod facebook {
     #[cfg(fbcode_build)]
     mod r#impl;
 
+    use std::collections::HashMap;
     use std::fmt::{self, Debug};
 
     #[cfg(fbcode_build)]
@@ -152,6 +176,25 @@ pub mod facebook {
         Master,
     }
 
+    /// Live usage of a `SharedConnectionPool`.
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    pub struct PoolStats {
+        /// Connections open in the pool, busy or idle
+        pub open_connections: usize,
+        /// Open connections that are not running a query
+        pub idle_connections: usize,
+        /// Queries waiting for a connection. Non-zero when the pool limit is exhausted.
+        pub waiters: usize,
+        /// Open connections by key (the database and the instance they connect to)
+        pub per_key_open_connections: HashMap<String, usize>,
+    }
+
+    impl PoolStats {
+        pub fn busy_connections(&self) -> usize {
+            self.open_connections.saturating_sub(self.idle_connections)
+        }
+    }
+
     #[derive(Copy, Clone, Debug)]
     pub struct PoolSizeConfig {
         pub write_pool_size: usize,
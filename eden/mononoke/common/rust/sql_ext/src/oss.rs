@@ -71,6 +71,11 @@ impl SharedConnectionPool {
     pub fn new() -> Self {
         Self
     }
+
+    /// No connections are opened through the pool outside of fbcode builds.
+    pub fn stats(&self) -> PoolStats {
+        PoolStats::default()
+    }
 }
 
 pub fn create_mysql_connections_unsharded(
@@ -0,0 +1,41 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use fbinit::FacebookInit;
+use stats::prelude::*;
+use std::time::Duration;
+use tokio::time;
+
+use crate::facebook::{PoolStats, SharedConnectionPool};
+
+define_stats! {
+    prefix = "mononoke.sql.pool";
+    open_connections: singleton_counter(),
+    idle_connections: singleton_counter(),
+    busy_connections: singleton_counter(),
+    waiters: singleton_counter(),
+    per_key_open_connections: dynamic_singleton_counter("per_key.{}.open_connections", (key: String)),
+}
+
+fn set_counters(fb: FacebookInit, stats: &PoolStats) {
+    STATS::open_connections.set_value(fb, stats.open_connections as i64);
+    STATS::idle_connections.set_value(fb, stats.idle_connections as i64);
+    STATS::busy_connections.set_value(fb, stats.busy_connections() as i64);
+    STATS::waiters.set_value(fb, stats.waiters as i64);
+    for (key, open) in stats.per_key_open_connections.iter() {
+        STATS::per_key_open_connections.set_value(fb, *open as i64, (key.clone(),));
+    }
+}
+
+/// Export the stats of `pool` as counters every `interval`, so that it shows when queries are
+/// waiting because the pool limit is exhausted. The returned future never completes, spawn it.
+pub async fn export_pool_stats(fb: FacebookInit, pool: SharedConnectionPool, interval: Duration) {
+    loop {
+        set_counters(fb, &pool.stats());
+        time::delay_for(interval).await;
+    }
+}
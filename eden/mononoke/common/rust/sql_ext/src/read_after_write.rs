@@ -0,0 +1,132 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use sql::Connection;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::SqlConnections;
+
+/// Connections of a logical session that read from the master for a while after the session
+/// writes, so that the session doesn't miss its own writes while the replicas catch up.
+///
+/// Clones share the session. The window starts when the session calls `note_write` after a
+/// write commits, so it only needs to be longer than the usual replication lag.
+#[derive(Clone)]
+pub struct ReadAfterWriteConnections {
+    connections: SqlConnections,
+    window: Duration,
+    last_write: Arc<Mutex<Option<Instant>>>,
+}
+
+impl ReadAfterWriteConnections {
+    /// The connection to write with. Call `note_write` once the write commits.
+    pub fn write_connection(&self) -> &Connection {
+        &self.connections.write_connection
+    }
+
+    /// Record that a write of the session committed. Reads in the next `window` go to the
+    /// master.
+    pub fn note_write(&self) {
+        *self.last_write.lock().expect("lock poisoned") = Some(Instant::now());
+    }
+
+    /// The connection to read with: the master if the session wrote recently, a replica
+    /// otherwise.
+    pub fn read_connection(&self) -> &Connection {
+        if self.reads_from_master() {
+            &self.connections.read_master_connection
+        } else {
+            &self.connections.read_connection
+        }
+    }
+
+    pub fn read_master_connection(&self) -> &Connection {
+        &self.connections.read_master_connection
+    }
+
+    /// Whether reads currently go to the master, because the session wrote within the window.
+    pub fn reads_from_master(&self) -> bool {
+        match *self.last_write.lock().expect("lock poisoned") {
+            Some(last_write) => last_write.elapsed() < self.window,
+            None => false,
+        }
+    }
+
+    pub fn into_inner(self) -> SqlConnections {
+        self.connections
+    }
+}
+
+impl SqlConnections {
+    /// Start a session on these connections that reads from the master for `window` after each
+    /// write.
+    pub fn with_read_after_write(self, window: Duration) -> ReadAfterWriteConnections {
+        ReadAfterWriteConnections {
+            connections: self,
+            window,
+            last_write: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::open_sqlite_in_memory;
+    use anyhow::Result;
+    use futures::compat::Future01CompatExt;
+
+    fn connections() -> Result<SqlConnections> {
+        Ok(SqlConnections::new_single(Connection::with_sqlite(
+            open_sqlite_in_memory()?,
+        )))
+    }
+
+    #[test]
+    fn test_read_after_write() -> Result<()> {
+        let session = connections()?.with_read_after_write(Duration::from_secs(60));
+        assert!(!session.reads_from_master());
+        session.note_write();
+        assert!(session.reads_from_master());
+        // Clones are the same session
+        assert!(session.clone().reads_from_master());
+        // Other sessions are not affected
+        let other = session
+            .into_inner()
+            .with_read_after_write(Duration::from_secs(60));
+        assert!(!other.reads_from_master());
+
+        // Once the window is over, reads go to replicas again
+        let session = connections()?.with_read_after_write(Duration::from_secs(0));
+        session.note_write();
+        assert!(!session.reads_from_master());
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_starts_at_commit() -> Result<()> {
+        async_unit::tokio_unit_test(async move {
+            let session = connections()?.with_read_after_write(Duration::from_millis(50));
+
+            // A transaction held open for longer than the window
+            let txn = session
+                .write_connection()
+                .start_transaction()
+                .compat()
+                .await?;
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+            assert!(!session.reads_from_master());
+            txn.commit().compat().await?;
+            session.note_write();
+
+            // The window starts at the commit, not when the transaction started
+            assert!(session.reads_from_master());
+            Ok(())
+        })
+    }
+}
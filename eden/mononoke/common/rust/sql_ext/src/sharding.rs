@@ -0,0 +1,205 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{bail, Result};
+use std::hash::Hasher;
+use twox_hash::{XxHash32, XxHash64};
+
+use crate::{SqlConnections, SqlShardedConnections};
+
+/// How keys are spread across shards.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShardHashing {
+    /// xxHash32 of the key modulo the number of shards. Changing the number of shards moves
+    /// most keys to another shard.
+    Modulo,
+    /// Jump consistent hash of the xxHash64 of the key. Adding a shard only moves the keys that
+    /// go to the new shard.
+    Consistent,
+}
+
+impl ShardHashing {
+    /// The shard, between 0 and `shard_count` excluded, that `key` goes to.
+    pub fn shard_id(&self, key: &[u8], shard_count: usize) -> usize {
+        assert!(shard_count > 0, "no shards to route to");
+        match self {
+            Self::Modulo => {
+                let mut hasher = XxHash32::with_seed(0);
+                hasher.write(key);
+                (hasher.finish() % shard_count as u64) as usize
+            }
+            Self::Consistent => {
+                let mut hasher = XxHash64::with_seed(0);
+                hasher.write(key);
+                jump_consistent_hash(hasher.finish(), shard_count)
+            }
+        }
+    }
+}
+
+/// Jump consistent hash, from "A Fast, Minimal Memory, Consistent Hash Algorithm" by Lamping
+/// and Veach.
+fn jump_consistent_hash(mut key: u64, buckets: usize) -> usize {
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b as usize
+}
+
+/// Sharded connections that pick the shard of a key, so that sharded stores don't each have to
+/// do it themselves.
+#[derive(Clone)]
+pub struct ShardRouter {
+    connections: SqlShardedConnections,
+    hashing: ShardHashing,
+}
+
+impl ShardRouter {
+    pub fn shard_count(&self) -> usize {
+        self.connections.write_connections.len()
+    }
+
+    pub fn shard_id(&self, key: &[u8]) -> usize {
+        self.hashing.shard_id(key, self.shard_count())
+    }
+
+    /// The connections of shard `shard_id`.
+    pub fn shard(&self, shard_id: usize) -> SqlConnections {
+        SqlConnections {
+            write_connection: self.connections.write_connections[shard_id].clone(),
+            read_connection: self.connections.read_connections[shard_id].clone(),
+            read_master_connection: self.connections.read_master_connections[shard_id].clone(),
+        }
+    }
+
+    /// The connections of the shard that `key` goes to.
+    pub fn shard_for_key(&self, key: &[u8]) -> SqlConnections {
+        self.shard(self.shard_id(key))
+    }
+
+    /// The connections of every shard, with their shard ids, for queries that fan out to all
+    /// the shards.
+    pub fn shards(&self) -> impl Iterator<Item = (usize, SqlConnections)> + '_ {
+        (0..self.shard_count()).map(move |shard_id| (shard_id, self.shard(shard_id)))
+    }
+
+    /// Split `keys` by the shard they go to, for batch queries that fan out to the shards
+    /// holding them. Shards with no keys are left out.
+    pub fn group_by_shard<K: AsRef<[u8]>>(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Vec<(SqlConnections, Vec<K>)> {
+        let mut groups: Vec<Vec<K>> = (0..self.shard_count()).map(|_| Vec::new()).collect();
+        for key in keys {
+            let shard_id = self.shard_id(key.as_ref());
+            groups[shard_id].push(key);
+        }
+        groups
+            .into_iter()
+            .enumerate()
+            .filter(|(_, keys)| !keys.is_empty())
+            .map(|(shard_id, keys)| (self.shard(shard_id), keys))
+            .collect()
+    }
+
+    pub fn into_inner(self) -> SqlShardedConnections {
+        self.connections
+    }
+}
+
+impl SqlShardedConnections {
+    /// Route keys to these shards with `hashing`. Fails if there are no shards.
+    pub fn with_routing(self, hashing: ShardHashing) -> Result<ShardRouter> {
+        if self.is_empty() {
+            bail!("sharded database constructed with no shards");
+        }
+        Ok(ShardRouter {
+            connections: self,
+            hashing,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::open_sqlite_in_memory;
+    use sql::Connection;
+
+    fn router(shard_count: usize, hashing: ShardHashing) -> Result<ShardRouter> {
+        let shards = (0..shard_count)
+            .map(|_| {
+                Ok(SqlConnections::new_single(Connection::with_sqlite(
+                    open_sqlite_in_memory()?,
+                )))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        SqlShardedConnections::from(shards).with_routing(hashing)
+    }
+
+    fn keys() -> Vec<String> {
+        (0..1000).map(|i| format!("key{}", i)).collect()
+    }
+
+    #[test]
+    fn test_modulo_matches_xxhash32() {
+        // Stores that sharded with xxHash32 modulo the shard count keep their layout.
+        for key in keys() {
+            let mut hasher = XxHash32::with_seed(0);
+            hasher.write(key.as_bytes());
+            assert_eq!(
+                ShardHashing::Modulo.shard_id(key.as_bytes(), 7),
+                (hasher.finish() % 7) as usize
+            );
+        }
+    }
+
+    #[test]
+    fn test_consistent_hashing_moves_keys_to_new_shard_only() {
+        let mut moved = 0;
+        for key in keys() {
+            let before = ShardHashing::Consistent.shard_id(key.as_bytes(), 10);
+            let after = ShardHashing::Consistent.shard_id(key.as_bytes(), 11);
+            assert!(before < 10);
+            if before != after {
+                assert_eq!(after, 10);
+                moved += 1;
+            }
+        }
+        // About 1 in 11 keys move to the new shard
+        assert!(moved > 0 && moved < 200, "{} keys moved", moved);
+    }
+
+    #[test]
+    fn test_no_shards() {
+        assert!(router(0, ShardHashing::Modulo).is_err());
+    }
+
+    #[test]
+    fn test_group_by_shard() -> Result<()> {
+        let router = router(4, ShardHashing::Consistent)?;
+        assert_eq!(router.shard_count(), 4);
+        assert_eq!(router.shards().count(), 4);
+
+        let groups = router.group_by_shard(keys());
+        assert_eq!(
+            groups.iter().map(|(_, keys)| keys.len()).sum::<usize>(),
+            1000
+        );
+        for (_, keys) in groups {
+            let shard_id = router.shard_id(keys[0].as_bytes());
+            assert!(keys
+                .iter()
+                .all(|key| router.shard_id(key.as_bytes()) == shard_id));
+        }
+        Ok(())
+    }
+}
@@ -5,31 +5,153 @@
  * GNU General Public License version 2.
  */
 
-use anyhow::Result;
-use sql::rusqlite::{Connection as SqliteConnection, OpenFlags as SqliteOpenFlags};
+use anyhow::{bail, Result};
+use sql::rusqlite::{
+    Connection as SqliteConnection, OpenFlags as SqliteOpenFlags, NO_PARAMS as SQLITE_NO_PARAMS,
+};
+use sql::Connection;
 use std::{fs::create_dir_all, path::Path, time::Duration};
 
-fn sqlite_setup_connection(con: &SqliteConnection) {
-    // By default, when there's a read/write contention, SQLite will not wait,
-    // but rather throw a `SQLITE_BUSY` error. See https://www.sqlite.org/lockingv3.html
-    // This means that tests will fail in cases when production setup (e.g. one with MySQL)
-    // would not. To change that, let's make sqlite wait for some time, before erroring out
-    let _ = con.busy_timeout(Duration::from_secs(10));
+use crate::SqlConnections;
+
+/// Value of the `synchronous` pragma: how often SQLite waits for writes to reach the disk.
+/// See https://www.sqlite.org/pragma.html#pragma_synchronous
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SqliteSynchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+impl SqliteSynchronous {
+    fn as_pragma_value(&self) -> &'static str {
+        match self {
+            Self::Off => "OFF",
+            Self::Normal => "NORMAL",
+            Self::Full => "FULL",
+            Self::Extra => "EXTRA",
+        }
+    }
+}
+
+/// How sqlite connections opened from a path are set up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SqliteOptions {
+    /// How long to wait for the locks of other connections before failing with `SQLITE_BUSY`.
+    pub busy_timeout: Duration,
+    /// Switch the DB to the WAL journal mode, in which readers and a writer don't block each
+    /// other. This is stored in the DB, so it applies to the other processes using it too.
+    pub wal: bool,
+    /// Leave SQLite's default (`FULL`) if unset. `NORMAL` is enough to not corrupt a DB in WAL
+    /// mode.
+    pub synchronous: Option<SqliteSynchronous>,
+}
+
+impl SqliteOptions {
+    pub fn with_busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.busy_timeout = busy_timeout;
+        self
+    }
+
+    pub fn with_wal(mut self) -> Self {
+        self.wal = true;
+        self
+    }
+
+    pub fn with_synchronous(mut self, synchronous: SqliteSynchronous) -> Self {
+        self.synchronous = Some(synchronous);
+        self
+    }
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        Self {
+            // By default, when there's a read/write contention, SQLite will not wait,
+            // but rather throw a `SQLITE_BUSY` error. See https://www.sqlite.org/lockingv3.html
+            // This means that tests will fail in cases when production setup (e.g. one with MySQL)
+            // would not. To change that, let's make sqlite wait for some time, before erroring out
+            busy_timeout: Duration::from_secs(10),
+            wal: false,
+            synchronous: None,
+        }
+    }
+}
+
+fn sqlite_setup_connection(con: &SqliteConnection, options: &SqliteOptions) -> Result<()> {
+    con.busy_timeout(options.busy_timeout)?;
+    if let Some(synchronous) = options.synchronous {
+        con.pragma_update(None, "synchronous", &synchronous.as_pragma_value())?;
+    }
 
     // By default, the `LIKE` operator is case-insensitive.  This doesn't
     // match MySQL, so change it to case-sensitive.
     let _ = con.pragma_update(None, "case_sensitive_like", &true);
+    Ok(())
+}
+
+fn sqlite_enable_wal(con: &SqliteConnection) -> Result<()> {
+    // Setting the journal mode returns the mode the DB is in after that.
+    let mode: String = con.query_row("PRAGMA journal_mode = WAL", SQLITE_NO_PARAMS, |row| {
+        row.get(0)
+    })?;
+    if !mode.eq_ignore_ascii_case("wal") {
+        bail!(
+            "Failed to switch sqlite DB to WAL mode, it is in {} mode",
+            mode
+        );
+    }
+    Ok(())
 }
 
 // Open a single sqlite connection to a new in memory database
 pub fn open_sqlite_in_memory() -> Result<SqliteConnection> {
     let con = SqliteConnection::open_in_memory()?;
-    sqlite_setup_connection(&con);
+    sqlite_setup_connection(&con, &SqliteOptions::default())?;
     Ok(con)
 }
 
+/// Open a connection to the in memory database called `name`, shared by all the connections
+/// of this process that open it by that name. It is created by the first connection, and dropped
+/// with the last one.
+///
+/// The connections share a cache, so they lock whole tables, and fail right away with
+/// `SQLITE_LOCKED` instead of waiting when a table is locked by another connection.
+pub fn open_shared_sqlite_in_memory(name: &str) -> Result<SqliteConnection> {
+    let flags = SqliteOpenFlags::SQLITE_OPEN_READ_WRITE
+        | SqliteOpenFlags::SQLITE_OPEN_CREATE
+        | SqliteOpenFlags::SQLITE_OPEN_URI;
+    let uri = format!("file:{}?mode=memory&cache=shared", name);
+    let con = SqliteConnection::open_with_flags(uri, flags)?;
+    sqlite_setup_connection(&con, &SqliteOptions::default())?;
+    Ok(con)
+}
+
+impl SqlConnections {
+    /// Connections to the shared in memory database called `name`, with a separate connection
+    /// for writes and for each kind of read, that all see the same data as they would with a
+    /// real database.
+    pub fn new_shared_sqlite_in_memory(name: &str) -> Result<Self> {
+        Ok(Self {
+            write_connection: Connection::with_sqlite(open_shared_sqlite_in_memory(name)?),
+            read_connection: Connection::with_sqlite(open_shared_sqlite_in_memory(name)?),
+            read_master_connection: Connection::with_sqlite(open_shared_sqlite_in_memory(name)?),
+        })
+    }
+}
+
 /// Open a single sqlite connection. The Sqlite DB will be created at path if necessary.
 pub fn open_sqlite_path<P: AsRef<Path>>(path: P, readonly: bool) -> Result<SqliteConnection> {
+    open_sqlite_path_with_options(path, readonly, &SqliteOptions::default())
+}
+
+/// Like `open_sqlite_path`, setting the connection up with `options`.
+pub fn open_sqlite_path_with_options<P: AsRef<Path>>(
+    path: P,
+    readonly: bool,
+    options: &SqliteOptions,
+) -> Result<SqliteConnection> {
     let path = path.as_ref();
     let con = {
         // Open a RW connection with create-on-open enabled, so that the Sqlite DB is initialized
@@ -41,7 +163,13 @@ pub fn open_sqlite_path<P: AsRef<Path>>(path: P, readonly: bool) -> Result<Sqlit
         }
         let flags = SqliteOpenFlags::SQLITE_OPEN_READ_WRITE | SqliteOpenFlags::SQLITE_OPEN_CREATE;
 
-        SqliteConnection::open_with_flags(&path, flags)?
+        let con = SqliteConnection::open_with_flags(&path, flags)?;
+        // The journal mode can only be changed with write access.
+        if options.wal {
+            con.busy_timeout(options.busy_timeout)?;
+            sqlite_enable_wal(&con)?;
+        }
+        con
     };
 
     let con = if readonly {
@@ -51,7 +179,7 @@ pub fn open_sqlite_path<P: AsRef<Path>>(path: P, readonly: bool) -> Result<Sqlit
         con
     };
 
-    sqlite_setup_connection(&con);
+    sqlite_setup_connection(&con, options)?;
     Ok(con)
 }
 
@@ -59,6 +187,16 @@ pub fn open_sqlite_path<P: AsRef<Path>>(path: P, readonly: bool) -> Result<Sqlit
 pub fn open_existing_sqlite_path<P: AsRef<Path>>(
     path: P,
     readonly: bool,
+) -> Result<SqliteConnection> {
+    open_existing_sqlite_path_with_options(path, readonly, &SqliteOptions::default())
+}
+
+/// Like `open_existing_sqlite_path`, setting the connection up with `options`. A readonly
+/// connection can't switch the DB to WAL mode, so `options.wal` only applies to writable ones.
+pub fn open_existing_sqlite_path_with_options<P: AsRef<Path>>(
+    path: P,
+    readonly: bool,
+    options: &SqliteOptions,
 ) -> Result<SqliteConnection> {
     let path = path.as_ref();
     let flags = if readonly {
@@ -68,6 +206,57 @@ pub fn open_existing_sqlite_path<P: AsRef<Path>>(
         SqliteOpenFlags::SQLITE_OPEN_READ_WRITE
     };
     let con = SqliteConnection::open_with_flags(path, flags)?;
-    sqlite_setup_connection(&con);
+    sqlite_setup_connection(&con, options)?;
+    if options.wal && !readonly {
+        sqlite_enable_wal(&con)?;
+    }
     Ok(con)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn journal_mode(con: &SqliteConnection) -> Result<String> {
+        Ok(con.query_row("PRAGMA journal_mode", SQLITE_NO_PARAMS, |row| row.get(0))?)
+    }
+
+    #[test]
+    fn test_wal() -> Result<()> {
+        let dir = TempDir::new("sqlite_wal")?;
+        let path = dir.path().join("db.sqlite");
+
+        let con = open_sqlite_path(&path, false)?;
+        assert_eq!(journal_mode(&con)?, "delete");
+
+        let options = SqliteOptions::default()
+            .with_wal()
+            .with_synchronous(SqliteSynchronous::Normal);
+        let con = open_existing_sqlite_path_with_options(&path, false, &options)?;
+        assert_eq!(journal_mode(&con)?, "wal");
+
+        // WAL mode is stored in the DB, so it stays on for other connections
+        let con = open_existing_sqlite_path(&path, true)?;
+        assert_eq!(journal_mode(&con)?, "wal");
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_in_memory() -> Result<()> {
+        let writer = open_shared_sqlite_in_memory("test_shared_in_memory")?;
+        writer.execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1);")?;
+        let count = |con: &SqliteConnection| -> Result<i64> {
+            Ok(con.query_row("SELECT COUNT(*) FROM t", SQLITE_NO_PARAMS, |row| row.get(0))?)
+        };
+
+        // Connections by the same name see the same data
+        let reader = open_shared_sqlite_in_memory("test_shared_in_memory")?;
+        assert_eq!(count(&reader)?, 1);
+
+        // Other names are other databases
+        let other = open_shared_sqlite_in_memory("test_shared_in_memory_other")?;
+        assert!(count(&other).is_err());
+        Ok(())
+    }
+}
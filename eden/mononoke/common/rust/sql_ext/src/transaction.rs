@@ -0,0 +1,261 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Error, Result};
+use futures::compat::Future01CompatExt;
+use rand::Rng;
+use sql::rusqlite::{Error as SqliteError, ErrorCode as SqliteErrorCode};
+use sql::{Connection, Transaction};
+use std::future::Future;
+use std::time::Duration;
+use tokio::time;
+
+use crate::SqlConnections;
+
+/// Messages of the MySQL errors after which the transaction was rolled back, because of a
+/// deadlock or a lock timeout, so it can be retried from the start.
+const ROLLED_BACK_MYSQL_ERRORS: &[&str] = &[
+    "Deadlock found when trying to get lock",
+    "Lock wait timeout exceeded",
+];
+
+/// Messages of the MySQL errors after which the connection is lost. The transaction can only be
+/// retried if this happened before the commit was sent, as the commit may have been applied.
+const CONNECTION_LOST_MYSQL_ERRORS: &[&str] = &[
+    "Lost connection to MySQL server",
+    "MySQL server has gone away",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransactionRetryOptions {
+    /// Attempts made before giving up on transient errors. 1 disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry. It doubles for each retry after that.
+    pub base_delay: Duration,
+    /// Retries are never delayed by more than this.
+    pub max_delay: Duration,
+    /// Fraction of each delay that is random, between 0 and 1, so that transactions which
+    /// conflicted with each other are not retried at the same time again.
+    pub jitter: f64,
+}
+
+impl TransactionRetryOptions {
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration, jitter: f64) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+            max_delay,
+            jitter: jitter.max(0.0).min(1.0),
+        }
+    }
+
+    fn jittered(&self, delay: Duration) -> Duration {
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        let factor = rand::thread_rng().gen_range(1.0 - self.jitter, 1.0);
+        delay.mul_f64(factor)
+    }
+}
+
+impl Default for TransactionRetryOptions {
+    fn default() -> Self {
+        Self::new(5, Duration::from_millis(50), Duration::from_secs(2), 0.5)
+    }
+}
+
+fn has_mysql_error(error: &Error, messages: &[&str]) -> bool {
+    error.chain().any(|cause| {
+        let message = cause.to_string();
+        messages.iter().any(|m| message.contains(m))
+    })
+}
+
+/// Whether the connection was lost, so that it is unknown whether the statement that failed
+/// with this error was applied.
+fn is_connection_lost_error(error: &Error) -> bool {
+    has_mysql_error(error, CONNECTION_LOST_MYSQL_ERRORS)
+}
+
+/// Whether the transaction that failed with this error before it was committed may succeed if it
+/// is run again.
+pub fn is_transient_error(error: &Error) -> bool {
+    let sqlite_busy = error
+        .chain()
+        .any(|cause| match cause.downcast_ref::<SqliteError>() {
+            Some(SqliteError::SqliteFailure(e, _)) => {
+                e.code == SqliteErrorCode::DatabaseBusy || e.code == SqliteErrorCode::DatabaseLocked
+            }
+            _ => false,
+        });
+    sqlite_busy
+        || has_mysql_error(error, ROLLED_BACK_MYSQL_ERRORS)
+        || is_connection_lost_error(error)
+}
+
+/// Run `body` in a transaction on `connection` and commit it, starting over in a new
+/// transaction when starting, running or committing it fails with a transient error. If the
+/// connection is lost while committing, the commit may have been applied, so the error is
+/// returned rather than retried.
+///
+/// The body may run several times, so it must only act through the transaction it is given.
+/// It returns the transaction, to be committed, along with its result.
+pub async fn transaction_with_retries<T, F, Fut>(
+    connection: &Connection,
+    options: &TransactionRetryOptions,
+    body: F,
+) -> Result<T>
+where
+    F: Fn(Transaction) -> Fut,
+    Fut: Future<Output = Result<(Transaction, T)>>,
+{
+    transaction_with_retries_if(connection, options, |_| false, body).await
+}
+
+/// Like `transaction_with_retries`, but also starts over when the transaction fails with an
+/// error `is_retryable` accepts, such as a conflict the body detected itself.
+pub async fn transaction_with_retries_if<T, R, F, Fut>(
+    connection: &Connection,
+    options: &TransactionRetryOptions,
+    is_retryable: R,
+    body: F,
+) -> Result<T>
+where
+    R: Fn(&Error) -> bool,
+    F: Fn(Transaction) -> Fut,
+    Fut: Future<Output = Result<(Transaction, T)>>,
+{
+    let mut delay = options.base_delay;
+    let mut attempt = 1;
+    loop {
+        let mut committing = false;
+        let res: Result<T> = async {
+            let txn = connection.start_transaction().compat().await?;
+            let (txn, value) = body(txn).await?;
+            committing = true;
+            txn.commit().compat().await?;
+            Ok(value)
+        }
+        .await;
+
+        match res {
+            Err(e)
+                if attempt < options.max_attempts
+                    && (is_transient_error(&e) || is_retryable(&e))
+                    && !(committing && is_connection_lost_error(&e)) =>
+            {
+                time::delay_for(options.jittered(delay)).await;
+                delay = (delay * 2).min(options.max_delay);
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+impl SqlConnections {
+    /// Run `body` in a transaction on the write connection, retrying on transient errors. See
+    /// `transaction_with_retries`.
+    pub async fn transaction_with_retries<T, F, Fut>(
+        &self,
+        options: &TransactionRetryOptions,
+        body: F,
+    ) -> Result<T>
+    where
+        F: Fn(Transaction) -> Fut,
+        Fut: Future<Output = Result<(Transaction, T)>>,
+    {
+        transaction_with_retries(&self.write_connection, options, body).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::open_sqlite_in_memory;
+    use anyhow::format_err;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn options() -> TransactionRetryOptions {
+        TransactionRetryOptions::new(3, Duration::from_millis(1), Duration::from_millis(2), 0.5)
+    }
+
+    #[test]
+    fn test_is_transient_error() {
+        let deadlock = format_err!("Deadlock found when trying to get lock; try restarting");
+        assert!(is_transient_error(&deadlock.context("While updating")));
+        assert!(!is_transient_error(&format_err!("Duplicate entry")));
+        let gone_away = format_err!("MySQL server has gone away");
+        assert!(is_transient_error(&gone_away));
+        assert!(is_connection_lost_error(&gone_away));
+        let deadlock = format_err!("Deadlock found when trying to get lock");
+        assert!(!is_connection_lost_error(&deadlock));
+    }
+
+    #[test]
+    fn test_transaction_with_retries() -> Result<()> {
+        async_unit::tokio_unit_test(async move {
+            let connections =
+                SqlConnections::new_single(Connection::with_sqlite(open_sqlite_in_memory()?));
+            let attempts = &AtomicU32::new(0);
+
+            // Transient errors are retried until the body succeeds
+            let res = connections
+                .transaction_with_retries(&options(), move |txn| async move {
+                    let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+                    if attempt < 3 {
+                        Err(format_err!("Lost connection to MySQL server during query"))
+                    } else {
+                        Ok((txn, attempt))
+                    }
+                })
+                .await?;
+            assert_eq!(res, 3);
+
+            // Running out of attempts returns the last error
+            attempts.store(0, Ordering::SeqCst);
+            let res: Result<()> = connections
+                .transaction_with_retries(&options(), move |_txn| async move {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    Err(format_err!("Deadlock found when trying to get lock"))
+                })
+                .await;
+            assert!(res.is_err());
+            assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+            // Other errors are not retried
+            attempts.store(0, Ordering::SeqCst);
+            let res: Result<()> = connections
+                .transaction_with_retries(&options(), move |_txn| async move {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    Err(format_err!("Duplicate entry"))
+                })
+                .await;
+            assert!(res.is_err());
+            assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+            // Unless the caller accepts them
+            attempts.store(0, Ordering::SeqCst);
+            let res = transaction_with_retries_if(
+                &connections.write_connection,
+                &options(),
+                |e| e.to_string() == "Duplicate entry",
+                move |txn| async move {
+                    let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+                    if attempt < 2 {
+                        Err(format_err!("Duplicate entry"))
+                    } else {
+                        Ok((txn, attempt))
+                    }
+                },
+            )
+            .await?;
+            assert_eq!(res, 2);
+            Ok(())
+        })
+    }
+}
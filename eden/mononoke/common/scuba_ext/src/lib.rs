@@ -7,6 +7,8 @@
 
 #![deny(warnings)]
 
+mod sink;
+
 use fbinit::FacebookInit;
 use futures_stats::{FutureStats, StreamStats};
 use observability::{ObservabilityContext, ScubaLoggingDecisionFields, ScubaVerbosityLevel};
@@ -18,11 +20,14 @@ use std::convert::TryInto;
 use std::io::Error as IoError;
 use std::num::NonZeroU64;
 use std::path::Path;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use time_ext::DurationExt;
 use tunables::tunables;
 
 pub use scribe_ext::ScribeClientImplementation;
+use sink::sample_to_json;
+pub use sink::{HttpSink, RotatingFileSink, ScubaSink, ScubaSinkOptions, ScubaSinkSpec, UdpSink};
 
 /// An extensible wrapper struct around `ScubaSampleBuilder`
 #[derive(Clone)]
@@ -32,6 +37,8 @@ pub struct MononokeScubaSampleBuilder {
     // This field decides if sampled out requests should
     // still be logged when verbose logging is enabled
     fallback_sampled_out_to_verbose: bool,
+    // Also receives every logged sample, on top of the scuba table or log file
+    maybe_sink: Option<Arc<dyn ScubaSink>>,
 }
 
 impl std::fmt::Debug for MononokeScubaSampleBuilder {
@@ -46,6 +53,7 @@ impl MononokeScubaSampleBuilder {
             inner: ScubaSampleBuilder::new(fb, scuba_table),
             maybe_observability_context: None,
             fallback_sampled_out_to_verbose: false,
+            maybe_sink: None,
         }
     }
 
@@ -54,6 +62,7 @@ impl MononokeScubaSampleBuilder {
             inner: ScubaSampleBuilder::with_discard(),
             maybe_observability_context: None,
             fallback_sampled_out_to_verbose: false,
+            maybe_sink: None,
         }
     }
 
@@ -71,6 +80,22 @@ impl MononokeScubaSampleBuilder {
         }
     }
 
+    pub fn with_sink(self, sink: Arc<dyn ScubaSink>) -> Self {
+        Self {
+            maybe_sink: Some(sink),
+            ..self
+        }
+    }
+
+    fn write_to_sink(&self, time: u64) {
+        if let Some(sink) = &self.maybe_sink {
+            // Like logging to scuba, this is best effort: a failing sink must not fail requests
+            if let Ok(json) = sample_to_json(self.inner.get_sample(), time) {
+                let _ = sink.write_sample(&json);
+            }
+        }
+    }
+
     fn get_logging_decision_fields(&self) -> ScubaLoggingDecisionFields {
         ScubaLoggingDecisionFields {
             maybe_session_id: self.get("session_uuid"),
@@ -157,7 +182,7 @@ impl MononokeScubaSampleBuilder {
 
             self.inner.add("msg", msg);
         }
-        self.inner.log();
+        self.log();
     }
 
     /// Same as `log_with_msg`, but sample is assumed to be verbose and is only logged
@@ -218,7 +243,14 @@ impl MononokeScubaSampleBuilder {
     }
 
     pub fn log(&mut self) -> bool {
-        self.inner.log()
+        let logged = self.inner.log();
+        if logged {
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_secs());
+            self.write_to_sink(time);
+        }
+        logged
     }
 
     /// Same as `log`, but sample is assumed to be verbose and is only logged
@@ -262,7 +294,11 @@ impl MononokeScubaSampleBuilder {
     }
 
     pub fn log_with_time(&mut self, time: u64) -> bool {
-        self.inner.log_with_time(time)
+        let logged = self.inner.log_with_time(time);
+        if logged {
+            self.write_to_sink(time);
+        }
+        logged
     }
 
     pub fn entry<K: Into<String>>(&mut self, key: K) -> Entry<String, ScubaValue> {
@@ -0,0 +1,402 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Sinks which receive every logged sample as a JSON object, in addition to the scuba table or
+//! log file of the builder. They give builds without access to scuba structured telemetry that
+//! can be shipped to other systems.
+
+use anyhow::{bail, format_err, Context, Error, Result};
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Request, Uri};
+use scuba::ScubaSample;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::runtime;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::time::{timeout, timeout_at, Instant};
+
+/// Somewhere logged samples are sent to
+pub trait ScubaSink: Send + Sync + 'static {
+    /// Called with every sample which passed sampling, serialized as a single line of JSON
+    fn write_sample(&self, json: &str) -> Result<()>;
+}
+
+/// Where to send samples, as given on the command line: `file:<path>` for a JSON-lines file,
+/// `udp:<host>:<port>` for one datagram per sample, or an `http://` URL to POST batches of
+/// samples to, as JSON lines.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScubaSinkSpec {
+    File(PathBuf),
+    Udp(String),
+    Http(String),
+}
+
+impl FromStr for ScubaSinkSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix("file:") {
+            Ok(ScubaSinkSpec::File(PathBuf::from(path)))
+        } else if let Some(addr) = s.strip_prefix("udp:") {
+            Ok(ScubaSinkSpec::Udp(addr.to_string()))
+        } else if s.starts_with("http://") {
+            Ok(ScubaSinkSpec::Http(s.to_string()))
+        } else {
+            bail!(
+                "Invalid scuba sink {:?}: expected file:<path>, udp:<host>:<port> or http://<host>[:<port>]/<path>",
+                s
+            )
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScubaSinkOptions {
+    /// File sinks are rotated once they would grow larger than this
+    pub max_file_size: u64,
+    /// Number of rotated files kept by file sinks, as <path>.1 (the most recent) to <path>.N
+    pub max_files: usize,
+}
+
+impl Default for ScubaSinkOptions {
+    fn default() -> Self {
+        Self {
+            max_file_size: 100 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+impl ScubaSinkSpec {
+    pub fn open(&self, options: ScubaSinkOptions) -> Result<Arc<dyn ScubaSink>> {
+        let sink: Arc<dyn ScubaSink> = match self {
+            ScubaSinkSpec::File(path) => Arc::new(RotatingFileSink::open(path, options)?),
+            ScubaSinkSpec::Udp(addr) => Arc::new(UdpSink::connect(addr)?),
+            ScubaSinkSpec::Http(url) => Arc::new(HttpSink::new(url)?),
+        };
+        Ok(sink)
+    }
+}
+
+pub(crate) fn sample_to_json(sample: &ScubaSample, time: u64) -> Result<String> {
+    let mut sample = sample.clone();
+    sample.add("time", time);
+    Ok(sample.to_json()?.to_string())
+}
+
+struct RotatingFile {
+    file: File,
+    size: u64,
+}
+
+/// Appends samples to a file, one JSON object per line, rotating it when it gets too large
+pub struct RotatingFileSink {
+    path: PathBuf,
+    options: ScubaSinkOptions,
+    current: Mutex<RotatingFile>,
+}
+
+impl RotatingFileSink {
+    pub fn open(path: &Path, options: ScubaSinkOptions) -> Result<Self> {
+        let current = Self::open_file(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            options,
+            current: Mutex::new(current),
+        })
+    }
+
+    fn open_file(path: &Path) -> Result<RotatingFile> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("While opening scuba sink {}", path.display()))?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile { file, size })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&self) -> Result<RotatingFile> {
+        if self.options.max_files > 0 {
+            for n in (1..self.options.max_files).rev() {
+                match fs::rename(self.rotated_path(n), self.rotated_path(n + 1)) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+        Self::open_file(&self.path)
+    }
+}
+
+impl ScubaSink for RotatingFileSink {
+    fn write_sample(&self, json: &str) -> Result<()> {
+        let mut current = self.current.lock().expect("lock poisoned");
+        let len = json.len() as u64 + 1;
+        if current.size > 0 && current.size + len > self.options.max_file_size {
+            *current = self.rotate()?;
+        }
+        writeln!(current.file, "{}", json)?;
+        current.size += len;
+        Ok(())
+    }
+}
+
+/// Sends every sample as a UDP datagram. Delivery is not guaranteed, and samples larger than a
+/// datagram are dropped.
+pub struct UdpSink {
+    socket: UdpSocket,
+}
+
+impl UdpSink {
+    pub fn connect(addr: &str) -> Result<Self> {
+        let addr = resolve(addr)?;
+        let bind_addr = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind_addr)?;
+        socket.connect(addr)?;
+        // Logging must not block requests
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+}
+
+impl ScubaSink for UdpSink {
+    fn write_sample(&self, json: &str) -> Result<()> {
+        self.socket.send(json.as_bytes())?;
+        Ok(())
+    }
+}
+
+// Samples waiting to be sent by an HttpSink. Samples are dropped when the queue is full, rather
+// than slowing down logging.
+const HTTP_QUEUE_SIZE: usize = 10000;
+// Samples are sent in batches of at most this many samples, at most this long after the first
+// sample of the batch was logged
+const HTTP_BATCH_SIZE: usize = 1000;
+const HTTP_BATCH_DELAY: Duration = Duration::from_secs(1);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// POSTs samples to an HTTP endpoint from a background thread, in batches of JSON lines. The
+/// connection to the endpoint is kept alive between batches.
+pub struct HttpSink {
+    sender: Mutex<Sender<String>>,
+}
+
+impl HttpSink {
+    pub fn new(url: &str) -> Result<Self> {
+        Self::with_batching(url, HTTP_BATCH_SIZE, HTTP_BATCH_DELAY)
+    }
+
+    fn with_batching(url: &str, batch_size: usize, batch_delay: Duration) -> Result<Self> {
+        let uri: Uri = url
+            .parse()
+            .with_context(|| format!("Invalid scuba sink URL {}", url))?;
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()?;
+        let client = Client::new();
+
+        let (sender, mut receiver) = channel::<String>(HTTP_QUEUE_SIZE);
+        thread::Builder::new()
+            .name("scuba_http_sink".to_string())
+            .spawn(move || {
+                runtime.block_on(async move {
+                    while let Some(batch) = next_batch(&mut receiver, batch_size, batch_delay).await
+                    {
+                        // Errors are dropped: the endpoint being down must not affect the server
+                        let _ = http_post(&client, &uri, batch).await;
+                    }
+                })
+            })?;
+        Ok(Self {
+            sender: Mutex::new(sender),
+        })
+    }
+}
+
+impl ScubaSink for HttpSink {
+    fn write_sample(&self, json: &str) -> Result<()> {
+        self.sender
+            .lock()
+            .expect("lock poisoned")
+            .try_send(json.to_string())
+            .map_err(|_| format_err!("Scuba HTTP sink queue is full or closed"))
+    }
+}
+
+fn resolve(addr: &str) -> Result<SocketAddr> {
+    addr.to_socket_addrs()
+        .with_context(|| format!("While resolving scuba sink address {}", addr))?
+        .next()
+        .ok_or_else(|| format_err!("Scuba sink address {} did not resolve", addr))
+}
+
+/// Wait for a sample, then for more until the batch is full or `delay` has passed. Returns None
+/// once the sink is dropped.
+async fn next_batch(
+    receiver: &mut Receiver<String>,
+    size: usize,
+    delay: Duration,
+) -> Option<Vec<String>> {
+    let mut batch = vec![receiver.recv().await?];
+    let deadline = Instant::now() + delay;
+    while batch.len() < size {
+        match timeout_at(deadline, receiver.recv()).await {
+            Ok(Some(json)) => batch.push(json),
+            Ok(None) | Err(_) => break,
+        }
+    }
+    Some(batch)
+}
+
+async fn http_post(client: &Client<HttpConnector>, uri: &Uri, batch: Vec<String>) -> Result<()> {
+    let mut body = batch.join("\n");
+    body.push('\n');
+    let request = Request::post(uri.clone())
+        .header(CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::from(body))?;
+    timeout(HTTP_TIMEOUT, async {
+        let response = client.request(request).await?;
+        let status = response.status();
+        // Read the whole response, so that the connection can be reused
+        hyper::body::to_bytes(response.into_body()).await?;
+        if !status.is_success() {
+            bail!("Scuba HTTP sink got {}", status);
+        }
+        Ok(())
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_parse_spec() -> Result<()> {
+        assert_eq!(
+            "file:/tmp/scuba.json".parse::<ScubaSinkSpec>()?,
+            ScubaSinkSpec::File(PathBuf::from("/tmp/scuba.json"))
+        );
+        assert_eq!(
+            "udp:localhost:1234".parse::<ScubaSinkSpec>()?,
+            ScubaSinkSpec::Udp("localhost:1234".to_string())
+        );
+        assert_eq!(
+            "http://localhost:8080/log".parse::<ScubaSinkSpec>()?,
+            ScubaSinkSpec::Http("http://localhost:8080/log".to_string())
+        );
+        assert!("https://localhost/log".parse::<ScubaSinkSpec>().is_err());
+        assert!("/tmp/scuba.json".parse::<ScubaSinkSpec>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_rotation() -> Result<()> {
+        let dir = TempDir::new("scuba_sink")?;
+        let path = dir.path().join("scuba.json");
+        let options = ScubaSinkOptions {
+            max_file_size: 10,
+            max_files: 2,
+        };
+        let sink = RotatingFileSink::open(&path, options)?;
+        for json in &["\"one\"", "\"two\"", "\"three\"", "\"four\""] {
+            sink.write_sample(json)?;
+        }
+
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap_or_default();
+        assert_eq!(read(path.clone()), "\"four\"\n");
+        assert_eq!(read(sink.rotated_path(1)), "\"three\"\n");
+        assert_eq!(read(sink.rotated_path(2)), "\"two\"\n");
+        assert!(!sink.rotated_path(3).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_http() -> Result<()> {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Response, Server};
+        use std::convert::Infallible;
+        use std::sync::mpsc::channel;
+        use std::time::Duration;
+
+        // An endpoint which reports the number of connections and the bodies it receives
+        let (sender, received) = channel();
+        let mut server_runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()?;
+        let server = server_runtime.enter(|| {
+            Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service_fn(move |_| {
+                let sender = Mutex::new(sender.clone());
+                sender.lock().unwrap().send(None).unwrap();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                        let sender = sender.lock().unwrap().clone();
+                        async move {
+                            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                            sender.send(Some(body)).unwrap();
+                            Ok::<_, Infallible>(Response::new(Body::empty()))
+                        }
+                    }))
+                }
+            }))
+        });
+        let url = format!("http://{}/log", server.local_addr());
+        thread::spawn(move || server_runtime.block_on(server));
+
+        let sink = HttpSink::with_batching(&url, 2, Duration::from_millis(100))?;
+        for json in &["1", "2", "3"] {
+            sink.write_sample(json)?;
+        }
+        let mut expected = vec![None, Some("1\n2\n"), Some("3\n")];
+        // The connection is reused for a batch logged after the previous one was sent
+        thread::sleep(Duration::from_millis(200));
+        sink.write_sample("4")?;
+        expected.push(Some("4\n"));
+
+        for expected in expected {
+            let got = received.recv_timeout(Duration::from_secs(5))?;
+            assert_eq!(got.as_deref(), expected.map(str::as_bytes));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_udp() -> Result<()> {
+        let receiver = UdpSocket::bind("127.0.0.1:0")?;
+        let sink = UdpSink::connect(&receiver.local_addr()?.to_string())?;
+        sink.write_sample("{\"int\":{}}")?;
+
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf)?;
+        assert_eq!(&buf[..len], b"{\"int\":{}}");
+        Ok(())
+    }
+}
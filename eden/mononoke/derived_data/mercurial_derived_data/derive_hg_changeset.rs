@@ -282,6 +282,28 @@ async fn generate_hg_changeset(
     parents: Vec<HgBlobChangeset>,
 ) -> Result<HgChangesetId, Error> {
     let start_timestamp = Instant::now();
+    let cs = regenerate_hg_changeset(&repo, ctx.clone(), bcs, parents).await?;
+    let csid = cs.get_changeset_id();
+
+    cs.save(&ctx, repo.blobstore()).await?;
+
+    STATS::generate_hg_from_bonsai_single_latency_ms
+        .add_value(start_timestamp.elapsed().as_millis() as i64);
+    STATS::generate_hg_from_bonsai_generated_commit_num.add_value(1);
+
+    Ok(csid)
+}
+
+/// Build the hg changeset for a bonsai changeset from its hg parents, without saving the
+/// changeset or recording it in the bonsai-hg mapping. The manifests and filenodes it refers
+/// to are still uploaded to the repo's blobstore, so use a repo with an in-memory blobstore
+/// override to avoid any writes.
+pub async fn regenerate_hg_changeset(
+    repo: &BlobRepo,
+    ctx: CoreContext,
+    bcs: BonsaiChangeset,
+    parents: Vec<HgBlobChangeset>,
+) -> Result<HgBlobChangeset, Error> {
     let parent_manifests = parents.iter().map(|p| p.manifestid()).collect();
 
     // NOTE: We're special-casing the first 2 parents here, since that's all Mercurial
@@ -308,7 +330,7 @@ async fn generate_hg_changeset(
     let step_parents = parents;
 
     let manifest_id =
-        get_manifest_from_bonsai(&repo, ctx.clone(), bcs.clone(), parent_manifests).await?;
+        get_manifest_from_bonsai(repo, ctx.clone(), bcs.clone(), parent_manifests).await?;
     let files =
         compute_changed_files(ctx.clone(), repo.clone(), manifest_id.clone(), mf_p1, mf_p2).await?;
 
@@ -324,16 +346,7 @@ async fn generate_hg_changeset(
     metadata.record_step_parents(step_parents.map(|blob| blob.get_changeset_id()));
 
     let content = HgChangesetContent::new_from_parts(hg_parents, manifest_id, metadata, files);
-    let cs = HgBlobChangeset::new(content)?;
-    let csid = cs.get_changeset_id();
-
-    cs.save(&ctx, repo.blobstore()).await?;
-
-    STATS::generate_hg_from_bonsai_single_latency_ms
-        .add_value(start_timestamp.elapsed().as_millis() as i64);
-    STATS::generate_hg_from_bonsai_generated_commit_num.add_value(1);
-
-    Ok(csid)
+    HgBlobChangeset::new(content)
 }
 
 pub async fn get_hg_from_bonsai_changeset(
@@ -11,6 +11,8 @@ pub mod derive_hg_changeset;
 pub mod derive_hg_manifest;
 mod mapping;
 
-pub use derive_hg_changeset::{get_hg_from_bonsai_changeset, get_manifest_from_bonsai};
+pub use derive_hg_changeset::{
+    get_hg_from_bonsai_changeset, get_manifest_from_bonsai, regenerate_hg_changeset,
+};
 pub use derive_hg_manifest::derive_hg_manifest;
 pub use mapping::{HgChangesetIdMapping, MappedHgChangesetId};
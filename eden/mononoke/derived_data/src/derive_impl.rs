@@ -10,7 +10,7 @@ use anyhow::{Error, Result};
 use blobrepo::BlobRepo;
 use blobstore::Loadable;
 use cacheblob::LeaseOps;
-use context::CoreContext;
+use context::{CoreContext, REQUEST_ID};
 use futures::{
     channel::oneshot,
     future::{try_join, try_join_all, FutureExt, TryFutureExt},
@@ -443,6 +443,9 @@ fn init_derived_data_scuba<Derivable: BonsaiDerivable>(
             builder.add("derived_data", Derivable::NAME);
             builder.add("reponame", name);
             builder.add("changeset", format!("{}", bcs_id));
+            if let Some(request_id) = ctx.request_id() {
+                builder.add(REQUEST_ID, request_id.as_str());
+            }
             builder
         }
         None => MononokeScubaSampleBuilder::with_discard(),
@@ -58,6 +58,8 @@ pub enum ErrorKind {
     HistoryFetchFailed(Key),
     #[error("Complete tree request failed")]
     CompleteTreeRequestFailed,
+    #[error("Path prefixes cannot be combined with base manifest nodes")]
+    PrefixesWithBaseNodes,
     #[error("Dag location to hash request failed")]
     CommitLocationToHashRequestFailed,
     #[error("Commit data request failed")]
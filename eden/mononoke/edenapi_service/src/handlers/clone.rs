@@ -11,7 +11,7 @@ use gotham::state::{FromState, State};
 use gotham_derive::{StateData, StaticResponseExtender};
 use serde::Deserialize;
 
-use edenapi_types::wire::{ToWire, WireCloneData, WireIdMapEntry};
+use edenapi_types::wire::{ToWire, WireCloneData, WireIdMapEntry, WirePullFastForwardRequest};
 use gotham_ext::content::ContentStream;
 use gotham_ext::error::HttpError;
 use gotham_ext::response::{BytesBody, StreamBody, TryIntoResponse};
@@ -22,7 +22,7 @@ use crate::context::ServerContext;
 use crate::errors::MononokeErrorExt;
 use crate::handlers::{EdenApiMethod, HandlerInfo};
 use crate::middleware::RequestContext;
-use crate::utils::{cbor, get_repo};
+use crate::utils::{cbor, get_repo, parse_wire_request};
 
 #[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
 pub struct CloneParams {
@@ -66,7 +66,10 @@ pub async fn clone_data(state: &mut State) -> Result<BytesBody<Bytes>, HttpError
 pub async fn full_idmap_clone_data(state: &mut State) -> Result<impl TryIntoResponse, HttpError> {
     let params = CloneParams::take_from(state);
 
-    state.put(HandlerInfo::new(&params.repo, EdenApiMethod::Clone));
+    state.put(HandlerInfo::new(
+        &params.repo,
+        EdenApiMethod::FullIdMapClone,
+    ));
 
     let sctx = ServerContext::borrow_from(state);
     let rctx = RequestContext::borrow_from(state).clone();
@@ -101,3 +104,44 @@ pub async fn full_idmap_clone_data(state: &mut State) -> Result<impl TryIntoResp
 
     Ok(StreamBody::new(content_stream, cbor::cbor_mime()))
 }
+
+/// Segmented changelog data for a client to move its master group forward. Unlike `clone_data`,
+/// only the commits that the client does not have yet are sent.
+pub async fn pull_fast_forward_master(state: &mut State) -> Result<BytesBody<Bytes>, HttpError> {
+    let params = CloneParams::take_from(state);
+
+    state.put(HandlerInfo::new(
+        &params.repo,
+        EdenApiMethod::PullFastForwardMaster,
+    ));
+
+    let sctx = ServerContext::borrow_from(state);
+    let rctx = RequestContext::borrow_from(state).clone();
+    let hg_repo_ctx = get_repo(&sctx, &rctx, &params.repo, None).await?;
+    let request = parse_wire_request::<WirePullFastForwardRequest>(state).await?;
+    let pull_data = hg_repo_ctx
+        .segmented_changelog_pull_fast_forward_master(
+            request.old_master.into(),
+            request.new_master.into(),
+        )
+        .await
+        .map_err(|e| e.into_http_error("error getting segmented changelog pull data"))?;
+    let idmap = pull_data
+        .idmap
+        .into_iter()
+        .map(|(k, v)| WireIdMapEntry {
+            dag_id: k.to_wire(),
+            hg_id: HgId::from(v.into_nodehash()).to_wire(),
+        })
+        .collect();
+    let wire_pull_data = WireCloneData {
+        head_id: pull_data.head_id.to_wire(),
+        flat_segments: pull_data.flat_segments.segments.to_wire(),
+        idmap,
+    };
+
+    Ok(BytesBody::new(
+        cbor::to_cbor_bytes(wire_pull_data).map_err(HttpError::e500)?,
+        cbor::cbor_mime(),
+    ))
+}
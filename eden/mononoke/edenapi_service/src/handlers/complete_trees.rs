@@ -65,6 +65,11 @@ pub async fn complete_trees(state: &mut State) -> Result<impl TryIntoResponse, H
 /// underneath each of these versions. Any tree node reachable
 /// from any of these root nodes will not be fetched.
 ///
+/// If the request specifies path prefixes, the tree is instead walked
+/// breadth-first and only the directories leading to, matching, or
+/// beneath one of the prefixes are returned. Base versions cannot
+/// be combined with prefixes.
+///
 /// This is essentially an HTTP-based implementation of Mercurial's
 /// `gettreepack` wire protocol command. This is generally considered
 /// a fairly expensive way to request trees. When possible, clients
@@ -90,8 +95,26 @@ fn fetch_trees_under_path(
         .map(|hgid| HgManifestId::new(HgNodeHash::from(hgid)))
         .collect::<Vec<_>>();
 
-    let stream = repo
-        .trees_under_path(path, root_nodes, base_nodes, request.depth)
+    let trees = if request.prefixes.is_empty() {
+        repo.trees_under_path(path, root_nodes, base_nodes, request.depth)
+            .left_stream()
+    } else {
+        if !base_nodes.is_empty() {
+            return Err(HttpError::e400(ErrorKind::PrefixesWithBaseNodes));
+        }
+
+        let prefixes = request
+            .prefixes
+            .into_iter()
+            .map(to_mononoke_path)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(HttpError::e400)?;
+
+        repo.trees_under_path_with_prefixes(path, root_nodes, request.depth, prefixes)
+            .right_stream()
+    };
+
+    let stream = trees
         .err_into::<Error>()
         .map_err(|e| EdenApiServerError::new(e.context(ErrorKind::CompleteTreeRequestFailed)))
         .and_then(move |(tree, path)| async { entry_for_tree(tree, path) })
@@ -13,7 +13,7 @@ use serde::Deserialize;
 
 use edenapi_types::{
     wire::{ToWire, WireFileRequest},
-    FileEntry, FileRequest,
+    Blake3, FileAuxData, FileEntry, FileRequest, Sha256,
 };
 use gotham_ext::{error::HttpError, response::TryIntoResponse};
 use load_limiter::Metric;
@@ -54,6 +54,31 @@ pub async fn files(state: &mut State) -> Result<impl TryIntoResponse, HttpError>
     ))
 }
 
+/// Fetch the size and content hashes of the files requested by the client, without their content.
+pub async fn files_aux(state: &mut State) -> Result<impl TryIntoResponse, HttpError> {
+    let params = FileParams::take_from(state);
+
+    state.put(HandlerInfo::new(&params.repo, EdenApiMethod::FilesAux));
+
+    let rctx = RequestContext::borrow_from(state).clone();
+    let sctx = ServerContext::borrow_from(state);
+
+    let repo = get_repo(&sctx, &rctx, &params.repo, None).await?;
+    let request = parse_wire_request::<WireFileRequest>(state).await?;
+
+    let fetches = request
+        .keys
+        .into_iter()
+        .map(move |key| fetch_file_aux(repo.clone(), key));
+
+    Ok(cbor_stream(
+        rctx,
+        stream::iter(fetches)
+            .buffer_unordered(MAX_CONCURRENT_FILE_FETCHES_PER_REQUEST)
+            .map(|r| r.map(|v| v.to_wire())),
+    ))
+}
+
 /// Fetch files for all of the requested keys concurrently.
 fn fetch_all_files(
     repo: HgRepoContext,
@@ -93,3 +118,26 @@ async fn fetch_file(repo: HgRepoContext, key: Key) -> Result<FileEntry, Error> {
 
     Ok(FileEntry::new(key, data, parents, metadata))
 }
+
+/// Fetch the aux data of the file for a single key.
+async fn fetch_file_aux(repo: HgRepoContext, key: Key) -> Result<FileAuxData, Error> {
+    let id = HgFileNodeId::from_node_hash(HgNodeHash::from(key.hgid));
+
+    let ctx = id
+        .context(repo)
+        .await
+        .with_context(|| ErrorKind::FileFetchFailed(key.clone()))?
+        .with_context(|| ErrorKind::KeyDoesNotExist(key.clone()))?;
+
+    let aux = ctx
+        .fetch_aux_data()
+        .await
+        .with_context(|| ErrorKind::FileFetchFailed(key.clone()))?;
+
+    Ok(FileAuxData {
+        key,
+        total_size: aux.total_size,
+        content_sha256: Sha256(aux.sha256.into_inner()),
+        content_blake3: Blake3(aux.blake3),
+    })
+}
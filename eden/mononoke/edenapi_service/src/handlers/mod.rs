@@ -32,6 +32,7 @@ mod complete_trees;
 mod files;
 mod history;
 mod repos;
+mod sparse;
 mod trees;
 
 /// Enum identifying the EdenAPI method that each handler corresponds to.
@@ -39,6 +40,7 @@ mod trees;
 #[derive(Copy, Clone)]
 pub enum EdenApiMethod {
     Files,
+    FilesAux,
     Trees,
     CompleteTrees,
     History,
@@ -47,12 +49,15 @@ pub enum EdenApiMethod {
     CommitRevlogData,
     Clone,
     FullIdMapClone,
+    PullFastForwardMaster,
+    SparseProfile,
 }
 
 impl fmt::Display for EdenApiMethod {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let name = match self {
             Self::Files => "files",
+            Self::FilesAux => "files_aux",
             Self::Trees => "trees",
             Self::CompleteTrees => "complete_trees",
             Self::History => "history",
@@ -61,6 +66,8 @@ impl fmt::Display for EdenApiMethod {
             Self::CommitRevlogData => "commit_revlog_data",
             Self::Clone => "clone",
             Self::FullIdMapClone => "full_idmap_clone",
+            Self::PullFastForwardMaster => "pull_fast_forward_master",
+            Self::SparseProfile => "sparse_profile",
         };
         write!(f, "{}", name)
     }
@@ -117,6 +124,7 @@ macro_rules! define_handler {
 
 define_handler!(repos_handler, repos::repos);
 define_handler!(files_handler, files::files);
+define_handler!(files_aux_handler, files::files_aux);
 define_handler!(trees_handler, trees::trees);
 define_handler!(complete_trees_handler, complete_trees::complete_trees);
 define_handler!(history_handler, history::history);
@@ -125,6 +133,11 @@ define_handler!(commit_hash_to_location_handler, commit::hash_to_location);
 define_handler!(commit_revlog_data_handler, commit::revlog_data);
 define_handler!(clone_handler, clone::clone_data);
 define_handler!(full_idmap_clone_handler, clone::full_idmap_clone_data);
+define_handler!(
+    pull_fast_forward_master_handler,
+    clone::pull_fast_forward_master
+);
+define_handler!(sparse_profile_handler, sparse::sparse_profile);
 
 fn health_handler(state: State) -> (State, &'static str) {
     if ServerContext::borrow_from(&state).will_exit() {
@@ -145,6 +158,10 @@ pub fn build_router(ctx: ServerContext) -> Router {
             .post("/:repo/files")
             .with_path_extractor::<files::FileParams>()
             .to(files_handler);
+        route
+            .post("/:repo/files/aux")
+            .with_path_extractor::<files::FileParams>()
+            .to(files_aux_handler);
         route
             .post("/:repo/trees")
             .with_path_extractor::<trees::TreeParams>()
@@ -177,5 +194,13 @@ pub fn build_router(ctx: ServerContext) -> Router {
             .post("/:repo/full_idmap_clone")
             .with_path_extractor::<clone::CloneParams>()
             .to(full_idmap_clone_handler);
+        route
+            .post("/:repo/pull_fast_forward_master")
+            .with_path_extractor::<clone::CloneParams>()
+            .to(pull_fast_forward_master_handler);
+        route
+            .get("/:repo/sparse_profile")
+            .with_path_extractor::<sparse::SparseProfileParams>()
+            .to(sparse_profile_handler);
     })
 }
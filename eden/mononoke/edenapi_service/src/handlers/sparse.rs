@@ -0,0 +1,50 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use bytes::Bytes;
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use serde::Deserialize;
+
+use edenapi_types::{SparseProfileResponse, ToWire};
+use gotham_ext::error::HttpError;
+use gotham_ext::response::BytesBody;
+use mononoke_api::path::MononokePath;
+
+use crate::context::ServerContext;
+use crate::handlers::{EdenApiMethod, HandlerInfo};
+use crate::middleware::RequestContext;
+use crate::utils::{cbor, get_repo, to_hg_path};
+
+#[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
+pub struct SparseProfileParams {
+    repo: String,
+}
+
+/// Return the sparse profile that clients should enable by default when
+/// cloning the repo, as set in the repo's config.
+pub async fn sparse_profile(state: &mut State) -> Result<BytesBody<Bytes>, HttpError> {
+    let params = SparseProfileParams::take_from(state);
+
+    state.put(HandlerInfo::new(&params.repo, EdenApiMethod::SparseProfile));
+
+    let sctx = ServerContext::borrow_from(state);
+    let rctx = RequestContext::borrow_from(state).clone();
+    let hg_repo_ctx = get_repo(&sctx, &rctx, &params.repo, None).await?;
+
+    let profile = hg_repo_ctx
+        .default_sparse_profile()
+        .map(|path| to_hg_path(&MononokePath::new(Some(path.clone()))))
+        .transpose()
+        .map_err(HttpError::e500)?;
+    let response = SparseProfileResponse { profile };
+
+    Ok(BytesBody::new(
+        cbor::to_cbor_bytes(response.to_wire()).map_err(HttpError::e500)?,
+        cbor::cbor_mime(),
+    ))
+}
@@ -21,6 +21,7 @@ define_stats! {
     failure_5xx: dynamic_timeseries("{}.failure_5xx", (repo_and_method: String); Rate, Sum),
     response_bytes_sent: dynamic_histogram("{}.response_bytes_sent", (repo_and_method: String); 1_500_000, 0, 150_000_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     files_duration: dynamic_histogram("{}.files_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    files_aux_duration: dynamic_histogram("{}.files_aux_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     trees_duration: dynamic_histogram("{}.trees_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     complete_trees_duration: dynamic_histogram("{}.complete_trees_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     history_duration: dynamic_histogram("{}.history_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
@@ -29,6 +30,8 @@ define_stats! {
     commit_revlog_data_duration: dynamic_histogram("{}.commit_revlog_data_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     clone_duration: dynamic_histogram("{}.clone_data_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     full_idmap_clone_duration: dynamic_histogram("{}.full_idmap_clone_data_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    pull_fast_forward_master_duration: dynamic_histogram("{}.pull_fast_forward_master_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    sparse_profile_duration: dynamic_histogram("{}.sparse_profile_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
 }
 
 fn log_stats(state: &mut State, status: StatusCode) -> Option<()> {
@@ -54,6 +57,7 @@ fn log_stats(state: &mut State, status: StatusCode) -> Option<()> {
             use EdenApiMethod::*;
             match method {
                 Files => STATS::files_duration.add_value(dur_ms, (repo,)),
+                FilesAux => STATS::files_aux_duration.add_value(dur_ms, (repo,)),
                 Trees => STATS::trees_duration.add_value(dur_ms, (repo,)),
                 CompleteTrees => STATS::complete_trees_duration.add_value(dur_ms, (repo,)),
                 History => STATS::history_duration.add_value(dur_ms, (repo,)),
@@ -66,6 +70,10 @@ fn log_stats(state: &mut State, status: StatusCode) -> Option<()> {
                 CommitRevlogData => STATS::commit_revlog_data_duration.add_value(dur_ms, (repo,)),
                 Clone => STATS::clone_duration.add_value(dur_ms, (repo,)),
                 FullIdMapClone => STATS::full_idmap_clone_duration.add_value(dur_ms, (repo,)),
+                PullFastForwardMaster => {
+                    STATS::pull_fast_forward_master_duration.add_value(dur_ms, (repo,))
+                }
+                SparseProfile => STATS::sparse_profile_duration.add_value(dur_ms, (repo,)),
             }
         }
 
@@ -14,10 +14,10 @@ use gotham::state::{request_id, FromState, State};
 use gotham_derive::StateData;
 use hyper::{Body, Response};
 use load_limiter::LoadLimiterEnvironment;
-use slog::{error, o, Logger};
+use slog::{error, Logger};
 
 use cloned::cloned;
-use context::{CoreContext, SessionContainer};
+use context::{CoreContext, RequestId, SessionContainer};
 use fbinit::FacebookInit;
 use gotham_ext::middleware::{ClientIdentity, Middleware};
 use scuba_ext::MononokeScubaSampleBuilder;
@@ -98,8 +98,13 @@ impl Middleware for RequestContextMiddleware {
             .build();
 
         let request_id = request_id(&state);
-        let logger = self.logger.new(o!("request_id" => request_id.to_string()));
-        let ctx = session.new_context(logger.clone(), MononokeScubaSampleBuilder::with_discard());
+        let ctx = session
+            .new_context(
+                self.logger.clone(),
+                MononokeScubaSampleBuilder::with_discard(),
+            )
+            .with_request_id(RequestId::from_string(request_id));
+        let logger = ctx.logger().clone();
 
         state.put(RequestContext::new(ctx, logger).await);
 
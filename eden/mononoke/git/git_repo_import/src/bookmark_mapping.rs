@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Error};
+use bookmarks::BookmarkName;
+use std::collections::HashMap;
+
+/// Names of the bookmarks git branches are imported as
+#[derive(Clone, Debug, Default)]
+pub struct BookmarkMapping {
+    /// Prepended to the names of branches which are not renamed
+    prefix: String,
+    /// Branches imported under a bookmark name of their own, which is used as is
+    renames: HashMap<String, String>,
+}
+
+impl BookmarkMapping {
+    pub fn new(prefix: String, renames: HashMap<String, String>) -> Self {
+        Self { prefix, renames }
+    }
+
+    /// Parse renames given as BRANCH=BOOKMARK
+    pub fn parse_renames<'a>(
+        renames: impl IntoIterator<Item = &'a str>,
+    ) -> Result<HashMap<String, String>, Error> {
+        renames
+            .into_iter()
+            .map(|rename| match rename.find('=') {
+                Some(idx) if idx > 0 && idx + 1 < rename.len() => {
+                    Ok((rename[..idx].to_string(), rename[idx + 1..].to_string()))
+                }
+                _ => Err(format_err!(
+                    "Invalid branch mapping {:?}, expected BRANCH=BOOKMARK",
+                    rename
+                )),
+            })
+            .collect()
+    }
+
+    pub fn bookmark_for_branch(&self, branch: &str) -> Result<BookmarkName, Error> {
+        match self.renames.get(branch) {
+            Some(bookmark) => BookmarkName::new(bookmark),
+            None => BookmarkName::new(format!("{}{}", self.prefix, branch)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bookmark_for_branch() -> Result<(), Error> {
+        let renames = BookmarkMapping::parse_renames(vec!["main=master", "release/1.0=stable"])?;
+        let mapping = BookmarkMapping::new("imported/".to_string(), renames);
+        assert_eq!(mapping.bookmark_for_branch("main")?.as_str(), "master");
+        assert_eq!(
+            mapping.bookmark_for_branch("release/1.0")?.as_str(),
+            "stable"
+        );
+        assert_eq!(
+            mapping.bookmark_for_branch("feature")?.as_str(),
+            "imported/feature"
+        );
+
+        let mapping = BookmarkMapping::default();
+        assert_eq!(mapping.bookmark_for_branch("main")?.as_str(), "main");
+
+        assert!(BookmarkMapping::parse_renames(vec!["main"]).is_err());
+        assert!(BookmarkMapping::parse_renames(vec!["=master"]).is_err());
+        assert!(BookmarkMapping::parse_renames(vec!["main="]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Detection of git-lfs pointer files. Git only stores the pointers, so they are imported as
+//! regular files, and the contents they point to have to be uploaded separately, e.g. with
+//! lfs_import.
+
+use anyhow::Error;
+use blobrepo::BlobRepo;
+use context::CoreContext;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use mercurial_types::blobs::{File, LFSContent};
+use mononoke_types::{BonsaiChangeset, ChangesetId, ContentId, MPath};
+
+const LFS_POINTER_PREFIX: &[u8] = b"version https://git-lfs.github.com/spec/v1\n";
+
+/// git-lfs pointers are around 130 bytes, so there is no need to look at larger files
+const MAX_LFS_POINTER_SIZE: u64 = 1024;
+
+#[derive(Clone, Debug)]
+pub struct LfsPointer {
+    pub cs_id: ChangesetId,
+    pub path: MPath,
+    pub content: LFSContent,
+}
+
+/// Parse the contents of a file as a git-lfs pointer, if it is one
+pub fn parse_lfs_pointer(content: &[u8]) -> Option<LFSContent> {
+    if !content.starts_with(LFS_POINTER_PREFIX) {
+        return None;
+    }
+    File::get_lfs_struct(&File::parse_content_to_lfs_hash_map(content)).ok()
+}
+
+/// Files added or modified by `changesets` which are git-lfs pointers
+pub async fn find_lfs_pointers<'a>(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    changesets: impl IntoIterator<Item = &'a BonsaiChangeset>,
+    concurrency: usize,
+) -> Result<Vec<LfsPointer>, Error> {
+    let candidates: Vec<(ChangesetId, MPath, ContentId)> = changesets
+        .into_iter()
+        .flat_map(|bcs| {
+            let cs_id = bcs.get_changeset_id();
+            bcs.file_changes().filter_map(move |(path, change)| {
+                let change = change?;
+                if change.size() <= MAX_LFS_POINTER_SIZE {
+                    Some((cs_id, path.clone(), change.content_id()))
+                } else {
+                    None
+                }
+            })
+        })
+        .collect();
+
+    stream::iter(candidates)
+        .map(|(cs_id, path, content_id)| async move {
+            let content = filestore::fetch_concat(repo.blobstore(), ctx, content_id).await?;
+            let pointer = parse_lfs_pointer(&content).map(|content| LfsPointer {
+                cs_id,
+                path,
+                content,
+            });
+            Ok::<_, Error>(pointer)
+        })
+        .buffer_unordered(concurrency)
+        .try_filter_map(|pointer| async move { Ok(pointer) })
+        .try_collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lfs_pointer() {
+        let pointer = concat!(
+            "version https://git-lfs.github.com/spec/v1\n",
+            "oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\n",
+            "size 12345\n",
+        );
+        let content = parse_lfs_pointer(pointer.as_bytes()).expect("valid pointer");
+        assert_eq!(content.size(), 12345);
+        assert_eq!(
+            content.oid().to_string(),
+            "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393"
+        );
+
+        // Only the git-lfs spec is recognised
+        assert!(parse_lfs_pointer(b"version 1\nsize 12345\n").is_none());
+        // Files which merely start like a pointer
+        assert!(
+            parse_lfs_pointer(b"version https://git-lfs.github.com/spec/v1\nhello\n").is_none()
+        );
+        assert!(parse_lfs_pointer(b"regular file\n").is_none());
+    }
+}
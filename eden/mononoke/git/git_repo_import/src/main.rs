@@ -0,0 +1,278 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Imports the branches of a git repository into a Mononoke repo, and creates or moves a bookmark
+//! for each of them.
+//!
+//! Each imported commit is recorded in the bonsai<->git mapping, so an interrupted import can be
+//! resumed by running the tool again: only the commits missing from the mapping are imported.
+
+mod bookmark_mapping;
+mod lfs;
+
+use anyhow::{format_err, Error};
+use blobrepo::BlobRepo;
+use bookmarks::{BookmarkName, BookmarkUpdateReason};
+use clap::Arg;
+use cmdlib::{
+    args::{self, MononokeMatches, RepoRequirement},
+    helpers::block_execute,
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use git2::{BranchType, Oid, Repository};
+use import_tools::{oid_to_sha1, GitimportPreferences, ImportMissingForCommit};
+use mononoke_types::ChangesetId;
+use slog::{info, warn};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::bookmark_mapping::BookmarkMapping;
+use crate::lfs::{find_lfs_pointers, LfsPointer};
+
+const ARG_GIT_REPOSITORY_PATH: &str = "git-repository-path";
+const ARG_BRANCH: &str = "branch";
+const ARG_BOOKMARK_PREFIX: &str = "bookmark-prefix";
+const ARG_MAP_BRANCH: &str = "map-branch";
+const ARG_DERIVE_HG: &str = "derive-hg";
+const ARG_HGGIT_COMPATIBILITY: &str = "hggit-compatibility";
+const ARG_CONCURRENCY: &str = "concurrency";
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let app = args::MononokeAppBuilder::new("Mononoke Git Repo Importer")
+        .with_repo_required(RepoRequirement::ExactlyOne)
+        .build()
+        .about(concat!(
+            "Import the branches of a git repository, and create or move a bookmark for each ",
+            "of them. Interrupted imports are resumed by running the tool again.",
+        ))
+        .arg(
+            Arg::with_name(ARG_GIT_REPOSITORY_PATH)
+                .required(true)
+                .help("Path to a git repository to import"),
+        )
+        .arg(
+            Arg::with_name(ARG_BRANCH)
+                .long(ARG_BRANCH)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("git branch to import, all local branches are imported if not specified"),
+        )
+        .arg(
+            Arg::with_name(ARG_BOOKMARK_PREFIX)
+                .long(ARG_BOOKMARK_PREFIX)
+                .takes_value(true)
+                .default_value("")
+                .help("prefix of the bookmarks branches are imported as"),
+        )
+        .arg(
+            Arg::with_name(ARG_MAP_BRANCH)
+                .long(ARG_MAP_BRANCH)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help(concat!(
+                    "bookmark to import a branch as, as BRANCH=BOOKMARK, e.g. main=master. ",
+                    "The bookmark prefix doesn't apply to it",
+                )),
+        )
+        .arg(
+            Arg::with_name(ARG_DERIVE_HG)
+                .long(ARG_DERIVE_HG)
+                .help("derive hg changesets for the imported commits"),
+        )
+        .arg(
+            Arg::with_name(ARG_HGGIT_COMPATIBILITY)
+                .long(ARG_HGGIT_COMPATIBILITY)
+                .help("Set commit extras for hggit compatibility"),
+        )
+        .arg(
+            Arg::with_name(ARG_CONCURRENCY)
+                .long(ARG_CONCURRENCY)
+                .takes_value(true)
+                .default_value("100")
+                .help("number of files checked at once for git-lfs pointers"),
+        );
+    let matches = app.get_matches();
+
+    args::init_cachelib(fb, &matches);
+    let logger = args::init_logging(fb, &matches)?;
+    args::init_config_store(fb, &logger, &matches)?;
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+
+    block_execute(
+        run(ctx, &matches),
+        fb,
+        "git_repo_import",
+        &logger,
+        &matches,
+        cmdlib::monitoring::AliveService,
+    )
+}
+
+async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(), Error> {
+    let path = Path::new(
+        matches
+            .value_of(ARG_GIT_REPOSITORY_PATH)
+            .ok_or_else(|| format_err!("{} is not set", ARG_GIT_REPOSITORY_PATH))?,
+    );
+    let only_branches: Option<HashSet<&str>> = matches
+        .values_of(ARG_BRANCH)
+        .map(|branches| branches.collect());
+    let mapping = BookmarkMapping::new(
+        matches
+            .value_of(ARG_BOOKMARK_PREFIX)
+            .unwrap_or_default()
+            .to_string(),
+        BookmarkMapping::parse_renames(matches.values_of(ARG_MAP_BRANCH).into_iter().flatten())?,
+    );
+    let concurrency = args::get_usize(matches, ARG_CONCURRENCY, 100);
+
+    let mut prefs = GitimportPreferences::default();
+    // The mapping is what makes the import resumable, and is needed to find the imported heads
+    prefs.enable_bonsai_git_mapping();
+    if matches.is_present(ARG_DERIVE_HG) {
+        prefs.enable_derive_hg();
+    }
+    if matches.is_present(ARG_HGGIT_COMPATIBILITY) {
+        prefs.enable_hggit_compatibility();
+    }
+
+    let repo = args::open_repo(ctx.fb, ctx.logger(), matches).await?;
+    let git_repo = Repository::open(path)?;
+    let branches = list_branches(&git_repo, only_branches.as_ref())?;
+    if branches.is_empty() {
+        return Err(format_err!("No branches to import in {}", path.display()));
+    }
+
+    let mut lfs_pointers = vec![];
+    for (branch, oid) in branches {
+        let bookmark = mapping.bookmark_for_branch(&branch)?;
+        let target = ImportMissingForCommit::new(oid, &ctx, &repo, &git_repo).await?;
+        let imported = import_tools::gitimport(&ctx, &repo, path, &target, prefs.clone()).await?;
+        info!(
+            ctx.logger(),
+            "imported {} new commits for branch {}",
+            imported.len(),
+            branch
+        );
+        lfs_pointers.extend(
+            find_lfs_pointers(
+                &ctx,
+                &repo,
+                imported.values().map(|(_, bcs)| bcs),
+                concurrency,
+            )
+            .await?,
+        );
+
+        let cs_id = repo
+            .bonsai_git_mapping()
+            .get_bonsai_from_git_sha1(&ctx, oid_to_sha1(&oid)?)
+            .await?
+            .ok_or_else(|| format_err!("{} of branch {} was not imported", oid, branch))?;
+        move_bookmark(&ctx, &repo, &bookmark, cs_id).await?;
+    }
+
+    report_lfs_pointers(&ctx, &lfs_pointers);
+    Ok(())
+}
+
+/// Local branches of the git repository with the commits they point to, sorted by name
+fn list_branches(
+    git_repo: &Repository,
+    only_branches: Option<&HashSet<&str>>,
+) -> Result<Vec<(String, Oid)>, Error> {
+    let mut branches = vec![];
+    for branch in git_repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        let name = branch
+            .name()?
+            .ok_or_else(|| format_err!("Branch name is not valid utf-8"))?
+            .to_string();
+        if only_branches.map_or(true, |only| only.contains(name.as_str())) {
+            let oid = branch.get().peel_to_commit()?.id();
+            branches.push((name, oid));
+        }
+    }
+    if let Some(only_branches) = only_branches {
+        for name in only_branches {
+            if !branches.iter().any(|(branch, _)| branch == name) {
+                return Err(format_err!("Branch {} does not exist", name));
+            }
+        }
+    }
+    branches.sort();
+    Ok(branches)
+}
+
+/// Create `bookmark` at `cs_id`, or move it there if it exists
+async fn move_bookmark(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    bookmark: &BookmarkName,
+    cs_id: ChangesetId,
+) -> Result<(), Error> {
+    let old_cs_id = repo.get_bonsai_bookmark(ctx.clone(), bookmark).await?;
+    if old_cs_id == Some(cs_id) {
+        info!(ctx.logger(), "{} is already at {}", bookmark, cs_id);
+        return Ok(());
+    }
+
+    let mut txn = repo.update_bookmark_transaction(ctx.clone());
+    match old_cs_id {
+        Some(old_cs_id) => {
+            info!(
+                ctx.logger(),
+                "moving {} from {} to {}", bookmark, old_cs_id, cs_id
+            );
+            txn.update(
+                bookmark,
+                cs_id,
+                old_cs_id,
+                BookmarkUpdateReason::ManualMove,
+                None,
+            )?;
+        }
+        None => {
+            info!(ctx.logger(), "creating {} at {}", bookmark, cs_id);
+            txn.create(bookmark, cs_id, BookmarkUpdateReason::ManualMove, None)?;
+        }
+    }
+    if !txn.commit().await? {
+        return Err(format_err!(
+            "{} was moved while it was being updated",
+            bookmark
+        ));
+    }
+    Ok(())
+}
+
+fn report_lfs_pointers(ctx: &CoreContext, lfs_pointers: &[LfsPointer]) {
+    if lfs_pointers.is_empty() {
+        return;
+    }
+    for pointer in lfs_pointers {
+        info!(
+            ctx.logger(),
+            "{} in {} is a git-lfs pointer to {} ({} bytes)",
+            pointer.path,
+            pointer.cs_id,
+            pointer.content.oid(),
+            pointer.content.size()
+        );
+    }
+    warn!(
+        ctx.logger(),
+        "{} imported files are git-lfs pointers, their contents have to be imported separately, e.g. with lfs_import",
+        lfs_pointers.len()
+    );
+}
@@ -8,7 +8,7 @@
 use anyhow::{format_err, Context as _};
 use async_trait::async_trait;
 use blobrepo::BlobRepo;
-use blobrepo_hg::BlobRepoHg;
+use blobrepo_hg::{find_case_conflicts, BlobRepoHg};
 use blobstore::Loadable;
 use bookmarks::BookmarkName;
 use bytes::Bytes;
@@ -182,6 +182,24 @@ impl FileContentManager for BlobRepoFileContentManager {
             .map_err(ErrorKind::from)
             .await
     }
+
+    async fn case_conflicts<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        cs_id: ChangesetId,
+        parent_cs_id: Option<ChangesetId>,
+    ) -> Result<Vec<(MPath, MPath)>, ErrorKind> {
+        let mf = derive_hg_manifest(ctx, &self.repo, cs_id).await?;
+        let parent_mf = match parent_cs_id {
+            Some(parent_cs_id) => Some(derive_hg_manifest(ctx, &self.repo, parent_cs_id).await?),
+            None => None,
+        };
+
+        find_case_conflicts(ctx, &self.repo, mf, parent_mf)
+            .await
+            .with_context(|| format!("Error finding case conflicts for bonsai: {}", cs_id))
+            .map_err(ErrorKind::from)
+    }
 }
 
 impl BlobRepoFileContentManager {
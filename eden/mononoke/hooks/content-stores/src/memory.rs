@@ -111,6 +111,18 @@ impl FileContentManager for InMemoryFileContentManager {
                 .into(),
         )
     }
+
+    async fn case_conflicts<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        _cs_id: ChangesetId,
+        _parent_cs_id: Option<ChangesetId>,
+    ) -> Result<Vec<(MPath, MPath)>, ErrorKind> {
+        Err(
+            format_err!("`case_conflicts` is not implemented for `InMemoryFileContentManager`")
+                .into(),
+        )
+    }
 }
 
 impl InMemoryFileContentManager {
@@ -49,6 +49,16 @@ pub trait FileContentManager: Send + Sync {
         bookmark: BookmarkName,
         paths: Vec<MPath>,
     ) -> Result<HashMap<MPath, ChangesetInfo>, ErrorKind>;
+
+    /// Case conflicts `cs_id` introduces relative to `parent_cs_id`, from the diff of their
+    /// manifests. In each pair, the second path was added by `cs_id`, and the first is the path
+    /// it conflicts with.
+    async fn case_conflicts<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        cs_id: ChangesetId,
+        parent_cs_id: Option<ChangesetId>,
+    ) -> Result<Vec<(MPath, MPath)>, ErrorKind>;
 }
 
 #[derive(Clone, Debug)]
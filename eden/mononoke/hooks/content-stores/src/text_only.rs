@@ -92,6 +92,15 @@ impl<T: FileContentManager + 'static> FileContentManager for TextOnlyFileContent
     ) -> Result<HashMap<MPath, ChangesetInfo>, ErrorKind> {
         self.inner.latest_changes(ctx, bookmark, paths).await
     }
+
+    async fn case_conflicts<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        cs_id: ChangesetId,
+        parent_cs_id: Option<ChangesetId>,
+    ) -> Result<Vec<(MPath, MPath)>, ErrorKind> {
+        self.inner.case_conflicts(ctx, cs_id, parent_cs_id).await
+    }
 }
 
 fn looks_like_binary(file_bytes: &[u8]) -> bool {
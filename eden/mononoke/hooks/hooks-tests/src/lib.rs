@@ -191,6 +191,37 @@ impl ChangesetHook for LatestChangesChangesetHook {
     }
 }
 
+#[derive(Clone)]
+struct CaseConflictsChangesetHook(Vec<(MPath, MPath)>);
+
+#[async_trait]
+impl ChangesetHook for CaseConflictsChangesetHook {
+    async fn run<'this: 'cs, 'ctx: 'this, 'cs, 'fetcher: 'cs>(
+        &'this self,
+        ctx: &'ctx CoreContext,
+        _bookmark: &BookmarkName,
+        changeset: &'cs BonsaiChangeset,
+        content_manager: &'fetcher dyn FileContentManager,
+        _cross_repo_push_source: CrossRepoPushSource,
+    ) -> Result<HookExecution, Error> {
+        let conflicts = content_manager
+            .case_conflicts(
+                ctx,
+                changeset.get_changeset_id(),
+                changeset.parents().next(),
+            )
+            .map_err(Error::from)
+            .await?;
+
+        if conflicts != self.0 {
+            return Ok(HookExecution::Rejected(HookRejectionInfo::new(
+                "found case conflicts don't match the expected ones",
+            )));
+        }
+        Ok(HookExecution::Accepted)
+    }
+}
+
 #[async_trait]
 impl ChangesetHook for FileContentMatchingChangesetHook {
     async fn run<'this: 'cs, 'ctx: 'this, 'cs, 'fetcher: 'cs>(
@@ -544,6 +575,48 @@ fn test_changeset_hook_mix(fb: FacebookInit) {
     });
 }
 
+#[fbinit::test]
+async fn test_evaluate_hooks_for_bookmark(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let bookmarks = hashmap! {
+        "bm1".to_string() => vec!["hook1".to_string(), "hook2".to_string()]
+    };
+    let mut hook_manager =
+        setup_hook_manager(ctx.fb, bookmarks, hashmap! {}, ContentFetcherType::InMemory).await;
+    hook_manager.register_changeset_hook(
+        "hook1",
+        always_accepting_changeset_hook(),
+        Default::default(),
+    );
+    hook_manager.register_changeset_hook(
+        "hook2",
+        always_rejecting_changeset_hook(),
+        Default::default(),
+    );
+
+    let outcomes = hook_manager
+        .evaluate_hooks_for_bookmark(
+            &ctx,
+            vec![default_changeset()].iter(),
+            &BookmarkName::new("bm1")?,
+            None,
+            CrossRepoPushSource::NativeToThisRepo,
+        )
+        .await?;
+    let map: HashMap<String, HookExecution> = outcomes
+        .into_iter()
+        .map(|outcome| (outcome.get_hook_name().to_string(), outcome.into()))
+        .collect();
+    assert_eq!(
+        map,
+        hashmap! {
+            "hook1".to_string() => HookExecution::Accepted,
+            "hook2".to_string() => default_rejection(),
+        }
+    );
+    Ok(())
+}
+
 #[fbinit::test]
 fn test_changeset_hook_file_text(fb: FacebookInit) {
     async_unit::tokio_unit_test(async move {
@@ -1126,6 +1199,59 @@ async fn test_cs_latest_changes_hook_with_blob_store(fb: FacebookInit) -> Result
     Ok(())
 }
 
+#[fbinit::test]
+async fn test_cs_case_conflicts_hook_with_blob_store(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    // set up a blobrepo
+    let repo = new_memblob_empty(None)?;
+    let root_id = CreateCommitContext::new_root(&ctx, &repo)
+        .add_file("dir/file", "dir/file")
+        .add_file("deleted", "deleted")
+        .commit()
+        .await?;
+    bookmark(&ctx, &repo, "master").set_to(root_id).await?;
+
+    let bcs_id = CreateCommitContext::new(&ctx, &repo, vec![root_id])
+        .delete_file("deleted")
+        .add_file("DIR/other", "DIR/other")
+        .add_file("Deleted", "Deleted")
+        .add_file("dir/new", "dir/new")
+        .commit()
+        .await?;
+    let changeset = bcs_id.load(&ctx, &repo.get_blobstore()).await?;
+
+    let hook_name = "hook".to_string();
+    // "Deleted" doesn't conflict, as "deleted" is removed by the same commit
+    let hook = Box::new(CaseConflictsChangesetHook(vec![(
+        MPath::new("dir")?,
+        MPath::new("DIR/other")?,
+    )]));
+
+    let hooks: HashMap<String, Box<dyn ChangesetHook>> = hashmap! {
+        hook_name.clone() => hook as Box<dyn ChangesetHook>,
+    };
+    let bookmarks = hashmap! {
+        "bm1".to_string() => vec![hook_name.clone()]
+    };
+    let regexes = hashmap! {};
+    let expected = hashmap! {
+        hook_name => HookExecution::Accepted,
+    };
+    run_changeset_hooks_with_mgr(
+        ctx.clone(),
+        Some(changeset),
+        "bm1",
+        hooks,
+        bookmarks,
+        regexes,
+        expected,
+        ContentFetcherType::Blob(repo),
+    )
+    .await;
+
+    Ok(())
+}
+
 #[fbinit::test]
 fn test_cs_hooks_with_blob_store(fb: FacebookInit) {
     async_unit::tokio_unit_test(async move {
@@ -200,6 +200,48 @@ impl HookManager {
         bookmark: &BookmarkName,
         maybe_pushvars: Option<&HashMap<String, Bytes>>,
         cross_repo_push_source: CrossRepoPushSource,
+    ) -> Result<Vec<HookOutcome>, Error> {
+        self.run_hooks_with_scuba(
+            ctx,
+            changesets,
+            bookmark,
+            maybe_pushvars,
+            cross_repo_push_source,
+            self.scuba.clone(),
+        )
+        .await
+    }
+
+    /// Evaluate the hooks for `bookmark` against changesets, as `run_hooks_for_bookmark` would,
+    /// but without logging the outcomes. This allows hook configuration changes to be tested
+    /// against past pushes before they are enabled.
+    pub async fn evaluate_hooks_for_bookmark(
+        &self,
+        ctx: &CoreContext,
+        changesets: impl Iterator<Item = &BonsaiChangeset> + Clone + itertools::Itertools,
+        bookmark: &BookmarkName,
+        maybe_pushvars: Option<&HashMap<String, Bytes>>,
+        cross_repo_push_source: CrossRepoPushSource,
+    ) -> Result<Vec<HookOutcome>, Error> {
+        self.run_hooks_with_scuba(
+            ctx,
+            changesets,
+            bookmark,
+            maybe_pushvars,
+            cross_repo_push_source,
+            MononokeScubaSampleBuilder::with_discard(),
+        )
+        .await
+    }
+
+    async fn run_hooks_with_scuba(
+        &self,
+        ctx: &CoreContext,
+        changesets: impl Iterator<Item = &BonsaiChangeset> + Clone + itertools::Itertools,
+        bookmark: &BookmarkName,
+        maybe_pushvars: Option<&HashMap<String, Bytes>>,
+        cross_repo_push_source: CrossRepoPushSource,
+        mut scuba: MononokeScubaSampleBuilder,
     ) -> Result<Vec<HookOutcome>, Error> {
         debug!(ctx.logger(), "Running hooks for bookmark {:?}", bookmark);
 
@@ -207,7 +249,6 @@ impl HookManager {
 
         let futs = FuturesUnordered::new();
 
-        let mut scuba = self.scuba.clone();
         let username = ctx.metadata().unix_name();
         let user_option = ctx.metadata().client_hostname().or(username);
 
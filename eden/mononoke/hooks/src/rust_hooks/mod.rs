@@ -18,6 +18,7 @@ pub(crate) mod limit_filesize;
 mod limit_path_length;
 mod lua_pattern;
 pub(crate) mod no_bad_filenames;
+mod no_case_conflicts;
 mod no_insecure_filenames;
 pub(crate) mod no_questionable_filenames;
 pub(crate) mod no_windows_filenames;
@@ -54,6 +55,7 @@ pub fn hook_name_to_changeset_hook<'a>(
             "limit_commitsize" => Some(b(limit_commitsize::LimitCommitsize::builder()
                 .set_from_config(config)
                 .build()?)),
+            "no_case_conflicts" => Some(b(no_case_conflicts::NoCaseConflicts::new())),
             _ => None,
         })
     }
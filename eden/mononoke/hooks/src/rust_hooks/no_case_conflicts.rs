@@ -0,0 +1,81 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::{
+    ChangesetHook, CrossRepoPushSource, FileContentManager, HookExecution, HookRejectionInfo,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bookmarks::BookmarkName;
+use context::CoreContext;
+use mononoke_types::BonsaiChangeset;
+
+// The rejection lists at most this many conflicts, as a bad import can add thousands
+const MAX_REPORTED_CONFLICTS: usize = 20;
+
+/// Rejects commits adding paths which differ only by case from other paths of the commit or of
+/// its parent, as only one of them can be checked out on case-insensitive filesystems.
+#[derive(Clone, Debug)]
+pub struct NoCaseConflicts;
+
+impl NoCaseConflicts {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl ChangesetHook for NoCaseConflicts {
+    async fn run<'this: 'cs, 'ctx: 'this, 'cs, 'fetcher: 'cs>(
+        &'this self,
+        ctx: &'ctx CoreContext,
+        _bookmark: &BookmarkName,
+        changeset: &'cs BonsaiChangeset,
+        content_manager: &'fetcher dyn FileContentManager,
+        cross_repo_push_source: CrossRepoPushSource,
+    ) -> Result<HookExecution> {
+        if cross_repo_push_source == CrossRepoPushSource::PushRedirected {
+            // For push-redirected commits, we rely on running source-repo hooks
+            return Ok(HookExecution::Accepted);
+        }
+
+        // Only added paths can conflict, which saves looking at the manifests of deletions
+        if changeset.file_changes().all(|(_, change)| change.is_none()) {
+            return Ok(HookExecution::Accepted);
+        }
+
+        let conflicts = content_manager
+            .case_conflicts(
+                ctx,
+                changeset.get_changeset_id(),
+                changeset.parents().next(),
+            )
+            .await?;
+        if conflicts.is_empty() {
+            return Ok(HookExecution::Accepted);
+        }
+
+        let mut long_description = format!(
+            "{} paths differ only by case from existing paths:",
+            conflicts.len()
+        );
+        for (existing, added) in conflicts.iter().take(MAX_REPORTED_CONFLICTS) {
+            long_description.push_str(&format!("\n  {} conflicts with {}", added, existing));
+        }
+        if conflicts.len() > MAX_REPORTED_CONFLICTS {
+            long_description.push_str(&format!(
+                "\n  and {} more",
+                conflicts.len() - MAX_REPORTED_CONFLICTS
+            ));
+        }
+        Ok(HookExecution::Rejected(HookRejectionInfo::new_long(
+            "Case conflicts are not allowed",
+            long_description,
+        )))
+    }
+}
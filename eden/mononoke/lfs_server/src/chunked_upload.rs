@@ -0,0 +1,402 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Uploads in chunks, for objects too large to reliably upload in a single request. Clients PUT
+//! each chunk to `/:repository/upload/:oid/:size/chunks/:offset`, with the Sha256 of the chunk in
+//! the `X-Chunk-Sha256` header, then POST to `/:repository/upload/:oid/:size/finish` once all
+//! the chunks are uploaded. An interrupted upload resumes from the offset returned by a GET of
+//! `/:repository/upload/:oid/:size/chunks`.
+//!
+//! Chunks are stored in the Filestore as contents of their own, and the chunk at each offset is
+//! recorded in the blobstore, so that the upload can be resumed on any server.
+
+use std::str::FromStr;
+
+use anyhow::{Context, Error};
+use blobstore::Blobstore;
+use bytes::Bytes;
+use futures::{
+    stream::{self, StreamExt, TryStreamExt},
+    Stream,
+};
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use http::header::CONTENT_LENGTH;
+use hyper::Body;
+use serde::{Deserialize, Serialize};
+
+use filestore::{self, FetchKey, StoreRequest};
+use gotham_ext::{
+    error::HttpError,
+    middleware::{HttpScubaKey, ScubaMiddlewareState},
+    response::{BytesBody, EmptyBody, TryIntoResponse},
+};
+use mononoke_types::{hash::Sha256, BlobstoreBytes, ContentId};
+
+use crate::errors::ErrorKind;
+use crate::lfs_server_context::RepositoryRequestContext;
+use crate::middleware::LfsMethod;
+use crate::upload::sync_internal_and_upstream;
+use crate::util::read_header_value;
+
+const CHUNK_SHA256_HEADER: &str = "X-Chunk-Sha256";
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct ChunkedUploadParams {
+    repository: String,
+    oid: String,
+    size: String,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct UploadChunkParams {
+    repository: String,
+    oid: String,
+    size: String,
+    offset: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChunkRecord {
+    content_id: String,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct ChunkedUploadStatus {
+    /// Number of bytes received from the start of the object, i.e. where to resume from
+    received: u64,
+}
+
+fn chunk_record_key(oid: &Sha256, size: u64, offset: u64) -> String {
+    format!("lfs_upload_chunk.sha256.{}.{}.{}", oid, size, offset)
+}
+
+fn parse_object(
+    ctx: &RepositoryRequestContext,
+    oid: &str,
+    size: &str,
+) -> Result<(Sha256, u64), HttpError> {
+    let oid = Sha256::from_str(oid).map_err(HttpError::e400)?;
+    let size = size.parse().map_err(Error::from).map_err(HttpError::e400)?;
+
+    if let Some(max_upload_size) = ctx.max_upload_size() {
+        if size > max_upload_size {
+            Err(HttpError::e400(ErrorKind::UploadTooLarge(
+                size,
+                max_upload_size,
+            )))?;
+        }
+    }
+
+    Ok((oid, size))
+}
+
+async fn load_chunk(
+    ctx: &RepositoryRequestContext,
+    oid: &Sha256,
+    size: u64,
+    offset: u64,
+) -> Result<Option<(ContentId, u64)>, Error> {
+    let key = chunk_record_key(oid, size, offset);
+    let data = match ctx.repo.blobstore().get(&ctx.ctx, &key).await? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    let record: ChunkRecord = serde_json::from_slice(data.as_raw_bytes())
+        .with_context(|| format!("Invalid chunk record {}", key))?;
+    let content_id = ContentId::from_str(&record.content_id)?;
+    Ok(Some((content_id, record.size)))
+}
+
+/// The contiguous chunks received from the start of the object
+async fn received_chunks(
+    ctx: &RepositoryRequestContext,
+    oid: &Sha256,
+    size: u64,
+) -> Result<Vec<(ContentId, u64)>, Error> {
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    while offset < size {
+        match load_chunk(ctx, oid, size, offset).await? {
+            Some((content_id, chunk_size)) => {
+                chunks.push((content_id, chunk_size));
+                offset += chunk_size;
+            }
+            None => break,
+        }
+    }
+    Ok(chunks)
+}
+
+async fn store_chunk<S>(
+    ctx: &RepositoryRequestContext,
+    oid: &Sha256,
+    size: u64,
+    offset: u64,
+    chunk_sha256: Sha256,
+    chunk_size: u64,
+    data: S,
+) -> Result<(), HttpError>
+where
+    S: Stream<Item = Result<Bytes, Error>> + Send,
+{
+    if chunk_size == 0 || offset + chunk_size > size {
+        Err(HttpError::e400(ErrorKind::ChunkOutOfBounds(
+            offset, chunk_size, size,
+        )))?;
+    }
+
+    let metadata = filestore::store(
+        ctx.repo.blobstore(),
+        ctx.repo.filestore_config(),
+        &ctx.ctx,
+        &StoreRequest::new(chunk_size),
+        data,
+    )
+    .await
+    .context(ErrorKind::FilestoreWriteFailure)
+    .map_err(HttpError::e500)?;
+
+    // The chunk is stored before it is verified, but it is only recorded if it matches. A chunk
+    // which does not is unreachable.
+    if metadata.sha256 != chunk_sha256 {
+        Err(HttpError::e400(ErrorKind::ChunkVerificationFailed(
+            offset,
+            chunk_sha256,
+            metadata.sha256,
+        )))?;
+    }
+
+    let record = ChunkRecord {
+        content_id: metadata.content_id.to_string(),
+        size: chunk_size,
+    };
+    let record = serde_json::to_vec(&record).map_err(HttpError::e500)?;
+    ctx.repo
+        .blobstore()
+        .put(
+            &ctx.ctx,
+            chunk_record_key(oid, size, offset),
+            BlobstoreBytes::from_bytes(record),
+        )
+        .await
+        .context(ErrorKind::FilestoreWriteFailure)
+        .map_err(HttpError::e500)?;
+
+    Ok(())
+}
+
+async fn finish_upload(
+    ctx: &RepositoryRequestContext,
+    oid: Sha256,
+    size: u64,
+    scuba: &mut Option<&mut ScubaMiddlewareState>,
+) -> Result<(), HttpError> {
+    let chunks = received_chunks(ctx, &oid, size)
+        .await
+        .context(ErrorKind::FilestoreReadFailure)
+        .map_err(HttpError::e500)?;
+    let received: u64 = chunks.iter().map(|(_, chunk_size)| chunk_size).sum();
+    if received != size {
+        Err(HttpError::e400(ErrorKind::UploadIncomplete(received, size)))?;
+    }
+
+    let data = stream::iter(chunks)
+        .then(move |(content_id, _)| async move {
+            let key = FetchKey::Canonical(content_id);
+            filestore::fetch(ctx.repo.get_blobstore(), ctx.ctx.clone(), &key)
+                .await?
+                .ok_or_else(|| Error::from(ErrorKind::ObjectDoesNotExist(key)))
+        })
+        .try_flatten();
+
+    // The Filestore verifies the whole object against its Sha256 as it stores it
+    filestore::store(
+        ctx.repo.blobstore(),
+        ctx.repo.filestore_config(),
+        &ctx.ctx,
+        &StoreRequest::with_sha256(size, oid),
+        data,
+    )
+    .await
+    .context(ErrorKind::FilestoreWriteFailure)
+    .map_err(HttpError::e500)?;
+
+    // Now that we have the object, this sends it to upstream, like any upload would
+    sync_internal_and_upstream(ctx, oid, size, scuba)
+        .await
+        .map_err(HttpError::e500)?;
+
+    Ok(())
+}
+
+pub async fn upload_chunk(state: &mut State) -> Result<impl TryIntoResponse, HttpError> {
+    let UploadChunkParams {
+        repository,
+        oid,
+        size,
+        offset,
+    } = state.take();
+
+    let ctx =
+        RepositoryRequestContext::instantiate(state, repository.clone(), LfsMethod::UploadChunk)
+            .await?;
+
+    let (oid, size) = parse_object(&ctx, &oid, &size)?;
+    let offset = offset
+        .parse()
+        .map_err(Error::from)
+        .map_err(HttpError::e400)?;
+    let chunk_size: u64 = read_header_value(state, CONTENT_LENGTH)
+        .ok_or_else(|| ErrorKind::ChunkMissingHeader("Content-Length"))
+        .map_err(HttpError::e400)?
+        .map_err(HttpError::e400)?;
+    let chunk_sha256: String = read_header_value(state, CHUNK_SHA256_HEADER)
+        .ok_or_else(|| ErrorKind::ChunkMissingHeader(CHUNK_SHA256_HEADER))
+        .map_err(HttpError::e400)?
+        .map_err(HttpError::e400)?;
+    let chunk_sha256 = Sha256::from_str(&chunk_sha256).map_err(HttpError::e400)?;
+
+    ScubaMiddlewareState::try_borrow_add(state, HttpScubaKey::RequestContentLength, chunk_size);
+
+    let mut received: u64 = 0;
+    let body = Body::take_from(state)
+        .map_err(|_| Error::from(ErrorKind::ClientCancelled))
+        .inspect_ok(|chunk| received += chunk.len() as u64);
+
+    let res = store_chunk(&ctx, &oid, size, offset, chunk_sha256, chunk_size, body).await;
+
+    ScubaMiddlewareState::try_borrow_add(state, HttpScubaKey::RequestBytesReceived, received);
+
+    res.map(|()| EmptyBody::new())
+}
+
+pub async fn upload_status(state: &mut State) -> Result<impl TryIntoResponse, HttpError> {
+    let ChunkedUploadParams {
+        repository,
+        oid,
+        size,
+    } = state.take();
+
+    let ctx =
+        RepositoryRequestContext::instantiate(state, repository.clone(), LfsMethod::UploadStatus)
+            .await?;
+
+    let (oid, size) = parse_object(&ctx, &oid, &size)?;
+
+    let chunks = received_chunks(&ctx, &oid, size)
+        .await
+        .context(ErrorKind::FilestoreReadFailure)
+        .map_err(HttpError::e500)?;
+    let status = ChunkedUploadStatus {
+        received: chunks.iter().map(|(_, chunk_size)| chunk_size).sum(),
+    };
+    let body = serde_json::to_string(&status).map_err(HttpError::e500)?;
+
+    Ok(BytesBody::new(body, mime::APPLICATION_JSON))
+}
+
+pub async fn upload_finish(state: &mut State) -> Result<impl TryIntoResponse, HttpError> {
+    let ChunkedUploadParams {
+        repository,
+        oid,
+        size,
+    } = state.take();
+
+    let ctx =
+        RepositoryRequestContext::instantiate(state, repository.clone(), LfsMethod::UploadFinish)
+            .await?;
+
+    let (oid, size) = parse_object(&ctx, &oid, &size)?;
+
+    let mut scuba = state.try_borrow_mut::<ScubaMiddlewareState>();
+    finish_upload(&ctx, oid, size, &mut scuba).await?;
+
+    Ok(EmptyBody::new())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fbinit::FacebookInit;
+    use futures::future;
+
+    const FOO_SHA256: &str = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+    const BAR_SHA256: &str = "fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9";
+    const FOOBAR_SHA256: &str = "c3ab8ff13720e8ad9047dd39466b3c8974e592c2fa383d4a3960714caef0c4f2";
+    const FOOBAR_SIZE: u64 = 6;
+
+    async fn store(
+        ctx: &RepositoryRequestContext,
+        offset: u64,
+        chunk_sha256: &str,
+        content: &'static str,
+    ) -> Result<(), Error> {
+        let data = stream::once(future::ready(Ok(Bytes::from(content))));
+        store_chunk(
+            ctx,
+            &Sha256::from_str(FOOBAR_SHA256)?,
+            FOOBAR_SIZE,
+            offset,
+            Sha256::from_str(chunk_sha256)?,
+            content.len() as u64,
+            data,
+        )
+        .await
+        .map_err(|e| e.error)
+    }
+
+    async fn received(ctx: &RepositoryRequestContext) -> Result<u64, Error> {
+        let oid = Sha256::from_str(FOOBAR_SHA256)?;
+        let chunks = received_chunks(ctx, &oid, FOOBAR_SIZE).await?;
+        Ok(chunks.iter().map(|(_, chunk_size)| chunk_size).sum())
+    }
+
+    #[fbinit::test]
+    async fn test_chunked_upload(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = RepositoryRequestContext::test_builder(fb)?
+            .upstream_uri(None)
+            .build()?;
+        let oid = Sha256::from_str(FOOBAR_SHA256)?;
+
+        // Nothing can be resumed until the first chunk is there
+        store(&ctx, 3, BAR_SHA256, "bar").await?;
+        assert_eq!(received(&ctx).await?, 0);
+        assert!(finish_upload(&ctx, oid, FOOBAR_SIZE, &mut None)
+            .await
+            .is_err());
+
+        store(&ctx, 0, FOO_SHA256, "foo").await?;
+        assert_eq!(received(&ctx).await?, FOOBAR_SIZE);
+
+        finish_upload(&ctx, oid, FOOBAR_SIZE, &mut None)
+            .await
+            .map_err(|e| e.error)?;
+        let key = FetchKey::Aliased(filestore::Alias::Sha256(oid));
+        let content = filestore::fetch_concat(ctx.repo.blobstore(), &ctx.ctx, key).await?;
+        assert_eq!(content, Bytes::from("foobar"));
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_chunk_verification(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = RepositoryRequestContext::test_builder(fb)?
+            .upstream_uri(None)
+            .build()?;
+
+        // The chunk does not match its Sha256, so it is not recorded
+        assert!(store(&ctx, 0, BAR_SHA256, "foo").await.is_err());
+        assert_eq!(received(&ctx).await?, 0);
+
+        // Chunks must fit in the object
+        assert!(store(&ctx, 4, BAR_SHA256, "bar").await.is_err());
+
+        Ok(())
+    }
+}
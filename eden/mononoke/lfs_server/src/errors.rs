@@ -13,6 +13,7 @@ use gotham_ext::error::HttpError;
 use lfs_protocol::{RequestObject, ResponseObject};
 
 use filestore::FetchKey;
+use mononoke_types::hash::Sha256;
 
 #[derive(Debug, Error)]
 pub enum ErrorKind {
@@ -72,6 +73,14 @@ pub enum ErrorKind {
     ObjectNotInternallyAvailableAndUpstreamUnavailable(RequestObject),
     #[error("Object could not be synced from upstream")]
     ObjectCannotBeSynced(RequestObject),
+    #[error("Chunk upload is missing its {0} header")]
+    ChunkMissingHeader(&'static str),
+    #[error("Chunk at offset {0} of size {1} does not fit in an object of size {2}")]
+    ChunkOutOfBounds(u64, u64, u64),
+    #[error("Chunk at offset {0} does not match its Sha256: {1} was expected, {2} was received")]
+    ChunkVerificationFailed(u64, Sha256, Sha256),
+    #[error("Upload is incomplete: {0} of {1} bytes were received")]
+    UploadIncomplete(u64, u64),
 }
 
 #[derive(Debug, Error)]
@@ -54,6 +54,7 @@ use crate::scuba::LfsScubaHandler;
 use crate::service::build_router;
 
 mod batch;
+mod chunked_upload;
 mod config;
 mod download;
 mod errors;
@@ -226,7 +227,6 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
 
     let matches = app.get_matches();
 
-
     let (caching, logger, mut runtime) = matches.init_mononoke(fb)?;
 
     let config_store = args::init_config_store(fb, &logger, &matches)?;
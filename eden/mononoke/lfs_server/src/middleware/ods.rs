@@ -28,6 +28,9 @@ define_stats! {
     repo_failure_4xx: dynamic_timeseries("{}.failure_4xx", (repo_and_method: String); Rate, Sum),
     repo_failure_5xx: dynamic_timeseries("{}.failure_5xx", (repo_and_method: String); Rate, Sum),
     upload_duration: dynamic_histogram("{}.upload_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    upload_chunk_duration: dynamic_histogram("{}.upload_chunk_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    upload_status_duration: dynamic_histogram("{}.upload_status_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    upload_finish_duration: dynamic_histogram("{}.upload_finish_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     download_duration: dynamic_histogram("{}.download_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     download_sha256_duration: dynamic_histogram("{}.download_sha256_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     batch_duration: dynamic_histogram("{}.batch_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
@@ -64,6 +67,12 @@ fn log_stats(state: &mut State, status: StatusCode) -> Option<()> {
                 LfsMethod::Upload => {
                     STATS::upload_duration.add_value(duration.as_millis_unchecked() as i64, (repo,))
                 }
+                LfsMethod::UploadChunk => STATS::upload_chunk_duration
+                    .add_value(duration.as_millis_unchecked() as i64, (repo,)),
+                LfsMethod::UploadStatus => STATS::upload_status_duration
+                    .add_value(duration.as_millis_unchecked() as i64, (repo,)),
+                LfsMethod::UploadFinish => STATS::upload_finish_duration
+                    .add_value(duration.as_millis_unchecked() as i64, (repo,)),
                 LfsMethod::Download => STATS::download_duration
                     .add_value(duration.as_millis_unchecked() as i64, (repo,)),
                 LfsMethod::DownloadSha256 => STATS::download_sha256_duration
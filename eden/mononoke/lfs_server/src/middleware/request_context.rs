@@ -7,18 +7,21 @@
 
 use std::fmt;
 
-use context::{CoreContext, SessionContainer};
+use context::{CoreContext, RequestId, SessionContainer};
 use fbinit::FacebookInit;
 use gotham::state::{request_id, FromState, State};
 use gotham_derive::StateData;
 use gotham_ext::middleware::{ClientIdentity, Middleware};
 use hyper::{body::Body, Response};
 use scuba_ext::MononokeScubaSampleBuilder;
-use slog::{o, Logger};
+use slog::Logger;
 
 #[derive(Copy, Clone)]
 pub enum LfsMethod {
     Upload,
+    UploadChunk,
+    UploadStatus,
+    UploadFinish,
     Download,
     DownloadSha256,
     Batch,
@@ -28,6 +31,9 @@ impl fmt::Display for LfsMethod {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let name = match self {
             Self::Upload => "upload",
+            Self::UploadChunk => "upload_chunk",
+            Self::UploadStatus => "upload_status",
+            Self::UploadFinish => "upload_finish",
             Self::Download => "download",
             Self::DownloadSha256 => "download_sha256",
             Self::Batch => "batch",
@@ -83,9 +89,13 @@ impl Middleware for RequestContextMiddleware {
     async fn inbound(&self, state: &mut State) -> Option<Response<Body>> {
         let request_id = request_id(&state);
 
-        let logger = self.logger.new(o!("request_id" => request_id.to_string()));
         let session = SessionContainer::new_with_defaults(self.fb);
-        let ctx = session.new_context(logger, MononokeScubaSampleBuilder::with_discard());
+        let ctx = session
+            .new_context(
+                self.logger.clone(),
+                MononokeScubaSampleBuilder::with_discard(),
+            )
+            .with_request_id(RequestId::from_string(request_id));
 
         let should_log = ClientIdentity::try_borrow_from(&state)
             .map(|client_identity| !client_identity.is_proxygen_test_identity())
@@ -23,6 +23,7 @@ use mime;
 use std::pin::Pin;
 
 use crate::batch;
+use crate::chunked_upload;
 use crate::download;
 use crate::lfs_server_context::LfsServerContext;
 use crate::upload;
@@ -64,6 +65,30 @@ fn upload_handler(mut state: State) -> Pin<Box<HandlerFuture>> {
     .boxed()
 }
 
+fn upload_chunk_handler(mut state: State) -> Pin<Box<HandlerFuture>> {
+    async move {
+        let res = chunked_upload::upload_chunk(&mut state).await;
+        build_response(res, state)
+    }
+    .boxed()
+}
+
+fn upload_status_handler(mut state: State) -> Pin<Box<HandlerFuture>> {
+    async move {
+        let res = chunked_upload::upload_status(&mut state).await;
+        build_response(res, state)
+    }
+    .boxed()
+}
+
+fn upload_finish_handler(mut state: State) -> Pin<Box<HandlerFuture>> {
+    async move {
+        let res = chunked_upload::upload_finish(&mut state).await;
+        build_response(res, state)
+    }
+    .boxed()
+}
+
 fn health_handler(state: State) -> (State, &'static str) {
     let lfs_ctx = LfsServerContext::borrow_from(&state);
     let res = if lfs_ctx.will_exit() {
@@ -114,6 +139,21 @@ pub fn build_router(fb: FacebookInit, lfs_ctx: LfsServerContext) -> Router {
             .with_path_extractor::<upload::UploadParams>()
             .to(upload_handler);
 
+        route
+            .put("/:repository/upload/:oid/:size/chunks/:offset")
+            .with_path_extractor::<chunked_upload::UploadChunkParams>()
+            .to(upload_chunk_handler);
+
+        route
+            .get("/:repository/upload/:oid/:size/chunks")
+            .with_path_extractor::<chunked_upload::ChunkedUploadParams>()
+            .to(upload_status_handler);
+
+        route
+            .post("/:repository/upload/:oid/:size/finish")
+            .with_path_extractor::<chunked_upload::ChunkedUploadParams>()
+            .to(upload_finish_handler);
+
         route.get("/health_check").to(health_handler);
         route.get("/config").to(config_handler);
     })
@@ -303,7 +303,7 @@ where
     res.map(|_| ())
 }
 
-async fn sync_internal_and_upstream(
+pub(crate) async fn sync_internal_and_upstream(
     ctx: &RepositoryRequestContext,
     oid: Sha256,
     size: u64,
@@ -246,6 +246,82 @@ where
             .boxed()
     }
 
+    /// Walks the tree entries of this manifest in breadth-first order,
+    /// yielding every tree on one level before any tree on the next.
+    ///
+    /// `depth` limits how many levels below this manifest are visited, so
+    /// `Some(0)` only yields this manifest and `Some(1)` also yields its
+    /// immediate subdirectories. `matcher` is called with the path of each
+    /// subdirectory (relative to this manifest) and decides whether that
+    /// subdirectory is yielded and descended into.
+    fn bfs_tree_entries<M>(
+        &self,
+        ctx: CoreContext,
+        store: Store,
+        depth: Option<usize>,
+        matcher: M,
+    ) -> BoxStream<
+        'static,
+        Result<
+            (
+                Option<MPath>,
+                <<Self as StoreLoadable<Store>>::Value as Manifest>::TreeId,
+            ),
+            Error,
+        >,
+    >
+    where
+        M: Fn(&MPath) -> bool + Send + Sync + 'static,
+    {
+        let root = self.clone();
+        (async_stream::stream! {
+            let mut level = vec![(None, root)];
+            let mut current_depth = 0;
+            while !level.is_empty() {
+                let descend = depth.map_or(true, |depth| current_depth < depth);
+                let loaded = stream::iter(level).map(|(path, tree_id)| {
+                    cloned!(ctx, store);
+                    async move {
+                        let manifest = if descend {
+                            Some(tree_id.load(&ctx, &store).await?)
+                        } else {
+                            None
+                        };
+                        Ok::<_, Error>((path, tree_id, manifest))
+                    }
+                })
+                .buffered(256);
+                pin_mut!(loaded);
+
+                let mut next_level = Vec::new();
+                while let Some(loaded) = loaded.next().await {
+                    let (path, tree_id, manifest) = match loaded {
+                        Ok(loaded) => loaded,
+                        Err(err) => {
+                            yield Err(err);
+                            return;
+                        }
+                    };
+                    if let Some(manifest) = manifest {
+                        for (name, entry) in manifest.list() {
+                            if let Entry::Tree(child_id) = entry {
+                                let child_path = MPath::join_opt_element(path.as_ref(), &name);
+                                if matcher(&child_path) {
+                                    next_level.push((Some(child_path), child_id));
+                                }
+                            }
+                        }
+                    }
+                    yield Ok((path, tree_id));
+                }
+
+                level = next_level;
+                current_depth += 1;
+            }
+        })
+        .boxed()
+    }
+
     /// Returns differences between two manifests.
     ///
     /// `self` is considered the "old" manifest (so entries missing there are "Added")
@@ -615,6 +615,89 @@ async fn test_find_entries(fb: FacebookInit) -> Result<()> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn test_bfs_tree_entries(fb: FacebookInit) -> Result<()> {
+    let blobstore: Arc<dyn Blobstore> = Arc::new(Memblob::default());
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx, blobstore);
+
+    let mf0 = derive_test_manifest(
+        ctx,
+        blobstore,
+        vec![],
+        btreemap! {
+            "one/1" => Some("1"),
+            "two/three/4" => Some("4"),
+            "two/three/four/6" => Some("6"),
+            "five/six/8" => Some("8"),
+            "five/seven/eight/9" => Some("9"),
+        },
+    )
+    .await?
+    .expect("expect non empty manifest");
+
+    let num_components = |path: &Option<MPath>| path.as_ref().map_or(0, |p| p.num_components());
+
+    // unlimited depth, every directory is visited one level at a time
+    {
+        let results: Vec<_> = mf0
+            .bfs_tree_entries(ctx.clone(), blobstore.clone(), None, |_| true)
+            .map_ok(|(path, _)| path)
+            .try_collect()
+            .await?;
+
+        let depths: Vec<_> = results.iter().map(num_components).collect();
+        let mut sorted_depths = depths.clone();
+        sorted_depths.sort();
+        assert_eq!(depths, sorted_depths);
+
+        assert_eq!(
+            results.into_iter().collect::<BTreeSet<_>>(),
+            make_paths(&[
+                "/",
+                "one",
+                "two",
+                "two/three",
+                "two/three/four",
+                "five",
+                "five/six",
+                "five/seven",
+                "five/seven/eight",
+            ])?
+        );
+    }
+
+    // limited depth
+    {
+        let results: BTreeSet<_> = mf0
+            .bfs_tree_entries(ctx.clone(), blobstore.clone(), Some(1), |_| true)
+            .map_ok(|(path, _)| path)
+            .try_collect()
+            .await?;
+
+        assert_eq!(results, make_paths(&["/", "one", "two", "five"])?);
+    }
+
+    // matcher restricts the walk
+    {
+        let prefix = MPath::new("five/seven")?;
+        let results: BTreeSet<_> = mf0
+            .bfs_tree_entries(ctx.clone(), blobstore.clone(), None, move |path| {
+                path.is_prefix_of(&prefix) || prefix.is_prefix_of(path)
+            })
+            .map_ok(|(path, _)| path)
+            .try_collect()
+            .await?;
+
+        assert_eq!(
+            results,
+            make_paths(&["/", "five", "five/seven", "five/seven/eight"])?
+        );
+    }
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn test_diff(fb: FacebookInit) -> Result<()> {
     let blobstore: Arc<dyn Blobstore> = Arc::new(Memblob::default());
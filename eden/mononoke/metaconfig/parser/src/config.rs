@@ -17,13 +17,13 @@ use std::{
 
 use crate::convert::Convert;
 use crate::errors::ConfigurationError;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use cached_config::ConfigStore;
 use metaconfig_types::{
     AllowlistEntry, CensoredScubaParams, CommitSyncConfig, CommonConfig, HgsqlGlobalrevsName,
     HgsqlName, Redaction, RepoConfig, RepoReadOnly, StorageConfig,
 };
-use mononoke_types::RepositoryId;
+use mononoke_types::{MPath, RepositoryId};
 use repos::{
     RawCommitSyncConfig, RawCommonConfig, RawRepoConfig, RawRepoConfigs, RawStorageConfig,
 };
@@ -105,7 +105,7 @@ pub fn load_storage_configs(
     Ok(StorageConfigs { storage })
 }
 
-fn parse_common_config(common: RawCommonConfig) -> Result<CommonConfig> {
+pub(crate) fn parse_common_config(common: RawCommonConfig) -> Result<CommonConfig> {
     let mut tiers_num = 0;
     let security_config: Vec<_> = common
         .whitelist_entry
@@ -177,7 +177,7 @@ fn parse_common_config(common: RawCommonConfig) -> Result<CommonConfig> {
     })
 }
 
-fn parse_repo_config(
+pub(crate) fn parse_repo_config(
     reponame: &str,
     repo_config: RawRepoConfig,
     common_storage_config: &HashMap<String, RawStorageConfig>,
@@ -225,6 +225,8 @@ fn parse_repo_config(
         warm_bookmark_cache_check_blobimport,
         repo_client_knobs,
         phabricator_callsign,
+        warm_bookmark_cache_required_derived_data,
+        default_sparse_profile,
         ..
     } = repo_config;
 
@@ -345,8 +347,27 @@ fn parse_repo_config(
 
     let warm_bookmark_cache_check_blobimport =
         warm_bookmark_cache_check_blobimport.unwrap_or(false);
+    let warm_bookmark_cache_required_derived_data: HashSet<_> =
+        warm_bookmark_cache_required_derived_data
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+    for ty in &warm_bookmark_cache_required_derived_data {
+        if !derived_data_config.is_enabled(ty) {
+            return Err(ConfigurationError::InvalidConfig(format!(
+                "warm bookmark cache requires derived data type {}, which is not enabled",
+                ty
+            ))
+            .into());
+        }
+    }
     let repo_client_knobs = repo_client_knobs.convert()?.unwrap_or_default();
 
+    let default_sparse_profile = default_sparse_profile
+        .map(|path| MPath::new(&path))
+        .transpose()
+        .context("invalid default_sparse_profile")?;
+
     Ok(RepoConfig {
         enabled,
         storage_config,
@@ -384,8 +405,10 @@ fn parse_repo_config(
         repo_client_use_warm_bookmarks_cache,
         segmented_changelog_config,
         warm_bookmark_cache_check_blobimport,
+        warm_bookmark_cache_required_derived_data,
         repo_client_knobs,
         phabricator_callsign,
+        default_sparse_profile,
     })
 }
 
@@ -436,10 +459,10 @@ mod test {
         FilestoreParams, HookBypass, HookConfig, HookManagerParams, HookParams,
         InfinitepushNamespace, InfinitepushParams, LfsParams, LocalDatabaseConfig,
         MetadataDatabaseConfig, MultiplexId, MultiplexedStoreType, PushParams, PushrebaseFlags,
-        PushrebaseParams, RemoteDatabaseConfig, RemoteMetadataDatabaseConfig, RepoClientKnobs,
-        SegmentedChangelogConfig, ShardableRemoteDatabaseConfig, ShardedRemoteDatabaseConfig,
-        SmallRepoCommitSyncConfig, SourceControlServiceMonitoring, SourceControlServiceParams,
-        UnodeVersion, WireprotoLoggingConfig,
+        PushrebaseParams, ReadQuota, RemoteDatabaseConfig, RemoteMetadataDatabaseConfig,
+        RepoClientKnobs, SegmentedChangelogConfig, ShardableRemoteDatabaseConfig,
+        ShardedRemoteDatabaseConfig, SmallRepoCommitSyncConfig, SourceControlServiceMonitoring,
+        SourceControlServiceParams, UnodeVersion, WireprotoLoggingConfig,
     };
     use mononoke_types::MPath;
     use nonzero_ext::nonzero;
@@ -700,7 +723,9 @@ mod test {
             hipster_acl="foo/test"
             repo_client_use_warm_bookmarks_cache=true
             warm_bookmark_cache_check_blobimport=true
+            warm_bookmark_cache_required_derived_data=["fsnodes", "unodes"]
             phabricator_callsign="FBS"
+            default_sparse_profile="tools/sparse/base"
 
             [wireproto_logging]
             scribe_category="category"
@@ -733,6 +758,7 @@ mod test {
             ]
             queue_db = { remote = { db_address = "queue_db_address" } }
             minimum_successful_writes = 2
+            minimum_successful_reads = 2
 
             [[bookmarks]]
             name="master"
@@ -792,6 +818,11 @@ mod test {
             [repo_client_knobs]
             allow_short_getpack_history = true
 
+            [repo_client_knobs.read_quota]
+            max_bytes = 1000000000
+            window_secs = 60
+            enforced = true
+
             [segmented_changelog_config]
             enabled = true
             update_algorithm = "ondemand"
@@ -858,6 +889,7 @@ mod test {
                 ),
             ],
             minimum_successful_writes: nonzero!(2usize),
+            minimum_successful_reads: nonzero!(2usize),
             queue_db: DatabaseConfig::Remote(RemoteDatabaseConfig {
                 db_address: "queue_db_address".into(),
             }),
@@ -1036,10 +1068,21 @@ mod test {
                     update_algorithm: Some(String::from("ondemand")),
                 },
                 warm_bookmark_cache_check_blobimport: true,
+                warm_bookmark_cache_required_derived_data: hashset! {
+                    String::from("fsnodes"),
+                    String::from("unodes"),
+                },
                 repo_client_knobs: RepoClientKnobs {
                     allow_short_getpack_history: true,
+                    read_quota: Some(ReadQuota {
+                        max_bytes: Some(1_000_000_000),
+                        max_objects: None,
+                        window: Duration::from_secs(60),
+                        enforced: true,
+                    }),
                 },
                 phabricator_callsign: Some("FBS".to_string()),
+                default_sparse_profile: Some(MPath::new("tools/sparse/base").unwrap()),
             },
         );
 
@@ -1092,8 +1135,10 @@ mod test {
                     update_algorithm: None,
                 },
                 warm_bookmark_cache_check_blobimport: false,
+                warm_bookmark_cache_required_derived_data: HashSet::new(),
                 repo_client_knobs: RepoClientKnobs::default(),
                 phabricator_callsign: Some("WWW".to_string()),
+                default_sparse_profile: None,
             },
         );
         assert_eq!(
@@ -1176,6 +1221,71 @@ mod test {
         assert!(msg.contains("InvalidPushvar"));
     }
 
+    #[test]
+    fn test_warm_bookmark_cache_requires_enabled_derived_data() {
+        let content = r#"
+            repoid=0
+            storage_config = "sqlite"
+            warm_bookmark_cache_required_derived_data = ["fsnodes", "filenodes"]
+
+            [storage.sqlite.metadata.local]
+            local_db_path = "/tmp/fbsource"
+
+            [storage.sqlite.blobstore.blob_files]
+            path = "/tmp/fbsource"
+
+            [derived_data_config.enabled]
+            types = ["fsnodes"]
+        "#;
+
+        let paths = btreemap! {
+            "common/commitsyncmap.toml" => "",
+            "repos/fbsource/server.toml" => content,
+        };
+
+        let config_store = ConfigStore::new(Arc::new(TestSource::new()), None, None);
+        let tmp_dir = write_files(&paths);
+        let res = load_repo_configs(tmp_dir.path(), &config_store);
+        let msg = format!("{:#?}", res);
+        println!("res = {}", msg);
+        assert!(res.is_err());
+        assert!(msg.contains("requires derived data type filenodes"));
+    }
+
+    #[test]
+    fn test_read_quota_requires_a_limit() {
+        let content = r#"
+            repoid=0
+            storage_config = "sqlite"
+
+            [storage.sqlite.metadata.local]
+            local_db_path = "/tmp/fbsource"
+
+            [storage.sqlite.blobstore.blob_files]
+            path = "/tmp/fbsource"
+
+            [repo_client_knobs]
+            allow_short_getpack_history = false
+
+            [repo_client_knobs.read_quota]
+            window_secs = 60
+            enforced = true
+        "#;
+
+        let paths = btreemap! {
+            "common/commitsyncmap.toml" => "",
+            "repos/fbsource/server.toml" => content,
+        };
+
+        let config_store = ConfigStore::new(Arc::new(TestSource::new()), None, None);
+        let tmp_dir = write_files(&paths);
+        let res = load_repo_configs(tmp_dir.path(), &config_store);
+        let msg = format!("{:#?}", res);
+        println!("res = {}", msg);
+        assert!(res.is_err());
+        assert!(msg.contains("read_quota must set max_bytes or max_objects"));
+    }
+
     #[test]
     fn test_broken_common_config() {
         fn check_fails(common: &str, expect: &str) {
@@ -1293,6 +1403,7 @@ mod test {
                             })
                         ],
                         minimum_successful_writes: nonzero!(1usize),
+                        minimum_successful_reads: nonzero!(1usize),
                         queue_db: DatabaseConfig::Remote(
                             RemoteDatabaseConfig {
                                 db_address: "queue_db_address".into(),
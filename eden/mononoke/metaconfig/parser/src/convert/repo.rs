@@ -7,6 +7,7 @@
 
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 use bookmarks_types::BookmarkName;
@@ -14,18 +15,19 @@ use metaconfig_types::{
     BookmarkOrRegex, BookmarkParams, Bundle2ReplayParams, CacheWarmupParams,
     CommitcloudBookmarksFillerMode, ComparableRegex, DerivedDataConfig, DerivedDataTypesConfig,
     HookBypass, HookConfig, HookManagerParams, HookParams, InfinitepushNamespace,
-    InfinitepushParams, LfsParams, PushParams, PushrebaseFlags, PushrebaseParams, RepoClientKnobs,
-    SegmentedChangelogConfig, ServiceWriteRestrictions, SourceControlServiceMonitoring,
-    SourceControlServiceParams, StorageConfig, UnodeVersion, WireprotoLoggingConfig,
+    InfinitepushParams, LfsParams, PushParams, PushrebaseFlags, PushrebaseParams, ReadQuota,
+    RepoClientKnobs, SegmentedChangelogConfig, ServiceWriteRestrictions,
+    SourceControlServiceMonitoring, SourceControlServiceParams, StorageConfig, UnodeVersion,
+    WireprotoLoggingConfig,
 };
 use mononoke_types::{MPath, PrefixTrie};
 use regex::Regex;
 use repos::{
     RawBookmarkConfig, RawBundle2ReplayParams, RawCacheWarmupConfig, RawCommitcloudBookmarksFiller,
     RawDerivedDataConfig, RawDerivedDataTypesConfig, RawHookConfig, RawHookManagerParams,
-    RawInfinitepushParams, RawLfsParams, RawPushParams, RawPushrebaseParams, RawRepoClientKnobs,
-    RawSegmentedChangelogConfig, RawServiceWriteRestrictions, RawSourceControlServiceMonitoring,
-    RawSourceControlServiceParams, RawWireprotoLoggingConfig,
+    RawInfinitepushParams, RawLfsParams, RawPushParams, RawPushrebaseParams, RawReadQuota,
+    RawRepoClientKnobs, RawSegmentedChangelogConfig, RawServiceWriteRestrictions,
+    RawSourceControlServiceMonitoring, RawSourceControlServiceParams, RawWireprotoLoggingConfig,
 };
 
 use crate::convert::Convert;
@@ -142,7 +144,7 @@ impl Convert for RawBookmarkConfig {
 
     fn convert(self) -> Result<Self::Output> {
         let bookmark_or_regex = match (self.regex, self.name) {
-            (None, Some(name)) => BookmarkOrRegex::Bookmark(BookmarkName::new(name).unwrap()),
+            (None, Some(name)) => BookmarkOrRegex::Bookmark(BookmarkName::new(name)?),
             (Some(regex), None) => match Regex::new(&regex) {
                 Ok(regex) => BookmarkOrRegex::Regex(ComparableRegex::new(regex)),
                 Err(err) => {
@@ -408,6 +410,34 @@ impl Convert for RawRepoClientKnobs {
     fn convert(self) -> Result<Self::Output> {
         Ok(RepoClientKnobs {
             allow_short_getpack_history: self.allow_short_getpack_history,
+            read_quota: self.read_quota.convert()?,
+        })
+    }
+}
+
+impl Convert for RawReadQuota {
+    type Output = ReadQuota;
+
+    fn convert(self) -> Result<Self::Output> {
+        if self.max_bytes.is_none() && self.max_objects.is_none() {
+            return Err(ConfigurationError::InvalidConfig(
+                "read_quota must set max_bytes or max_objects".to_string(),
+            )
+            .into());
+        }
+        if self.window_secs <= 0 {
+            return Err(ConfigurationError::InvalidConfig(format!(
+                "read_quota window_secs must be positive, got {}",
+                self.window_secs
+            ))
+            .into());
+        }
+
+        Ok(ReadQuota {
+            max_bytes: self.max_bytes.map(|v| v.try_into()).transpose()?,
+            max_objects: self.max_objects.map(|v| v.try_into()).transpose()?,
+            window: Duration::from_secs(self.window_secs.try_into()?),
+            enforced: self.enforced,
         })
     }
 }
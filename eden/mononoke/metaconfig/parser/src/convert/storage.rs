@@ -73,6 +73,22 @@ impl Convert for RawBlobstoreConfig {
                         anyhow!("Must require at least 1 successful write to make a put succeed")
                     })?;
 
+                let unchecked_minimum_successful_reads: usize =
+                    raw.minimum_successful_reads.unwrap_or(1).try_into()?;
+
+                if unchecked_minimum_successful_reads > raw.components.len() {
+                    return Err(anyhow!(
+                        "Not enough blobstores for {} required reads (have {})",
+                        unchecked_minimum_successful_reads,
+                        raw.components.len()
+                    ));
+                }
+
+                let minimum_successful_reads =
+                    NonZeroUsize::new(unchecked_minimum_successful_reads).ok_or_else(|| {
+                        anyhow!("Must require at least 1 successful read to make a get succeed")
+                    })?;
+
                 BlobConfig::Multiplexed {
                     multiplex_id: raw
                         .multiplex_id
@@ -94,6 +110,7 @@ impl Convert for RawBlobstoreConfig {
                         })
                         .collect::<Result<Vec<_>>>()?,
                     minimum_successful_writes,
+                    minimum_successful_reads,
                     queue_db: raw
                         .queue_db
                         .ok_or_else(|| anyhow!("missing queue_db from configuration"))?
@@ -122,6 +139,16 @@ impl Convert for RawBlobstoreConfig {
                 region_name: raw.region_name,
                 endpoint: raw.endpoint,
             },
+            RawBlobstoreConfig::gcs(raw) => BlobConfig::Gcs {
+                bucket: raw.bucket,
+                prefix: raw.prefix.unwrap_or_default(),
+                credentials_path: raw.credentials_path.map(PathBuf::from),
+            },
+            RawBlobstoreConfig::http(raw) => BlobConfig::Http {
+                get_url: raw.get_url,
+                put_url: raw.put_url,
+                headers: raw.headers.unwrap_or_default(),
+            },
             RawBlobstoreConfig::UnknownField(f) => {
                 return Err(anyhow!("unsupported blobstore configuration ({})", f));
             }
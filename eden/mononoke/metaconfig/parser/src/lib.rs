@@ -14,9 +14,11 @@ pub mod config;
 mod convert;
 pub mod errors;
 mod raw;
+pub mod validate;
 
 pub use crate::config::{
     load_common_config, load_repo_configs, load_storage_configs, RepoConfigs, StorageConfigs,
 };
 pub use crate::errors::ConfigurationError;
+pub use crate::validate::{validate_configs, ConfigProblem};
 pub use convert::Convert;
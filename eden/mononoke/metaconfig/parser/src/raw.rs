@@ -97,7 +97,7 @@ fn read_raw_configs_toml(config_path: &Path) -> Result<RawRepoConfigs> {
     })
 }
 
-fn read_toml_path<T>(path: &Path, defaults: bool) -> Result<T>
+pub(crate) fn read_toml_path<T>(path: &Path, defaults: bool) -> Result<T>
 where
     T: serde::de::DeserializeOwned + Default,
 {
@@ -0,0 +1,481 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Validation of Mononoke configuration, to be run before configs are rolled out. Unlike loading,
+//! which stops at the first error, validation carries on and reports every problem it finds,
+//! together with the file it was found in.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::Path,
+};
+
+use anyhow::{anyhow, Error, Result};
+use bookmarks_types::BookmarkName;
+use cached_config::ConfigStore;
+use metaconfig_types::CommitSyncConfig;
+use mononoke_types::RepositoryId;
+use repos::{
+    RawCommitSyncConfig, RawCommonConfig, RawRepoConfig, RawRepoConfigs, RawStorageConfig,
+};
+
+use crate::config::{parse_common_config, parse_repo_config};
+use crate::convert::Convert;
+use crate::errors::ConfigurationError;
+use crate::raw::{read_raw_configs, read_toml_path};
+
+const COMMIT_SYNC_FILE: &str = "common/commitsyncmap.toml";
+const COMMON_FILE: &str = "common/common.toml";
+const STORAGE_FILE: &str = "common/storage.toml";
+const REPOS_DIR: &str = "repos";
+const REPO_FILE: &str = "server.toml";
+
+/// A problem found while validating configuration
+#[derive(Debug)]
+pub struct ConfigProblem {
+    /// File the problem was found in. Errors in the syntax of TOML files carry the line and
+    /// column they are at.
+    pub location: String,
+    /// What is wrong
+    pub error: Error,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:#}", self.location, self.error)
+    }
+}
+
+/// Validate the repo and storage configs, and their references to each other. Returns all the
+/// problems found, which is empty if the configs are valid.
+pub fn validate_configs(
+    config_path: impl AsRef<Path>,
+    config_store: &ConfigStore,
+) -> Vec<ConfigProblem> {
+    let config_path = config_path.as_ref();
+    let mut problems = Problems::default();
+
+    let (raw_configs, locations) = if config_path.is_dir() {
+        let raw_configs = read_raw_configs_toml(config_path, &mut problems);
+        (raw_configs, Locations::TomlDir(config_path))
+    } else {
+        match read_raw_configs(config_path, config_store) {
+            Ok(raw_configs) => (raw_configs, Locations::Single(config_path)),
+            Err(err) => {
+                problems.report(config_path.display().to_string(), err);
+                return problems.0;
+            }
+        }
+    };
+
+    // If some of the files couldn't be read, the repos they define are missing, and references
+    // to their repo ids can't be checked
+    let all_repos_read = problems.0.is_empty();
+    check_raw_configs(&raw_configs, &locations, all_repos_read, &mut problems);
+    problems.0
+}
+
+#[derive(Default)]
+struct Problems(Vec<ConfigProblem>);
+
+impl Problems {
+    fn report(&mut self, location: String, error: impl Into<Error>) {
+        self.0.push(ConfigProblem {
+            location,
+            error: error.into(),
+        });
+    }
+
+    fn check<T>(&mut self, location: String, res: Result<T>) -> Option<T> {
+        match res {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.report(location, err);
+                None
+            }
+        }
+    }
+}
+
+/// Where the parts of the configuration come from
+enum Locations<'a> {
+    /// A directory of TOML files, one per repo plus the common ones
+    TomlDir(&'a Path),
+    /// A single JSON file, or a configerator path
+    Single(&'a Path),
+}
+
+impl Locations<'_> {
+    fn common_file(&self, file: &str) -> String {
+        match self {
+            Locations::TomlDir(path) => path.join(file).display().to_string(),
+            Locations::Single(path) => path.display().to_string(),
+        }
+    }
+
+    fn repo_file(&self, reponame: &str) -> String {
+        match self {
+            Locations::TomlDir(path) => path
+                .join(REPOS_DIR)
+                .join(reponame)
+                .join(REPO_FILE)
+                .display()
+                .to_string(),
+            Locations::Single(path) => format!("{} (repo {})", path.display(), reponame),
+        }
+    }
+}
+
+/// Read a directory of TOML configs file by file, so that an error in one of them doesn't hide
+/// the errors in the others. Files which can't be read are left out of the result.
+fn read_raw_configs_toml(config_path: &Path, problems: &mut Problems) -> RawRepoConfigs {
+    let commit_sync: Option<HashMap<String, RawCommitSyncConfig>> =
+        read_file(&config_path.join(COMMIT_SYNC_FILE), false, problems);
+    let common: Option<RawCommonConfig> = read_file(&config_path.join(COMMON_FILE), true, problems);
+    let storage: Option<HashMap<String, RawStorageConfig>> =
+        read_file(&config_path.join(STORAGE_FILE), true, problems);
+
+    let mut repos = HashMap::new();
+    let repos_dir = config_path.join(REPOS_DIR);
+    let entries = match repos_dir.read_dir() {
+        Ok(entries) => entries,
+        Err(err) => {
+            problems.report(
+                repos_dir.display().to_string(),
+                Error::from(err).context(ConfigurationError::InvalidFileStructure(format!(
+                    "expected '{}' directory under {}",
+                    REPOS_DIR,
+                    config_path.display()
+                ))),
+            );
+            return RawRepoConfigs {
+                commit_sync: commit_sync.unwrap_or_default(),
+                common: common.unwrap_or_default(),
+                repos,
+                storage: storage.unwrap_or_default(),
+            };
+        }
+    };
+    for entry in entries {
+        let repo_config_path = match entry {
+            Ok(entry) => entry.path(),
+            Err(err) => {
+                problems.report(repos_dir.display().to_string(), err);
+                continue;
+            }
+        };
+        let reponame = match repo_config_path.file_name().and_then(|s| s.to_str()) {
+            Some(reponame) => reponame.to_string(),
+            None => {
+                problems.report(
+                    repo_config_path.display().to_string(),
+                    ConfigurationError::InvalidFileStructure(format!(
+                        "invalid repo path {:?}",
+                        repo_config_path
+                    )),
+                );
+                continue;
+            }
+        };
+        let repo_file = repo_config_path.join(REPO_FILE);
+        if let Some(repo_config) = read_file::<RawRepoConfig>(&repo_file, false, problems) {
+            repos.insert(reponame, repo_config);
+        }
+    }
+
+    RawRepoConfigs {
+        commit_sync: commit_sync.unwrap_or_default(),
+        common: common.unwrap_or_default(),
+        repos,
+        storage: storage.unwrap_or_default(),
+    }
+}
+
+fn read_file<T>(path: &Path, defaults: bool, problems: &mut Problems) -> Option<T>
+where
+    T: serde::de::DeserializeOwned + Default,
+{
+    problems.check(path.display().to_string(), read_toml_path(path, defaults))
+}
+
+fn check_raw_configs(
+    raw_configs: &RawRepoConfigs,
+    locations: &Locations<'_>,
+    all_repos_read: bool,
+    problems: &mut Problems,
+) {
+    problems.check(
+        locations.common_file(COMMON_FILE),
+        parse_common_config(raw_configs.common.clone()),
+    );
+
+    let storage: BTreeMap<_, _> = raw_configs.storage.iter().collect();
+    for (name, raw_storage_config) in storage {
+        problems.check(
+            locations.common_file(STORAGE_FILE),
+            raw_storage_config
+                .clone()
+                .convert()
+                .map_err(|err| err.context(format!("invalid storage config {}", name))),
+        );
+    }
+
+    let mut commit_sync = HashMap::new();
+    let raw_commit_sync: BTreeMap<_, _> = raw_configs.commit_sync.iter().collect();
+    for (name, raw_commit_sync_config) in &raw_commit_sync {
+        let res = raw_commit_sync_config
+            .clone()
+            .convert()
+            .map_err(|err| err.context(format!("invalid commit sync config {}", name)));
+        if let Some(config) = problems.check(locations.common_file(COMMIT_SYNC_FILE), res) {
+            commit_sync.insert(name.to_string(), config);
+        }
+    }
+
+    let mut repoids = BTreeMap::<_, Vec<_>>::new();
+    let repos: BTreeMap<_, _> = raw_configs.repos.iter().collect();
+    for (reponame, raw_repo_config) in repos {
+        if let Some(repoid) = raw_repo_config.repoid {
+            repoids.entry(repoid).or_default().push(reponame.as_str());
+        }
+        check_repo_config(
+            reponame,
+            raw_repo_config,
+            &raw_configs.storage,
+            &commit_sync,
+            locations,
+            problems,
+        );
+    }
+
+    for (repoid, reponames) in &repoids {
+        if let [first, others @ ..] = reponames.as_slice() {
+            if !others.is_empty() {
+                let err = Error::from(ConfigurationError::DuplicatedRepoId(RepositoryId::new(
+                    *repoid,
+                )))
+                .context(format!("repoid is also used by {}", others.join(", ")));
+                problems.report(locations.repo_file(first), err);
+            }
+        }
+    }
+
+    if all_repos_read {
+        for (name, raw_commit_sync_config) in raw_commit_sync {
+            let referenced = std::iter::once(raw_commit_sync_config.large_repo_id).chain(
+                raw_commit_sync_config
+                    .small_repos
+                    .iter()
+                    .map(|small_repo| small_repo.repoid),
+            );
+            for repoid in referenced {
+                if !repoids.contains_key(&repoid) {
+                    problems.report(
+                        locations.common_file(COMMIT_SYNC_FILE),
+                        anyhow!(
+                            "commit sync config {} refers to repoid {}, which no repo has",
+                            name,
+                            repoid
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn check_repo_config(
+    reponame: &str,
+    raw_repo_config: &RawRepoConfig,
+    common_storage_config: &HashMap<String, RawStorageConfig>,
+    commit_sync: &HashMap<String, CommitSyncConfig>,
+    locations: &Locations<'_>,
+    problems: &mut Problems,
+) {
+    let location = locations.repo_file(reponame);
+    let problems_before = problems.0.len();
+
+    let storage_exists = |name: &str| {
+        raw_repo_config
+            .storage
+            .as_ref()
+            .map_or(false, |storage| storage.contains_key(name))
+            || common_storage_config.contains_key(name)
+    };
+    let storage_names = raw_repo_config.storage_config.iter().chain(
+        raw_repo_config
+            .wireproto_logging
+            .as_ref()
+            .and_then(|wireproto_logging| wireproto_logging.storage_config.as_ref()),
+    );
+    for name in storage_names {
+        if !storage_exists(name) {
+            problems.report(
+                location.clone(),
+                ConfigurationError::InvalidConfig(format!("Storage \"{}\" not defined", name)),
+            );
+        }
+    }
+
+    // Bookmark names are checked here, so that the error says which one is wrong
+    for name in bookmark_names(raw_repo_config) {
+        if let Err(err) = BookmarkName::new(name) {
+            problems.report(location.clone(), err.context("invalid bookmark name"));
+        }
+    }
+
+    // Conversion reports the remaining problems, but stops at the first one, which is likely to
+    // have been reported already
+    if problems.0.len() == problems_before {
+        problems.check(
+            location,
+            parse_repo_config(
+                reponame,
+                raw_repo_config.clone(),
+                common_storage_config,
+                commit_sync,
+            ),
+        );
+    }
+}
+
+/// All the bookmark names a repo config refers to
+fn bookmark_names(raw_repo_config: &RawRepoConfig) -> Vec<&str> {
+    let mut names = Vec::new();
+    for bookmark in raw_repo_config.bookmarks.iter().flatten() {
+        names.extend(bookmark.name.as_deref());
+        names.extend(
+            bookmark
+                .hooks_skip_ancestors_of
+                .iter()
+                .flatten()
+                .map(String::as_str),
+        );
+    }
+    if let Some(cache_warmup) = &raw_repo_config.cache_warmup {
+        names.push(cache_warmup.bookmark.as_str());
+    }
+    if let Some(pushrebase) = &raw_repo_config.pushrebase {
+        names.extend(pushrebase.globalrevs_publishing_bookmark.as_deref());
+    }
+    if let Some(monitoring) = &raw_repo_config.source_control_service_monitoring {
+        names.extend(
+            monitoring
+                .bookmarks_to_report_age
+                .iter()
+                .map(String::as_str),
+        );
+    }
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cached_config::TestSource;
+    use maplit::btreemap;
+    use std::fs::{create_dir_all, write};
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    const COMMON_CONTENT: &str = r#"
+        [[whitelist_entry]]
+        tier = "tier1"
+    "#;
+
+    const STORAGE_CONTENT: &str = r#"
+        [files.metadata.local]
+        local_db_path = "/tmp/files"
+
+        [files.blobstore.blob_files]
+        path = "/tmp/files"
+    "#;
+
+    fn validate(files: BTreeMap<&str, &str>) -> Vec<String> {
+        let tmp_dir = TempDir::new("mononoke_test_config").expect("tmp_dir failed");
+        create_dir_all(tmp_dir.path().join(REPOS_DIR)).expect("create repos failed");
+        for (path, content) in files {
+            let path = tmp_dir.path().join(path);
+            create_dir_all(path.parent().expect("missing parent")).expect("create dir failed");
+            write(path, content).expect("write failed");
+        }
+
+        let config_store = ConfigStore::new(Arc::new(TestSource::new()), None, None);
+        validate_configs(tmp_dir.path(), &config_store)
+            .into_iter()
+            .map(|problem| {
+                // Make locations relative to the config directory
+                let prefix = format!("{}/", tmp_dir.path().display());
+                problem.to_string().replace(&prefix, "")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_valid_configs() {
+        let problems = validate(btreemap! {
+            COMMON_FILE => COMMON_CONTENT,
+            STORAGE_FILE => STORAGE_CONTENT,
+            COMMIT_SYNC_FILE => r#"
+                [mega]
+                large_repo_id = 1
+                common_pushrebase_bookmarks = ["master"]
+
+                [[mega.small_repos]]
+                repoid = 2
+                bookmark_prefix = "small/"
+                default_action = "preserve"
+            "#,
+            "repos/large/server.toml" => r#"
+                repoid = 1
+                storage_config = "files"
+
+                [[bookmarks]]
+                name = "master"
+            "#,
+            "repos/small/server.toml" => r#"
+                repoid = 2
+                storage_config = "files"
+            "#,
+        });
+        assert!(problems.is_empty(), "{:#?}", problems);
+    }
+
+    #[test]
+    fn test_validate_reports_all_problems() {
+        let problems = validate(btreemap! {
+            COMMON_FILE => COMMON_CONTENT,
+            STORAGE_FILE => STORAGE_CONTENT,
+            COMMIT_SYNC_FILE => "",
+            "repos/broken/server.toml" => "repoid = ",
+            "repos/nostorage/server.toml" => r#"
+                repoid = 1
+                storage_config = "missing"
+            "#,
+            "repos/badbookmark/server.toml" => r#"
+                repoid = 1
+                storage_config = "files"
+
+                [[bookmarks]]
+                name = "mäster"
+            "#,
+        });
+        assert_eq!(problems.len(), 4, "{:#?}", problems);
+        assert!(problems[0].starts_with("repos/broken/server.toml: "));
+        assert!(problems[0].contains("line 1"), "{}", problems[0]);
+        assert!(problems[1].starts_with("repos/badbookmark/server.toml: invalid bookmark name"));
+        assert_eq!(
+            problems[2],
+            "repos/nostorage/server.toml: invalid config options: Storage \"missing\" not defined"
+        );
+        assert_eq!(
+            problems[3],
+            "repos/badbookmark/server.toml: repoid is also used by nostorage: repoid 1 used more than once"
+        );
+    }
+}
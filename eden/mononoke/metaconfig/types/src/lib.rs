@@ -13,7 +13,7 @@
 
 use anyhow::{anyhow, Error, Result};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt,
     num::{NonZeroU64, NonZeroUsize},
     ops::Deref,
@@ -29,7 +29,7 @@ use bookmarks_types::BookmarkName;
 use mononoke_types::{BonsaiChangeset, MPath, PrefixTrie, RepositoryId};
 use regex::Regex;
 use scuba::ScubaValue;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 use sql::mysql;
 use sql::mysql_async::{
     from_value_opt,
@@ -195,10 +195,15 @@ pub struct RepoConfig {
     /// Do not consider bookmark warm unless blobimport processed it.
     /// That means that changeset is present in both Mononoke and hg.
     pub warm_bookmark_cache_check_blobimport: bool,
+    /// Derived data types which must be derived for a changeset before the warm bookmark cache
+    /// serves a bookmark pointing to it, in addition to those the server asks for.
+    pub warm_bookmark_cache_required_derived_data: HashSet<String>,
     /// Configuration for repo_client module
     pub repo_client_knobs: RepoClientKnobs,
     /// Callsign to check phabricator commits
     pub phabricator_callsign: Option<String>,
+    /// Sparse profile that clients enable by default when cloning the repo
+    pub default_sparse_profile: Option<MPath>,
 }
 
 /// Configuration for repo_client module
@@ -206,6 +211,21 @@ pub struct RepoConfig {
 pub struct RepoClientKnobs {
     /// Return shorter file history in getpack call
     pub allow_short_getpack_history: bool,
+    /// Limit how much each client identity can read
+    pub read_quota: Option<ReadQuota>,
+}
+
+/// Limits on how much a single client identity can read from a repo within a time window
+#[derive(Eq, Copy, Clone, Debug, PartialEq)]
+pub struct ReadQuota {
+    /// Maximum number of bytes sent to the client in a window
+    pub max_bytes: Option<u64>,
+    /// Maximum number of files and trees sent to the client in a window
+    pub max_objects: Option<u64>,
+    /// Length of the window after which usage is reset
+    pub window: Duration,
+    /// Reject requests from clients over quota, rather than only logging them
+    pub enforced: bool,
 }
 
 /// Config for derived data
@@ -626,7 +646,18 @@ pub struct LfsParams {
 }
 
 /// Id used to discriminate diffirent underlying blobstore instances
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd, Deserialize)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Deserialize,
+    Serialize
+)]
 #[derive(mysql::OptTryFromRowField)]
 pub struct BlobstoreId(u64);
 
@@ -775,6 +806,9 @@ pub enum BlobConfig {
         blobstores: Vec<(BlobstoreId, MultiplexedStoreType, BlobConfig)>,
         /// The number of writes that must succeed for a `put` to the multiplex to succeed
         minimum_successful_writes: NonZeroUsize,
+        /// The number of blobstores that must agree on a value for a `get` from the multiplex
+        /// to succeed
+        minimum_successful_reads: NonZeroUsize,
         /// 1 in scuba_sample_rate samples will be logged.
         scuba_sample_rate: NonZeroU64,
         /// DB config to use for the sync queue
@@ -814,6 +848,25 @@ pub enum BlobConfig {
         /// S3 host:port to connect to
         endpoint: String,
     },
+    /// Store in a Google Cloud Storage bucket
+    Gcs {
+        /// Bucket to connect to
+        bucket: String,
+        /// Prefix to be prepended to all the keys
+        prefix: String,
+        /// Path to the key of the service account to authenticate as. If None, the path in
+        /// $GOOGLE_APPLICATION_CREDENTIALS is used
+        credentials_path: Option<PathBuf>,
+    },
+    /// Store on an HTTP server, such as a WebDAV server or an artifact cache
+    Http {
+        /// URL to read blobs from, where {key} is replaced by the key of the blob
+        get_url: String,
+        /// URL to write blobs to, if different from get_url
+        put_url: Option<String>,
+        /// Headers to send with every request
+        headers: BTreeMap<String, String>,
+    },
 }
 
 impl BlobConfig {
@@ -824,7 +877,12 @@ impl BlobConfig {
 
         match self {
             Disabled | Files { .. } | Sqlite { .. } => true,
-            Manifold { .. } | Mysql { .. } | ManifoldWithTtl { .. } | S3 { .. } => false,
+            Manifold { .. }
+            | Mysql { .. }
+            | ManifoldWithTtl { .. }
+            | S3 { .. }
+            | Gcs { .. }
+            | Http { .. } => false,
             Multiplexed { blobstores, .. } => blobstores
                 .iter()
                 .map(|(_, _, config)| config)
@@ -57,6 +57,7 @@ pub use crate::path::MononokePath;
 pub use crate::repo::{BookmarkFreshness, Repo, RepoContext};
 pub use crate::repo_write::create_changeset::{CreateChange, CreateCopyInfo};
 pub use crate::repo_write::land_stack::PushrebaseOutcome;
+pub use crate::repo_write::move_bookmark::{BookmarkMoveCheck, BookmarkMoveVerdict};
 pub use crate::repo_write::RepoWriteContext;
 pub use crate::specifiers::{
     ChangesetId, ChangesetIdPrefix, ChangesetPrefixSpecifier, ChangesetSpecifier,
@@ -66,7 +67,7 @@ pub use crate::tree::{TreeContext, TreeEntry, TreeId, TreeSummary};
 pub use crate::xrepo::CandidateSelectionHintArgs;
 
 // Re-export types that are useful for clients.
-pub use context::{CoreContext, LoggingContainer, SessionContainer};
+pub use context::{CoreContext, LoggingContainer, RequestId, SessionContainer};
 
 /// An instance of Mononoke, which may manage multiple repositories.
 pub struct Mononoke {
@@ -21,6 +21,8 @@ use blobstore_factory::make_metadata_sql_factory;
 pub use bookmarks::Freshness as BookmarkFreshness;
 use bookmarks::{BookmarkKind, BookmarkName, BookmarkPagination, BookmarkPrefix, Bookmarks};
 use changeset_info::ChangesetInfo;
+use chrono::NaiveDate;
+use commit_stats::{update_commit_stats, AuthorCommitStats, DailyCommitStats, SqlCommitStats};
 use context::CoreContext;
 use cross_repo_sync::{
     types::Target, CandidateSelectionHint, CommitSyncContext, CommitSyncRepos, CommitSyncer,
@@ -55,7 +57,7 @@ use repo_read_write_status::{RepoReadWriteFetcher, SqlRepoReadWriteStatus};
 use revset::AncestorsNodeStream;
 use segmented_changelog::{CloneData, Location, SegmentedChangelog, StreamCloneData};
 use skiplist::{fetch_skiplist_index, SkiplistIndex};
-use slog::{debug, error, o, Logger};
+use slog::{debug, error, o, warn, Logger};
 use sql_construct::facebook::FbSqlConstruct;
 use sql_construct::SqlConstruct;
 use sql_construct::SqlConstructFromMetadataDatabaseConfig;
@@ -107,8 +109,12 @@ pub struct Repo {
     pub(crate) live_commit_sync_config: Arc<dyn LiveCommitSyncConfig>,
     pub(crate) hook_manager: Arc<HookManager>,
     pub(crate) readonly_fetcher: RepoReadWriteFetcher,
+    pub(crate) commit_stats: Arc<SqlCommitStats>,
 }
 
+/// Number of bookmark update log entries read at once when updating commit statistics
+const COMMIT_STATS_BATCH_SIZE: u64 = 1000;
+
 #[derive(Clone)]
 pub struct RepoContext {
     ctx: CoreContext,
@@ -218,6 +224,8 @@ impl Repo {
             WarmBookmarksCacheDerivedData::HgOnly => {
                 warm_bookmarks_cache_builder
                     .add_derived_data_warmers(Some(MappedHgChangesetId::NAME))?;
+                warm_bookmarks_cache_builder
+                    .add_derived_data_warmers(&config.warm_bookmark_cache_required_derived_data)?;
             }
             WarmBookmarksCacheDerivedData::AllKinds => {
                 warm_bookmarks_cache_builder.add_all_derived_data_warmers()?;
@@ -239,6 +247,13 @@ impl Repo {
         let warm_bookmarks_cache =
             warm_bookmarks_cache_builder.build(env.warm_bookmarks_cache_delay);
 
+        let commit_stats = SqlCommitStats::with_metadata_database_config(
+            env.fb,
+            &config.storage_config.metadata,
+            &env.mysql_options,
+            env.readonly_storage.0,
+        );
+
         let sql_read_write_status = async {
             if let Some(addr) = &config.write_lock_db_address {
                 let r = SqlRepoReadWriteStatus::with_xdb(
@@ -261,6 +276,7 @@ impl Repo {
             warm_bookmarks_cache,
             hook_manager,
             sql_read_write_status,
+            commit_stats,
         ) = try_join!(
             repo_permission_checker.watched(&logger),
             service_permission_checker.watched(&logger),
@@ -268,6 +284,7 @@ impl Repo {
             warm_bookmarks_cache.watched(&logger),
             hook_manager.watched(&logger),
             sql_read_write_status.watched(&logger),
+            commit_stats.watched(&logger),
         )?;
 
         let readonly_fetcher = RepoReadWriteFetcher::new(
@@ -288,6 +305,7 @@ impl Repo {
             live_commit_sync_config,
             hook_manager,
             readonly_fetcher,
+            commit_stats: Arc::new(commit_stats),
         })
     }
 
@@ -386,6 +404,7 @@ impl Repo {
             live_commit_sync_config,
             hook_manager,
             readonly_fetcher,
+            commit_stats: Arc::new(SqlCommitStats::with_sqlite_in_memory()?),
         })
     }
 
@@ -434,6 +453,11 @@ impl Repo {
         &self.readonly_fetcher
     }
 
+    /// The commit activity statistics of the bookmarks.
+    pub fn commit_stats(&self) -> &Arc<SqlCommitStats> {
+        &self.commit_stats
+    }
+
     /// The configuration for the referenced repository.
     pub fn config(&self) -> &RepoConfig {
         &self.config
@@ -772,6 +796,11 @@ impl RepoContext {
         self.repo.readonly_fetcher()
     }
 
+    /// The commit activity statistics of the bookmarks.
+    pub fn commit_stats(&self) -> &Arc<SqlCommitStats> {
+        self.repo.commit_stats()
+    }
+
     /// The configuration for the referenced repository.
     pub fn config(&self) -> &RepoConfig {
         self.repo.config()
@@ -1454,6 +1483,94 @@ impl RepoContext {
             .map_err(MononokeError::from)?;
         Ok(clone_data)
     }
+
+    /// Data for a client with a segmented changelog up to `old_master` to move its master to
+    /// `new_master`. Only the commits that are new to the client are sent.
+    pub async fn segmented_changelog_pull_fast_forward_master(
+        &self,
+        old_master: ChangesetId,
+        new_master: ChangesetId,
+    ) -> Result<CloneData<ChangesetId>, MononokeError> {
+        let blob_repo = self.blob_repo();
+        let segmented_changelog =
+            blob_repo
+                .attribute::<dyn SegmentedChangelog>()
+                .ok_or_else(|| {
+                    MononokeError::InvalidRequest(String::from(
+                        "Segmented Changelog is not enabled for this repo",
+                    ))
+                })?;
+        let pull_data = segmented_changelog
+            .pull_fast_forward_master(&self.ctx, old_master, new_master)
+            .await
+            .map_err(MononokeError::from)?;
+        Ok(pull_data)
+    }
+
+    /// Commit activity of a bookmark for each day from `since` to `until` included, in UTC.
+    /// Days when no commits landed are left out.
+    pub async fn daily_commit_stats(
+        &self,
+        bookmark: &BookmarkName,
+        since: NaiveDate,
+        until: NaiveDate,
+    ) -> Result<Vec<DailyCommitStats>, MononokeError> {
+        check_commit_stats_range(since, until)?;
+        self.update_commit_stats(bookmark).await;
+        let stats = self
+            .commit_stats()
+            .get_daily_stats(&self.ctx, self.repoid(), bookmark, since, until)
+            .await?;
+        Ok(stats)
+    }
+
+    /// The `limit` authors with the most commits landed on a bookmark from `since` to `until`
+    /// included, in UTC.
+    pub async fn top_commit_authors(
+        &self,
+        bookmark: &BookmarkName,
+        since: NaiveDate,
+        until: NaiveDate,
+        limit: u64,
+    ) -> Result<Vec<AuthorCommitStats>, MononokeError> {
+        check_commit_stats_range(since, until)?;
+        self.update_commit_stats(bookmark).await;
+        let authors = self
+            .commit_stats()
+            .get_top_authors(&self.ctx, self.repoid(), bookmark, since, until, limit)
+            .await?;
+        Ok(authors)
+    }
+
+    /// Count the commits which landed since the statistics were last read. Failing to do so is
+    /// not an error, as the statistics are only a bit stale then, e.g. if the metadata database
+    /// is read-only.
+    async fn update_commit_stats(&self, bookmark: &BookmarkName) {
+        if let Err(e) = update_commit_stats(
+            &self.ctx,
+            self.blob_repo(),
+            self.commit_stats(),
+            bookmark,
+            COMMIT_STATS_BATCH_SIZE,
+        )
+        .await
+        {
+            warn!(
+                self.ctx.logger(),
+                "Failed to update commit stats of {}: {:?}", bookmark, e
+            );
+        }
+    }
+}
+
+fn check_commit_stats_range(since: NaiveDate, until: NaiveDate) -> Result<(), MononokeError> {
+    if since > until {
+        return Err(MononokeError::InvalidRequest(format!(
+            "invalid date range: {} is after {}",
+            since, until
+        )));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
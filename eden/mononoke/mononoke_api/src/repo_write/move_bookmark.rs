@@ -10,7 +10,7 @@ use std::sync::Arc;
 
 use anyhow::Context;
 use bookmarks::{BookmarkName, BookmarkUpdateReason};
-use bookmarks_movement::{BookmarkUpdatePolicy, BookmarkUpdateTargets};
+use bookmarks_movement::{BookmarkUpdatePolicy, BookmarkUpdateTargets, UpdateBookmarkOp};
 use bytes::Bytes;
 use metaconfig_types::BookmarkAttrs;
 use mononoke_types::ChangesetId;
@@ -20,22 +20,19 @@ use tunables::tunables;
 use crate::errors::MononokeError;
 use crate::repo_write::{PermissionsModel, RepoWriteContext};
 
+pub use bookmarks_movement::{BookmarkMoveCheck, BookmarkMoveVerdict};
+
 impl RepoWriteContext {
-    /// Move a bookmark.
-    pub async fn move_bookmark(
-        &self,
-        bookmark: impl AsRef<str>,
+    /// Build the operation that moves a bookmark, finding out where it
+    /// currently points to if `old_target` is not provided.
+    async fn move_bookmark_op<'a>(
+        &'a self,
+        bookmark: &'a BookmarkName,
         target: ChangesetId,
         old_target: Option<ChangesetId>,
         allow_non_fast_forward: bool,
-        pushvars: Option<&HashMap<String, Bytes>>,
-    ) -> Result<(), MononokeError> {
-        let bookmark = bookmark.as_ref();
-        self.check_method_permitted("move_bookmark")?;
-
-        let bookmark = BookmarkName::new(bookmark)?;
-        let bookmark_attrs = BookmarkAttrs::new(self.config().bookmarks.clone());
-
+        pushvars: Option<&'a HashMap<String, Bytes>>,
+    ) -> Result<UpdateBookmarkOp<'a>, MononokeError> {
         // We need to find out where the bookmark currently points to in order
         // to move it.  Make sure to bypass any out-of-date caches.
         let old_target = match old_target {
@@ -43,7 +40,7 @@ impl RepoWriteContext {
             None => self
                 .blob_repo()
                 .bookmarks()
-                .get(self.ctx().clone(), &bookmark)
+                .get(self.ctx().clone(), bookmark)
                 .await
                 .context("Failed to fetch old bookmark target")?
                 .ok_or_else(|| {
@@ -51,11 +48,8 @@ impl RepoWriteContext {
                 })?,
         };
 
-        let lca_hint: Arc<dyn LeastCommonAncestorsHint> = self.skiplist_index().clone();
-
-        // Move the bookmark.
-        let mut op = bookmarks_movement::UpdateBookmarkOp::new(
-            &bookmark,
+        let mut op = UpdateBookmarkOp::new(
+            bookmark,
             BookmarkUpdateTargets {
                 old: old_target,
                 new: target,
@@ -69,14 +63,45 @@ impl RepoWriteContext {
         )
         .with_pushvars(pushvars);
 
-        if !tunables().get_disable_commit_scribe_logging_scs() {
-            op = op.log_new_public_commits_to_scribe();
-        }
-
         if let PermissionsModel::ServiceIdentity(service_identity) = &self.permissions_model {
             op = op.for_service(service_identity, &self.config().source_control_service);
         }
 
+        Ok(op)
+    }
+
+    /// Move a bookmark.
+    pub async fn move_bookmark(
+        &self,
+        bookmark: impl AsRef<str>,
+        target: ChangesetId,
+        old_target: Option<ChangesetId>,
+        allow_non_fast_forward: bool,
+        pushvars: Option<&HashMap<String, Bytes>>,
+    ) -> Result<(), MononokeError> {
+        let bookmark = bookmark.as_ref();
+        self.check_method_permitted("move_bookmark")?;
+
+        let bookmark = BookmarkName::new(bookmark)?;
+        let bookmark_attrs = BookmarkAttrs::new(self.config().bookmarks.clone());
+
+        let lca_hint: Arc<dyn LeastCommonAncestorsHint> = self.skiplist_index().clone();
+
+        // Move the bookmark.
+        let mut op = self
+            .move_bookmark_op(
+                &bookmark,
+                target,
+                old_target,
+                allow_non_fast_forward,
+                pushvars,
+            )
+            .await?;
+
+        if !tunables().get_disable_commit_scribe_logging_scs() {
+            op = op.log_new_public_commits_to_scribe();
+        }
+
         op.run(
             self.ctx(),
             self.blob_repo(),
@@ -91,4 +116,49 @@ impl RepoWriteContext {
 
         Ok(())
     }
+
+    /// Run the checks that moving a bookmark would be subject to (hooks,
+    /// bookmark config, fast-forward requirements, case conflicts, etc.),
+    /// without moving it.
+    pub async fn validate_move_bookmark(
+        &self,
+        bookmark: impl AsRef<str>,
+        target: ChangesetId,
+        old_target: Option<ChangesetId>,
+        allow_non_fast_forward: bool,
+        pushvars: Option<&HashMap<String, Bytes>>,
+    ) -> Result<BookmarkMoveVerdict, MononokeError> {
+        let bookmark = bookmark.as_ref();
+        self.check_method_permitted("move_bookmark")?;
+
+        let bookmark = BookmarkName::new(bookmark)?;
+        let bookmark_attrs = BookmarkAttrs::new(self.config().bookmarks.clone());
+
+        let lca_hint: Arc<dyn LeastCommonAncestorsHint> = self.skiplist_index().clone();
+
+        let op = self
+            .move_bookmark_op(
+                &bookmark,
+                target,
+                old_target,
+                allow_non_fast_forward,
+                pushvars,
+            )
+            .await?;
+
+        let verdict = op
+            .validate(
+                self.ctx(),
+                self.blob_repo(),
+                &lca_hint,
+                &self.config().infinitepush,
+                &self.config().pushrebase,
+                &bookmark_attrs,
+                self.hook_manager().as_ref(),
+                self.readonly_fetcher(),
+            )
+            .await;
+
+        Ok(verdict)
+    }
 }
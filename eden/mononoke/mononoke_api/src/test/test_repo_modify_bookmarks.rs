@@ -17,6 +17,7 @@ use mononoke_types::ChangesetId;
 use tests_utils::drawdag::create_from_dag;
 
 use crate::repo::{BookmarkFreshness, Repo, RepoContext};
+use crate::repo_write::move_bookmark::BookmarkMoveCheck;
 
 async fn init_repo(ctx: &CoreContext) -> Result<(RepoContext, BTreeMap<String, ChangesetId>)> {
     let blob_repo = blobrepo_factory::new_memblob_empty(None)?;
@@ -140,6 +141,50 @@ async fn move_bookmark(fb: FacebookInit) -> Result<()> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn validate_move_bookmark(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (repo, changesets) = init_repo(&ctx).await?;
+    let repo = repo.write().await?;
+
+    let verdict = repo
+        .validate_move_bookmark("trunk", changesets["E"], None, false, None)
+        .await?;
+    assert!(verdict.is_allowed());
+
+    // Moving to a non-descendant commit is only rejected by the fast-forward
+    // check.
+    let verdict = repo
+        .validate_move_bookmark("trunk", changesets["G"], None, false, None)
+        .await?;
+    assert!(!verdict.is_allowed());
+    let rejected_checks = verdict
+        .rejections()
+        .map(|(check, _)| check)
+        .collect::<Vec<_>>();
+    assert_eq!(rejected_checks, vec![BookmarkMoveCheck::FastForward]);
+    let verdict = repo
+        .validate_move_bookmark("trunk", changesets["G"], None, true, None)
+        .await?;
+    assert!(verdict.is_allowed());
+
+    // The bookmark was not moved.
+    let trunk = repo
+        .resolve_bookmark("trunk", BookmarkFreshness::MostRecent)
+        .await?
+        .expect("bookmark should be set");
+    assert_eq!(trunk.id(), changesets["C"]);
+
+    // Missing bookmarks can't be moved.
+    assert!(
+        repo.validate_move_bookmark("missing", changesets["E"], None, false, None)
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn delete_bookmark(fb: FacebookInit) -> Result<()> {
     let ctx = CoreContext::test_mock(fb);
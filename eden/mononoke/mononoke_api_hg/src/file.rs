@@ -15,7 +15,7 @@ use mercurial_types::{
     envelope::HgFileEnvelope, FileType, HgFileHistoryEntry, HgFileNodeId, HgNodeHash, HgParents,
 };
 use mononoke_api::errors::MononokeError;
-use mononoke_types::{fsnode::FsnodeFile, MPath};
+use mononoke_types::{fsnode::FsnodeFile, hash::Sha256, MPath};
 use remotefilelog::create_getpack_v2_blob;
 use revisionstore_types::Metadata;
 
@@ -118,6 +118,46 @@ impl HgFileContext {
             metadata.sha256,
         ))
     }
+
+    /// Fetches the size and content hashes of this file, for clients which need them but not
+    /// the file's content.
+    ///
+    /// The size and Sha256 are read from the content's metadata. The filestore doesn't store
+    /// Blake3 hashes, so the Blake3 is computed by streaming the content.
+    pub async fn fetch_aux_data(&self) -> Result<HgFileAuxData, MononokeError> {
+        let content_id = self.envelope.content_id();
+        let fetch_key = filestore::FetchKey::Canonical(content_id);
+        let blobstore = self.repo.blob_repo().blobstore();
+        let ctx = self.repo.ctx();
+        let not_found = || {
+            MononokeError::NotAvailable(format!("content not found for content id {}", content_id))
+        };
+        let metadata = filestore::get_metadata(blobstore, ctx, &fetch_key)
+            .await?
+            .ok_or_else(not_found)?;
+        let content = filestore::fetch(blobstore.clone(), ctx.clone(), &fetch_key)
+            .await?
+            .ok_or_else(not_found)?;
+        let hasher = content
+            .try_fold(blake3::Hasher::new(), |mut hasher, chunk| async move {
+                hasher.update(&chunk);
+                Ok(hasher)
+            })
+            .await?;
+        Ok(HgFileAuxData {
+            total_size: metadata.total_size,
+            sha256: metadata.sha256,
+            blake3: *hasher.finalize().as_bytes(),
+        })
+    }
+}
+
+/// The size and content hashes of a file, without copy metadata.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HgFileAuxData {
+    pub total_size: u64,
+    pub sha256: Sha256,
+    pub blake3: [u8; 32],
 }
 
 #[async_trait]
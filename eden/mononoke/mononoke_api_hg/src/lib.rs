@@ -17,6 +17,6 @@ pub mod tree;
 
 pub use data::{HgDataContext, HgDataId};
 pub use ext::RepoContextHgExt;
-pub use file::HgFileContext;
+pub use file::{HgFileAuxData, HgFileContext};
 pub use repo::HgRepoContext;
 pub use tree::HgTreeContext;
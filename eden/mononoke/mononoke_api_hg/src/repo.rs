@@ -6,6 +6,7 @@
  */
 
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use anyhow::{self, format_err, Context};
 use blobrepo::BlobRepo;
@@ -15,6 +16,7 @@ use context::CoreContext;
 use futures::compat::Stream01CompatExt;
 use futures::{future, stream, Stream, StreamExt, TryStream, TryStreamExt};
 use hgproto::GettreepackArgs;
+use manifest::{Entry, ManifestOps};
 use mercurial_types::blobs::RevlogChangeset;
 use mercurial_types::{HgChangesetId, HgFileNodeId, HgManifestId};
 use metaconfig_types::RepoConfig;
@@ -55,6 +57,12 @@ impl HgRepoContext {
         self.repo.config()
     }
 
+    /// The sparse profile that clients should enable by default when
+    /// cloning the repository, if one is configured.
+    pub fn default_sparse_profile(&self) -> Option<&MPath> {
+        self.config().default_sparse_profile.as_ref()
+    }
+
     /// Look up a file in the repo by `HgFileNodeId`.
     pub async fn file(
         &self,
@@ -119,6 +127,77 @@ impl HgRepoContext {
             })
     }
 
+    /// Request the trees under the given path, restricted to the
+    /// directories that lead to, match, or lie beneath one of `prefixes`.
+    ///
+    /// Trees are walked breadth-first from each of the root versions, so
+    /// a client can hydrate a few subtrees of a large directory in one
+    /// request. Unlike `trees_under_path`, this does not support excluding
+    /// trees that are reachable from base versions.
+    pub fn trees_under_path_with_prefixes(
+        &self,
+        path: MononokePath,
+        root_versions: impl IntoIterator<Item = HgManifestId>,
+        depth: Option<usize>,
+        prefixes: Vec<MononokePath>,
+    ) -> impl TryStream<Ok = (HgTreeContext, MononokePath), Error = MononokeError> {
+        let ctx = self.ctx().clone();
+        let blobstore = self.blob_repo().get_blobstore();
+        let rootdir = path.into_mpath();
+        let prefixes = Arc::new(
+            prefixes
+                .into_iter()
+                .map(MononokePath::into_mpath)
+                .collect::<Vec<_>>(),
+        );
+
+        stream::iter(root_versions)
+            .then({
+                let ctx = ctx.clone();
+                let blobstore = blobstore.clone();
+                let rootdir = rootdir.clone();
+                move |mfid| mfid.find_entry(ctx.clone(), blobstore.clone(), rootdir.clone())
+            })
+            .map_ok(move |entry| match entry {
+                Some(Entry::Tree(mfid)) => {
+                    let prefixes = prefixes.clone();
+                    let rootdir = rootdir.clone();
+                    mfid.bfs_tree_entries(ctx.clone(), blobstore.clone(), depth, {
+                        let rootdir = rootdir.clone();
+                        move |path| {
+                            let path = MPath::join_opt(rootdir.as_ref(), path);
+                            prefixes.iter().any(|prefix| {
+                                MPath::is_prefix_of_opt(prefix.as_ref(), path.iter().flatten())
+                                    || MPath::is_prefix_of_opt(
+                                        path.as_ref(),
+                                        prefix.iter().flatten(),
+                                    )
+                            })
+                        }
+                    })
+                    .map_ok(move |(path, mfid)| {
+                        let path = MPath::join_opt(rootdir.as_ref(), path.iter().flatten());
+                        (mfid, path)
+                    })
+                    .left_stream()
+                }
+                _ => stream::empty().right_stream(),
+            })
+            .try_flatten()
+            .map_err(MononokeError::from)
+            .and_then({
+                let repo = self.clone();
+                move |(mfid, path): (HgManifestId, Option<MPath>)| {
+                    let repo = repo.clone();
+                    async move {
+                        let tree = HgTreeContext::new(repo, mfid).await?;
+                        let path = MononokePath::new(path);
+                        Ok((tree, path))
+                    }
+                }
+            })
+    }
+
     /// This provides the same functionality as
     /// `mononoke_api::RepoContext::location_to_changeset_id`. It just wraps the request and
     /// response using Mercurial specific types.
@@ -257,8 +336,47 @@ impl HgRepoContext {
     pub async fn segmented_changelog_clone_data(
         &self,
     ) -> Result<CloneData<HgChangesetId>, MononokeError> {
-        const CHUNK_SIZE: usize = 1000;
         let m_clone_data = self.repo().segmented_changelog_clone_data().await?;
+        self.to_hg_clone_data(m_clone_data).await
+    }
+
+    /// This provides the same functionality as
+    /// `mononoke_api::RepoContext::segmented_changelog_pull_fast_forward_master`. It just
+    /// translates to and from Mercurial types.
+    pub async fn segmented_changelog_pull_fast_forward_master(
+        &self,
+        hg_old_master: HgChangesetId,
+        hg_new_master: HgChangesetId,
+    ) -> Result<CloneData<HgChangesetId>, MononokeError> {
+        let hg_to_bonsai: HashMap<HgChangesetId, ChangesetId> = self
+            .blob_repo()
+            .get_hg_bonsai_mapping(self.ctx().clone(), vec![hg_old_master, hg_new_master])
+            .await?
+            .into_iter()
+            .collect();
+        let to_bonsai = |hg_id: HgChangesetId| {
+            hg_to_bonsai.get(&hg_id).cloned().ok_or_else(|| {
+                MononokeError::InvalidRequest(format!(
+                    "failed to find bonsai equivalent for {}",
+                    hg_id
+                ))
+            })
+        };
+        let m_pull_data = self
+            .repo()
+            .segmented_changelog_pull_fast_forward_master(
+                to_bonsai(hg_old_master)?,
+                to_bonsai(hg_new_master)?,
+            )
+            .await?;
+        self.to_hg_clone_data(m_pull_data).await
+    }
+
+    async fn to_hg_clone_data(
+        &self,
+        m_clone_data: CloneData<ChangesetId>,
+    ) -> Result<CloneData<HgChangesetId>, MononokeError> {
+        const CHUNK_SIZE: usize = 1000;
         let idmap_list = m_clone_data.idmap.into_iter().collect::<Vec<_>>();
         let mut hg_idmap = HashMap::new();
         for chunk in idmap_list.chunks(CHUNK_SIZE) {
@@ -348,7 +466,7 @@ mod tests {
     use super::*;
 
     use std::collections::BTreeSet;
-    use std::sync::Arc;
+    use std::convert::TryFrom;
 
     use anyhow::Error;
     use blobstore::Loadable;
@@ -422,6 +540,49 @@ mod tests {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_trees_under_path_with_prefixes(fb: FacebookInit) -> Result<(), MononokeError> {
+        let ctx = CoreContext::test_mock(fb);
+        let blob_repo = blobrepo_factory::new_memblob_empty(None)?;
+
+        let commit = CreateCommitContext::new_root(&ctx, &blob_repo)
+            .add_file("dir1/a", "1")
+            .add_file("dir2/b", "1")
+            .add_file("dir3/a/b/c", "1")
+            .add_file("dir3/d/e", "1")
+            .commit()
+            .await?;
+
+        let root_mfid = root_manifest_id(ctx.clone(), &blob_repo, commit).await?;
+
+        let repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+        let repo_ctx = RepoContext::new(ctx, Arc::new(repo)).await?;
+        let hg = repo_ctx.hg();
+
+        let trees = hg
+            .trees_under_path_with_prefixes(
+                MononokePath::try_from("dir3")?,
+                vec![root_mfid],
+                None,
+                vec![MononokePath::try_from("dir3/a")?],
+            )
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let paths = trees
+            .into_iter()
+            .map(|(_, path)| format!("{}", path))
+            .collect::<BTreeSet<_>>();
+        let expected = vec!["dir3", "dir3/a", "dir3/a/b"]
+            .into_iter()
+            .map(ToString::to_string)
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(paths, expected);
+
+        Ok(())
+    }
+
     /// Get the HgManifestId of the root tree manifest for the given commit.
     async fn root_manifest_id(
         ctx: CoreContext,
@@ -45,7 +45,9 @@ pub use file_change::{FileChange, FileType};
 pub use file_contents::{ChunkedFileContents, ContentChunkPointer, FileContents};
 pub use generation::{Generation, FIRST_GENERATION};
 pub use globalrev::Globalrev;
-pub use path::{check_case_conflicts, MPath, MPathElement, MPathHash, PrefixTrie, RepoPath};
+pub use path::{
+    check_case_conflicts, find_case_conflicts, MPath, MPathElement, MPathHash, PrefixTrie, RepoPath,
+};
 pub use rawbundle2::RawBundle2;
 pub use repo::{RepositoryId, REPO_PREFIX_REGEX};
 pub use svnrev::Svnrev;
@@ -1220,6 +1220,20 @@ where
     None
 }
 
+/// Returns all the path pairs that would introduce a case-conflict, in the same order as
+/// `check_case_conflicts`. Paths which conflict are not added, so each pair is reported against
+/// the first of the conflicting paths.
+pub fn find_case_conflicts<P, I>(iter: I) -> Vec<(MPath, MPath)>
+where
+    P: CaseConflictTrieUpdate,
+    I: IntoIterator<Item = P>,
+{
+    let mut trie = CaseConflictTrie::new();
+    iter.into_iter()
+        .filter_map(|update| update.apply(&mut trie))
+        .collect()
+}
+
 // TODO: Do we need this? Why?
 impl<P> FromIterator<P> for CaseConflictTrie
 where
@@ -1517,9 +1531,21 @@ mod test {
             Some((m("a/b"), m("a/B/d"))),
         );
         assert_eq!(
-            check_case_conflicts(paths.into_iter()), // works from MPath
+            check_case_conflicts(paths.clone().into_iter()), // works from MPath
             Some((m("a/b"), m("a/B/d"))),
         );
+
+        let paths = paths
+            .into_iter()
+            .chain(vec![m("A/e"), m("a/D"), m("a/b/d")]);
+        assert_eq!(
+            find_case_conflicts(paths),
+            vec![
+                (m("a/b"), m("a/B/d")),
+                (m("a"), m("A/e")),
+                (m("a/d"), m("a/D")),
+            ],
+        );
     }
 
     fn check_pcf_paths<I, T>(paths: I) -> Result<()>
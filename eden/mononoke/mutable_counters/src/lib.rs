@@ -13,13 +13,29 @@
 /// track of. Storing all of them in the same table makes maintenance easier and safer,
 /// for example, we can have conditional updates.
 use anyhow::Error;
+use cloned::cloned;
 use context::{CoreContext, PerfCounterType};
-use futures_ext::{BoxFuture, FutureExt};
+use futures::{
+    compat::Future01CompatExt,
+    stream::{self, StreamExt, TryStreamExt},
+};
+use futures_ext::{BoxFuture, BoxStream, FutureExt, StreamExt as _};
 use futures_old::{future, Future};
 use mononoke_types::RepositoryId;
 use sql::{queries, Connection, Transaction as SqlTransaction};
 use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
 use sql_ext::{SqlConnections, TransactionResult};
+use std::time::Duration;
+
+/// Outcome of a compare-and-swap on a counter
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CasOutcome {
+    /// The counter had the expected value and now has the new one
+    Swapped,
+    /// The counter did not have the expected value and was left alone. `current` is the value
+    /// read right after the failed swap, so callers can retry without reading it again.
+    Conflict { current: Option<i64> },
+}
 
 pub trait MutableCounters: Send + Sync + 'static {
     /// Get the current value of the counter
@@ -54,6 +70,29 @@ pub trait MutableCounters: Send + Sync + 'static {
         ctx: CoreContext,
         repoid: RepositoryId,
     ) -> BoxFuture<Vec<(String, i64)>, Error>;
+
+    /// Atomically set the counter to `new_value` if it currently has the value `expected`.
+    /// Unlike `set_counter`, an `expected` of None means that the counter must not exist yet,
+    /// so that two jobs creating the same counter cannot both succeed.
+    fn compare_and_swap_counter(
+        &self,
+        ctx: CoreContext,
+        repoid: RepositoryId,
+        name: &str,
+        expected: Option<i64>,
+        new_value: i64,
+    ) -> BoxFuture<CasOutcome, Error>;
+
+    /// Watch the counter for changes. The stream yields the current value first, then every
+    /// new value seen when polling every `poll_interval`. Changes that are undone between
+    /// two polls are not seen.
+    fn watch_counter(
+        &self,
+        ctx: CoreContext,
+        repoid: RepositoryId,
+        name: &str,
+        poll_interval: Duration,
+    ) -> BoxStream<Option<i64>, Error>;
 }
 
 queries! {
@@ -83,6 +122,18 @@ queries! {
         )
     }
 
+    write InsertCounter(
+        repo_id: RepositoryId, name: &str, value: i64
+    ) {
+        none,
+        mysql(
+            "INSERT IGNORE INTO mutable_counters (repo_id, name, value) VALUES ({repo_id}, {name}, {value})"
+        )
+        sqlite(
+            "INSERT OR IGNORE INTO mutable_counters (repo_id, name, value) VALUES ({repo_id}, CAST({name} AS TEXT), {value})"
+        )
+    }
+
     read GetCounter(repo_id: RepositoryId, name: &str) -> (i64) {
         mysql(
             "SELECT value FROM mutable_counters WHERE repo_id = {repo_id} and name = {name}"
@@ -179,6 +230,78 @@ impl MutableCounters for SqlMutableCounters {
             .map(|counters| counters.into_iter().collect())
             .boxify()
     }
+
+    fn compare_and_swap_counter(
+        &self,
+        ctx: CoreContext,
+        repoid: RepositoryId,
+        name: &str,
+        expected: Option<i64>,
+        new_value: i64,
+    ) -> BoxFuture<CasOutcome, Error> {
+        let this = self.clone();
+        let name = name.to_string();
+        self.write_connection
+            .start_transaction()
+            .and_then({
+                cloned!(ctx, name);
+                move |txn| {
+                    Self::compare_and_swap_counter_on_txn(
+                        ctx, repoid, &name, expected, new_value, txn,
+                    )
+                }
+            })
+            .and_then(move |txn_result| match txn_result {
+                TransactionResult::Succeeded(txn) => {
+                    txn.commit().map(|()| CasOutcome::Swapped).left_future()
+                }
+                TransactionResult::Failed => this
+                    .get_counter(ctx, repoid, &name)
+                    .map(move |current| {
+                        // MySQL does not count rows whose value is unchanged as affected, so
+                        // swapping a value for itself looks like a failure.
+                        if expected == Some(new_value) && current == expected {
+                            CasOutcome::Swapped
+                        } else {
+                            CasOutcome::Conflict { current }
+                        }
+                    })
+                    .right_future(),
+            })
+            .boxify()
+    }
+
+    fn watch_counter(
+        &self,
+        ctx: CoreContext,
+        repoid: RepositoryId,
+        name: &str,
+        poll_interval: Duration,
+    ) -> BoxStream<Option<i64>, Error> {
+        let this = self.clone();
+        let name = name.to_string();
+        stream::try_unfold(None, move |last: Option<Option<i64>>| {
+            cloned!(this, ctx, name);
+            async move {
+                if last.is_some() {
+                    tokio::time::delay_for(poll_interval).await;
+                }
+                loop {
+                    let current = this
+                        .get_counter(ctx.clone(), repoid, &name)
+                        .compat()
+                        .await?;
+                    if last != Some(current) {
+                        return Ok(Some((current, Some(current))));
+                    }
+                    tokio::time::delay_for(poll_interval).await;
+                }
+            }
+        })
+        .boxed()
+        .compat()
+        .boxify()
+    }
 }
 
 impl SqlMutableCounters {
@@ -213,4 +336,32 @@ impl SqlMutableCounters {
         })
         .boxify()
     }
+
+    /// Like `set_counter_on_txn`, but an `expected` value of None only succeeds if the counter
+    /// does not exist yet, instead of setting it unconditionally.
+    pub fn compare_and_swap_counter_on_txn(
+        ctx: CoreContext,
+        repoid: RepositoryId,
+        name: &str,
+        expected: Option<i64>,
+        new_value: i64,
+        txn: SqlTransaction,
+    ) -> BoxFuture<TransactionResult, Error> {
+        match expected {
+            Some(_) => Self::set_counter_on_txn(ctx, repoid, name, new_value, expected, txn),
+            None => {
+                ctx.perf_counters()
+                    .increment_counter(PerfCounterType::SqlWrites);
+                InsertCounter::query_with_transaction(txn, &repoid, &name, &new_value)
+                    .map(|(txn, result)| {
+                        if result.affected_rows() >= 1 {
+                            TransactionResult::Succeeded(txn)
+                        } else {
+                            TransactionResult::Failed
+                        }
+                    })
+                    .boxify()
+            }
+        }
+    }
 }
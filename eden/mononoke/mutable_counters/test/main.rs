@@ -10,11 +10,13 @@
 use anyhow::{Error, Result};
 use context::CoreContext;
 use fbinit::FacebookInit;
-use futures::compat::Future01CompatExt;
+use futures::compat::{Future01CompatExt, Stream01CompatExt};
+use futures::stream::TryStreamExt;
 use futures_old::Future;
 use mononoke_types_mocks::repo::{REPO_ONE, REPO_ZERO};
-use mutable_counters::{MutableCounters, SqlMutableCounters};
+use mutable_counters::{CasOutcome, MutableCounters, SqlMutableCounters};
 use sql_construct::SqlConstruct;
+use std::time::Duration;
 
 fn create_db() -> SqlMutableCounters {
     SqlMutableCounters::with_sqlite_in_memory().unwrap()
@@ -166,3 +168,103 @@ fn test_counter_conditional_update(fb: FacebookInit) {
         None
     );
 }
+
+#[fbinit::test]
+fn test_counter_compare_and_swap(fb: FacebookInit) {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let ctx = CoreContext::test_mock(fb);
+    let mutable_counters = create_db();
+
+    let counter = "counter".to_string();
+
+    // Only one of two jobs creating the counter succeeds
+    assert_eq!(
+        run_future(
+            &mut runtime,
+            mutable_counters.compare_and_swap_counter(ctx.clone(), REPO_ZERO, &counter, None, 1),
+        )
+        .unwrap(),
+        CasOutcome::Swapped
+    );
+    assert_eq!(
+        run_future(
+            &mut runtime,
+            mutable_counters.compare_and_swap_counter(ctx.clone(), REPO_ZERO, &counter, None, 2),
+        )
+        .unwrap(),
+        CasOutcome::Conflict { current: Some(1) }
+    );
+
+    assert_eq!(
+        run_future(
+            &mut runtime,
+            mutable_counters.compare_and_swap_counter(ctx.clone(), REPO_ZERO, &counter, Some(1), 3),
+        )
+        .unwrap(),
+        CasOutcome::Swapped
+    );
+    assert_eq!(
+        run_future(
+            &mut runtime,
+            mutable_counters.compare_and_swap_counter(ctx.clone(), REPO_ZERO, &counter, Some(1), 4),
+        )
+        .unwrap(),
+        CasOutcome::Conflict { current: Some(3) }
+    );
+
+    // Swapping a value for itself succeeds
+    assert_eq!(
+        run_future(
+            &mut runtime,
+            mutable_counters.compare_and_swap_counter(ctx.clone(), REPO_ZERO, &counter, Some(3), 3),
+        )
+        .unwrap(),
+        CasOutcome::Swapped
+    );
+
+    // The same counter in another repo is separate
+    assert_eq!(
+        run_future(
+            &mut runtime,
+            mutable_counters.compare_and_swap_counter(ctx.clone(), REPO_ONE, &counter, Some(3), 5),
+        )
+        .unwrap(),
+        CasOutcome::Conflict { current: None }
+    );
+    assert_eq!(
+        run_future(
+            &mut runtime,
+            mutable_counters.get_counter(ctx.clone(), REPO_ZERO, &counter),
+        )
+        .unwrap(),
+        Some(3)
+    );
+}
+
+#[fbinit::test]
+async fn test_counter_watch(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mutable_counters = create_db();
+
+    let counter = "counter".to_string();
+
+    let mut watch = mutable_counters
+        .watch_counter(ctx.clone(), REPO_ZERO, &counter, Duration::from_millis(1))
+        .compat();
+    assert_eq!(watch.try_next().await?, Some(None));
+
+    mutable_counters
+        .set_counter(ctx.clone(), REPO_ZERO, &counter, 1, None)
+        .compat()
+        .await?;
+    assert_eq!(watch.try_next().await?, Some(Some(1)));
+
+    mutable_counters
+        .set_counter(ctx.clone(), REPO_ZERO, &counter, 2, Some(1))
+        .compat()
+        .await?;
+    assert_eq!(watch.try_next().await?, Some(Some(2)));
+
+    Ok(())
+}
@@ -0,0 +1,341 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Batched pushrebase. Pushes queued for the same bookmark which change disjoint sets of paths
+//! can't conflict with each other, so they are rebased on top of each other and landed with a
+//! single bookmark move, instead of racing each other for the bookmark one at a time.
+//!
+//!  ```text
+//!     O  <- `onto` bookmark after the batch, head of the rebased push B
+//!     |
+//!     O  <- rebased push A
+//!     |
+//!     O  <- `onto` bookmark before the batch
+//!     |
+//!     O  O <- push B
+//!     | /
+//!     O  O <- push A
+//!     | /
+//!     O
+//!  ```
+
+use crate::{
+    check_filenodes_backfilled, create_rebased_changesets,
+    fetch_bonsai_range_ancestor_not_included, find_changed_files, find_closest_root,
+    find_only_head_or_fail, find_roots, get_bookmark_value, intersect_changed_files,
+    maybe_validate_commit, rebased_changesets_into_pairs, should_fail_pushrebase,
+    try_move_bookmark, PushrebaseCommitHook, PushrebaseDistance, PushrebaseError, PushrebaseHook,
+    PushrebaseInternalError, PushrebaseOutcome, PushrebaseRetryNum, PushrebaseTransactionHook,
+    RebasedChangesets, MAX_REBASE_ATTEMPTS,
+};
+use blobrepo::BlobRepo;
+use bookmarks::BookmarkName;
+use context::CoreContext;
+use futures::future::{try_join, try_join_all, TryFutureExt};
+use mercurial_types::MPath;
+use metaconfig_types::PushrebaseFlags;
+use mononoke_types::{check_case_conflicts, BonsaiChangeset, ChangesetId};
+use std::collections::{HashMap, HashSet};
+
+/// Outcome of one of the pushes of a batch
+#[derive(Debug)]
+pub enum PushrebaseBatchResult {
+    /// The push landed with the rest of the batch. The head of the outcome is the rebased head of
+    /// this push, which the bookmark is past if later pushes of the batch landed as well.
+    Landed(PushrebaseOutcome),
+    /// The push changes paths which an earlier push of the batch changes too, so it has to be
+    /// pushrebased again once the batch has landed
+    Deferred,
+    /// The push can't be landed, e.g. because it conflicts with the commits on the bookmark
+    Failed(PushrebaseError),
+}
+
+struct BatchedPush {
+    /// Index of the push in the batch
+    index: usize,
+    head: ChangesetId,
+    root: ChangesetId,
+    changed_files: Vec<MPath>,
+    bcs: Vec<BonsaiChangeset>,
+    latest_rebase_attempt: ChangesetId,
+    pushrebase_distance: PushrebaseDistance,
+}
+
+struct RebasedPush {
+    index: usize,
+    head: ChangesetId,
+    rebased_changesets: RebasedChangesets,
+    hooks: Vec<Box<dyn PushrebaseTransactionHook>>,
+    pushrebase_distance: PushrebaseDistance,
+}
+
+/// Does a pushrebase of each of the pushed sets in `pushes` onto `onto_bookmark`, in order, and
+/// moves the bookmark once for all of them.
+///
+/// Pushes which change paths also changed by an earlier push are deferred, and pushes which fail
+/// don't prevent the others from landing. The commits from the pushed sets should already be
+/// committed to the blobrepo. Batched bookmark moves have no bundle replay data, as it is recorded
+/// for a single push.
+pub async fn do_pushrebase_bonsai_batch(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    config: &PushrebaseFlags,
+    onto_bookmark: &BookmarkName,
+    pushes: &[HashSet<BonsaiChangeset>],
+    prepushrebase_hooks: &[Box<dyn PushrebaseHook>],
+) -> Result<Vec<PushrebaseBatchResult>, PushrebaseError> {
+    let mut results: Vec<Option<PushrebaseBatchResult>> = pushes.iter().map(|_| None).collect();
+
+    let mut batch = vec![];
+    let mut batch_changed_files = vec![];
+    for (index, pushed) in pushes.iter().enumerate() {
+        match prepare_push(ctx, repo, config, onto_bookmark, index, pushed).await {
+            Ok(push) => {
+                // TODO: Avoid these clones
+                match intersect_changed_files(
+                    batch_changed_files.clone(),
+                    push.changed_files.clone(),
+                ) {
+                    Ok(()) => {
+                        batch_changed_files.extend(push.changed_files.iter().cloned());
+                        batch.push(push);
+                    }
+                    Err(_) => results[index] = Some(PushrebaseBatchResult::Deferred),
+                }
+            }
+            Err(err) => results[index] = Some(PushrebaseBatchResult::Failed(err)),
+        }
+    }
+
+    for retry_num in 0..MAX_REBASE_ATTEMPTS {
+        if batch.is_empty() {
+            break;
+        }
+        let retry_num = PushrebaseRetryNum(retry_num);
+        let bookmark_val = get_bookmark_value(&ctx, &repo, onto_bookmark).await?;
+
+        let mut checked: Vec<BatchedPush> = vec![];
+        for mut push in batch {
+            match check_server_changes(ctx, repo, config, &push, bookmark_val, &checked).await {
+                Ok(distance) => {
+                    push.pushrebase_distance = push.pushrebase_distance.add(distance);
+                    push.latest_rebase_attempt = bookmark_val.unwrap_or(push.root);
+                    checked.push(push);
+                }
+                Err(err) => results[push.index] = Some(PushrebaseBatchResult::Failed(err)),
+            }
+        }
+
+        // Each push is rebased onto the rebased head of the previous one
+        let mut onto = bookmark_val;
+        let mut rebased: Vec<RebasedPush> = vec![];
+        let mut batch_distance = 0;
+        batch = vec![];
+        for push in checked {
+            let res = rebase_push(
+                ctx,
+                repo,
+                config,
+                &push,
+                onto,
+                prepushrebase_hooks,
+                retry_num,
+            )
+            .await;
+            match res {
+                Ok((head, rebased_changesets, hooks)) => {
+                    onto = Some(head);
+                    rebased.push(RebasedPush {
+                        index: push.index,
+                        head,
+                        rebased_changesets,
+                        hooks,
+                        pushrebase_distance: push.pushrebase_distance.add(batch_distance),
+                    });
+                    batch_distance += push.bcs.len();
+                    batch.push(push);
+                }
+                Err(err) => results[push.index] = Some(PushrebaseBatchResult::Failed(err)),
+            }
+        }
+
+        let new_head = match rebased.last() {
+            Some(push) => push.head,
+            None => break,
+        };
+
+        let mut all_rebased_changesets = HashMap::new();
+        let mut hooks = vec![];
+        for push in rebased.iter_mut() {
+            all_rebased_changesets.extend(push.rebased_changesets.clone());
+            hooks.append(&mut push.hooks);
+        }
+
+        let moved = try_move_bookmark(
+            ctx.clone(),
+            &repo,
+            &onto_bookmark,
+            bookmark_val,
+            new_head,
+            None,
+            all_rebased_changesets,
+            hooks,
+        )
+        .await?;
+
+        if moved.is_some() {
+            for push in rebased {
+                results[push.index] = Some(PushrebaseBatchResult::Landed(PushrebaseOutcome {
+                    head: push.head,
+                    retry_num,
+                    rebased_changesets: rebased_changesets_into_pairs(push.rebased_changesets),
+                    pushrebase_distance: push.pushrebase_distance,
+                }));
+            }
+            batch = vec![];
+            break;
+        }
+    }
+
+    for push in batch {
+        results[push.index] = Some(PushrebaseBatchResult::Failed(
+            PushrebaseInternalError::TooManyRebaseAttempts.into(),
+        ));
+    }
+
+    Ok(results
+        .into_iter()
+        .map(|result| result.expect("every push of the batch has a result"))
+        .collect())
+}
+
+async fn prepare_push(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    config: &PushrebaseFlags,
+    onto_bookmark: &BookmarkName,
+    index: usize,
+    pushed: &HashSet<BonsaiChangeset>,
+) -> Result<BatchedPush, PushrebaseError> {
+    let head = find_only_head_or_fail(&pushed)?;
+    let roots = find_roots(&pushed);
+
+    let root = find_closest_root(&ctx, &repo, &config, onto_bookmark, &roots).await?;
+
+    let (changed_files, bcs) = try_join(
+        find_changed_files(&ctx, &repo, root, head),
+        fetch_bonsai_range_ancestor_not_included(ctx, &repo, root, head),
+    )
+    .await?;
+
+    check_filenodes_backfilled(&ctx, &repo, &head, config.not_generated_filenodes_limit).await?;
+
+    Ok(BatchedPush {
+        index,
+        head,
+        root,
+        changed_files,
+        bcs,
+        latest_rebase_attempt: root,
+        pushrebase_distance: PushrebaseDistance(0),
+    })
+}
+
+/// Checks that `push` doesn't conflict with the commits which landed on the bookmark since it was
+/// last checked, and returns the number of these commits
+async fn check_server_changes(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    config: &PushrebaseFlags,
+    push: &BatchedPush,
+    bookmark_val: Option<ChangesetId>,
+    earlier_pushes: &[BatchedPush],
+) -> Result<usize, PushrebaseError> {
+    let server_head = bookmark_val.unwrap_or(push.root);
+    let server_bcs = fetch_bonsai_range_ancestor_not_included(
+        &ctx,
+        &repo,
+        push.latest_rebase_attempt,
+        server_head,
+    )
+    .await?;
+
+    for bcs in server_bcs.iter() {
+        if should_fail_pushrebase(bcs) {
+            return Err(PushrebaseError::ForceFailPushrebase(bcs.get_changeset_id()));
+        }
+    }
+
+    if config.casefolding_check {
+        // The earlier pushes of the batch will be between the server commits and this push
+        let earlier_bcs = earlier_pushes.iter().flat_map(|push| push.bcs.iter().rev());
+        let conflict = check_case_conflicts(
+            server_bcs
+                .iter()
+                .rev()
+                .chain(earlier_bcs)
+                .chain(push.bcs.iter().rev()),
+        );
+        if let Some(conflict) = conflict {
+            return Err(PushrebaseError::PotentialCaseConflict(conflict.1));
+        }
+    }
+
+    let server_cf =
+        find_changed_files(&ctx, &repo, push.latest_rebase_attempt, server_head).await?;
+    intersect_changed_files(server_cf, push.changed_files.clone())?;
+
+    Ok(server_bcs.len())
+}
+
+async fn rebase_push(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    config: &PushrebaseFlags,
+    push: &BatchedPush,
+    onto: Option<ChangesetId>,
+    prepushrebase_hooks: &[Box<dyn PushrebaseHook>],
+    retry_num: PushrebaseRetryNum,
+) -> Result<
+    (
+        ChangesetId,
+        RebasedChangesets,
+        Vec<Box<dyn PushrebaseTransactionHook>>,
+    ),
+    PushrebaseError,
+> {
+    let mut hooks: Vec<Box<dyn PushrebaseCommitHook>> = try_join_all(
+        prepushrebase_hooks
+            .iter()
+            .map(|h| h.prepushrebase().map_err(PushrebaseError::from)),
+    )
+    .await?;
+
+    let (new_head, rebased_changesets) = create_rebased_changesets(
+        &ctx,
+        &repo,
+        config,
+        push.root,
+        push.head,
+        onto.unwrap_or(push.root),
+        &mut hooks,
+    )
+    .await?;
+
+    for (old_id, (new_id, _)) in &rebased_changesets {
+        maybe_validate_commit(ctx, repo, old_id, new_id, retry_num).await?;
+    }
+
+    let hooks = try_join_all(
+        hooks
+            .into_iter()
+            .map(|h| h.into_transaction_hook(ctx, &rebased_changesets)),
+    )
+    .await?;
+
+    Ok((new_head, rebased_changesets, hooks))
+}
@@ -81,8 +81,10 @@ use std::sync::Arc;
 use thiserror::Error;
 use tunables::tunables;
 
+pub use batch::{do_pushrebase_bonsai_batch, PushrebaseBatchResult};
 pub use hook::{PushrebaseCommitHook, PushrebaseHook, PushrebaseTransactionHook};
 
+mod batch;
 mod hook;
 
 const MAX_REBASE_ATTEMPTS: usize = 100;
@@ -3434,6 +3436,69 @@ mod tests {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_pushrebase_batch(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = linear::getrepo(fb).await;
+        let book = master_bookmark();
+        bookmark(&ctx, &repo, book.clone())
+            .set_to("a5ffa77602a066db7d5cfb9fb5823a0895717c5a")
+            .await?;
+        let old_master = resolve_cs_id(&ctx, &repo, "master").await?;
+        let log_entries = repo
+            .count_further_bookmark_log_entries(ctx.clone(), 0, None)
+            .await?;
+
+        // Bottom commit of the repo
+        let root = "2d7d4ba9ce0a6ffd222de7785b249ead9c51c536";
+        let mut pushes = vec![];
+        for (path, content) in &[
+            ("batch1", "content1"),
+            ("batch2", "content2"),
+            // Changed by the first push as well
+            ("batch1", "other content"),
+            // Changed by the commits on master
+            ("files", "content"),
+        ] {
+            let bcs_id = CreateCommitContext::new(&ctx, &repo, vec![root])
+                .add_file(*path, *content)
+                .commit()
+                .await?;
+            pushes.push(hashset![bcs_id.load(&ctx, repo.blobstore()).await?]);
+        }
+
+        let results =
+            do_pushrebase_bonsai_batch(&ctx, &repo, &Default::default(), &book, &pushes, &[])
+                .await?;
+        assert_eq!(results.len(), 4);
+        assert!(matches!(results[2], PushrebaseBatchResult::Deferred));
+        assert!(matches!(
+            results[3],
+            PushrebaseBatchResult::Failed(PushrebaseError::Conflicts(_))
+        ));
+        let landed = results[..2]
+            .iter()
+            .map(|result| match result {
+                PushrebaseBatchResult::Landed(outcome) => Ok(outcome.head),
+                _ => Err(format_err!("push should have landed: {:?}", result)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Both pushes landed with a single bookmark move, one on top of the other
+        assert_eq!(resolve_cs_id(&ctx, &repo, "master").await?, landed[1]);
+        let second_bcs = landed[1].load(&ctx, repo.blobstore()).await?;
+        assert_eq!(second_bcs.parents().collect::<Vec<_>>(), vec![landed[0]]);
+        let first_bcs = landed[0].load(&ctx, repo.blobstore()).await?;
+        assert_eq!(first_bcs.parents().collect::<Vec<_>>(), vec![old_master]);
+        assert_eq!(
+            repo.count_further_bookmark_log_entries(ctx.clone(), 0, None)
+                .await?,
+            log_entries + 1
+        );
+
+        Ok(())
+    }
+
     async fn ensure_content(
         ctx: &CoreContext,
         hg_cs_id: HgChangesetId,
@@ -43,6 +43,9 @@ use warm_bookmarks_cache::WarmBookmarksCache;
 
 #[cfg(fbcode_build)]
 mod facebook;
+mod read_quota;
+
+pub use read_quota::{OverQuota, ReadQuotaTracker};
 
 #[derive(Clone)]
 pub struct SqlStreamingCloneConfig {
@@ -62,6 +65,8 @@ pub struct MononokeRepo {
     // Reverse filler queue for recording accepted infinitepush bundles
     // This field is `None` if we don't want recording to happen
     maybe_reverse_filler_queue: Option<Arc<dyn ReverseFillerQueue>>,
+    // Usage of each client, if reads are limited by a quota
+    read_quota: Option<Arc<ReadQuotaTracker>>,
 }
 
 impl MononokeRepo {
@@ -179,6 +184,12 @@ impl MononokeRepo {
         // TODO: Update Metaconfig so we just have this in config:
         let bookmark_attrs = BookmarkAttrs::new(repo.config().bookmarks.clone());
 
+        let read_quota = repo
+            .config()
+            .repo_client_knobs
+            .read_quota
+            .map(|quota| Arc::new(ReadQuotaTracker::new(quota)));
+
         Ok(Self {
             repo,
             streaming_clone,
@@ -186,6 +197,7 @@ impl MononokeRepo {
             maybe_reverse_filler_queue,
             lfs_rolled_out_hostnames,
             bookmark_attrs,
+            read_quota,
         })
     }
 
@@ -296,6 +308,10 @@ impl MononokeRepo {
     pub fn live_commit_sync_config(&self) -> Arc<dyn LiveCommitSyncConfig> {
         self.repo.live_commit_sync_config()
     }
+
+    pub fn read_quota(&self) -> Option<&Arc<ReadQuotaTracker>> {
+        self.read_quota.as_ref()
+    }
 }
 
 async fn streaming_clone(
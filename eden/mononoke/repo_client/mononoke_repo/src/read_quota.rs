@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use metaconfig_types::ReadQuota;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Above this many tracked clients, clients whose window has ended are dropped
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+struct ClientUsage {
+    window_start: Instant,
+    bytes: u64,
+    objects: u64,
+}
+
+/// Usage of a client which went over its read quota
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OverQuota {
+    pub bytes: u64,
+    pub objects: u64,
+    /// How long until the client's window ends and its usage is reset
+    pub retry_after: Duration,
+}
+
+/// Tracks how much each client identity has read from a repo in the current
+/// quota window. This is shared by all the connections to the repo.
+pub struct ReadQuotaTracker {
+    quota: ReadQuota,
+    usage: Mutex<HashMap<String, ClientUsage>>,
+}
+
+impl ReadQuotaTracker {
+    pub fn new(quota: ReadQuota) -> Self {
+        Self {
+            quota,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn quota(&self) -> &ReadQuota {
+        &self.quota
+    }
+
+    /// Add bytes and objects sent to a client to its usage
+    pub fn record(&self, client: &str, bytes: u64, objects: u64) {
+        let window = self.quota.window;
+        let mut usage = self.usage.lock().expect("lock poisoned");
+        if !usage.contains_key(client) && usage.len() >= MAX_TRACKED_CLIENTS {
+            usage.retain(|_, client_usage| client_usage.window_start.elapsed() < window);
+        }
+
+        let client_usage = usage
+            .entry(client.to_string())
+            .or_insert_with(|| ClientUsage {
+                window_start: Instant::now(),
+                bytes: 0,
+                objects: 0,
+            });
+        if client_usage.window_start.elapsed() >= window {
+            *client_usage = ClientUsage {
+                window_start: Instant::now(),
+                bytes: 0,
+                objects: 0,
+            };
+        }
+        client_usage.bytes += bytes;
+        client_usage.objects += objects;
+    }
+
+    /// Check whether a client has gone over its quota in the current window
+    pub fn check(&self, client: &str) -> Option<OverQuota> {
+        let usage = self.usage.lock().expect("lock poisoned");
+        let client_usage = usage.get(client)?;
+        let elapsed = client_usage.window_start.elapsed();
+        if elapsed >= self.quota.window {
+            return None;
+        }
+
+        let over_bytes = self
+            .quota
+            .max_bytes
+            .map_or(false, |max_bytes| client_usage.bytes > max_bytes);
+        let over_objects = self
+            .quota
+            .max_objects
+            .map_or(false, |max_objects| client_usage.objects > max_objects);
+        if over_bytes || over_objects {
+            Some(OverQuota {
+                bytes: client_usage.bytes,
+                objects: client_usage.objects,
+                retry_after: self.quota.window - elapsed,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_quota() {
+        let tracker = ReadQuotaTracker::new(ReadQuota {
+            max_bytes: Some(100),
+            max_objects: Some(10),
+            window: Duration::from_secs(3600),
+            enforced: true,
+        });
+
+        assert_eq!(tracker.check("alice"), None);
+        tracker.record("alice", 100, 10);
+        assert_eq!(tracker.check("alice"), None);
+
+        tracker.record("alice", 1, 0);
+        let over = tracker.check("alice").expect("alice should be over quota");
+        assert_eq!((over.bytes, over.objects), (101, 10));
+        assert!(over.retry_after <= Duration::from_secs(3600));
+
+        // Other clients are not affected
+        tracker.record("bob", 0, 11);
+        assert!(tracker.check("bob").is_some());
+        assert_eq!(tracker.check("carol"), None);
+    }
+
+    #[test]
+    fn test_read_quota_window() {
+        let tracker = ReadQuotaTracker::new(ReadQuota {
+            max_bytes: Some(100),
+            max_objects: None,
+            window: Duration::from_secs(0),
+            enforced: true,
+        });
+
+        // Usage is reset once the window ends
+        tracker.record("alice", 1000, 0);
+        assert_eq!(tracker.check("alice"), None);
+    }
+}
@@ -416,4 +416,34 @@ mod test {
             assert_eq!(fetcher.readonly().compat().await.unwrap(), ReadWrite);
         });
     }
+
+    #[test]
+    fn test_lock_and_unlock() {
+        async_unit::tokio_unit_test(async move {
+            let sql_repo_read_write_status =
+                SqlRepoReadWriteStatus::with_sqlite_in_memory().unwrap();
+            let fetcher = RepoReadWriteFetcher::new(
+                Some(sql_repo_read_write_status),
+                ReadWrite,
+                HgsqlName("repo".to_string()),
+            );
+
+            fetcher
+                .set_read_only(&"migration in progress".to_string())
+                .compat()
+                .await
+                .unwrap();
+            assert_eq!(
+                fetcher.readonly().compat().await.unwrap(),
+                ReadOnly("migration in progress".to_string())
+            );
+
+            fetcher
+                .set_mononoke_read_write(&"migration done".to_string())
+                .compat()
+                .await
+                .unwrap();
+            assert_eq!(fetcher.readonly().compat().await.unwrap(), ReadWrite);
+        });
+    }
 }
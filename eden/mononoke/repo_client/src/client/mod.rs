@@ -21,7 +21,9 @@ use bookmarks_types::BookmarkKind;
 use bytes::Bytes;
 use bytes_old::{BufMut as BufMutOld, Bytes as BytesOld, BytesMut as BytesMutOld};
 use cloned::cloned;
-use context::{CoreContext, LoggingContainer, PerfCounterType, PerfCounters, SessionContainer};
+use context::{
+    CoreContext, LoggingContainer, PerfCounterType, PerfCounters, RequestId, SessionContainer,
+};
 use filenodes::FilenodeResult;
 use futures::{
     channel::oneshot::{self, Sender},
@@ -89,12 +91,14 @@ use tunables::tunables;
 
 mod logging;
 mod monitor;
+mod read_quota;
 mod session_bookmarks_cache;
 mod tests;
 
 use logging::CommandLogger;
 pub use logging::WireprotoLogging;
 use monitor::Monitor;
+use read_quota::{enforce_read_quota, ClientReadQuota};
 use session_bookmarks_cache::SessionBookmarkCache;
 
 define_stats! {
@@ -453,6 +457,12 @@ impl RepoClient {
         self.request_perf_counters.clone()
     }
 
+    fn read_quota(&self) -> Option<ClientReadQuota> {
+        self.repo
+            .read_quota()
+            .map(|tracker| ClientReadQuota::new(tracker.clone(), &self.session))
+    }
+
     fn command_future<F, I, E, H>(
         &self,
         command: &str,
@@ -497,11 +507,12 @@ impl RepoClient {
         scuba
             .sampled_unless_verbose(sampling_rate.0)
             .add("command", command);
-        scuba.clone().log_with_msg("Start processing", None);
 
-        let ctx =
-            self.session
-                .new_context_with_scribe(logger, scuba, self.logging.scribe().clone());
+        let ctx = self
+            .session
+            .new_context_with_scribe(logger, scuba, self.logging.scribe().clone())
+            .with_request_id(RequestId::new());
+        ctx.scuba().clone().log_with_msg("Start processing", None);
 
         let command_logger = CommandLogger::new(
             ctx.clone(),
@@ -629,6 +640,7 @@ impl RepoClient {
 
         let undesired_path_logger =
             try_boxstream!(UndesiredPathLogger::new(ctx.clone(), self.repo.blobrepo()));
+        let read_quota = self.read_quota();
 
         let changed_entries = gettreepack_entries(ctx.clone(), self.repo.blobrepo(), params)
             .filter({
@@ -645,6 +657,9 @@ impl RepoClient {
                         .increment_counter(PerfCounterType::GettreepackNumTreepacks);
 
                     ctx.session().bump_load(Metric::EgressTotalManifests, 1.0);
+                    if let Some(read_quota) = &read_quota {
+                        read_quota.record_objects(1);
+                    }
                     STATS::total_tree_count.add_value(1);
                     if ctx.session().is_quicksand() {
                         STATS::quicksand_tree_count.add_value(1);
@@ -688,6 +703,8 @@ impl RepoClient {
             // That shouldn't be a problem because requests are quite small
             let getpack_params = Arc::new(Mutex::new(vec![]));
             let repo = self.repo.blobrepo().clone();
+            let reponame = self.repo.reponame().clone();
+            let read_quota = self.read_quota();
 
             let lfs_params = self.lfs_params();
 
@@ -698,7 +715,13 @@ impl RepoClient {
 
             let request_stream = move || {
                 let content_stream = {
-                    cloned!(ctx, getpack_params, lfs_params, undesired_path_logger);
+                    cloned!(
+                        ctx,
+                        getpack_params,
+                        lfs_params,
+                        undesired_path_logger,
+                        read_quota
+                    );
 
                     async move {
                         let buffered_params = BufferedParams {
@@ -718,7 +741,7 @@ impl RepoClient {
 
                         let res = stream::iter(params.into_iter())
                             .map({
-                                cloned!(ctx, getpack_params, repo, lfs_params);
+                                cloned!(ctx, getpack_params, repo, lfs_params, read_quota);
                                 move |(path, filenodes)| {
                                     {
                                         let mut getpack_params = getpack_params.lock().unwrap();
@@ -726,6 +749,9 @@ impl RepoClient {
                                     }
 
                                     ctx.session().bump_load(Metric::EgressGetpackFiles, 1.0);
+                                    if let Some(read_quota) = &read_quota {
+                                        read_quota.record_objects(filenodes.len() as u64);
+                                    }
 
                                     let blob_futs: Vec<_> = filenodes
                                         .iter()
@@ -868,7 +894,9 @@ impl RepoClient {
                     .flatten()
                     .chain(stream_old::once(Ok(wirepack::Part::End)));
 
-                wirepack::packer::WirePackPacker::new(serialized_stream, wirepack::Kind::File)
+                let packer =
+                    wirepack::packer::WirePackPacker::new(serialized_stream, wirepack::Kind::File);
+                let s = packer
                     .and_then(|chunk| chunk.into_bytes())
                     .inspect({
                         cloned!(ctx);
@@ -917,7 +945,9 @@ impl RepoClient {
 
                             Ok(())
                         }
-                    })
+                    });
+
+                enforce_read_quota(&ctx, &reponame, read_quota, name, s)
             };
 
             throttle_stream(
@@ -1493,19 +1523,29 @@ impl HgCommands for RepoClient {
                 .flatten_err()
                 .boxed()
                 .compat()
-                .timed(move |stats, _| {
-                    STATS::getbundle_ms
-                        .add_value(stats.completion_time.as_millis_unchecked() as i64);
-                    command_logger.finalize_command(ctx, &stats, Some(&value));
-                    Ok(())
+                .timed({
+                    cloned!(ctx);
+                    move |stats, _| {
+                        STATS::getbundle_ms
+                            .add_value(stats.completion_time.as_millis_unchecked() as i64);
+                        command_logger.finalize_command(ctx, &stats, Some(&value));
+                        Ok(())
+                    }
                 })
                 .boxify();
 
-            throttle_stream(
+            let s = throttle_stream(
                 &self.session,
                 Metric::EgressCommits,
                 ops::GETBUNDLE,
                 move || s,
+            );
+            enforce_read_quota(
+                &ctx,
+                self.repo.reponame(),
+                self.read_quota(),
+                ops::GETBUNDLE,
+                s,
             )
         })
     }
@@ -1888,6 +1928,7 @@ impl HgCommands for RepoClient {
                         }
                     })
                     .timed({
+                        cloned!(ctx);
                         move |stats, _| {
                             if stats.completion_time > *SLOW_REQUEST_THRESHOLD {
                                 command_logger.add_trimmed_scuba_extra("command_args", &args);
@@ -1899,11 +1940,18 @@ impl HgCommands for RepoClient {
                         }
                     });
 
-                throttle_stream(
+                let s = throttle_stream(
                     &self.session,
                     Metric::EgressTotalManifests,
                     ops::GETTREEPACK,
                     move || s,
+                );
+                enforce_read_quota(
+                    &ctx,
+                    self.repo.reponame(),
+                    self.read_quota(),
+                    ops::GETTREEPACK,
+                    s,
                 )
             },
         )
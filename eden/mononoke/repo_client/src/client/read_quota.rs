@@ -0,0 +1,111 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::errors::ErrorKind;
+
+use anyhow::Error;
+use bytes_old::Bytes as BytesOld;
+use context::{CoreContext, SessionContainer};
+use futures_01_ext::{BoxStream, StreamExt as OldStreamExt};
+use futures_old::{stream as stream_old, Stream};
+use mononoke_repo::ReadQuotaTracker;
+use stats::prelude::*;
+use std::sync::Arc;
+
+define_stats! {
+    prefix = "mononoke.repo_client";
+    read_quota_exceeded: dynamic_timeseries("read_quota_exceeded.{}", (reponame: String); Rate, Sum),
+}
+
+/// The read quota of the client of one session
+#[derive(Clone)]
+pub struct ClientReadQuota {
+    tracker: Arc<ReadQuotaTracker>,
+    client: String,
+}
+
+impl ClientReadQuota {
+    pub fn new(tracker: Arc<ReadQuotaTracker>, session: &SessionContainer) -> Self {
+        Self {
+            tracker,
+            client: client_identity(session),
+        }
+    }
+
+    /// Count files or trees sent to the client against its quota
+    pub fn record_objects(&self, objects: u64) {
+        self.tracker.record(&self.client, 0, objects);
+    }
+}
+
+/// The identities of a client, which all of its sessions share a quota under. Clients without
+/// identities are told apart by their host.
+fn client_identity(session: &SessionContainer) -> String {
+    let metadata = session.metadata();
+    let identities: Vec<_> = metadata
+        .identities()
+        .iter()
+        .map(|identity| identity.to_string())
+        .collect();
+    if !identities.is_empty() {
+        return identities.join(",");
+    }
+
+    match (metadata.client_hostname(), metadata.client_ip()) {
+        (Some(hostname), _) => hostname.to_string(),
+        (None, Some(ip)) => ip.to_string(),
+        (None, None) => "unknown".to_string(),
+    }
+}
+
+/// Reject a read request if the client has gone over its quota, and count the bytes of the
+/// response against the quota otherwise. Clients over quota are logged to scuba, and only
+/// rejected if the quota is enforced.
+pub fn enforce_read_quota<S>(
+    ctx: &CoreContext,
+    reponame: &str,
+    read_quota: Option<ClientReadQuota>,
+    request_name: &'static str,
+    stream: S,
+) -> BoxStream<BytesOld, Error>
+where
+    S: Stream<Item = BytesOld, Error = Error> + Send + 'static,
+{
+    let read_quota = match read_quota {
+        Some(read_quota) => read_quota,
+        None => return stream.boxify(),
+    };
+
+    if let Some(over) = read_quota.tracker.check(&read_quota.client) {
+        let enforced = read_quota.tracker.quota().enforced;
+        STATS::read_quota_exceeded.add_value(1, (reponame.to_string(),));
+        ctx.scuba()
+            .clone()
+            .add("read_quota_client", read_quota.client.clone())
+            .add("read_quota_bytes", over.bytes)
+            .add("read_quota_objects", over.objects)
+            .add("read_quota_enforced", enforced)
+            .log_with_msg("Read quota exceeded", None);
+
+        if enforced {
+            let err = ErrorKind::ReadQuotaExceeded {
+                request_name: request_name.to_string(),
+                client: read_quota.client,
+                retry_after_secs: over.retry_after.as_secs().max(1),
+            };
+            return stream_old::once(Err(err.into())).boxify();
+        }
+    }
+
+    stream
+        .inspect(move |bytes| {
+            read_quota
+                .tracker
+                .record(&read_quota.client, bytes.len() as u64, 0)
+        })
+        .boxify()
+}
@@ -24,6 +24,14 @@ pub enum ErrorKind {
         #[source]
         reason: ThrottleReason,
     },
+    #[error(
+        "Request {request_name} was rejected because {client} is over its read quota, retry in {retry_after_secs}s"
+    )]
+    ReadQuotaExceeded {
+        request_name: String,
+        client: String,
+        retry_after_secs: u64,
+    },
     #[error("Repo is marked as read-only: {0}")]
     RepoReadOnly(String),
 }
@@ -16,7 +16,7 @@ use identity::Identity;
 use maplit::hashset;
 use mononoke_api::{
     ChangesetContext, ChangesetId, ChangesetSpecifier, CoreContext, FileContext, FileId, Mononoke,
-    RepoContext, SessionContainer, TreeContext, TreeId,
+    RepoContext, RequestId, SessionContainer, TreeContext, TreeId,
 };
 use mononoke_types::hash::{Sha1, Sha256};
 use once_cell::sync::Lazy;
@@ -115,7 +115,9 @@ impl SourceControlServiceImpl {
         let session = self.create_session(identities).await?;
         scuba.add("session_uuid", session.metadata().session_id().to_string());
 
-        let ctx = session.new_context_with_scribe(self.logger.clone(), scuba, self.scribe.clone());
+        let ctx = session
+            .new_context_with_scribe(self.logger.clone(), scuba, self.scribe.clone())
+            .with_request_id(RequestId::new());
         Ok(ctx)
     }
 
@@ -19,7 +19,7 @@ use fbinit::FacebookInit;
 use mononoke_types::RepositoryId;
 use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
 use sql_ext::replication::{NoReplicaLagMonitor, ReplicaLagMonitor};
-use sql_ext::SqlConnections;
+use sql_ext::{ShardRouter, SqlConnections};
 
 use crate::bundle::SqlBundleStore;
 use crate::dag::Dag;
@@ -46,6 +46,7 @@ use crate::DisabledSegmentedChangelog;
 #[derive(Default, Clone)]
 pub struct SegmentedChangelogBuilder {
     connections: Option<SqlConnections>,
+    idmap_shards: Option<ShardRouter>,
     repo_id: Option<RepositoryId>,
     idmap_version: Option<IdMapVersion>,
     replica_lag_monitor: Option<Arc<dyn ReplicaLagMonitor>>,
@@ -66,6 +67,7 @@ impl SqlConstruct for SegmentedChangelogBuilder {
     fn from_sql_connections(connections: SqlConnections) -> Self {
         Self {
             connections: Some(connections),
+            idmap_shards: None,
             repo_id: None,
             idmap_version: None,
             replica_lag_monitor: None,
@@ -161,6 +163,13 @@ impl SegmentedChangelogBuilder {
         self
     }
 
+    /// Keep the IdMap in a sharded database. All the entries of a repo go to the shard its id
+    /// is routed to, so that each batch of inserts still commits in a single transaction.
+    pub fn with_sharded_idmap(mut self, shards: ShardRouter) -> Self {
+        self.idmap_shards = Some(shards);
+        self
+    }
+
     pub fn with_repo_id(mut self, repo_id: RepositoryId) -> Self {
         self.repo_id = Some(repo_id);
         self
@@ -255,7 +264,7 @@ impl SegmentedChangelogBuilder {
 
     #[allow(dead_code)]
     pub(crate) fn build_sql_idmap(&mut self) -> Result<SqlIdMap> {
-        let connections = self.connections_clone()?;
+        let connections = self.idmap_connections()?;
         let replica_lag_monitor = self.replica_lag_monitor();
         let repo_id = self.repo_id()?;
         let idmap_version = self.idmap_version();
@@ -268,7 +277,7 @@ impl SegmentedChangelogBuilder {
     }
 
     pub(crate) fn build_sql_idmap_factory(&mut self) -> Result<SqlIdMapFactory> {
-        let connections = self.connections_clone()?;
+        let connections = self.idmap_connections()?;
         let replica_lag_monitor = self.replica_lag_monitor();
         let repo_id = self.repo_id()?;
         Ok(SqlIdMapFactory::new(
@@ -337,6 +346,13 @@ impl SegmentedChangelogBuilder {
         Ok(connections.clone())
     }
 
+    fn idmap_connections(&self) -> Result<SqlConnections> {
+        match &self.idmap_shards {
+            Some(shards) => Ok(shards.shard_for_key(&self.repo_id()?.id().to_be_bytes())),
+            None => self.connections_clone(),
+        }
+    }
+
     fn blobstore(&mut self) -> Result<Arc<dyn Blobstore>> {
         self.blobstore.take().ok_or_else(|| {
             format_err!("SegmentedChangelog cannot be built without Blobstore being specified.")
@@ -162,6 +162,47 @@ impl<'a> SegmentedChangelog for ReadDag<'a> {
         };
         Ok(stream_clone_data)
     }
+
+    async fn pull_fast_forward_master(
+        &self,
+        ctx: &CoreContext,
+        old_master: ChangesetId,
+        new_master: ChangesetId,
+    ) -> Result<CloneData<ChangesetId>> {
+        let (old_vertex, new_vertex) = futures::try_join!(
+            self.idmap.get_vertex(ctx, old_master),
+            self.idmap.get_vertex(ctx, new_master),
+        )
+        .context("error fetching vertexes for pull")?;
+        if !self.iddag.is_ancestor(old_vertex, new_vertex)? {
+            return Err(format_err!(
+                "pull of {} is not a fast-forward of {}",
+                new_master,
+                old_master
+            ));
+        }
+        let flat_segments = self
+            .iddag
+            .flat_segments_range(old_vertex + 1, new_vertex)
+            .context("error during flat segment retrieval")?;
+        let mut universal_ids = vec![new_vertex];
+        for segment in flat_segments.segments.iter() {
+            if segment.parents.len() >= 2 {
+                universal_ids.extend_from_slice(&segment.parents);
+            }
+        }
+        let idmap = self
+            .idmap
+            .find_many_changeset_ids(&ctx, universal_ids)
+            .await
+            .context("error retrieving mappings for pulled universal ids")?;
+        let pull_data = CloneData {
+            head_id: new_vertex,
+            flat_segments,
+            idmap,
+        };
+        Ok(pull_data)
+    }
 }
 
 impl<'a> ReadDag<'a> {
@@ -255,6 +296,18 @@ impl<T: SegmentedChangelogDelegate + Sync + Send> SegmentedChangelog for T {
         delegate.full_idmap_clone_data(ctx).await
     }
 
+    async fn pull_fast_forward_master(
+        &self,
+        ctx: &CoreContext,
+        old_master: ChangesetId,
+        new_master: ChangesetId,
+    ) -> Result<CloneData<ChangesetId>> {
+        let delegate = self.segmented_changelog_delegate(ctx).await?;
+        delegate
+            .pull_fast_forward_master(ctx, old_master, new_master)
+            .await
+    }
+
     async fn many_changeset_ids_to_locations(
         &self,
         ctx: &CoreContext,
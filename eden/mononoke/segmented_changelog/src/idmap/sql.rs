@@ -380,6 +380,7 @@ mod tests {
 
     use maplit::hashmap;
     use sql::{rusqlite::Connection as SqliteConnection, Connection};
+    use sql_ext::{ShardHashing, SqlShardedConnections};
 
     use fbinit::FacebookInit;
 
@@ -593,6 +594,47 @@ mod tests {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_sharded_idmap(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let shards = (0..4)
+            .map(|_| {
+                let con = SqliteConnection::open_in_memory()?;
+                con.execute_batch(SegmentedChangelogBuilder::CREATION_QUERY)?;
+                Ok(SqlConnections::new_single(Connection::with_sqlite(con)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let router = SqlShardedConnections::from(shards).with_routing(ShardHashing::Modulo)?;
+
+        for repo in 0..8 {
+            let repo_id = RepositoryId::new(repo);
+            let idmap = SegmentedChangelogBuilder::new()
+                .with_sharded_idmap(router.clone())
+                .with_repo_id(repo_id)
+                .build_sql_idmap()?;
+            idmap.insert(&ctx, Vertex(0), AS_CSID).await?;
+            idmap.insert(&ctx, Vertex(1), ONES_CSID).await?;
+            assert_eq!(idmap.get_changeset_id(&ctx, Vertex(1)).await?, ONES_CSID);
+
+            // The entries of the repo are all on the shard it is routed to
+            let shard_id = router.shard_id(&repo.to_be_bytes());
+            for (id, connections) in router.shards() {
+                let shard_idmap = SegmentedChangelogBuilder::new()
+                    .with_sql_connections(connections)
+                    .with_repo_id(repo_id)
+                    .build_sql_idmap()?;
+                let last_entry = shard_idmap.get_last_entry(&ctx).await?;
+                if id == shard_id {
+                    assert_eq!(last_entry, Some((Vertex(1), ONES_CSID)));
+                } else {
+                    assert_eq!(last_entry, None);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     #[fbinit::test]
     async fn test_many_repo_id_many_versions(fb: FacebookInit) -> Result<()> {
         let ctx = CoreContext::test_mock(fb);
@@ -40,6 +40,7 @@ mod tests;
 pub use ::dag::{CloneData, FlatSegment, Id as Vertex, Location, PreparedFlatSegments};
 
 pub use crate::builder::SegmentedChangelogBuilder;
+pub use crate::manager::SegmentedChangelogManager;
 
 // public for benchmarking
 pub use crate::idmap::{ConcurrentMemIdMap, IdMap};
@@ -137,6 +138,20 @@ pub trait SegmentedChangelog: Send + Sync {
         &self,
         ctx: &CoreContext,
     ) -> Result<StreamCloneData<ChangesetId>>;
+
+    /// Returns the data a client needs to move its master group from `old_master` to
+    /// `new_master`, a descendant of it.
+    ///
+    /// The client is expected to have the master group up to `old_master`, as it was sent by an
+    /// earlier clone or pull. Only the flat segments of the commits that follow are sent, along
+    /// with the idmap entries the client has to know for them: `new_master` and the parents of
+    /// merges.
+    async fn pull_fast_forward_master(
+        &self,
+        ctx: &CoreContext,
+        old_master: ChangesetId,
+        new_master: ChangesetId,
+    ) -> Result<CloneData<ChangesetId>>;
 }
 
 pub struct DisabledSegmentedChangelog;
@@ -186,4 +201,15 @@ impl SegmentedChangelog for DisabledSegmentedChangelog {
             "Segmented Changelog is not enabled for this repo",
         ))
     }
+
+    async fn pull_fast_forward_master(
+        &self,
+        _ctx: &CoreContext,
+        _old_master: ChangesetId,
+        _new_master: ChangesetId,
+    ) -> Result<CloneData<ChangesetId>> {
+        Err(format_err!(
+            "Segmented Changelog is not enabled for this repo",
+        ))
+    }
 }
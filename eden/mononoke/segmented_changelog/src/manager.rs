@@ -193,4 +193,20 @@ impl SegmentedChangelog for SegmentedChangelogManager {
             .context("error loading segmented changelog from save")?;
         dag.full_idmap_clone_data(ctx).await
     }
+
+    async fn pull_fast_forward_master(
+        &self,
+        ctx: &CoreContext,
+        old_master: ChangesetId,
+        new_master: ChangesetId,
+    ) -> Result<CloneData<ChangesetId>> {
+        let (_, dag) = self.load_dag(&ctx).await.with_context(|| {
+            format!(
+                "repo {}: error loading segmented changelog from save",
+                self.repo_id
+            )
+        })?;
+        dag.pull_fast_forward_master(ctx, old_master, new_master)
+            .await
+    }
 }
@@ -210,4 +210,20 @@ impl SegmentedChangelog for OnDemandUpdateDag {
         let read_dag = ReadDag::new(&iddag, self.idmap.clone());
         read_dag.full_idmap_clone_data(ctx).await
     }
+
+    async fn pull_fast_forward_master(
+        &self,
+        ctx: &CoreContext,
+        old_master: ChangesetId,
+        new_master: ChangesetId,
+    ) -> Result<CloneData<ChangesetId>> {
+        self.build_up_to_cs(ctx, new_master)
+            .await
+            .context("error while getting an up to date dag")?;
+        let iddag = self.iddag.read().await;
+        let read_dag = ReadDag::new(&iddag, self.idmap.clone());
+        read_dag
+            .pull_fast_forward_master(ctx, old_master, new_master)
+            .await
+    }
 }
@@ -17,7 +17,7 @@ use futures::StreamExt;
 use blobrepo::BlobRepo;
 use caching_ext::{CachelibHandler, MemcacheHandler};
 use context::CoreContext;
-use dag::{InProcessIdDag, Location};
+use dag::{Group, InProcessIdDag, Location};
 use fixtures::{linear, merge_even, merge_uneven, unshared_merge_even};
 use mononoke_types::ChangesetId;
 use phases::mark_reachable_as_public;
@@ -587,6 +587,50 @@ async fn test_full_idmap_clone_data(fb: FacebookInit) -> Result<()> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn test_pull_fast_forward_master(fb: FacebookInit) -> Result<()> {
+    // The client has the iddag up to `old_master` and extends it with the pull data.
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo = linear::getrepo(fb).await;
+
+    let head = resolve_cs_id(&ctx, &blobrepo, "79a13814c5ce7330173ec04d279bf95ab3f652fb").await?;
+    setup_phases(&ctx, &blobrepo, head).await?;
+    let dag = new_build_all_from_blobrepo(&ctx, &blobrepo, head).await?;
+
+    let old_master =
+        resolve_cs_id(&ctx, &blobrepo, "0ed509bf086fadcb8a8a5384dc3b550729b0fc17").await?;
+    let old_vertex = dag.idmap.get_vertex(&ctx, old_master).await?;
+    let pull_data = dag.pull_fast_forward_master(&ctx, old_master, head).await?;
+    let first_low = pull_data.flat_segments.segments.first().map(|s| s.low);
+    assert_eq!(first_low, Some(old_vertex + 1));
+    assert_eq!(pull_data.head_id, old_vertex + 4);
+    assert_eq!(pull_data.idmap.get(&pull_data.head_id), Some(&head));
+
+    let mut new_iddag = InProcessIdDag::new_in_process();
+    let old_segments = dag
+        .iddag
+        .flat_segments_range(Group::MASTER.min_id(), old_vertex)?;
+    new_iddag.build_segments_volatile_from_prepared_flat_segments(&old_segments)?;
+    new_iddag.build_segments_volatile_from_prepared_flat_segments(&pull_data.flat_segments)?;
+    let new_dag = Dag::new(new_iddag, dag.idmap.clone());
+    let answer = new_dag
+        .location_to_changeset_id(&ctx, Location::new(head, 4))
+        .await?;
+    assert_eq!(answer, old_master);
+
+    // Nothing to send when the client is up to date.
+    let pull_data = dag.pull_fast_forward_master(&ctx, head, head).await?;
+    assert!(pull_data.flat_segments.segments.is_empty());
+
+    // Moving master backwards is not a fast-forward.
+    assert!(dag
+        .pull_fast_forward_master(&ctx, head, old_master)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn test_caching(fb: FacebookInit) -> Result<()> {
     let ctx = CoreContext::test_mock(fb);
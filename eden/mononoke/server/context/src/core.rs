@@ -13,7 +13,7 @@ use slog_glog_fmt::default_drain;
 use sshrelay::Metadata;
 use std::sync::Arc;
 
-use crate::logging::{LoggingContainer, SamplingKey};
+use crate::logging::{LoggingContainer, RequestId, SamplingKey};
 use crate::perf_counters::PerfCounters;
 use crate::perf_counters_stack::PerfCountersStack;
 use crate::session::{SessionClass, SessionContainer};
@@ -62,8 +62,11 @@ impl CoreContext {
     /// Create a new CoreContext, with a reset LoggingContainer. This is useful to reset perf
     /// counters. The existing CoreContext is unaffected.
     pub fn clone_and_reset(&self) -> Self {
-        self.session
-            .new_context(self.logger().clone(), self.scuba().clone())
+        let mut ctx = self
+            .session
+            .new_context(self.logger().clone(), self.scuba().clone());
+        ctx.logging.set_request_id(self.request_id().cloned());
+        ctx
     }
 
     pub fn clone_and_sample(&self, sampling_key: SamplingKey) -> Self {
@@ -74,15 +77,29 @@ impl CoreContext {
         }
     }
 
+    /// Create a new CoreContext for the request with the given id. The id is attached to
+    /// everything logged through the context and the contexts derived from it, including
+    /// blobstore, SQL and derived data logging, so that the work done for one request can be
+    /// followed across subsystems. Servers should call this where requests come in.
+    pub fn with_request_id(&self, request_id: RequestId) -> Self {
+        Self {
+            fb: self.fb,
+            session: self.session.clone(),
+            logging: self.logging.clone_with_request_id(request_id),
+        }
+    }
+
     pub fn with_mutated_scuba(
         &self,
         sample: impl FnOnce(MononokeScubaSampleBuilder) -> MononokeScubaSampleBuilder,
     ) -> Self {
-        self.session.new_context_with_scribe(
+        let mut ctx = self.session.new_context_with_scribe(
             self.logger().clone(),
             sample(self.scuba().clone()),
             self.scribe().clone(),
-        )
+        );
+        ctx.logging.set_request_id(self.request_id().cloned());
+        ctx
     }
 
     pub(crate) fn new_with_containers(
@@ -105,6 +122,10 @@ impl CoreContext {
         self.logging.sampling_key()
     }
 
+    pub fn request_id(&self) -> Option<&RequestId> {
+        self.logging.request_id()
+    }
+
     pub fn scuba(&self) -> &MononokeScubaSampleBuilder {
         &self.logging.scuba()
     }
@@ -133,3 +154,32 @@ impl CoreContext {
         self.logging.fork_perf_counters()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::logging::REQUEST_ID;
+
+    #[fbinit::test]
+    fn test_request_id_propagation(fb: FacebookInit) {
+        let ctx = CoreContext::test_mock(fb);
+        assert!(ctx.request_id().is_none());
+        assert!(ctx.scuba().get(REQUEST_ID).is_none());
+
+        let request_id = RequestId::new();
+        let ctx = ctx.with_request_id(request_id.clone());
+        assert_eq!(ctx.request_id(), Some(&request_id));
+        assert!(ctx.scuba().get(REQUEST_ID).is_some());
+
+        // Contexts derived from a request's context belong to the same request
+        let derived = vec![
+            ctx.clone_and_reset(),
+            ctx.clone_and_sample(SamplingKey::new()),
+            ctx.with_mutated_scuba(|sample| sample),
+        ];
+        for derived in derived {
+            assert_eq!(derived.request_id(), Some(&request_id));
+            assert!(derived.scuba().get(REQUEST_ID).is_some());
+        }
+    }
+}
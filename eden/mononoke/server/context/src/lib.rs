@@ -11,7 +11,7 @@
 pub use session_id::SessionId;
 
 pub use crate::core::CoreContext;
-pub use crate::logging::{LoggingContainer, SamplingKey};
+pub use crate::logging::{LoggingContainer, RequestId, SamplingKey, REQUEST_ID};
 pub use crate::perf_counters::{PerfCounterType, PerfCounters};
 pub use crate::session::{SessionClass, SessionContainer, SessionContainerBuilder};
 
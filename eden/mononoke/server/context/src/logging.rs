@@ -6,8 +6,10 @@
  */
 
 use fbinit::FacebookInit;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use scuba_ext::MononokeScubaSampleBuilder;
-use slog::Logger;
+use slog::{o, Logger};
+use std::fmt;
 use std::sync::{
     atomic::{AtomicU32, Ordering},
     Arc,
@@ -38,12 +40,44 @@ impl SamplingKey {
     }
 }
 
+/// Identifies a single request made to a server, e.g. a wireproto command or an HTTP request,
+/// so that the logs of the blobstore, SQL and derived data work done for it can be correlated
+/// with the request. Unlike a session id, a new one is generated for each request of a session.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn new() -> Self {
+        let s: String = thread_rng().sample_iter(&Alphanumeric).take(16).collect();
+        Self(s)
+    }
+
+    /// Use an id generated by another layer, e.g. the HTTP framework
+    pub fn from_string<T: ToString>(s: T) -> Self {
+        Self(s.to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Key the request id is logged with, in both log records and scuba samples
+pub const REQUEST_ID: &str = "request_id";
+
 #[derive(Debug, Clone)]
 pub struct LoggingContainer {
     logger: Logger,
     scuba: Arc<MononokeScubaSampleBuilder>,
     perf_counters: PerfCountersStack,
     sampling_key: Option<SamplingKey>,
+    request_id: Option<RequestId>,
     scribe: Scribe,
 }
 
@@ -54,6 +88,7 @@ impl LoggingContainer {
             scuba: Arc::new(scuba),
             perf_counters: Default::default(),
             sampling_key: None,
+            request_id: None,
             scribe: Scribe::new(fb),
         }
     }
@@ -70,10 +105,34 @@ impl LoggingContainer {
             scuba: self.scuba.clone(),
             perf_counters: self.perf_counters.clone(),
             sampling_key: Some(sampling_key),
+            request_id: self.request_id.clone(),
+            scribe: self.scribe.clone(),
+        }
+    }
+
+    /// Tag the logger and scuba sample builder with the request id, so that everything logged
+    /// through this container carries it
+    pub fn clone_with_request_id(&self, request_id: RequestId) -> Self {
+        let mut scuba = (*self.scuba).clone();
+        scuba.add(REQUEST_ID, request_id.as_str());
+        Self {
+            logger: self
+                .logger
+                .new(o!(REQUEST_ID => request_id.as_str().to_owned())),
+            scuba: Arc::new(scuba),
+            perf_counters: self.perf_counters.clone(),
+            sampling_key: self.sampling_key,
+            request_id: Some(request_id),
             scribe: self.scribe.clone(),
         }
     }
 
+    /// Carry over the request id of the container this one was made from. The logger and scuba
+    /// sample builder are expected to have been tagged with it already.
+    pub(crate) fn set_request_id(&mut self, request_id: Option<RequestId>) {
+        self.request_id = request_id;
+    }
+
     pub fn with_scribe(&mut self, scribe: Scribe) -> &mut Self {
         self.scribe = scribe;
         self
@@ -95,6 +154,10 @@ impl LoggingContainer {
         self.sampling_key.as_ref()
     }
 
+    pub fn request_id(&self) -> Option<&RequestId> {
+        self.request_id.as_ref()
+    }
+
     pub fn scribe(&self) -> &Scribe {
         &self.scribe
     }
@@ -99,8 +99,8 @@ define_perf_counters! {
         ManifoldBlobSumDelay,
         ManifoldBlobRetries,
         ManifoldBlobConflicts,
-        S3BlobRetries,
-        S3BlobSumDelay,
+        BlobstoreRetries,
+        BlobstoreRetrySumDelay,
     }
 }
 
@@ -187,8 +187,8 @@ impl PerfCounterType {
             | ManifoldBlobSumDelay
             | ManifoldBlobRetries
             | ManifoldBlobConflicts
-            | S3BlobRetries
-            | S3BlobSumDelay => PerfCounterTypeUpdateFunc::Add,
+            | BlobstoreRetries
+            | BlobstoreRetrySumDelay => PerfCounterTypeUpdateFunc::Add,
             BlobGetsMaxLatency
             | BlobPresenceChecksMaxLatency
             | BlobPutsMaxLatency
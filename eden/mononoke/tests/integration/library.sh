@@ -807,6 +807,12 @@ skiplist_index_blobstore_key="$SKIPLIST_INDEX_BLOBSTORE_KEY"
 CONFIG
 fi
 
+if [[ -n "${DEFAULT_SPARSE_PROFILE:-}" ]]; then
+  cat >> "repos/$reponame/server.toml" <<CONFIG
+default_sparse_profile="$DEFAULT_SPARSE_PROFILE"
+CONFIG
+fi
+
 # Normally point to common storageconfig, but if none passed, create per-repo
 if [[ -z "$storageconfig" ]]; then
   storageconfig="blobstore_$reponame"
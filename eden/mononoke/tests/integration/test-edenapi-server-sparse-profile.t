@@ -0,0 +1,69 @@
+# Copyright (c) Facebook, Inc. and its affiliates.
+#
+# This software may be used and distributed according to the terms of the
+# GNU General Public License found in the LICENSE file in the root
+# directory of this source tree.
+
+  $ . "${TEST_FIXTURES}/library.sh"
+
+Set up local hgrc and Mononoke config, with a default sparse profile.
+  $ DEFAULT_SPARSE_PROFILE=sparse/base setup_common_config
+  $ cd $TESTTMP
+
+Initialize test repo.
+  $ hginit_treemanifest repo-hg
+  $ cd repo-hg
+  $ setup_hg_server
+  $ mkdir base other sparse
+  $ echo a > base/a
+  $ echo b > base/b
+  $ echo c > other/c
+  $ cat > sparse/base <<EOF
+  > [include]
+  > base
+  > sparse
+  > EOF
+  $ hg commit -Aqm "add files and sparse profile"
+  $ hg bookmark master_bookmark -r tip
+
+Blobimport test repo.
+  $ cd ..
+  $ blobimport repo-hg/.hg repo
+
+Start up EdenAPI server.
+  $ start_edenapi_server
+
+Point clients at it.
+  $ cat >> $HGRCPATH <<EOF
+  > [extensions]
+  > sparse=
+  > [edenapi]
+  > url=$EDENAPI_URI
+  > [auth]
+  > edenapi.cert=$TEST_CERTDIR/localhost.crt
+  > edenapi.key=$TEST_CERTDIR/localhost.key
+  > edenapi.prefix=localhost
+  > edenapi.schemes=https
+  > edenapi.cacerts=$TEST_CERTDIR/root-ca.crt
+  > EOF
+
+Clone with the profile recommended by the server.
+  $ hgclone_treemanifest ssh://user@dummy/repo-hg repo-sparse --eden-sparse --config remotefilelog.reponame=repo
+  $ cd repo-sparse
+  $ cat .hg/sparse
+  %include sparse/base
+  [include]
+  
+  [exclude]
+  
+  $ find . -type f -not -path './.hg/*' | sort
+  ./base/a
+  ./base/b
+  ./sparse/base
+  $ hg status
+  $ cd ..
+
+The profile can't be combined with other sparse flags.
+  $ hgclone_treemanifest ssh://user@dummy/repo-hg repo-both --eden-sparse --enable-profile sparse/base --config remotefilelog.reponame=repo
+  abort: too many flags specified.
+  [255]
@@ -174,6 +174,27 @@ Redact a file in log-only mode
   * [TASK]Censor b      : dir/g (log only) (glob)
   * [TASK]Censor c      : dir/c (glob)
   $ sqlite3 "$TESTTMP/monsql/sqlite_dbs" 'SELECT * FROM censored_contents;'
-  1|content.blake2.21c519fe0eb401bc97888f270902935f858d0c5361211f892fd26ed9ce127ff9|[TASK]Censor b|*|0 (glob)
-  2|content.blake2.096c8cc4a38f793ac05fc3506ed6346deb5b857100642adbf4de6720411b10e2|[TASK]Censor c|*|0 (glob)
-  6|content.blake2.0991063aafe55b2bcbbfa6b349e76ab5d57a102c89e841abdac8ce3f84d55b8a|[TASK]Censor b|*|1 (glob)
+  1|content.blake2.21c519fe0eb401bc97888f270902935f858d0c5361211f892fd26ed9ce127ff9|[TASK]Censor b|*|0| (glob)
+  2|content.blake2.096c8cc4a38f793ac05fc3506ed6346deb5b857100642adbf4de6720411b10e2|[TASK]Censor c|*|0| (glob)
+  6|content.blake2.0991063aafe55b2bcbbfa6b349e76ab5d57a102c89e841abdac8ce3f84d55b8a|[TASK]Censor b|*|1| (glob)
+
+Redact keys directly, with a reason
+  $ mononoke_admin redaction add-keys "[TASK]Censor e" content.blake2.0000000000000000000000000000000000000000000000000000000000000000 --reason "leaked secret"
+  * Redacted 1 keys under [TASK]Censor e (glob)
+  $ mononoke_admin redaction list-keys --task "[TASK]Censor e"
+  * content.blake2.0000000000000000000000000000000000000000000000000000000000000000 [TASK]Censor e       added *: leaked secret (glob)
+
+Dry run a redaction of content which is in master_bookmark
+  $ mononoke_admin redaction dry-run content.blake2.21c519fe0eb401bc97888f270902935f858d0c5361211f892fd26ed9ce127ff9 content.blake2.0000000000000000000000000000000000000000000000000000000000000000 --commit master_bookmark
+  * using repo "repo" repoid RepositoryId(0) (glob)
+  * content.blake2.21c519fe0eb401bc97888f270902935f858d0c5361211f892fd26ed9ce127ff9: present in blobstore, already redacted: content.blake2.21c519fe0eb401bc97888f270902935f858d0c5361211f892fd26ed9ce127ff9 [TASK]Censor b       added * (glob)
+  * content.blake2.0000000000000000000000000000000000000000000000000000000000000000: missing from blobstore, already redacted: * leaked secret (glob)
+  * Looking for files with this content in master_bookmark... (glob)
+  * changeset resolved as: ChangesetId(Blake2(*)) (glob)
+  * Would redact in master_bookmark: b content.blake2.21c519fe0eb401bc97888f270902935f858d0c5361211f892fd26ed9ce127ff9 (glob)
+  * 1 files would be redacted in master_bookmark. Checking them out would be impossible, unless the keys are redacted with --log-only (glob)
+
+Remove the keys again
+  $ mononoke_admin redaction remove-keys content.blake2.0000000000000000000000000000000000000000000000000000000000000000
+  $ mononoke_admin redaction list-keys --task "[TASK]Censor e"
+  * No keys are redacted (glob)
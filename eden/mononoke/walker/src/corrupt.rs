@@ -0,0 +1,108 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Nodes that scrub could not load, written out so that the affected blobs can be re-derived or
+//! re-uploaded without walking the whole repo again.
+
+use crate::graph::{Node, WrappedPath};
+
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+/// One line of the corrupt nodes file
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CorruptNodeRecord {
+    pub repo: String,
+    pub node_type: String,
+    /// Key of the node's blob in the repo blobstore, or its identity for nodes not in the
+    /// blobstore (e.g. bookmarks)
+    pub key: String,
+    /// Repo path the node was found at, with "/" for the root, if the node type tracks it
+    pub path: Option<String>,
+}
+
+impl CorruptNodeRecord {
+    pub fn new(repo: &str, node: &Node) -> Self {
+        Self {
+            repo: repo.to_string(),
+            node_type: node.get_type().to_string(),
+            key: node.stats_key(),
+            path: node.stats_path().map(|path| match path {
+                WrappedPath::Root => "/".to_string(),
+                WrappedPath::NonRoot(_) => path.to_string(),
+            }),
+        }
+    }
+}
+
+/// Appends a record for each corrupt node to a file, one JSON object per line
+pub struct CorruptNodeWriter {
+    file: Mutex<BufWriter<File>>,
+}
+
+impl CorruptNodeWriter {
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        let file = File::create(path)
+            .with_context(|| format!("While creating corrupt nodes file {}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn record(&self, repo: &str, node: &Node) -> Result<(), Error> {
+        let json = serde_json::to_string(&CorruptNodeRecord::new(repo, node))?;
+        let mut file = self.file.lock().expect("lock poisoned");
+        writeln!(file, "{}", json)?;
+        // Flush as we go, so that records are not lost if the walk fails later on
+        file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::PathKey;
+    use mercurial_types::HgManifestId;
+    use mononoke_types::MPath;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_record() -> Result<(), Error> {
+        let id = HgManifestId::from_str("e797dcabdd6d16ec4ae614165178b60d7054305b")?;
+        let node = Node::HgManifest(PathKey::new(
+            id,
+            WrappedPath::from(Some(MPath::new("foo/bar")?)),
+        ));
+        let record = CorruptNodeRecord::new("repo", &node);
+        assert_eq!(
+            record,
+            CorruptNodeRecord {
+                repo: "repo".to_string(),
+                node_type: "HgManifest".to_string(),
+                key: "hgmanifest.sha1.e797dcabdd6d16ec4ae614165178b60d7054305b".to_string(),
+                path: Some("foo/bar".to_string()),
+            }
+        );
+
+        let root = Node::HgManifest(PathKey::new(id, WrappedPath::Root));
+        assert_eq!(
+            CorruptNodeRecord::new("repo", &root).path,
+            Some("/".to_string())
+        );
+
+        let json = serde_json::to_string(&record)?;
+        assert_eq!(serde_json::from_str::<CorruptNodeRecord>(&json)?, record);
+        Ok(())
+    }
+}
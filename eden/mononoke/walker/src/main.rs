@@ -20,11 +20,13 @@ use cmdlib::{
 mod blobstore;
 mod checkpoint;
 mod corpus;
+mod corrupt;
 #[macro_use]
 mod graph;
 mod log;
 mod parse_node;
 mod progress;
+mod reachable;
 mod sampling;
 mod scrub;
 mod setup;
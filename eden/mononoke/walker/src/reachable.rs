@@ -0,0 +1,88 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Blobstore keys that scrub loaded, written out so that a garbage collector can mark them as
+//! reachable.
+
+use anyhow::{Context, Error};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Appends each key loaded from the blobstore to a file, one key per line.
+///
+/// Keys are written to a temporary file which only replaces the requested one once the walk is
+/// finished, as marking from a partial list of keys would let live blobs be collected.
+pub struct ReachableKeyWriter {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    file: Mutex<BufWriter<File>>,
+}
+
+impl ReachableKeyWriter {
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let file = File::create(&tmp_path).with_context(|| {
+            format!("While creating reachable keys file {}", tmp_path.display())
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            tmp_path,
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn record<'a>(&self, keys: impl IntoIterator<Item = &'a String>) -> Result<(), Error> {
+        let mut file = self.file.lock().expect("lock poisoned");
+        for key in keys {
+            writeln!(file, "{}", key)?;
+        }
+        Ok(())
+    }
+
+    /// Called once every walk has completed, to move the keys to the requested path
+    pub fn finish(&self) -> Result<(), Error> {
+        let mut file = self.file.lock().expect("lock poisoned");
+        file.flush()?;
+        file.get_ref().sync_all()?;
+        fs::rename(&self.tmp_path, &self.path).with_context(|| {
+            format!(
+                "While renaming reachable keys file to {}",
+                self.path.display()
+            )
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_only_complete_after_finish() -> Result<(), Error> {
+        let dir = TempDir::new("reachable")?;
+        let path = dir.path().join("keys");
+        let writer = ReachableKeyWriter::create(&path)?;
+        writer.record(&["repo0000.a".to_string(), "repo0000.b".to_string()])?;
+        writer.record(&["repo0001.c".to_string()])?;
+        assert!(!path.exists());
+
+        writer.finish()?;
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            "repo0000.a\nrepo0000.b\nrepo0001.c\n"
+        );
+        Ok(())
+    }
+}
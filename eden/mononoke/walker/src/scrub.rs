@@ -5,24 +5,27 @@
  * GNU General Public License version 2.
  */
 
+use crate::corrupt::CorruptNodeWriter;
 use crate::graph::{FileContentData, Node, NodeData, NodeType};
 use crate::log;
 use crate::progress::{
     progress_stream, report_state, ProgressOptions, ProgressReporter, ProgressReporterUnprotected,
     ProgressStateCountByType, ProgressStateMutex,
 };
+use crate::reachable::ReachableKeyWriter;
 use crate::sampling::{SamplingOptions, SamplingWalkVisitor, WalkSampleMapping};
 use crate::setup::{
     parse_node_types, parse_progress_args, parse_sampling_args, setup_common, JobWalkParams,
-    OutputFormat, RepoSubcommandParams, EXCLUDE_OUTPUT_NODE_TYPE_ARG, INCLUDE_OUTPUT_NODE_TYPE_ARG,
-    LIMIT_DATA_FETCH_ARG, OUTPUT_FORMAT_ARG, SCRUB,
+    OutputFormat, RepoSubcommandParams, CORRUPT_NODES_OUTPUT_ARG, EXCLUDE_OUTPUT_NODE_TYPE_ARG,
+    INCLUDE_OUTPUT_NODE_TYPE_ARG, LIMIT_DATA_FETCH_ARG, OUTPUT_FORMAT_ARG,
+    REACHABLE_KEYS_OUTPUT_ARG, SCRUB,
 };
 use crate::sizing::SizingSample;
 use crate::tail::walk_exact_tail;
 use crate::validate::TOTAL;
 use crate::walk::{EmptyRoute, RepoWalkParams, RepoWalkTypeParams};
 
-use anyhow::{format_err, Error};
+use anyhow::{bail, format_err, Error};
 use clap::ArgMatches;
 use cloned::cloned;
 use cmdlib::args::MononokeMatches;
@@ -41,6 +44,7 @@ use stats::prelude::*;
 use std::{
     collections::{HashMap, HashSet},
     fmt,
+    path::Path,
     str::FromStr,
     sync::Arc,
     time::Duration,
@@ -102,6 +106,8 @@ fn loading_stream<InStream, SS>(
     sampler: Arc<WalkSampleMapping<Node, ScrubSample>>,
     output_node_types: HashSet<NodeType>,
     output_format: OutputFormat,
+    corrupt_nodes: Option<(String, Arc<CorruptNodeWriter>)>,
+    reachable_keys: Option<Arc<ReachableKeyWriter>>,
 ) -> impl Stream<Item = Result<(Node, Option<NodeData>, Option<ScrubStats>), Error>>
 where
     InStream: Stream<Item = Result<(Node, Option<NodeData>, Option<SS>), Error>> + 'static + Send,
@@ -111,21 +117,31 @@ where
             Some(NodeData::FileContent(FileContentData::ContentStream(file_bytes_stream)))
                 if !limit_data_fetch =>
             {
-                cloned!(sampler);
+                cloned!(sampler, reachable_keys);
                 file_bytes_stream
                     .try_fold(0, |acc, file_bytes| future::ok(acc + file_bytes.size()))
-                    .map_ok(move |num_bytes| {
-                        let size = ScrubStats::from(sampler.complete_step(&n).as_ref());
-                        (
-                            n,
-                            Some(NodeData::FileContent(FileContentData::Consumed(num_bytes))),
-                            Some(size),
-                        )
+                    .and_then(move |num_bytes| {
+                        let sample = sampler.complete_step(&n);
+                        let recorded = record_reachable(reachable_keys.as_deref(), sample.as_ref());
+                        let size = ScrubStats::from(sample.as_ref());
+                        future::ready(recorded.map(|()| {
+                            (
+                                n,
+                                Some(NodeData::FileContent(FileContentData::Consumed(num_bytes))),
+                                Some(size),
+                            )
+                        }))
                     })
                     .map_err(|e| e.context(format_err!("While scrubbing file content stream")))
                     .left_future()
             }
             data_opt => {
+                let recorded = match (&data_opt, &corrupt_nodes) {
+                    (Some(NodeData::ErrorAsData(node)), Some((repo, writer))) => {
+                        writer.record(repo, node)
+                    }
+                    _ => Ok(()),
+                };
                 if output_node_types.contains(&n.get_type()) {
                     match output_format {
                         OutputFormat::Debug => println!("Node {:?}: NodeData: {:?}", n, data_opt),
@@ -135,16 +151,33 @@ where
                         }
                     }
                 }
-                let size = data_opt
-                    .as_ref()
-                    .map(|_d| ScrubStats::from(sampler.complete_step(&n).as_ref()));
-                future::ok((n, data_opt, size)).right_future()
+                let (size, recorded) = match data_opt {
+                    Some(_) => {
+                        let sample = sampler.complete_step(&n);
+                        let recorded = recorded.and_then(|()| {
+                            record_reachable(reachable_keys.as_deref(), sample.as_ref())
+                        });
+                        (Some(ScrubStats::from(sample.as_ref())), recorded)
+                    }
+                    None => (None, recorded),
+                };
+                future::ready(recorded.map(|()| (n, data_opt, size))).right_future()
             }
         }
     })
     .try_buffer_unordered(scheduled_max)
 }
 
+fn record_reachable(
+    reachable_keys: Option<&ReachableKeyWriter>,
+    sample: Option<&ScrubSample>,
+) -> Result<(), Error> {
+    match (reachable_keys, sample) {
+        (Some(writer), Some(sample)) => writer.record(sample.data.keys()),
+        _ => Ok(()),
+    }
+}
+
 #[derive(Debug)]
 struct ScrubSample {
     data: HashMap<String, u64>,
@@ -331,6 +364,8 @@ struct ScrubCommand {
     progress_options: ProgressOptions,
     sampling_options: SamplingOptions,
     sampler: Arc<WalkSampleMapping<Node, ScrubSample>>,
+    corrupt_nodes: Option<Arc<CorruptNodeWriter>>,
+    reachable_keys: Option<Arc<ReachableKeyWriter>>,
 }
 
 impl ScrubCommand {
@@ -362,13 +397,32 @@ pub async fn scrub_objects<'a>(
         &[],
     )?;
 
+    let sampling_options = parse_sampling_args(&sub_m, 1)?;
+    let reachable_keys = sub_m
+        .value_of(REACHABLE_KEYS_OUTPUT_ARG)
+        .map(|path| {
+            if sampling_options.sample_rate != 1 || !sampling_options.exclude_types.is_empty() {
+                bail!(
+                    "--{} needs every node to be sampled, so it can't be combined with sampling args",
+                    REACHABLE_KEYS_OUTPUT_ARG
+                );
+            }
+            ReachableKeyWriter::create(Path::new(path)).map(Arc::new)
+        })
+        .transpose()?;
+
     let command = ScrubCommand {
         limit_data_fetch: sub_m.is_present(LIMIT_DATA_FETCH_ARG),
         output_format,
         output_node_types,
         progress_options: parse_progress_args(&sub_m),
-        sampling_options: parse_sampling_args(&sub_m, 1)?,
+        sampling_options,
         sampler,
+        corrupt_nodes: sub_m
+            .value_of(CORRUPT_NODES_OUTPUT_ARG)
+            .map(|path| CorruptNodeWriter::create(Path::new(path)).map(Arc::new))
+            .transpose()?,
+        reachable_keys: reachable_keys.clone(),
     };
 
     let mut all_walks = Vec::new();
@@ -380,7 +434,11 @@ pub async fn scrub_objects<'a>(
         let walk = run_one(fb, job_params, sub_params, repo_params, command);
         all_walks.push(walk);
     }
-    try_join_all(all_walks).await.map(|_| ())
+    try_join_all(all_walks).await?;
+    if let Some(reachable_keys) = reachable_keys {
+        reachable_keys.finish()?;
+    }
+    Ok(())
 }
 
 async fn run_one(
@@ -404,6 +462,10 @@ async fn run_one(
         cloned!(command, job_params.quiet, sub_params.progress_state,);
         move |ctx: &CoreContext, repo_params: &RepoWalkParams| {
             cloned!(ctx, repo_params.scheduled_max);
+            let corrupt_nodes = command
+                .corrupt_nodes
+                .clone()
+                .map(|writer| (repo_params.repo.name().clone(), writer));
             async move |walk_output| {
                 let walk_progress = progress_stream(quiet, &progress_state, walk_output);
                 let loading = loading_stream(
@@ -413,6 +475,8 @@ async fn run_one(
                     command.sampler,
                     command.output_node_types,
                     command.output_format,
+                    corrupt_nodes,
+                    command.reachable_keys,
                 );
                 let report_sizing = progress_stream(quiet, &sizing_progress_state, loading);
 
@@ -127,6 +127,8 @@ pub const EXCLUDE_OUTPUT_NODE_TYPE_ARG: &str = "exclude-output-node-type";
 pub const INCLUDE_OUTPUT_NODE_TYPE_ARG: &str = "include-output-node-type";
 pub const OUTPUT_FORMAT_ARG: &str = "output-format";
 pub const OUTPUT_DIR_ARG: &str = "output-dir";
+pub const CORRUPT_NODES_OUTPUT_ARG: &str = "corrupt-nodes-output";
+pub const REACHABLE_KEYS_OUTPUT_ARG: &str = "reachable-keys-output";
 const SCUBA_TABLE_ARG: &str = "scuba-table";
 const SCUBA_LOG_FILE_ARG: &str = "scuba-log-file";
 
@@ -535,6 +537,20 @@ pub fn setup_toplevel_app<'a, 'b>(
                 .default_value(OutputFormat::PrettyDebug.as_ref())
                 .required(false)
                 .help("Set the output format"),
+        )
+        .arg(
+            Arg::with_name(CORRUPT_NODES_OUTPUT_ARG)
+                .long(CORRUPT_NODES_OUTPUT_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("File to write the nodes that could not be loaded to, one JSON object per line, so they can be repaired. Combine with --error-as-data-node-type to continue walking past them."),
+        )
+        .arg(
+            Arg::with_name(REACHABLE_KEYS_OUTPUT_ARG)
+                .long(REACHABLE_KEYS_OUTPUT_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("File to write the blobstore key of every blob loaded to, one per line, once all walks have completed. Used to mark reachable blobs for sqlblob_gc, so it requires every node to be sampled."),
         );
 
     let compression_benefit = setup_subcommand_args(
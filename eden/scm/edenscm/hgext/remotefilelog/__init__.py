@@ -42,6 +42,13 @@ Configs:
     ``remotefilelog.prefetchdelay`` specifies delay between background
     prefetches in seconds after operations that change the working copy parent
 
+    ``remotefilelog.prefetchdaemon.interval`` specifies how many seconds
+    ``hg debugprefetchdaemon`` waits between checks for new revisions.
+
+    ``remotefilelog.prefetchdaemon.maxrevs`` specifies the maximum number of
+    revisions ``hg debugprefetchdaemon`` prefetches per check. Zero or a
+    negative value means no limit.
+
     ``remotefilelog.data.gencountlimit`` constraints the minimum number of data
     pack files required to be considered part of a generation. In particular,
     minimum number of packs files > gencountlimit.
@@ -210,6 +217,8 @@ configitem("remotefilelog", "server", default=None)
 configitem("remotefilelog", "getpackversion", default=1)
 configitem("remotefilelog", "commitsperrepack", default=100)
 configitem("remotefilelog", "http", default=False)
+configitem("remotefilelog", "prefetchdaemon.interval", default=60)
+configitem("remotefilelog", "prefetchdaemon.maxrevs", default=10)
 configitem("edenapi", "url", default=None)
 
 testedwith = "ships-with-fb-hgext"
@@ -923,6 +932,14 @@ def pull(orig, ui, repo, *pats, **opts):
     result = orig(ui, repo, *pats, **opts)
 
     if shallowrepo.requirement in repo.requirements:
+        if ui.configbool("scmstore", "negative-cache"):
+            # The pull may have brought in data the server previously reported
+            # missing, so drop the negative caches.
+            repo.fileslog.contentstore.markforrefresh()
+            datastore = getattr(repo.manifestlog, "datastore", None)
+            if util.safehasattr(datastore, "markforrefresh"):
+                datastore.markforrefresh()
+
         # prefetch if it's configured
         prefetchrevset = ui.config("remotefilelog", "pullprefetch", None)
         bgrepack = repo.ui.configbool("remotefilelog", "backgroundrepack", False)
@@ -1067,6 +1084,40 @@ def debugwaitonprefetch(ui, repo, **opts):
     return debugcommands.debugwaitonprefetch(repo)
 
 
+@command(
+    "debugprefetchdaemon",
+    [
+        ("r", "rev", [], _("prefetch the specified revisions"), _("REV")),
+        ("", "interval", 0, _("seconds between checks"), _("SECONDS")),
+        ("", "once", None, _("check once, then exit")),
+        ("", "background", None, _("run in a background process")),
+        ("", "stop", None, _("stop the running daemon")),
+    ],
+    _("hg debugprefetchdaemon [OPTIONS]"),
+)
+def debugprefetchdaemon(ui, repo, **opts):
+    """prefetch new revisions as they are pulled
+
+    Periodically checks for revisions matching the prefetch revset which
+    weren't prefetched yet, such as commits brought in by a pull or bookmarks
+    which moved, and prefetches their trees and files. Checks are skipped
+    while another command holds the working copy lock.
+
+    With ``scmstore.enabled``, file fetches use the ``prefetch`` priority, so
+    when ``scmstore.ratelimit.edenapi.*`` limits are set they wait for the
+    fetches of interactive commands sharing the same cache.
+
+    Without --rev, the revset is the same as the one :hg:`prefetch` uses.
+    The daemon exits when :hg:`debugprefetchdaemon --stop` is run.
+    """
+    if not shallowrepo.requirement in repo.requirements:
+        raise error.Abort(_("repo is not shallow"))
+    if "eden" in repo.requirements:
+        raise error.Abort(_("cannot prefetch full revisions in an EdenFS repository"))
+    opts = resolveprefetchopts(ui, opts)
+    return debugcommands.debugprefetchdaemon(ui, repo, **opts)
+
+
 def resolveprefetchopts(ui, opts):
     if not opts.get("rev"):
         revset = [".", "draft()"]
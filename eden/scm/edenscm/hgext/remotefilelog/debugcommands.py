@@ -9,10 +9,19 @@ from __future__ import absolute_import
 import hashlib
 import os
 import sys
+import time
 
 from bindings import revisionstore
 from edenscm.hgext import extutil
-from edenscm.mercurial import error, filelog, progress, pycompat, revlog, util
+from edenscm.mercurial import (
+    error,
+    filelog,
+    progress,
+    pycompat,
+    revlog,
+    scmutil,
+    util,
+)
 from edenscm.mercurial.i18n import _, _x
 from edenscm.mercurial.node import bin, hex, nullid, short
 
@@ -505,3 +514,78 @@ def debugwaitonprefetch(repo):
         _("prefetching in %s") % repo.origroot,
     ):
         pass
+
+
+def _repobusy(repo):
+    """Return True if another command holds the working copy lock"""
+    try:
+        repo.wlock(wait=False).release()
+    except error.LockHeld:
+        return True
+    return False
+
+
+def _waitforstop(repo, stopfile, seconds):
+    """Sleep for up to 'seconds', returning True early if asked to stop"""
+    deadline = time.time() + seconds
+    while not repo.svfs.exists(stopfile):
+        remaining = deadline - time.time()
+        if remaining <= 0:
+            return False
+        time.sleep(min(remaining, 1))
+    return True
+
+
+def debugprefetchdaemon(ui, repo, **opts):
+    stopfile = "prefetchdaemon.stop"
+    if opts.get("stop"):
+        repo.svfs.write(stopfile, b"")
+        ui.status(_("asked the prefetch daemon to stop\n"))
+        return 0
+
+    if opts.get("background"):
+        cmd = [util.hgexecutable(), "-R", repo.origroot, "debugprefetchdaemon"]
+        for rev in opts.get("rev"):
+            cmd += ["-r", rev]
+        if opts.get("interval"):
+            cmd += ["--interval", str(opts.get("interval"))]
+        util.spawndetached(cmd)
+        return 0
+
+    interval = opts.get("interval") or ui.configint(
+        "remotefilelog", "prefetchdaemon.interval"
+    )
+    maxrevs = ui.configint("remotefilelog", "prefetchdaemon.maxrevs")
+
+    # Let interactive commands sharing the remote rate limits go first. The
+    # file stores read it from repo.ui when invalidateall() rebuilds them below.
+    repo.ui.setconfig("scmstore", "priority", "prefetch", "debugprefetchdaemon")
+
+    repo.svfs.tryunlink(stopfile)
+    prefetched = set()
+    while True:
+        # Pick up the commits and bookmarks pulled since the last check.
+        repo.invalidateall()
+        if _repobusy(repo):
+            ui.debug("repo is busy, not prefetching\n")
+        else:
+            revs = scmutil.revrange(repo, opts.get("rev"))
+            nodes = [repo[rev].node() for rev in revs]
+            nodes = [node for node in nodes if node not in prefetched]
+            if maxrevs > 0:
+                nodes = nodes[:maxrevs]
+            if nodes:
+                try:
+                    repo.prefetch(repo.revs("%ln", nodes), base=repo["."].rev())
+                except Exception as ex:
+                    # Try again at the next check.
+                    ui.warn(_("prefetch failed: %s\n") % ex)
+                else:
+                    prefetched.update(nodes)
+                    ui.status(_("prefetched %d revisions\n") % len(nodes))
+
+        if opts.get("once") or _waitforstop(repo, stopfile, interval):
+            break
+
+    repo.svfs.tryunlink(stopfile)
+    return 0
@@ -537,7 +537,9 @@ class remotefileslog(filelog.fileslog):
         that is present in the local store, for this specific case, let's
         build shared-only stores.
 
-        Do not use it except in the fileserverclient.prefetch method!
+        Do not use it except to force prefetches, in the
+        fileserverclient.prefetch method and the native prefetch of
+        shallowrepo._prefetch!
         """
 
         sharedonlyremotestore = revisionstore.pyremotestore(
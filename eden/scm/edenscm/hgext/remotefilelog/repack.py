@@ -31,7 +31,15 @@ def domaintenancerepack(repo):
 
 
 def backgroundrepack(repo, incremental=True):
-    cmd = [util.hgexecutable(), "-R", repo.origroot, "repack"]
+    # Let the fetches of interactive commands go first.
+    cmd = [
+        util.hgexecutable(),
+        "-R",
+        repo.origroot,
+        "repack",
+        "--config",
+        "scmstore.priority=background",
+    ]
     msg = _("(running background repack)\n")
     if incremental:
         cmd.append("--incremental")
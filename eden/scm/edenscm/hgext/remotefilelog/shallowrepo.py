@@ -163,6 +163,24 @@ def wraprepo(repo):
             else:
                 skip = set()
 
+            # With scmstore.prefetch, trees and files are fetched by the native
+            # store stack, which fetches many batches concurrently and writes
+            # them through to the shared cache.
+            nativeprefetch = self.ui.configbool("scmstore", "prefetch")
+            if (
+                nativeprefetch
+                and not pats
+                and matcher is None
+                and util.safehasattr(self, "_bfsprefetch")
+            ):
+                # Fetch the full trees level by level up front, rather than one
+                # directory at a time while reading the manifests below. Sparse
+                # checkouts only need part of the trees, which are read below.
+                sparsematch = self.maybesparsematch(*revs)
+                if sparsematch is None or sparsematch.always():
+                    mfnodes = [self[rev].manifestnode() for rev in revs]
+                    self._bfsprefetch("", mfnodes)
+
             # Copy the skip set to start large and avoid constant resizing,
             # and since it's likely to be very similar to the prefetch set.
             files = skip.copy()
@@ -200,6 +218,29 @@ def wraprepo(repo):
             files.difference_update(skip)
             serverfiles.difference_update(skip)
 
+            if nativeprefetch:
+                # Like the fileservice below, files known to be on the server
+                # are fetched even if the local store has them.
+                for fileids, force in [(serverfiles, True), (files, False)]:
+                    keys = [
+                        (path, fnode)
+                        for (path, fnode) in fileids
+                        if path != ".hgtags" and self.shallowmatch(path)
+                    ]
+                    if not keys:
+                        continue
+                    if force:
+                        stores = self.fileslog.makesharedonlyruststore(self)
+                    else:
+                        stores = (
+                            self.fileslog.contentstore,
+                            self.fileslog.metadatastore,
+                        )
+                    contentstore, metadatastore = stores
+                    contentstore.prefetch(keys)
+                    metadatastore.prefetch(keys)
+                return
+
             # Fetch files known to be on the server
             if serverfiles:
                 results = [(path, hex(fnode)) for (path, fnode) in serverfiles]
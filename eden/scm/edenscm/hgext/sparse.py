@@ -535,7 +535,9 @@ def _clonesparsecmd(orig, ui, repo, *args, **opts):
     include_pat = opts.get("include")
     exclude_pat = opts.get("exclude")
     enableprofile_pat = opts.get("enable_profile")
+    edensparse = opts.get("eden_sparse")
     include = exclude = enableprofile = False
+    pat = None
     if include_pat:
         pat = include_pat
         include = True
@@ -545,11 +547,18 @@ def _clonesparsecmd(orig, ui, repo, *args, **opts):
     if enableprofile_pat:
         pat = enableprofile_pat
         enableprofile = True
-    if sum([include, exclude, enableprofile]) > 1:
+    if sum([include, exclude, enableprofile, bool(edensparse)]) > 1:
         raise error.Abort(_("too many flags specified."))
-    if include or exclude or enableprofile:
+    if include or exclude or enableprofile or edensparse:
 
         def clone_sparse(orig, self, node, overwrite, *args, **kwargs):
+            pats, enable = pat, enableprofile
+            if edensparse:
+                profile = _serversparseprofile(self)
+                if profile is None:
+                    self.ui.warn(_("server has no default sparse profile\n"))
+                    return orig(self, node, overwrite, *args, **kwargs)
+                pats, enable = [profile], True
             # sparse clone is a special snowflake as in that case always
             # are outside of the repo's dir hierachy, yet we always want
             # to name our includes/excludes/enables using repo-root
@@ -562,29 +571,51 @@ def _clonesparsecmd(orig, ui, repo, *args, **opts):
                 _config(
                     self.ui,
                     self,
-                    pat,
+                    pats,
                     {},
                     include=include,
                     exclude=exclude,
-                    enableprofile=enableprofile,
+                    enableprofile=enable,
                     # Allow unsafe sparse profiles because usually people call
                     # fbclone command which already includes a few safeguards.
                     allowunsafeprofilechanges=True,
                 )
             ret = orig(self, node, overwrite, *args, **kwargs)
-            if enableprofile:
-                _checknonexistingprofiles(ui, self, pat)
+            if enable:
+                _checknonexistingprofiles(ui, self, pats)
             return ret
 
         extensions.wrapfunction(hg, "updaterepo", clone_sparse)
     return orig(ui, repo, *args, **opts)
 
 
+def _serversparseprofile(repo):
+    """Ask the server which sparse profile new working copies should enable
+
+    Returns None if the server does not recommend one.
+    """
+    edenapi = getattr(repo, "edenapi", None)
+    if edenapi is None:
+        raise error.Abort(
+            _("--eden-sparse requires EdenAPI"),
+            hint=_("set edenapi.url to the EdenAPI server for this repo"),
+        )
+    return edenapi.sparseprofile(repo.name)
+
+
 def _setupclone(ui):
     entry = commands.table["clone"]
     entry[1].append(("", "enable-profile", [], "enable a sparse profile"))
     entry[1].append(("", "include", [], "include sparse pattern"))
     entry[1].append(("", "exclude", [], "exclude sparse pattern"))
+    entry[1].append(
+        (
+            "",
+            "eden-sparse",
+            None,
+            "enable the sparse profile recommended by the server",
+        )
+    )
     extensions.wrapcommand(commands.table, "clone", _clonesparsecmd)
 
 
@@ -152,6 +152,36 @@ fetching.
 
     [treemanifest]
     http = True
+
+`treemanifest.warmup` causes the client to walk the working copy parent's tree
+after a pull, and prefetch the tree nodes missing from the local store in
+batches of `treemanifest.warmupbatchsize` nodes, so that the next command
+walking the working copy doesn't fetch them one at a time.
+`treemanifest.warmupdepth` limits how deep the tree is walked.
+
+::
+
+    [treemanifest]
+    warmup = True
+    warmupbatchsize = 1000
+    warmupdepth = 10
+
+`treemanifest.pullprefetchhints` causes the client to ask the server, during a
+pull, for the list of trees it will need to check out the pulled heads. The
+trees missing from the local store are then prefetched in the background.
+
+::
+
+    [treemanifest]
+    pullprefetchhints = True
+
+`treemanifest.prefetchhintlimit` is the maximum number of trees a server lists
+in the prefetch hints sent on pull.
+
+::
+
+    [treemanifest]
+    prefetchhintlimit = 100000
 """
 from __future__ import absolute_import
 
@@ -197,7 +227,7 @@ from edenscm.mercurial import (
 from edenscm.mercurial.commands import debug as debugcommands
 from edenscm.mercurial.i18n import _, _n
 from edenscm.mercurial.node import bin, hex, nullid, short
-from edenscm.mercurial.pycompat import decodeutf8, range
+from edenscm.mercurial.pycompat import decodeutf8, encodeutf8, range
 
 from .. import clienttelemetry
 from ..extutil import flock
@@ -245,14 +275,24 @@ configitem("treemanifest", "treeonly", default=True)
 configitem("treemanifest", "prefetchdraftparents", default=True)
 configitem("treemanifest", "ondemandfetch", default=False)
 configitem("treemanifest", "http", default=False)
+configitem("treemanifest", "warmup", default=False)
+configitem("treemanifest", "warmupbatchsize", default=1000)
+configitem("treemanifest", "warmupdepth", default=None)
+configitem("treemanifest", "pullprefetchhints", default=False)
+configitem("treemanifest", "prefetchhintlimit", default=100000)
 
 PACK_CATEGORY = "manifests"
 
 TREEGROUP_PARTTYPE = "b2x:treegroup"
 # Temporary part type while we migrate the arguments
 TREEGROUP_PARTTYPE2 = "b2x:treegroup2"
+PREFETCHHINTS_PARTTYPE = "b2x:treeprefetchhints"
 RECEIVEDNODE_RECORD = "receivednodes"
 
+# The prefetch hints received on pull are stored in this file, in the store,
+# until the background prefetch consumes them.
+PREFETCHHINTS_FILE = "treeprefetchhints"
+
 # When looking for a recent manifest to consider our base during tree
 # prefetches, this constant defines how far back we should search.
 BASENODESEARCHMAX = 25000
@@ -2217,6 +2257,85 @@ def _registerbundle2parts():
             except BaseException as ex:
                 bundler.addpart(bundle2.createerrorpart(str(ex)))
 
+    @bundle2.parthandler(PREFETCHHINTS_PARTTYPE)
+    def prefetchhintshandler(op, part):
+        """Saves the trees the server hinted at, so they can be prefetched in
+        the background once the pull is done.
+        """
+        op.repo.svfs.write(PREFETCHHINTS_FILE, part.read())
+
+    @exchange.getbundle2partsgenerator(PREFETCHHINTS_PARTTYPE)
+    def _getbundleprefetchhintspart(
+        bundler,
+        repo,
+        source,
+        bundlecaps=None,
+        b2caps=None,
+        heads=None,
+        common=None,
+        **kwargs
+    ):
+        """add a part listing the trees needed to check out the pulled heads"""
+        if (
+            "treeprefetchhints" not in (bundlecaps or set())
+            or not repo.svfs.treemanifestserver
+            or not kwargs.get("cg", True)
+        ):
+            return
+
+        outgoing = exchange._computeoutgoing(repo, heads, common)
+        try:
+            keys = computeprefetchhints(repo, outgoing)
+        except Exception as ex:
+            # The hints are only an optimization, they must not fail the pull.
+            repo.ui.log(
+                "exceptions",
+                exception_type=type(ex).__name__,
+                exception_msg=str(ex),
+                fatal="false",
+            )
+            return
+
+        if keys:
+            data = "".join("%s %s\n" % (hex(node), path) for path, node in keys)
+            bundler.newpart(
+                PREFETCHHINTS_PARTTYPE, data=encodeutf8(data), mandatory=False
+            )
+
+
+def computeprefetchhints(repo, outgoing):
+    """Returns the (path, node) keys of the trees of the pulled heads that
+    differ from the trees of the heads the client already has.
+    """
+    if not outgoing.missing:
+        return []
+
+    limit = repo.ui.configint("treemanifest", "prefetchhintlimit")
+    depth = repo.ui.configint("treemanifest", "fetchdepth")
+    datastore = repo.manifestlog.datastore
+
+    # The client may have heads the server doesn't have trees for.
+    basemfnodes = [
+        repo[node].manifestnode() for node in outgoing.commonheads if node != nullid
+    ]
+    basemfnodes = [n for n in basemfnodes if not datastore.getmissing([("", n)])]
+
+    keys = []
+    seen = set()
+    for ctx in repo.set("heads(%ln)", outgoing.missing):
+        subtrees = rustmanifest.subdirdiff(
+            datastore, "", ctx.manifestnode(), basemfnodes, depth
+        )
+        for subname, subnode, x, x, x, x in subtrees:
+            key = (subname, subnode)
+            if key in seen:
+                continue
+            seen.add(key)
+            keys.append(key)
+            if len(keys) >= limit:
+                return keys
+    return keys
+
 
 def createtreepackpart(repo, outgoing, partname, sendtrees=shallowbundle.AllTrees):
     if sendtrees == shallowbundle.NoTrees:
@@ -2296,6 +2415,12 @@ def pull(orig, ui, repo, *pats, **opts):
                 exception_msg=str(ex),
                 fatal="false",
             )
+        if ui.configbool("treemanifest", "pullprefetchhints") and repo.svfs.exists(
+            PREFETCHHINTS_FILE
+        ):
+            util.spawndetached(
+                [util.hgexecutable(), "-R", repo.origroot, "debugprefetchtreehints"]
+            )
     return result
 
 
@@ -2334,6 +2459,53 @@ def _postpullprefetch(ui, repo):
 
         repo.prefetchtrees(mfnodes, basemfnodes=basemfnodes)
 
+    if ui.configbool("treemanifest", "warmup"):
+        warmworkingcopy(repo)
+
+
+@command("debugprefetchtreehints", [], _("hg debugprefetchtreehints"))
+def debugprefetchtreehints(ui, repo, **opts):
+    """prefetch the trees hinted at by the server during the last pull"""
+    if not repo.svfs.exists(PREFETCHHINTS_FILE):
+        ui.debug("no tree prefetch hints\n")
+        return
+
+    keys = []
+    for line in decodeutf8(repo.svfs.read(PREFETCHHINTS_FILE)).splitlines():
+        node, path = line.split(" ", 1)
+        keys.append((path, bin(node)))
+
+    mfstore = repo.manifestlog.datastore
+    missing = list(mfstore.getmissing(keys))
+    if missing:
+        with progress.spinner(ui, _("prefetching hinted trees")):
+            mfstore.prefetch(missing)
+    repo.svfs.tryunlink(PREFETCHHINTS_FILE)
+    ui.debug("prefetched %d of %d hinted trees\n" % (len(missing), len(keys)))
+
+
+def warmworkingcopy(repo):
+    """Prefetch the trees of the working copy parent missing from the local store
+
+    The missing tree nodes are found with a BFS over the tree, and fetched
+    in batches, one level at a time.
+    """
+    ui = repo.ui
+    p1 = repo.dirstate.p1()
+    if p1 == nullid:
+        return
+    mfnode = repo[p1].manifestnode()
+    batchsize = ui.configint("treemanifest", "warmupbatchsize")
+    depth = ui.configint("treemanifest", "warmupdepth")
+    with progress.spinner(ui, _("warming up trees")):
+        trees, fetched, batches = rustmanifest.warmup(
+            repo.manifestlog.datastore, mfnode, "", batchsize, depth
+        )
+    ui.debug(
+        "warmed up %d trees of %s: fetched %d in %d batches\n"
+        % (trees, short(p1), fetched, batches)
+    )
+
 
 @util.timefunction("findrecenttrees")
 def _findrecenttree(repo, startnode, targetmfnodes):
@@ -2799,6 +2971,9 @@ def _debugbundle2part(orig, ui, part, all, **opts):
         for key in sorted(tempstore):
             ui.write(indent_string)
             ui.write("%s %s\n" % (hex(key[1]), key[0]))
+    elif part.type == PREFETCHHINTS_PARTTYPE:
+        for line in decodeutf8(part.read()).splitlines():
+            ui.write("    %s\n" % line)
 
     orig(ui, part, all, **opts)
 
@@ -3091,6 +3266,9 @@ class vfscachestore(cachestorecommon):
 
 def pullbundle2extraprepare(orig, pullop, kwargs):
     repo = pullop.repo
-    if treeenabled(repo.ui) and repo.ui.configbool("treemanifest", "treeonly"):
+    if treeenabled(repo.ui):
         bundlecaps = kwargs.get("bundlecaps", set())
-        bundlecaps.add("treeonly")
+        if repo.ui.configbool("treemanifest", "treeonly"):
+            bundlecaps.add("treeonly")
+        if repo.ui.configbool("treemanifest", "pullprefetchhints"):
+            bundlecaps.add("treeprefetchhints")
@@ -164,6 +164,7 @@ globalopts = cmdutil._typedflags(
         ("", "version", False, _("output version information and exit")),
         ("h", "help", False, _("display help and exit")),
         ("", "hidden", False, _("consider hidden changesets")),
+        ("", "offline", False, _("do not fetch file or tree data from the server")),
         (
             "",
             "pager",
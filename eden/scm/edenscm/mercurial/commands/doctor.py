@@ -94,15 +94,8 @@ def doctor(ui, **opts):
         sharedpath = os.path.join(shallowutil.getcachepath(ui), repo.name)
         localpath = svfs.base
         for suffix in [None, "manifests"]:
-            repair(
-                ui,
-                "revisionstore",
-                sharedpath,
-                revisionstore.repair,
-                localpath,
-                suffix,
-                ui._uiconfig._rcfg._rcfg,
-            )
+            checkcache(ui, sharedpath, suffix)
+            checkcache(ui, localpath, suffix, local=True)
 
     ui.write(_("checking commit references\n"))
     _try(ui, checklaggingremotename, repo)
@@ -143,6 +136,43 @@ def repair(ui, name, path, fixfunc, *args, **kwargs):
                     ui.write_err(_("%s: repaired\n") % name)
 
 
+def checkcache(ui, path, suffix=None, local=False):
+    # type: (..., str, typing.Optional[str], bool) -> None
+    """Check and repair the indexedlog stores of a cache directory
+
+    Stores which are fine are only listed with --verbose.
+    """
+    if suffix:
+        path = os.path.join(path, suffix)
+    if not os.path.isdir(path):
+        return
+    with progress.spinner(ui, "checking %s" % path):
+        try:
+            checks = revisionstore.checkcache(path, local, ui._uiconfig._rcfg._rcfg)
+        except Exception as ex:
+            ui.warn(_("%s: failed to check: %s\n") % (path, ex))
+            return
+
+    for name, status, problems, repairlog in checks:
+        storepath = os.path.join(path, name)
+        tracing.singleton.event(
+            (
+                ("cat", "repair"),
+                ("name", "check %s" % storepath),
+                ("details", "%s\n%s" % (status, "\n".join(problems))),
+            )
+        )
+        if status in ("ok", "missing"):
+            if ui.verbose:
+                ui.write_err(_("%s: %s\n") % (storepath, status))
+            continue
+        ui.write_err(_("%s: %s\n") % (storepath, status))
+        for problem in problems:
+            ui.write_err("  %s\n" % problem)
+        if ui.verbose and repairlog:
+            ui.write_err(indent(repairlog))
+
+
 def quickchecklog(ui, log, name, knownbroken):
     """
     knownbroken: a set of known broken *changelog* revisions
@@ -500,12 +530,4 @@ def runglobalindexedlogdoctor(ui):
 
     for path in shallowutil.getallcachepaths(ui):
         for suffix in [None, "manifests"]:
-            repair(
-                ui,
-                "revisionstore",
-                path,
-                revisionstore.repair,
-                None,
-                suffix,
-                ui._uiconfig._rcfg._rcfg,
-            )
+            checkcache(ui, path, suffix)
@@ -449,6 +449,16 @@ def dispatch(req):
                 ui.write_err(msg, label="hgmetrics")
             # Write to blackbox, and sampling
             ui.log("metrics", pformat({"metrics": metrics}, width=1024), **hgmetrics)
+            # Keep the store counters for 'hg debugcachestats'.
+            if (
+                ui.configbool("scmstore", "cachestats")
+                and ui.config("remotefilelog", "cachepath")
+                and any(key.startswith("scmstore.") for key in hgmetrics)
+            ):
+                try:
+                    bindings.revisionstore.recordcachestats(hgmetrics, ui._rcfg._rcfg)
+                except Exception as ex:
+                    ui.debug("cannot record cache stats: %s\n" % ex)
         blackbox.sync()
 
     if util.isoldversion():
@@ -1172,6 +1182,10 @@ def _dispatch(req):
             for ui_ in uis:
                 ui_.setconfig("ui", "interactive", "off", "-y")
 
+        if options["offline"]:
+            for ui_ in uis:
+                ui_.setconfig("scmstore", "offline", "true", "--offline")
+
         if cmdoptions.get("insecure", False):
             for ui_ in uis:
                 ui_.insecureconnections = True
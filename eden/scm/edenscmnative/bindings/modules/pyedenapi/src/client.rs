@@ -59,6 +59,14 @@ py_class!(pub class client |py| {
         self.inner(py).clone().health_py(py)
     }
 
+    /// sparseprofile(repo: str) -> Optional[str]
+    ///
+    /// Path of the sparse profile that new working copies of the repo
+    /// should enable, or None if the server does not recommend one.
+    def sparseprofile(&self, repo: String) -> PyResult<Option<PyPathBuf>> {
+        self.inner(py).clone().sparse_profile_py(py, repo)
+    }
+
     def files(
         &self,
         store: PyObject,
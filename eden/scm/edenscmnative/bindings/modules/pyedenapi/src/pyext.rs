@@ -42,6 +42,13 @@ pub trait EdenApiPyExt: EdenApi {
         meta_to_dict(py, &meta)
     }
 
+    fn sparse_profile_py(&self, py: Python, repo: String) -> PyResult<Option<PyPathBuf>> {
+        let response = py
+            .allow_threads(|| self.sparse_profile_blocking(repo))
+            .map_pyerr(py)?;
+        Ok(response.profile.map(PyPathBuf::from))
+    }
+
     fn files_py(
         self: Arc<Self>,
         py: Python,
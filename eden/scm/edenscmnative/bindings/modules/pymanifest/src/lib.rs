@@ -21,7 +21,7 @@ use manifest_tree::TreeManifest;
 use pathmatcher::{AlwaysMatcher, Matcher, TreeMatcher};
 use pypathmatcher::PythonMatcher;
 use pyrevisionstore::PythonHgIdDataStore;
-use revisionstore::{HgIdDataStore, RemoteDataStore, StoreKey, StoreResult};
+use revisionstore::{HgIdDataStore, LocalStore, RemoteDataStore, StoreKey, StoreResult};
 use types::{Key, Node, RepoPath, RepoPathBuf};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -55,6 +55,19 @@ impl<T: HgIdDataStore + RemoteDataStore> manifest_tree::TreeStore for ManifestSt
         let keys = keys.iter().map(|k| StoreKey::from(k)).collect::<Vec<_>>();
         self.underlying.prefetch(&keys).map(|_| ())
     }
+
+    fn get_missing(&self, keys: &[Key]) -> Result<Vec<Key>> {
+        let keys = keys.iter().map(|k| StoreKey::from(k)).collect::<Vec<_>>();
+        Ok(self
+            .underlying
+            .get_missing(&keys)?
+            .into_iter()
+            .filter_map(|key| match key {
+                StoreKey::HgId(key) => Some(key),
+                StoreKey::Content(_, _) => None,
+            })
+            .collect())
+    }
 }
 
 pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
@@ -88,6 +101,20 @@ pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
             )
         ),
     )?;
+    m.add(
+        py,
+        "warmup",
+        py_fn!(
+            py,
+            warmup(
+                store: PyObject,
+                node: &PyBytes,
+                path: PyPathBuf,
+                batch_size: usize,
+                depth: Option<usize> = None
+            )
+        ),
+    )?;
     Ok(m)
 }
 
@@ -524,6 +551,25 @@ pub fn prefetch(
     Ok(PyNone)
 }
 
+/// Prefetch the trees under `node` missing from `store`, in batches of at most `batch_size`.
+///
+/// Returns the number of trees walked, the number of trees fetched, and the number of batches.
+pub fn warmup(
+    py: Python,
+    store: PyObject,
+    node: &PyBytes,
+    path: PyPathBuf,
+    batch_size: usize,
+    depth: Option<usize>,
+) -> PyResult<(usize, usize, usize)> {
+    let store = Arc::new(ManifestStore::new(PythonHgIdDataStore::new(store)));
+    let node = pybytes_to_node(py, node)?;
+    let repo_path_buf = path.to_repo_path_buf().map_pyerr(py)?;
+    let key = Key::new(repo_path_buf, node);
+    let stats = manifest_tree::warm_up(store, key, batch_size, depth).map_pyerr(py)?;
+    Ok((stats.trees, stats.fetched, stats.batches))
+}
+
 fn insert(
     tree: &mut TreeManifest,
     pending_delete: &mut HashSet<RepoPathBuf>,
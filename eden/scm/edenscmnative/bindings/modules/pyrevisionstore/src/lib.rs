@@ -10,6 +10,7 @@
 #![allow(non_camel_case_types)]
 
 use std::{
+    collections::HashMap,
     convert::TryInto,
     fs::read_dir,
     path::{Path, PathBuf},
@@ -28,6 +29,8 @@ use progress::null::NullProgressFactory;
 use pyconfigparser::config;
 use pyprogress::PyProgressFactory;
 use revisionstore::{
+    cachestats::{counters_from_metrics, CacheStats},
+    newstore::doctor::{check_cache, CacheKind, DoctorOptions},
     repack, ContentStore, ContentStoreBuilder, CorruptionPolicy, DataPack, DataPackStore,
     DataPackVersion, Delta, EdenApiFileStore, EdenApiTreeStore, ExtStoredPolicy, HgIdDataStore,
     HgIdHistoryStore, HgIdMutableDeltaStore, HgIdMutableHistoryStore, HgIdRemoteStore, HistoryPack,
@@ -101,6 +104,16 @@ pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
             )
         ),
     )?;
+    m.add(
+        py,
+        "checkcache",
+        py_fn!(py, checkcache(path: &PyPath, local: bool, config: config)),
+    )?;
+    m.add(
+        py,
+        "recordcachestats",
+        py_fn!(py, recordcachestats(metrics: HashMap<String, usize>, config: config)),
+    )?;
     Ok(m)
 }
 
@@ -165,6 +178,58 @@ fn repair(
     .map(Into::into)
 }
 
+/// Check and repair the stores of the cache directory at `path`.
+///
+/// Returns a list of `(name, status, problems, repairlog)` tuples, one per store.
+fn checkcache(
+    py: Python,
+    path: &PyPath,
+    local: bool,
+    config: config,
+) -> PyResult<Vec<(Str, Str, Vec<Str>, Str)>> {
+    let config = config.get_cfg(py);
+    let kind = if local {
+        CacheKind::Local
+    } else {
+        CacheKind::Shared
+    };
+    let report = py
+        .allow_threads(|| {
+            let options = DoctorOptions::from_config(&config)?;
+            check_cache(path.as_path(), kind, &config, &options)
+        })
+        .map_pyerr(py)?;
+    Ok(report
+        .stores
+        .into_iter()
+        .map(|check| {
+            (
+                check.name.to_string().into(),
+                check.status.to_string().into(),
+                check.problems.into_iter().map(Into::into).collect(),
+                check.repair_log.into(),
+            )
+        })
+        .collect())
+}
+
+/// Add the `scmstore` counters among the process `metrics` to the cache stats reported by
+/// `hg debugcachestats`.
+fn recordcachestats(
+    py: Python,
+    metrics: HashMap<String, usize>,
+    config: config,
+) -> PyResult<PyNone> {
+    let config = config.get_cfg(py);
+    let counters = counters_from_metrics(metrics);
+    if counters.is_empty() {
+        return Ok(PyNone);
+    }
+    py.allow_threads(|| CacheStats::record(CacheStats::path(&config)?, &counters))
+        .map_pyerr(py)?;
+    Ok(PyNone)
+}
+
 py_class!(class datapack |py| {
     data store: Box<DataPack>;
 
@@ -915,7 +980,8 @@ py_class!(pub class contentstore |py| {
         let mut builder = ContentStoreBuilder::new(&config).correlator(correlator);
 
         builder = if let Some(edenapi) = edenapi {
-            builder.remotestore(edenapi.extract_inner(py))
+            let edenapi = edenapi.extract_inner(py);
+            builder.edenapi_files(edenapi.clone()).remotestore(edenapi)
         } else {
             builder.remotestore(remotestore)
         };
@@ -53,8 +53,14 @@ impl BackingStore {
                 let fileremotestore = EdenApiFileStore::new(repo.clone(), edenapi.clone(), None);
                 let treeremotestore = EdenApiTreeStore::new(repo, edenapi, None);
                 (
-                    blobstore.remotestore(fileremotestore).build()?,
-                    treestore.remotestore(treeremotestore).build()?,
+                    blobstore
+                        .edenapi_files(fileremotestore.clone())
+                        .remotestore(fileremotestore)
+                        .build()?,
+                    treestore
+                        .edenapi_trees(treeremotestore.clone())
+                        .remotestore(treeremotestore)
+                        .build()?,
                 )
             }
             _ => (
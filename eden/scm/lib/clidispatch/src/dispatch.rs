@@ -170,6 +170,11 @@ pub fn dispatch(command_table: &CommandTable, args: &[String], io: &IO) -> Resul
         &global_opts.configfile,
         &global_opts.config,
     )?;
+    if global_opts.offline {
+        optional_repo
+            .config_mut()
+            .set("scmstore", "offline", Some("true"), &"--offline".into());
+    }
     let config = optional_repo.config();
 
     initialize_indexedlog(&config)?;
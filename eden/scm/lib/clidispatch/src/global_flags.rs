@@ -71,6 +71,9 @@ define_flags! {
         /// consider hidden changesets
         hidden: bool,
 
+        /// do not fetch file or tree data from the server
+        offline: bool,
+
         /// when to paginate (boolean, always, auto, or never)
         pager: String = "auto",
 
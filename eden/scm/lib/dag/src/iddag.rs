@@ -6,6 +6,7 @@
  */
 
 use crate::errors::bug;
+use crate::errors::programming;
 use crate::errors::NotFoundError;
 use crate::id::{Group, Id};
 #[cfg(any(test, feature = "indexedlog-backend"))]
@@ -545,6 +546,47 @@ impl<Store: IdDagStore> IdDag<Store> {
         }
         Ok(PreparedFlatSegments { segments })
     }
+
+    /// Export flat segments that cover exactly `low..=high`, within a group.
+    ///
+    /// This is used to extend a DAG that already has `..low` with the ids
+    /// that follow. Segments overlapping `low` or `high` are cut. The part of
+    /// a flat segment above `low` has `low - 1` as its only parent.
+    pub fn flat_segments_range(&self, low: Id, high: Id) -> Result<PreparedFlatSegments> {
+        let level = 0;
+        let mut segments = Vec::new();
+        if low > high {
+            return Ok(PreparedFlatSegments { segments });
+        }
+        if low.group() != high.group() {
+            return programming(format!(
+                "flat_segments_range: {} and {} are in different groups",
+                low, high
+            ));
+        }
+        for sr in self.iter_segments_ascending(low, level)? {
+            let segment = sr?;
+            let span = segment.span()?;
+            if span.low > high {
+                break;
+            }
+            let fs = if span.low < low {
+                FlatSegment {
+                    low,
+                    high: span.high.min(high),
+                    parents: vec![low - 1],
+                }
+            } else {
+                FlatSegment {
+                    low: span.low,
+                    high: span.high.min(high),
+                    parents: segment.parents()?,
+                }
+            };
+            segments.push(fs);
+        }
+        Ok(PreparedFlatSegments { segments })
+    }
 }
 
 // User-facing DAG-related algorithms.
@@ -1657,4 +1699,40 @@ mod tests {
         assert_eq!(test_dag.max_level().unwrap(), 3);
         assert_eq!(test_dag.all().unwrap().count(), 1002);
     }
+
+    #[test]
+    fn test_flat_segments_range() {
+        let dir = tempdir().unwrap();
+        let test_dir = tempdir().unwrap();
+        let mut dag = IdDag::open(dir.path()).unwrap();
+        let mut test_dag = IdDag::open(test_dir.path()).unwrap();
+
+        // A linear dag is a single flat segment, which gets cut.
+        let get_parents = |id: Id| -> Result<Vec<Id>> {
+            match id.0 {
+                0 => Ok(Vec::new()),
+                _ => Ok(vec![id - 1]),
+            }
+        };
+        dag.build_segments_volatile(Id(100), &get_parents).unwrap();
+
+        let segments = dag.flat_segments_range(Id(0), Id(40)).unwrap();
+        test_dag
+            .build_segments_volatile_from_prepared_flat_segments(&segments)
+            .unwrap();
+        assert_eq!(test_dag.all().unwrap().count(), 41);
+
+        let segments = dag.flat_segments_range(Id(41), Id(100)).unwrap();
+        assert_eq!(segments.segments.len(), 1);
+        assert_eq!(segments.segments[0].low, Id(41));
+        assert_eq!(segments.segments[0].parents, vec![Id(40)]);
+        test_dag
+            .build_segments_volatile_from_prepared_flat_segments(&segments)
+            .unwrap();
+        assert_eq!(test_dag.all().unwrap().count(), 101);
+        assert_eq!(test_dag.parent_ids(Id(41)).unwrap(), vec![Id(40)]);
+
+        let segments = dag.flat_segments_range(Id(101), Id(100)).unwrap();
+        assert!(segments.segments.is_empty());
+    }
 }
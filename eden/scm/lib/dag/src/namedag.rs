@@ -27,6 +27,7 @@ use crate::nameset::SyncNameSetQuery;
 use crate::ops::DagAddHeads;
 use crate::ops::DagAlgorithm;
 use crate::ops::DagImportCloneData;
+use crate::ops::DagImportPullData;
 use crate::ops::DagPersistent;
 use crate::ops::IdConvert;
 use crate::ops::IdMapSnapshot;
@@ -237,6 +238,32 @@ where
     }
 }
 
+impl<IS, M, P, S> DagImportPullData for AbstractNameDag<IdDag<IS>, M, P, S>
+where
+    IS: IdDagStore,
+    M: IdMapAssignHead + Send + Sync,
+    P: Send + Sync,
+    S: Send + Sync,
+{
+    fn import_pull_data(&mut self, pull_data: CloneData<VertexName>) -> Result<()> {
+        if let Some(first) = pull_data.flat_segments.segments.first() {
+            let next_id = self.dag.next_free_id(0, Group::MASTER)?;
+            if first.low != next_id {
+                return programming(format!(
+                    "pull data starts at {} but the master group ends before {}",
+                    first.low, next_id
+                ));
+            }
+        }
+        for (id, name) in pull_data.idmap {
+            self.map.insert(id, name.as_ref())?;
+        }
+        self.dag
+            .build_segments_volatile_from_prepared_flat_segments(&pull_data.flat_segments)?;
+        Ok(())
+    }
+}
+
 impl<IS, M, P, S> AbstractNameDag<IdDag<IS>, M, P, S>
 where
     IS: IdDagStore,
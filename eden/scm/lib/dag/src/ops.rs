@@ -234,6 +234,14 @@ pub trait DagImportCloneData {
     fn import_clone_data(&mut self, clone_data: CloneData<VertexName>) -> Result<()>;
 }
 
+/// Import a generated `CloneData` object covering only the commits that are
+/// new since the last clone or pull into the DAG.
+pub trait DagImportPullData {
+    /// Extends the master group using a `CloneData` object whose flat segments
+    /// start right after the ids that are already in the master group.
+    fn import_pull_data(&mut self, pull_data: CloneData<VertexName>) -> Result<()>;
+}
+
 /// Persistent the DAG on disk.
 #[async_trait::async_trait]
 pub trait DagPersistent {
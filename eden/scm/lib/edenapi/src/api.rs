@@ -9,8 +9,8 @@ use async_trait::async_trait;
 
 use edenapi_types::{
     CloneData, CommitHashToLocationResponse, CommitLocationToHashRequest,
-    CommitLocationToHashResponse, CommitRevlogData, EdenApiServerError, FileEntry, HistoryEntry,
-    TreeAttributes, TreeEntry,
+    CommitLocationToHashResponse, CommitRevlogData, EdenApiServerError, FileAuxData, FileEntry,
+    HistoryEntry, SparseProfileResponse, TreeAttributes, TreeEntry,
 };
 use http_client::Progress;
 use types::{HgId, Key, RepoPathBuf};
@@ -31,6 +31,14 @@ pub trait EdenApi: Send + Sync + 'static {
         progress: Option<ProgressCallback>,
     ) -> Result<Fetch<FileEntry>, EdenApiError>;
 
+    /// Fetch the size and content hashes of files, without their content.
+    async fn files_aux(
+        &self,
+        repo: String,
+        keys: Vec<Key>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Fetch<FileAuxData>, EdenApiError>;
+
     async fn history(
         &self,
         repo: String,
@@ -76,6 +84,16 @@ pub trait EdenApi: Send + Sync + 'static {
         progress: Option<ProgressCallback>,
     ) -> Result<CloneData<HgId>, EdenApiError>;
 
+    /// The segmented changelog data needed to move a client's master group,
+    /// known up to `old_master`, to `new_master`. Only the commits that are
+    /// not in the client's master group yet are included.
+    async fn pull_fast_forward_master(
+        &self,
+        repo: String,
+        old_master: HgId,
+        new_master: HgId,
+    ) -> Result<CloneData<HgId>, EdenApiError>;
+
     async fn commit_location_to_hash(
         &self,
         repo: String,
@@ -90,4 +108,8 @@ pub trait EdenApi: Send + Sync + 'static {
         hgids: Vec<HgId>,
         progress: Option<ProgressCallback>,
     ) -> Result<Fetch<CommitHashToLocationResponse>, EdenApiError>;
+
+    /// The sparse profile that new working copies of the repo should enable,
+    /// if the server has one configured.
+    async fn sparse_profile(&self, repo: String) -> Result<SparseProfileResponse, EdenApiError>;
 }
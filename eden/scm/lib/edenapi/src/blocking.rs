@@ -8,8 +8,8 @@
 use async_runtime::block_on_exclusive as block_on_future;
 use edenapi_types::{
     CloneData, CommitHashToLocationResponse, CommitLocationToHashRequest,
-    CommitLocationToHashResponse, CommitRevlogData, EdenApiServerError, FileEntry, HistoryEntry,
-    TreeAttributes, TreeEntry,
+    CommitLocationToHashResponse, CommitRevlogData, EdenApiServerError, FileAuxData, FileEntry,
+    HistoryEntry, SparseProfileResponse, TreeAttributes, TreeEntry,
 };
 use types::{HgId, Key, RepoPathBuf};
 
@@ -31,6 +31,15 @@ pub trait EdenApiBlocking: EdenApi {
         BlockingFetch::from_async(self.files(repo, keys, progress))
     }
 
+    fn files_aux_blocking(
+        &self,
+        repo: String,
+        keys: Vec<Key>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<BlockingFetch<FileAuxData>, EdenApiError> {
+        BlockingFetch::from_async(self.files_aux(repo, keys, progress))
+    }
+
     fn history_blocking(
         &self,
         repo: String,
@@ -95,6 +104,15 @@ pub trait EdenApiBlocking: EdenApi {
         block_on_future(self.full_idmap_clone_data(repo, progress))
     }
 
+    fn pull_fast_forward_master_blocking(
+        &self,
+        repo: String,
+        old_master: HgId,
+        new_master: HgId,
+    ) -> Result<CloneData<HgId>, EdenApiError> {
+        block_on_future(self.pull_fast_forward_master(repo, old_master, new_master))
+    }
+
     fn commit_location_to_hash_blocking(
         &self,
         repo: String,
@@ -113,6 +131,10 @@ pub trait EdenApiBlocking: EdenApi {
     ) -> Result<BlockingFetch<CommitHashToLocationResponse>, EdenApiError> {
         BlockingFetch::from_async(self.commit_hash_to_location(repo, repo_master, hgids, progress))
     }
+
+    fn sparse_profile_blocking(&self, repo: String) -> Result<SparseProfileResponse, EdenApiError> {
+        block_on_future(self.sparse_profile(repo))
+    }
 }
 
 impl<T: EdenApi + ?Sized> EdenApiBlocking for T {}
@@ -34,6 +34,12 @@ pub struct Builder {
     max_history: Option<usize>,
     max_location_to_hash: Option<usize>,
     timeout: Option<Duration>,
+    files_timeout: Option<Duration>,
+    trees_timeout: Option<Duration>,
+    max_connections: Option<usize>,
+    max_host_connections: Option<usize>,
+    max_cached_connections: Option<usize>,
+    max_concurrent_streams: Option<usize>,
     debug: bool,
     correlator: Option<String>,
     http_version: Option<HttpVersion>,
@@ -64,12 +70,28 @@ impl Builder {
             .map_err(|e| ConfigError::Malformed("edenapi.validate-certs".into(), e))?
             .unwrap_or_default();
 
-        let (cert, key, ca_bundle) = AuthSection::from_config(&config)
+        let (auth_cert, auth_key, auth_ca_bundle) = AuthSection::from_config(&config)
             .validate(validate_certs)
             .best_match_for(&server_url)?
             .map(|auth| (auth.cert, auth.key, auth.cacerts))
             .unwrap_or_default();
 
+        // Paths set in the edenapi section take precedence over the auth section.
+        let cert = config
+            .get_opt::<PathBuf>("edenapi", "cert")
+            .map_err(|e| ConfigError::Malformed("edenapi.cert".into(), e))?
+            .or(auth_cert);
+
+        let key = config
+            .get_opt::<PathBuf>("edenapi", "key")
+            .map_err(|e| ConfigError::Malformed("edenapi.key".into(), e))?
+            .or(auth_key);
+
+        let ca_bundle = config
+            .get_opt::<PathBuf>("edenapi", "cacerts")
+            .map_err(|e| ConfigError::Malformed("edenapi.cacerts".into(), e))?
+            .or(auth_ca_bundle);
+
         let headers = config
             .get_opt::<String>("edenapi", "headers")
             .map_err(|e| ConfigError::Malformed("edenapi.headers".into(), e))?
@@ -99,6 +121,32 @@ impl Builder {
             .map_err(|e| ConfigError::Malformed("edenapi.timeout".into(), e))?
             .map(Duration::from_secs);
 
+        let files_timeout = config
+            .get_opt("edenapi", "files-timeout")
+            .map_err(|e| ConfigError::Malformed("edenapi.files-timeout".into(), e))?
+            .map(Duration::from_secs);
+
+        let trees_timeout = config
+            .get_opt("edenapi", "trees-timeout")
+            .map_err(|e| ConfigError::Malformed("edenapi.trees-timeout".into(), e))?
+            .map(Duration::from_secs);
+
+        let max_connections = config
+            .get_opt("edenapi", "max-connections")
+            .map_err(|e| ConfigError::Malformed("edenapi.max-connections".into(), e))?;
+
+        let max_host_connections = config
+            .get_opt("edenapi", "max-host-connections")
+            .map_err(|e| ConfigError::Malformed("edenapi.max-host-connections".into(), e))?;
+
+        let max_cached_connections = config
+            .get_opt("edenapi", "max-cached-connections")
+            .map_err(|e| ConfigError::Malformed("edenapi.max-cached-connections".into(), e))?;
+
+        let max_concurrent_streams = config
+            .get_opt("edenapi", "max-concurrent-streams")
+            .map_err(|e| ConfigError::Malformed("edenapi.max-concurrent-streams".into(), e))?;
+
         let debug = config
             .get_opt("edenapi", "debug")
             .map_err(|e| ConfigError::Malformed("edenapi.timeout".into(), e))?
@@ -130,6 +178,12 @@ impl Builder {
             max_history,
             max_location_to_hash,
             timeout,
+            files_timeout,
+            trees_timeout,
+            max_connections,
+            max_host_connections,
+            max_cached_connections,
+            max_concurrent_streams,
             debug,
             correlator: None,
             http_version,
@@ -222,6 +276,58 @@ impl Builder {
         self
     }
 
+    /// Timeout for file requests, overriding `timeout`. File requests are
+    /// usually small and latency-sensitive.
+    pub fn files_timeout(mut self, timeout: Duration) -> Self {
+        self.files_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for tree requests, overriding `timeout`. Large tree batches,
+    /// such as those of a checkout, may need more time than other requests.
+    pub fn trees_timeout(mut self, timeout: Duration) -> Self {
+        self.trees_timeout = Some(timeout);
+        self
+    }
+
+    /// Maximum number of connections the client opens at once when sending
+    /// concurrent requests. Unlimited by default.
+    pub fn max_connections(mut self, max_connections: Option<usize>) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Maximum number of connections the client opens to the server at once.
+    /// With HTTP/2, concurrent requests share connections, so this only
+    /// matters once `max_concurrent_streams` is reached. Unlimited by default.
+    pub fn max_host_connections(mut self, max_host_connections: Option<usize>) -> Self {
+        self.max_host_connections = max_host_connections;
+        self
+    }
+
+    /// Maximum number of idle connections kept open for reuse by later
+    /// requests. Defaults to libcurl's, which grows with the number of
+    /// concurrent requests.
+    pub fn max_cached_connections(mut self, max_cached_connections: Option<usize>) -> Self {
+        self.max_cached_connections = max_cached_connections;
+        self
+    }
+
+    /// Maximum number of concurrent requests multiplexed over a single
+    /// HTTP/2 connection. Defaults to libcurl's, which is 100.
+    pub fn max_concurrent_streams(mut self, max_concurrent_streams: Option<usize>) -> Self {
+        self.max_concurrent_streams = max_concurrent_streams;
+        self
+    }
+
+    /// HTTP version to use. With HTTP/2 (the default), concurrent requests
+    /// are multiplexed over shared connections rather than each opening
+    /// their own.
+    pub fn http_version(mut self, http_version: HttpVersion) -> Self {
+        self.http_version = Some(http_version);
+        self
+    }
+
     /// Unique identifier that will be logged by both the client and server for
     /// every request, allowing log entries on both sides to be correlated. Also
     /// allows correlating multiple requests that were made by the same instance
@@ -247,12 +353,25 @@ pub(crate) struct Config {
     pub(crate) max_history: Option<usize>,
     pub(crate) max_location_to_hash: Option<usize>,
     pub(crate) timeout: Option<Duration>,
+    pub(crate) files_timeout: Option<Duration>,
+    pub(crate) trees_timeout: Option<Duration>,
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) max_host_connections: Option<usize>,
+    pub(crate) max_cached_connections: Option<usize>,
+    pub(crate) max_concurrent_streams: Option<usize>,
     pub(crate) debug: bool,
     pub(crate) correlator: Option<String>,
     pub(crate) http_version: Option<HttpVersion>,
     pub(crate) validate_certs: bool,
 }
 
+impl Config {
+    /// Whether concurrent requests share HTTP/2 connections.
+    pub(crate) fn multiplex(&self) -> bool {
+        !matches!(self.http_version, Some(HttpVersion::V11))
+    }
+}
+
 impl TryFrom<Builder> for Config {
     type Error = EdenApiError;
 
@@ -268,6 +387,12 @@ impl TryFrom<Builder> for Config {
             max_history,
             max_location_to_hash,
             timeout,
+            files_timeout,
+            trees_timeout,
+            max_connections,
+            max_host_connections,
+            max_cached_connections,
+            max_concurrent_streams,
             debug,
             correlator,
             http_version,
@@ -288,6 +413,10 @@ impl TryFrom<Builder> for Config {
         let max_files = max_files.filter(|n| *n > 0);
         let max_trees = max_trees.filter(|n| *n > 0);
         let max_history = max_history.filter(|n| *n > 0);
+        let max_connections = max_connections.filter(|n| *n > 0);
+        let max_host_connections = max_host_connections.filter(|n| *n > 0);
+        let max_cached_connections = max_cached_connections.filter(|n| *n > 0);
+        let max_concurrent_streams = max_concurrent_streams.filter(|n| *n > 0);
 
         Ok(Config {
             server_url,
@@ -300,6 +429,12 @@ impl TryFrom<Builder> for Config {
             max_history,
             max_location_to_hash,
             timeout,
+            files_timeout,
+            trees_timeout,
+            max_connections,
+            max_host_connections,
+            max_cached_connections,
+            max_concurrent_streams,
             debug,
             correlator,
             http_version,
@@ -7,6 +7,7 @@
 
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::time::Duration;
 
 use anyhow::format_err;
 use async_trait::async_trait;
@@ -21,14 +22,14 @@ use auth::check_certs;
 use edenapi_types::{
     wire::{
         WireCloneData, WireCommitHashToLocationResponse, WireCommitLocationToHashResponse,
-        WireFileEntry, WireHistoryResponseChunk, WireIdMapEntry, WireToApiConversionError,
-        WireTreeEntry,
+        WireFileAuxData, WireFileEntry, WireHistoryResponseChunk, WireIdMapEntry,
+        WireSparseProfileResponse, WireToApiConversionError, WireTreeEntry,
     },
     CloneData, CommitHashToLocationRequestBatch, CommitHashToLocationResponse,
     CommitLocationToHashRequest, CommitLocationToHashRequestBatch, CommitLocationToHashResponse,
-    CommitRevlogData, CommitRevlogDataRequest, CompleteTreeRequest, EdenApiServerError, FileEntry,
-    FileRequest, HistoryEntry, HistoryRequest, ToApi, ToWire, TreeAttributes, TreeEntry,
-    TreeRequest,
+    CommitRevlogData, CommitRevlogDataRequest, CompleteTreeRequest, EdenApiServerError,
+    FileAuxData, FileEntry, FileRequest, HistoryEntry, HistoryRequest, PullFastForwardRequest,
+    SparseProfileResponse, ToApi, ToWire, TreeAttributes, TreeEntry, TreeRequest,
 };
 use hg_http::http_client;
 use http_client::{AsyncResponse, HttpClient, HttpClientError, Progress, Request};
@@ -46,14 +47,17 @@ const RESERVED_CHARS: &AsciiSet = &NON_ALPHANUMERIC.remove(b'_').remove(b'-').re
 mod paths {
     pub const HEALTH_CHECK: &str = "health_check";
     pub const FILES: &str = "files";
+    pub const FILES_AUX: &str = "files/aux";
     pub const HISTORY: &str = "history";
     pub const TREES: &str = "trees";
     pub const COMPLETE_TREES: &str = "trees/complete";
     pub const COMMIT_REVLOG_DATA: &str = "commit/revlog_data";
     pub const CLONE_DATA: &str = "clone";
     pub const FULL_IDMAP_CLONE_DATA: &str = "full_idmap_clone";
+    pub const PULL_FAST_FORWARD_MASTER: &str = "pull_fast_forward_master";
     pub const COMMIT_LOCATION_TO_HASH: &str = "commit/location_to_hash";
     pub const COMMIT_HASH_TO_LOCATION: &str = "commit/hash_to_location";
+    pub const SPARSE_PROFILE: &str = "sparse_profile";
 }
 
 pub struct Client {
@@ -64,7 +68,14 @@ pub struct Client {
 impl Client {
     /// Create an EdenAPI client with the given configuration.
     pub(crate) fn with_config(config: Config) -> Self {
-        let client = http_client("edenapi").verbose(config.debug);
+        let multiplex = config.multiplex();
+        let client = http_client("edenapi")
+            .verbose(config.debug)
+            .max_connections(config.max_connections)
+            .max_host_connections(config.max_host_connections)
+            .max_cached_connections(config.max_cached_connections)
+            .max_concurrent_streams(config.max_concurrent_streams)
+            .multiplex(multiplex);
         Self { config, client }
     }
 
@@ -113,6 +124,12 @@ impl Client {
             req = req.http_version(http_version);
         }
 
+        // Rather than opening a connection per request, wait to find out
+        // whether the server supports multiplexing them over one connection.
+        if self.config.multiplex() {
+            req = req.pipewait(true);
+        }
+
         Ok(req)
     }
 
@@ -258,10 +275,36 @@ impl EdenApi for Client {
         let requests = self.prepare(&url, keys, self.config.max_files, |keys| {
             FileRequest { keys }.to_wire()
         })?;
+        let requests = with_timeout(requests, self.config.files_timeout);
 
         Ok(self.fetch::<WireFileEntry>(requests, progress).await?)
     }
 
+    async fn files_aux(
+        &self,
+        repo: String,
+        keys: Vec<Key>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Fetch<FileAuxData>, EdenApiError> {
+        let msg = format!("Requesting aux data for {} file(s)", keys.len());
+        tracing::info!("{}", &msg);
+        if self.config.debug {
+            eprintln!("{}", &msg);
+        }
+
+        if keys.is_empty() {
+            return Ok(Fetch::empty());
+        }
+
+        let url = self.url(paths::FILES_AUX, Some(&repo))?;
+        let requests = self.prepare(&url, keys, self.config.max_files, |keys| {
+            FileRequest { keys }.to_wire()
+        })?;
+        let requests = with_timeout(requests, self.config.files_timeout);
+
+        Ok(self.fetch::<WireFileAuxData>(requests, progress).await?)
+    }
+
     async fn history(
         &self,
         repo: String,
@@ -330,6 +373,7 @@ impl EdenApi for Client {
             }
             .to_wire()
         })?;
+        let requests = with_timeout(requests, self.config.trees_timeout);
 
         Ok(self.fetch::<WireTreeEntry>(requests, progress).await?)
     }
@@ -359,6 +403,7 @@ impl EdenApi for Client {
             mfnodes,
             basemfnodes,
             depth,
+            prefixes: Vec::new(),
         }
         .to_wire();
 
@@ -461,6 +506,37 @@ impl EdenApi for Client {
         Ok(clone_data)
     }
 
+    async fn pull_fast_forward_master(
+        &self,
+        repo: String,
+        old_master: HgId,
+        new_master: HgId,
+    ) -> Result<CloneData<HgId>, EdenApiError> {
+        let msg = format!(
+            "Requesting pull fast forward from {} to {} for the '{}' repository",
+            old_master, new_master, repo
+        );
+        tracing::info!("{}", &msg);
+        if self.config.debug {
+            eprintln!("{}", &msg);
+        }
+
+        let url = self.url(paths::PULL_FAST_FORWARD_MASTER, Some(&repo))?;
+        let pull_fast_forward_req = PullFastForwardRequest {
+            old_master,
+            new_master,
+        };
+        let req = self
+            .configure(Request::post(url))?
+            .cbor(&pull_fast_forward_req.to_wire())
+            .map_err(EdenApiError::RequestSerializationFailed)?;
+        let mut fetch = self.fetch::<WireCloneData>(vec![req], None).await?;
+        let pull_data = fetch.entries.next().await.ok_or_else(|| {
+            EdenApiError::Other(format_err!("pull data missing from reponse body"))
+        })??;
+        Ok(pull_data)
+    }
+
     async fn commit_location_to_hash(
         &self,
         repo: String,
@@ -528,6 +604,27 @@ impl EdenApi for Client {
             .fetch::<WireCommitHashToLocationResponse>(formatted, progress)
             .await?)
     }
+
+    async fn sparse_profile(&self, repo: String) -> Result<SparseProfileResponse, EdenApiError> {
+        let msg = format!(
+            "Requesting default sparse profile for the '{}' repository",
+            repo
+        );
+        tracing::info!("{}", &msg);
+        if self.config.debug {
+            eprintln!("{}", &msg);
+        }
+
+        let url = self.url(paths::SPARSE_PROFILE, Some(&repo))?;
+        let req = self.configure(Request::get(url))?;
+        let mut fetch = self
+            .fetch::<WireSparseProfileResponse>(vec![req], None)
+            .await?;
+        let response = fetch.entries.next().await.ok_or_else(|| {
+            EdenApiError::Other(format_err!("sparse profile missing from response body"))
+        })??;
+        Ok(response)
+    }
 }
 
 /// Split up a collection of keys into batches of at most `batch_size`.
@@ -546,16 +643,35 @@ fn split_into_batches<T>(
     }
 }
 
+/// Override the timeout set by `Client::configure`, for requests whose type has its own timeout.
+fn with_timeout(requests: Vec<Request>, timeout: Option<Duration>) -> Vec<Request> {
+    match timeout {
+        Some(timeout) => requests
+            .into_iter()
+            .map(|req| req.timeout(timeout))
+            .collect(),
+        None => requests,
+    }
+}
+
 async fn raise_for_status(res: AsyncResponse) -> Result<AsyncResponse, EdenApiError> {
     if res.status.as_u16() < 400 {
         return Ok(res);
     }
 
+    // Only the delay-seconds form of Retry-After is supported; the server never sends dates.
+    let retry_after = res
+        .headers
+        .get(http::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs);
     let body = res.body.try_concat().await?;
     let message = String::from_utf8_lossy(&body).into_owned();
     Err(EdenApiError::HttpError {
         status: res.status,
         message,
+        retry_after,
     })
 }
 
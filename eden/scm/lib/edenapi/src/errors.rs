@@ -5,6 +5,8 @@
  * GNU General Public License version 2.
  */
 
+use std::time::Duration;
+
 use thiserror::Error;
 
 use auth::X509Error;
@@ -23,7 +25,13 @@ pub enum EdenApiError {
     #[error(transparent)]
     Http(#[from] HttpClientError),
     #[error("Server reported an error ({status}): {message}")]
-    HttpError { status: StatusCode, message: String },
+    HttpError {
+        status: StatusCode,
+        message: String,
+        /// How long the server asked the client to wait before retrying, from the
+        /// `Retry-After` header.
+        retry_after: Option<Duration>,
+    },
     #[error(transparent)]
     InvalidUrl(#[from] url::ParseError),
     #[error(transparent)]
@@ -34,6 +42,32 @@ pub enum EdenApiError {
     Other(#[from] anyhow::Error),
 }
 
+impl EdenApiError {
+    /// How long the server asked the client to wait before retrying, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            EdenApiError::HttpError { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Whether the server rejected the request because the client should slow down, rather than
+    /// because the request failed.
+    pub fn is_throttled(&self) -> bool {
+        match self {
+            EdenApiError::HttpError {
+                status,
+                retry_after,
+                ..
+            } => {
+                *status == StatusCode::TOO_MANY_REQUESTS
+                    || (*status == StatusCode::SERVICE_UNAVAILABLE && retry_after.is_some())
+            }
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ConfigError {
     #[error("No server URL specified")]
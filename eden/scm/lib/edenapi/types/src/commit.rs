@@ -112,6 +112,26 @@ impl Arbitrary for CommitHashToLocationResponse {
     }
 }
 
+/// Request for the segmented changelog data that a client with the master group up to
+/// `old_master` needs to move it to `new_master`. The response is a `CloneData` that only has
+/// the commits that are new to the client.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+pub struct PullFastForwardRequest {
+    pub old_master: HgId,
+    pub new_master: HgId,
+}
+
+#[cfg(any(test, feature = "for-tests"))]
+impl Arbitrary for PullFastForwardRequest {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        PullFastForwardRequest {
+            old_master: Arbitrary::arbitrary(g),
+            new_master: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
 /// The list of Mercurial commit identifiers for which we want the commit data to be returned.
 #[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[derive(Serialize, Deserialize)]
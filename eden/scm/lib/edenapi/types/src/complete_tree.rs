@@ -19,12 +19,19 @@ use types::{hgid::HgId, path::RepoPathBuf};
 /// containing the keys of the desired tree nodes.
 ///
 /// In all cases, trees will be returned in a `TreeResponse`.
+///
+/// If `prefixes` is non-empty, only directories that lead to, match, or lie
+/// beneath one of the given paths (relative to the repository root) will be
+/// returned. This allows a client to hydrate a few subtrees in one request
+/// without fetching every sibling directory along the way.
 #[derive(Clone, Debug, Eq, Deserialize, Serialize, PartialEq)]
 pub struct CompleteTreeRequest {
     pub rootdir: RepoPathBuf,
     pub mfnodes: Vec<HgId>,
     pub basemfnodes: Vec<HgId>,
     pub depth: Option<usize>,
+    #[serde(default)]
+    pub prefixes: Vec<RepoPathBuf>,
 }
 
 impl CompleteTreeRequest {
@@ -39,8 +46,15 @@ impl CompleteTreeRequest {
             mfnodes,
             basemfnodes,
             depth,
+            prefixes: Vec::new(),
         }
     }
+
+    /// Restrict the returned trees to the given path prefixes.
+    pub fn with_prefixes(mut self, prefixes: Vec<RepoPathBuf>) -> Self {
+        self.prefixes = prefixes;
+        self
+    }
 }
 
 #[cfg(any(test, feature = "for-tests"))]
@@ -54,6 +68,7 @@ impl Arbitrary for CompleteTreeRequest {
             mfnodes: Arbitrary::arbitrary(g),
             basemfnodes: Arbitrary::arbitrary(g),
             depth: Arbitrary::arbitrary(g),
+            prefixes: Arbitrary::arbitrary(g),
         }
     }
 }
@@ -15,7 +15,7 @@ use quickcheck::Arbitrary;
 use revisionstore_types::Metadata;
 use types::{hgid::HgId, key::Key, parents::Parents};
 
-use crate::InvalidHgId;
+use crate::{Blake3, InvalidHgId, Sha256};
 
 /// Tombstone string that replaces the content of redacted files.
 /// TODO(T48685378): Handle redacted content in a less hacky way.
@@ -145,6 +145,28 @@ impl Arbitrary for FileEntry {
     }
 }
 
+/// The size and content hashes of a file, without copy metadata. For LFS files, they are those
+/// of the LFS blob rather than of the pointer.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct FileAuxData {
+    pub key: Key,
+    pub total_size: u64,
+    pub content_sha256: Sha256,
+    pub content_blake3: Blake3,
+}
+
+#[cfg(any(test, feature = "for-tests"))]
+impl Arbitrary for FileAuxData {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        Self {
+            key: Arbitrary::arbitrary(g),
+            total_size: Arbitrary::arbitrary(g),
+            content_sha256: Arbitrary::arbitrary(g),
+            content_blake3: Arbitrary::arbitrary(g),
+        }
+    }
+}
+
 #[derive(Clone, Default, Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub struct FileRequest {
     pub keys: Vec<Key>,
@@ -282,10 +282,14 @@ pub fn parse_history_req(value: &Value) -> Result<HistoryRequest> {
 ///         "26d6acbabf823b844917f04cfbe6747c80983119",
 ///         "111caaed68164b939f6e2f58680b462ebc3174c7"
 ///     ],
-///     "depth": 1
+///     "depth": 1,
+///     "prefixes": ["path/to/root/dir/subdir"]
 /// }
 /// ```
 ///
+/// The `prefixes` field is optional; if present, only directories along
+/// or beneath the given paths are returned.
+///
 pub fn parse_complete_tree_req(value: &Value) -> Result<CompleteTreeRequest> {
     let obj = value.as_object().context("input must be a JSON object")?;
 
@@ -306,11 +310,25 @@ pub fn parse_complete_tree_req(value: &Value) -> Result<CompleteTreeRequest> {
         .and_then(|d| d.as_u64())
         .map(|d| d as usize);
 
+    let prefixes = match obj.get("prefixes") {
+        Some(prefixes) => prefixes
+            .as_array()
+            .context("prefixes field must be an array")?
+            .iter()
+            .map(|p| {
+                let p = p.as_str().context("prefixes must be strings")?;
+                Ok(RepoPathBuf::from_string(p.to_string())?)
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+
     Ok(CompleteTreeRequest {
         rootdir,
         mfnodes,
         basemfnodes,
         depth,
+        prefixes,
     })
 }
 
@@ -543,6 +561,7 @@ impl ToJson for CompleteTreeRequest {
             "mfnodes": self.mfnodes.to_json(),
             "basemfnodes": self.basemfnodes.to_json(),
             "depth": self.depth,
+            "prefixes": self.prefixes,
         })
     }
 }
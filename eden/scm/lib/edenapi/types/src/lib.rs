@@ -31,23 +31,25 @@ pub mod file;
 pub mod history;
 pub mod json;
 pub mod metadata;
+pub mod sparse;
 pub mod tree;
 pub mod wire;
 
 pub use crate::commit::{
     CommitHashToLocationRequestBatch, CommitHashToLocationResponse, CommitLocationToHashRequest,
     CommitLocationToHashRequestBatch, CommitLocationToHashResponse, CommitRevlogData,
-    CommitRevlogDataRequest,
+    CommitRevlogDataRequest, PullFastForwardRequest,
 };
 pub use crate::complete_tree::CompleteTreeRequest;
-pub use crate::file::{FileEntry, FileError, FileRequest};
+pub use crate::file::{FileAuxData, FileEntry, FileError, FileRequest};
 pub use crate::history::{
     HistoryEntry, HistoryRequest, HistoryResponse, HistoryResponseChunk, WireHistoryEntry,
 };
 pub use crate::metadata::{
-    ContentId, DirectoryMetadata, DirectoryMetadataRequest, FileMetadata, FileMetadataRequest,
-    FileType, FsnodeId, Sha1, Sha256,
+    Blake3, ContentId, DirectoryMetadata, DirectoryMetadataRequest, FileMetadata,
+    FileMetadataRequest, FileType, FsnodeId, Sha1, Sha256,
 };
+pub use crate::sparse::SparseProfileResponse;
 pub use crate::tree::{
     TreeAttributes, TreeChildDirectoryEntry, TreeChildEntry, TreeChildFileEntry, TreeEntry,
     TreeError, TreeRequest,
@@ -103,6 +103,29 @@ impl fmt::Debug for Sha256 {
     }
 }
 
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blake3(pub [u8; 32]);
+
+impl fmt::Display for Blake3 {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Blake3(\"")?;
+        for d in &self.0 {
+            write!(fmt, "{:02x}", d)?;
+        }
+        write!(fmt, "\")")
+    }
+}
+
+impl fmt::Debug for Blake3 {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Blake3(\"")?;
+        for d in &self.0 {
+            write!(fmt, "{:02x}", d)?;
+        }
+        write!(fmt, "\")")
+    }
+}
+
 #[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ContentId(pub [u8; 32]);
 
@@ -250,6 +273,15 @@ impl Arbitrary for ContentId {
     }
 }
 
+#[cfg(any(test, feature = "for-tests"))]
+impl Arbitrary for Blake3 {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let mut v = Self::default();
+        g.fill_bytes(&mut v.0);
+        v
+    }
+}
+
 #[cfg(any(test, feature = "for-tests"))]
 impl Arbitrary for Sha1 {
     fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
@@ -0,0 +1,30 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#[cfg(any(test, feature = "for-tests"))]
+use quickcheck::Arbitrary;
+use serde_derive::{Deserialize, Serialize};
+
+use types::RepoPathBuf;
+
+/// The sparse profile that the server recommends enabling in new working
+/// copies of the repository. `profile` is `None` if the repository does not
+/// have a default sparse profile configured.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+pub struct SparseProfileResponse {
+    pub profile: Option<RepoPathBuf>,
+}
+
+#[cfg(any(test, feature = "for-tests"))]
+impl Arbitrary for SparseProfileResponse {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        SparseProfileResponse {
+            profile: Arbitrary::arbitrary(g),
+        }
+    }
+}
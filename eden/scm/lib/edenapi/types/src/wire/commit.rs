@@ -14,7 +14,7 @@ use types::HgId;
 
 use crate::commit::{
     CommitHashToLocationRequestBatch, CommitHashToLocationResponse, CommitLocationToHashRequest,
-    CommitLocationToHashRequestBatch, CommitLocationToHashResponse,
+    CommitLocationToHashRequestBatch, CommitLocationToHashResponse, PullFastForwardRequest,
 };
 use crate::wire::{ToApi, ToWire, WireHgId, WireToApiConversionError};
 
@@ -252,6 +252,45 @@ impl Arbitrary for WireCommitHashToLocationResponse {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct WirePullFastForwardRequest {
+    #[serde(rename = "1")]
+    pub old_master: WireHgId,
+    #[serde(rename = "2")]
+    pub new_master: WireHgId,
+}
+
+impl ToWire for PullFastForwardRequest {
+    type Wire = WirePullFastForwardRequest;
+
+    fn to_wire(self) -> Self::Wire {
+        Self::Wire {
+            old_master: self.old_master.to_wire(),
+            new_master: self.new_master.to_wire(),
+        }
+    }
+}
+
+impl ToApi for WirePullFastForwardRequest {
+    type Api = PullFastForwardRequest;
+    type Error = WireToApiConversionError;
+
+    fn to_api(self) -> Result<Self::Api, Self::Error> {
+        let api = Self::Api {
+            old_master: self.old_master.to_api()?,
+            new_master: self.new_master.to_api()?,
+        };
+        Ok(api)
+    }
+}
+
+#[cfg(any(test, feature = "for-tests"))]
+impl Arbitrary for WirePullFastForwardRequest {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        PullFastForwardRequest::arbitrary(g).to_wire()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -328,5 +367,15 @@ mod tests {
         ) -> bool {
             check_wire_roundtrip(v)
         }
+
+        fn test_roundtrip_serialize_pull_fast_forward_request(
+            v: WirePullFastForwardRequest
+        ) -> bool {
+            check_serialize_roundtrip(v)
+        }
+
+        fn test_roundtrip_wire_pull_fast_forward_request(v: PullFastForwardRequest) -> bool {
+            check_wire_roundtrip(v)
+        }
     }
 }
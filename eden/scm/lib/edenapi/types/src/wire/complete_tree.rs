@@ -35,6 +35,9 @@ pub struct WireCompleteTreeRequest {
 
     #[serde(rename = "3", default, skip_serializing_if = "is_default")]
     pub depth: Option<usize>,
+
+    #[serde(rename = "4", default, skip_serializing_if = "is_default")]
+    pub prefixes: Vec<WireRepoPathBuf>,
 }
 
 impl ToWire for CompleteTreeRequest {
@@ -46,6 +49,7 @@ impl ToWire for CompleteTreeRequest {
             mfnodes: self.mfnodes.to_wire(),
             basemfnodes: self.basemfnodes.to_wire(),
             depth: self.depth,
+            prefixes: self.prefixes.to_wire(),
         }
     }
 }
@@ -60,6 +64,7 @@ impl ToApi for WireCompleteTreeRequest {
             mfnodes: self.mfnodes.to_api()?,
             basemfnodes: self.basemfnodes.to_api()?,
             depth: self.depth,
+            prefixes: self.prefixes.to_api()?,
         })
     }
 }
@@ -75,6 +80,7 @@ impl Arbitrary for WireCompleteTreeRequest {
             mfnodes: Arbitrary::arbitrary(g),
             basemfnodes: Arbitrary::arbitrary(g),
             depth: Arbitrary::arbitrary(g),
+            prefixes: Arbitrary::arbitrary(g),
         }
     }
 }
@@ -11,10 +11,11 @@ use quickcheck::Arbitrary;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::{
-    file::{FileEntry, FileRequest},
+    file::{FileAuxData, FileEntry, FileRequest},
     wire::{
-        is_default, ToApi, ToWire, WireKey, WireParents, WireRevisionstoreMetadata,
-        WireToApiConversionError,
+        is_default,
+        metadata::{WireBlake3, WireSha256},
+        ToApi, ToWire, WireKey, WireParents, WireRevisionstoreMetadata, WireToApiConversionError,
     },
 };
 
@@ -60,6 +61,48 @@ impl ToApi for WireFileEntry {
     }
 }
 
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WireFileAuxData {
+    #[serde(rename = "0", default, skip_serializing_if = "is_default")]
+    key: WireKey,
+
+    #[serde(rename = "1", default, skip_serializing_if = "is_default")]
+    total_size: u64,
+
+    #[serde(rename = "2", default, skip_serializing_if = "is_default")]
+    content_sha256: WireSha256,
+
+    #[serde(rename = "3", default, skip_serializing_if = "is_default")]
+    content_blake3: WireBlake3,
+}
+
+impl ToWire for FileAuxData {
+    type Wire = WireFileAuxData;
+
+    fn to_wire(self) -> Self::Wire {
+        WireFileAuxData {
+            key: self.key.to_wire(),
+            total_size: self.total_size,
+            content_sha256: self.content_sha256.to_wire(),
+            content_blake3: self.content_blake3.to_wire(),
+        }
+    }
+}
+
+impl ToApi for WireFileAuxData {
+    type Api = FileAuxData;
+    type Error = WireToApiConversionError;
+
+    fn to_api(self) -> Result<Self::Api, Self::Error> {
+        Ok(FileAuxData {
+            key: self.key.to_api()?,
+            total_size: self.total_size,
+            content_sha256: self.content_sha256.to_api()?,
+            content_blake3: self.content_blake3.to_api()?,
+        })
+    }
+}
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub struct WireFileRequest {
     #[serde(rename = "0", default, skip_serializing_if = "is_default")]
@@ -100,6 +143,13 @@ impl Arbitrary for WireFileEntry {
     }
 }
 
+#[cfg(any(test, feature = "for-tests"))]
+impl Arbitrary for WireFileAuxData {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        FileAuxData::arbitrary(g).to_wire()
+    }
+}
+
 #[cfg(any(test, feature = "for-tests"))]
 impl Arbitrary for WireFileRequest {
     fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
@@ -133,5 +183,13 @@ mod tests {
         fn test_entry_roundtrip_wire(v: FileEntry) -> bool {
             check_wire_roundtrip(v)
         }
+
+        fn test_aux_roundtrip_serialize(v: WireFileAuxData) -> bool {
+            check_serialize_roundtrip(v)
+        }
+
+        fn test_aux_roundtrip_wire(v: FileAuxData) -> bool {
+            check_wire_roundtrip(v)
+        }
     }
 }
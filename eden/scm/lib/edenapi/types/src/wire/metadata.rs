@@ -14,8 +14,8 @@ use serde_derive::{Deserialize, Serialize};
 
 use crate::{
     wire::{is_default, TryFromBytesError},
-    ContentId, DirectoryMetadata, DirectoryMetadataRequest, FileMetadata, FileMetadataRequest,
-    FileType, FsnodeId, Sha1, Sha256, ToApi, ToWire, WireToApiConversionError,
+    Blake3, ContentId, DirectoryMetadata, DirectoryMetadataRequest, FileMetadata,
+    FileMetadataRequest, FileType, FsnodeId, Sha1, Sha256, ToApi, ToWire, WireToApiConversionError,
 };
 
 /// Directory entry metadata
@@ -519,6 +519,62 @@ impl<'de> serde::Deserialize<'de> for WireSha256 {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WireBlake3([u8; WireBlake3::len()]);
+
+impl WireBlake3 {
+    pub const fn len() -> usize {
+        32
+    }
+}
+
+impl ToWire for Blake3 {
+    type Wire = WireBlake3;
+
+    fn to_wire(self) -> Self::Wire {
+        WireBlake3(self.0)
+    }
+}
+
+impl ToApi for WireBlake3 {
+    type Api = Blake3;
+    type Error = Infallible;
+
+    fn to_api(self) -> Result<Self::Api, Self::Error> {
+        Ok(Blake3(self.0))
+    }
+}
+
+impl serde::Serialize for WireBlake3 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for WireBlake3 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes: serde_bytes::ByteBuf = serde_bytes::deserialize(deserializer)?;
+        let bytes = bytes.as_ref();
+
+        if bytes.len() == Self::len() {
+            let mut ary = [0u8; Self::len()];
+            ary.copy_from_slice(&bytes);
+            Ok(WireBlake3(ary))
+        } else {
+            Err(D::Error::custom(TryFromBytesError {
+                expected_len: Self::len(),
+                found_len: bytes.len(),
+            }))
+        }
+    }
+}
+
 #[cfg(any(test, feature = "for-tests"))]
 impl Arbitrary for WireDirectoryMetadata {
     fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
@@ -631,6 +687,15 @@ impl Arbitrary for WireSha256 {
     }
 }
 
+#[cfg(any(test, feature = "for-tests"))]
+impl Arbitrary for WireBlake3 {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let mut v = Self::default();
+        g.fill_bytes(&mut v.0);
+        v
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -51,6 +51,7 @@ pub mod complete_tree;
 pub mod file;
 pub mod history;
 pub mod metadata;
+pub mod sparse;
 pub mod tree;
 
 use dag_types::id::Id as DagId;
@@ -60,15 +61,16 @@ pub use crate::wire::{
     commit::{
         WireCommitHashToLocationRequestBatch, WireCommitHashToLocationResponse, WireCommitLocation,
         WireCommitLocationToHashRequest, WireCommitLocationToHashRequestBatch,
-        WireCommitLocationToHashResponse,
+        WireCommitLocationToHashResponse, WirePullFastForwardRequest,
     },
     complete_tree::WireCompleteTreeRequest,
-    file::{WireFileEntry, WireFileRequest},
+    file::{WireFileAuxData, WireFileEntry, WireFileRequest},
     history::{WireHistoryRequest, WireHistoryResponseChunk, WireWireHistoryEntry},
     metadata::{
         WireDirectoryMetadata, WireDirectoryMetadataRequest, WireFileMetadata,
         WireFileMetadataRequest,
     },
+    sparse::WireSparseProfileResponse,
     tree::{WireTreeEntry, WireTreeRequest},
 };
 
@@ -0,0 +1,67 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#[cfg(any(test, feature = "for-tests"))]
+use quickcheck::Arbitrary;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::sparse::SparseProfileResponse;
+use crate::wire::{ToApi, ToWire, WireRepoPathBuf, WireToApiConversionError};
+
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct WireSparseProfileResponse {
+    #[serde(rename = "1")]
+    pub profile: Option<WireRepoPathBuf>,
+}
+
+impl ToWire for SparseProfileResponse {
+    type Wire = WireSparseProfileResponse;
+
+    fn to_wire(self) -> Self::Wire {
+        Self::Wire {
+            profile: self.profile.to_wire(),
+        }
+    }
+}
+
+impl ToApi for WireSparseProfileResponse {
+    type Api = SparseProfileResponse;
+    type Error = WireToApiConversionError;
+
+    fn to_api(self) -> Result<Self::Api, Self::Error> {
+        let api = Self::Api {
+            profile: self.profile.to_api()?,
+        };
+        Ok(api)
+    }
+}
+
+#[cfg(any(test, feature = "for-tests"))]
+impl Arbitrary for WireSparseProfileResponse {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        SparseProfileResponse::arbitrary(g).to_wire()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::wire::tests::{check_serialize_roundtrip, check_wire_roundtrip};
+
+    use quickcheck::quickcheck;
+
+    quickcheck! {
+        fn test_response_roundtrip_serialize(v: WireSparseProfileResponse) -> bool {
+            check_serialize_roundtrip(v)
+        }
+
+        fn test_response_roundtrip_wire(v: SparseProfileResponse) -> bool {
+            check_wire_roundtrip(v)
+        }
+    }
+}
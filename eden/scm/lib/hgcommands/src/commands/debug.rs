@@ -14,15 +14,20 @@ pub use super::IO;
 
 commands! {
     mod args;
+    mod cachegc;
+    mod cachestats;
     mod causerusterror;
     mod dumpindexedlog;
     mod dumptrace;
     mod dynamicconfig;
     mod fsync;
     mod http;
+    mod indexedlogstats;
     mod newstore;
     mod python;
+    mod repairstore;
     mod segmentclone;
+    mod segmentpull;
     mod store;
 }
 
@@ -0,0 +1,48 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use super::NoOpts;
+use super::Repo;
+use super::Result;
+use super::IO;
+use clidispatch::errors;
+use revisionstore::IndexedLogHgIdDataStore;
+
+pub fn run(_opts: NoOpts, io: &IO, repo: Repo) -> Result<u8> {
+    let config = repo.config();
+    let cachepath = match config.get("remotefilelog", "cachepath") {
+        Some(c) => c.to_string(),
+        None => return Err(errors::Abort("remotefilelog.cachepath is not set".into()).into()),
+    };
+    let reponame = match config.get("remotefilelog", "reponame") {
+        Some(c) => c.to_string(),
+        None => return Err(errors::Abort("remotefilelog.reponame is not set".into()).into()),
+    };
+
+    for path in &[
+        format!("{}/{}/indexedlogdatastore", cachepath, reponame),
+        format!("{}/{}/manifests/indexedlogdatastore", cachepath, reponame),
+    ] {
+        match IndexedLogHgIdDataStore::gc(path, &config)? {
+            Some(freed) => io.write(&format!("{}: freed {} bytes\n", path, freed))?,
+            None => {
+                return Err(
+                    errors::Abort("indexedlog.data.max-cache-size is not set".into()).into(),
+                );
+            }
+        }
+    }
+    Ok(0)
+}
+
+pub fn name() -> &'static str {
+    "debugcachegc"
+}
+
+pub fn doc() -> &'static str {
+    "garbage collect the shared indexedlog cache"
+}
@@ -0,0 +1,73 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::BTreeMap;
+
+use super::define_flags;
+use super::ConfigSet;
+use super::Result;
+use super::IO;
+use clidispatch::errors;
+use hgtime::HgTime;
+use revisionstore::cachestats::{CacheStats, StoreCounters};
+
+define_flags! {
+    pub struct DebugCacheStatsOpts {
+        /// reset the counters reported since the last reset
+        reset: bool,
+    }
+}
+
+pub fn run(opts: DebugCacheStatsOpts, io: &IO, config: ConfigSet) -> Result<u8> {
+    if config.get("remotefilelog", "cachepath").is_none() {
+        return Err(errors::Abort("remotefilelog.cachepath is not set".into()).into());
+    }
+    let path = CacheStats::path(&config)?;
+
+    if opts.reset {
+        CacheStats::reset(&path)?;
+        io.write("cache stats reset\n")?;
+        return Ok(0);
+    }
+
+    if !config.get_or_default::<bool>("scmstore", "cachestats")? {
+        io.write_err("note: scmstore.cachestats is not set, commands don't record cache stats\n")?;
+    }
+    let stats = CacheStats::read(&path)?;
+    write_counters(io, "total", &stats.total)?;
+    let since = match stats.reset_time {
+        Some(time) => {
+            let time = HgTime {
+                unixtime: time as i64,
+                offset: 0,
+            };
+            format!("since last reset ({})", time.to_utc())
+        }
+        None => "since last reset (never reset)".to_string(),
+    };
+    write_counters(io, &since, &stats.since_reset)?;
+    Ok(0)
+}
+
+fn write_counters(io: &IO, title: &str, counters: &BTreeMap<String, StoreCounters>) -> Result<()> {
+    io.write(&format!("{}:\n", title))?;
+    if counters.is_empty() {
+        io.write("  no data\n")?;
+    }
+    for (store, store_counters) in counters {
+        io.write(&format!("  {}: {}\n", store, store_counters))?;
+    }
+    Ok(())
+}
+
+pub fn name() -> &'static str {
+    "debugcachestats"
+}
+
+pub fn doc() -> &'static str {
+    "report the hit ratios of the stores of the shared cache"
+}
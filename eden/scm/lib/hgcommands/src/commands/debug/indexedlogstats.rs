@@ -0,0 +1,86 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use super::define_flags;
+use super::ConfigSet;
+use super::Result;
+use super::IO;
+use clidispatch::errors;
+use revisionstore::{
+    IndexedLogDataStoreType, IndexedLogHgIdDataStore, IndexedLogHgIdHistoryStore,
+    IndexedLogHistoryStoreType,
+};
+
+define_flags! {
+    pub struct DebugIndexedLogStatsOpts {
+        /// the stores are history stores
+        history: bool,
+
+        /// the stores are local (non-rotated) stores
+        local: bool,
+
+        #[args]
+        paths: Vec<String>,
+    }
+}
+
+pub fn run(opts: DebugIndexedLogStatsOpts, io: &IO, config: ConfigSet) -> Result<u8> {
+    // Without paths, report on the shared cache of the current repo.
+    let stores = if opts.paths.is_empty() {
+        let cachepath = match config.get("remotefilelog", "cachepath") {
+            Some(c) => c.to_string(),
+            None => return Err(errors::Abort("remotefilelog.cachepath is not set".into()).into()),
+        };
+        let reponame = match config.get("remotefilelog", "reponame") {
+            Some(c) => c.to_string(),
+            None => return Err(errors::Abort("remotefilelog.reponame is not set".into()).into()),
+        };
+        let root = format!("{}/{}", cachepath, reponame);
+        vec![
+            (format!("{}/indexedlogdatastore", root), false),
+            (format!("{}/indexedloghistorystore", root), true),
+            (format!("{}/manifests/indexedlogdatastore", root), false),
+            (format!("{}/manifests/indexedloghistorystore", root), true),
+        ]
+    } else {
+        opts.paths
+            .into_iter()
+            .map(|path| (path, opts.history))
+            .collect()
+    };
+
+    for (path, history) in stores {
+        let stats = if history {
+            let store_type = if opts.local {
+                IndexedLogHistoryStoreType::Local
+            } else {
+                IndexedLogHistoryStoreType::Shared
+            };
+            IndexedLogHgIdHistoryStore::stats(&path, &config, store_type)
+        } else {
+            let store_type = if opts.local {
+                IndexedLogDataStoreType::Local
+            } else {
+                IndexedLogDataStoreType::Shared
+            };
+            IndexedLogHgIdDataStore::stats(&path, &config, store_type)
+        };
+        match stats {
+            Ok(stats) => io.write(&format!("{}:\n{}\n", path, stats))?,
+            Err(e) => io.write_err(&format!("{}: cannot read store: {:#}\n\n", path, e))?,
+        }
+    }
+    Ok(0)
+}
+
+pub fn name() -> &'static str {
+    "debugindexedlogstats"
+}
+
+pub fn doc() -> &'static str {
+    "report statistics about indexedlog stores"
+}
@@ -15,20 +15,35 @@ use clidispatch::errors;
 use edenapi::Builder;
 use edenapi_types::{FileEntry, TreeEntry};
 use revisionstore::{
-    indexedlogdatastore::{IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+    indexedlogdatastore::{Entry, IndexedLogDataStoreType, IndexedLogHgIdDataStore},
     newstore::{
-        edenapi::EdenApiAdapter, fallback::FallbackStore, BoxedReadStore, KeyStream, ReadStore,
+        edenapi::{EdenApiAdapter, EdenApiAdapterOptions},
+        fallback::{FallbackFetchOptions, FallbackStore, WriteErrorPolicy, WritePolicy},
+        BoxedReadStore, FetchError, KeyStream, ReadStore,
     },
     ExtStoredPolicy,
 };
 use types::{HgId, Key, RepoPathBuf};
 
-use super::NoOpts;
+use super::define_flags;
 use super::Repo;
 use super::Result;
 use super::IO;
 
-pub fn run(_opts: NoOpts, io: &IO, repo: Repo) -> Result<u8> {
+define_flags! {
+    pub struct DebugNewStoreOpts {
+        /// directory path of a tree to fetch recursively, instead of the test keys
+        path: String,
+
+        /// node of the tree to fetch recursively
+        node: String,
+
+        /// maximum depth of the recursive tree fetch
+        depth: i64 = 3,
+    }
+}
+
+pub fn run(opts: DebugNewStoreOpts, io: &IO, repo: Repo) -> Result<u8> {
     let config = repo.config();
 
     let reponame = match config.get("remotefilelog", "reponame") {
@@ -68,16 +83,21 @@ pub fn run(_opts: NoOpts, io: &IO, repo: Repo) -> Result<u8> {
 
     // EdenApi tree store
     let edenapi = Arc::new(EdenApiAdapter {
-        client: Builder::from_config(config)?.build()?,
+        client: Arc::new(Builder::from_config(config)?.build()?),
         repo: reponame,
+        options: EdenApiAdapterOptions::from_config(&config)?,
     });
 
+    let fetch_options = FallbackFetchOptions::from_config(&config)?;
+
     // Fallback store combinator (trees)
     let tree_fallback = Arc::new(FallbackStore {
         preferred: tree_indexedstore.clone(),
         fallback: edenapi.clone() as BoxedReadStore<Key, TreeEntry>,
         write_store: tree_indexedstore,
-        write: true,
+        write_policy: WritePolicy::WriteThrough,
+        write_error_policy: WriteErrorPolicy::Log,
+        fetch_options: fetch_options.clone(),
     });
 
     // Fallback store combinator (files)
@@ -85,9 +105,20 @@ pub fn run(_opts: NoOpts, io: &IO, repo: Repo) -> Result<u8> {
         preferred: file_indexedstore.clone(),
         fallback: edenapi as BoxedReadStore<Key, FileEntry>,
         write_store: file_indexedstore,
-        write: true,
+        write_policy: WritePolicy::WriteThrough,
+        write_error_policy: WriteErrorPolicy::Log,
+        fetch_options,
     });
 
+    if !opts.node.is_empty() {
+        let root = Key::new(
+            RepoPathBuf::from_string(opts.path)?,
+            HgId::from_str(&opts.node)?,
+        );
+        let failed = fetch_recursive(io, tree_fallback, file_fallback, root, opts.depth)?;
+        return Ok(if failed > 0 { 1 } else { 0 });
+    }
+
     // Test trees
     let tree_keystrings = [
         (
@@ -113,18 +144,18 @@ pub fn run(_opts: NoOpts, io: &IO, repo: Repo) -> Result<u8> {
         tree_fallback.fetch_stream(Box::pin(stream::iter(tree_keys)) as KeyStream<Key>),
     ));
 
+    let mut failed = 0;
     for item in fetched_trees {
-        let msg = format!(
-            "tree {}\n",
-            std::str::from_utf8(
-                &item
-                    .expect("failed to fetch tree")
-                    .content()
-                    .expect("failed to extract Entry content")
-            )
-            .expect("failed to convert to convert to string")
-        );
-        io.write(&msg)?;
+        match item {
+            Ok(mut entry) => {
+                let content = entry.content()?;
+                io.write(&format!("tree {}\n", std::str::from_utf8(&content)?))?;
+            }
+            Err(e) => {
+                failed += 1;
+                report_error(io, &e)?;
+            }
+        }
     }
 
     // Test files
@@ -156,20 +187,126 @@ pub fn run(_opts: NoOpts, io: &IO, repo: Repo) -> Result<u8> {
     ));
 
     for item in fetched_files {
-        let msg = format!(
-            "file {}\n",
-            std::str::from_utf8(
-                &item
-                    .expect("failed to fetch file")
-                    .content()
-                    .expect("failed to extract Entry content")
-            )
-            .expect("failed to convert to convert to string")
-        );
-        io.write(&msg)?;
+        match item {
+            Ok(mut entry) => {
+                let content = entry.content()?;
+                io.write(&format!("file {}\n", std::str::from_utf8(&content)?))?;
+            }
+            Err(e) => {
+                failed += 1;
+                report_error(io, &e)?;
+            }
+        }
+    }
+
+    Ok(if failed > 0 { 1 } else { 0 })
+}
+
+/// Fetch the tree `root` and its descendants down to `depth` levels below it, like a
+/// gettreepack would. Each level is fetched as a single stream of keys, trees through
+/// `trees` and files through `files`, so that batching and fallback behave as they would
+/// for a real checkout. Returns the number of keys which failed to be fetched.
+fn fetch_recursive(
+    io: &IO,
+    trees: BoxedReadStore<Key, Entry>,
+    files: BoxedReadStore<Key, Entry>,
+    root: Key,
+    depth: i64,
+) -> Result<usize> {
+    let mut failed = 0;
+    let mut level = vec![root];
+    for depth in 0..=depth.max(0) {
+        if level.is_empty() {
+            break;
+        }
+        let tree_count = level.len();
+        let mut subtrees = vec![];
+        let mut file_keys = vec![];
+        let fetched = block_on_stream(block_on(
+            trees
+                .clone()
+                .fetch_stream(Box::pin(stream::iter(level)) as KeyStream<Key>),
+        ));
+        for item in fetched {
+            match item {
+                Ok(mut entry) => {
+                    let key = entry.key().clone();
+                    let content = entry.content()?;
+                    for (child, is_tree) in tree_children(&key, &content)? {
+                        if is_tree {
+                            subtrees.push(child);
+                        } else {
+                            file_keys.push(child);
+                        }
+                    }
+                }
+                Err(e) => {
+                    failed += 1;
+                    report_error(io, &e)?;
+                }
+            }
+        }
+
+        let file_count = file_keys.len();
+        let fetched = block_on_stream(block_on(
+            files
+                .clone()
+                .fetch_stream(Box::pin(stream::iter(file_keys)) as KeyStream<Key>),
+        ));
+        for item in fetched {
+            if let Err(e) = item {
+                failed += 1;
+                report_error(io, &e)?;
+            }
+        }
+
+        io.write(&format!(
+            "depth {}: fetched {} trees and {} files\n",
+            depth, tree_count, file_count
+        ))?;
+
+        // Subtrees below the depth limit are not fetched.
+        level = subtrees;
     }
+    io.write(&format!("{} keys failed\n", failed))?;
+    Ok(failed)
+}
+
+/// Parse the entries of the tree `key`, in the manifest format: one `<name>\0<hex node><flag>`
+/// line per entry, where the flag is `t` for directories.
+fn tree_children(key: &Key, content: &[u8]) -> Result<Vec<(Key, bool)>> {
+    let mut children = vec![];
+    for line in content
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+    {
+        let sep = match line.iter().position(|b| *b == b'\0') {
+            Some(sep) => sep,
+            None => return Err(errors::Abort(format!("malformed tree {}", key).into()).into()),
+        };
+        let name = std::str::from_utf8(&line[..sep])?;
+        let rest = std::str::from_utf8(&line[sep + 1..])?;
+        if rest.len() < HgId::hex_len() {
+            return Err(errors::Abort(format!("malformed tree {}", key).into()).into());
+        }
+        let (hex, flag) = rest.split_at(HgId::hex_len());
+        let path = if key.path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", key.path, name)
+        };
+        children.push((
+            Key::new(RepoPathBuf::from_string(path)?, HgId::from_str(hex)?),
+            flag == "t",
+        ));
+    }
+    Ok(children)
+}
 
-    Ok(0)
+fn report_error(io: &IO, e: &FetchError<Key>) -> Result<()> {
+    let retryable = if e.is_retryable() { " (retryable)" } else { "" };
+    io.write_err(&format!("{}{}\n", e, retryable))?;
+    Ok(())
 }
 
 pub fn name() -> &'static str {
@@ -177,5 +314,9 @@ pub fn name() -> &'static str {
 }
 
 pub fn doc() -> &'static str {
-    "test newstore storage api"
+    r#"test newstore storage api
+
+    Without options, fetch a few test trees and files. With ``--node``, fetch
+    the tree at ``--path`` with that node, and all the trees and files below it
+    down to ``--depth`` levels, one level at a time."#
 }
@@ -0,0 +1,160 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::Arc;
+
+use futures::stream;
+
+use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+use clidispatch::errors;
+use edenapi::Builder;
+use edenapi_types::{FileEntry, TreeEntry};
+use revisionstore::{
+    indexedlogdatastore::Entry,
+    newstore::{
+        edenapi::{EdenApiAdapter, EdenApiAdapterOptions},
+        fallback::{FallbackFetchOptions, FallbackStore, WriteErrorPolicy, WritePolicy},
+        verify::scan_store,
+        BoxedReadStore, KeyStream, ReadStore,
+    },
+    ExtStoredPolicy, HgIdMutableDeltaStore, IndexedLogDataStoreType, IndexedLogHgIdDataStore,
+    IndexedLogHgIdHistoryStore, IndexedLogHistoryStoreType,
+};
+use types::Key;
+
+use super::define_flags;
+use super::Repo;
+use super::Result;
+use super::IO;
+
+define_flags! {
+    pub struct DebugRepairStoreOpts {
+        /// fetch the corrupt entries again from the server
+        refetch: bool,
+    }
+}
+
+pub fn run(opts: DebugRepairStoreOpts, io: &IO, repo: Repo) -> Result<u8> {
+    let config = repo.config();
+    let reponame = match config.get("remotefilelog", "reponame") {
+        Some(c) => c.to_string(),
+        None => return Err(errors::Abort("remotefilelog.reponame is not set".into()).into()),
+    };
+    let cachepath = match config.get("remotefilelog", "cachepath") {
+        Some(c) => c.to_string(),
+        None => return Err(errors::Abort("remotefilelog.cachepath is not set".into()).into()),
+    };
+
+    let edenapi = if opts.refetch {
+        Some(Arc::new(EdenApiAdapter {
+            client: Arc::new(Builder::from_config(config)?.build()?),
+            repo: reponame.clone(),
+            options: EdenApiAdapterOptions::from_config(&config)?,
+        }))
+    } else {
+        None
+    };
+
+    let mut remaining = 0;
+    for &(kind, dir) in [("file", ""), ("tree", "manifests/")].iter() {
+        let path = format!("{}/{}/{}indexedlogdatastore", cachepath, reponame, dir);
+        let store = Arc::new(IndexedLogHgIdDataStore::new(
+            &path,
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?);
+        let history = IndexedLogHgIdHistoryStore::new(
+            format!("{}/{}/{}indexedloghistorystore", cachepath, reponame, dir),
+            &config,
+            IndexedLogHistoryStoreType::Shared,
+        )?;
+
+        let report = scan_store(&store, &history)?;
+        io.write(&format!(
+            "{}: checked {} {}s, {} corrupt, {} unverifiable, {} unreadable\n",
+            path,
+            report.checked,
+            kind,
+            report.corrupt.len(),
+            report.unverifiable,
+            report.unreadable
+        ))?;
+        for (key, e) in report.corrupt.iter() {
+            io.write_err(&format!("corrupt {} {}: {}\n", kind, key, e))?;
+        }
+
+        let keys: Vec<Key> = report.corrupt.into_iter().map(|(key, _)| key).collect();
+        let edenapi = match edenapi.as_ref() {
+            Some(edenapi) if !keys.is_empty() => edenapi,
+            _ => {
+                remaining += keys.len();
+                continue;
+            }
+        };
+
+        // The corrupt entries are quarantined, so they are fetched from the server and the
+        // fetched entries are written to the store, where they shadow the corrupt ones.
+        let fallback: BoxedReadStore<Key, Entry> = if kind == "tree" {
+            Arc::new(FallbackStore {
+                preferred: store.clone(),
+                fallback: edenapi.clone() as BoxedReadStore<Key, TreeEntry>,
+                write_store: store.clone(),
+                write_policy: WritePolicy::WriteThrough,
+                write_error_policy: WriteErrorPolicy::Log,
+                fetch_options: FallbackFetchOptions::from_config(&config)?,
+            })
+        } else {
+            Arc::new(FallbackStore {
+                preferred: store.clone(),
+                fallback: edenapi.clone() as BoxedReadStore<Key, FileEntry>,
+                write_store: store.clone(),
+                write_policy: WritePolicy::WriteThrough,
+                write_error_policy: WriteErrorPolicy::Log,
+                fetch_options: FallbackFetchOptions::from_config(&config)?,
+            })
+        };
+        let fetched = block_on_stream(block_on(
+            fallback.fetch_stream(Box::pin(stream::iter(keys)) as KeyStream<Key>),
+        ));
+        let mut repaired = 0;
+        for item in fetched {
+            match item {
+                Ok(_) => repaired += 1,
+                Err(e) => {
+                    remaining += 1;
+                    io.write_err(&format!("failed to refetch: {}\n", e))?;
+                }
+            }
+        }
+        store.flush()?;
+        io.write(&format!("{}: refetched {} {}s\n", path, repaired, kind))?;
+    }
+
+    if remaining > 0 && !opts.refetch {
+        io.write_err("run with --refetch to replace the corrupt entries\n")?;
+    }
+    Ok(if remaining > 0 { 1 } else { 0 })
+}
+
+pub fn name() -> &'static str {
+    "debugrepairstore"
+}
+
+pub fn doc() -> &'static str {
+    r#"check the shared file and tree stores for corrupt entries
+
+    Check the content of every entry of the shared indexedlog file and tree
+    stores against its node. Entries whose parents are unknown, LFS pointers
+    and redacted content can't be checked.
+
+    The stores are append-only, so corrupt entries can't be deleted. With
+    ``--refetch``, they are fetched again from the server, and the fetched
+    entries are written to the stores, where they replace the corrupt ones.
+
+    Returns 0 if no corrupt entries are left, 1 otherwise."#
+}
@@ -0,0 +1,113 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use super::define_flags;
+use super::Repo;
+use super::Result;
+use super::IO;
+use anyhow::Context;
+use async_runtime::block_on_exclusive as block_on;
+use clidispatch::errors;
+use dag::namedag::IndexedLogNameDagPath;
+use dag::ops::DagImportPullData;
+use dag::ops::DagPersistent;
+use dag::ops::IdConvert;
+use dag::ops::Open;
+use dag::CloneData;
+use dag::Group;
+use dag::VertexName;
+use edenapi::EdenApiBlocking;
+use std::collections::HashMap;
+use std::fs;
+use types::HgId;
+
+define_flags! {
+    pub struct DebugSegmentPullOpts {
+        #[arg]
+        master: String,
+    }
+}
+
+pub fn run(opts: DebugSegmentPullOpts, io: &IO, repo: Repo) -> Result<u8> {
+    let config = repo.config();
+    let reponame = match config.get("remotefilelog", "reponame") {
+        Some(reponame) => reponame.to_string(),
+        None => return Err(errors::Abort("remotefilelog.reponame is not set".into()).into()),
+    };
+    let new_master = HgId::from_hex(opts.master.as_bytes())
+        .map_err(|_| errors::Abort(format!("invalid commit hash {}", opts.master).into()))?;
+
+    let namedag_path = IndexedLogNameDagPath(repo.store_path().join("segments/v1"));
+    let mut namedag = namedag_path
+        .open()
+        .context("error opening segmented changelog")?;
+    let next_id = namedag.dag().next_free_id(0, Group::MASTER)?;
+    if next_id <= Group::MASTER.min_id() {
+        return Err(errors::Abort("segmented changelog has no master commits".into()).into());
+    }
+    let old_master = block_on(namedag.vertex_name(next_id - 1))
+        .context("error looking up the local master commit")?;
+    let old_master = HgId::from_slice(old_master.as_ref())?;
+    if old_master == new_master {
+        io.write("master is up to date\n")?;
+        return Ok(0);
+    }
+
+    let edenapi_client = edenapi::Builder::from_config(config)?.build()?;
+    let pull_data = edenapi_client
+        .pull_fast_forward_master_blocking(reponame, old_master, new_master)
+        .context("error pulling segmented changelog")?;
+
+    let idmap: HashMap<dag::Id, dag::Vertex> = pull_data
+        .idmap
+        .into_iter()
+        .map(|(k, v)| (k, VertexName::copy_from(&v.into_byte_array())))
+        .collect();
+    let pulled = pull_data.head_id.0 + 1 - next_id.0;
+    let vertex_pull_data = CloneData {
+        head_id: pull_data.head_id,
+        flat_segments: pull_data.flat_segments,
+        idmap,
+    };
+    namedag
+        .import_pull_data(vertex_pull_data)
+        .context("error importing segmented changelog")?;
+
+    let master = VertexName::copy_from(new_master.as_ref());
+    block_on(namedag.flush(&[master])).context("error writing segmented changelog to disk")?;
+
+    // Move the remote master bookmark, keeping the other remote names.
+    let remotenames_path = repo.store_path().join("remotenames");
+    let remotenames = match fs::read_to_string(&remotenames_path) {
+        Ok(remotenames) => remotenames,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => Err(e).context("error reading remotenames")?,
+    };
+    let mut updated: String = remotenames
+        .lines()
+        .filter(|line| !line.ends_with(" bookmarks remote/master"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    updated.push_str(&format!(
+        "{} bookmarks remote/master\n",
+        new_master.to_hex()
+    ));
+    fs::write(&remotenames_path, updated.as_bytes()).context("error writing remotenames")?;
+
+    io.write(&format!("pulled {} commits\n", pulled))?;
+    Ok(0)
+}
+
+pub fn name() -> &'static str {
+    "debugsegmentpull"
+}
+
+pub fn doc() -> &'static str {
+    r#"pull new master commits into a segmented changelog
+
+    Only the commits between the local master and MASTER are downloaded."#
+}
@@ -0,0 +1,569 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::format_err;
+use bytes::Bytes;
+use futures::stream;
+
+use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+use clidispatch::errors;
+use dag::Vertex;
+use edenapi::Builder;
+use edenapi_types::TreeEntry;
+use hgcommits::{ReadCommitText, RevlogCommits};
+use manifest::Manifest;
+use manifest_tree::{TreeManifest, TreeStore};
+use pathmatcher::{
+    expand_curly_brackets, normalize_glob, plain_to_glob, DirectoryMatch, Matcher, TreeMatcher,
+};
+use revisionstore::{
+    indexedlogdatastore::{Entry, IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+    newstore::{
+        edenapi::{EdenApiAdapter, EdenApiAdapterOptions},
+        scmstore::ScmStoreBuilder,
+        BoxedReadStore, KeyStream, ReadStore,
+    },
+    ExtStoredPolicy,
+};
+use treestate::{filestate::StateFlags, store::BlockId, tree::VisitorResult, treestate::TreeState};
+use types::{HgId, Key, RepoPath};
+
+use super::define_flags;
+use super::FormatterOpts;
+use super::Repo;
+use super::Result;
+use super::WalkOpts;
+use super::IO;
+
+define_flags! {
+    pub struct FilesOpts {
+        /// search the repository as it is in REV
+        #[short('r')]
+        rev: String,
+
+        /// end filenames with NUL, for use with xargs
+        #[short('0')]
+        print0: bool,
+
+        walk_opts: WalkOpts,
+        formatter_opts: FormatterOpts,
+
+        #[args]
+        args: Vec<String>,
+    }
+}
+
+/// Changelog backends which don't keep the full commit texts in the revlog.
+const NON_REVLOG_CHANGELOGS: &[&str] = &[
+    "gitchangelog",
+    "hybridchangelog",
+    "lazychangelog",
+    "lazytextchangelog",
+    "segmentedchangelog",
+];
+
+const DIRSTATE_HEADER: &[u8] = b"\ntreestate\n\0";
+
+pub fn run(opts: FilesOpts, io: &IO, repo: Repo) -> Result<u8> {
+    // Only the plain listing of a treemanifest repo whose commits are in the revlog is done here.
+    // Everything else, like sizes in verbose mode, is left to Python.
+    let config = repo.config();
+    if !opts.formatter_opts.template.is_empty()
+        || config.get_or_default::<bool>("ui", "verbose")?
+        || config.get_or_default::<bool>("ui", "debug")?
+        || !config.get_or("treemanifest", "treeonly", || true)?
+    {
+        return Err(errors::FallbackToPython.into());
+    }
+    let requirements = read_requirements(&repo.dot_hg_path().join("requires"))?;
+    let store_requirements = read_requirements(&repo.store_path().join("requires"))?;
+    if requirements.contains("eden")
+        || NON_REVLOG_CHANGELOGS
+            .iter()
+            .any(|name| store_requirements.contains(*name))
+    {
+        return Err(errors::FallbackToPython.into());
+    }
+
+    let cwd = match std::env::current_dir()?
+        .strip_prefix(repo.path())
+        .ok()
+        .and_then(|cwd| cwd.to_str())
+    {
+        Some(cwd) => cwd.replace('\\', "/"),
+        None => return Err(errors::FallbackToPython.into()),
+    };
+    let matcher = FilesMatcher::new(repo.path(), &cwd, &opts)?;
+
+    let working_copy = match opts.rev.as_str() {
+        "" => read_working_copy(&repo, &requirements)?,
+        rev => {
+            let node = match rev {
+                "." => read_working_copy(&repo, &requirements)?.p1,
+                // Anything which isn't a full hash is resolved by Python.
+                rev if rev.len() == HgId::hex_len() => match HgId::from_hex(rev.as_bytes()) {
+                    Ok(node) => node,
+                    Err(_) => return Err(errors::FallbackToPython.into()),
+                },
+                _ => return Err(errors::FallbackToPython.into()),
+            };
+            WorkingCopy::clean(node)
+        }
+    };
+
+    let mut files = BTreeSet::new();
+    if !working_copy.p1.is_null() {
+        let manifest_id = read_manifest_id(repo.store_path(), working_copy.p1)?;
+        let tree_store = ScmTreeStore::new(&repo)?;
+        let manifest = TreeManifest::durable(Arc::new(tree_store), manifest_id);
+        for file in manifest.files(&matcher) {
+            let path = file?.path.into_string();
+            if !working_copy.removed.contains(&path) {
+                files.insert(path);
+            }
+        }
+    }
+    for path in working_copy.added {
+        if matcher.matches_file(RepoPath::from_str(&path)?)? {
+            files.insert(path);
+        }
+    }
+
+    let end = if opts.print0 { "\0" } else { "\n" };
+    for path in files.iter() {
+        io.write(format!("{}{}", relative_path(&cwd, path), end))?;
+    }
+    Ok(if files.is_empty() { 1 } else { 0 })
+}
+
+fn read_requirements(path: &Path) -> Result<BTreeSet<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content.lines().map(|line| line.to_string()).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The parent of the working copy, and the files added or removed since.
+struct WorkingCopy {
+    p1: HgId,
+    added: BTreeSet<String>,
+    removed: BTreeSet<String>,
+}
+
+impl WorkingCopy {
+    fn clean(p1: HgId) -> Self {
+        WorkingCopy {
+            p1,
+            added: BTreeSet::new(),
+            removed: BTreeSet::new(),
+        }
+    }
+}
+
+/// Read the working copy from the treestate dirstate. Merges, where files also come from the
+/// second parent, and sparse checkouts, where files outside of the sparse profile are not
+/// listed, are left to Python.
+fn read_working_copy(repo: &Repo, requirements: &BTreeSet<String>) -> Result<WorkingCopy> {
+    let sparse = repo.dot_hg_path().join("sparse");
+    if !requirements.contains("treestate") || (sparse.exists() && fs::metadata(&sparse)?.len() > 0)
+    {
+        return Err(errors::FallbackToPython.into());
+    }
+
+    // The dirstate is made of both parents, the treestate header, and `\0` separated
+    // `key=value` metadata naming the treestate file and its root.
+    let dirstate = match fs::read(repo.dot_hg_path().join("dirstate")) {
+        Ok(dirstate) => dirstate,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let damaged = || errors::Abort("working directory state appears damaged!".into());
+    if dirstate.is_empty() {
+        return Ok(WorkingCopy::clean(HgId::null_id().clone()));
+    }
+    let parents_end = HgId::len() * 2;
+    let header_end = parents_end + DIRSTATE_HEADER.len();
+    if dirstate.len() < parents_end
+        || (dirstate.len() > parents_end
+            && dirstate.get(parents_end..header_end) != Some(DIRSTATE_HEADER))
+    {
+        return Err(damaged().into());
+    }
+    let p1 = HgId::from_slice(&dirstate[..HgId::len()])?;
+    let p2 = HgId::from_slice(&dirstate[HgId::len()..parents_end])?;
+    if !p2.is_null() {
+        return Err(errors::FallbackToPython.into());
+    }
+
+    let mut filename = None;
+    let mut root_id = None;
+    for entry in dirstate
+        .get(header_end..)
+        .unwrap_or_default()
+        .split(|b| *b == 0)
+    {
+        let entry = std::str::from_utf8(entry)?;
+        if let Some(sep) = entry.find('=') {
+            match &entry[..sep] {
+                "filename" => filename = Some(entry[sep + 1..].to_string()),
+                "rootid" => root_id = Some(entry[sep + 1..].parse::<u64>().map_err(|_| damaged())?),
+                _ => {}
+            }
+        }
+    }
+    let mut working_copy = WorkingCopy::clean(p1);
+    let (filename, root_id) = match (filename, root_id) {
+        (Some(filename), Some(root_id)) => (filename, root_id),
+        _ => return Ok(working_copy),
+    };
+
+    let path = repo.dot_hg_path().join("treestate").join(filename);
+    let mut treestate = TreeState::open(path, Some(BlockId(root_id)))?;
+    // Directories where all files exist both in p1 and in the working copy are skipped.
+    let unchanged = StateFlags::EXIST_P1 | StateFlags::EXIST_NEXT;
+    treestate.visit(
+        &mut |components, file| {
+            let path = String::from_utf8(components.concat())?;
+            if file.state.contains(StateFlags::EXIST_NEXT) {
+                working_copy.added.insert(path);
+            } else {
+                working_copy.removed.insert(path);
+            }
+            Ok(VisitorResult::NotChanged)
+        },
+        &|_, dir| match dir.get_aggregated_state() {
+            Some(state) => !state.intersection.contains(unchanged),
+            None => true,
+        },
+        &|_, file| {
+            file.state.contains(StateFlags::EXIST_P1) != file.state.contains(StateFlags::EXIST_NEXT)
+        },
+    )?;
+    Ok(working_copy)
+}
+
+/// Read the root manifest node of the commit `node` from the revlog changelog, where it is on
+/// the first line of the commit text. Unknown commits are left to Python, which may find them
+/// elsewhere.
+fn read_manifest_id(store_path: &Path, node: HgId) -> Result<HgId> {
+    let commits = RevlogCommits::new(store_path)?;
+    let vertex = Vertex::copy_from(node.as_ref());
+    let text = match block_on(async move { commits.get_commit_raw_text(&vertex).await })? {
+        Some(text) => text,
+        None => return Err(errors::FallbackToPython.into()),
+    };
+    let line = text.split(|b| *b == b'\n').next().unwrap_or_default();
+    Ok(HgId::from_hex(line)?)
+}
+
+/// Trees read through the newstore stack: the local and shared indexedlog caches, falling back
+/// to EdenApi for the trees which aren't there yet.
+struct ScmTreeStore {
+    store: BoxedReadStore<Key, Entry>,
+}
+
+impl ScmTreeStore {
+    /// Repos which don't fetch trees from EdenApi are left to Python.
+    fn new(repo: &Repo) -> Result<Self> {
+        let config = repo.config();
+        let (reponame, cachepath) = match (
+            config.get("remotefilelog", "reponame"),
+            config.get("remotefilelog", "cachepath"),
+        ) {
+            (Some(reponame), Some(cachepath)) => (reponame.to_string(), cachepath.to_string()),
+            _ => return Err(errors::FallbackToPython.into()),
+        };
+        if config.get("edenapi", "url").is_none() {
+            return Err(errors::FallbackToPython.into());
+        }
+
+        let shared = Arc::new(IndexedLogHgIdDataStore::new(
+            format!("{}/{}/manifests/indexedlogdatastore", cachepath, reponame),
+            ExtStoredPolicy::Use,
+            config,
+            IndexedLogDataStoreType::Shared,
+        )?);
+        let edenapi = Arc::new(EdenApiAdapter {
+            client: Arc::new(Builder::from_config(config)?.build()?),
+            repo: reponame,
+            options: EdenApiAdapterOptions::from_config(config)?,
+        });
+        let mut builder = ScmStoreBuilder::new(shared)
+            .config(config)?
+            .tree_remote(edenapi as BoxedReadStore<Key, TreeEntry>);
+        let local = repo.store_path().join("manifests/indexedlogdatastore");
+        if local.exists() {
+            builder = builder.local(Arc::new(IndexedLogHgIdDataStore::new(
+                local,
+                ExtStoredPolicy::Use,
+                config,
+                IndexedLogDataStoreType::Local,
+            )?));
+        }
+        Ok(ScmTreeStore {
+            store: builder.build(),
+        })
+    }
+
+    fn fetch(&self, keys: Vec<Key>) -> Result<Vec<Entry>> {
+        let fetched = block_on_stream(block_on(
+            self.store
+                .clone()
+                .fetch_stream(Box::pin(stream::iter(keys)) as KeyStream<Key>),
+        ));
+        let mut entries = vec![];
+        for item in fetched {
+            entries.push(item?);
+        }
+        Ok(entries)
+    }
+}
+
+impl TreeStore for ScmTreeStore {
+    fn get(&self, path: &RepoPath, hgid: HgId) -> Result<Bytes> {
+        let key = Key::new(path.to_owned(), hgid);
+        match self.fetch(vec![key.clone()])?.pop() {
+            Some(mut entry) => Ok(Bytes::copy_from_slice(entry.content()?.as_ref())),
+            None => Err(format_err!("tree {} is not found", key)),
+        }
+    }
+
+    fn insert(&self, _path: &RepoPath, _hgid: HgId, _data: Bytes) -> Result<()> {
+        Err(format_err!("insert is not implemented."))
+    }
+
+    fn prefetch(&self, keys: Vec<Key>) -> Result<()> {
+        // Fetched trees are written to the shared cache, where `get` finds them.
+        self.fetch(keys)?;
+        Ok(())
+    }
+}
+
+/// Matches the files selected on the command line like `scmutil.match`: those matching any of
+/// the patterns, or all files without patterns, and any of the `-I` patterns, but none of the
+/// `-X` patterns.
+struct FilesMatcher {
+    patterns: Option<TreeMatcher>,
+    include: Option<TreeMatcher>,
+    exclude: Option<TreeMatcher>,
+}
+
+impl FilesMatcher {
+    fn new(root: &Path, cwd: &str, opts: &FilesOpts) -> Result<Self> {
+        Ok(FilesMatcher {
+            patterns: compile_patterns(root, cwd, &opts.args, "relpath")?,
+            include: compile_patterns(root, cwd, &opts.walk_opts.include, "glob")?,
+            exclude: compile_patterns(root, cwd, &opts.walk_opts.exclude, "glob")?,
+        })
+    }
+}
+
+impl Matcher for FilesMatcher {
+    fn matches_directory(&self, path: &RepoPath) -> Result<DirectoryMatch> {
+        let mut result = DirectoryMatch::Everything;
+        for matcher in self.patterns.iter().chain(self.include.iter()) {
+            match matcher.matches_directory(path)? {
+                DirectoryMatch::Nothing => return Ok(DirectoryMatch::Nothing),
+                DirectoryMatch::ShouldTraverse => result = DirectoryMatch::ShouldTraverse,
+                DirectoryMatch::Everything => {}
+            }
+        }
+        if let Some(exclude) = &self.exclude {
+            match exclude.matches_directory(path)? {
+                DirectoryMatch::Everything => return Ok(DirectoryMatch::Nothing),
+                DirectoryMatch::ShouldTraverse => result = DirectoryMatch::ShouldTraverse,
+                DirectoryMatch::Nothing => {}
+            }
+        }
+        Ok(result)
+    }
+
+    fn matches_file(&self, path: &RepoPath) -> Result<bool> {
+        for matcher in self.patterns.iter().chain(self.include.iter()) {
+            if !matcher.matches_file(path)? {
+                return Ok(false);
+            }
+        }
+        match &self.exclude {
+            Some(exclude) => Ok(!exclude.matches_file(path)?),
+            None => Ok(true),
+        }
+    }
+}
+
+/// Compile `patterns` into a single matcher, or `None` if there are no patterns.
+fn compile_patterns(
+    root: &Path,
+    cwd: &str,
+    patterns: &[String],
+    default: &str,
+) -> Result<Option<TreeMatcher>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut rules = vec![];
+    for pattern in patterns {
+        rules.extend(pattern_rules(root, cwd, pattern, default)?);
+    }
+    Ok(Some(TreeMatcher::from_rules(rules.iter())?))
+}
+
+/// Translate a pattern, with `default` as its kind if it has no `kind:` prefix, into
+/// `TreeMatcher` rules. Patterns match the files they name and the files below them. Kinds
+/// other than `path`, `relpath`, `glob` and `relglob` are left to Python.
+fn pattern_rules(root: &Path, cwd: &str, pattern: &str, default: &str) -> Result<Vec<String>> {
+    const KINDS: &[&str] = &[
+        "path",
+        "relpath",
+        "glob",
+        "relglob",
+        "re",
+        "relre",
+        "set",
+        "listfile",
+        "listfile0",
+        "include",
+        "subinclude",
+        "rootfilesin",
+    ];
+    let (kind, name) = match pattern.find(':') {
+        Some(sep) if KINDS.contains(&&pattern[..sep]) => (&pattern[..sep], &pattern[sep + 1..]),
+        _ => (default, pattern),
+    };
+    let globs = match kind {
+        "path" => vec![plain_to_glob(&canonical_path(root, "", name)?)],
+        "relpath" => vec![plain_to_glob(&canonical_path(root, cwd, name)?)],
+        "glob" => expand_curly_brackets(&canonical_path(root, cwd, name)?)
+            .iter()
+            .map(|glob| normalize_glob(glob))
+            .collect(),
+        "relglob" => expand_curly_brackets(name)
+            .iter()
+            .map(|glob| format!("**/{}", normalize_glob(glob)))
+            .collect(),
+        _ => return Err(errors::FallbackToPython.into()),
+    };
+
+    let mut rules = vec![];
+    for glob in globs {
+        if glob.is_empty() {
+            rules.push("**".to_string());
+            continue;
+        }
+        // A leading "!" would make the rule negative.
+        let glob = if glob.starts_with('!') {
+            format!("\\{}", glob)
+        } else {
+            glob
+        };
+        rules.push(format!("{}/**", glob));
+        rules.push(glob);
+    }
+    Ok(rules)
+}
+
+/// Path of `name` relative to the repo root, where relative names are relative to `cwd`.
+fn canonical_path(root: &Path, cwd: &str, name: &str) -> Result<String> {
+    let not_under_root =
+        || errors::Abort(format!("{} not under root '{}'", name, root.display()).into());
+    let joined = if Path::new(name).is_absolute() {
+        match Path::new(name)
+            .strip_prefix(root)
+            .ok()
+            .and_then(|p| p.to_str())
+        {
+            Some(relative) => relative.replace('\\', "/"),
+            None => return Err(not_under_root().into()),
+        }
+    } else {
+        format!("{}/{}", cwd, name)
+    };
+    let mut components = vec![];
+    for component in joined.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if components.pop().is_none() {
+                    return Err(not_under_root().into());
+                }
+            }
+            component => components.push(component),
+        }
+    }
+    Ok(components.join("/"))
+}
+
+/// Path of the file `path` relative to `cwd`, both being relative to the repo root.
+fn relative_path(cwd: &str, path: &str) -> String {
+    if cwd.is_empty() {
+        return path.to_string();
+    }
+    let cwd: Vec<&str> = cwd.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    let common = cwd
+        .iter()
+        .zip(path.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = vec![".."; cwd.len() - common];
+    relative.extend_from_slice(&path[common..]);
+    relative.join("/")
+}
+
+pub fn name() -> &'static str {
+    "files|fi|fil|file"
+}
+
+pub fn doc() -> &'static str {
+    r#"list tracked files
+
+    Print files under Mercurial control in the working directory or
+    specified revision for given files (excluding removed files).
+    Files can be specified as filenames or filesets.
+
+    If no files are given to match, this command prints the names
+    of all files under Mercurial control.
+
+    .. container:: verbose
+
+      Examples:
+
+      - list all files under the current directory::
+
+          hg files .
+
+      - shows sizes and flags for current revision::
+
+          hg files -vr .
+
+      - list all files named README::
+
+          hg files -I "**/README"
+
+      - list all binary files::
+
+          hg files "set:binary()"
+
+      - find files containing a regular expression::
+
+          hg files "set:grep('bob')"
+
+      - search tracked file contents with xargs and grep::
+
+          hg files -0 | xargs -0 grep foo
+
+    See :hg:`help patterns` and :hg:`help filesets` for more information
+    on specifying file patterns.
+
+    Returns 0 if a match is found, 1 otherwise."#
+}
@@ -6,17 +6,21 @@
  */
 
 use std::convert::{TryFrom, TryInto};
+use std::os::raw::c_long;
 use std::pin::Pin;
 use std::sync::Arc;
 
-use curl::easy::Easy2;
+use curl::{
+    easy::Easy2,
+    multi::{Multi, MultiError},
+};
 use futures::prelude::*;
 
 use crate::{
     driver::MultiDriver,
     errors::{Abort, HttpClientError},
     handler::{Buffered, Streaming},
-    pool::Pool,
+    pool::{Pool, PoolMulti},
     progress::Progress,
     receiver::{ChannelReceiver, Receiver},
     request::{Request, StreamRequest},
@@ -43,6 +47,11 @@ pub struct HttpClient {
     pool: Pool,
     report_stats: Option<Arc<dyn Fn(&Stats) + Send + Sync + 'static>>,
     verbose: bool,
+    max_connections: Option<usize>,
+    max_host_connections: Option<usize>,
+    max_cached_connections: Option<usize>,
+    max_concurrent_streams: Option<usize>,
+    multiplex: bool,
 }
 
 impl HttpClient {
@@ -51,6 +60,11 @@ impl HttpClient {
             pool: Pool::new(),
             report_stats: None,
             verbose: false,
+            max_connections: None,
+            max_host_connections: None,
+            max_cached_connections: None,
+            max_concurrent_streams: None,
+            multiplex: true,
         }
     }
 
@@ -74,6 +88,71 @@ impl HttpClient {
         Self { verbose, ..self }
     }
 
+    /// Maximum number of connections open at once by a batch of concurrent
+    /// transfers. Transfers beyond the limit are queued until a connection is
+    /// available. Unlimited by default.
+    pub fn max_connections(self, max_connections: Option<usize>) -> Self {
+        Self {
+            max_connections,
+            ..self
+        }
+    }
+
+    /// Maximum number of connections open at once to a single host. Since
+    /// connections are only reused for the same host, this mostly matters
+    /// for HTTP/1.1, where each connection carries one transfer at a time.
+    /// Unlimited by default.
+    pub fn max_host_connections(self, max_host_connections: Option<usize>) -> Self {
+        Self {
+            max_host_connections,
+            ..self
+        }
+    }
+
+    /// Maximum number of idle connections kept open for reuse by later
+    /// transfers. By default, libcurl keeps up to 4 times as many
+    /// connections as there are concurrent transfers.
+    pub fn max_cached_connections(self, max_cached_connections: Option<usize>) -> Self {
+        Self {
+            max_cached_connections,
+            ..self
+        }
+    }
+
+    /// Maximum number of concurrent transfers multiplexed over a single
+    /// HTTP/2 connection. Beyond this, libcurl opens another connection.
+    /// Uses libcurl's default of 100 if unset.
+    pub fn max_concurrent_streams(self, max_concurrent_streams: Option<usize>) -> Self {
+        Self {
+            max_concurrent_streams,
+            ..self
+        }
+    }
+
+    /// Whether concurrent transfers to the same host may share an HTTP/2
+    /// connection. Enabled by default; requests should also set `pipewait`
+    /// so that they wait for an existing connection rather than racing to
+    /// open their own.
+    pub fn multiplex(self, multiplex: bool) -> Self {
+        Self { multiplex, ..self }
+    }
+
+    /// Take a `Multi` handle from the pool, configured for this client.
+    fn multi(&self) -> Result<PoolMulti, HttpClientError> {
+        let mut pool_multi = self.pool.multi();
+        let multi = pool_multi.get_mut();
+        // Zero lifts the limits, which matters for handles reused from the pool.
+        multi.set_max_total_connections(self.max_connections.unwrap_or(0))?;
+        multi.set_max_host_connections(self.max_host_connections.unwrap_or(0))?;
+        // Zero is libcurl's default, sizing the cache from the number of transfers.
+        multi.set_max_connects(self.max_cached_connections.unwrap_or(0))?;
+        multi.pipelining(false, self.multiplex)?;
+        if let Some(max_concurrent_streams) = self.max_concurrent_streams {
+            set_max_concurrent_streams(multi, max_concurrent_streams)?;
+        }
+        Ok(pool_multi)
+    }
+
     /// Perform multiple HTTP requests concurrently.
     ///
     /// This function will block until all transfers have completed.
@@ -105,7 +184,7 @@ impl HttpClient {
         F: FnMut(Result<Response, HttpClientError>) -> Result<(), Abort>,
         P: FnMut(Progress),
     {
-        let multi = self.pool.multi();
+        let multi = self.multi()?;
         let driver = MultiDriver::new(multi.get(), progress_cb, self.verbose);
 
         for request in requests {
@@ -210,7 +289,7 @@ impl HttpClient {
         R: Receiver,
         P: FnMut(Progress),
     {
-        let multi = self.pool.multi();
+        let multi = self.multi()?;
         let driver = MultiDriver::new(multi.get(), progress_cb, self.verbose);
 
         for request in requests {
@@ -232,6 +311,24 @@ impl HttpClient {
 /// Callback for `MultiDriver::perform` when working with
 /// a `Streaming` handler. Reports the result of the
 /// completed request to the handler's `Receiver`.
+// Not bound by the `curl` crate yet. Added in libcurl 7.67.0.
+const CURLMOPT_MAX_CONCURRENT_STREAMS: curl_sys::CURLMoption = curl_sys::CURLOPTTYPE_LONG + 16;
+
+fn set_max_concurrent_streams(multi: &mut Multi, streams: usize) -> Result<(), MultiError> {
+    let code = unsafe {
+        curl_sys::curl_multi_setopt(
+            multi.raw(),
+            CURLMOPT_MAX_CONCURRENT_STREAMS,
+            streams as c_long,
+        )
+    };
+    if code == curl_sys::CURLM_OK {
+        Ok(())
+    } else {
+        Err(MultiError::new(code))
+    }
+}
+
 fn report_result_and_drop_receiver<R: Receiver>(
     res: Result<Easy2<Streaming<R>>, (Easy2<Streaming<R>>, curl::Error)>,
 ) -> Result<(), Abort> {
@@ -323,6 +420,28 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_connection_limits() -> Result<()> {
+        let mock = mock("GET", "/test").with_status(201).expect(4).create();
+        let url = Url::parse(&mockito::server_url())?.join("test")?;
+
+        // Mockito only speaks HTTP/1.1, so transfers queue for the single connection.
+        let client = HttpClient::new()
+            .max_connections(Some(1))
+            .max_host_connections(Some(1))
+            .max_cached_connections(Some(1))
+            .max_concurrent_streams(Some(2));
+        let requests = (0..4).map(|_| Request::get(url.clone()).pipewait(true));
+        let stats = client.send(requests, |res| {
+            assert_eq!(res.unwrap().status, StatusCode::CREATED);
+            Ok(())
+        })?;
+
+        mock.assert();
+        assert_eq!(stats.requests, 4);
+        Ok(())
+    }
+
     #[test]
     fn test_stream() -> Result<()> {
         let body1 = b"body1";
@@ -66,6 +66,7 @@ pub struct Request {
     cainfo: Option<PathBuf>,
     timeout: Option<Duration>,
     http_version: HttpVersion,
+    pipewait: bool,
     min_transfer_speed: Option<MinTransferSpeed>,
 }
 
@@ -85,6 +86,7 @@ impl Request {
             // Attempt to use HTTP/2 by default. Will fall back to HTTP/1.1
             // if version negotiation with the server fails.
             http_version: HttpVersion::V2,
+            pipewait: false,
             min_transfer_speed: None,
         }
     }
@@ -125,6 +127,13 @@ impl Request {
         }
     }
 
+    /// Wait for a connection to the same host which is being set up, or is
+    /// busy, if it might be able to multiplex this request, rather than
+    /// opening a new connection. Only useful for concurrent requests.
+    pub fn pipewait(self, pipewait: bool) -> Self {
+        Self { pipewait, ..self }
+    }
+
     /// Set transfer speed options for this request.
     pub fn min_transfer_speed(self, min_transfer_speed: MinTransferSpeed) -> Self {
         Self {
@@ -296,6 +305,7 @@ impl Request {
         }
 
         easy.http_version(self.http_version)?;
+        easy.pipewait(self.pipewait)?;
 
         if let Some(mts) = self.min_transfer_speed {
             easy.low_speed_limit(mts.min_bytes_per_second)?;
@@ -63,6 +63,22 @@ impl ScopedDirLock {
         Ok(result)
     }
 
+    /// Lock the given directory, unless it is already locked, in which case `None` is returned
+    /// rather than waiting for it to be unlocked.
+    pub fn try_new(path: &Path) -> crate::Result<Option<Self>> {
+        let file = crate::utils::open_dir(path).context(path, "cannot open for locking")?;
+        match file.try_lock_exclusive() {
+            Ok(()) => {}
+            Err(e) if e.kind() == fs2::lock_contended_error().kind() => return Ok(None),
+            Err(e) => return Err(e).context(path, "cannot lock"),
+        }
+        let result = Self {
+            file,
+            path: path.to_path_buf(),
+        };
+        Ok(Some(result))
+    }
+
     /// Get the path to the directory being locked.
     pub fn path(&self) -> &Path {
         &self.path
@@ -140,6 +156,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_dir_try_lock() {
+        let dir = tempdir().unwrap();
+        let lock = ScopedDirLock::try_new(dir.path()).unwrap();
+        assert!(lock.is_some());
+        assert!(ScopedDirLock::try_new(dir.path()).unwrap().is_none());
+        drop(lock);
+        assert!(ScopedDirLock::try_new(dir.path()).unwrap().is_some());
+    }
+
     #[test]
     fn test_dir_lock() {
         let dir = tempdir().unwrap();
@@ -620,6 +620,20 @@ impl RotateLog {
     pub fn iter_dirty(&mut self) -> impl Iterator<Item = crate::Result<&[u8]>> {
         self.writable_log().iter_dirty()
     }
+
+    /// Convert a slice to [`Bytes`].
+    /// Do not copy the slice if it's from the on-disk buffer of one of the logs.
+    ///
+    /// Only logs that are already loaded are considered, since slices can only
+    /// come from those. This does not load any log.
+    pub fn slice_to_bytes(&self, slice: &[u8]) -> Bytes {
+        for log in self.logs.iter().filter_map(|log| log.get()) {
+            if log.disk_buf.range_of_slice(slice).is_some() {
+                return log.slice_to_bytes(slice);
+            }
+        }
+        Bytes::copy_from_slice(slice)
+    }
 }
 
 /// Wrap `Log` in a `OnceCell`.
@@ -1318,6 +1332,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_slice_to_bytes() {
+        let dir = tempdir().unwrap();
+        let mut rotate = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(100)
+            .open(&dir)
+            .unwrap();
+
+        rotate.append(vec![b'a'; 101]).unwrap();
+        rotate.sync().unwrap(); // trigger rotate
+        rotate.append(vec![b'b'; 10]).unwrap();
+        rotate.sync().unwrap();
+        rotate.append(vec![b'c'; 10]).unwrap();
+
+        // Entries on disk, in the latest log or a rotated one, are not copied.
+        for entry in rotate.iter() {
+            let entry = entry.unwrap();
+            let bytes = rotate.slice_to_bytes(entry);
+            assert_eq!(bytes.as_ref(), entry);
+            assert_eq!(bytes.as_ptr() == entry.as_ptr(), entry[0] != b'c');
+        }
+    }
+
     #[test]
     fn test_recover_from_empty_logs() {
         let dir = tempdir().unwrap();
@@ -670,6 +670,63 @@ pub fn prefetch(
     Ok(())
 }
 
+/// What a `warm_up` pass walked and fetched.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WarmUpStats {
+    /// Number of tree nodes walked.
+    pub trees: usize,
+    /// Number of tree nodes which were missing locally, and were prefetched.
+    pub fetched: usize,
+    /// Number of prefetch requests sent to the store.
+    pub batches: usize,
+}
+
+/// Prefetch the tree nodes under the given Key, up to the given depth, which are missing from
+/// the local store.
+///
+/// This is meant to run at startup on the working copy parent's root tree, so that the first
+/// command which walks the working copy after a clone or a pull, like `hg status`, doesn't fault
+/// in hundreds of trees one at a time. Unlike `prefetch`, the nodes already present are filtered
+/// out here rather than by the store, and the missing ones are sent in batches of at most
+/// `batch_size` keys, so that a single level of a wide tree doesn't turn into one huge request.
+pub fn warm_up(
+    store: Arc<dyn TreeStore + Send + Sync>,
+    key: Key,
+    batch_size: usize,
+    mut depth: Option<usize>,
+) -> Result<WarmUpStats> {
+    let tree = TreeManifest::durable(store, key.hgid);
+    let mut dirs = vec![DirLink::from_link(&tree.root, key.path).unwrap()];
+    let mut stats = WarmUpStats::default();
+
+    while !dirs.is_empty() {
+        let keys = dirs.iter().filter_map(|d| d.key()).collect::<Vec<_>>();
+        stats.trees += keys.len();
+        let missing = tree.store.get_missing(&keys)?;
+        stats.fetched += missing.len();
+        for batch in missing.chunks(batch_size.max(1)) {
+            tree.store.prefetch(batch.to_vec())?;
+            stats.batches += 1;
+        }
+
+        dirs = dirs
+            .into_iter()
+            .map(|d| Ok(d.list(&tree.store)?.1))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        depth = match depth {
+            Some(0) => break,
+            Some(d) => Some(d - 1),
+            None => None,
+        };
+    }
+
+    Ok(stats)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1507,4 +1564,99 @@ mod tests {
             ]),
         );
     }
+
+    /// Serves tree nodes from `local`, where they are copied from `remote` when prefetched.
+    struct WarmUpStore {
+        local: TestStore,
+        remote: Arc<TestStore>,
+    }
+
+    impl TreeStore for WarmUpStore {
+        fn get(&self, path: &RepoPath, hgid: HgId) -> Result<Bytes> {
+            self.local.get(path, hgid)
+        }
+
+        fn insert(&self, path: &RepoPath, hgid: HgId, data: Bytes) -> Result<()> {
+            self.local.insert(path, hgid, data)
+        }
+
+        fn prefetch(&self, keys: Vec<Key>) -> Result<()> {
+            for key in keys.iter() {
+                let data = self.remote.get(&key.path, key.hgid)?;
+                self.local.insert(&key.path, key.hgid, data)?;
+            }
+            self.local.prefetch(keys)
+        }
+
+        fn get_missing(&self, keys: &[Key]) -> Result<Vec<Key>> {
+            Ok(keys
+                .iter()
+                .filter(|key| self.local.get(&key.path, key.hgid).is_err())
+                .cloned()
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let remote = Arc::new(TestStore::new());
+        let mut tree = TreeManifest::ephemeral(remote.clone());
+        tree.insert(repo_path_buf("a1/b1/c1/d1"), make_meta("10"))
+            .unwrap();
+        tree.insert(repo_path_buf("a1/b2"), make_meta("20"))
+            .unwrap();
+        tree.insert(repo_path_buf("a2/b2/c2"), make_meta("30"))
+            .unwrap();
+        tree.insert(repo_path_buf("a3/b3"), make_meta("40"))
+            .unwrap();
+        tree.insert(repo_path_buf("a4/b4"), make_meta("50"))
+            .unwrap();
+        let root = tree.flush().unwrap();
+
+        // The root and "a1" trees are already present locally.
+        let store = Arc::new(WarmUpStore {
+            local: TestStore::new(),
+            remote: remote.clone(),
+        });
+        for path in [RepoPath::empty(), repo_path("a1")].iter() {
+            let hgid = match tree.get(path).unwrap().unwrap() {
+                FsNodeMetadata::Directory(Some(hgid)) => hgid,
+                _ => panic!("expected a durable directory at {}", path),
+            };
+            store
+                .insert(path, hgid, remote.get(path, hgid).unwrap())
+                .unwrap();
+        }
+
+        let key = Key::new(RepoPathBuf::new(), root);
+        let stats = warm_up(store.clone(), key.clone(), 2, None).unwrap();
+        assert_eq!(
+            stats,
+            WarmUpStats {
+                trees: 8,
+                fetched: 6,
+                batches: 4,
+            }
+        );
+        assert_eq!(
+            store
+                .local
+                .fetches()
+                .iter()
+                .map(|keys| keys.len())
+                .collect::<Vec<_>>(),
+            vec![2, 1, 2, 1]
+        );
+
+        let tree = TreeManifest::durable(store.clone(), root);
+        assert_eq!(
+            tree.get_file(repo_path("a1/b1/c1/d1")).unwrap(),
+            Some(make_meta("10"))
+        );
+
+        // Everything is present locally now.
+        let stats = warm_up(store.clone(), key, 2, None).unwrap();
+        assert_eq!(stats.fetched, 0);
+        assert_eq!(stats.batches, 0);
+    }
 }
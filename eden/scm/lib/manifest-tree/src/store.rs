@@ -32,6 +32,13 @@ pub trait TreeStore {
     fn prefetch(&self, _keys: Vec<Key>) -> Result<()> {
         Ok(())
     }
+
+    /// Return the keys whose tree nodes aren't available locally, and would have to be fetched.
+    /// Stores which can't tell what they have locally return all the keys, which is also the
+    /// default implementation.
+    fn get_missing(&self, keys: &[Key]) -> Result<Vec<Key>> {
+        Ok(keys.to_vec())
+    }
 }
 
 #[derive(Clone)]
@@ -75,6 +82,11 @@ impl InnerStore {
         )
         .in_scope(|| self.tree_store.prefetch(keys))
     }
+
+    pub fn get_missing(&self, keys: &[Key]) -> Result<Vec<Key>> {
+        tracing::debug_span!("tree::store::get_missing", count = keys.len())
+            .in_scope(|| self.tree_store.get_missing(keys))
+    }
 }
 
 /// The `Entry` is the data that is stored on disk. It should be seen as opaque to whether it
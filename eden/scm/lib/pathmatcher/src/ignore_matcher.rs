@@ -0,0 +1,409 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Matcher for the rules of ignore files
+//!
+//! [IgnoreMatcher] evaluates the rules of `.gitignore` and `.hgignore` files
+//! given their content. Unlike [crate::GitignoreMatcher], it does not read
+//! anything from the file system, so it can be used on any tree, such as a
+//! manifest.
+
+use anyhow::{bail, format_err, Result};
+use bitflags::bitflags;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::RegexSet;
+
+use types::RepoPath;
+
+use crate::{plain_to_glob, DirectoryMatch, Matcher};
+
+bitflags! {
+    struct RuleFlags: u8 {
+        // A negative rule (ex. "!foo"), which un-ignores paths.
+        const NEGATIVE = 1;
+
+        // A rule which only matches directories (ex. "foo/").
+        const DIR_ONLY = 2;
+    }
+}
+
+/// Collects the rules of ignore files, in order of precedence, to build an
+/// [IgnoreMatcher].
+pub struct IgnoreMatcherBuilder {
+    globs: GlobSetBuilder,
+    glob_rules: Vec<usize>,
+    regexes: Vec<String>,
+    regex_rules: Vec<usize>,
+    rule_flags: Vec<RuleFlags>,
+}
+
+impl Default for IgnoreMatcherBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IgnoreMatcherBuilder {
+    pub fn new() -> Self {
+        Self {
+            globs: GlobSetBuilder::new(),
+            glob_rules: Vec::new(),
+            regexes: Vec::new(),
+            regex_rules: Vec::new(),
+            rule_flags: Vec::new(),
+        }
+    }
+
+    /// Add the rules of the `.gitignore` file of the directory `dir`, which is
+    /// relative to the root and empty for the root itself.
+    ///
+    /// The syntax is the one of git:
+    /// - Blank lines and lines starting with `#` are skipped. Trailing spaces
+    ///   are removed unless escaped with `\`.
+    /// - `!` negates a rule: the paths it matches are no longer ignored.
+    ///   However, the paths in an ignored directory cannot be un-ignored.
+    /// - Rules ending with `/` only match directories.
+    /// - Rules with a `/` elsewhere are relative to `dir`. Other rules match
+    ///   at any level below `dir`.
+    ///
+    /// Rules added later take precedence, so the `.gitignore` files of parent
+    /// directories should be added before the ones of their subdirectories.
+    pub fn add_gitignore(&mut self, dir: &str, content: &str) -> Result<&mut Self> {
+        for line in content.lines() {
+            let line = trim_trailing_spaces(line.trim_end_matches('\r'));
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut flags = RuleFlags::empty();
+            let mut pat = line;
+            if pat.starts_with('!') {
+                flags |= RuleFlags::NEGATIVE;
+                pat = &pat[1..];
+            } else if pat.starts_with("\\!") || pat.starts_with("\\#") {
+                pat = &pat[1..];
+            }
+            if pat.ends_with('/') {
+                flags |= RuleFlags::DIR_ONLY;
+                pat = pat.trim_end_matches('/');
+            }
+            if pat.is_empty() {
+                continue;
+            }
+
+            let anchored = pat.contains('/');
+            let pat = pat.strip_prefix('/').unwrap_or(pat);
+            let glob = match (dir.is_empty(), anchored) {
+                (true, true) => pat.to_string(),
+                (true, false) => format!("**/{}", pat),
+                (false, true) => format!("{}/{}", plain_to_glob(dir), pat),
+                (false, false) => format!("{}/**/{}", plain_to_glob(dir), pat),
+            };
+            self.add_glob(&glob, flags)
+                .map_err(|e| format_err!("invalid rule {:?} in {}/.gitignore: {}", line, dir, e))?;
+        }
+        Ok(self)
+    }
+
+    /// Add the rules of a `.hgignore` file, which are relative to the root.
+    ///
+    /// The syntax is the one of Mercurial:
+    /// - `#` starts a comment, unless escaped with `\`. Blank lines are
+    ///   skipped.
+    /// - `syntax: regexp` (the default), `syntax: glob` or `syntax: rootglob`
+    ///   sets the kind of the rules after it. Invalid syntaxes are skipped
+    ///   like Mercurial does, keeping the previous one.
+    /// - A rule may override the kind with a `re:`, `glob:` or `rootglob:`
+    ///   prefix.
+    /// - Regular expressions match anywhere in the path unless they start
+    ///   with `^`. Globs match at any level, and root globs from the root.
+    ///
+    /// Ignoring a directory ignores everything in it. `include:` and
+    /// `subinclude:` rules are not supported.
+    pub fn add_hgignore(&mut self, content: &str) -> Result<&mut Self> {
+        let mut syntax = "relre";
+        for line in content.lines() {
+            let line = strip_hgignore_comment(line);
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(kind) = line.strip_prefix("syntax:") {
+                if let Some(kind) = hgignore_kind(kind.trim()) {
+                    syntax = kind;
+                }
+                continue;
+            }
+
+            let (kind, pat) = match line.find(':') {
+                Some(sep) => match hgignore_kind(&line[..sep]) {
+                    Some(kind) => (kind, &line[sep + 1..]),
+                    None => (syntax, line),
+                },
+                None => (syntax, line),
+            };
+            let result = match kind {
+                "relre" => self.add_regex(pat),
+                "relglob" => self.add_glob(&format!("**/{}", pat), RuleFlags::empty()),
+                "rootglob" => self.add_glob(pat, RuleFlags::empty()),
+                _ => bail!("{} rules are not supported in .hgignore: {:?}", kind, line),
+            };
+            result.map_err(|e| format_err!("invalid rule {:?} in .hgignore: {}", line, e))?;
+        }
+        Ok(self)
+    }
+
+    pub fn build(&self) -> Result<IgnoreMatcher> {
+        Ok(IgnoreMatcher {
+            glob_set: self.globs.build()?,
+            glob_rules: self.glob_rules.clone(),
+            regex_set: RegexSet::new(&self.regexes)?,
+            regex_rules: self.regex_rules.clone(),
+            rule_flags: self.rule_flags.clone(),
+        })
+    }
+
+    fn add_glob(&mut self, pat: &str, flags: RuleFlags) -> Result<()> {
+        let glob = GlobBuilder::new(pat).literal_separator(true).build()?;
+        self.globs.add(glob);
+        self.glob_rules.push(self.rule_flags.len());
+        self.rule_flags.push(flags);
+        Ok(())
+    }
+
+    fn add_regex(&mut self, pat: &str) -> Result<()> {
+        // Check the regex now, so the error points at the rule.
+        RegexSet::new([pat])?;
+        self.regexes.push(pat.to_string());
+        self.regex_rules.push(self.rule_flags.len());
+        self.rule_flags.push(RuleFlags::empty());
+        Ok(())
+    }
+}
+
+/// Pattern matcher for the rules of `.gitignore` and `.hgignore` files,
+/// created by [IgnoreMatcherBuilder].
+///
+/// The last rule matching a path decides if it is ignored. A path is also
+/// ignored if any of its parent directories is.
+#[derive(Clone, Debug)]
+pub struct IgnoreMatcher {
+    glob_set: GlobSet,
+    // Index of the rule of each glob of `glob_set`.
+    glob_rules: Vec<usize>,
+    regex_set: RegexSet,
+    // Index of the rule of each regex of `regex_set`.
+    regex_rules: Vec<usize>,
+    rule_flags: Vec<RuleFlags>,
+}
+
+impl IgnoreMatcher {
+    /// Return if `path` is ignored.
+    ///
+    /// `/` should be used as the path separator, regardless of system.
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        for (index, _) in path.match_indices('/') {
+            if self.match_rules(&path[..index], true) == Some(true) {
+                return true;
+            }
+        }
+        self.match_rules(path, is_dir) == Some(true)
+    }
+
+    /// Return if `path` is ignored by the last rule matching it, regardless
+    /// of its parent directories, or `None` if no rule matches it.
+    fn match_rules(&self, path: &str, is_dir: bool) -> Option<bool> {
+        let globs = self
+            .glob_set
+            .matches(path)
+            .into_iter()
+            .map(|id| self.glob_rules[id]);
+        let regexes = self
+            .regex_set
+            .matches(path)
+            .into_iter()
+            .map(|id| self.regex_rules[id]);
+        globs
+            .chain(regexes)
+            .filter(|&rule| is_dir || !self.rule_flags[rule].contains(RuleFlags::DIR_ONLY))
+            .max()
+            .map(|rule| !self.rule_flags[rule].contains(RuleFlags::NEGATIVE))
+    }
+}
+
+impl Matcher for IgnoreMatcher {
+    fn matches_directory(&self, path: &RepoPath) -> Result<DirectoryMatch> {
+        let dm = if self.rule_flags.is_empty() {
+            DirectoryMatch::Nothing
+        } else if !path.as_str().is_empty() && self.matches(path.as_str(), true) {
+            DirectoryMatch::Everything
+        } else {
+            DirectoryMatch::ShouldTraverse
+        };
+        Ok(dm)
+    }
+
+    fn matches_file(&self, path: &RepoPath) -> Result<bool> {
+        Ok(self.matches(path.as_str(), false))
+    }
+}
+
+/// Remove the trailing spaces of a `.gitignore` line, except escaped ones.
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    while line[..end].ends_with(' ') && !line[..end - 1].ends_with('\\') {
+        end -= 1;
+    }
+    &line[..end]
+}
+
+/// Remove the comment of a `.hgignore` line, and unescape the `#` in the
+/// rest of it.
+fn strip_hgignore_comment(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some('#') => result.push('#'),
+                Some(next) => {
+                    result.push('\\');
+                    result.push(next);
+                }
+                None => result.push('\\'),
+            },
+            '#' => break,
+            ch => result.push(ch),
+        }
+    }
+    result
+}
+
+/// The kind of the rules of a `.hgignore` syntax or rule prefix.
+fn hgignore_kind(name: &str) -> Option<&'static str> {
+    match name {
+        "re" | "regexp" | "relre" => Some("relre"),
+        "glob" | "relglob" => Some("relglob"),
+        "rootglob" => Some("rootglob"),
+        "include" => Some("include"),
+        "subinclude" => Some("subinclude"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gitignore(rules: &[(&str, &str)]) -> IgnoreMatcher {
+        let mut builder = IgnoreMatcherBuilder::new();
+        for (dir, content) in rules {
+            builder.add_gitignore(dir, content).unwrap();
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_gitignore_rules() {
+        let m = gitignore(&[("", "# comment\n*.o\n!keep.o\nbuild/\n/root.txt\n")]);
+        assert!(m.matches("a.o", false));
+        assert!(m.matches("x/y/a.o", false));
+        assert!(!m.matches("x/keep.o", false));
+        assert!(!m.matches("# comment", false));
+
+        // Directory-only rules don't match files, but do match the files in
+        // the directories they match.
+        assert!(!m.matches("x/build", false));
+        assert!(m.matches("x/build", true));
+        assert!(m.matches("x/build/a.txt", false));
+
+        // Rules with a "/" are anchored.
+        assert!(m.matches("root.txt", false));
+        assert!(!m.matches("x/root.txt", false));
+    }
+
+    #[test]
+    fn test_gitignore_precedence() {
+        let m = gitignore(&[
+            ("", "*.log\nlogs/\n"),
+            ("a", "!*.log\n!logs/\nb/c.txt\n"),
+            ("a/b", "x.log\n"),
+        ]);
+        assert!(m.matches("x.log", false));
+        // Later rules override earlier ones.
+        assert!(!m.matches("a/x.log", false));
+        assert!(!m.matches("a/y/x.log", false));
+        assert!(m.matches("a/b/x.log", false));
+        assert!(m.matches("a/b/c.txt", false));
+        assert!(!m.matches("b/c.txt", false));
+        // Files cannot be un-ignored if their parent directory is ignored.
+        assert!(m.matches("logs/x.log", false));
+        assert!(!m.matches("a/logs", true));
+        assert!(m.matches("logs", true));
+    }
+
+    #[test]
+    fn test_gitignore_escapes() {
+        let m = gitignore(&[("", "\\!important\n\\#hash\ntrailing\\ \nspaces   \n")]);
+        assert!(m.matches("!important", false));
+        assert!(m.matches("#hash", false));
+        assert!(m.matches("trailing ", false));
+        assert!(m.matches("spaces", false));
+        assert!(!m.matches("spaces ", false));
+    }
+
+    #[test]
+    fn test_hgignore() {
+        let mut builder = IgnoreMatcherBuilder::new();
+        builder
+            .add_hgignore(
+                r#"
+# regexps match anywhere
+\.orig$
+^build/
+syntax: glob
+*.pyc  # compiled
+tmp
+glob:nothash\#
+syntax: invalid
+re:^gen
+syntax: rootglob
+docs/*.html
+"#,
+            )
+            .unwrap();
+        let m = builder.build().unwrap();
+        assert!(m.matches("a/b.orig", false));
+        assert!(!m.matches("a/b.orig.txt", false));
+        assert!(m.matches("build/x", false));
+        assert!(!m.matches("a/build/x", false));
+        assert!(m.matches("a/b.pyc", false));
+        assert!(m.matches("a/tmp/c", false));
+        assert!(m.matches("nothash#", false));
+        assert!(m.matches("genfiles/x", false));
+        assert!(!m.matches("a/genfiles/x", false));
+        assert!(m.matches("docs/a.html", false));
+        assert!(!m.matches("x/docs/a.html", false));
+
+        let dir = |path| RepoPath::from_str(path).unwrap();
+        assert_eq!(
+            m.matches_directory(dir("a/tmp")).unwrap(),
+            DirectoryMatch::Everything
+        );
+        assert_eq!(
+            m.matches_directory(dir("a")).unwrap(),
+            DirectoryMatch::ShouldTraverse
+        );
+    }
+
+    #[test]
+    fn test_hgignore_unsupported() {
+        let mut builder = IgnoreMatcherBuilder::new();
+        assert!(builder.add_hgignore("include:other").is_err());
+        assert!(builder.add_hgignore("re:(").is_err());
+    }
+}
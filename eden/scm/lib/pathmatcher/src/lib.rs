@@ -6,6 +6,7 @@
  */
 
 mod gitignore_matcher;
+mod ignore_matcher;
 mod tree_matcher;
 mod utils;
 
@@ -87,5 +88,6 @@ impl Matcher for NeverMatcher {
 }
 
 pub use gitignore_matcher::GitignoreMatcher;
+pub use ignore_matcher::{IgnoreMatcher, IgnoreMatcherBuilder};
 pub use tree_matcher::TreeMatcher;
 pub use utils::{expand_curly_brackets, normalize_glob, plain_to_glob};
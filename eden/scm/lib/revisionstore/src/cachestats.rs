@@ -0,0 +1,311 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Cache effectiveness counters, kept across processes.
+//!
+//! The `FetchMetrics` counters of the stores only live as long as the process. When a command
+//! exits, `CacheStats::record` adds the hits, misses, remote requests and bytes of each store to
+//! running totals kept in a small indexedlog in the cache directory, which
+//! `hg debugcachestats` reads back.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use mincode::{deserialize, serialize};
+use serde_derive::{Deserialize, Serialize};
+
+use configparser::config::ConfigSet;
+use indexedlog::{lock::ScopedDirLock, log::IndexOutput};
+use util::path::create_shared_dir;
+
+use crate::{
+    indexedlogutil::{Store, StoreOpenOptions},
+    util::Error,
+};
+
+/// Version of the on-disk format, which is also the key every entry is indexed by.
+const VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreCounters {
+    /// Keys requested from the store.
+    pub requested: u64,
+    /// Keys the store served.
+    pub hits: u64,
+    /// Keys the store reported as not found.
+    pub misses: u64,
+    /// Requests the store sent to a server.
+    pub remote_requests: u64,
+    /// Content bytes the store served.
+    pub bytes: u64,
+}
+
+impl StoreCounters {
+    /// The share of lookups which were hits, if there were any.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / lookups as f64)
+        }
+    }
+
+    fn add(&mut self, other: &StoreCounters) {
+        self.requested += other.requested;
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.remote_requests += other.remote_requests;
+        self.bytes += other.bytes;
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == StoreCounters::default()
+    }
+}
+
+impl fmt::Display for StoreCounters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} requested, {} hits, {} misses",
+            self.requested, self.hits, self.misses
+        )?;
+        if let Some(ratio) = self.hit_ratio() {
+            write!(f, " ({:.1}% hit ratio)", ratio * 100.0)?;
+        }
+        write!(
+            f,
+            ", {} remote requests, {} bytes",
+            self.remote_requests, self.bytes
+        )
+    }
+}
+
+/// Parse the counters of each store out of the `scmstore.<store>.<counter>` process metrics.
+pub fn counters_from_metrics(
+    metrics: impl IntoIterator<Item = (String, usize)>,
+) -> BTreeMap<String, StoreCounters> {
+    let mut counters: BTreeMap<String, StoreCounters> = BTreeMap::new();
+    for (name, value) in metrics {
+        let name = match name.strip_prefix("scmstore.") {
+            Some(name) => name,
+            None => continue,
+        };
+        // Store names can contain dots, like "edenapi.files", so the counter is the last part.
+        let (store, counter) = match name.rfind('.') {
+            Some(i) => (&name[..i], &name[i + 1..]),
+            None => continue,
+        };
+        let field = {
+            let store_counters = counters.entry(store.to_string()).or_default();
+            match counter {
+                "requested" => &mut store_counters.requested,
+                "hits" => &mut store_counters.hits,
+                "misses" => &mut store_counters.misses,
+                "requests" => &mut store_counters.remote_requests,
+                "bytes" => &mut store_counters.bytes,
+                _ => continue,
+            }
+        };
+        *field += value as u64;
+    }
+    counters.retain(|_, store_counters| !store_counters.is_empty());
+    counters
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Counters of each store since they were first recorded.
+    pub total: BTreeMap<String, StoreCounters>,
+
+    /// Counters of each store since the last reset.
+    pub since_reset: BTreeMap<String, StoreCounters>,
+
+    /// When the counters were last reset, in seconds since the epoch.
+    pub reset_time: Option<u64>,
+}
+
+impl CacheStats {
+    /// Where the stats of the cache at `remotefilelog.cachepath` are kept. They cover every repo
+    /// sharing the cache.
+    pub fn path(config: &ConfigSet) -> Result<PathBuf> {
+        let cachepath: PathBuf = config
+            .get_or_default::<Option<_>>("remotefilelog", "cachepath")?
+            .ok_or_else(|| Error::ConfigNotSet("remotefilelog.cachepath".into()))?;
+        Ok(cachepath.join("cachestats"))
+    }
+
+    fn open_options() -> StoreOpenOptions {
+        // Only the latest entry is ever read, so the logs can be tiny.
+        StoreOpenOptions::new()
+            .max_log_count(2)
+            .max_bytes_per_log(1000 * 1000)
+            .create(true)
+            .index("version", |_| vec![IndexOutput::Reference(0..1)])
+    }
+
+    fn latest(log: &Store) -> Result<CacheStats> {
+        match log.lookup(0, [VERSION])?.next() {
+            Some(entry) => Ok(deserialize(&entry?[1..])?),
+            None => Ok(CacheStats::default()),
+        }
+    }
+
+    /// Read the stats kept at `path`.
+    pub fn read(path: impl AsRef<Path>) -> Result<CacheStats> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(CacheStats::default());
+        }
+        let log = CacheStats::open_options().shared(path.join("log"))?;
+        CacheStats::latest(&log)
+    }
+
+    /// Apply `update` to the stats kept at `path`, and return the updated stats.
+    ///
+    /// Every entry holds the full stats, so that rotating the logs doesn't lose anything. The
+    /// update is a read-modify-write, done under `_lock`, a lock of the directory, which is
+    /// separate from the lock the log takes on its own directory.
+    fn update(
+        path: &Path,
+        _lock: ScopedDirLock,
+        update: impl FnOnce(&mut CacheStats),
+    ) -> Result<CacheStats> {
+        let mut log = CacheStats::open_options().shared(path.join("log"))?;
+        let mut stats = CacheStats::latest(&log)?;
+        update(&mut stats);
+
+        let mut buf = vec![VERSION];
+        buf.extend_from_slice(&serialize(&stats)?);
+        log.append(buf)?;
+        log.flush()?;
+        Ok(stats)
+    }
+
+    /// Add `counters` to the stats kept at `path`. This is done when commands exit, so rather
+    /// than waiting for another process updating the stats, the counters are dropped and `None`
+    /// is returned.
+    pub fn record(
+        path: impl AsRef<Path>,
+        counters: &BTreeMap<String, StoreCounters>,
+    ) -> Result<Option<CacheStats>> {
+        let path = path.as_ref();
+        create_shared_dir(path)?;
+        let lock = match ScopedDirLock::try_new(path)? {
+            Some(lock) => lock,
+            None => return Ok(None),
+        };
+        let stats = CacheStats::update(path, lock, |stats| {
+            for (store, store_counters) in counters {
+                stats
+                    .total
+                    .entry(store.clone())
+                    .or_default()
+                    .add(store_counters);
+                stats
+                    .since_reset
+                    .entry(store.clone())
+                    .or_default()
+                    .add(store_counters);
+            }
+        })?;
+        Ok(Some(stats))
+    }
+
+    /// Reset the counters since the last reset of the stats kept at `path`.
+    pub fn reset(path: impl AsRef<Path>) -> Result<CacheStats> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let path = path.as_ref();
+        create_shared_dir(path)?;
+        let lock = ScopedDirLock::new(path)?;
+        CacheStats::update(path, lock, |stats| {
+            stats.since_reset.clear();
+            stats.reset_time = Some(now);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_counters_from_metrics() {
+        let metrics = vec![
+            ("scmstore.edenapi.files.requested".to_string(), 3),
+            ("scmstore.edenapi.files.hits".to_string(), 2),
+            ("scmstore.edenapi.files.requests".to_string(), 1),
+            ("scmstore.edenapi.files.latency_ms.lt_100".to_string(), 1),
+            ("scmstore.indexedlog.hits".to_string(), 5),
+            ("scmstore.indexedlog.misses".to_string(), 3),
+            ("scmstore.fallback.write_errors".to_string(), 1),
+            ("other.hits".to_string(), 1),
+        ];
+        let counters = counters_from_metrics(metrics);
+        assert_eq!(
+            counters.keys().collect::<Vec<_>>(),
+            vec!["edenapi.files", "indexedlog"]
+        );
+        assert_eq!(
+            counters["edenapi.files"],
+            StoreCounters {
+                requested: 3,
+                hits: 2,
+                remote_requests: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(counters["indexedlog"].hit_ratio(), Some(5.0 / 8.0));
+    }
+
+    #[test]
+    fn test_record_and_reset() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let path = tempdir.path().join("cachestats");
+        assert_eq!(CacheStats::read(&path)?, CacheStats::default());
+
+        let mut counters = BTreeMap::new();
+        counters.insert(
+            "indexedlog".to_string(),
+            StoreCounters {
+                hits: 2,
+                misses: 1,
+                ..Default::default()
+            },
+        );
+        CacheStats::record(&path, &counters)?;
+        CacheStats::record(&path, &counters)?;
+        let stats = CacheStats::read(&path)?;
+        assert_eq!(stats.total["indexedlog"].hits, 4);
+        assert_eq!(stats.since_reset["indexedlog"].misses, 2);
+        assert_eq!(stats.reset_time, None);
+
+        CacheStats::reset(&path)?;
+        CacheStats::record(&path, &counters)?;
+        let stats = CacheStats::read(&path)?;
+        assert_eq!(stats.total["indexedlog"].hits, 6);
+        assert_eq!(stats.since_reset["indexedlog"].hits, 2);
+        assert!(stats.reset_time.is_some());
+
+        // Counters are dropped rather than waiting for another process updating the stats.
+        let lock = ScopedDirLock::new(&path)?;
+        assert_eq!(CacheStats::record(&path, &counters)?, None);
+        drop(lock);
+        assert_eq!(CacheStats::read(&path)?.total["indexedlog"].hits, 6);
+        Ok(())
+    }
+}
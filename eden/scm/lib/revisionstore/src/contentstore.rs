@@ -13,10 +13,12 @@ use std::{
 };
 
 use anyhow::{format_err, Result};
+use futures::{stream, StreamExt};
 use minibytes::Bytes;
 use regex::Regex;
-use tracing::info_span;
+use tracing::{info_span, warn};
 
+use async_runtime::block_on_exclusive as block_on_future;
 use configparser::{config::ConfigSet, convert::ByteCount};
 use hgtime::HgTime;
 use types::{Key, RepoPathBuf};
@@ -26,19 +28,32 @@ use crate::{
         strip_metadata, ContentDataStore, ContentMetadata, Delta, HgIdDataStore,
         HgIdMutableDeltaStore, Metadata, RemoteDataStore, ReportingRemoteDataStore, StoreResult,
     },
-    indexedlogdatastore::{IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+    edenapi::{EdenApiFileStore, EdenApiTreeStore},
+    indexedlogdatastore::{Entry, IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+    indexedloghistorystore::{IndexedLogHgIdHistoryStore, IndexedLogHistoryStoreType},
     lfs::{LfsFallbackRemoteStore, LfsMultiplexer, LfsRemote, LfsStore},
     localstore::{ExtStoredPolicy, LocalStore},
     memcache::MemcacheStore,
     multiplexstore::MultiplexDeltaStore,
+    negativecache::NegativeCache,
+    newstore::{
+        edenapi::{EdenApiAdapter, EdenApiAdapterOptions},
+        fallback::FallbackFetchOptions,
+        getpack::GetPackStore,
+        legacy::LegacyDatastore,
+        prefetch::prefetch,
+        scmstore::{scmstore_enabled, scmstore_offline, ScmStoreBuilder},
+        BoxedReadStore, FetchError,
+    },
     packstore::{CorruptionPolicy, MutableDataPackStore},
-    remotestore::HgIdRemoteStore,
+    remotestore::{HgIdRemoteStore, OfflineRemoteStore},
     repack::RepackLocation,
     types::StoreKey,
     uniondatastore::{UnionContentDataStore, UnionHgIdDataStore},
     util::{
         check_run_once, get_cache_packs_path, get_cache_path, get_indexedlogdatastore_path,
-        get_local_path, get_packs_path, RUN_ONCE_FILENAME,
+        get_indexedloghistorystore_path, get_local_path, get_negativecache_path, get_packs_path,
+        RUN_ONCE_FILENAME,
     },
 };
 
@@ -53,6 +68,17 @@ pub struct ContentStore {
     remote_store: Option<Arc<ReportingRemoteDataStore>>,
 
     blob_stores: UnionContentDataStore<Arc<dyn ContentDataStore>>,
+
+    /// When `scmstore.enabled` is set, fetches are first served by the newstore stack, and only
+    /// go through the legacy stores for keys it couldn't find.
+    scmstore: Option<BoxedReadStore<Key, Entry>>,
+    /// The local stores of the `scmstore` stack, which never go to the network.
+    scmstore_local: Option<BoxedReadStore<Key, Entry>>,
+    /// The same stack as `scmstore`, tuned for prefetching many keys at once.
+    scmstore_prefetch: Option<BoxedReadStore<Key, Entry>>,
+    /// Keys recently found missing from the server by the scmstore stack, invalidated on refresh.
+    negative_cache: Option<Arc<NegativeCache>>,
+    extstored_policy: ExtStoredPolicy,
 }
 
 impl ContentStore {
@@ -125,6 +151,93 @@ impl ContentStore {
     pub fn get_shared_mutable(&self) -> Arc<dyn HgIdMutableDeltaStore> {
         self.shared_mutabledatastore.clone()
     }
+
+    /// Fetch a single key through the newstore stack. Keys which the local stores of the stack
+    /// don't have, but the legacy local stores do (eg. in datapacks), are left to the legacy
+    /// stores rather than fetched remotely.
+    fn scmstore_get(&self, key: &StoreKey) -> Result<Option<Entry>> {
+        let local = match self.scmstore_local.as_ref() {
+            Some(local) => local,
+            None => return Ok(None),
+        };
+        match self.scmstore_fetch_with(local, &[key.clone()]).pop() {
+            Some(Ok(entry)) => return Ok(Some(entry)),
+            Some(Err(FetchError::NotFound(_))) | None => {
+                if self.get_missing(&[key.clone()])?.is_empty() {
+                    return Ok(None);
+                }
+            }
+            // Corrupt local entries are fetched again through the full stack.
+            Some(Err(_)) => {}
+        }
+        Ok(self.scmstore_fetch(&[key.clone()]).pop())
+    }
+
+    /// Fetch the given keys through the newstore stack, returning the entries it found. Errors
+    /// are logged and treated as missing keys so that the legacy stores get a chance to serve them.
+    fn scmstore_fetch(&self, keys: &[StoreKey]) -> Vec<Entry> {
+        let scmstore = match self.scmstore.as_ref() {
+            Some(scmstore) => scmstore,
+            None => return vec![],
+        };
+        self.scmstore_fetch_with(scmstore, keys)
+            .into_iter()
+            .filter_map(|res| match res {
+                Ok(entry) => Some(entry),
+                Err(FetchError::NotFound(_)) => None,
+                Err(e) => {
+                    warn!({ error = %e }, "scmstore fetch failed, falling back to ContentStore");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Fetch the given keys from `scmstore`. LFS pointers are reported as not found when they
+    /// are ignored by the `ExtStoredPolicy`.
+    fn scmstore_fetch_with(
+        &self,
+        scmstore: &BoxedReadStore<Key, Entry>,
+        keys: &[StoreKey],
+    ) -> Vec<Result<Entry, FetchError<Key>>> {
+        let scmstore = scmstore.clone();
+        let keys = hgid_keys(keys);
+        if keys.is_empty() {
+            return vec![];
+        }
+
+        // The stream must be fully drained, otherwise values fetched remotely may not have been
+        // written to the shared cache yet.
+        let results = block_on_future(async move {
+            scmstore
+                .fetch_stream(Box::pin(stream::iter(keys)))
+                .await
+                .collect::<Vec<_>>()
+                .await
+        });
+
+        results
+            .into_iter()
+            .map(|res| match res {
+                Ok(entry)
+                    if self.extstored_policy == ExtStoredPolicy::Ignore
+                        && entry.metadata().is_lfs() =>
+                {
+                    Err(FetchError::not_found(entry.key().clone()))
+                }
+                res => res,
+            })
+            .collect()
+    }
+}
+
+fn hgid_keys(keys: &[StoreKey]) -> Vec<Key> {
+    keys.iter()
+        .filter_map(|k| match k {
+            StoreKey::HgId(k) => Some(k.clone()),
+            StoreKey::Content(_, _) => None,
+        })
+        .collect()
 }
 
 // Repack specific methods, not to be used directly but by the repack code.
@@ -158,14 +271,24 @@ impl ContentStore {
 
 impl HgIdDataStore for ContentStore {
     fn get(&self, key: StoreKey) -> Result<StoreResult<Vec<u8>>> {
+        if let Some(mut entry) = self.scmstore_get(&key)? {
+            return Ok(StoreResult::Found(entry.content()?.as_ref().to_vec()));
+        }
         self.datastore.get(key)
     }
 
     fn get_meta(&self, key: StoreKey) -> Result<StoreResult<Metadata>> {
+        if let Some(entry) = self.scmstore_get(&key)? {
+            return Ok(StoreResult::Found(entry.metadata().clone()));
+        }
         self.datastore.get_meta(key)
     }
 
     fn refresh(&self) -> Result<()> {
+        // New data may have been pulled, which the server previously didn't have.
+        if let Some(negative_cache) = self.negative_cache.as_ref() {
+            negative_cache.invalidate()?;
+        }
         self.datastore.refresh()
     }
 }
@@ -173,7 +296,16 @@ impl HgIdDataStore for ContentStore {
 impl RemoteDataStore for ContentStore {
     fn prefetch(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
         if let Some(remote_store) = self.remote_store.as_ref() {
-            let missing = self.get_missing(keys)?;
+            let mut missing = self.get_missing(keys)?;
+            if let Some(scmstore) = self.scmstore_prefetch.as_ref() {
+                if !missing.is_empty() {
+                    // Values fetched by the scmstore are written through to the shared cache, so
+                    // anything it found is no longer missing.
+                    let keys = Box::pin(stream::iter(hgid_keys(&missing)));
+                    block_on_future(prefetch(scmstore.clone(), keys));
+                    missing = self.get_missing(&missing)?;
+                }
+            }
             if missing == vec![] {
                 Ok(vec![])
             } else {
@@ -254,9 +386,16 @@ pub struct ContentStoreBuilder<'a> {
     remotestore: Option<Arc<dyn HgIdRemoteStore>>,
     suffix: Option<PathBuf>,
     memcachestore: Option<Arc<MemcacheStore>>,
+    edenapi: Option<EdenApiStore>,
     correlator: Option<String>,
 }
 
+/// The EdenApi store used as the remote of the scmstore stack.
+enum EdenApiStore {
+    Files(Arc<EdenApiFileStore>),
+    Trees(Arc<EdenApiTreeStore>),
+}
+
 impl<'a> ContentStoreBuilder<'a> {
     pub fn new(config: &'a ConfigSet) -> Self {
         Self {
@@ -265,6 +404,7 @@ impl<'a> ContentStoreBuilder<'a> {
             config,
             remotestore: None,
             memcachestore: None,
+            edenapi: None,
             suffix: None,
             correlator: None,
         }
@@ -295,6 +435,20 @@ impl<'a> ContentStoreBuilder<'a> {
         self
     }
 
+    /// EdenApi file store used as the remote of the scmstore stack when `scmstore.enabled` is set.
+    /// This doesn't replace `remotestore`, which is still used by the legacy stores.
+    pub fn edenapi_files(mut self, edenapi: Arc<EdenApiFileStore>) -> Self {
+        self.edenapi = Some(EdenApiStore::Files(edenapi));
+        self
+    }
+
+    /// EdenApi tree store used as the remote of the scmstore stack when `scmstore.enabled` is set.
+    /// This doesn't replace `remotestore`, which is still used by the legacy stores.
+    pub fn edenapi_trees(mut self, edenapi: Arc<EdenApiTreeStore>) -> Self {
+        self.edenapi = Some(EdenApiStore::Trees(edenapi));
+        self
+    }
+
     pub fn suffix(mut self, suffix: impl AsRef<Path>) -> Self {
         self.suffix = Some(suffix.as_ref().to_path_buf());
         self
@@ -305,7 +459,17 @@ impl<'a> ContentStoreBuilder<'a> {
         self
     }
 
-    pub fn build(self) -> Result<ContentStore> {
+    pub fn build(mut self) -> Result<ContentStore> {
+        // In offline mode, keys missing locally are reported as requiring network access rather
+        // than fetched.
+        let offline = scmstore_offline(self.config)?;
+        if offline {
+            self.memcachestore = None;
+            if self.remotestore.is_some() {
+                self.remotestore = Some(Arc::new(OfflineRemoteStore));
+            }
+        }
+
         let local_path = get_local_path(&self.local_path, &self.suffix)?;
         let cache_path = get_cache_path(self.config, &self.suffix)?;
         check_cache_buster(&self.config, &cache_path);
@@ -369,6 +533,42 @@ impl<'a> ContentStoreBuilder<'a> {
         let shared_lfs_store = Arc::new(LfsStore::shared(&cache_path, self.config)?);
         blob_stores.add(shared_lfs_store.clone());
 
+        let mut scmstore = if scmstore_enabled(self.config)? {
+            Some(
+                ScmStoreBuilder::new(shared_indexedlogdatastore.clone())
+                    .config(self.config)?
+                    .lfs(shared_lfs_store.clone()),
+            )
+        } else {
+            None
+        };
+        let negative_cache = match scmstore {
+            Some(_)
+                if self
+                    .config
+                    .get_or_default::<bool>("scmstore", "negative-cache")? =>
+            {
+                Some(Arc::new(NegativeCache::new(
+                    get_negativecache_path(&cache_path)?,
+                    self.config,
+                )?))
+            }
+            _ => None,
+        };
+        if let Some(negative_cache) = negative_cache.as_ref() {
+            scmstore = scmstore.map(|builder| builder.negative_cache(negative_cache.clone()));
+        }
+        if scmstore.is_some() && self.config.get_or_default::<bool>("scmstore", "verify")? {
+            // Entries of the shared cache are checked against their parents in the shared
+            // history, which is fetched along with them.
+            let history = Arc::new(IndexedLogHgIdHistoryStore::new(
+                get_indexedloghistorystore_path(&cache_path)?,
+                self.config,
+                IndexedLogHistoryStoreType::Shared,
+            )?);
+            scmstore = scmstore.map(|builder| builder.verify(history));
+        }
+
         let primary: Arc<dyn HgIdMutableDeltaStore> =
             if self
                 .config
@@ -413,6 +613,7 @@ impl<'a> ContentStoreBuilder<'a> {
                     self.config,
                     IndexedLogDataStoreType::Local,
                 )?);
+                scmstore = scmstore.map(|builder| builder.local(local_indexedlogdatastore.clone()));
 
                 let primary: Arc<dyn HgIdMutableDeltaStore> =
                     if self
@@ -455,6 +656,45 @@ impl<'a> ContentStoreBuilder<'a> {
                 (None, None)
             };
 
+        let edenapi_remote = self.edenapi.is_some();
+        let scmstore_local = scmstore.as_ref().map(|builder| builder.clone().build());
+        let scmstore = match scmstore {
+            Some(mut builder) => {
+                // Values fetched remotely go through the same mutable store as the legacy path,
+                // so that large files still end up in the LFS store.
+                builder =
+                    builder.write_store(Arc::new(LegacyDatastore(shared_mutabledatastore.clone())));
+                if let Some(memcachestore) = self.memcachestore.as_ref() {
+                    builder = builder.memcache(memcachestore.clone());
+                }
+                let options = EdenApiAdapterOptions::from_config(self.config)?;
+                builder = match self.edenapi {
+                    Some(EdenApiStore::Files(edenapi)) => {
+                        let adapter = Arc::new(EdenApiAdapter {
+                            client: edenapi.client(),
+                            repo: edenapi.repo().to_string(),
+                            options,
+                        });
+                        builder
+                            .progress(edenapi.progress())
+                            .file_remote(adapter.clone())
+                            .aux_remote(adapter)
+                    }
+                    Some(EdenApiStore::Trees(edenapi)) => builder
+                        .progress(edenapi.progress())
+                        .tree_remote(Arc::new(EdenApiAdapter {
+                            client: edenapi.client(),
+                            repo: edenapi.repo().to_string(),
+                            options,
+                        })),
+                    None => builder,
+                };
+                Some(builder)
+            }
+            None => None,
+        };
+
+        let mut getpack = None;
         let remote_store: Option<Arc<ReportingRemoteDataStore>> = if let Some(remotestore) =
             self.remotestore
         {
@@ -495,10 +735,11 @@ impl<'a> ContentStoreBuilder<'a> {
             // at this step and be written to the LFS store.
             let filenode_remotestore = remotestore.datastore(shared_store.clone());
             remotestores.add(filenode_remotestore.clone());
+            getpack = Some(filenode_remotestore.clone());
 
             // Third, the LFS remote store. The previously fetched LFS pointers will be used to
             // fetch the actual blobs in this store.
-            if enable_lfs {
+            if enable_lfs && !offline {
                 let lfs_remote_store = Arc::new(LfsRemote::new(
                     shared_lfs_store,
                     local_lfs_store,
@@ -525,12 +766,37 @@ impl<'a> ContentStoreBuilder<'a> {
             None
         };
 
+        let (scmstore, scmstore_prefetch) = match scmstore {
+            Some(mut builder) => {
+                // Servers which don't expose EdenApi are queried with getpack through the
+                // legacy remote store, which writes the fetched values to the shared cache.
+                if let (false, Some(remote)) = (edenapi_remote, getpack) {
+                    builder = builder.getpack_remote(Arc::new(GetPackStore::new(
+                        remote,
+                        shared_mutabledatastore.clone(),
+                        self.config,
+                    )?));
+                }
+                let prefetch = builder
+                    .clone()
+                    .fetch_options(FallbackFetchOptions::prefetch_from_config(self.config)?)
+                    .build();
+                (Some(builder.build()), Some(prefetch))
+            }
+            None => (None, None),
+        };
+
         Ok(ContentStore {
             datastore,
             local_mutabledatastore,
             shared_mutabledatastore,
             remote_store,
             blob_stores,
+            scmstore,
+            scmstore_local,
+            scmstore_prefetch,
+            negative_cache,
+            extstored_policy,
         })
     }
 }
@@ -586,13 +852,16 @@ mod tests {
     use minibytes::Bytes;
     use tempfile::TempDir;
 
-    use types::testutil::*;
+    use types::{testutil::*, HgId, NodeInfo, Parents};
     use util::path::create_dir;
 
     use crate::{
+        edenapi::{EdenApiRemoteStore, File},
+        error::RequiresNetwork,
+        historystore::HgIdMutableHistoryStore,
         metadatastore::MetadataStore,
         repack::{repack, RepackKind, RepackLocation},
-        testutil::{make_config, make_lfs_config, FakeHgIdRemoteStore},
+        testutil::{make_config, make_lfs_config, FakeEdenApi, FakeHgIdRemoteStore},
         types::ContentHash,
     };
 
@@ -754,6 +1023,208 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_scmstore_remote() -> Result<()> {
+        let cachedir = TempDir::new()?;
+        let localdir = TempDir::new()?;
+        let mut config = make_config(&cachedir);
+        config.set("scmstore", "enabled", Some("true"), &Default::default());
+
+        let k = key("a", "1");
+        let data = Bytes::from(&[1, 2, 3, 4][..]);
+
+        let mut files = HashMap::new();
+        files.insert(k.clone(), data.clone());
+        let client = FakeEdenApi::new().files(files).into_arc();
+        let edenapi = EdenApiRemoteStore::<File>::new("repo", client, None);
+
+        let store = ContentStoreBuilder::new(&config)
+            .local_path(&localdir)
+            .edenapi_files(edenapi.clone())
+            .remotestore(edenapi)
+            .build()?;
+        let data_get = store.get(StoreKey::hgid(k.clone()))?;
+        assert_eq!(data_get, StoreResult::Found(data.as_ref().to_vec()));
+        drop(store);
+
+        // The fetched value was written to the shared cache.
+        let store = ContentStore::new(&localdir, &config)?;
+        let data_get = store.get(StoreKey::hgid(k))?;
+        assert_eq!(data_get, StoreResult::Found(data.as_ref().to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scmstore_local_pack() -> Result<()> {
+        let cachedir = TempDir::new()?;
+        let localdir = TempDir::new()?;
+        let mut config = make_config(&cachedir);
+        config.set("scmstore", "enabled", Some("true"), &Default::default());
+        config.set(
+            "remotefilelog",
+            "write-local-to-indexedlog",
+            Some("false"),
+            &Default::default(),
+        );
+
+        let k = key("a", "1");
+        let data = Bytes::from(&[1, 2, 3, 4][..]);
+
+        let client = FakeEdenApi::new().into_arc();
+        let edenapi = EdenApiRemoteStore::<File>::new("repo", client.clone(), None);
+
+        let store = ContentStoreBuilder::new(&config)
+            .local_path(&localdir)
+            .edenapi_files(edenapi.clone())
+            .remotestore(edenapi)
+            .build()?;
+        let delta = Delta {
+            data: data.clone(),
+            base: None,
+            key: k.clone(),
+        };
+        store.add(&delta, &Default::default())?;
+
+        // The value is only in a local datapack, which the scmstore stack doesn't read from, and
+        // is served from it without going to the network.
+        let k = StoreKey::hgid(k);
+        assert_eq!(store.get_missing(&[k.clone()])?, vec![]);
+        assert_eq!(
+            store.get(k.clone())?,
+            StoreResult::Found(data.as_ref().to_vec())
+        );
+        assert!(matches!(store.get_meta(k)?, StoreResult::Found(_)));
+        assert_eq!(client.requests(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scmstore_verify() -> Result<()> {
+        let cachedir = TempDir::new()?;
+        let localdir = TempDir::new()?;
+        let mut config = make_config(&cachedir);
+        config.set("scmstore", "enabled", Some("true"), &Default::default());
+        config.set("scmstore", "verify", Some("true"), &Default::default());
+
+        let data = Bytes::from(&[1, 2, 3, 4][..]);
+        let null = *HgId::null_id();
+        let k = Key::new(
+            key("a", "1").path,
+            HgId::from_content(&data, Parents::new(null, null)),
+        );
+
+        // The shared cache holds a corrupt entry, whose parents are known.
+        let cache_path = get_cache_path(&config, &None)?;
+        let history = IndexedLogHgIdHistoryStore::new(
+            get_indexedloghistorystore_path(&cache_path)?,
+            &config,
+            IndexedLogHistoryStoreType::Shared,
+        )?;
+        let parent = Key::new(k.path.clone(), null);
+        history.add(
+            &k,
+            &NodeInfo {
+                parents: [parent.clone(), parent],
+                linknode: null,
+            },
+        )?;
+        history.flush()?;
+        let cache = IndexedLogHgIdDataStore::new(
+            get_indexedlogdatastore_path(&cache_path)?,
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?;
+        let corrupt = Delta {
+            data: Bytes::from(&[4, 3, 2, 1][..]),
+            base: None,
+            key: k.clone(),
+        };
+        cache.add(&corrupt, &Default::default())?;
+        cache.flush()?;
+        drop(cache);
+
+        let mut files = HashMap::new();
+        files.insert(k.clone(), data.clone());
+        let client = FakeEdenApi::new().files(files).into_arc();
+        let edenapi = EdenApiRemoteStore::<File>::new("repo", client, None);
+
+        // The corrupt entry is quarantined and fetched again.
+        let store = ContentStoreBuilder::new(&config)
+            .local_path(&localdir)
+            .edenapi_files(edenapi.clone())
+            .remotestore(edenapi)
+            .build()?;
+        let data_get = store.get(StoreKey::hgid(k))?;
+        assert_eq!(data_get, StoreResult::Found(data.as_ref().to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scmstore_prefetch() -> Result<()> {
+        let cachedir = TempDir::new()?;
+        let localdir = TempDir::new()?;
+        let mut config = make_config(&cachedir);
+        config.set("scmstore", "enabled", Some("true"), &Default::default());
+
+        let k = key("a", "1");
+        let data = Bytes::from(&[1, 2, 3, 4][..]);
+
+        let mut files = HashMap::new();
+        files.insert(k.clone(), data.clone());
+        let client = FakeEdenApi::new().files(files).into_arc();
+        let edenapi = EdenApiRemoteStore::<File>::new("repo", client, None);
+
+        let store = ContentStoreBuilder::new(&config)
+            .local_path(&localdir)
+            .edenapi_files(edenapi.clone())
+            .remotestore(edenapi)
+            .build()?;
+        let k = StoreKey::hgid(k);
+        assert_eq!(store.prefetch(&[k.clone()])?, vec![]);
+        assert_eq!(store.get_missing(&[k])?, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scmstore_offline() -> Result<()> {
+        let cachedir = TempDir::new()?;
+        let localdir = TempDir::new()?;
+        let mut config = make_config(&cachedir);
+        config.set("scmstore", "enabled", Some("true"), &Default::default());
+        config.set("scmstore", "offline", Some("true"), &Default::default());
+
+        let k = key("a", "1");
+        let mut files = HashMap::new();
+        files.insert(k.clone(), Bytes::from(&[1, 2, 3, 4][..]));
+        let client = FakeEdenApi::new().files(files).into_arc();
+        let edenapi = EdenApiRemoteStore::<File>::new("repo", client, None);
+
+        let store = ContentStoreBuilder::new(&config)
+            .local_path(&localdir)
+            .edenapi_files(edenapi.clone())
+            .remotestore(edenapi)
+            .build()?;
+        let k = StoreKey::hgid(k);
+        for res in vec![
+            store.get(k.clone()).map(|_| ()),
+            store.prefetch(&[k.clone()]).map(|_| ()),
+        ] {
+            let err = res.expect_err("offline fetch should fail");
+            let err = err
+                .downcast_ref::<RequiresNetwork>()
+                .expect("expected a RequiresNetwork error");
+            assert_eq!(err.keys, vec![k.clone()]);
+        }
+        assert_eq!(store.get_missing(&[k.clone()])?, vec![k]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_not_in_remote_store() -> Result<()> {
         let cachedir = TempDir::new()?;
@@ -80,6 +80,21 @@ impl<T: EdenApiStoreKind> EdenApiRemoteStore<T> {
             _phantom: PhantomData,
         })
     }
+
+    /// The underlying EdenAPI client.
+    pub fn client(&self) -> Arc<dyn EdenApi> {
+        self.client.clone()
+    }
+
+    /// The name of the repo this store fetches data for.
+    pub fn repo(&self) -> &str {
+        &self.repo
+    }
+
+    /// The progress bars of this store's fetches.
+    pub fn progress(&self) -> Arc<dyn ProgressFactory> {
+        self.progress.clone()
+    }
 }
 
 impl HgIdRemoteStore for EdenApiRemoteStore<File> {
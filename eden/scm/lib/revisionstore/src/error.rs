@@ -13,11 +13,53 @@ use url::Url;
 
 use http::status::StatusCode;
 use http_client::{HttpClientError, Method};
+use types::{HgId, Key};
+
+use crate::types::StoreKey;
 
 #[derive(Debug, Error)]
 #[error("Empty Mutable Pack")]
 pub struct EmptyMutablePack;
 
+/// The content of an entry doesn't hash to its key's node.
+#[derive(Debug, Error)]
+#[error("Hash mismatch for {}: content hashes to {}", .key, .computed)]
+pub struct HashMismatch {
+    pub key: Key,
+    pub computed: HgId,
+}
+
+/// The keys aren't available locally, and fetching them was disabled by `--offline` or
+/// `scmstore.offline`.
+#[derive(Debug, Error)]
+#[error(
+    "{} key(s) missing locally, fetching them requires network access (offline mode is on): {}",
+    .keys.len(),
+    format_keys(.keys)
+)]
+pub struct RequiresNetwork {
+    pub keys: Vec<StoreKey>,
+}
+
+/// Maximum number of keys listed in a `RequiresNetwork` error message.
+const MAX_LISTED_KEYS: usize = 10;
+
+fn format_keys(keys: &[StoreKey]) -> String {
+    let mut listed = keys
+        .iter()
+        .take(MAX_LISTED_KEYS)
+        .map(|key| match key {
+            StoreKey::HgId(key) => key.to_string(),
+            StoreKey::Content(hash, _) => format!("{:?}", hash),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    if keys.len() > MAX_LISTED_KEYS {
+        listed.push_str(&format!(", and {} more", keys.len() - MAX_LISTED_KEYS));
+    }
+    listed
+}
+
 #[derive(Error, Debug)]
 #[error("Fetch failed: {} {}", .url, .method)]
 pub struct FetchError {
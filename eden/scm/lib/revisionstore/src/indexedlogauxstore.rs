@@ -0,0 +1,245 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::{
+    io::{Cursor, Read, Write},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use futures::{FutureExt, StreamExt};
+use parking_lot::RwLock;
+use tokio::task::spawn_blocking;
+
+use configparser::{config::ConfigSet, convert::ByteCount};
+use edenapi_types::Blake3;
+use indexedlog::log::IndexOutput;
+use types::{hgid::ReadHgIdExt, HgId, Key, Sha256};
+
+use crate::{
+    indexedlogutil::{Store, StoreOpenOptions},
+    newstore::{
+        auxdata::FileAuxData, metrics::FetchMetrics, FetchError, FetchStream, KeyStream, ReadStore,
+        WriteResults, WriteStore, WriteStream,
+    },
+};
+
+const METRICS: FetchMetrics = FetchMetrics::new("indexedlog.aux");
+
+/// Length of an entry: node, total size, sha256 and blake3.
+const ENTRY_LEN: usize = HgId::len() + 8 + Sha256::len() + 32;
+
+/// Caches the `FileAuxData` of files, so that their size and content hashes can be looked up
+/// without reading (or fetching) their content. Entries are indexed by file node only, since the
+/// aux data of a file doesn't depend on its path, and by content sha256, so that the files with
+/// a given content can be found.
+pub struct IndexedLogAuxStore {
+    log: RwLock<Store>,
+}
+
+impl IndexedLogAuxStore {
+    /// Create or open a shared `IndexedLogAuxStore`.
+    pub fn new(path: impl AsRef<Path>, config: &ConfigSet) -> Result<Self> {
+        let log = IndexedLogAuxStore::open_options(config)?.shared(path)?;
+        Ok(IndexedLogAuxStore {
+            log: RwLock::new(log),
+        })
+    }
+
+    fn open_options(config: &ConfigSet) -> Result<StoreOpenOptions> {
+        // Default configuration: 4 x 100MB, which is about 6 million entries.
+        let mut open_options = StoreOpenOptions::new()
+            .max_log_count(4)
+            .max_bytes_per_log(100 * 1000 * 1000)
+            .auto_sync_threshold(10 * 1024 * 1024)
+            .create(true)
+            .index("node", |_| {
+                vec![IndexOutput::Reference(0..HgId::len() as u64)]
+            })
+            .index("sha256", |_| {
+                let start = HgId::len() as u64 + 8;
+                vec![IndexOutput::Reference(start..start + Sha256::len() as u64)]
+            });
+
+        if let Some(max_bytes_per_log) =
+            config.get_opt::<ByteCount>("indexedlog", "aux.max-bytes-per-log")?
+        {
+            open_options = open_options.max_bytes_per_log(max_bytes_per_log.value());
+        }
+        Ok(open_options)
+    }
+
+    /// Read the aux data of `key` from the log.
+    ///
+    /// The on-disk format of an entry is the following:
+    /// - HgId <20 bytes>
+    /// - Total size: 8 unsigned bytes, big-endian
+    /// - Content sha256 <32 bytes>
+    /// - Content blake3 <32 bytes>
+    ///
+    /// Entries written without a blake3 hash are ignored, so that the aux data is fetched again.
+    fn get(&self, key: &Key) -> Result<Option<FileAuxData>> {
+        let log = self.log.read();
+        for buf in log.lookup(0, key.hgid.as_ref().to_vec())? {
+            let buf = buf?;
+            if buf.len() < ENTRY_LEN {
+                continue;
+            }
+
+            let mut cur = Cursor::new(buf);
+            let _hgid = cur.read_hgid()?;
+            let total_size = cur.read_u64::<BigEndian>()?;
+            let mut sha256 = [0; Sha256::len()];
+            cur.read_exact(&mut sha256)?;
+            let mut blake3 = Blake3::default();
+            cur.read_exact(&mut blake3.0)?;
+            return Ok(Some(FileAuxData {
+                key: key.clone(),
+                total_size,
+                content_sha256: Sha256::from(sha256),
+                content_blake3: blake3,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Find a file node whose content has the given sha256.
+    pub fn get_hgid(&self, sha256: &Sha256) -> Result<Option<HgId>> {
+        let log = self.log.read();
+        let mut entries = log.lookup(1, sha256.as_ref().to_vec())?;
+        match entries.next() {
+            None => Ok(None),
+            Some(buf) => Ok(Some(Cursor::new(buf?).read_hgid()?)),
+        }
+    }
+
+    /// Write the aux data of a file to the log. See [`get`] for the on-disk format.
+    fn put(&self, aux: &FileAuxData) -> Result<()> {
+        let mut buf = Vec::with_capacity(ENTRY_LEN);
+        buf.write_all(aux.key.hgid.as_ref())?;
+        buf.write_u64::<BigEndian>(aux.total_size)?;
+        buf.write_all(aux.content_sha256.as_ref())?;
+        buf.write_all(&aux.content_blake3.0)?;
+        Ok(self.log.write().append(buf)?)
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.log.write().flush()
+    }
+}
+
+#[async_trait]
+impl ReadStore<Key, FileAuxData> for IndexedLogAuxStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, FileAuxData> {
+        Box::pin(keys.then(move |key| {
+            let self_ = self.clone();
+            let key_ = key.clone();
+            spawn_blocking(move || {
+                METRICS.requested(1);
+                let start = Instant::now();
+                let res = match self_.get(&key) {
+                    Ok(None) => {
+                        METRICS.miss(1);
+                        Err(FetchError::not_found(key))
+                    }
+                    Ok(Some(aux)) => {
+                        METRICS.hit(1);
+                        Ok(aux)
+                    }
+                    Err(e) => {
+                        METRICS.error(1);
+                        Err(FetchError::with_key(key, e))
+                    }
+                };
+                METRICS.latency(start.elapsed());
+                res
+            })
+            .map(move |spawn_res| match spawn_res {
+                Ok(res) => res,
+                Err(e) => Err(FetchError::with_key(key_, e)),
+            })
+        }))
+    }
+}
+
+#[async_trait]
+impl WriteStore<Key, FileAuxData> for IndexedLogAuxStore {
+    async fn write_stream(self: Arc<Self>, values: WriteStream<FileAuxData>) -> WriteResults<Key> {
+        Box::pin(values.then(move |value| {
+            let self_ = self.clone();
+            let key = value.key.clone();
+            spawn_blocking(move || match self_.put(&value) {
+                Ok(()) => Ok(value.key),
+                Err(e) => Err((Some(value.key), e)),
+            })
+            .map(move |spawn_res| match spawn_res {
+                Ok(res) => res,
+                Err(e) => Err((Some(key), e.into())),
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::stream;
+    use tempfile::TempDir;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use types::testutil::*;
+
+    #[test]
+    fn test_aux_roundtrip() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let store = Arc::new(IndexedLogAuxStore::new(&tempdir, &ConfigSet::new())?);
+        let aux = FileAuxData {
+            key: key("a", "1"),
+            total_size: 1234,
+            content_sha256: Sha256::from([7; Sha256::len()]),
+            content_blake3: Blake3([9; 32]),
+        };
+
+        let written: Vec<_> = block_on_stream(block_on(
+            store
+                .clone()
+                .write_stream(Box::pin(stream::iter(vec![aux.clone()]))),
+        ))
+        .collect();
+        assert_eq!(written.len(), 1);
+        assert!(written[0].is_ok());
+        store.flush()?;
+
+        // The aux data of a file is shared by every path the file is at.
+        let other = key("b", "1");
+        let reopened = Arc::new(IndexedLogAuxStore::new(&tempdir, &ConfigSet::new())?);
+        let fetched: Vec<_> =
+            block_on_stream(block_on(reopened.clone().fetch_stream(Box::pin(
+                stream::iter(vec![aux.key.clone(), other.clone()]),
+            ))))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            fetched,
+            vec![
+                aux.clone(),
+                FileAuxData {
+                    key: other,
+                    ..aux.clone()
+                }
+            ]
+        );
+
+        assert_eq!(reopened.get_hgid(&aux.content_sha256)?, Some(aux.key.hgid));
+        assert_eq!(reopened.get_hgid(&Sha256::from([8; Sha256::len()]))?, None);
+        Ok(())
+    }
+}
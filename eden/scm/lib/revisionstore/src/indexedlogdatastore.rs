@@ -6,9 +6,14 @@
  */
 
 use std::{
-    io::{Cursor, Write},
+    collections::HashSet,
+    fs,
+    io::{Cursor, ErrorKind, Read, Write},
+    ops::Range,
     path::{Path, PathBuf},
+    str::FromStr,
     sync::Arc,
+    time::Instant,
 };
 
 use anyhow::{bail, ensure, Result};
@@ -18,6 +23,7 @@ use futures::{FutureExt, StreamExt};
 use minibytes::Bytes;
 use parking_lot::RwLock;
 use tokio::task::spawn_blocking;
+use tracing::{debug_span, Span};
 
 use configparser::{config::ConfigSet, convert::ByteCount};
 use edenapi_types::{FileEntry, TreeEntry};
@@ -27,10 +33,11 @@ use types::{hgid::ReadHgIdExt, HgId, Key, RepoPath};
 
 use crate::{
     datastore::{Delta, HgIdDataStore, HgIdMutableDeltaStore, Metadata, StoreResult},
-    indexedlogutil::{Store, StoreOpenOptions},
+    indexedlogutil::{gc_shared, EntryInfo, IndexedLogStats, Store, StoreOpenOptions},
     localstore::{ExtStoredPolicy, LocalStore},
     newstore::{
-        FetchError, FetchStream, KeyStream, ReadStore, WriteResults, WriteStore, WriteStream,
+        metrics::FetchMetrics, FetchError, FetchStream, KeyStream, ReadStore, StoreError, StoreId,
+        WriteResults, WriteStore, WriteStream,
     },
     repack::ToKeys,
     sliceext::SliceExt,
@@ -43,6 +50,21 @@ pub enum IndexedLogDataStoreType {
     Shared,
 }
 
+/// Name of the file, in the directory of the store, listing the nodes of quarantined entries.
+const QUARANTINE_FILE: &str = "quarantine";
+
+fn read_quarantine(path: &Path) -> Result<HashSet<HgId>> {
+    match fs::read_to_string(path) {
+        // Lines which don't parse are left over from an interrupted write.
+        Ok(content) => Ok(content
+            .lines()
+            .filter_map(|line| HgId::from_str(line.trim()).ok())
+            .collect()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
 struct IndexedLogHgIdDataStoreInner {
     log: Store,
 }
@@ -50,6 +72,45 @@ struct IndexedLogHgIdDataStoreInner {
 pub struct IndexedLogHgIdDataStore {
     inner: RwLock<IndexedLogHgIdDataStoreInner>,
     extstored_policy: ExtStoredPolicy,
+    metrics: FetchMetrics,
+    quarantined: RwLock<HashSet<HgId>>,
+    quarantine_path: PathBuf,
+    zstd: Option<ZstdOptions>,
+    store_id: StoreId,
+}
+
+/// How the content of an entry is compressed on disk.
+///
+/// Entries compressed with lz4 are written in the original format, which every version reads.
+/// Entries in other formats are wrapped in an envelope, which versions only knowing lz4 read as
+/// an entry for the working directory id with an empty path, and never look up. The envelope
+/// holds the format followed by the entry itself, and is indexed by the id of that entry in a
+/// separate index, which those versions don't have. They fetch these entries again instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ContentFormat {
+    Lz4 = 0,
+    Zstd = 1,
+}
+
+/// Length of the header of an envelope: the working directory id, an empty path, empty
+/// metadata and the length of the rest of the envelope.
+const ENVELOPE_HEADER_LEN: usize = HgId::len() + 2 + 4 + 8;
+
+impl ContentFormat {
+    fn from_version(version: u8) -> Result<Self> {
+        match version {
+            0 => Ok(ContentFormat::Lz4),
+            1 => Ok(ContentFormat::Zstd),
+            _ => bail!("unknown content format {}", version),
+        }
+    }
+}
+
+/// Content at least `threshold` bytes long is compressed with zstd rather than lz4.
+#[derive(Clone, Copy, Debug)]
+struct ZstdOptions {
+    threshold: usize,
+    level: i32,
 }
 
 #[derive(Clone, Debug)]
@@ -59,6 +120,7 @@ pub struct Entry {
 
     content: Option<Bytes>,
     compressed_content: Option<Bytes>,
+    format: ContentFormat,
 }
 
 impl std::cmp::PartialEq for Entry {
@@ -79,6 +141,7 @@ impl Entry {
             content: Some(content),
             metadata,
             compressed_content: None,
+            format: ContentFormat::Lz4,
         }
     }
 
@@ -92,13 +155,43 @@ impl Entry {
     /// - Content len: 8 unsigned bytes, big-endian
     /// - Content: <Content len> bytes, lz4 compressed
     ///
+    /// Entries whose content is compressed otherwise are in an envelope, see `ContentFormat`:
+    /// - Header: the working directory id, 0 as path len, 0 as metadata len, and the length of
+    ///   the rest of the envelope as content len
+    /// - Content format: 1 unsigned byte (1: zstd)
+    /// - The entry, in the format above, with its content compressed according to the format
+    ///
     /// The metadata-list is a list of Metadata, encode with:
     /// - Flag: 1 byte,
     /// - Len: 2 unsigned bytes, big-endian
     /// - Value: <Len> bytes, big-endian
+    ///
+    /// The compressed content is copied out of `data`.
     fn from_slice(data: &[u8]) -> Result<Self> {
+        let (mut entry, range) = Entry::parse(data)?;
+        entry.compressed_content = Some(Bytes::copy_from_slice(&data[range]));
+        Ok(entry)
+    }
+
+    /// Read an entry from `data` and deserialize it, like `from_slice`. The compressed content
+    /// is a slice of `data`, which is not copied.
+    fn from_bytes(data: Bytes) -> Result<Self> {
+        let (mut entry, range) = Entry::parse(&data)?;
+        entry.compressed_content = Some(data.slice(range));
+        Ok(entry)
+    }
+
+    /// Deserialize an entry without its content, and return where its compressed content is in
+    /// `data`.
+    fn parse(data: &[u8]) -> Result<(Self, Range<usize>)> {
         let mut cur = Cursor::new(data);
-        let hgid = cur.read_hgid()?;
+        let mut hgid = cur.read_hgid()?;
+        let mut format = ContentFormat::Lz4;
+        if hgid.is_wdir() {
+            cur.set_position(ENVELOPE_HEADER_LEN as u64);
+            format = ContentFormat::from_version(cur.read_u8()?)?;
+            hgid = cur.read_hgid()?;
+        }
 
         let name_len = cur.read_u16::<BigEndian>()? as u64;
         let name_slice =
@@ -111,49 +204,73 @@ impl Entry {
         let metadata = Metadata::read(&mut cur)?;
 
         let compressed_len = cur.read_u64::<BigEndian>()?;
-        let compressed =
-            data.get_err(cur.position() as usize..(cur.position() + compressed_len) as usize)?;
+        let range = cur.position() as usize..(cur.position() + compressed_len) as usize;
+        data.get_err(range.clone())?;
 
-        Ok(Entry {
+        let entry = Entry {
             key,
             content: None,
-            compressed_content: Some(Bytes::copy_from_slice(compressed)),
+            compressed_content: None,
             metadata,
-        })
+            format,
+        };
+        Ok((entry, range))
     }
 
-    /// Read an entry from the IndexedLog and deserialize it.
+    /// Read an entry from the IndexedLog and deserialize it. The compressed content of entries
+    /// already on disk points into the memory-mapped log rather than being copied.
     pub fn from_log(key: &Key, log: &Store) -> Result<Option<Self>> {
-        let mut log_entry = log.lookup(0, key.hgid.as_ref().to_vec())?;
-        let buf = match log_entry.next() {
-            None => return Ok(None),
-            Some(buf) => buf?,
-        };
-
-        Entry::from_slice(buf).map(Some)
+        // Entries in the original format, then entries in an envelope.
+        for index in 0..2 {
+            let mut log_entry = log.lookup(index, key.hgid.as_ref())?;
+            if let Some(buf) = log_entry.next() {
+                return Entry::from_bytes(log.slice_to_bytes(buf?)).map(Some);
+            }
+        }
+        Ok(None)
     }
 
     /// Write an entry to the IndexedLog. See [`from_log`] for the detail about the on-disk format.
     pub fn write_to_log(self, log: &mut Store) -> Result<()> {
+        self.write_to_log_with(log, None)
+    }
+
+    /// Write an entry to the IndexedLog, compressing its content with zstd if `zstd` is set and
+    /// the content is large enough. Already compressed content is written as is.
+    fn write_to_log_with(self, log: &mut Store, zstd: Option<ZstdOptions>) -> Result<()> {
+        let (format, compressed) = if let Some(compressed) = self.compressed_content {
+            (self.format, compressed)
+        } else {
+            match (self.content, zstd) {
+                (Some(raw), Some(zstd)) if raw.len() >= zstd.threshold => (
+                    ContentFormat::Zstd,
+                    zstd::stream::encode_all(raw.as_ref(), zstd.level)?.into(),
+                ),
+                (Some(raw), _) => (ContentFormat::Lz4, compress(&raw)?.into()),
+                (None, _) => bail!("No content"),
+            }
+        };
+
         let mut buf = Vec::new();
+        if format != ContentFormat::Lz4 {
+            buf.write_all(HgId::wdir_id().as_ref())?;
+            buf.write_u16::<BigEndian>(0)?;
+            Metadata::default().write(&mut buf)?;
+            // The length of the envelope, filled in once the entry is written.
+            buf.write_u64::<BigEndian>(0)?;
+            buf.write_u8(format as u8)?;
+        }
         buf.write_all(self.key.hgid.as_ref())?;
         let path_slice = self.key.path.as_byte_slice();
         buf.write_u16::<BigEndian>(path_slice.len() as u16)?;
         buf.write_all(path_slice)?;
         self.metadata.write(&mut buf)?;
-
-        let compressed = if let Some(compressed) = self.compressed_content {
-            compressed
-        } else {
-            if let Some(raw) = self.content {
-                compress(&raw)?.into()
-            } else {
-                bail!("No content");
-            }
-        };
-
         buf.write_u64::<BigEndian>(compressed.len() as u64)?;
         buf.write_all(&compressed)?;
+        if format != ContentFormat::Lz4 {
+            let len = (buf.len() - ENVELOPE_HEADER_LEN) as u64;
+            buf[ENVELOPE_HEADER_LEN - 8..ENVELOPE_HEADER_LEN].copy_from_slice(&len.to_be_bytes());
+        }
 
         Ok(log.append(buf)?)
     }
@@ -164,7 +281,10 @@ impl Entry {
         }
 
         if let Some(compressed) = self.compressed_content.as_ref() {
-            let raw = Bytes::from(decompress(&compressed)?);
+            let raw = match self.format {
+                ContentFormat::Lz4 => Bytes::from(decompress(&compressed)?),
+                ContentFormat::Zstd => Bytes::from(zstd::stream::decode_all(compressed.as_ref())?),
+            };
             Ok(raw)
         } else {
             bail!("No content");
@@ -177,10 +297,30 @@ impl Entry {
         Ok(self.content.as_ref().unwrap().clone())
     }
 
+    /// The content of an entry compressed with zstd, decompressed as it is read rather than in a
+    /// single buffer of the size of the content. `None` for other entries, whose content can only
+    /// be read whole with `content`.
+    pub(crate) fn content_reader(&self) -> Result<Option<impl Read + Send + 'static>> {
+        match (&self.content, &self.compressed_content, self.format) {
+            (None, Some(compressed), ContentFormat::Zstd) => Ok(Some(
+                zstd::stream::read::Decoder::new(Cursor::new(compressed.clone()))?,
+            )),
+            _ => Ok(None),
+        }
+    }
+
     pub fn metadata(&self) -> &Metadata {
         &self.metadata
     }
 
+    /// Size of the content held by this entry, compressed if it hasn't been read yet.
+    pub(crate) fn content_len(&self) -> usize {
+        self.content
+            .as_ref()
+            .or(self.compressed_content.as_ref())
+            .map_or(0, |content| content.len())
+    }
+
     pub fn key(&self) -> &Key {
         &self.key
     }
@@ -196,14 +336,33 @@ impl IndexedLogHgIdDataStore {
     ) -> Result<Self> {
         let open_options = IndexedLogHgIdDataStore::open_options(config)?;
 
-        let log = match store_type {
-            IndexedLogDataStoreType::Local => open_options.local(&path),
-            IndexedLogDataStoreType::Shared => open_options.shared(&path),
-        }?;
+        let (log, metrics) = match store_type {
+            IndexedLogDataStoreType::Local => (
+                open_options.local(&path)?,
+                FetchMetrics::new("indexedlog.local"),
+            ),
+            IndexedLogDataStoreType::Shared => {
+                // Collect the oldest logs before opening the store so that this process doesn't
+                // hold them mapped. Failing to do so isn't fatal, the cache will simply be larger.
+                if let Some(max_cache_size) = IndexedLogHgIdDataStore::max_cache_size(config)? {
+                    let _ = gc_shared(&path, max_cache_size);
+                }
+                (
+                    open_options.shared(&path)?,
+                    FetchMetrics::new("indexedlog.shared"),
+                )
+            }
+        };
 
+        let quarantine_path = path.as_ref().join(QUARANTINE_FILE);
         Ok(IndexedLogHgIdDataStore {
             inner: RwLock::new(IndexedLogHgIdDataStoreInner { log }),
             extstored_policy,
+            metrics,
+            quarantined: RwLock::new(read_quarantine(&quarantine_path)?),
+            quarantine_path,
+            zstd: IndexedLogHgIdDataStore::zstd_options(config)?,
+            store_id: StoreId::IndexedLog(path.as_ref().to_path_buf()),
         })
     }
 
@@ -216,6 +375,14 @@ impl IndexedLogHgIdDataStore {
             .create(true)
             .index("node", |_| {
                 vec![IndexOutput::Reference(0..HgId::len() as u64)]
+            })
+            .index("envelope_node", |data| {
+                if data.starts_with(HgId::wdir_id().as_ref()) {
+                    let start = ENVELOPE_HEADER_LEN as u64 + 1;
+                    vec![IndexOutput::Reference(start..start + HgId::len() as u64)]
+                } else {
+                    vec![]
+                }
             });
 
         if let Some(max_log_count) = config.get_opt::<u8>("indexedlog", "data.max-log-count")? {
@@ -235,6 +402,100 @@ impl IndexedLogHgIdDataStore {
         Ok(open_options)
     }
 
+    /// Entries whose content is at least `indexedlog.data.zstd-threshold` long are compressed
+    /// with zstd, at level `indexedlog.data.zstd-level`. Other entries are compressed with lz4.
+    fn zstd_options(config: &ConfigSet) -> Result<Option<ZstdOptions>> {
+        let threshold = match config.get_opt::<ByteCount>("indexedlog", "data.zstd-threshold")? {
+            Some(threshold) => threshold.value() as usize,
+            None => return Ok(None),
+        };
+        let level = config.get_or("indexedlog", "data.zstd-level", || 3)?;
+        Ok(Some(ZstdOptions { threshold, level }))
+    }
+
+    /// Maximum size of a shared store on disk, set via `indexedlog.data.max-cache-size`.
+    fn max_cache_size(config: &ConfigSet) -> Result<Option<u64>> {
+        Ok(config
+            .get_opt::<ByteCount>("indexedlog", "data.max-cache-size")?
+            .map(|size| size.value()))
+    }
+
+    /// Remove the oldest logs of the shared store at `path` until it fits in
+    /// `indexedlog.data.max-cache-size`. Returns the number of bytes freed, or `None` when no
+    /// maximum size is configured.
+    pub fn gc(path: impl AsRef<Path>, config: &ConfigSet) -> Result<Option<u64>> {
+        match IndexedLogHgIdDataStore::max_cache_size(config)? {
+            Some(max_cache_size) => Ok(Some(gc_shared(path, max_cache_size)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Hide the entry for `key` until a new entry is written for it, so that a corrupt entry is
+    /// fetched again. The new entry then shadows the corrupt one on disk.
+    ///
+    /// The quarantine is recorded next to the log, so that other processes opening the store
+    /// later don't read the corrupt entry either. The entry is hidden from this store even if
+    /// recording it fails.
+    pub fn quarantine(&self, key: Key) -> Result<()> {
+        if !self.quarantined.write().insert(key.hgid) {
+            return Ok(());
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.quarantine_path)?;
+        writeln!(file, "{}", key.hgid.to_hex())?;
+        Ok(())
+    }
+
+    /// Lift the quarantine of `hgid`, whose entry was just written again.
+    fn release(&self, hgid: &HgId) {
+        let mut quarantined = self.quarantined.write();
+        if !quarantined.remove(hgid) {
+            return;
+        }
+        let mut content = String::new();
+        for hgid in quarantined.iter() {
+            content.push_str(&hgid.to_hex());
+            content.push('\n');
+        }
+        // Failing to rewrite the file only hides the new entry from processes which open the
+        // store later, which then fetch it again.
+        let _ = fs::write(&self.quarantine_path, content);
+    }
+
+    fn lookup(&self, key: &Key, log: &Store) -> Result<Option<Entry>> {
+        if self.quarantined.read().contains(&key.hgid) {
+            return Ok(None);
+        }
+        Entry::from_log(key, log)
+    }
+
+    /// Read the entry for `key`, unless it is quarantined.
+    pub(crate) fn entry(&self, key: &Key) -> Result<Option<Entry>> {
+        self.lookup(key, &self.inner.read().log)
+    }
+
+    /// Scan the store at `path`, decompressing every entry, and report its `IndexedLogStats`.
+    pub fn stats(
+        path: impl AsRef<Path>,
+        config: &ConfigSet,
+        store_type: IndexedLogDataStoreType,
+    ) -> Result<IndexedLogStats> {
+        let open_options = IndexedLogHgIdDataStore::open_options(config)?.create(false);
+        let log = match store_type {
+            IndexedLogDataStoreType::Local => open_options.local(&path)?,
+            IndexedLogDataStoreType::Shared => open_options.shared(&path)?,
+        };
+        log.stats(&path, |buf| {
+            let mut entry = Entry::from_slice(buf)?;
+            Ok(EntryInfo {
+                index_key: entry.key.hgid.as_ref().to_vec(),
+                content_len: entry.content()?.len() as u64,
+            })
+        })
+    }
+
     pub fn repair(
         path: PathBuf,
         config: &ConfigSet,
@@ -277,13 +538,39 @@ impl ReadStore<Key, Entry> for IndexedLogHgIdDataStore {
         Box::pin(keys.then(move |key| {
             let self_ = self.clone();
             let key_ = key.clone();
+            let parent = Span::current();
             spawn_blocking(move || {
+                let metrics = &self_.metrics;
+                let span = debug_span!(
+                    parent: &parent,
+                    "IndexedLogHgIdDataStore::fetch",
+                    store = metrics.store(),
+                    key = %key,
+                    found = &false
+                );
+                let _guard = span.enter();
+                metrics.requested(1);
+                let start = Instant::now();
                 let inner = self_.inner.read();
-                match Entry::from_log(&key, &inner.log) {
-                    Ok(None) => Err(FetchError::not_found(key.clone())),
-                    Ok(Some(entry)) => Ok(entry),
-                    Err(e) => Err(FetchError::with_key(key.clone(), e)),
-                }
+                let res = match self_.lookup(&key, &inner.log) {
+                    Ok(None) => {
+                        metrics.miss(1);
+                        Err(FetchError::not_found(key.clone()))
+                    }
+                    Ok(Some(entry)) => {
+                        span.record("found", &true);
+                        metrics.hit(1);
+                        metrics.bytes(entry.compressed_content.as_ref().map_or(0, |c| c.len()));
+                        Ok(entry)
+                    }
+                    Err(e) => {
+                        metrics.error(1);
+                        let e = StoreError::new(self_.store_id.clone(), e);
+                        Err(FetchError::with_key(key.clone(), e))
+                    }
+                };
+                metrics.latency(start.elapsed());
+                res
             })
             .map(move |spawn_res| {
                 match spawn_res {
@@ -305,8 +592,11 @@ impl WriteStore<Key, Entry> for IndexedLogHgIdDataStore {
             spawn_blocking(move || {
                 let mut inner = self_.inner.write();
                 let key = value.key.clone();
-                match value.write_to_log(&mut inner.log) {
-                    Ok(()) => Ok(key),
+                match value.write_to_log_with(&mut inner.log, self_.zstd) {
+                    Ok(()) => {
+                        self_.release(&key.hgid);
+                        Ok(key)
+                    }
                     Err(e) => Err((Some(key), e)),
                 }
             })
@@ -327,7 +617,9 @@ impl HgIdMutableDeltaStore for IndexedLogHgIdDataStore {
 
         let entry = Entry::new(delta.key.clone(), delta.data.clone(), metadata.clone());
         let mut inner = self.inner.write();
-        entry.write_to_log(&mut inner.log)
+        entry.write_to_log_with(&mut inner.log, self.zstd)?;
+        self.release(&delta.key.hgid);
+        Ok(())
     }
 
     fn flush(&self) -> Result<Option<Vec<PathBuf>>> {
@@ -342,7 +634,7 @@ impl LocalStore for IndexedLogHgIdDataStore {
         Ok(keys
             .iter()
             .filter(|k| match k {
-                StoreKey::HgId(k) => match Entry::from_log(k, &inner.log) {
+                StoreKey::HgId(k) => match self.lookup(k, &inner.log) {
                     Ok(None) | Err(_) => true,
                     Ok(Some(_)) => false,
                 },
@@ -361,7 +653,7 @@ impl HgIdDataStore for IndexedLogHgIdDataStore {
         };
 
         let inner = self.inner.read();
-        let mut entry = match Entry::from_log(&key, &inner.log)? {
+        let mut entry = match self.lookup(&key, &inner.log)? {
             None => return Ok(StoreResult::NotFound(StoreKey::HgId(key))),
             Some(entry) => entry,
         };
@@ -381,7 +673,7 @@ impl HgIdDataStore for IndexedLogHgIdDataStore {
         };
 
         let inner = self.inner.read();
-        let entry = match Entry::from_log(&key, &inner.log)? {
+        let entry = match self.lookup(&key, &inner.log)? {
             None => return Ok(StoreResult::NotFound(StoreKey::HgId(key))),
             Some(entry) => entry,
         };
@@ -417,6 +709,7 @@ mod tests {
 
     use std::fs::remove_file;
 
+    use anyhow::anyhow;
     use futures::stream;
     use minibytes::Bytes;
     use tempfile::TempDir;
@@ -424,7 +717,19 @@ mod tests {
     use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
     use types::testutil::*;
 
-    use crate::newstore::fallback::FallbackStore;
+    use crate::newstore::fallback::{
+        FallbackFetchOptions, FallbackStore, WriteErrorPolicy, WritePolicy,
+    };
+
+    /// A `WriteStore` which fails every write.
+    struct FailingWriteStore;
+
+    #[async_trait]
+    impl WriteStore<Key, Entry> for FailingWriteStore {
+        async fn write_stream(self: Arc<Self>, values: WriteStream<Entry>) -> WriteResults<Key> {
+            Box::pin(values.map(|value| Err((Some(value.key().clone()), anyhow!("write failed")))))
+        }
+    }
 
     #[test]
     fn test_empty() {
@@ -493,6 +798,68 @@ mod tests {
         assert_eq!(StoreResult::Found(delta.data.as_ref().to_vec()), read_data);
     }
 
+    #[test]
+    fn test_entry_not_copied() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let log = IndexedLogHgIdDataStore::new(
+            &tempdir,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?;
+
+        let delta = Delta {
+            data: Bytes::from(&[1, 2, 3, 4][..]),
+            base: None,
+            key: key("a", "1"),
+        };
+        log.add(&delta, &Default::default())?;
+        log.flush()?;
+
+        // Both reads point into the memory-mapped log.
+        let first = log.entry(&delta.key)?.unwrap();
+        let second = log.entry(&delta.key)?.unwrap();
+        let first = first.compressed_content.unwrap();
+        let second = second.compressed_content.unwrap();
+        assert_eq!(first.as_ptr(), second.as_ptr());
+        Ok(())
+    }
+
+    #[test]
+    fn test_quarantine_persisted() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let open = || {
+            IndexedLogHgIdDataStore::new(
+                &tempdir,
+                ExtStoredPolicy::Use,
+                &ConfigSet::new(),
+                IndexedLogDataStoreType::Shared,
+            )
+        };
+
+        let delta = Delta {
+            data: Bytes::from(&[1, 2, 3, 4][..]),
+            base: None,
+            key: key("a", "1"),
+        };
+        let log = open()?;
+        log.add(&delta, &Default::default())?;
+        log.flush()?;
+        log.quarantine(delta.key.clone())?;
+        assert!(log.entry(&delta.key)?.is_none());
+        drop(log);
+
+        // The quarantine outlives the store, until the entry is written again.
+        let log = open()?;
+        assert!(log.entry(&delta.key)?.is_none());
+        log.add(&delta, &Default::default())?;
+        log.flush()?;
+        drop(log);
+        let log = open()?;
+        assert!(log.entry(&delta.key)?.is_some());
+        Ok(())
+    }
+
     #[test]
     fn test_lookup_failure() {
         let tempdir = TempDir::new().unwrap();
@@ -666,6 +1033,168 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_zstd() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let mut config = ConfigSet::new();
+        config.set(
+            "indexedlog",
+            "data.zstd-threshold",
+            Some("100"),
+            &Default::default(),
+        );
+
+        let log = IndexedLogHgIdDataStore::new(
+            &tempdir,
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?;
+        let small = Delta {
+            data: Bytes::from(&[1, 2, 3, 4][..]),
+            base: None,
+            key: key("a", "1"),
+        };
+        let large = Delta {
+            data: Bytes::from(vec![5; 1000]),
+            base: None,
+            key: key("a", "2"),
+        };
+        log.add(&small, &Default::default())?;
+        log.add(&large, &Default::default())?;
+        log.flush()?;
+
+        let inner = log.inner.read();
+        let format = |k| -> Result<ContentFormat> {
+            Ok(Entry::from_log(k, &inner.log)?
+                .expect("entry not found")
+                .format)
+        };
+        assert_eq!(format(&small.key)?, ContentFormat::Lz4);
+        assert_eq!(format(&large.key)?, ContentFormat::Zstd);
+
+        // Versions which only know lz4 look entries up in the first index, where the zstd entry
+        // is only found under the working directory id.
+        let found = |hgid: &HgId| -> Result<bool> {
+            Ok(inner.log.lookup(0, hgid.as_ref())?.next().is_some())
+        };
+        assert!(!found(&large.key.hgid)?);
+        assert!(found(HgId::wdir_id())?);
+        drop(inner);
+        drop(log);
+
+        // Both formats can be read regardless of the configuration.
+        let log = IndexedLogHgIdDataStore::new(
+            &tempdir,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?;
+        for delta in &[small, large] {
+            assert_eq!(
+                log.get(StoreKey::hgid(delta.key.clone()))?,
+                StoreResult::Found(delta.data.as_ref().to_vec())
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let log = IndexedLogHgIdDataStore::new(
+            &tempdir,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?;
+        for (i, len) in [10, 1000].iter().enumerate() {
+            let delta = Delta {
+                data: Bytes::from(vec![i as u8; *len]),
+                base: None,
+                key: key("a", &format!("{}", i + 1)),
+            };
+            log.add(&delta, &Default::default())?;
+        }
+        log.flush()?;
+
+        let stats = IndexedLogHgIdDataStore::stats(
+            &tempdir,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?;
+        assert_eq!(stats.logs, 1);
+        assert!(stats.last_rotation.is_some());
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.content_bytes, 1010);
+        // The large entry compresses well.
+        assert!(stats.stored_bytes < stats.content_bytes);
+        assert_eq!(stats.corrupt_entries, 0);
+        assert_eq!(stats.unindexed_entries, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_max_cache_size() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let mut config = ConfigSet::new();
+        config.set(
+            "indexedlog",
+            "data.max-log-count",
+            Some("10"),
+            &Default::default(),
+        );
+        config.set(
+            "indexedlog",
+            "data.max-bytes-per-log",
+            Some("10"),
+            &Default::default(),
+        );
+
+        let log = IndexedLogHgIdDataStore::new(
+            &tempdir,
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?;
+        let keys = vec![key("a", "1"), key("a", "2"), key("a", "3")];
+        for k in &keys {
+            let delta = Delta {
+                data: Bytes::from(&[1, 2, 3, 4][..]),
+                base: None,
+                key: k.clone(),
+            };
+            log.add(&delta, &Default::default())?;
+            log.flush()?;
+        }
+        drop(log);
+
+        // No limit is configured, nothing is collected.
+        assert_eq!(IndexedLogHgIdDataStore::gc(&tempdir, &config)?, None);
+
+        // Opening the store collects everything but the latest log.
+        config.set(
+            "indexedlog",
+            "data.max-cache-size",
+            Some("1"),
+            &Default::default(),
+        );
+        let log = IndexedLogHgIdDataStore::new(
+            &tempdir,
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?;
+        for k in &keys {
+            let k = StoreKey::hgid(k.clone());
+            assert_eq!(log.get_missing(&[k.clone()])?, vec![k]);
+        }
+        drop(log);
+
+        assert_eq!(IndexedLogHgIdDataStore::gc(&tempdir, &config)?, Some(0));
+        Ok(())
+    }
+
     #[test]
     fn test_newstore_read() {
         let tempdir = TempDir::new().unwrap();
@@ -751,7 +1280,9 @@ mod tests {
             preferred: log1.clone(),
             fallback: log2,
             write_store: log1,
-            write: false,
+            write_policy: WritePolicy::NoWrite,
+            write_error_policy: WriteErrorPolicy::Log,
+            fetch_options: FallbackFetchOptions::default(),
         });
 
         let mut fetched: Vec<_> = block_on_stream(block_on(
@@ -782,6 +1313,174 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_newstore_fallback_ordered_batches() {
+        let tempdir1 = TempDir::new().unwrap();
+        let tempdir2 = TempDir::new().unwrap();
+        let log1 = IndexedLogHgIdDataStore::new(
+            &tempdir1,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )
+        .unwrap();
+        let log2 = IndexedLogHgIdDataStore::new(
+            &tempdir2,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )
+        .unwrap();
+
+        let keys: Vec<_> = (1..=5).map(|i| key("a", &i.to_string())).collect();
+        let metadata = Default::default();
+        for (i, k) in keys.iter().enumerate() {
+            let delta = Delta {
+                data: Bytes::from(vec![i as u8]),
+                base: None,
+                key: k.clone(),
+            };
+            log2.add(&delta, &metadata).unwrap();
+        }
+        log2.flush().unwrap();
+
+        let log1 = Arc::new(log1);
+        let log2 = Arc::new(log2);
+
+        let fallback = Arc::new(FallbackStore {
+            preferred: log1.clone(),
+            fallback: log2,
+            write_store: log1,
+            write_policy: WritePolicy::NoWrite,
+            write_error_policy: WriteErrorPolicy::Log,
+            fetch_options: FallbackFetchOptions {
+                batch_size: 2,
+                concurrency: 3,
+                ordered_batches: true,
+                ..Default::default()
+            },
+        });
+
+        let fetched: Vec<_> = block_on_stream(block_on(
+            fallback.fetch_stream(Box::pin(stream::iter(keys.clone()))),
+        ))
+        .map(|r| {
+            r.expect("failed to fetch from fallback store")
+                .key()
+                .clone()
+        })
+        .collect();
+
+        assert_eq!(fetched, keys);
+    }
+
+    #[test]
+    fn test_newstore_fallback_write_through() -> Result<()> {
+        let tempdir1 = TempDir::new()?;
+        let tempdir2 = TempDir::new()?;
+        let log1 = Arc::new(IndexedLogHgIdDataStore::new(
+            &tempdir1,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?);
+        let log2 = IndexedLogHgIdDataStore::new(
+            &tempdir2,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?;
+
+        let k = key("a", "1");
+        let delta = Delta {
+            data: Bytes::from(&[1, 2, 3, 4][..]),
+            base: None,
+            key: k.clone(),
+        };
+        log2.add(&delta, &Default::default())?;
+        log2.flush()?;
+
+        let fallback = Arc::new(FallbackStore {
+            preferred: log1.clone(),
+            fallback: Arc::new(log2),
+            write_store: log1.clone(),
+            write_policy: WritePolicy::WriteThrough,
+            write_error_policy: WriteErrorPolicy::Fail,
+            fetch_options: FallbackFetchOptions::default(),
+        });
+
+        let fetched: Vec<_> = block_on_stream(block_on(
+            fallback.fetch_stream(Box::pin(stream::iter(vec![k.clone()]))),
+        ))
+        .collect();
+        assert_eq!(fetched.len(), 1);
+        assert!(fetched[0].is_ok());
+
+        // The value was written to the preferred store before being returned.
+        let k = StoreKey::hgid(k);
+        assert_eq!(
+            log1.get(k)?,
+            StoreResult::Found(delta.data.as_ref().to_vec())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_newstore_fallback_write_error() -> Result<()> {
+        let tempdir1 = TempDir::new()?;
+        let tempdir2 = TempDir::new()?;
+        let log1 = IndexedLogHgIdDataStore::new(
+            &tempdir1,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?;
+        let log2 = IndexedLogHgIdDataStore::new(
+            &tempdir2,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?;
+
+        let k = key("a", "1");
+        let delta = Delta {
+            data: Bytes::from(&[1, 2, 3, 4][..]),
+            base: None,
+            key: k.clone(),
+        };
+        log2.add(&delta, &Default::default())?;
+        log2.flush()?;
+        let log1 = Arc::new(log1);
+        let log2 = Arc::new(log2);
+
+        let fetch = |write_error_policy| -> Vec<Result<Entry, FetchError<Key>>> {
+            let fallback = Arc::new(FallbackStore {
+                preferred: log1.clone(),
+                fallback: log2.clone(),
+                write_store: Arc::new(FailingWriteStore),
+                write_policy: WritePolicy::WriteThrough,
+                write_error_policy,
+                fetch_options: FallbackFetchOptions::default(),
+            });
+            block_on_stream(block_on(
+                fallback.fetch_stream(Box::pin(stream::iter(vec![k.clone()]))),
+            ))
+            .collect()
+        };
+
+        let fetched = fetch(WriteErrorPolicy::Log);
+        assert_eq!(fetched.len(), 1);
+        assert!(fetched[0].is_ok());
+
+        let fetched = fetch(WriteErrorPolicy::Fail);
+        assert_eq!(fetched.len(), 1);
+        match &fetched[0] {
+            Err(FetchError::KeyedError(key, _)) => assert_eq!(key, &k),
+            _ => panic!("expected a write error"),
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_newstore_write_read() {
         let tempdir = TempDir::new().unwrap();
@@ -24,7 +24,7 @@ use types::{
 
 use crate::{
     historystore::{HgIdHistoryStore, HgIdMutableHistoryStore},
-    indexedlogutil::{Store, StoreOpenOptions},
+    indexedlogutil::{EntryInfo, IndexedLogStats, Store, StoreOpenOptions},
     localstore::LocalStore,
     repack::ToKeys,
     sliceext::SliceExt,
@@ -227,6 +227,26 @@ impl IndexedLogHgIdHistoryStore {
         Ok(open_options)
     }
 
+    /// Scan the store at `path` and report its `IndexedLogStats`.
+    pub fn stats(
+        path: impl AsRef<Path>,
+        config: &ConfigSet,
+        store_type: IndexedLogHistoryStoreType,
+    ) -> Result<IndexedLogStats> {
+        let open_options = IndexedLogHgIdHistoryStore::open_options(config)?.create(false);
+        let log = match store_type {
+            IndexedLogHistoryStoreType::Local => open_options.local(&path)?,
+            IndexedLogHistoryStoreType::Shared => open_options.shared(&path)?,
+        };
+        log.stats(&path, |buf| {
+            let entry = Entry::from_slice(buf)?;
+            Ok(EntryInfo {
+                index_key: Entry::key_to_index_key(&entry.key),
+                content_len: buf.len() as u64,
+            })
+        })
+    }
+
     pub fn repair(
         path: PathBuf,
         config: &ConfigSet,
@@ -5,11 +5,16 @@
  * GNU General Public License version 2.
  */
 
-use std::path::{Path, PathBuf};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use anyhow::Result;
 
 use indexedlog::{
+    lock::ScopedDirLock,
     log::{self, IndexDef, IndexOutput, Log, LogLookupIter},
     rotate::{self, RotateLog, RotateLogLookupIter},
     Result as IndexedlogResult,
@@ -59,6 +64,15 @@ impl Store {
         }
     }
 
+    /// Convert a slice returned by `lookup` or `iter` to `Bytes`, without copying it if it is
+    /// backed by the on-disk buffer of the store.
+    pub fn slice_to_bytes(&self, slice: &[u8]) -> Bytes {
+        match self {
+            Store::Local(log) => log.slice_to_bytes(slice),
+            Store::Shared(log) => log.slice_to_bytes(slice),
+        }
+    }
+
     pub fn flush(&mut self) -> Result<()> {
         match self {
             Store::Local(log) => {
@@ -70,6 +84,132 @@ impl Store {
         };
         Ok(())
     }
+
+    /// Scan every entry of the store at `path`. `parse` checks that an entry can be read, and
+    /// returns its `EntryInfo`. Entries which can't be read are counted as corrupt, and entries
+    /// which can't be looked up with their key in the first index are counted as unindexed.
+    pub fn stats(
+        &self,
+        path: impl AsRef<Path>,
+        parse: impl Fn(&[u8]) -> Result<EntryInfo>,
+    ) -> Result<IndexedLogStats> {
+        let path = path.as_ref();
+        let mut stats = IndexedLogStats::default();
+        if let Store::Shared(_) = self {
+            stats.logs = 0;
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                let is_log = entry.file_name().to_string_lossy().parse::<u8>().is_ok();
+                if is_log && entry.file_type()?.is_dir() {
+                    stats.logs += 1;
+                }
+            }
+            stats.last_rotation = Some(fs::metadata(path.join("latest"))?.modified()?);
+        }
+
+        for buf in self.iter() {
+            stats.entries += 1;
+            let info = buf.map_err(anyhow::Error::from).and_then(|buf| {
+                stats.stored_bytes += buf.len() as u64;
+                let info = parse(buf)?;
+                let indexed = self
+                    .lookup(0, &info.index_key)?
+                    .any(|found| found.map_or(false, |found| found == buf));
+                Ok((info, indexed))
+            });
+            match info {
+                Ok((info, indexed)) => {
+                    stats.content_bytes += info.content_len;
+                    if !indexed {
+                        stats.unindexed_entries += 1;
+                    }
+                }
+                Err(e) => {
+                    stats.corrupt_entries += 1;
+                    if stats.errors.len() < IndexedLogStats::MAX_ERRORS {
+                        stats.errors.push(format!("{:#}", e));
+                    }
+                }
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// Information about an entry read by `Store::stats`.
+pub struct EntryInfo {
+    /// The key the entry is stored under in the store's first index.
+    pub index_key: Vec<u8>,
+
+    /// Length of the content of the entry, once decompressed.
+    pub content_len: u64,
+}
+
+/// Statistics about an indexedlog store, gathered by scanning all its entries.
+#[derive(Debug)]
+pub struct IndexedLogStats {
+    /// Number of logs; always 1 for local stores.
+    pub logs: usize,
+
+    /// When the latest log of a shared store was created.
+    pub last_rotation: Option<SystemTime>,
+
+    pub entries: u64,
+
+    /// Total size of the entries, as stored on disk.
+    pub stored_bytes: u64,
+
+    /// Total size of the content of the entries, once decompressed.
+    pub content_bytes: u64,
+
+    /// Entries which couldn't be read.
+    pub corrupt_entries: u64,
+
+    /// Entries which can't be found through the store's index.
+    pub unindexed_entries: u64,
+
+    /// The first errors encountered while reading entries.
+    pub errors: Vec<String>,
+}
+
+impl IndexedLogStats {
+    const MAX_ERRORS: usize = 10;
+}
+
+impl Default for IndexedLogStats {
+    fn default() -> Self {
+        IndexedLogStats {
+            logs: 1,
+            last_rotation: None,
+            entries: 0,
+            stored_bytes: 0,
+            content_bytes: 0,
+            corrupt_entries: 0,
+            unindexed_entries: 0,
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for IndexedLogStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "logs: {}", self.logs)?;
+        if let Some(last_rotation) = self.last_rotation {
+            let age = SystemTime::now()
+                .duration_since(last_rotation)
+                .unwrap_or_default();
+            writeln!(f, "last rotation: {}s ago", age.as_secs())?;
+        }
+        writeln!(f, "entries: {}", self.entries)?;
+        writeln!(f, "stored bytes: {}", self.stored_bytes)?;
+        writeln!(f, "content bytes: {}", self.content_bytes)?;
+        writeln!(f, "corrupt entries: {}", self.corrupt_entries)?;
+        writeln!(f, "unindexed entries: {}", self.unindexed_entries)?;
+        for error in &self.errors {
+            writeln!(f, "error: {}", error)?;
+        }
+        Ok(())
+    }
 }
 
 /// Iterator returned from `Store::lookup`.
@@ -211,6 +351,61 @@ impl StoreOpenOptions {
     }
 }
 
+/// Remove the oldest logs of the shared store at `path` until it takes at most `max_bytes` on
+/// disk. The latest log is always kept, so the store may still be larger than `max_bytes`.
+///
+/// Returns the number of bytes freed.
+pub fn gc_shared(path: impl AsRef<Path>, max_bytes: u64) -> Result<u64> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        return Ok(0);
+    }
+    let _lock = ScopedDirLock::new(path)?;
+
+    let latest = match fs::read_to_string(path.join("latest")) {
+        Ok(latest) => latest.trim().parse::<u8>()?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+
+    // Walk the logs from the newest to the oldest. A `RotateLog` stops loading older logs at the
+    // first missing one, so deleting a suffix of this sequence is safe for concurrent readers.
+    let mut total = 0;
+    let mut freed = 0;
+    for age in 0..=u8::MAX {
+        let log_path = path.join(format!("{}", latest.wrapping_sub(age)));
+        if !log_path.is_dir() {
+            break;
+        }
+
+        let size = dir_size(&log_path)?;
+        total += size;
+        if age > 0 && total > max_bytes {
+            // Like `RotateLog`, delete the `meta` file first to atomically mark the log as deleted.
+            // On Windows, this can fail if other processes have the log mmap-ed.
+            if fs::remove_file(log_path.join("meta"))
+                .and_then(|_| fs::remove_dir_all(&log_path))
+                .is_ok()
+            {
+                freed += size;
+            }
+        }
+    }
+
+    Ok(freed)
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -292,4 +487,38 @@ mod tests {
         assert_eq!(store.lookup(0, b"aa")?.count(), 0);
         Ok(())
     }
+
+    #[test]
+    fn test_gc_shared() -> Result<()> {
+        let dir = TempDir::new()?;
+
+        let open_options = || {
+            StoreOpenOptions::new()
+                .index("hex", |_| vec![IndexOutput::Reference(0..2)])
+                .max_log_count(10)
+                .max_bytes_per_log(10)
+        };
+        let mut store = open_options().shared(&dir)?;
+        for value in &[b"aabcd", b"abbcd", b"acbcd", b"adbcd"] {
+            store.append(value)?;
+            store.append(value)?;
+            store.flush()?;
+        }
+        drop(store);
+
+        let freed = gc_shared(&dir, 0)?;
+        assert!(freed > 0);
+        assert_eq!(gc_shared(&dir, 0)?, 0);
+
+        let store = open_options().shared(&dir)?;
+        assert_eq!(store.lookup(0, b"aa")?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc_shared_missing() -> Result<()> {
+        let dir = TempDir::new()?;
+        assert_eq!(gc_shared(dir.path().join("missing"), 0)?, 0);
+        Ok(())
+    }
 }
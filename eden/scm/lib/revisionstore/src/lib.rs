@@ -143,18 +143,21 @@ mod types;
 mod unionstore;
 mod util;
 
+pub mod cachestats;
 pub mod datapack;
 pub mod datastore;
 pub mod edenapi;
 pub mod error;
 pub mod historypack;
 pub mod historystore;
+pub mod indexedlogauxstore;
 pub mod indexedlogdatastore;
 pub mod localstore;
 pub mod multiplexstore;
 pub mod mutabledatapack;
 pub mod mutablehistorypack;
 pub mod mutablepack;
+pub mod negativecache;
 pub mod newstore;
 pub mod packstore;
 pub mod packwriter;
@@ -172,6 +175,7 @@ pub use crate::historypack::{HistoryEntry, HistoryPack, HistoryPackVersion};
 pub use crate::historystore::{HgIdHistoryStore, HgIdMutableHistoryStore, RemoteHistoryStore};
 pub use crate::indexedlogdatastore::{IndexedLogDataStoreType, IndexedLogHgIdDataStore};
 pub use crate::indexedloghistorystore::{IndexedLogHgIdHistoryStore, IndexedLogHistoryStoreType};
+pub use crate::indexedlogutil::IndexedLogStats;
 pub use crate::localstore::{ExtStoredPolicy, LocalStore};
 pub use crate::memcache::MemcacheStore;
 pub use crate::metadatastore::{MetadataStore, MetadataStoreBuilder};
@@ -7,12 +7,22 @@
 
 //! Adapters around Memcache to be transparently used as HgIdDataStore or HgIdHistoryStore.
 
-use std::{mem::size_of, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    mem::size_of,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream, FutureExt, StreamExt};
+use futures_batch::ChunksTimeoutStreamExt;
 use minibytes::Bytes;
 use serde_derive::{Deserialize, Serialize};
-use tracing::info_span;
+use tokio::task::spawn_blocking;
+use tracing::{info_span, warn};
 
 use types::{Key, NodeInfo};
 
@@ -21,7 +31,12 @@ use crate::{
         Delta, HgIdDataStore, HgIdMutableDeltaStore, Metadata, RemoteDataStore, StoreResult,
     },
     historystore::{HgIdHistoryStore, HgIdMutableHistoryStore, RemoteHistoryStore},
+    indexedlogdatastore::Entry,
     localstore::LocalStore,
+    newstore::{
+        metrics::FetchMetrics, FetchError, FetchStream, KeyStream, ReadStore, WriteResults,
+        WriteStore, WriteStream,
+    },
     remotestore::HgIdRemoteStore,
     types::StoreKey,
 };
@@ -161,6 +176,96 @@ impl HgIdRemoteStore for MemcacheStore {
     }
 }
 
+/// Maximum number of keys looked up in Memcache at once by the newstore adapter.
+const FETCH_BATCH_SIZE: usize = 100;
+
+/// How long the newstore adapter waits for a batch of keys to fill up.
+const FETCH_BATCH_TIMEOUT: Duration = Duration::from_millis(10);
+
+const METRICS: FetchMetrics = FetchMetrics::new("memcache");
+
+impl MemcacheStore {
+    /// Look up a batch of keys. Memcache is a best-effort cache, so failures are logged and
+    /// reported as "not found" to let a `FallbackStore` query the next store.
+    fn fetch_batch(&self, keys: Vec<Key>) -> Vec<Result<Entry, FetchError<Key>>> {
+        METRICS.requested(keys.len());
+        METRICS.request(1);
+        let start = Instant::now();
+
+        let mut found = HashMap::new();
+        match self.get_data_iter(&keys) {
+            Ok(iter) => {
+                for mcdata in iter {
+                    if let Ok(mcdata) = mcdata {
+                        METRICS.bytes(mcdata.data.len());
+                        let entry = Entry::new(mcdata.key.clone(), mcdata.data, mcdata.metadata);
+                        found.insert(mcdata.key, entry);
+                    }
+                }
+            }
+            Err(e) => {
+                METRICS.error(keys.len());
+                warn!({ error = %e }, "memcache fetch failed");
+            }
+        }
+        METRICS.hit(found.len());
+        METRICS.miss(keys.len() - found.len());
+        METRICS.latency(start.elapsed());
+
+        keys.into_iter()
+            .map(|key| match found.remove(&key) {
+                Some(entry) => Ok(entry),
+                None => Err(FetchError::not_found(key)),
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ReadStore<Key, Entry> for MemcacheStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+        Box::pin(
+            keys.chunks_timeout(FETCH_BATCH_SIZE, FETCH_BATCH_TIMEOUT)
+                .then(move |keys| {
+                    let self_ = self.clone();
+                    let keys_ = keys.clone();
+                    spawn_blocking(move || self_.fetch_batch(keys_)).map(move |spawn_res| {
+                        match spawn_res {
+                            Ok(results) => results,
+                            Err(e) => {
+                                warn!({ error = %e }, "memcache fetch failed");
+                                keys.into_iter()
+                                    .map(FetchError::not_found)
+                                    .map(Err)
+                                    .collect()
+                            }
+                        }
+                    })
+                })
+                .flat_map(stream::iter),
+        )
+    }
+}
+
+#[async_trait]
+impl WriteStore<Key, Entry> for MemcacheStore {
+    /// Values are written in the background, the returned stream doesn't wait for Memcache.
+    async fn write_stream(self: Arc<Self>, values: WriteStream<Entry>) -> WriteResults<Key> {
+        Box::pin(values.map(move |mut value| {
+            let key = value.key().clone();
+            let delta = Delta {
+                data: value.content().map_err(|e| (Some(key.clone()), e))?,
+                base: None,
+                key: key.clone(),
+            };
+            let metadata = value.metadata().clone();
+            let self_ = self.clone();
+            spawn_blocking(move || self_.add_data(&delta, &metadata));
+            Ok(key)
+        }))
+    }
+}
+
 struct MemcacheHgIdDataStore {
     store: Arc<dyn HgIdMutableDeltaStore>,
     memcache: Arc<MemcacheStore>,
@@ -312,3 +417,48 @@ impl RemoteHistoryStore for MemcacheHgIdHistoryStore {
         Ok(())
     }
 }
+
+#[cfg(all(test, not(all(fbcode_build, target_os = "linux"))))]
+mod tests {
+    use super::*;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use configparser::config::ConfigSet;
+    use progress::null::NullProgressFactory;
+    use types::testutil::*;
+
+    #[test]
+    fn test_newstore_dummy() -> Result<()> {
+        let memcache = Arc::new(MemcacheStore::new(
+            &ConfigSet::new(),
+            NullProgressFactory::arc(),
+        )?);
+        let k = key("a", "1");
+
+        let entry = Entry::new(
+            k.clone(),
+            Bytes::from(&[1, 2, 3, 4][..]),
+            Default::default(),
+        );
+        let written = block_on_stream(block_on(
+            memcache
+                .clone()
+                .write_stream(Box::pin(stream::iter(vec![entry]))),
+        ))
+        .collect::<Vec<_>>();
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0].as_ref().unwrap(), &k);
+
+        let fetched = block_on_stream(block_on(
+            memcache.fetch_stream(Box::pin(stream::iter(vec![k.clone()]))),
+        ))
+        .collect::<Vec<_>>();
+        assert_eq!(fetched.len(), 1);
+        match &fetched[0] {
+            Err(FetchError::NotFound(missing)) => assert_eq!(missing, &k),
+            _ => panic!("expected a NotFound error"),
+        }
+
+        Ok(())
+    }
+}
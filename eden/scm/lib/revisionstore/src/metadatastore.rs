@@ -21,8 +21,9 @@ use crate::{
     localstore::LocalStore,
     memcache::MemcacheStore,
     multiplexstore::MultiplexHgIdHistoryStore,
+    newstore::scmstore::scmstore_offline,
     packstore::{CorruptionPolicy, MutableHistoryPackStore},
-    remotestore::HgIdRemoteStore,
+    remotestore::{HgIdRemoteStore, OfflineRemoteStore},
     repack::RepackLocation,
     types::StoreKey,
     unionhistorystore::UnionHgIdHistoryStore,
@@ -222,7 +223,17 @@ impl<'a> MetadataStoreBuilder<'a> {
         self
     }
 
-    pub fn build(self) -> Result<MetadataStore> {
+    pub fn build(mut self) -> Result<MetadataStore> {
+        // In offline mode, keys missing locally are reported as requiring network access rather
+        // than fetched.
+        let offline = scmstore_offline(self.config)?;
+        if offline {
+            self.memcachestore = None;
+            if self.remotestore.is_some() {
+                self.remotestore = Some(Arc::new(OfflineRemoteStore));
+            }
+        }
+
         let local_path = get_local_path(&self.local_path, &self.suffix)?;
         let cache_path = get_cache_path(self.config, &self.suffix)?;
         let max_pending: u64 = self
@@ -0,0 +1,427 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{Cursor, Write},
+    mem,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use futures::{future, stream, FutureExt, StreamExt};
+use parking_lot::{Mutex, RwLock};
+use tokio::task::spawn_blocking;
+use tracing::error;
+
+use configparser::config::ConfigSet;
+use indexedlog::log::IndexOutput;
+use types::{HgId, Key};
+
+use crate::{
+    indexedlogdatastore::Entry,
+    indexedlogutil::{Store, StoreOpenOptions},
+    newstore::{
+        metrics::FetchMetrics, BoxedReadStore, FetchError, FetchStream, KeyStream, ReadStore,
+    },
+};
+
+const METRICS: FetchMetrics = FetchMetrics::new("negativecache");
+
+/// Records the keys the server reported missing, so that fetches for data which doesn't exist,
+/// for instance triggered by broken linknodes, aren't sent to the server over and over again.
+///
+/// Entries expire after `scmstore.negative-cache-ttl` seconds (one hour by default), and all
+/// entries are dropped by `invalidate`, which is called whenever new data may have been pulled.
+pub struct NegativeCache {
+    log: RwLock<Store>,
+
+    /// Path of the file recording when the cache was last invalidated.
+    invalidated_path: PathBuf,
+
+    /// Entries older than this (in milliseconds since the epoch) are ignored.
+    invalidated: AtomicU64,
+
+    ttl: Duration,
+}
+
+impl NegativeCache {
+    /// Create or open a shared `NegativeCache`.
+    pub fn new(path: impl AsRef<Path>, config: &ConfigSet) -> Result<Self> {
+        let path = path.as_ref();
+        let log = NegativeCache::open_options().shared(path.join("log"))?;
+        let invalidated_path = path.join("invalidated");
+        let invalidated = match fs::read_to_string(&invalidated_path) {
+            Ok(invalidated) => invalidated
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| NegativeCache::now()),
+            Err(_) => 0,
+        };
+        let ttl = config.get_or::<u64>("scmstore", "negative-cache-ttl", || 3600)?;
+        Ok(NegativeCache {
+            log: RwLock::new(log),
+            invalidated_path,
+            invalidated: AtomicU64::new(invalidated),
+            ttl: Duration::from_secs(ttl),
+        })
+    }
+
+    fn open_options() -> StoreOpenOptions {
+        // Entries are small and short-lived: 2 x 10MB is plenty.
+        StoreOpenOptions::new()
+            .max_log_count(2)
+            .max_bytes_per_log(10 * 1000 * 1000)
+            .auto_sync_threshold(1024 * 1024)
+            .create(true)
+            .index("key", |data| {
+                vec![IndexOutput::Reference(8..data.len() as u64)]
+            })
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64)
+    }
+
+    fn index_key(key: &Key) -> Vec<u8> {
+        let mut index_key = Vec::with_capacity(HgId::len() + key.path.as_byte_slice().len());
+        index_key.extend_from_slice(key.hgid.as_ref());
+        index_key.extend_from_slice(key.path.as_byte_slice());
+        index_key
+    }
+
+    /// Whether `key` was recently reported missing.
+    ///
+    /// The on-disk format of an entry is the following:
+    /// - Time the key was reported missing, in milliseconds since the epoch: 8 unsigned bytes,
+    ///   big-endian
+    /// - HgId <20 bytes>
+    /// - Path <variable length>
+    pub fn contains(&self, key: &Key) -> Result<bool> {
+        let invalidated = self.invalidated.load(Ordering::SeqCst);
+        let expired = NegativeCache::now().saturating_sub(self.ttl.as_millis() as u64);
+        let log = self.log.read();
+        for entry in log.lookup(0, NegativeCache::index_key(key))? {
+            let recorded = Cursor::new(entry?).read_u64::<BigEndian>()?;
+            if recorded > invalidated && recorded > expired {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Record that `key` is missing from the server. See [`contains`] for the on-disk format.
+    pub fn add(&self, key: &Key) -> Result<()> {
+        let index_key = NegativeCache::index_key(key);
+        let mut buf = Vec::with_capacity(8 + index_key.len());
+        buf.write_u64::<BigEndian>(NegativeCache::now())?;
+        buf.write_all(&index_key)?;
+        Ok(self.log.write().append(buf)?)
+    }
+
+    /// Drop every entry recorded so far, for instance because a pull may have brought in the
+    /// data they were missing.
+    pub fn invalidate(&self) -> Result<()> {
+        let now = NegativeCache::now();
+        fs::write(&self.invalidated_path, now.to_string())?;
+        self.invalidated.store(now, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.log.write().flush()
+    }
+}
+
+/// The keys of a fetch through a `NegativeCacheStore`.
+#[derive(Default)]
+struct FetchState {
+    /// Keys sent to the underlying store which it hasn't returned yet.
+    pending: HashSet<Key>,
+
+    /// Keys answered from the negative cache.
+    cached: Vec<Key>,
+
+    /// Keys the underlying store reported missing.
+    missing: Vec<Key>,
+
+    /// Whether the underlying store failed in a way which can't be attributed to a key, in which
+    /// case the keys it didn't return aren't reported as not found.
+    failed: bool,
+}
+
+/// Consults a `NegativeCache` before fetching from the underlying (remote) store, and records the
+/// keys it reports missing.
+///
+/// Only keys explicitly reported with `FetchError::NotFound` are recorded. Keys the underlying
+/// store leaves out of its response are reported as not found to the caller, but aren't
+/// recorded, since a truncated response or a batch dropped by the server leaves keys out too.
+pub struct NegativeCacheStore {
+    pub store: BoxedReadStore<Key, Entry>,
+    pub cache: Arc<NegativeCache>,
+}
+
+impl NegativeCacheStore {
+    /// Record the keys reported missing by a fetch, and return the keys to report as not found.
+    fn finish(cache: Arc<NegativeCache>, state: &Mutex<FetchState>) -> Vec<Key> {
+        let mut state = state.lock();
+        // Keys reported missing by the underlying store were already returned by it, the keys it
+        // left out still have to be reported.
+        let record = mem::take(&mut state.missing);
+        let left_out: Vec<Key> = if state.failed {
+            Vec::new()
+        } else {
+            state.pending.drain().collect()
+        };
+        if !record.is_empty() {
+            spawn_blocking(move || {
+                let res: Result<()> = record
+                    .iter()
+                    .try_for_each(|key| cache.add(key))
+                    .and_then(|()| cache.flush());
+                if let Err(e) = res {
+                    error!({ error = %e }, "error writing to the negative cache");
+                }
+            });
+        }
+
+        let mut not_found = mem::take(&mut state.cached);
+        not_found.extend(left_out);
+        not_found
+    }
+}
+
+#[async_trait]
+impl ReadStore<Key, Entry> for NegativeCacheStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+        let state = Arc::new(Mutex::new(FetchState::default()));
+
+        let cache = self.cache.clone();
+        let lookup_state = state.clone();
+        let keys = keys.filter_map(move |key| {
+            let cache = cache.clone();
+            let state = lookup_state.clone();
+            let key_ = key.clone();
+            spawn_blocking(move || cache.contains(&key_)).map(move |spawn_res| {
+                METRICS.requested(1);
+                let mut state = state.lock();
+                match spawn_res {
+                    Ok(Ok(true)) => {
+                        METRICS.hit(1);
+                        state.cached.push(key);
+                        None
+                    }
+                    Ok(Ok(false)) => {
+                        METRICS.miss(1);
+                        state.pending.insert(key.clone());
+                        Some(key)
+                    }
+                    // Failing to read the cache shouldn't fail the fetch.
+                    Ok(Err(e)) => {
+                        METRICS.error(1);
+                        error!({ error = %e, key = %key }, "error reading the negative cache");
+                        state.pending.insert(key.clone());
+                        Some(key)
+                    }
+                    Err(e) => {
+                        METRICS.error(1);
+                        error!({ error = %e, key = %key }, "error reading the negative cache");
+                        state.pending.insert(key.clone());
+                        Some(key)
+                    }
+                }
+            })
+        });
+
+        let fetch_state = state.clone();
+        let results = self
+            .store
+            .clone()
+            .fetch_stream(Box::pin(keys))
+            .await
+            .inspect(move |res| {
+                let mut state = fetch_state.lock();
+                match res {
+                    Ok(entry) => {
+                        state.pending.remove(entry.key());
+                    }
+                    Err(FetchError::NotFound(key)) => {
+                        if state.pending.remove(key) {
+                            state.missing.push(key.clone());
+                        }
+                    }
+                    Err(FetchError::KeyedError(key, _)) => {
+                        state.pending.remove(key);
+                    }
+                    Err(FetchError::Other(_)) => state.failed = true,
+                }
+            });
+
+        let cache = self.cache.clone();
+        let not_found = stream::once(future::lazy(move |_| {
+            stream::iter(
+                NegativeCacheStore::finish(cache, &state)
+                    .into_iter()
+                    .map(|key| Err(FetchError::not_found(key))),
+            )
+        }))
+        .flatten();
+
+        Box::pin(results.chain(not_found))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use minibytes::Bytes;
+    use tempfile::TempDir;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use types::testutil::*;
+
+    /// Returns the keys it has content for, and counts the keys it was asked for. The other keys
+    /// are reported missing if `report_missing` is set, or left out of the response otherwise.
+    struct FakeRemote {
+        present: Vec<Key>,
+        report_missing: bool,
+        requested: Mutex<Vec<Key>>,
+    }
+
+    #[async_trait]
+    impl ReadStore<Key, Entry> for FakeRemote {
+        async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+            Box::pin(keys.filter_map(move |key| {
+                self.requested.lock().push(key.clone());
+                let found = self.present.contains(&key);
+                future::ready(if found {
+                    Some(Ok(Entry::new(key, Bytes::new(), Default::default())))
+                } else if self.report_missing {
+                    Some(Err(FetchError::not_found(key)))
+                } else {
+                    None
+                })
+            }))
+        }
+    }
+
+    fn fetch(store: Arc<NegativeCacheStore>, keys: Vec<Key>) -> (usize, Vec<Key>) {
+        let (mut found, mut not_found) = (0, vec![]);
+        for res in block_on_stream(block_on(store.fetch_stream(Box::pin(stream::iter(keys))))) {
+            match res {
+                Ok(_) => found += 1,
+                Err(FetchError::NotFound(key)) => not_found.push(key),
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+        (found, not_found)
+    }
+
+    #[test]
+    fn test_negative_cache() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let (present, absent) = (key("a", "1"), key("b", "2"));
+        let remote = Arc::new(FakeRemote {
+            present: vec![present.clone()],
+            report_missing: true,
+            requested: Mutex::new(vec![]),
+        });
+        let cache = Arc::new(NegativeCache::new(&tempdir, &ConfigSet::new())?);
+        let store = Arc::new(NegativeCacheStore {
+            store: remote.clone(),
+            cache: cache.clone(),
+        });
+
+        let keys = vec![present.clone(), absent.clone()];
+        assert_eq!(
+            fetch(store.clone(), keys.clone()),
+            (1, vec![absent.clone()])
+        );
+        assert_eq!(remote.requested.lock().len(), 2);
+
+        // Wait for the missing key to be recorded in the background.
+        let start = std::time::Instant::now();
+        while !cache.contains(&absent)? {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // The missing key isn't requested again, even by another process.
+        let reopened = Arc::new(NegativeCacheStore {
+            store: remote.clone(),
+            cache: Arc::new(NegativeCache::new(&tempdir, &ConfigSet::new())?),
+        });
+        assert_eq!(fetch(reopened, keys.clone()), (1, vec![absent.clone()]));
+        assert_eq!(remote.requested.lock().len(), 3);
+
+        // Until the cache is invalidated.
+        std::thread::sleep(Duration::from_millis(2));
+        cache.invalidate()?;
+        assert!(!cache.contains(&absent)?);
+        assert_eq!(fetch(store, keys), (1, vec![absent]));
+        assert_eq!(remote.requested.lock().len(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_negative_cache_left_out() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let absent = key("b", "2");
+        let remote = Arc::new(FakeRemote {
+            present: vec![],
+            report_missing: false,
+            requested: Mutex::new(vec![]),
+        });
+        let cache = Arc::new(NegativeCache::new(&tempdir, &ConfigSet::new())?);
+        let store = Arc::new(NegativeCacheStore {
+            store: remote.clone(),
+            cache: cache.clone(),
+        });
+
+        // Keys left out of the response are reported as not found, but aren't recorded.
+        assert_eq!(
+            fetch(store.clone(), vec![absent.clone()]),
+            (0, vec![absent.clone()])
+        );
+        assert_eq!(
+            fetch(store, vec![absent.clone()]),
+            (0, vec![absent.clone()])
+        );
+        assert_eq!(remote.requested.lock().len(), 2);
+        assert!(!cache.contains(&absent)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_negative_cache_ttl() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let mut config = ConfigSet::new();
+        config.set(
+            "scmstore",
+            "negative-cache-ttl",
+            Some("0"),
+            &Default::default(),
+        );
+        let cache = NegativeCache::new(&tempdir, &config)?;
+        let k = key("a", "1");
+        cache.add(&k)?;
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(!cache.contains(&k)?);
+        Ok(())
+    }
+}
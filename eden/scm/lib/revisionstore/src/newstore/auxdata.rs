@@ -0,0 +1,247 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! File aux data: the size and content hashes of a file, which can be cached and looked up
+//! without reading the file's content.
+//!
+//! Aux data which isn't cached is computed from the content of the files stored locally, or
+//! fetched from EdenApi's aux data endpoint, so that files are not downloaded to be hashed.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{FutureExt, StreamExt};
+use minibytes::Bytes;
+use tokio::task::spawn_blocking;
+
+use edenapi_types::Blake3;
+use types::{Key, Sha256};
+
+use crate::{
+    datastore::{strip_metadata, Metadata},
+    indexedlogdatastore::Entry,
+    newstore::{BoxedReadStore, FetchError, FetchStream, KeyStream, ReadStore},
+    types::ContentHash,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileAuxData {
+    pub key: Key,
+
+    /// Size of the file content, without copy metadata.
+    pub total_size: u64,
+
+    /// SHA-256 of the file content, without copy metadata.
+    pub content_sha256: Sha256,
+
+    /// BLAKE3 of the file content, without copy metadata.
+    pub content_blake3: Blake3,
+}
+
+impl FileAuxData {
+    /// Compute the aux data of a file from its stored content. Returns None for LFS pointers,
+    /// since the blake3 hash of an LFS blob can't be computed from its pointer.
+    pub fn from_content(key: Key, content: &Bytes, metadata: &Metadata) -> Result<Option<Self>> {
+        if metadata.is_lfs() {
+            return Ok(None);
+        }
+        let (content, _) = strip_metadata(content)?;
+        Ok(Some(FileAuxData {
+            key,
+            total_size: content.len() as u64,
+            content_sha256: ContentHash::sha256(&content).unwrap_sha256(),
+            content_blake3: content_blake3(&content),
+        }))
+    }
+
+    pub fn from_entry(mut entry: Entry) -> Result<Option<Self>> {
+        let content = entry.content()?;
+        FileAuxData::from_content(entry.key().clone(), &content, entry.metadata())
+    }
+}
+
+impl From<edenapi_types::FileAuxData> for FileAuxData {
+    fn from(aux: edenapi_types::FileAuxData) -> Self {
+        FileAuxData {
+            key: aux.key,
+            total_size: aux.total_size,
+            content_sha256: Sha256::from(aux.content_sha256.0),
+            content_blake3: aux.content_blake3,
+        }
+    }
+}
+
+pub(crate) fn content_blake3(content: &[u8]) -> Blake3 {
+    Blake3(*blake3::hash(content).as_bytes())
+}
+
+/// Compute aux data from the files of an `Entry` store. LFS pointers are reported as not found,
+/// so that their aux data is fetched from the server instead.
+pub struct ComputedAuxStore {
+    pub store: BoxedReadStore<Key, Entry>,
+}
+
+#[async_trait]
+impl ReadStore<Key, FileAuxData> for ComputedAuxStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, FileAuxData> {
+        Box::pin(
+            self.store
+                .clone()
+                .fetch_stream(keys)
+                .await
+                .then(|res| async move {
+                    let entry = res?;
+                    let key = entry.key().clone();
+                    let key_ = key.clone();
+                    spawn_blocking(move || FileAuxData::from_entry(entry))
+                        .map(move |spawn_res| match spawn_res {
+                            Ok(Ok(Some(aux))) => Ok(aux),
+                            Ok(Ok(None)) => Err(FetchError::not_found(key)),
+                            Ok(Err(e)) => Err(FetchError::with_key(key, e)),
+                            Err(e) => Err(FetchError::with_key(key_, e)),
+                        })
+                        .await
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use futures::stream;
+    use maplit::hashmap;
+    use tempfile::TempDir;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use configparser::config::ConfigSet;
+    use types::testutil::*;
+
+    use crate::{
+        datastore::{Delta, HgIdMutableDeltaStore},
+        indexedlogauxstore::IndexedLogAuxStore,
+        indexedlogdatastore::{IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+        localstore::ExtStoredPolicy,
+        newstore::{
+            edenapi::EdenApiAdapter,
+            fallback::{FallbackFetchOptions, FallbackStore, WriteErrorPolicy, WritePolicy},
+        },
+        testutil::FakeEdenApi,
+    };
+
+    fn fetch_aux(store: BoxedReadStore<Key, FileAuxData>, keys: Vec<Key>) -> Vec<FileAuxData> {
+        block_on_stream(block_on(store.fetch_stream(Box::pin(stream::iter(keys)))))
+            .collect::<Result<_, _>>()
+            .expect("failed to fetch aux data")
+    }
+
+    fn aux(key: Key, content: &[u8]) -> FileAuxData {
+        FileAuxData {
+            key,
+            total_size: content.len() as u64,
+            content_sha256: ContentHash::sha256(&Bytes::copy_from_slice(content)).unwrap_sha256(),
+            content_blake3: content_blake3(content),
+        }
+    }
+
+    #[test]
+    fn test_computed_aux_store() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let config = ConfigSet::new();
+        let log = Arc::new(IndexedLogHgIdDataStore::new(
+            tempdir.path().join("data"),
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?);
+        let copied = key("copied", "1");
+        log.add(
+            &Delta {
+                data: Bytes::from(format!(
+                    "\x01\ncopy: a\ncopyrev: {}\n\x01\ncontent",
+                    "2".repeat(40)
+                )),
+                base: None,
+                key: copied.clone(),
+            },
+            &Default::default(),
+        )?;
+        let lfs = key("lfs", "3");
+        let pointer = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 12345\n",
+            "ab".repeat(32)
+        );
+        log.add(
+            &Delta {
+                data: Bytes::from(pointer.into_bytes()),
+                base: None,
+                key: lfs.clone(),
+            },
+            &Metadata {
+                size: None,
+                flags: Some(Metadata::LFS_FLAG),
+            },
+        )?;
+
+        // The aux data of the LFS file can't be computed from its pointer, so it is fetched
+        // from the server.
+        let files: HashMap<Key, Bytes> = hashmap! { lfs.clone() => Bytes::from(&b"large"[..]) };
+        let remote = Arc::new(EdenApiAdapter {
+            client: Arc::new(FakeEdenApi::new().files(files)),
+            repo: "repo".to_string(),
+            options: Default::default(),
+        });
+        let aux_store = Arc::new(IndexedLogAuxStore::new(
+            tempdir.path().join("aux"),
+            &config,
+        )?);
+        let store = Arc::new(FallbackStore {
+            preferred: aux_store.clone(),
+            fallback: Arc::new(FallbackStore {
+                preferred: Arc::new(ComputedAuxStore { store: log }),
+                fallback: remote,
+                write_store: aux_store.clone(),
+                write_policy: WritePolicy::NoWrite,
+                write_error_policy: WriteErrorPolicy::Log,
+                fetch_options: FallbackFetchOptions::default(),
+            }),
+            write_store: aux_store.clone(),
+            write_policy: WritePolicy::WriteThrough,
+            write_error_policy: WriteErrorPolicy::Log,
+            fetch_options: FallbackFetchOptions::default(),
+        });
+
+        let expected = vec![aux(copied.clone(), b"content"), aux(lfs.clone(), b"large")];
+        let mut fetched = fetch_aux(store, vec![copied.clone(), lfs.clone()]);
+        fetched.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(fetched, expected);
+
+        // The computed and fetched aux data was cached.
+        assert_eq!(fetch_aux(aux_store, vec![copied, lfs]), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_edenapi_aux() {
+        let k = key("a", "1");
+        let files: HashMap<Key, Bytes> = hashmap! { k.clone() => Bytes::from(&b"01234"[..]) };
+        let client = Arc::new(FakeEdenApi::new().files(files));
+        let adapter = Arc::new(EdenApiAdapter {
+            client: client.clone(),
+            repo: "repo".to_string(),
+            options: Default::default(),
+        });
+        assert_eq!(fetch_aux(adapter, vec![k.clone()]), vec![aux(k, b"01234")]);
+        // The aux data was requested from the aux data endpoint, without fetching the file.
+        assert_eq!(client.aux_requests(), 1);
+        assert_eq!(client.requests(), 0);
+    }
+}
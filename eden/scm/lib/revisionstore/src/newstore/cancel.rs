@@ -0,0 +1,281 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Deadlines and cancellation for fetch streams.
+//!
+//! A `CancellableStore` stops the fetches through it when its `CancellationToken` is cancelled,
+//! when they run longer than its timeout, or when the process is interrupted (Ctrl-C). The underlying stream
+//! is dropped right away, which aborts the EdenApi requests it has in flight instead of waiting
+//! for them to drain, and the stream ends with a `FetchInterrupted` error.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{
+    future::{self, BoxFuture, Either},
+    stream, FutureExt, StreamExt,
+};
+use thiserror::Error;
+use tokio::{
+    sync::Notify,
+    time::{sleep_until, Instant},
+};
+
+use crate::newstore::{BoxedReadStore, FetchError, FetchStream, KeyStream, ReadStore};
+
+/// Why a fetch was stopped before it completed.
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+pub enum FetchInterrupted {
+    #[error("fetch cancelled")]
+    Cancelled,
+
+    #[error("fetch interrupted")]
+    Interrupted,
+
+    #[error("fetch deadline exceeded")]
+    DeadlineExceeded,
+}
+
+#[derive(Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// Cancels the fetches of the stores it is given to. Cloning a `CancellationToken` shares it.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<TokenState>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Stop the fetches in progress, and fail the ones started later.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            // Created before checking, so that a cancellation between the check and the wait
+            // isn't lost.
+            let notified = self.state.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+pub struct CancellableStore<K, V> {
+    pub store: BoxedReadStore<K, V>,
+
+    /// Stop the fetches when this token is cancelled.
+    pub token: Option<CancellationToken>,
+
+    /// Stop each fetch still in progress this long after it started.
+    pub timeout: Option<Duration>,
+
+    /// Stop the fetches when the process receives SIGINT. Handlers installed before, such as
+    /// Python's, are still called.
+    pub interruptible: bool,
+}
+
+impl<K, V> CancellableStore<K, V> {
+    /// Resolves when a fetch starting now should stop, with the reason why.
+    fn stopped(&self) -> BoxFuture<'static, FetchInterrupted> {
+        let token = self.token.clone();
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let interruptible = self.interruptible;
+        async move {
+            let cancelled = async {
+                match token {
+                    Some(token) => token.cancelled().await,
+                    None => future::pending().await,
+                }
+            };
+            let expired = async {
+                match deadline {
+                    Some(deadline) => sleep_until(deadline).await,
+                    None => future::pending().await,
+                }
+            };
+            let interrupted = async {
+                // Failing to listen for the signal only loses the ability to stop early.
+                if !interruptible || tokio::signal::ctrl_c().await.is_err() {
+                    future::pending::<()>().await;
+                }
+            };
+            tokio::select! {
+                biased;
+                _ = cancelled => FetchInterrupted::Cancelled,
+                _ = interrupted => FetchInterrupted::Interrupted,
+                _ = expired => FetchInterrupted::DeadlineExceeded,
+            }
+        }
+        .boxed()
+    }
+}
+
+#[async_trait]
+impl<K, V> ReadStore<K, V> for CancellableStore<K, V>
+where
+    K: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<K>) -> FetchStream<K, V> {
+        let stopped = self.stopped();
+        let fetched: FetchStream<K, V> =
+            Box::pin(stream::once(self.store.clone().fetch_stream(keys)).flatten());
+
+        // Once stopped, the underlying stream is dropped and the stream ends after the error.
+        Box::pin(stream::unfold(
+            (Some(fetched), stopped),
+            |(fetched, mut stopped)| async move {
+                let mut fetched = fetched?;
+                match future::select(&mut stopped, fetched.next()).await {
+                    Either::Left((reason, _)) => {
+                        Some((Err(FetchError::from(reason)), (None, stopped)))
+                    }
+                    Either::Right((Some(item), _)) => Some((item, (Some(fetched), stopped))),
+                    Either::Right((None, _)) => None,
+                }
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use minibytes::Bytes;
+
+    use async_runtime::block_on_future as block_on;
+    use types::{testutil::*, Key};
+
+    use crate::indexedlogdatastore::Entry;
+
+    /// Returns the first key right away, and never returns the others.
+    struct HangingStore;
+
+    #[async_trait]
+    impl ReadStore<Key, Entry> for HangingStore {
+        async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+            Box::pin(keys.enumerate().then(|(i, key)| async move {
+                if i > 0 {
+                    future::pending::<()>().await;
+                }
+                Ok(Entry::new(
+                    key,
+                    Bytes::from_static(b"data"),
+                    Default::default(),
+                ))
+            }))
+        }
+    }
+
+    fn fetch(store: CancellableStore<Key, Entry>) -> Vec<Result<Key, String>> {
+        let keys = vec![key("a", "1"), key("b", "2")];
+        block_on(async move {
+            Arc::new(store)
+                .fetch_stream(Box::pin(stream::iter(keys)))
+                .await
+                .map(|res| {
+                    res.map(|entry| entry.key().clone())
+                        .map_err(|e| e.to_string())
+                })
+                .collect::<Vec<_>>()
+                .await
+        })
+    }
+
+    #[test]
+    fn test_deadline() {
+        let store = CancellableStore {
+            store: Arc::new(HangingStore),
+            token: None,
+            timeout: Some(Duration::from_millis(50)),
+            interruptible: false,
+        };
+        assert_eq!(
+            fetch(store),
+            vec![
+                Ok(key("a", "1")),
+                Err("fetch deadline exceeded".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timeout_per_fetch() {
+        let store = CancellableStore {
+            store: Arc::new(HangingStore),
+            token: None,
+            timeout: Some(Duration::from_millis(50)),
+            interruptible: false,
+        };
+        // The timeout only starts with the fetch, not when the store is built.
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(
+            fetch(store),
+            vec![
+                Ok(key("a", "1")),
+                Err("fetch deadline exceeded".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let token = CancellationToken::new();
+        let store = CancellableStore {
+            store: Arc::new(HangingStore),
+            token: Some(token.clone()),
+            timeout: None,
+            interruptible: false,
+        };
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        });
+        assert_eq!(
+            fetch(store),
+            vec![Ok(key("a", "1")), Err("fetch cancelled".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_cancelled_before_fetch() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let store = CancellableStore {
+            store: Arc::new(HangingStore),
+            token: Some(token),
+            timeout: None,
+            interruptible: false,
+        };
+        assert_eq!(fetch(store), vec![Err("fetch cancelled".to_string())]);
+    }
+}
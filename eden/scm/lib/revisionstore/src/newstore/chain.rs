@@ -0,0 +1,268 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A combinator which queries an ordered list of stores, for instance memory, local store,
+//! shared cache, memcache and EdenApi, without spelling out nested `FallbackStore`s.
+//!
+//! Keys missing from a hop are looked up in the next one. Each hop can have values found in the
+//! later hops written back to it, with its own `WritePolicy`.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::format_err;
+use async_trait::async_trait;
+use futures::StreamExt;
+
+use crate::newstore::{
+    fallback::{FallbackFetchOptions, FallbackStore, WriteErrorPolicy, WritePolicy},
+    BoxedReadStore, BoxedWriteStore, FetchError, FetchStream, KeyStream, ReadStore, WriteResults,
+    WriteStore, WriteStream,
+};
+
+/// A store in a `ChainStore`.
+pub struct ChainHop<K, V> {
+    /// Name of the hop, under which its hits are reported.
+    pub name: &'static str,
+
+    pub store: BoxedReadStore<K, V>,
+
+    /// Where values found in the later hops are written, usually the same store as `store`.
+    pub write_store: Option<BoxedWriteStore<K, V>>,
+
+    pub write_policy: WritePolicy,
+}
+
+impl<K, V> ChainHop<K, V> {
+    /// A hop which values found in later hops are not written to.
+    pub fn new(name: &'static str, store: BoxedReadStore<K, V>) -> Self {
+        ChainHop {
+            name,
+            store,
+            write_store: None,
+            write_policy: WritePolicy::NoWrite,
+        }
+    }
+
+    /// Write the values found in later hops to `write_store`, according to `write_policy`.
+    pub fn write_back(
+        mut self,
+        write_store: BoxedWriteStore<K, V>,
+        write_policy: WritePolicy,
+    ) -> Self {
+        self.write_store = Some(write_store);
+        self.write_policy = write_policy;
+        self
+    }
+}
+
+pub struct ChainStore<K, V> {
+    store: BoxedReadStore<K, V>,
+    hits: Vec<(&'static str, Arc<AtomicUsize>)>,
+}
+
+impl<K, V> ChainStore<K, V>
+where
+    K: fmt::Display + fmt::Debug + Send + Sync + Clone + Unpin + 'static,
+    V: Send + Sync + Clone + 'static,
+{
+    /// Query `hops` in order. Keys missing from a hop are batched and sent to the next hop
+    /// according to `fetch_options`.
+    pub fn new(hops: Vec<ChainHop<K, V>>, fetch_options: FallbackFetchOptions) -> Self {
+        let hits: Vec<_> = hops
+            .iter()
+            .map(|hop| (hop.name, Arc::new(AtomicUsize::new(0))))
+            .collect();
+
+        // Build the chain from the last hop, each hop falling back to the rest of the chain.
+        let mut store: BoxedReadStore<K, V> = Arc::new(EmptyStore);
+        for (hop, (_, hits)) in hops.into_iter().zip(hits.iter()).rev() {
+            let counted = Arc::new(CountingStore {
+                store: hop.store,
+                hits: hits.clone(),
+            });
+            store = Arc::new(FallbackStore {
+                preferred: counted,
+                fallback: store,
+                write_store: hop.write_store.unwrap_or_else(|| Arc::new(EmptyStore)),
+                write_policy: hop.write_policy,
+                write_error_policy: WriteErrorPolicy::Log,
+                fetch_options: fetch_options.clone(),
+            });
+        }
+        ChainStore { store, hits }
+    }
+}
+
+impl<K, V> ChainStore<K, V> {
+    /// The number of keys found in each hop so far, in order.
+    pub fn hits(&self) -> Vec<(&'static str, usize)> {
+        self.hits
+            .iter()
+            .map(|(name, hits)| (*name, hits.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<K, V> ReadStore<K, V> for ChainStore<K, V>
+where
+    K: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<K>) -> FetchStream<K, V> {
+        self.store.clone().fetch_stream(keys).await
+    }
+}
+
+/// Counts the values found in a hop.
+struct CountingStore<K, V> {
+    store: BoxedReadStore<K, V>,
+    hits: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl<K, V> ReadStore<K, V> for CountingStore<K, V>
+where
+    K: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<K>) -> FetchStream<K, V> {
+        let hits = self.hits.clone();
+        Box::pin(
+            self.store
+                .clone()
+                .fetch_stream(keys)
+                .await
+                .inspect(move |res| {
+                    if res.is_ok() {
+                        hits.fetch_add(1, Ordering::Relaxed);
+                    }
+                }),
+        )
+    }
+}
+
+/// The end of the chain, which contains nothing, and the write store of the hops which aren't
+/// written to.
+struct EmptyStore;
+
+#[async_trait]
+impl<K, V> ReadStore<K, V> for EmptyStore
+where
+    K: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<K>) -> FetchStream<K, V> {
+        Box::pin(keys.map(|key| Err(FetchError::not_found(key))))
+    }
+}
+
+#[async_trait]
+impl<K, V> WriteStore<K, V> for EmptyStore
+where
+    K: Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    async fn write_stream(self: Arc<Self>, values: WriteStream<V>) -> WriteResults<K> {
+        Box::pin(values.map(|_| Err((None, format_err!("store is not writable")))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+    use futures::stream;
+    use minibytes::Bytes;
+    use tempfile::TempDir;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use configparser::config::ConfigSet;
+    use types::{testutil::*, Key};
+
+    use crate::{
+        datastore::{Delta, HgIdDataStore, HgIdMutableDeltaStore, StoreResult},
+        indexedlogdatastore::{Entry, IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+        localstore::ExtStoredPolicy,
+        types::StoreKey,
+    };
+
+    #[test]
+    fn test_chain() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let open = |name: &str| -> Result<Arc<IndexedLogHgIdDataStore>> {
+            Ok(Arc::new(IndexedLogHgIdDataStore::new(
+                tempdir.path().join(name),
+                ExtStoredPolicy::Use,
+                &ConfigSet::new(),
+                IndexedLogDataStoreType::Shared,
+            )?))
+        };
+        let (local, cache, remote) = (open("local")?, open("cache")?, open("remote")?);
+
+        let add = |store: &IndexedLogHgIdDataStore, k: &Key| {
+            store.add(
+                &Delta {
+                    data: Bytes::from(&b"content"[..]),
+                    base: None,
+                    key: k.clone(),
+                },
+                &Default::default(),
+            )
+        };
+        let (in_local, in_cache, in_remote) = (key("a", "1"), key("b", "2"), key("c", "3"));
+        add(&local, &in_local)?;
+        add(&cache, &in_cache)?;
+        add(&remote, &in_remote)?;
+
+        let chain = Arc::new(ChainStore::new(
+            vec![
+                ChainHop::new("local", local.clone() as BoxedReadStore<Key, Entry>),
+                ChainHop::new("cache", cache.clone())
+                    .write_back(cache.clone(), WritePolicy::WriteThrough),
+                ChainHop::new("remote", remote),
+            ],
+            FallbackFetchOptions::default(),
+        ));
+
+        let keys = vec![
+            in_local.clone(),
+            in_cache.clone(),
+            in_remote.clone(),
+            key("d", "4"),
+        ];
+        let fetched: Vec<_> = block_on_stream(block_on(
+            chain.clone().fetch_stream(Box::pin(stream::iter(keys))),
+        ))
+        .collect();
+        assert_eq!(fetched.len(), 4);
+        assert_eq!(fetched.iter().filter(|res| res.is_ok()).count(), 3);
+        assert_eq!(
+            chain.hits(),
+            vec![("local", 1), ("cache", 1), ("remote", 1)]
+        );
+
+        // The value found in the remote hop was written back to the cache, but not to the
+        // local store.
+        assert_eq!(
+            cache.get(StoreKey::hgid(in_remote.clone()))?,
+            StoreResult::Found(b"content".to_vec())
+        );
+        assert_eq!(
+            local.get(StoreKey::hgid(in_remote.clone()))?,
+            StoreResult::NotFound(StoreKey::hgid(in_remote))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Streaming fetches, which return the content of each value as a stream of chunks.
+//!
+//! Large files can then be written out chunk by chunk, rather than being copied into a single
+//! heap allocation of the size of the file. Stores which receive whole values (EdenApi) split them
+//! into slices of the value they hold, which are not copied. Indexedlog entries compressed with
+//! zstd are decompressed chunk by chunk as the content is read.
+
+use std::{io::Read, iter, pin::Pin, sync::Arc};
+
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use futures::{stream, Stream, StreamExt};
+use minibytes::Bytes;
+use parking_lot::Mutex;
+
+use edenapi::EdenApi;
+use edenapi_types::FileEntry;
+use types::Key;
+
+use crate::{
+    datastore::Metadata,
+    indexedlogdatastore::Entry,
+    newstore::{
+        edenapi::EdenApiAdapter, BoxedReadStore, FetchError, FetchStream, KeyStream, ReadStore,
+    },
+};
+
+/// Default size of the chunks a value's content is split into.
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The content of a single value, as a stream of chunks. Unlike `BoxStream`, it is `Sync` so
+/// that `ChunkedEntry` can be returned by a `ReadStore`.
+pub type ContentStream = Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send + Sync + 'static>>;
+
+/// A fetched value whose content is streamed.
+pub struct ChunkedEntry {
+    key: Key,
+    metadata: Metadata,
+    content: ContentStream,
+}
+
+impl ChunkedEntry {
+    pub fn new(key: Key, metadata: Metadata, content: ContentStream) -> Self {
+        ChunkedEntry {
+            key,
+            metadata,
+            content,
+        }
+    }
+
+    /// Stream `content` in chunks of at most `chunk_size` bytes, without copying it.
+    pub fn from_bytes(key: Key, metadata: Metadata, content: Bytes, chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let chunks = (0..content.len())
+            .step_by(chunk_size)
+            .map(move |start| Ok(content.slice(start..(start + chunk_size).min(content.len()))));
+        ChunkedEntry::new(key, metadata, Box::pin(stream::iter(chunks)))
+    }
+
+    /// Stream the content read from `reader` in chunks of at most `chunk_size` bytes. Each chunk
+    /// is only read once the previous one was consumed.
+    pub fn from_reader(
+        key: Key,
+        metadata: Metadata,
+        reader: impl Read + Send + 'static,
+        chunk_size: usize,
+    ) -> Self {
+        let chunk_size = chunk_size.max(1);
+        // The mutex makes the stream `Sync`, it is never contended.
+        let reader = Mutex::new(Some(reader));
+        let chunks = iter::from_fn(move || {
+            let mut reader = reader.lock();
+            let mut chunk = Vec::new();
+            let read = reader
+                .as_mut()?
+                .take(chunk_size as u64)
+                .read_to_end(&mut chunk);
+            match read {
+                Ok(0) => {
+                    *reader = None;
+                    None
+                }
+                Ok(_) => Some(Ok(Bytes::from(chunk))),
+                Err(e) => {
+                    *reader = None;
+                    Some(Err(e.into()))
+                }
+            }
+        });
+        ChunkedEntry::new(key, metadata, Box::pin(stream::iter(chunks)))
+    }
+
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn into_content(self) -> ContentStream {
+        self.content
+    }
+}
+
+/// Stream the content of the entries of an `Entry` store, for instance an indexedlog store.
+pub struct ChunkedStore {
+    pub store: BoxedReadStore<Key, Entry>,
+    pub chunk_size: usize,
+}
+
+#[async_trait]
+impl ReadStore<Key, ChunkedEntry> for ChunkedStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, ChunkedEntry> {
+        let chunk_size = self.chunk_size;
+        Box::pin(self.store.clone().fetch_stream(keys).await.map(move |res| {
+            let mut entry = res?;
+            let key = entry.key().clone();
+            let metadata = entry.metadata().clone();
+            let reader = entry
+                .content_reader()
+                .map_err(|e| FetchError::with_key(key.clone(), e))?;
+            if let Some(reader) = reader {
+                return Ok(ChunkedEntry::from_reader(key, metadata, reader, chunk_size));
+            }
+            // Other entries can only be decompressed whole, then they are streamed in slices.
+            let content = entry
+                .content()
+                .map_err(|e| FetchError::with_key(key.clone(), e))?;
+            Ok(ChunkedEntry::from_bytes(key, metadata, content, chunk_size))
+        }))
+    }
+}
+
+// EdenApi returns whole files, so the received buffer is streamed in chunks of
+// `EdenApiAdapterOptions::chunk_size` bytes.
+#[async_trait]
+impl<C> ReadStore<Key, ChunkedEntry> for EdenApiAdapter<C>
+where
+    C: EdenApi + ?Sized,
+{
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, ChunkedEntry> {
+        let chunk_size = self.options.chunk_size;
+        Box::pin(
+            ReadStore::<Key, FileEntry>::fetch_stream(self, keys)
+                .await
+                .map(move |res| {
+                    let entry = res?;
+                    Ok(ChunkedEntry::from_bytes(
+                        entry.key().clone(),
+                        entry.metadata().clone(),
+                        entry.data_unchecked().into(),
+                        chunk_size,
+                    ))
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use futures::TryStreamExt;
+    use maplit::hashmap;
+    use tempfile::TempDir;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use configparser::config::ConfigSet;
+    use types::testutil::*;
+
+    use crate::{
+        datastore::{Delta, HgIdMutableDeltaStore},
+        indexedlogdatastore::{IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+        localstore::ExtStoredPolicy,
+        newstore::edenapi::EdenApiAdapterOptions,
+        testutil::FakeEdenApi,
+    };
+
+    fn fetch_chunks(store: BoxedReadStore<Key, ChunkedEntry>, k: Key) -> Vec<Bytes> {
+        let mut fetched: Vec<_> = block_on_stream(block_on(
+            store.fetch_stream(Box::pin(stream::iter(vec![k.clone()]))),
+        ))
+        .collect();
+        assert_eq!(fetched.len(), 1);
+        let entry = fetched.pop().unwrap().expect("failed to fetch");
+        assert_eq!(entry.key(), &k);
+        block_on(entry.into_content().try_collect()).expect("failed to read content")
+    }
+
+    #[test]
+    fn test_chunked_store() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let log = IndexedLogHgIdDataStore::new(
+            &tempdir,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?;
+        let k = key("a", "1");
+        log.add(
+            &Delta {
+                data: Bytes::from(&b"0123456789"[..]),
+                base: None,
+                key: k.clone(),
+            },
+            &Default::default(),
+        )?;
+
+        let store = Arc::new(ChunkedStore {
+            store: Arc::new(log),
+            chunk_size: 4,
+        });
+        assert_eq!(
+            fetch_chunks(store, k),
+            vec![
+                Bytes::from(&b"0123"[..]),
+                Bytes::from(&b"4567"[..]),
+                Bytes::from(&b"89"[..])
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_store_zstd() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let mut config = ConfigSet::new();
+        config.set(
+            "indexedlog",
+            "data.zstd-threshold",
+            Some("1"),
+            &Default::default(),
+        );
+        let log = IndexedLogHgIdDataStore::new(
+            &tempdir,
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?;
+        let k = key("a", "1");
+        log.add(
+            &Delta {
+                data: Bytes::from(&b"0123456789"[..]),
+                base: None,
+                key: k.clone(),
+            },
+            &Default::default(),
+        )?;
+
+        let store = Arc::new(ChunkedStore {
+            store: Arc::new(log),
+            chunk_size: 4,
+        });
+        assert_eq!(
+            fetch_chunks(store, k),
+            vec![
+                Bytes::from(&b"0123"[..]),
+                Bytes::from(&b"4567"[..]),
+                Bytes::from(&b"89"[..])
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_edenapi() {
+        let k = key("a", "1");
+        let files: HashMap<Key, Bytes> = hashmap! { k.clone() => Bytes::from(&b"01234"[..]) };
+        let adapter = Arc::new(EdenApiAdapter {
+            client: Arc::new(FakeEdenApi::new().files(files)),
+            repo: "repo".to_string(),
+            options: EdenApiAdapterOptions {
+                chunk_size: 3,
+                ..Default::default()
+            },
+        });
+        assert_eq!(
+            fetch_chunks(adapter, k),
+            vec![Bytes::from(&b"012"[..]), Bytes::from(&b"34"[..])]
+        );
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Fetch file content by content hash rather than by path and node, so that EdenFS, which
+//! addresses file blobs by their content hash, can share the stores of hg's cache.
+//!
+//! Content is looked up in the LFS blob store, which is already keyed by sha256, then through the
+//! aux data store, which maps a sha256 back to a file node whose content is fetched from an
+//! `Entry` store and checked against the hash. Only the sha256 hashes the aux data store keeps
+//! are supported, and since the path of the file is not known, the `Entry` store is given keys
+//! with an empty path.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::{future, stream, StreamExt};
+use minibytes::Bytes;
+use tokio::task::spawn_blocking;
+
+use types::{HgId, Key, RepoPathBuf};
+
+use crate::{
+    datastore::{strip_metadata, ContentDataStore, StoreResult},
+    indexedlogauxstore::IndexedLogAuxStore,
+    indexedlogdatastore::Entry,
+    newstore::{BoxedReadStore, FetchError, FetchStream, KeyStream, ReadStore},
+    types::{ContentHash, StoreKey},
+};
+
+/// File content, without copy metadata, and its hash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentBlob {
+    pub hash: ContentHash,
+    pub content: Bytes,
+}
+
+pub struct ContentAddressedStore {
+    /// Maps content hashes to the file nodes to fetch from `store`.
+    pub aux: Arc<IndexedLogAuxStore>,
+
+    /// Where the content of the file nodes found in `aux` is fetched from.
+    pub store: BoxedReadStore<Key, Entry>,
+
+    /// Checked first, for the content of LFS files.
+    pub lfs: Option<Arc<dyn ContentDataStore>>,
+}
+
+impl ContentAddressedStore {
+    /// Look up the content in the LFS store, then find the node of a file with this content.
+    fn lookup(&self, hash: &ContentHash) -> Result<Lookup> {
+        if let Some(lfs) = self.lfs.as_ref() {
+            if let StoreResult::Found(content) = lfs.blob(StoreKey::content(hash.clone()))? {
+                return Ok(Lookup::Content(content));
+            }
+        }
+        let hgid = match hash {
+            ContentHash::Sha256(sha256) => self.aux.get_hgid(sha256)?,
+        };
+        Ok(hgid.map_or(Lookup::NotFound, Lookup::HgId))
+    }
+
+    async fn fetch(
+        self: Arc<Self>,
+        hash: ContentHash,
+    ) -> Result<ContentBlob, FetchError<ContentHash>> {
+        let self_ = self.clone();
+        let hash_ = hash.clone();
+        let found = spawn_blocking(move || self_.lookup(&hash_))
+            .await
+            .map_err(|e| FetchError::with_key(hash.clone(), e))?
+            .map_err(|e| FetchError::with_key(hash.clone(), e))?;
+
+        let hgid = match found {
+            Lookup::Content(content) => return Ok(ContentBlob { hash, content }),
+            Lookup::HgId(hgid) => hgid,
+            Lookup::NotFound => return Err(FetchError::not_found(hash)),
+        };
+
+        let key = Key::new(RepoPathBuf::new(), hgid);
+        let keys = Box::pin(stream::once(future::ready(key)));
+        let mut entry = match self.store.clone().fetch_stream(keys).await.next().await {
+            Some(Ok(entry)) => entry,
+            Some(Err(FetchError::NotFound(_))) | None => return Err(FetchError::not_found(hash)),
+            Some(Err(e)) => return Err(FetchError::with_key(hash, e)),
+        };
+        let content = entry
+            .content()
+            .and_then(|content| {
+                if entry.metadata().is_lfs() {
+                    return Err(anyhow!("file node {} is an LFS pointer", hgid));
+                }
+                Ok(strip_metadata(&content)?.0)
+            })
+            .map_err(|e| FetchError::with_key(hash.clone(), e))?;
+
+        if ContentHash::sha256(&content) != hash {
+            return Err(FetchError::with_key(
+                hash,
+                anyhow!("content of file node {} doesn't match its hash", hgid),
+            ));
+        }
+        Ok(ContentBlob { hash, content })
+    }
+}
+
+/// What the local stores know of some content.
+enum Lookup {
+    Content(Bytes),
+    HgId(HgId),
+    NotFound,
+}
+
+#[async_trait]
+impl ReadStore<ContentHash, ContentBlob> for ContentAddressedStore {
+    async fn fetch_stream(
+        self: Arc<Self>,
+        keys: KeyStream<ContentHash>,
+    ) -> FetchStream<ContentHash, ContentBlob> {
+        Box::pin(keys.then(move |hash| self.clone().fetch(hash)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use configparser::config::ConfigSet;
+    use types::{testutil::*, Sha256};
+
+    use crate::{
+        datastore::{Delta, HgIdMutableDeltaStore, Metadata},
+        indexedlogdatastore::{IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+        lfs::LfsStore,
+        localstore::ExtStoredPolicy,
+        newstore::{auxdata::FileAuxData, WriteStore},
+    };
+
+    fn fetch(
+        store: Arc<ContentAddressedStore>,
+        hashes: Vec<ContentHash>,
+    ) -> Vec<Result<Bytes, String>> {
+        block_on_stream(block_on(store.fetch_stream(Box::pin(stream::iter(hashes)))))
+            .map(|res| res.map(|blob| blob.content).map_err(|e| e.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_content_addressed_fetch() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let config = ConfigSet::new();
+        let log = Arc::new(IndexedLogHgIdDataStore::new(
+            tempdir.path().join("data"),
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?);
+        let aux = Arc::new(IndexedLogAuxStore::new(
+            tempdir.path().join("aux"),
+            &config,
+        )?);
+        let lfs = Arc::new(LfsStore::shared(&tempdir, &config)?);
+
+        // A copied file, whose content is stored with copy metadata.
+        let copied = key("copied", "1");
+        let content = Bytes::from_static(b"content");
+        log.add(
+            &Delta {
+                data: Bytes::from(format!(
+                    "\x01\ncopy: a\ncopyrev: {}\n\x01\ncontent",
+                    "2".repeat(40)
+                )),
+                base: None,
+                key: copied.clone(),
+            },
+            &Default::default(),
+        )?;
+        // A file whose cached aux data doesn't match its content.
+        let corrupt = key("corrupt", "3");
+        log.add(
+            &Delta {
+                data: Bytes::from_static(b"other"),
+                base: None,
+                key: corrupt.clone(),
+            },
+            &Metadata::default(),
+        )?;
+        let corrupt_hash = ContentHash::Sha256(Sha256::from([1; Sha256::len()]));
+        let aux_data = vec![
+            FileAuxData {
+                key: copied,
+                total_size: content.len() as u64,
+                content_sha256: ContentHash::sha256(&content).unwrap_sha256(),
+                content_blake3: Default::default(),
+            },
+            FileAuxData {
+                key: corrupt,
+                total_size: 5,
+                content_sha256: corrupt_hash.clone().unwrap_sha256(),
+                content_blake3: Default::default(),
+            },
+        ];
+        let written: Vec<_> = block_on_stream(block_on(
+            aux.clone().write_stream(Box::pin(stream::iter(aux_data))),
+        ))
+        .collect();
+        assert!(written.iter().all(|res| res.is_ok()));
+
+        let lfs_content = Bytes::from_static(b"large content");
+        lfs.add(
+            &Delta {
+                data: lfs_content.clone(),
+                base: None,
+                key: key("large", "4"),
+            },
+            &Metadata::default(),
+        )?;
+
+        let store = Arc::new(ContentAddressedStore {
+            aux,
+            store: log,
+            lfs: Some(lfs),
+        });
+        let missing = ContentHash::sha256(&Bytes::from_static(b"missing"));
+        let fetched = fetch(
+            store,
+            vec![
+                ContentHash::sha256(&content),
+                ContentHash::sha256(&lfs_content),
+                missing.clone(),
+                corrupt_hash.clone(),
+            ],
+        );
+        assert_eq!(fetched[0], Ok(content));
+        assert_eq!(fetched[1], Ok(lfs_content));
+        assert_eq!(
+            fetched[2],
+            Err(format!("failed to fetch key '{}': key not found", missing))
+        );
+        assert!(fetched[3].as_ref().unwrap_err().contains("doesn't match"));
+        Ok(())
+    }
+}
@@ -0,0 +1,643 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Checks the stores of a cache directory for `hg doctor`.
+//!
+//! Each store is checked in turn: its directory must exist, its lock must be available, the
+//! `latest` file of a rotated store must point to a log, and the store must open. Stores are then
+//! repaired, which verifies their logs and rebuilds corrupt indices, and the outcome is reported
+//! per store, so that a damaged cache doesn't surface as an opaque error the next time it's used.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+
+use configparser::config::ConfigSet;
+use indexedlog::{lock::ScopedDirLock, utils::atomic_read};
+
+use crate::{
+    indexedlogdatastore::{IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+    indexedloghistorystore::{IndexedLogHgIdHistoryStore, IndexedLogHistoryStoreType},
+    lfs::LfsStore,
+    localstore::ExtStoredPolicy,
+};
+
+/// Whether a cache directory holds the shared (rotated) stores, or the local stores of a repo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheKind {
+    Shared,
+    Local,
+}
+
+#[derive(Clone, Debug)]
+pub struct DoctorOptions {
+    /// Repair the stores with problems. Otherwise, they are only reported.
+    pub repair: bool,
+
+    /// How long to wait for the lock of a store before reporting it as locked.
+    pub lock_timeout: Duration,
+}
+
+impl DoctorOptions {
+    pub fn from_config(config: &ConfigSet) -> Result<Self> {
+        Ok(DoctorOptions {
+            repair: true,
+            lock_timeout: Duration::from_millis(config.get_or(
+                "scmstore",
+                "doctor-lock-timeout-ms",
+                || 5000,
+            )?),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StoreStatus {
+    /// The store doesn't exist. It's created the first time it's used.
+    Missing,
+
+    Ok,
+
+    /// The store had problems, and was repaired.
+    Repaired,
+
+    /// Another process held the lock of the store for longer than the lock timeout, so the
+    /// store wasn't checked.
+    Locked,
+
+    /// The store has problems which weren't repaired, or couldn't be.
+    Broken,
+}
+
+impl fmt::Display for StoreStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self {
+            StoreStatus::Missing => "missing",
+            StoreStatus::Ok => "ok",
+            StoreStatus::Repaired => "repaired",
+            StoreStatus::Locked => "locked by another process",
+            StoreStatus::Broken => "broken",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StoreCheck {
+    /// The store's path, relative to the cache directory.
+    pub name: &'static str,
+
+    pub status: StoreStatus,
+
+    /// The problems found in the store, whether or not they were repaired.
+    pub problems: Vec<String>,
+
+    /// What the repair did, if the store was repaired.
+    pub repair_log: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct DoctorReport {
+    pub path: PathBuf,
+    pub stores: Vec<StoreCheck>,
+}
+
+impl DoctorReport {
+    /// Whether every store is usable, possibly after being repaired.
+    pub fn is_healthy(&self) -> bool {
+        self.stores
+            .iter()
+            .all(|check| check.status != StoreStatus::Broken)
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in self.stores.iter() {
+            writeln!(f, "{}: {}", check.name, check.status)?;
+            for problem in check.problems.iter() {
+                writeln!(f, "  {}", problem)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct StoreSpec {
+    /// The store's directory, relative to the cache directory.
+    name: &'static str,
+
+    /// The directories of the store's logs, relative to the cache directory.
+    logs: &'static [&'static str],
+
+    open: fn(&Path, &ConfigSet, CacheKind) -> Result<()>,
+    repair: fn(&Path, &ConfigSet, CacheKind) -> Result<String>,
+}
+
+const SHARED_STORES: &[StoreSpec] = &[
+    StoreSpec {
+        name: "indexedlogdatastore",
+        logs: &["indexedlogdatastore"],
+        open: open_data,
+        repair: repair_data,
+    },
+    StoreSpec {
+        name: "indexedloghistorystore",
+        logs: &["indexedloghistorystore"],
+        open: open_history,
+        repair: repair_history,
+    },
+    StoreSpec {
+        name: "lfs",
+        logs: &["lfs/pointers", "lfs/blobs"],
+        open: open_lfs,
+        repair: repair_lfs,
+    },
+];
+
+const LOCAL_STORES: &[StoreSpec] = &[
+    StoreSpec {
+        name: "indexedlogdatastore",
+        logs: &["indexedlogdatastore"],
+        open: open_data,
+        repair: repair_data,
+    },
+    StoreSpec {
+        name: "indexedloghistorystore",
+        logs: &["indexedloghistorystore"],
+        open: open_history,
+        repair: repair_history,
+    },
+];
+
+/// Check, and with `options.repair`, repair the stores of the cache directory at `path`.
+pub fn check_cache(
+    path: impl AsRef<Path>,
+    kind: CacheKind,
+    config: &ConfigSet,
+    options: &DoctorOptions,
+) -> Result<DoctorReport> {
+    let path = path.as_ref();
+    let specs = match kind {
+        CacheKind::Shared => SHARED_STORES,
+        CacheKind::Local => LOCAL_STORES,
+    };
+    if path.exists() && !path.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} is not a directory", path),
+        )
+        .into());
+    }
+
+    let stores = specs
+        .iter()
+        .map(|spec| check_store(path, spec, kind, config, options))
+        .collect();
+    Ok(DoctorReport {
+        path: path.to_path_buf(),
+        stores,
+    })
+}
+
+fn check_store(
+    path: &Path,
+    spec: &StoreSpec,
+    kind: CacheKind,
+    config: &ConfigSet,
+    options: &DoctorOptions,
+) -> StoreCheck {
+    let mut check = StoreCheck {
+        name: spec.name,
+        status: StoreStatus::Ok,
+        problems: vec![],
+        repair_log: String::new(),
+    };
+
+    if !path.join(spec.name).exists() {
+        check.status = StoreStatus::Missing;
+        return check;
+    }
+
+    for log in spec.logs {
+        let log_path = path.join(log);
+        if !log_path.exists() {
+            continue;
+        }
+        if !log_path.is_dir() {
+            // Repairing the store won't help, the file has to be moved out of the way.
+            check.status = StoreStatus::Broken;
+            check.problems.push(format!("{} is not a directory", log));
+            return check;
+        }
+
+        let lock = match lock_with_timeout(&log_path, options.lock_timeout) {
+            Ok(Some(lock)) => lock,
+            Ok(None) => {
+                check.status = StoreStatus::Locked;
+                return check;
+            }
+            Err(e) => {
+                check.problems.push(format!("{}: cannot lock: {}", log, e));
+                continue;
+            }
+        };
+        if kind == CacheKind::Shared {
+            check_rotation(lock.path(), log, &mut check.problems);
+        }
+        // The lock is released before opening the store, which takes it again.
+    }
+
+    // Opening the store would fail for the same reasons as the checks above.
+    if check.problems.is_empty() {
+        if let Err(e) = (spec.open)(path, config, kind) {
+            check.problems.push(format!("cannot open: {:#}", e));
+        }
+    }
+    if !options.repair {
+        if !check.problems.is_empty() {
+            check.status = StoreStatus::Broken;
+        }
+        return check;
+    }
+
+    // Repairing verifies the logs and their indices, which finds corruption that doesn't prevent
+    // the store from opening, so it's done even if no problems were found so far.
+    match (spec.repair)(path, config, kind) {
+        Ok(repair_log) => {
+            check.problems.extend(repair_fixes(&repair_log));
+            check.repair_log = repair_log;
+        }
+        Err(e) => {
+            check.status = StoreStatus::Broken;
+            check.problems.push(format!("cannot repair: {:#}", e));
+            return check;
+        }
+    }
+    if check.problems.is_empty() {
+        return check;
+    }
+    if kind == CacheKind::Shared {
+        for log in spec.logs {
+            if let Err(e) = remove_unreachable_logs(&path.join(log)) {
+                check
+                    .problems
+                    .push(format!("{}: cannot remove logs: {}", log, e));
+            }
+        }
+    }
+    check.status = match (spec.open)(path, config, kind) {
+        Ok(()) => StoreStatus::Repaired,
+        Err(e) => {
+            check
+                .problems
+                .push(format!("cannot open after repair: {:#}", e));
+            StoreStatus::Broken
+        }
+    };
+    check
+}
+
+/// The lines of an indexedlog repair log which report something that was fixed, as opposed to
+/// something that was checked and found fine.
+fn repair_fixes(repair_log: &str) -> Vec<String> {
+    repair_log
+        .lines()
+        .filter(|line| {
+            !(line.is_empty()
+                || line.starts_with("Attempt to repair log")
+                || line.starts_with("Verified ")
+                || line.starts_with("Latest = ")
+                || line.ends_with("passed integrity check"))
+        })
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Take the lock of the directory at `path`, or return `None` if it's still held by another
+/// process after `timeout`.
+fn lock_with_timeout(path: &Path, timeout: Duration) -> Result<Option<ScopedDirLock>> {
+    let (sender, receiver) = mpsc::channel();
+    let path = path.to_path_buf();
+    // If the lock is taken after the timeout, sending it fails and it's released right away.
+    thread::spawn(move || sender.send(ScopedDirLock::new(&path)));
+    match receiver.recv_timeout(timeout) {
+        Ok(lock) => Ok(Some(lock?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Check that the `latest` file of the rotated store at `path` points to a log, and that every
+/// log is reachable from it. Logs older than a missing one aren't read, nor ever removed.
+fn check_rotation(path: &Path, name: &str, problems: &mut Vec<String>) {
+    let ids = match log_ids(path) {
+        Ok(ids) => ids,
+        Err(e) => {
+            problems.push(format!("{}: cannot list logs: {}", name, e));
+            return;
+        }
+    };
+    if ids.is_empty() {
+        // Nothing was written to the store yet.
+        return;
+    }
+
+    let latest = match read_latest(path) {
+        Ok(latest) => latest,
+        Err(e) => {
+            problems.push(format!("{}: cannot read 'latest': {}", name, e));
+            return;
+        }
+    };
+    if !ids.contains(&latest) {
+        problems.push(format!(
+            "{}: 'latest' points to missing log {}",
+            name, latest
+        ));
+        return;
+    }
+
+    let unreachable = unreachable_logs(latest, &ids);
+    if !unreachable.is_empty() {
+        problems.push(format!(
+            "{}: {} logs are left over from an interrupted rotation",
+            name,
+            unreachable.len()
+        ));
+    }
+}
+
+fn log_ids(path: &Path) -> io::Result<Vec<u8>> {
+    let mut ids = vec![];
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|n| n.parse::<u8>().ok())
+        {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+fn read_latest(path: &Path) -> Result<u8> {
+    // `latest` can be a symlink, see `indexedlog::utils::atomic_write`.
+    let latest = String::from_utf8(atomic_read(&path.join("latest"))?)?;
+    Ok(latest.trim().parse::<u8>()?)
+}
+
+/// The logs in `ids` which aren't reachable from `latest`, going from newer to older logs.
+fn unreachable_logs(latest: u8, ids: &[u8]) -> Vec<u8> {
+    let mut reachable = vec![];
+    for age in 0..=u8::MAX {
+        let id = latest.wrapping_sub(age);
+        if !ids.contains(&id) {
+            break;
+        }
+        reachable.push(id);
+    }
+    ids.iter()
+        .copied()
+        .filter(|id| !reachable.contains(id))
+        .collect()
+}
+
+/// Remove the logs of the rotated store at `path` which aren't reachable from its `latest` log.
+fn remove_unreachable_logs(path: &Path) -> Result<()> {
+    if !path.is_dir() {
+        return Ok(());
+    }
+    let _lock = ScopedDirLock::new(path)?;
+    let ids = log_ids(path)?;
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let latest = read_latest(path)?;
+    for id in unreachable_logs(latest, &ids) {
+        let log_path = path.join(format!("{}", id));
+        // Like `RotateLog`, delete the `meta` file first to atomically mark the log as deleted.
+        let _ = fs::remove_file(log_path.join("meta"));
+        fs::remove_dir_all(&log_path)?;
+    }
+    Ok(())
+}
+
+fn open_data(path: &Path, config: &ConfigSet, kind: CacheKind) -> Result<()> {
+    IndexedLogHgIdDataStore::new(
+        path.join("indexedlogdatastore"),
+        ExtStoredPolicy::Use,
+        config,
+        match kind {
+            CacheKind::Shared => IndexedLogDataStoreType::Shared,
+            CacheKind::Local => IndexedLogDataStoreType::Local,
+        },
+    )?;
+    Ok(())
+}
+
+fn repair_data(path: &Path, config: &ConfigSet, kind: CacheKind) -> Result<String> {
+    IndexedLogHgIdDataStore::repair(
+        path.join("indexedlogdatastore"),
+        config,
+        match kind {
+            CacheKind::Shared => IndexedLogDataStoreType::Shared,
+            CacheKind::Local => IndexedLogDataStoreType::Local,
+        },
+    )
+}
+
+fn open_history(path: &Path, config: &ConfigSet, kind: CacheKind) -> Result<()> {
+    IndexedLogHgIdHistoryStore::new(
+        path.join("indexedloghistorystore"),
+        config,
+        match kind {
+            CacheKind::Shared => IndexedLogHistoryStoreType::Shared,
+            CacheKind::Local => IndexedLogHistoryStoreType::Local,
+        },
+    )?;
+    Ok(())
+}
+
+fn repair_history(path: &Path, config: &ConfigSet, kind: CacheKind) -> Result<String> {
+    IndexedLogHgIdHistoryStore::repair(
+        path.join("indexedloghistorystore"),
+        config,
+        match kind {
+            CacheKind::Shared => IndexedLogHistoryStoreType::Shared,
+            CacheKind::Local => IndexedLogHistoryStoreType::Local,
+        },
+    )
+}
+
+fn open_lfs(path: &Path, config: &ConfigSet, _kind: CacheKind) -> Result<()> {
+    LfsStore::shared(path, config)?;
+    Ok(())
+}
+
+fn repair_lfs(path: &Path, _config: &ConfigSet, _kind: CacheKind) -> Result<String> {
+    LfsStore::repair(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use minibytes::Bytes;
+    use tempfile::TempDir;
+
+    use types::testutil::*;
+
+    use crate::{
+        datastore::{Delta, HgIdMutableDeltaStore, Metadata},
+        localstore::LocalStore,
+    };
+
+    fn options() -> DoctorOptions {
+        DoctorOptions {
+            repair: true,
+            lock_timeout: Duration::from_millis(100),
+        }
+    }
+
+    fn status(report: &DoctorReport, name: &str) -> StoreStatus {
+        report
+            .stores
+            .iter()
+            .find(|check| check.name == name)
+            .unwrap()
+            .status
+            .clone()
+    }
+
+    #[test]
+    fn test_missing_stores() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let report = check_cache(
+            tempdir.path(),
+            CacheKind::Shared,
+            &ConfigSet::new(),
+            &options(),
+        )?;
+        assert!(report.is_healthy());
+        assert!(report
+            .stores
+            .iter()
+            .all(|check| check.status == StoreStatus::Missing));
+        Ok(())
+    }
+
+    #[test]
+    fn test_repair_latest() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let config = ConfigSet::new();
+        let k = key("a", "1");
+        {
+            let store = IndexedLogHgIdDataStore::new(
+                tempdir.path().join("indexedlogdatastore"),
+                ExtStoredPolicy::Use,
+                &config,
+                IndexedLogDataStoreType::Shared,
+            )?;
+            let delta = Delta {
+                data: Bytes::from(&b"a"[..]),
+                base: None,
+                key: k.clone(),
+            };
+            store.add(&delta, &Metadata::default())?;
+            store.flush()?;
+        }
+        let store_path = tempdir.path().join("indexedlogdatastore");
+        fs::write(store_path.join("latest"), "garbage")?;
+
+        let report = check_cache(tempdir.path(), CacheKind::Shared, &config, &options())?;
+        assert_eq!(
+            status(&report, "indexedlogdatastore"),
+            StoreStatus::Repaired
+        );
+        assert!(report.is_healthy());
+        assert_eq!(read_latest(&store_path)?, 0);
+
+        // A log which isn't reachable from the latest one is removed.
+        fs::create_dir(store_path.join("42"))?;
+        let report = check_cache(tempdir.path(), CacheKind::Shared, &config, &options())?;
+        assert_eq!(
+            status(&report, "indexedlogdatastore"),
+            StoreStatus::Repaired
+        );
+        assert!(!store_path.join("42").exists());
+
+        let store = IndexedLogHgIdDataStore::new(
+            &store_path,
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?;
+        assert_eq!(store.get_missing(&[k.clone().into()])?, vec![]);
+
+        let report = check_cache(tempdir.path(), CacheKind::Shared, &config, &options())?;
+        assert_eq!(status(&report, "indexedlogdatastore"), StoreStatus::Ok);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_index() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let config = ConfigSet::new();
+        let store_path = tempdir.path().join("indexedlogdatastore");
+        {
+            let store = IndexedLogHgIdDataStore::new(
+                &store_path,
+                ExtStoredPolicy::Use,
+                &config,
+                IndexedLogDataStoreType::Shared,
+            )?;
+            let delta = Delta {
+                data: Bytes::from(&b"a"[..]),
+                base: None,
+                key: key("a", "1"),
+            };
+            store.add(&delta, &Metadata::default())?;
+            store.flush()?;
+        }
+        fs::write(store_path.join("0").join("index2-node"), "y")?;
+
+        let report = check_cache(tempdir.path(), CacheKind::Shared, &config, &options())?;
+        assert_eq!(
+            status(&report, "indexedlogdatastore"),
+            StoreStatus::Repaired
+        );
+        assert!(report.is_healthy());
+        Ok(())
+    }
+
+    #[test]
+    fn test_locked() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let config = ConfigSet::new();
+        let store_path = tempdir.path().join("indexedloghistorystore");
+        IndexedLogHgIdHistoryStore::new(&store_path, &config, IndexedLogHistoryStoreType::Shared)?;
+
+        let _lock = ScopedDirLock::new(&store_path)?;
+        let report = check_cache(tempdir.path(), CacheKind::Shared, &config, &options())?;
+        assert_eq!(
+            status(&report, "indexedloghistorystore"),
+            StoreStatus::Locked
+        );
+        Ok(())
+    }
+}
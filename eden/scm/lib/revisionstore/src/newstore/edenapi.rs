@@ -5,64 +5,327 @@
  * GNU General Public License version 2.
  */
 
+use std::future::Future;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use anyhow::Result;
 use async_trait::async_trait;
-use futures::StreamExt;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
 use futures_batch::ChunksTimeoutStreamExt;
+use parking_lot::Mutex;
+use tracing::{info_span, warn, Instrument};
 
-use edenapi::EdenApi;
+use configparser::{config::ConfigSet, convert::ByteCount};
+use edenapi::{EdenApi, EdenApiError};
 use edenapi_types::{FileEntry, TreeAttributes, TreeEntry};
+use http::StatusCode;
 use types::Key;
 
-use crate::newstore::{fetch_error, FetchError, FetchStream, KeyStream, ReadStore};
+use crate::newstore::{
+    auxdata::FileAuxData, chunked::DEFAULT_CHUNK_SIZE, fetch_error, metrics::FetchMetrics,
+    FetchError, FetchStream, KeyStream, ReadStore, StoreError, StoreId,
+};
 
-// TODO(meyer): These should be configurable
 // EdenApi's API is batch-based and async, and it will split a large batch into multiple requests to send in parallel
 // but it won't join separate batches into larger ones. Because the input stream may not terminate in a timely fashion,
 // we group the stream into batches with a timeout so that EdenApi will actually be sent batches, rather than constructing
-// a batch of one for each item in the stream. This is worth investigating in the future, though - we could be sending
-// "batches of one" to EdenApi, or we could change the EdenApi client to batch across requests, not just within them.
-// I believe Arun has determined that even with HTTP2, some level of batching within requests is advantageous instead
-// of individually streaming a separate request for each key, but it's still worth making sure we're doing the rgiht thing.
-// We might also want to just grab all ready items from the stream in a batch, with no timeout, if the cost of small batches
-// is smaller than the cost of the timeout waiting to collect larger ones.
-const BATCH_SIZE: usize = 100;
-const BATCH_TIMEOUT: Duration = Duration::from_millis(100);
-
-pub struct EdenApiAdapter<C> {
-    pub client: C,
+// a batch of one for each item in the stream. Batches larger than `max_request_keys` are split here so that a single
+// huge batch (eg. every tree touched by a checkout) is issued as several requests which can be in flight concurrently.
+
+/// Batching settings used by `EdenApiAdapter` when turning a key stream into EdenApi requests.
+#[derive(Clone, Debug)]
+pub struct EdenApiAdapterOptions {
+    /// Maximum number of keys accumulated from the input stream before a batch is flushed.
+    pub batch_size: usize,
+
+    /// How long to wait for a batch to fill up before flushing a partial batch.
+    pub batch_timeout: Duration,
+
+    /// Maximum number of keys sent in a single EdenApi request. Larger batches are split.
+    pub max_request_keys: usize,
+
+    /// Maximum number of EdenApi requests in flight at once.
+    pub request_concurrency: usize,
+
+    /// Size of the chunks file content is split into by streaming fetches.
+    pub chunk_size: usize,
+
+    /// How many times a request the server throttled is retried before it fails.
+    pub throttle_retries: u32,
+
+    /// How long to wait before retrying a throttled request, when the server doesn't say.
+    /// Doubled on each retry.
+    pub throttle_delay: Duration,
+
+    /// How long a fetch may be paused by throttling before a warning is logged.
+    pub throttle_warning: Duration,
+}
+
+impl Default for EdenApiAdapterOptions {
+    fn default() -> Self {
+        EdenApiAdapterOptions {
+            batch_size: 1000,
+            batch_timeout: Duration::from_millis(100),
+            max_request_keys: 100,
+            request_concurrency: 4,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            throttle_retries: 5,
+            throttle_delay: Duration::from_secs(1),
+            throttle_warning: Duration::from_secs(10),
+        }
+    }
+}
+
+impl EdenApiAdapterOptions {
+    pub fn from_config(config: &ConfigSet) -> Result<Self> {
+        let mut options = EdenApiAdapterOptions::default();
+        if let Some(batch_size) = config.get_opt::<usize>("scmstore", "edenapi-batch-size")? {
+            options.batch_size = batch_size.max(1);
+        }
+        if let Some(timeout) = config.get_opt::<u64>("scmstore", "edenapi-batch-timeout-ms")? {
+            options.batch_timeout = Duration::from_millis(timeout);
+        }
+        if let Some(max_keys) = config.get_opt::<usize>("scmstore", "edenapi-max-request-keys")? {
+            options.max_request_keys = max_keys.max(1);
+        }
+        if let Some(concurrency) =
+            config.get_opt::<usize>("scmstore", "edenapi-request-concurrency")?
+        {
+            options.request_concurrency = concurrency.max(1);
+        }
+        if let Some(chunk_size) = config.get_opt::<ByteCount>("scmstore", "chunk-size")? {
+            options.chunk_size = (chunk_size.value() as usize).max(1);
+        }
+        if let Some(retries) = config.get_opt::<u32>("scmstore", "edenapi-throttle-retries")? {
+            options.throttle_retries = retries;
+        }
+        if let Some(delay) = config.get_opt::<u64>("scmstore", "edenapi-throttle-delay-ms")? {
+            options.throttle_delay = Duration::from_millis(delay);
+        }
+        if let Some(warning) = config.get_opt::<u64>("scmstore", "edenapi-throttle-warning-ms")? {
+            options.throttle_warning = Duration::from_millis(warning);
+        }
+        Ok(options)
+    }
+
+    /// Group a stream of keys into the batches of keys which will be sent as individual requests.
+    fn requests(&self, keys: KeyStream<Key>) -> BoxStream<'static, Vec<Key>> {
+        let max_request_keys = self.max_request_keys;
+        Box::pin(
+            keys.chunks_timeout(self.batch_size, self.batch_timeout)
+                .flat_map(move |batch| stream::iter(split_batch(batch, max_request_keys))),
+        )
+    }
+}
+
+fn split_batch(mut batch: Vec<Key>, max_request_keys: usize) -> Vec<Vec<Key>> {
+    let max_request_keys = max_request_keys.max(1);
+    let mut requests = Vec::with_capacity((batch.len() + max_request_keys - 1) / max_request_keys);
+    while batch.len() > max_request_keys {
+        let rest = batch.split_off(max_request_keys);
+        requests.push(batch);
+        batch = rest;
+    }
+    requests.push(batch);
+    requests
+}
+
+/// Longest the adapter waits before retrying a throttled request, whatever the server asks for.
+const MAX_THROTTLE_DELAY: Duration = Duration::from_secs(60);
+
+/// Tracks server backpressure for the requests of a single fetch stream. When a request is
+/// throttled, every request of the stream waits until the delay has passed, so that the key
+/// stream is paused rather than hammering the server.
+#[derive(Default)]
+struct Throttle {
+    state: Mutex<ThrottleState>,
+}
+
+#[derive(Default)]
+struct ThrottleState {
+    /// Requests wait until then before being sent.
+    until: Option<Instant>,
+
+    /// Total time the stream was paused for.
+    paused: Duration,
+
+    warned: bool,
+}
+
+impl Throttle {
+    async fn wait(&self) {
+        let until = self.state.lock().until;
+        if let Some(until) = until {
+            let now = Instant::now();
+            if until > now {
+                tokio::time::sleep(until - now).await;
+            }
+        }
+    }
+
+    /// Pause the requests for `delay`. Returns the total time the stream was paused for, if it
+    /// reached `warning` for the first time.
+    fn pause(&self, delay: Duration, warning: Duration) -> Option<Duration> {
+        let mut state = self.state.lock();
+        let now = Instant::now();
+        let until = now + delay;
+        let current = state.until.filter(|current| *current > now);
+        if current.map_or(true, |current| until > current) {
+            state.paused += until - current.unwrap_or(now);
+            state.until = Some(until);
+        }
+        if !state.warned && state.paused >= warning {
+            state.warned = true;
+            return Some(state.paused);
+        }
+        None
+    }
+}
+
+const TREE_METRICS: FetchMetrics = FetchMetrics::new("edenapi.trees");
+const FILE_METRICS: FetchMetrics = FetchMetrics::new("edenapi.files");
+const AUX_METRICS: FetchMetrics = FetchMetrics::new("edenapi.aux");
+
+pub struct EdenApiAdapter<C: ?Sized> {
+    pub client: Arc<C>,
     pub repo: String,
+    pub options: EdenApiAdapterOptions,
+}
+
+impl<C: ?Sized> EdenApiAdapter<C> {
+    /// Attribute an error to the `endpoint` of this adapter's repo.
+    fn store_error(&self, endpoint: &'static str, e: impl Into<anyhow::Error>) -> StoreError {
+        let store = StoreId::EdenApi {
+            repo: self.repo.clone(),
+            endpoint,
+        };
+        StoreError::new(store, e)
+    }
+
+    /// Attribute a request error to the `endpoint` of this adapter's repo. Network errors, server
+    /// errors and throttling are retryable.
+    fn request_error(&self, endpoint: &'static str, e: EdenApiError) -> StoreError {
+        let retryable = match &e {
+            EdenApiError::Http(_) => true,
+            EdenApiError::HttpError { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        };
+        self.store_error(endpoint, e).retryable(retryable)
+    }
+
+    /// Send a request, retrying it after the delay the server asks for when it is throttled,
+    /// up to `throttle_retries` times. Throttled requests pause the other requests of the
+    /// stream through `throttle`.
+    async fn send<T, F, Fut>(
+        &self,
+        throttle: &Throttle,
+        metrics: &FetchMetrics,
+        mut request: F,
+    ) -> Result<T, EdenApiError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, EdenApiError>>,
+    {
+        let mut retries = 0;
+        loop {
+            throttle.wait().await;
+            metrics.request(1);
+            match request().await {
+                Err(e) if e.is_throttled() && retries < self.options.throttle_retries => {
+                    metrics.throttled(1);
+                    let delay = e
+                        .retry_after()
+                        .unwrap_or_else(|| {
+                            self.options.throttle_delay * 2u32.saturating_pow(retries)
+                        })
+                        .min(MAX_THROTTLE_DELAY);
+                    retries += 1;
+                    if let Some(paused) = throttle.pause(delay, self.options.throttle_warning) {
+                        warn!(
+                            "EdenApi server for repo {} is throttling {} fetches, paused for {:?} so far",
+                            self.repo,
+                            metrics.store(),
+                            paused
+                        );
+                    }
+                }
+                res => return res,
+            }
+        }
+    }
 }
 
 #[async_trait]
 impl<C> ReadStore<Key, TreeEntry> for EdenApiAdapter<C>
 where
-    C: EdenApi,
+    C: EdenApi + ?Sized,
 {
     async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, TreeEntry> {
+        let concurrency = self.options.request_concurrency;
+        let throttle = Arc::new(Throttle::default());
         Box::pin(
-            keys.chunks_timeout(BATCH_SIZE, BATCH_TIMEOUT)
-                .then(move |keys| {
+            self.options
+                .requests(keys)
+                .map(move |keys| {
                     let self_ = self.clone();
+                    let throttle = throttle.clone();
                     async move {
-                        self_
-                            .client
-                            .trees(self_.repo.clone(), keys, Some(TreeAttributes::all()), None)
-                            .await
-                            .map_or_else(fetch_error, |s| {
-                                Box::pin(s.entries.map(|v| match v {
-                                    Ok(Ok(v)) => Ok(v),
+                        TREE_METRICS.requested(keys.len());
+                        let start = Instant::now();
+                        let span = info_span!("EdenApiAdapter::trees", keys = keys.len());
+                        let response = self_
+                            .send(&throttle, &TREE_METRICS, || {
+                                self_.client.trees(
+                                    self_.repo.clone(),
+                                    keys.clone(),
+                                    Some(TreeAttributes::all()),
+                                    None,
+                                )
+                            })
+                            .instrument(span)
+                            .await;
+                        TREE_METRICS.latency(start.elapsed());
+                        let adapter = self_.clone();
+                        response.map_or_else(
+                            |e| {
+                                TREE_METRICS.error(1);
+                                fetch_error(self_.request_error("trees", e))
+                            },
+                            |s| {
+                                Box::pin(s.entries.map(move |v| match v {
+                                    Ok(Ok(v)) => {
+                                        TREE_METRICS.hit(1);
+                                        TREE_METRICS
+                                            .bytes(v.data_unchecked().map_or(0, |d| d.len()));
+                                        Ok(v)
+                                    }
                                     // TODO: Separate out NotFound errors from EdenApi
                                     // TODO: We could eliminate this redundant key clone with a trait, I think.
-                                    Ok(Err(e)) => Err(FetchError::maybe_with_key(e.key.clone(), e)),
+                                    Ok(Err(e)) => {
+                                        TREE_METRICS.error(1);
+                                        let key = e.key.clone();
+                                        Err(FetchError::maybe_with_key(
+                                            key,
+                                            adapter.store_error("trees", e),
+                                        ))
+                                    }
                                     // TODO: What should happen when an entire batch fails?
-                                    Err(e) => Err(FetchError::from(e)),
+                                    Err(e) => {
+                                        TREE_METRICS.error(1);
+                                        Err(FetchError::from(adapter.request_error("trees", e)))
+                                    }
                                 })) as FetchStream<Key, TreeEntry>
-                            })
+                            },
+                        )
                     }
                 })
+                .buffer_unordered(concurrency)
                 .flatten(),
         )
     }
@@ -71,26 +334,240 @@ where
 #[async_trait]
 impl<C> ReadStore<Key, FileEntry> for EdenApiAdapter<C>
 where
-    C: EdenApi,
+    C: EdenApi + ?Sized,
 {
     async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, FileEntry> {
+        let concurrency = self.options.request_concurrency;
+        let throttle = Arc::new(Throttle::default());
         Box::pin(
-            keys.chunks_timeout(BATCH_SIZE, BATCH_TIMEOUT)
-                .then(move |keys| {
+            self.options
+                .requests(keys)
+                .map(move |keys| {
                     let self_ = self.clone();
+                    let throttle = throttle.clone();
                     async move {
-                        self_
-                            .client
-                            .files(self_.repo.clone(), keys, None)
-                            .await
-                            .map_or_else(fetch_error, |s| {
+                        FILE_METRICS.requested(keys.len());
+                        let start = Instant::now();
+                        let span = info_span!("EdenApiAdapter::files", keys = keys.len());
+                        let response = self_
+                            .send(&throttle, &FILE_METRICS, || {
+                                self_.client.files(self_.repo.clone(), keys.clone(), None)
+                            })
+                            .instrument(span)
+                            .await;
+                        FILE_METRICS.latency(start.elapsed());
+                        let adapter = self_.clone();
+                        response.map_or_else(
+                            |e| {
+                                FILE_METRICS.error(1);
+                                fetch_error(self_.request_error("files", e))
+                            },
+                            |s| {
                                 // TODO: Add per-item errors to EdenApi `files`
-                                Box::pin(s.entries.map(|v| v.map_err(FetchError::from)))
-                                    as FetchStream<Key, FileEntry>
+                                Box::pin(s.entries.map(move |v| match v {
+                                    Ok(v) => {
+                                        FILE_METRICS.hit(1);
+                                        FILE_METRICS.bytes(v.data_unchecked().len());
+                                        Ok(v)
+                                    }
+                                    Err(e) => {
+                                        FILE_METRICS.error(1);
+                                        Err(FetchError::from(adapter.request_error("files", e)))
+                                    }
+                                })) as FetchStream<Key, FileEntry>
+                            },
+                        )
+                    }
+                })
+                .buffer_unordered(concurrency)
+                .flatten(),
+        )
+    }
+}
+
+#[async_trait]
+impl<C> ReadStore<Key, FileAuxData> for EdenApiAdapter<C>
+where
+    C: EdenApi + ?Sized,
+{
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, FileAuxData> {
+        let concurrency = self.options.request_concurrency;
+        let throttle = Arc::new(Throttle::default());
+        Box::pin(
+            self.options
+                .requests(keys)
+                .map(move |keys| {
+                    let self_ = self.clone();
+                    let throttle = throttle.clone();
+                    async move {
+                        AUX_METRICS.requested(keys.len());
+                        let start = Instant::now();
+                        let span = info_span!("EdenApiAdapter::files_aux", keys = keys.len());
+                        let response = self_
+                            .send(&throttle, &AUX_METRICS, || {
+                                self_
+                                    .client
+                                    .files_aux(self_.repo.clone(), keys.clone(), None)
                             })
+                            .instrument(span)
+                            .await;
+                        AUX_METRICS.latency(start.elapsed());
+                        let adapter = self_.clone();
+                        response.map_or_else(
+                            |e| {
+                                AUX_METRICS.error(1);
+                                fetch_error(self_.request_error("files_aux", e))
+                            },
+                            |s| {
+                                Box::pin(s.entries.map(move |v| match v {
+                                    Ok(v) => {
+                                        AUX_METRICS.hit(1);
+                                        Ok(FileAuxData::from(v))
+                                    }
+                                    Err(e) => {
+                                        AUX_METRICS.error(1);
+                                        Err(FetchError::from(adapter.request_error("files_aux", e)))
+                                    }
+                                })) as FetchStream<Key, FileAuxData>
+                            },
+                        )
                     }
                 })
+                .buffer_unordered(concurrency)
                 .flatten(),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use maplit::hashmap;
+    use minibytes::Bytes;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use types::testutil::*;
+
+    use crate::testutil::FakeEdenApi;
+
+    #[test]
+    fn test_split_batch() {
+        let keys: Vec<_> = (1..=5).map(|i| key("a", &i.to_string())).collect();
+        assert_eq!(split_batch(keys.clone(), 5), vec![keys.clone()]);
+        assert_eq!(
+            split_batch(keys.clone(), 2),
+            vec![
+                keys[0..2].to_vec(),
+                keys[2..4].to_vec(),
+                keys[4..5].to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_requests() {
+        let options = EdenApiAdapterOptions {
+            batch_size: 4,
+            max_request_keys: 3,
+            ..Default::default()
+        };
+        let keys: Vec<_> = (1..=6).map(|i| key("a", &i.to_string())).collect();
+        let requests: Vec<_> =
+            block_on_stream(options.requests(Box::pin(stream::iter(keys.clone())))).collect();
+        assert_eq!(
+            requests,
+            vec![
+                keys[0..3].to_vec(),
+                keys[3..4].to_vec(),
+                keys[4..6].to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_request_error() {
+        let adapter = EdenApiAdapter {
+            client: Arc::new(FakeEdenApi::new()),
+            repo: "repo".to_string(),
+            options: Default::default(),
+        };
+        let k = key("a", "1");
+        let error = |status| {
+            let e = EdenApiError::HttpError {
+                status,
+                message: "error".to_string(),
+                retry_after: None,
+            };
+            FetchError::with_key(k.clone(), adapter.request_error("files", e))
+        };
+
+        let unavailable = error(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(unavailable.key(), Some(&k));
+        assert!(unavailable.is_retryable());
+        assert_eq!(
+            unavailable.store_error().map(|e| &e.store),
+            Some(&StoreId::EdenApi {
+                repo: "repo".to_string(),
+                endpoint: "files"
+            })
+        );
+        assert!(!error(StatusCode::BAD_REQUEST).is_retryable());
+        assert!(!FetchError::not_found(k).is_retryable());
+    }
+
+    #[test]
+    fn test_throttled() {
+        let k = key("a", "1");
+        let files: HashMap<Key, Bytes> = hashmap! { k.clone() => Bytes::from(&b"content"[..]) };
+        let fetch = |client: Arc<FakeEdenApi>, throttle_retries| {
+            let adapter = Arc::new(EdenApiAdapter {
+                client,
+                repo: "repo".to_string(),
+                options: EdenApiAdapterOptions {
+                    throttle_retries,
+                    ..Default::default()
+                },
+            });
+            let keys = Box::pin(stream::iter(vec![k.clone()]));
+            let fetched: Vec<Result<FileEntry, _>> =
+                block_on_stream(block_on(adapter.fetch_stream(keys))).collect();
+            fetched
+        };
+
+        // Throttled requests are retried after the delay the server asks for.
+        let client = FakeEdenApi::new()
+            .files(files.clone())
+            .throttled(2)
+            .into_arc();
+        let fetched = fetch(client.clone(), 5);
+        assert_eq!(fetched.len(), 1);
+        assert!(fetched[0].is_ok());
+        assert_eq!(client.requests(), 3);
+
+        // Until they have been retried too many times.
+        let client = FakeEdenApi::new().files(files).throttled(3).into_arc();
+        let fetched = fetch(client.clone(), 2);
+        assert_eq!(client.requests(), 3);
+        assert!(fetched[0].as_ref().unwrap_err().is_retryable());
+    }
+
+    #[test]
+    fn test_throttle_pause() {
+        let throttle = Throttle::default();
+        let warning = Duration::from_secs(10);
+        assert_eq!(throttle.pause(Duration::from_secs(6), warning), None);
+
+        // Overlapping pauses only count once.
+        assert_eq!(throttle.pause(Duration::from_secs(3), warning), None);
+        let paused = throttle
+            .pause(Duration::from_secs(11), warning)
+            .expect("no warning");
+        assert!(paused >= Duration::from_secs(11) && paused < Duration::from_secs(12));
+
+        // The warning is only given once.
+        assert_eq!(throttle.pause(Duration::from_secs(20), warning), None);
+    }
+}
@@ -8,15 +8,25 @@
 use std::convert::From;
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
+use anyhow::Result;
 use async_trait::async_trait;
-use futures::{channel::mpsc::channel, SinkExt, StreamExt, TryStreamExt};
-use tracing::error;
+use futures::{
+    channel::mpsc::{channel, unbounded},
+    future,
+    stream::{self, BoxStream},
+    SinkExt, StreamExt, TryStreamExt,
+};
+use futures_batch::ChunksTimeoutStreamExt;
+use tracing::{error, info_span, Instrument, Span};
 
+use configparser::config::ConfigSet;
 use streams::select_drop;
 
 use crate::newstore::{
-    BoxedReadStore, BoxedWriteStore, FetchError, FetchStream, KeyStream, ReadStore,
+    metrics::FetchMetrics, BoxedReadStore, BoxedWriteStore, FetchError, FetchStream, KeyStream,
+    ReadStore, WriteResults, WriteStore, WriteStream,
 };
 
 /// A combinator which queries a preferred store, then falls back to a fallback store
@@ -34,12 +44,115 @@ pub struct FallbackStore<K, VP, VF> {
     /// this will be the same as the preferred store.
     pub write_store: BoxedWriteStore<K, VP>,
 
-    /// If `write` is true, values read from the fallback store will be written to `write_store`
-    pub write: bool,
+    /// Controls whether and when values read from the fallback store are written to
+    /// `write_store`.
+    pub write_policy: WritePolicy,
+
+    /// Controls what happens to a value whose write to `write_store` failed.
+    pub write_error_policy: WriteErrorPolicy,
+
+    /// Controls how keys missing from the preferred store are sent to the fallback store.
+    pub fetch_options: FallbackFetchOptions,
+}
+
+/// When values read from the fallback store are written to the `write_store`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Values read from the fallback store are not written.
+    NoWrite,
+
+    /// Each batch of values read from the fallback store is written before it is returned, so
+    /// any value returned by the `FallbackStore` can then be read from `write_store`.
+    WriteThrough,
+
+    /// Values read from the fallback store are returned immediately and queued to be written
+    /// by a background task. Queued values may be lost if the process exits first.
+    WriteBack,
+}
+
+/// What to do with a value read from the fallback store when writing it to the `write_store`
+/// fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteErrorPolicy {
+    /// Log the error and return the value anyway.
+    Log,
+
+    /// Return the write error in place of the value. With `WritePolicy::WriteBack`, values are
+    /// returned before they are written, so write errors can only be logged.
+    Fail,
+}
+
+/// Batching and concurrency settings for fetches from the fallback store.
+#[derive(Clone, Debug)]
+pub struct FallbackFetchOptions {
+    /// Maximum number of missing keys sent to the fallback store in a single `fetch_stream` call.
+    pub batch_size: usize,
+
+    /// How long to wait for a batch to fill up before sending a partial batch.
+    pub batch_timeout: Duration,
+
+    /// Maximum number of batches being fetched from the fallback store at once.
+    pub concurrency: usize,
+
+    /// If `ordered_batches` is true, fallback results are returned in the order their batches
+    /// were sent, and in the order the fallback store returned them within each batch.
+    /// Otherwise the results of whichever batch completes first are returned first. Either way,
+    /// results from the preferred store are interleaved with them as they arrive, so the output
+    /// as a whole is not in the order the keys were requested.
+    pub ordered_batches: bool,
+}
+
+impl Default for FallbackFetchOptions {
+    fn default() -> Self {
+        FallbackFetchOptions {
+            batch_size: 100,
+            batch_timeout: Duration::from_millis(100),
+            concurrency: 4,
+            ordered_batches: false,
+        }
+    }
+}
+
+impl FallbackFetchOptions {
+    pub fn from_config(config: &ConfigSet) -> Result<Self> {
+        let mut options = FallbackFetchOptions::default();
+        if let Some(batch_size) = config.get_opt::<usize>("scmstore", "fallback-batch-size")? {
+            options.batch_size = batch_size.max(1);
+        }
+        if let Some(timeout) = config.get_opt::<u64>("scmstore", "fallback-batch-timeout-ms")? {
+            options.batch_timeout = Duration::from_millis(timeout);
+        }
+        if let Some(concurrency) = config.get_opt::<usize>("scmstore", "fallback-concurrency")? {
+            options.concurrency = concurrency.max(1);
+        }
+        if let Some(ordered) = config.get_opt::<bool>("scmstore", "fallback-ordered-batches")? {
+            options.ordered_batches = ordered;
+        }
+        Ok(options)
+    }
+
+    /// Options for prefetching, which sends many more keys at once than regular fetches and so
+    /// benefits from more batches in flight. The concurrency is read from
+    /// `scmstore.prefetch-concurrency`, and defaults to 4 times the regular fetch concurrency.
+    pub fn prefetch_from_config(config: &ConfigSet) -> Result<Self> {
+        let mut options = FallbackFetchOptions::from_config(config)?;
+        options.concurrency = match config.get_opt::<usize>("scmstore", "prefetch-concurrency")? {
+            Some(concurrency) => concurrency.max(1),
+            None => options.concurrency * 4,
+        };
+        Ok(options)
+    }
 }
 
 const CHANNEL_BUFFER: usize = 200;
 
+const METRICS: FetchMetrics = FetchMetrics::new("fallback");
+
+// Hits, misses and errors are counted separately for each of the two stores.
+const PREFERRED_METRICS: FetchMetrics = FetchMetrics::new("fallback.preferred");
+
+const FALLBACK_METRICS: FetchMetrics = FetchMetrics::new("fallback.fallback");
+
 #[async_trait]
 impl<K, VP, VF> ReadStore<K, VP> for FallbackStore<K, VP, VF>
 where
@@ -51,6 +164,8 @@ where
         // TODO(meyer): Write a custom Stream implementation to try to avoid use of channels
         let (sender, receiver) = channel(CHANNEL_BUFFER);
 
+        let keys = Box::pin(keys.inspect(|_| METRICS.requested(1)));
+
         let preferred_stream =
             self.preferred
                 .clone()
@@ -61,55 +176,181 @@ where
                     async move {
                         use FetchError::*;
                         match res {
-                            Ok(v) => Some(Ok(v)),
+                            Ok(v) => {
+                                PREFERRED_METRICS.hit(1);
+                                Some(Ok(v))
+                            }
                             // TODO(meyer): Looks like we aren't up to date with futures crate, missing "feed" method, which is probably better here.
                             // I think this might serialize the fallback stream as-written.
-                            Err(NotFound(k)) => match sender.send(k.clone()).await {
-                                Ok(()) => None,
-                                Err(e) => Some(Err(FetchError::with_key(k, e))),
-                            },
+                            Err(NotFound(k)) => {
+                                METRICS.fallback(1);
+                                match sender.send(k.clone()).await {
+                                    Ok(()) => None,
+                                    Err(e) => {
+                                        METRICS.error(1);
+                                        Some(Err(FetchError::with_key(k, e)))
+                                    }
+                                }
+                            }
                             // TODO(meyer): Should we also fall back on KeyedError, but also log an error?
-                            Err(e) => Some(Err(e)),
+                            Err(e) => {
+                                PREFERRED_METRICS.error(1);
+                                Some(Err(e))
+                            }
                         }
                     }
                 });
 
-        let (write_sender, write_receiver) = channel(CHANNEL_BUFFER);
-
-        let write_fallbacks = self.write;
-        let fallback_stream = self
-            .fallback
-            .clone()
-            .fetch_stream(Box::pin(receiver))
-            .await
-            .map_ok(|v| v.into())
-            .and_then(move |v: VP| {
-                let mut write_sender = write_sender.clone();
+        // Values are written in the background with `WriteBack`. The queue is unbounded so that
+        // slow writes never hold up fetches, and the writer stops once the queue is closed,
+        // i.e. once the returned stream is dropped.
+        let write_sender = if self.write_policy == WritePolicy::WriteBack {
+            let (write_sender, write_receiver) = unbounded();
+            let write_results = self
+                .write_store
+                .clone()
+                .write_stream(Box::pin(write_receiver))
+                .await;
+            tokio::spawn(write_results.for_each(|res| {
+                if let Err((key, e)) = res {
+                    METRICS.write_error(1);
+                    error!({ error = %e, key = ?key }, "error writing fallback value");
+                }
+                future::ready(())
+            }));
+            Some(write_sender)
+        } else {
+            None
+        };
+
+        // Each batch of missing keys is fetched to completion as its own `fetch_stream` call,
+        // so that up to `concurrency` batches are actually in flight at once rather than
+        // only having their (lazy) streams constructed concurrently.
+        let options = self.fetch_options.clone();
+        let fallback = self.fallback.clone();
+        let write_store = self.write_store.clone();
+        let write_through = self.write_policy == WritePolicy::WriteThrough;
+        let write_error_policy = self.write_error_policy;
+        let batches = receiver
+            .chunks_timeout(options.batch_size, options.batch_timeout)
+            .map(move |batch| {
+                let fallback = fallback.clone();
+                let write_store = write_store.clone();
+                let span = info_span!(
+                    "FallbackStore::fallback_batch",
+                    keys = batch.len(),
+                    hits = &0,
+                    misses = &0,
+                    errors = &0
+                );
                 async move {
-                    if write_fallbacks {
-                        if let Err(e) = write_sender.send(v.clone()).await {
-                            // TODO(meyer): Eventually add better tracing support to these traits. Each combinator should have a span, etc.
-                            // TODO(meyer): Update tracing? Looks like we don't have access to the most recent version of the macro syntax.
-                            error!({ error = %e }, "error writing fallback value to channel");
-                        }
+                    let (mut hits, mut misses, mut errors) = (0, 0, 0);
+                    let results = fallback
+                        .fetch_stream(Box::pin(stream::iter(batch)))
+                        .await
+                        .inspect(|res| match res {
+                            Ok(_) => hits += 1,
+                            Err(FetchError::NotFound(_)) => misses += 1,
+                            Err(_) => errors += 1,
+                        })
+                        .map_ok(VP::from)
+                        .collect::<Vec<_>>()
+                        .await;
+                    FALLBACK_METRICS.hit(hits);
+                    FALLBACK_METRICS.miss(misses);
+                    FALLBACK_METRICS.error(errors);
+                    let span = Span::current();
+                    span.record("hits", &hits);
+                    span.record("misses", &misses);
+                    span.record("errors", &errors);
+
+                    if write_through {
+                        write_batch(write_store, results, write_error_policy)
+                            .instrument(info_span!("FallbackStore::write_batch"))
+                            .await
+                    } else {
+                        results
                     }
-                    Ok(v)
                 }
+                .instrument(span)
             });
+        let fallback_results: BoxStream<'static, _> = if options.ordered_batches {
+            Box::pin(batches.buffered(options.concurrency))
+        } else {
+            Box::pin(batches.buffer_unordered(options.concurrency))
+        };
 
-        // TODO(meyer): This whole "fake filter map" approach to driving the write stream forward seems bad.
-        let write_results_null = self
-            .write_store
-            .clone()
-            .write_stream(Box::pin(write_receiver))
-            .await
-            // TODO(meyer): Don't swallow all write errors here.
-            .filter_map(|_res| futures::future::ready(None));
+        let fallback_stream = fallback_results
+            .flat_map(stream::iter)
+            .map_ok(move |v: VP| {
+                if let Some(write_sender) = write_sender.as_ref() {
+                    if let Err(e) = write_sender.unbounded_send(v.clone()) {
+                        METRICS.write_error(1);
+                        error!({ error = %e }, "error queueing fallback value for writing");
+                    }
+                }
+                v
+            });
 
         // TODO(meyer): Implement `select_all_drop` if we continue with this approach
-        Box::pin(select_drop(
-            preferred_stream,
-            select_drop(fallback_stream, write_results_null),
-        ))
+        Box::pin(select_drop(preferred_stream, fallback_stream))
     }
 }
+
+// Values written to a `FallbackStore` go to its `write_store`, so that a stack of stores can be
+// used for both reads and writes.
+#[async_trait]
+impl<K, VP, VF> WriteStore<K, VP> for FallbackStore<K, VP, VF>
+where
+    K: Send + Sync + 'static,
+    VF: Send + Sync + 'static,
+    VP: Send + Sync + 'static,
+{
+    async fn write_stream(self: Arc<Self>, values: WriteStream<VP>) -> WriteResults<K> {
+        self.write_store.clone().write_stream(values).await
+    }
+}
+
+/// Write the successfully fetched values of a batch to `write_store`, returning the batch once
+/// all the writes completed.
+async fn write_batch<K, V>(
+    write_store: BoxedWriteStore<K, V>,
+    results: Vec<Result<V, FetchError<K>>>,
+    write_error_policy: WriteErrorPolicy,
+) -> Vec<Result<V, FetchError<K>>>
+where
+    K: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    V: Send + Sync + Clone + 'static,
+{
+    let values: Vec<V> = results
+        .iter()
+        .filter_map(|res| res.as_ref().ok().cloned())
+        .collect();
+    let mut write_results = write_store
+        .write_stream(Box::pin(stream::iter(values)))
+        .await
+        .collect::<Vec<_>>()
+        .await
+        .into_iter();
+
+    // Write stores return one result per value, in order.
+    results
+        .into_iter()
+        .map(|res| {
+            let value = res?;
+            match write_results.next() {
+                Some(Err((key, e))) => {
+                    METRICS.write_error(1);
+                    match write_error_policy {
+                        WriteErrorPolicy::Log => {
+                            error!({ error = %e, key = ?key }, "error writing fallback value");
+                            Ok(value)
+                        }
+                        WriteErrorPolicy::Fail => Err(FetchError::maybe_with_key(key, e)),
+                    }
+                }
+                _ => Ok(value),
+            }
+        })
+        .collect()
+}
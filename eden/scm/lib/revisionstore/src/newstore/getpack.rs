@@ -0,0 +1,253 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A remote store for servers which don't expose EdenApi.
+//!
+//! Keys are fetched in batches with the legacy `getpackv2` wire protocol, through the repo's ssh
+//! or http peer. The peer writes the values it receives to a local store rather than returning
+//! them, so they are read back from that store once a batch is fetched.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::format_err;
+use async_trait::async_trait;
+use futures::{stream, FutureExt, StreamExt};
+use futures_batch::ChunksTimeoutStreamExt;
+use tokio::task::spawn_blocking;
+
+use configparser::config::ConfigSet;
+use types::Key;
+
+use crate::{
+    datastore::{HgIdMutableDeltaStore, RemoteDataStore, StoreResult},
+    indexedlogdatastore::Entry,
+    newstore::{metrics::FetchMetrics, FetchError, FetchStream, KeyStream, ReadStore},
+    types::StoreKey,
+};
+
+const METRICS: FetchMetrics = FetchMetrics::new("getpack");
+
+pub struct GetPackStore {
+    /// The peer's remote store, which fetches keys with `getpackv2` and writes them to `store`.
+    pub remote: Arc<dyn RemoteDataStore>,
+
+    /// Where `remote` writes the values it fetches, and where they are read back from.
+    pub store: Arc<dyn HgIdMutableDeltaStore>,
+
+    /// Maximum number of keys fetched at once.
+    pub batch_size: usize,
+
+    /// How long to wait for a batch to fill up before fetching a partial batch.
+    pub batch_timeout: Duration,
+}
+
+impl GetPackStore {
+    /// Fetch keys through `remote`, reading them back from `store`, in batches set by
+    /// `scmstore.getpack-batch-size` and `scmstore.getpack-batch-timeout-ms`.
+    pub fn new(
+        remote: Arc<dyn RemoteDataStore>,
+        store: Arc<dyn HgIdMutableDeltaStore>,
+        config: &ConfigSet,
+    ) -> anyhow::Result<Self> {
+        Ok(GetPackStore {
+            remote,
+            store,
+            batch_size: config
+                .get_or("scmstore", "getpack-batch-size", || 1000usize)?
+                .max(1),
+            batch_timeout: Duration::from_millis(config.get_or(
+                "scmstore",
+                "getpack-batch-timeout-ms",
+                || 100,
+            )?),
+        })
+    }
+
+    fn fetch_batch(&self, keys: Vec<Key>) -> Vec<Result<Entry, FetchError<Key>>> {
+        METRICS.requested(keys.len());
+        METRICS.request(1);
+        let store_keys: Vec<_> = keys.iter().cloned().map(StoreKey::hgid).collect();
+        // The peer reports the keys it didn't write to `store`, which are looked up anyway and
+        // reported as not found.
+        if let Err(e) = self.remote.prefetch(&store_keys) {
+            METRICS.error(keys.len());
+            return batch_error(keys, e);
+        }
+        keys.into_iter().map(|key| self.read(key)).collect()
+    }
+
+    fn read(&self, key: Key) -> Result<Entry, FetchError<Key>> {
+        use StoreResult::*;
+        let store_key = StoreKey::hgid(key.clone());
+        let content = match self.store.get(store_key.clone()) {
+            Ok(Found(content)) => content,
+            Ok(NotFound(_)) => {
+                METRICS.miss(1);
+                return Err(FetchError::not_found(key));
+            }
+            Err(e) => {
+                METRICS.error(1);
+                return Err(FetchError::with_key(key, e));
+            }
+        };
+        let metadata = match self.store.get_meta(store_key) {
+            Ok(Found(metadata)) => metadata,
+            Ok(NotFound(_)) => Default::default(),
+            Err(e) => {
+                METRICS.error(1);
+                return Err(FetchError::with_key(key, e));
+            }
+        };
+        METRICS.hit(1);
+        METRICS.bytes(content.len());
+        Ok(Entry::new(key, content.into(), metadata))
+    }
+}
+
+/// Fail every key of a batch with the error which failed the batch.
+fn batch_error(keys: Vec<Key>, e: impl Into<anyhow::Error>) -> Vec<Result<Entry, FetchError<Key>>> {
+    let message = format!("{:#}", e.into());
+    keys.into_iter()
+        .map(|key| Err(FetchError::with_key(key, format_err!("{}", message))))
+        .collect()
+}
+
+#[async_trait]
+impl ReadStore<Key, Entry> for GetPackStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+        Box::pin(
+            keys.chunks_timeout(self.batch_size, self.batch_timeout)
+                .then(move |batch| {
+                    let self_ = self.clone();
+                    let keys = batch.clone();
+                    spawn_blocking(move || self_.fetch_batch(batch)).map(move |spawn_res| {
+                        match spawn_res {
+                            Ok(results) => results,
+                            Err(e) => batch_error(keys, e),
+                        }
+                    })
+                })
+                .flat_map(stream::iter),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use anyhow::Result;
+    use maplit::hashmap;
+    use minibytes::Bytes;
+    use parking_lot::Mutex;
+    use tempfile::TempDir;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use types::testutil::*;
+
+    use crate::{
+        datastore::{Delta, HgIdDataStore, Metadata},
+        indexedlogdatastore::{IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+        localstore::{ExtStoredPolicy, LocalStore},
+    };
+
+    /// Writes the content it has for the requested keys to `store`, like the peer would.
+    struct FakePeer {
+        content: HashMap<Key, Bytes>,
+        store: Arc<dyn HgIdMutableDeltaStore>,
+        batches: Mutex<Vec<usize>>,
+    }
+
+    impl RemoteDataStore for FakePeer {
+        fn prefetch(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+            self.batches.lock().push(keys.len());
+            let mut missing = vec![];
+            for store_key in keys {
+                match store_key {
+                    StoreKey::HgId(key) if self.content.contains_key(key) => {
+                        let delta = Delta {
+                            data: self.content[key].clone(),
+                            base: None,
+                            key: key.clone(),
+                        };
+                        self.store.add(&delta, &Metadata::default())?;
+                    }
+                    _ => missing.push(store_key.clone()),
+                }
+            }
+            Ok(missing)
+        }
+
+        fn upload(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+            Ok(keys.to_vec())
+        }
+    }
+
+    impl HgIdDataStore for FakePeer {
+        fn get(&self, _key: StoreKey) -> Result<StoreResult<Vec<u8>>> {
+            unimplemented!()
+        }
+
+        fn get_meta(&self, _key: StoreKey) -> Result<StoreResult<Metadata>> {
+            unimplemented!()
+        }
+
+        fn refresh(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LocalStore for FakePeer {
+        fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+            Ok(keys.to_vec())
+        }
+    }
+
+    #[test]
+    fn test_getpack() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let store = Arc::new(IndexedLogHgIdDataStore::new(
+            tempdir.path().join("store"),
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?);
+        let (a, b, missing) = (key("a", "1"), key("b", "2"), key("c", "3"));
+        let peer = Arc::new(FakePeer {
+            content: hashmap! {
+                a.clone() => Bytes::from(&b"a"[..]),
+                b.clone() => Bytes::from(&b"b"[..]),
+            },
+            store: store.clone(),
+            batches: Mutex::new(vec![]),
+        });
+        let getpack = Arc::new(GetPackStore {
+            remote: peer.clone(),
+            store,
+            batch_size: 2,
+            batch_timeout: Duration::from_secs(1),
+        });
+
+        let keys = vec![a.clone(), b.clone(), missing.clone()];
+        let fetched: Vec<_> =
+            block_on_stream(block_on(getpack.fetch_stream(Box::pin(stream::iter(keys))))).collect();
+        assert_eq!(*peer.batches.lock(), vec![2, 1]);
+        assert_eq!(fetched.len(), 3);
+        assert_eq!(
+            fetched[0].as_ref().unwrap().clone().content()?.as_ref(),
+            b"a"
+        );
+        assert_eq!(fetched[1].as_ref().unwrap().key(), &b);
+        match &fetched[2] {
+            Err(FetchError::NotFound(key)) => assert_eq!(key, &missing),
+            _ => panic!("expected a missing key"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Fetch counters for the newstore combinators and adapters.
+//!
+//! Counters are reported through `hg_metrics`, so they end up in the blackbox
+//! and in `devel.print-metrics` output next to the other hg metrics, under
+//! `scmstore.<store>.<counter>`.
+
+use std::time::Duration;
+
+use hg_metrics::increment_counter;
+
+/// Upper bounds (in milliseconds) of the latency histogram buckets. Anything slower than
+/// the last bound is counted in the `inf` bucket.
+const LATENCY_BUCKETS_MS: &[u128] = &[1, 10, 100, 1000, 10000];
+
+#[derive(Clone, Debug)]
+pub struct FetchMetrics {
+    store: &'static str,
+}
+
+impl FetchMetrics {
+    pub const fn new(store: &'static str) -> Self {
+        FetchMetrics { store }
+    }
+
+    /// The name of the store, as it appears in counter names.
+    pub fn store(&self) -> &'static str {
+        self.store
+    }
+
+    fn increment(&self, counter: &str, value: usize) {
+        if value > 0 {
+            increment_counter(format!("scmstore.{}.{}", self.store, counter), value);
+        }
+    }
+
+    /// Keys requested from this store.
+    pub fn requested(&self, keys: usize) {
+        self.increment("requested", keys);
+    }
+
+    /// Keys successfully served by this store.
+    pub fn hit(&self, keys: usize) {
+        self.increment("hits", keys);
+    }
+
+    /// Keys this store reported as not found.
+    pub fn miss(&self, keys: usize) {
+        self.increment("misses", keys);
+    }
+
+    /// Keys which were sent on to a fallback store.
+    pub fn fallback(&self, keys: usize) {
+        self.increment("fallbacks", keys);
+    }
+
+    /// Keys which failed with an error other than "not found".
+    pub fn error(&self, keys: usize) {
+        self.increment("errors", keys);
+    }
+
+    /// Values which couldn't be written to a store.
+    pub fn write_error(&self, values: usize) {
+        self.increment("write_errors", values);
+    }
+
+    /// Requests issued to a remote store.
+    pub fn request(&self, requests: usize) {
+        self.increment("requests", requests);
+    }
+
+    /// Requests a remote store asked to retry later.
+    pub fn throttled(&self, requests: usize) {
+        self.increment("throttled", requests);
+    }
+
+    /// Content bytes served by this store.
+    pub fn bytes(&self, bytes: usize) {
+        self.increment("bytes", bytes);
+    }
+
+    /// Record the latency of a single lookup or request, both as a running total and
+    /// as a coarse histogram.
+    pub fn latency(&self, elapsed: Duration) {
+        let millis = elapsed.as_millis();
+        self.increment("time_ms", millis as usize);
+        let bucket = match LATENCY_BUCKETS_MS.iter().find(|bound| millis < **bound) {
+            Some(bound) => format!("latency_ms.lt_{}", bound),
+            None => "latency_ms.inf".to_string(),
+        };
+        increment_counter(format!("scmstore.{}.{}", self.store, bucket), 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hg_metrics::summarize;
+
+    fn counter(name: &str) -> Option<usize> {
+        summarize()
+            .into_iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v)
+    }
+
+    #[test]
+    fn test_fetch_metrics() {
+        let metrics = FetchMetrics::new("test_fetch_metrics");
+        metrics.requested(3);
+        metrics.hit(2);
+        metrics.miss(1);
+        metrics.miss(0);
+        metrics.latency(Duration::from_millis(50));
+        metrics.latency(Duration::from_secs(20));
+
+        assert_eq!(counter("scmstore.test_fetch_metrics.requested"), Some(3));
+        assert_eq!(counter("scmstore.test_fetch_metrics.hits"), Some(2));
+        assert_eq!(counter("scmstore.test_fetch_metrics.misses"), Some(1));
+        assert_eq!(counter("scmstore.test_fetch_metrics.errors"), None);
+        assert_eq!(counter("scmstore.test_fetch_metrics.time_ms"), Some(20050));
+        assert_eq!(
+            counter("scmstore.test_fetch_metrics.latency_ms.lt_100"),
+            Some(1)
+        );
+        assert_eq!(
+            counter("scmstore.test_fetch_metrics.latency_ms.inf"),
+            Some(1)
+        );
+    }
+}
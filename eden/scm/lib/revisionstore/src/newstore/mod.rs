@@ -5,7 +5,7 @@
  * GNU General Public License version 2.
  */
 
-use std::{fmt, sync::Arc};
+use std::{fmt, path::PathBuf, sync::Arc};
 
 use anyhow::Error;
 use async_trait::async_trait;
@@ -15,9 +15,24 @@ use futures::{
 };
 use thiserror::Error;
 
+pub mod auxdata;
+pub mod cancel;
+pub mod chain;
+pub mod chunked;
+pub mod contentaddressed;
+pub mod doctor;
 pub mod edenapi;
 pub mod fallback;
+pub mod getpack;
 pub mod legacy;
+pub mod metrics;
+pub mod multiplex;
+pub mod prefetch;
+pub mod progress;
+pub mod ratelimit;
+pub mod scmstore;
+pub mod singleflight;
+pub mod verify;
 
 /// A pinned, boxed stream of keys to fetch.
 pub type KeyStream<K> = BoxStream<'static, K>;
@@ -68,6 +83,128 @@ where
     pub fn from(err: impl Into<Error>) -> Self {
         FetchError::Other(err.into())
     }
+
+    /// The key which failed to be fetched, if known.
+    pub fn key(&self) -> Option<&K> {
+        match self {
+            FetchError::NotFound(key) | FetchError::KeyedError(key, _) => Some(key),
+            FetchError::Other(_) => None,
+        }
+    }
+
+    /// The error reported by the store which failed, if the store reported one.
+    pub fn store_error(&self) -> Option<&StoreError> {
+        match self {
+            FetchError::NotFound(_) => None,
+            FetchError::KeyedError(_, e) | FetchError::Other(e) => e.downcast_ref(),
+        }
+    }
+
+    /// Whether fetching the key again may succeed.
+    pub fn is_retryable(&self) -> bool {
+        self.store_error().map_or(false, |e| e.retryable)
+    }
+}
+
+/// Identifies the store an error originated from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StoreId {
+    /// An indexedlog store, by path.
+    IndexedLog(PathBuf),
+
+    /// An EdenApi endpoint.
+    EdenApi {
+        repo: String,
+        endpoint: &'static str,
+    },
+}
+
+impl fmt::Display for StoreId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreId::IndexedLog(path) => write!(f, "indexedlog store {}", path.display()),
+            StoreId::EdenApi { repo, endpoint } => {
+                write!(f, "EdenApi {} endpoint for repo {}", endpoint, repo)
+            }
+        }
+    }
+}
+
+/// An error reported by a store, recording which store failed and whether the failure is
+/// transient. Stores wrap their errors in it before returning them in a `FetchError`.
+#[derive(Debug, Error)]
+#[error("{store}: {error}")]
+pub struct StoreError {
+    pub store: StoreId,
+    pub retryable: bool,
+    pub error: Error,
+}
+
+impl StoreError {
+    pub fn new(store: StoreId, error: impl Into<Error>) -> Self {
+        StoreError {
+            store,
+            retryable: false,
+            error: error.into(),
+        }
+    }
+
+    pub fn retryable(mut self, retryable: bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+}
+
+/// How urgently the keys of a fetch are needed. Stores which queue requests, such as
+/// `RateLimitedStore`, serve higher priorities first, so that background work never delays the
+/// commands a user is waiting on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Speculative fetches, which can be dropped or delayed indefinitely.
+    Prefetch,
+
+    /// Bulk operations nobody is waiting on, such as repack.
+    Background,
+
+    /// Fetches a user is waiting on.
+    Interactive,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [
+        Priority::Prefetch,
+        Priority::Background,
+        Priority::Interactive,
+    ];
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Interactive
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Priority::Prefetch => write!(f, "prefetch"),
+            Priority::Background => write!(f, "background"),
+            Priority::Interactive => write!(f, "interactive"),
+        }
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefetch" => Ok(Priority::Prefetch),
+            "background" => Ok(Priority::Background),
+            "interactive" => Ok(Priority::Interactive),
+            _ => Err(anyhow::format_err!("invalid fetch priority '{}'", s)),
+        }
+    }
 }
 
 /// Transform an error into a single-item FetchStream
@@ -96,5 +233,7 @@ pub trait ReadStore<K: fmt::Display + fmt::Debug + Send + Sync + 'static, V: Sen
 pub trait WriteStore<K: Send + Sync + 'static, V: Send + Sync + 'static>:
     Send + Sync + 'static
 {
+    /// Write a stream of values to the underlying store. Exactly one result is returned per
+    /// value, in the order the values were received.
     async fn write_stream(self: Arc<Self>, values: WriteStream<V>) -> WriteResults<K>;
 }
@@ -0,0 +1,132 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A combinator which writes every value to several stores, the newstore counterpart of
+//! `MultiplexDeltaStore`. Commit and amend write new content to the local store and, optionally,
+//! to a cache, or to both the old and new store formats during a migration.
+
+use std::{fmt, sync::Arc};
+
+use anyhow::format_err;
+use async_trait::async_trait;
+use futures::{
+    channel::mpsc::{unbounded, UnboundedSender},
+    stream, StreamExt,
+};
+
+use crate::newstore::{BoxedWriteStore, WriteResults, WriteStore, WriteStream};
+
+pub struct MultiplexStore<K, V> {
+    pub stores: Vec<BoxedWriteStore<K, V>>,
+}
+
+#[async_trait]
+impl<K, V> WriteStore<K, V> for MultiplexStore<K, V>
+where
+    K: fmt::Debug + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    async fn write_stream(self: Arc<Self>, values: WriteStream<V>) -> WriteResults<K> {
+        let mut senders: Vec<UnboundedSender<V>> = Vec::with_capacity(self.stores.len());
+        let mut results: Vec<WriteResults<K>> = Vec::with_capacity(self.stores.len());
+        for store in self.stores.iter() {
+            let (sender, receiver) = unbounded();
+            senders.push(sender);
+            results.push(store.clone().write_stream(Box::pin(receiver)).await);
+        }
+
+        // Every store returns exactly one result per value, in order, so the results for a
+        // value are the next result of each store. The first error is returned.
+        Box::pin(stream::unfold(
+            (values, senders, results),
+            |(mut values, senders, mut results)| async move {
+                let value = values.next().await?;
+                for sender in senders.iter() {
+                    let _ = sender.unbounded_send(value.clone());
+                }
+                let mut combined = None;
+                for store_results in results.iter_mut() {
+                    let res = match store_results.next().await {
+                        Some(res) => res,
+                        None => Err((None, format_err!("write store ended early"))),
+                    };
+                    combined = match (combined, res) {
+                        (Some(Err(e)), _) => Some(Err(e)),
+                        (_, res) => Some(res),
+                    };
+                }
+                let combined = match combined {
+                    Some(res) => res,
+                    None => Err((None, format_err!("no store to write to"))),
+                };
+                Some((combined, (values, senders, results)))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+    use minibytes::Bytes;
+    use tempfile::TempDir;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use configparser::config::ConfigSet;
+    use types::{testutil::*, Key};
+
+    use crate::{
+        datastore::{HgIdDataStore, StoreResult},
+        indexedlogdatastore::{Entry, IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+        localstore::ExtStoredPolicy,
+        types::StoreKey,
+    };
+
+    #[test]
+    fn test_multiplex_write() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let open = |name: &str| -> Result<Arc<IndexedLogHgIdDataStore>> {
+            Ok(Arc::new(IndexedLogHgIdDataStore::new(
+                tempdir.path().join(name),
+                ExtStoredPolicy::Use,
+                &ConfigSet::new(),
+                IndexedLogDataStoreType::Local,
+            )?))
+        };
+        let (first, second) = (open("first")?, open("second")?);
+        let multiplex = Arc::new(MultiplexStore {
+            stores: vec![first.clone() as BoxedWriteStore<Key, Entry>, second.clone()],
+        });
+
+        let entries: Vec<_> = (1..=3)
+            .map(|i| {
+                Entry::new(
+                    key("a", &i.to_string()),
+                    Bytes::from(vec![i as u8; 4]),
+                    Default::default(),
+                )
+            })
+            .collect();
+        let written: Vec<_> = block_on_stream(block_on(
+            multiplex.write_stream(Box::pin(stream::iter(entries.clone()))),
+        ))
+        .collect();
+        assert_eq!(written.len(), 3);
+
+        for (res, mut entry) in written.into_iter().zip(entries) {
+            let key = res.map_err(|(_, e)| e)?;
+            assert_eq!(&key, entry.key());
+            let expected = StoreResult::Found(entry.content()?.as_ref().to_vec());
+            for store in &[&first, &second] {
+                assert_eq!(store.get(StoreKey::hgid(key.clone()))?, expected);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Prefetching through a newstore fetch stack.
+//!
+//! Prefetching only cares about values ending up in the caches the stack writes to, so the
+//! fetched values are dropped as soon as they are received rather than being collected.
+
+use std::fmt;
+
+use futures::{future, StreamExt};
+use tracing::warn;
+
+use crate::newstore::{BoxedReadStore, FetchError, KeyStream};
+
+/// Outcome of a prefetch.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrefetchStats {
+    /// Keys which were fetched.
+    pub fetched: usize,
+
+    /// Keys which weren't found by any store.
+    pub missing: usize,
+
+    /// Keys which failed with an error other than "not found".
+    pub errors: usize,
+}
+
+/// Drive `keys` through `store`, discarding the fetched values.
+pub async fn prefetch<K, V>(store: BoxedReadStore<K, V>, keys: KeyStream<K>) -> PrefetchStats
+where
+    K: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    store
+        .fetch_stream(keys)
+        .await
+        .fold(PrefetchStats::default(), |mut stats, res| {
+            match res {
+                Ok(_) => stats.fetched += 1,
+                Err(FetchError::NotFound(_)) => stats.missing += 1,
+                Err(e) => {
+                    warn!({ error = %e }, "prefetch failed");
+                    stats.errors += 1;
+                }
+            }
+            future::ready(stats)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use futures::stream;
+    use minibytes::Bytes;
+    use tempfile::TempDir;
+
+    use async_runtime::block_on_future as block_on;
+    use configparser::config::ConfigSet;
+    use types::{testutil::*, Key};
+
+    use crate::{
+        datastore::{Delta, HgIdMutableDeltaStore},
+        indexedlogdatastore::{Entry, IndexedLogDataStoreType, IndexedLogHgIdDataStore},
+        localstore::ExtStoredPolicy,
+    };
+
+    #[test]
+    fn test_prefetch_stats() -> anyhow::Result<()> {
+        let tempdir = TempDir::new()?;
+        let log = IndexedLogHgIdDataStore::new(
+            &tempdir,
+            ExtStoredPolicy::Use,
+            &ConfigSet::new(),
+            IndexedLogDataStoreType::Shared,
+        )?;
+        let delta = Delta {
+            data: Bytes::from(&[1, 2, 3, 4][..]),
+            base: None,
+            key: key("a", "1"),
+        };
+        log.add(&delta, &Default::default())?;
+        log.flush()?;
+
+        let store: BoxedReadStore<Key, Entry> = Arc::new(log);
+        let keys = vec![key("a", "1"), key("a", "2"), key("b", "3")];
+        let stats = block_on(prefetch(store, Box::pin(stream::iter(keys))));
+        assert_eq!(
+            stats,
+            PrefetchStats {
+                fetched: 1,
+                missing: 2,
+                errors: 0,
+            }
+        );
+        Ok(())
+    }
+}
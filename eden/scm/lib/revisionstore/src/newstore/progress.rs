@@ -0,0 +1,329 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Progress reporting for fetch streams, so that long checkouts and prefetches aren't silent.
+//!
+//! A `FetchProgress` owns a progress bar which is shown while at least one fetch through the
+//! stack is in progress. The `ProgressStore` at the top of the stack counts the keys requested
+//! and completed, and `ProgressStore`s around the stores values are downloaded from (memcache,
+//! EdenApi) report the bytes downloaded and where they came from.
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use parking_lot::Mutex;
+
+use edenapi_types::{FileEntry, TreeEntry};
+use progress::{ProgressBar, ProgressFactory, Unit};
+
+use crate::{
+    indexedlogdatastore::Entry,
+    newstore::{BoxedReadStore, FetchStream, KeyStream, ReadStore},
+};
+
+/// Minimum delay between two updates of the progress bar message.
+const MESSAGE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Values whose size is reported as downloaded bytes.
+pub trait FetchedBytes {
+    fn fetched_bytes(&self) -> u64;
+}
+
+impl FetchedBytes for Entry {
+    fn fetched_bytes(&self) -> u64 {
+        self.content_len() as u64
+    }
+}
+
+impl FetchedBytes for FileEntry {
+    fn fetched_bytes(&self) -> u64 {
+        self.data_unchecked().len() as u64
+    }
+}
+
+impl FetchedBytes for TreeEntry {
+    fn fetched_bytes(&self) -> u64 {
+        self.data_unchecked().map_or(0, |data| data.len()) as u64
+    }
+}
+
+#[derive(Default)]
+struct ProgressState {
+    bar: Option<Box<dyn ProgressBar>>,
+
+    /// Fetches in progress. The bar is dropped once they are all done.
+    fetches: usize,
+
+    total: u64,
+    completed: u64,
+    bytes: u64,
+    source: Option<&'static str>,
+    message_updated: Option<Instant>,
+}
+
+/// The progress bar of a fetch stack, shared by all the fetches through it.
+pub struct FetchProgress {
+    factory: Arc<dyn ProgressFactory>,
+    message: String,
+    unit: &'static str,
+    state: Mutex<ProgressState>,
+}
+
+impl FetchProgress {
+    /// Show the fetches on progress bars created by `factory`, with the given message and unit,
+    /// for instance "fetching files" and "files".
+    pub fn new(
+        factory: Arc<dyn ProgressFactory>,
+        message: impl ToString,
+        unit: &'static str,
+    ) -> Arc<Self> {
+        Arc::new(FetchProgress {
+            factory,
+            message: message.to_string(),
+            unit,
+            state: Mutex::new(ProgressState::default()),
+        })
+    }
+
+    fn start(self: &Arc<Self>) -> FetchGuard {
+        let mut state = self.state.lock();
+        state.fetches += 1;
+        if state.bar.is_none() {
+            // Failing to show progress shouldn't fail the fetch.
+            state.bar = self
+                .factory
+                .bar(&self.message, Some(0), Unit::Named(self.unit))
+                .ok();
+        }
+        FetchGuard(self.clone())
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock();
+        state.fetches -= 1;
+        if state.fetches == 0 {
+            *state = ProgressState::default();
+        }
+    }
+
+    fn requested(&self, keys: u64) {
+        let mut state = self.state.lock();
+        state.total += keys;
+        if let Some(bar) = state.bar.as_ref() {
+            let _ = bar.set_total(Some(state.total));
+        }
+    }
+
+    fn completed(&self, keys: u64) {
+        let mut state = self.state.lock();
+        state.completed += keys;
+        if let Some(bar) = state.bar.as_ref() {
+            let _ = bar.set(state.completed);
+        }
+    }
+
+    fn fetched(&self, source: &'static str, bytes: u64) {
+        let mut state = self.state.lock();
+        state.bytes += bytes;
+        let changed_source = state.source != Some(source);
+        state.source = Some(source);
+        let due = state
+            .message_updated
+            .map_or(true, |updated| updated.elapsed() >= MESSAGE_INTERVAL);
+        if changed_source || due {
+            state.message_updated = Some(Instant::now());
+            let message = format!(
+                "{} from {} ({:.1} MB)",
+                self.message,
+                source,
+                state.bytes as f64 / 1_000_000.0
+            );
+            if let Some(bar) = state.bar.as_ref() {
+                let _ = bar.set_message(&message);
+            }
+        }
+    }
+}
+
+/// Marks a fetch as in progress until it is dropped.
+struct FetchGuard(Arc<FetchProgress>);
+
+impl Drop for FetchGuard {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+pub struct ProgressStore<K, V> {
+    pub store: BoxedReadStore<K, V>,
+    pub progress: Arc<FetchProgress>,
+
+    /// The name of the store values are downloaded from, for stores inside the stack, which
+    /// report downloaded bytes. `None` at the top of the stack, which counts the keys requested
+    /// and completed.
+    pub source: Option<&'static str>,
+}
+
+#[async_trait]
+impl<K, V> ReadStore<K, V> for ProgressStore<K, V>
+where
+    K: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    V: FetchedBytes + Send + Sync + 'static,
+{
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<K>) -> FetchStream<K, V> {
+        let progress = self.progress.clone();
+        match self.source {
+            Some(source) => Box::pin(self.store.clone().fetch_stream(keys).await.inspect(
+                move |res| {
+                    if let Ok(value) = res {
+                        progress.fetched(source, value.fetched_bytes());
+                    }
+                },
+            )),
+            None => {
+                let guard = progress.start();
+                let requested = progress.clone();
+                let keys = keys.inspect(move |_| requested.requested(1));
+                Box::pin(
+                    self.store
+                        .clone()
+                        .fetch_stream(Box::pin(keys))
+                        .await
+                        .inspect(move |_| guard.0.completed(1)),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+    use futures::stream;
+    use minibytes::Bytes;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use progress::ProgressSpinner;
+    use types::{testutil::*, Key};
+
+    use crate::newstore::FetchError;
+
+    /// Records the state of the last progress bar it created.
+    #[derive(Default)]
+    struct TestProgress {
+        state: Arc<Mutex<(u64, Option<u64>, String)>>,
+    }
+
+    struct TestBar(Arc<Mutex<(u64, Option<u64>, String)>>);
+
+    impl ProgressFactory for TestProgress {
+        fn bar(
+            &self,
+            message: &str,
+            total: Option<u64>,
+            _unit: Unit,
+        ) -> Result<Box<dyn ProgressBar>> {
+            *self.state.lock() = (0, total, message.to_string());
+            Ok(Box::new(TestBar(self.state.clone())))
+        }
+
+        fn spinner(&self, _message: &str) -> Result<Box<dyn ProgressSpinner>> {
+            unimplemented!()
+        }
+    }
+
+    impl ProgressBar for TestBar {
+        fn position(&self) -> Result<u64> {
+            Ok(self.0.lock().0)
+        }
+
+        fn total(&self) -> Result<Option<u64>> {
+            Ok(self.0.lock().1)
+        }
+
+        fn set(&self, pos: u64) -> Result<()> {
+            self.0.lock().0 = pos;
+            Ok(())
+        }
+
+        fn set_total(&self, total: Option<u64>) -> Result<()> {
+            self.0.lock().1 = total;
+            Ok(())
+        }
+
+        fn increment(&self, delta: u64) -> Result<()> {
+            self.0.lock().0 += delta;
+            Ok(())
+        }
+
+        fn set_message(&self, message: &str) -> Result<()> {
+            self.0.lock().2 = message.to_string();
+            Ok(())
+        }
+    }
+
+    /// Returns a 600KB entry for every key but "z".
+    struct Remote;
+
+    #[async_trait]
+    impl ReadStore<Key, Entry> for Remote {
+        async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+            Box::pin(keys.map(|key| {
+                if key == key_for("z") {
+                    Err(FetchError::not_found(key))
+                } else {
+                    Ok(Entry::new(
+                        key,
+                        Bytes::from(vec![0; 600_000]),
+                        Default::default(),
+                    ))
+                }
+            }))
+        }
+    }
+
+    fn key_for(path: &str) -> Key {
+        key(path, "1")
+    }
+
+    #[test]
+    fn test_progress() {
+        let factory = TestProgress::default();
+        let state = factory.state.clone();
+        let progress = FetchProgress::new(Arc::new(factory), "fetching files", "files");
+        let remote = Arc::new(ProgressStore {
+            store: Arc::new(Remote) as BoxedReadStore<Key, Entry>,
+            progress: progress.clone(),
+            source: Some("edenapi"),
+        });
+        let store = Arc::new(ProgressStore {
+            store: remote as BoxedReadStore<Key, Entry>,
+            progress: progress.clone(),
+            source: None,
+        });
+
+        let keys = vec![key_for("a"), key_for("b"), key_for("z")];
+        let fetched: Vec<_> =
+            block_on_stream(block_on(store.fetch_stream(Box::pin(stream::iter(keys))))).collect();
+        assert_eq!(fetched.iter().filter(|res| res.is_ok()).count(), 2);
+
+        // Missing keys are completed too.
+        let (position, total, message) = state.lock().clone();
+        assert_eq!((position, total), (3, Some(3)));
+        assert_eq!(message, "fetching files from edenapi (0.6 MB)");
+
+        // The bar is dropped once the fetch is done.
+        assert!(progress.state.lock().bar.is_none());
+    }
+}
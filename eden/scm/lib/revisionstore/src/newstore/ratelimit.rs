@@ -0,0 +1,511 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A combinator which caps the rate and concurrency of the requests sent to a remote store.
+//!
+//! Keys are sent to the underlying store in batches, each of which is a request. All the stores
+//! sharing a `RateLimiter` (usually every store in front of the same remote) share its limits,
+//! so mass operations such as repack or history backfills can't overload the remote.
+//!
+//! Requests are admitted by `Priority`: a request waits while requests of a higher priority are
+//! waiting for the same limiter, so background load is shed first. Each process usually fetches
+//! at a single priority, so limiters in front of a shared cache also wait for the requests of a
+//! higher priority of the other processes using the cache, such as a prefetch daemon yielding to
+//! an interactive `hg status`.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use fs2::FileExt;
+use futures::{stream, StreamExt};
+use futures_batch::ChunksTimeoutStreamExt;
+use parking_lot::Mutex;
+use tokio::{
+    sync::{Notify, OwnedSemaphorePermit, Semaphore},
+    time::{sleep, sleep_until, Instant},
+};
+
+use configparser::config::ConfigSet;
+use util::path::create_shared_dir;
+
+use crate::newstore::{
+    metrics::FetchMetrics, BoxedReadStore, FetchStream, KeyStream, Priority, ReadStore,
+};
+
+/// Maximum number of requests a single fetch waits on at once when concurrency isn't capped.
+const MAX_BATCHES: usize = 16;
+
+/// How often a request checks whether the requests of a higher priority of other processes are
+/// done.
+const SHARED_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const METRICS: FetchMetrics = FetchMetrics::new("ratelimit");
+
+/// Rate and concurrency limits shared by all the stores in front of a remote. Cloning a
+/// `RateLimiter` shares its limits.
+#[derive(Clone)]
+pub struct RateLimiter {
+    /// Minimum delay between the start of two requests.
+    interval: Option<Duration>,
+
+    /// When the next request may start.
+    next: Arc<Mutex<Instant>>,
+
+    /// Permits for the requests in flight.
+    permits: Option<Arc<Semaphore>>,
+
+    /// Maximum number of requests a single fetch has in flight at once.
+    concurrency: usize,
+
+    /// Maximum number of keys sent in a single request.
+    batch_size: usize,
+
+    /// How long to wait for a batch to fill up before sending a partial batch.
+    batch_timeout: Duration,
+
+    /// The requests waiting to start, by priority.
+    waiting: Arc<Waiting>,
+
+    /// The requests of the other processes sharing the limits, if any.
+    shared: Option<SharedWaiting>,
+}
+
+/// Counts the requests waiting for a `RateLimiter` at each priority.
+#[derive(Default)]
+struct Waiting {
+    counts: [AtomicUsize; Priority::ALL.len()],
+    notify: Notify,
+}
+
+impl Waiting {
+    /// Whether any request of a higher priority than `priority` is waiting.
+    fn outranked(&self, priority: Priority) -> bool {
+        Priority::ALL
+            .iter()
+            .filter(|p| **p > priority)
+            .any(|p| self.counts[*p as usize].load(Ordering::SeqCst) > 0)
+    }
+
+    /// Wait until no request of a higher priority than `priority` is waiting.
+    async fn yield_to_higher(&self, priority: Priority) {
+        loop {
+            // Created before checking, so that a wakeup between the check and the wait isn't lost.
+            let notified = self.notify.notified();
+            if !self.outranked(priority) {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Registers a request as waiting until it is dropped.
+struct WaitingGuard<'a> {
+    waiting: &'a Waiting,
+    priority: Priority,
+}
+
+impl<'a> WaitingGuard<'a> {
+    fn new(waiting: &'a Waiting, priority: Priority) -> Self {
+        waiting.counts[priority as usize].fetch_add(1, Ordering::SeqCst);
+        WaitingGuard { waiting, priority }
+    }
+}
+
+impl<'a> Drop for WaitingGuard<'a> {
+    fn drop(&mut self) {
+        self.waiting.counts[self.priority as usize].fetch_sub(1, Ordering::SeqCst);
+        self.waiting.notify.notify_waiters();
+    }
+}
+
+/// Lock files through which the limiters of several processes order their requests. A request
+/// holds a shared lock on the file of its priority while it waits and while it is in flight, and
+/// a file is only free to lock exclusively when no request of its priority is.
+#[derive(Clone)]
+struct SharedWaiting {
+    dir: PathBuf,
+    name: String,
+}
+
+impl SharedWaiting {
+    fn open(&self, priority: Priority) -> Result<File> {
+        create_shared_dir(&self.dir)?;
+        let path = self.dir.join(format!("{}.{}", self.name, priority));
+        Ok(OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?)
+    }
+
+    /// Registers a request as waiting or in flight until the returned file is closed.
+    fn register(&self, priority: Priority) -> Result<File> {
+        let file = self.open(priority)?;
+        // Only blocks while another process checks whether the file is locked.
+        file.lock_shared()?;
+        Ok(file)
+    }
+
+    /// Whether a request of a higher priority than `priority` is waiting or in flight.
+    fn outranked(&self, priority: Priority) -> Result<bool> {
+        for higher in Priority::ALL.iter().filter(|p| **p > priority) {
+            let file = self.open(*higher)?;
+            match file.try_lock_exclusive() {
+                Ok(()) => file.unlock()?,
+                Err(e) if e.kind() == fs2::lock_contended_error().kind() => return Ok(true),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Wait until no request of a higher priority than `priority` is waiting or in flight.
+    async fn yield_to_higher(&self, priority: Priority) -> Result<()> {
+        while self.outranked(priority)? {
+            sleep(SHARED_POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+}
+
+/// Holds the admission of a request by a `RateLimiter` until the request is done.
+struct Admission {
+    _permit: Option<OwnedSemaphorePermit>,
+    _shared: Option<File>,
+}
+
+impl RateLimiter {
+    /// Allow at most `qps` requests to start per second, and at most `concurrency` requests in
+    /// flight at once. Either limit can be left out.
+    pub fn new(qps: Option<u32>, concurrency: Option<usize>) -> Self {
+        RateLimiter {
+            interval: qps.map(|qps| Duration::from_secs(1) / qps.max(1)),
+            next: Arc::new(Mutex::new(Instant::now())),
+            permits: concurrency.map(|concurrency| Arc::new(Semaphore::new(concurrency.max(1)))),
+            concurrency: concurrency.map_or(MAX_BATCHES, |concurrency| concurrency.max(1)),
+            batch_size: 100,
+            batch_timeout: Duration::from_millis(100),
+            waiting: Arc::new(Waiting::default()),
+            shared: None,
+        }
+    }
+
+    /// Order the requests by priority with those of the other limiters sharing `dir`, usually
+    /// those of the other processes using the same cache, in front of the remote `name`.
+    pub fn shared(mut self, dir: PathBuf, name: &str) -> Self {
+        self.shared = Some(SharedWaiting {
+            dir,
+            name: name.to_string(),
+        });
+        self
+    }
+
+    /// Override the number of keys sent in a single request.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Read the limits of the remote `name` from the `scmstore.ratelimit.<name>.qps`,
+    /// `scmstore.ratelimit.<name>.concurrency` and `scmstore.ratelimit.<name>.batch-size`
+    /// configs. Returns `None` if no limit is configured, or if `scmstore.ratelimit.bypass` is
+    /// set, which commands can set to run without limits. Requests are ordered with those of the
+    /// other processes using the cache at `remotefilelog.cachepath`, if set.
+    pub fn from_config(config: &ConfigSet, name: &str) -> Result<Option<Self>> {
+        if config.get_or_default::<bool>("scmstore", "ratelimit.bypass")? {
+            return Ok(None);
+        }
+        let qps = config.get_opt::<u32>("scmstore", &format!("ratelimit.{}.qps", name))?;
+        let concurrency =
+            config.get_opt::<usize>("scmstore", &format!("ratelimit.{}.concurrency", name))?;
+        if qps.is_none() && concurrency.is_none() {
+            return Ok(None);
+        }
+        let mut limiter = RateLimiter::new(qps, concurrency);
+        if let Some(batch_size) =
+            config.get_opt::<usize>("scmstore", &format!("ratelimit.{}.batch-size", name))?
+        {
+            limiter = limiter.batch_size(batch_size);
+        }
+        if let Some(cachepath) = config.get_opt::<PathBuf>("remotefilelog", "cachepath")? {
+            limiter = limiter.shared(cachepath.join("ratelimit"), name);
+        }
+        Ok(Some(limiter))
+    }
+
+    /// Wait until a request of the given priority is allowed to start. The request is in flight
+    /// until the returned admission is dropped.
+    async fn acquire(&self, priority: Priority) -> Admission {
+        let start = Instant::now();
+        let _waiting = WaitingGuard::new(&self.waiting, priority);
+        // Failing to use the lock files only loses the ordering with the other processes.
+        let shared = match self.shared.as_ref() {
+            Some(shared) => match shared.register(priority) {
+                Ok(file) => shared.yield_to_higher(priority).await.ok().map(|()| file),
+                Err(_) => None,
+            },
+            None => None,
+        };
+        let permit = loop {
+            self.waiting.yield_to_higher(priority).await;
+            let permit = match self.permits.as_ref() {
+                Some(permits) => permits.clone().acquire_owned().await.ok(),
+                None => None,
+            };
+            // Semaphore permits are granted in order, so a higher priority request may have
+            // started waiting while this one was queued. Hand the permit over to it.
+            if !self.waiting.outranked(priority) {
+                break permit;
+            }
+        };
+        if let Some(interval) = self.interval {
+            let next = {
+                let mut next = self.next.lock();
+                let allowed = (*next).max(Instant::now());
+                *next = allowed + interval;
+                allowed
+            };
+            sleep_until(next).await;
+        }
+        METRICS.latency(start.elapsed());
+        Admission {
+            _permit: permit,
+            _shared: shared,
+        }
+    }
+}
+
+pub struct RateLimitedStore<K, V> {
+    pub store: BoxedReadStore<K, V>,
+    pub limiter: RateLimiter,
+    pub priority: Priority,
+}
+
+#[async_trait]
+impl<K, V> ReadStore<K, V> for RateLimitedStore<K, V>
+where
+    K: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<K>) -> FetchStream<K, V> {
+        let limiter = self.limiter.clone();
+        let concurrency = limiter.concurrency;
+        let priority = self.priority;
+
+        // Each batch is fetched to completion while holding its permit, so that the permit
+        // actually covers the request rather than only the construction of its stream.
+        Box::pin(
+            keys.chunks_timeout(limiter.batch_size, limiter.batch_timeout)
+                .map(move |batch| {
+                    let store = self.store.clone();
+                    let limiter = limiter.clone();
+                    async move {
+                        METRICS.requested(batch.len());
+                        METRICS.request(1);
+                        let _admission = limiter.acquire(priority).await;
+                        store
+                            .fetch_stream(Box::pin(stream::iter(batch)))
+                            .await
+                            .collect::<Vec<_>>()
+                            .await
+                    }
+                })
+                .buffer_unordered(concurrency)
+                .flat_map(stream::iter),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future;
+    use minibytes::Bytes;
+
+    use async_runtime::block_on_future as block_on;
+    use types::{testutil::*, Key};
+
+    use crate::indexedlogdatastore::Entry;
+
+    /// Returns every key after a delay, tracking the largest number of requests in flight and
+    /// the order keys were served in.
+    #[derive(Default)]
+    struct SlowStore {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+        served: Mutex<Vec<Key>>,
+    }
+
+    #[async_trait]
+    impl ReadStore<Key, Entry> for SlowStore {
+        async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Box::pin(keys.map(move |key| {
+                self.served.lock().push(key.clone());
+                Ok(Entry::new(key, Bytes::new(), Default::default()))
+            }))
+        }
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let underlying = Arc::new(SlowStore::default());
+        let limiter = RateLimiter::new(Some(50), Some(1)).batch_size(1);
+        let fetch = |keys: Vec<Key>| {
+            let store = Arc::new(RateLimitedStore {
+                store: underlying.clone() as BoxedReadStore<Key, Entry>,
+                limiter: limiter.clone(),
+                priority: Priority::Interactive,
+            });
+            async move {
+                store
+                    .fetch_stream(Box::pin(stream::iter(keys)))
+                    .await
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        let start = std::time::Instant::now();
+        let (first, second) = block_on(future::join(
+            fetch(vec![key("a", "1"), key("b", "2")]),
+            fetch(vec![key("c", "3"), key("d", "4")]),
+        ));
+        assert_eq!(first.len() + second.len(), 4);
+        assert!(first.iter().chain(second.iter()).all(|res| res.is_ok()));
+
+        // The limits are shared by both fetches: 4 requests, one at a time, 20ms apart.
+        assert_eq!(underlying.max_in_flight.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn test_priority() {
+        let underlying = Arc::new(SlowStore::default());
+        let limiter = RateLimiter::new(None, Some(1)).batch_size(1);
+        let fetch = |keys: Vec<Key>, priority: Priority| {
+            let store = Arc::new(RateLimitedStore {
+                store: underlying.clone() as BoxedReadStore<Key, Entry>,
+                limiter: limiter.clone(),
+                priority,
+            });
+            async move {
+                store
+                    .fetch_stream(Box::pin(stream::iter(keys)))
+                    .await
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        // Several background fetches queue up for the only permit, then an interactive fetch
+        // arrives while the first background request is in flight.
+        let background: Vec<_> = (1..=3)
+            .map(|i| fetch(vec![key("bg", &i.to_string())], Priority::Background))
+            .collect();
+        let interactive = fetch(vec![key("fg", "1")], Priority::Interactive);
+        let interactive = async move {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            interactive.await
+        };
+        block_on(future::join(future::join_all(background), interactive));
+
+        // The interactive request is served as soon as the permit is released.
+        let served = underlying.served.lock().clone();
+        assert_eq!(served.len(), 4);
+        assert_eq!(served[1], key("fg", "1"));
+    }
+
+    #[test]
+    fn test_shared_priority() -> Result<()> {
+        let tempdir = tempfile::TempDir::new()?;
+        let underlying = Arc::new(SlowStore::default());
+        // Separate limiters, as in separate processes, which only share the lock files.
+        let fetch = |k: Key, priority: Priority| {
+            let store = Arc::new(RateLimitedStore {
+                store: underlying.clone() as BoxedReadStore<Key, Entry>,
+                limiter: RateLimiter::new(None, None)
+                    .shared(tempdir.path().join("ratelimit"), "edenapi"),
+                priority,
+            });
+            async move {
+                store
+                    .fetch_stream(Box::pin(stream::iter(vec![k])))
+                    .await
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        let interactive = fetch(key("fg", "1"), Priority::Interactive);
+        let background = fetch(key("bg", "1"), Priority::Background);
+        let background = async move {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            background.await
+        };
+        block_on(future::join(interactive, background));
+
+        // The background request only started once the interactive one was done.
+        assert_eq!(underlying.max_in_flight.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            underlying.served.lock().clone(),
+            vec![key("fg", "1"), key("bg", "1")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_priority_order() -> Result<()> {
+        assert!(Priority::Interactive > Priority::Background);
+        assert!(Priority::Background > Priority::Prefetch);
+        assert_eq!(Priority::default(), Priority::Interactive);
+        assert_eq!("background".parse::<Priority>()?, Priority::Background);
+        assert!("urgent".parse::<Priority>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limit_config() -> Result<()> {
+        let mut config = ConfigSet::new();
+        assert!(RateLimiter::from_config(&config, "edenapi")?.is_none());
+
+        config.set(
+            "scmstore",
+            "ratelimit.edenapi.qps",
+            Some("10"),
+            &Default::default(),
+        );
+        let limiter = RateLimiter::from_config(&config, "edenapi")?.expect("no limiter");
+        assert_eq!(limiter.interval, Some(Duration::from_millis(100)));
+        assert!(limiter.permits.is_none());
+        assert!(RateLimiter::from_config(&config, "memcache")?.is_none());
+
+        config.set(
+            "scmstore",
+            "ratelimit.bypass",
+            Some("true"),
+            &Default::default(),
+        );
+        assert!(RateLimiter::from_config(&config, "edenapi")?.is_none());
+        Ok(())
+    }
+}
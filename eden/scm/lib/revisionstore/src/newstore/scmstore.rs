@@ -0,0 +1,514 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Construction of the full newstore fetch stack used in place of the legacy `ContentStore`.
+//!
+//! The stack queries, in order, the local indexedlog store, the shared indexedlog cache, the
+//! shared LFS store, memcache and finally EdenApi. Values fetched from memcache or EdenApi are
+//! written back to the shared cache, and values fetched from EdenApi are also written to
+//! memcache. Against servers which don't expose EdenApi, the legacy getpack protocol takes its
+//! place. Concurrent fetches of the same key from memcache or EdenApi are coalesced, including
+//! across clones of the same `ScmStoreBuilder`.
+//!
+//! In offline mode (`scmstore.offline`), memcache and EdenApi are left out of the stack, and keys
+//! which would have been fetched from them are reported as `RequiresNetwork` errors.
+//!
+//! Requests to the remote store can be rate limited (`scmstore.ratelimit.edenapi.*`), with the
+//! limits shared across clones of the same `ScmStoreBuilder`. Keys the remote store recently
+//! didn't have can be recorded in a `NegativeCache`, so that they aren't requested again.
+//!
+//! Fetches through the stack stop when the process is interrupted, when a `CancellationToken`
+//! is cancelled, or when they run longer than `scmstore.fetch-timeout-ms`, aborting the requests
+//! in flight.
+//!
+//! The same stack can also serve the aux data (size and content hashes) of files, which is cached
+//! in an `IndexedLogAuxStore`, computed from the content stored locally, or otherwise fetched from
+//! the remote aux data store without fetching the content.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::StreamExt;
+
+use configparser::config::ConfigSet;
+use edenapi_types::{FileEntry, TreeEntry};
+use progress::ProgressFactory;
+use types::Key;
+
+use crate::{
+    error::RequiresNetwork,
+    historystore::HgIdHistoryStore,
+    indexedlogauxstore::IndexedLogAuxStore,
+    indexedlogdatastore::{Entry, IndexedLogHgIdDataStore},
+    lfs::LfsStore,
+    memcache::MemcacheStore,
+    negativecache::{NegativeCache, NegativeCacheStore},
+    newstore::{
+        auxdata::{ComputedAuxStore, FileAuxData},
+        cancel::{CancellableStore, CancellationToken},
+        fallback::{FallbackFetchOptions, FallbackStore, WriteErrorPolicy, WritePolicy},
+        getpack::GetPackStore,
+        legacy::LegacyDatastore,
+        progress::{FetchProgress, ProgressStore},
+        ratelimit::{RateLimitedStore, RateLimiter},
+        singleflight::{InFlight, SingleFlightStore},
+        verify::VerifyingStore,
+        BoxedReadStore, BoxedWriteStore, FetchError, FetchStream, KeyStream, Priority, ReadStore,
+        WriteResults, WriteStore, WriteStream,
+    },
+    types::StoreKey,
+};
+
+/// Returns true if the `scmstore.enabled` config routes `ContentStore` fetches through the
+/// newstore stack.
+pub fn scmstore_enabled(config: &ConfigSet) -> Result<bool> {
+    Ok(config.get_or_default::<bool>("scmstore", "enabled")?)
+}
+
+/// Returns true if the `scmstore.offline` config disables all network fetches.
+pub fn scmstore_offline(config: &ConfigSet) -> Result<bool> {
+    Ok(config.get_or_default::<bool>("scmstore", "offline")?)
+}
+
+/// The remote store at the bottom of the stack.
+#[derive(Clone)]
+enum Remote {
+    Files(BoxedReadStore<Key, FileEntry>),
+    Trees(BoxedReadStore<Key, TreeEntry>),
+
+    /// A `GetPackStore`, which writes the values it fetches to the shared cache itself.
+    GetPack(BoxedReadStore<Key, Entry>),
+}
+
+#[derive(Clone)]
+pub struct ScmStoreBuilder {
+    shared: Arc<IndexedLogHgIdDataStore>,
+    local: Option<Arc<IndexedLogHgIdDataStore>>,
+    lfs: Option<Arc<LfsStore>>,
+    memcache: Option<Arc<MemcacheStore>>,
+    remote: Option<Remote>,
+    aux_remote: Option<BoxedReadStore<Key, FileAuxData>>,
+    write_store: Option<BoxedWriteStore<Key, Entry>>,
+    fetch_options: FallbackFetchOptions,
+    in_flight: InFlight,
+    history: Option<Arc<dyn HgIdHistoryStore>>,
+    offline: bool,
+    rate_limiter: Option<RateLimiter>,
+    priority: Priority,
+    negative_cache: Option<Arc<NegativeCache>>,
+    progress: Option<Arc<dyn ProgressFactory>>,
+    cancel: Option<CancellationToken>,
+    timeout: Option<Duration>,
+    interruptible: bool,
+}
+
+impl ScmStoreBuilder {
+    /// Start building a stack around the shared indexedlog cache, which is always present.
+    pub fn new(shared: Arc<IndexedLogHgIdDataStore>) -> Self {
+        ScmStoreBuilder {
+            shared,
+            local: None,
+            lfs: None,
+            memcache: None,
+            remote: None,
+            aux_remote: None,
+            write_store: None,
+            fetch_options: FallbackFetchOptions::default(),
+            in_flight: InFlight::new(),
+            history: None,
+            offline: false,
+            rate_limiter: None,
+            priority: Priority::default(),
+            negative_cache: None,
+            progress: None,
+            cancel: None,
+            timeout: None,
+            interruptible: false,
+        }
+    }
+
+    /// Read the fallback batching options, offline mode, remote rate limits, fetch priority and
+    /// fetch timeout from the config. Fetches stop when the process is interrupted unless
+    /// `scmstore.interruptible` is false.
+    pub fn config(mut self, config: &ConfigSet) -> Result<Self> {
+        self.fetch_options = FallbackFetchOptions::from_config(config)?;
+        self.offline = scmstore_offline(config)?;
+        self.rate_limiter = RateLimiter::from_config(config, "edenapi")?;
+        if let Some(priority) = config.get_opt::<String>("scmstore", "priority")? {
+            self.priority = priority.parse()?;
+        }
+        if let Some(timeout) = config.get_opt::<u64>("scmstore", "fetch-timeout-ms")? {
+            self.timeout = Some(Duration::from_millis(timeout));
+        }
+        self.interruptible = config.get_or("scmstore", "interruptible", || true)?;
+        Ok(self)
+    }
+
+    /// Set the priority of the fetches sent to the remote store. Background commands such as
+    /// repack use a lower priority, so that they yield to the interactive commands sharing the
+    /// same rate limits, including those of other processes using the same cache. Priorities only
+    /// apply when a rate limit is set.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Limit the rate and concurrency of the requests sent to the remote store.
+    pub fn rate_limit(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Don't send the remote store the keys it recently reported missing, and record the keys
+    /// it reports missing in `negative_cache`.
+    pub fn negative_cache(mut self, negative_cache: Arc<NegativeCache>) -> Self {
+        self.negative_cache = Some(negative_cache);
+        self
+    }
+
+    /// Show the progress of fetches on progress bars created by `progress`: the keys requested
+    /// and completed, and the bytes downloaded from memcache and the remote store.
+    pub fn progress(mut self, progress: Arc<dyn ProgressFactory>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Stop the fetches through the stack when `token` is cancelled.
+    pub fn cancel(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Stop each fetch through the stack still in progress `timeout` after it started.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stop the fetches through the stack when the process is interrupted.
+    pub fn interruptible(mut self, interruptible: bool) -> Self {
+        self.interruptible = interruptible;
+        self
+    }
+
+    /// Leave memcache and the remote store out of the stack, and report the keys which would
+    /// have been fetched from them as requiring network access.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Override the fallback batching options.
+    pub fn fetch_options(mut self, fetch_options: FallbackFetchOptions) -> Self {
+        self.fetch_options = fetch_options;
+        self
+    }
+
+    /// The local (non-cache) indexedlog store, queried before the shared cache.
+    pub fn local(mut self, local: Arc<IndexedLogHgIdDataStore>) -> Self {
+        self.local = Some(local);
+        self
+    }
+
+    /// The shared LFS store, queried after the shared indexedlog cache.
+    pub(crate) fn lfs(mut self, lfs: Arc<LfsStore>) -> Self {
+        self.lfs = Some(lfs);
+        self
+    }
+
+    /// Query memcache before the remote store. Values fetched from the remote store are written
+    /// back to memcache in the background.
+    pub fn memcache(mut self, memcache: Arc<MemcacheStore>) -> Self {
+        self.memcache = Some(memcache);
+        self
+    }
+
+    /// Fetch files which aren't available locally from `remote`.
+    pub fn file_remote(mut self, remote: BoxedReadStore<Key, FileEntry>) -> Self {
+        self.remote = Some(Remote::Files(remote));
+        self
+    }
+
+    /// Fetch the aux data of files which aren't available locally from `remote`.
+    pub fn aux_remote(mut self, remote: BoxedReadStore<Key, FileAuxData>) -> Self {
+        self.aux_remote = Some(remote);
+        self
+    }
+
+    /// Fetch trees which aren't available locally from `remote`.
+    pub fn tree_remote(mut self, remote: BoxedReadStore<Key, TreeEntry>) -> Self {
+        self.remote = Some(Remote::Trees(remote));
+        self
+    }
+
+    /// Fetch the keys which aren't available locally with the legacy getpack protocol, for
+    /// servers which don't expose EdenApi. `remote` must write the values it fetches to the
+    /// shared cache, so they aren't written back again.
+    pub fn getpack_remote(mut self, remote: Arc<GetPackStore>) -> Self {
+        self.remote = Some(Remote::GetPack(remote));
+        self
+    }
+
+    /// Where values fetched from memcache or the remote store are written. Defaults to the
+    /// shared indexedlog cache.
+    pub fn write_store(mut self, write_store: BoxedWriteStore<Key, Entry>) -> Self {
+        self.write_store = Some(write_store);
+        self
+    }
+
+    /// Verify the entries read from the shared cache against their parents in `history`.
+    /// Corrupt entries are quarantined and fetched again from the remote store.
+    pub fn verify(mut self, history: Arc<dyn HgIdHistoryStore>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub fn build(self) -> BoxedReadStore<Key, Entry> {
+        let fetch_options = self.fetch_options;
+        let fallback = |preferred: BoxedReadStore<Key, Entry>,
+                        fallback: BoxedReadStore<Key, Entry>,
+                        write_store: BoxedWriteStore<Key, Entry>,
+                        write_policy: WritePolicy|
+         -> BoxedReadStore<Key, Entry> {
+            Arc::new(FallbackStore {
+                preferred,
+                fallback,
+                write_store,
+                write_policy,
+                write_error_policy: WriteErrorPolicy::Log,
+                fetch_options: fetch_options.clone(),
+            })
+        };
+
+        let (message, unit) = match self.remote {
+            Some(Remote::Trees(_)) => ("fetching trees", "trees"),
+            _ => ("fetching files", "files"),
+        };
+        let progress = self
+            .progress
+            .map(|factory| FetchProgress::new(factory, message, unit));
+        let downloaded = |store: BoxedReadStore<Key, Entry>,
+                          source: &'static str|
+         -> BoxedReadStore<Key, Entry> {
+            match progress.as_ref() {
+                Some(progress) => Arc::new(ProgressStore {
+                    store,
+                    progress: progress.clone(),
+                    source: Some(source),
+                }),
+                None => store,
+            }
+        };
+
+        let shared = self.shared;
+        let write_store = self
+            .write_store
+            .unwrap_or_else(|| shared.clone() as BoxedWriteStore<Key, Entry>);
+
+        // Build the stack bottom-up: remote, memcache, LFS, shared cache, local. Wrapping the
+        // remote store in a `FallbackStore` over an empty store converts its values to `Entry`.
+        let getpack = matches!(self.remote, Some(Remote::GetPack(_)));
+        let remote: Option<BoxedReadStore<Key, Entry>> = self.remote.map(|remote| match remote {
+            Remote::Files(remote) => Arc::new(FallbackStore {
+                preferred: Arc::new(EmptyStore),
+                fallback: remote,
+                write_store: Arc::new(EmptyStore),
+                write_policy: WritePolicy::NoWrite,
+                write_error_policy: WriteErrorPolicy::Log,
+                fetch_options: fetch_options.clone(),
+            }) as BoxedReadStore<Key, Entry>,
+            Remote::Trees(remote) => Arc::new(FallbackStore {
+                preferred: Arc::new(EmptyStore),
+                fallback: remote,
+                write_store: Arc::new(EmptyStore),
+                write_policy: WritePolicy::NoWrite,
+                write_error_policy: WriteErrorPolicy::Log,
+                fetch_options: fetch_options.clone(),
+            }),
+            Remote::GetPack(remote) => remote,
+        });
+        let source = if getpack { "getpack" } else { "edenapi" };
+        let remote = remote.map(|remote| downloaded(remote, source));
+
+        let rate_limiter = self.rate_limiter;
+        let priority = self.priority;
+        let remote = remote.map(|remote| match rate_limiter {
+            Some(limiter) => Arc::new(RateLimitedStore {
+                store: remote,
+                limiter,
+                priority,
+            }) as BoxedReadStore<Key, Entry>,
+            None => remote,
+        });
+        let negative_cache = self.negative_cache;
+        let remote = remote.map(|remote| match negative_cache {
+            Some(cache) => Arc::new(NegativeCacheStore {
+                store: remote,
+                cache,
+            }) as BoxedReadStore<Key, Entry>,
+            None => remote,
+        });
+
+        let remote = match (self.memcache, remote) {
+            // Values fetched with getpack are already in the shared cache, so only the values
+            // found in memcache are written to it.
+            (Some(memcache), Some(remote)) if getpack => Some(fallback(
+                fallback(
+                    Arc::new(EmptyStore),
+                    downloaded(memcache.clone(), "memcache"),
+                    write_store.clone(),
+                    WritePolicy::WriteThrough,
+                ),
+                remote,
+                memcache,
+                WritePolicy::WriteBack,
+            )),
+            (Some(memcache), Some(remote)) => Some(fallback(
+                downloaded(memcache.clone(), "memcache"),
+                remote,
+                memcache,
+                WritePolicy::WriteBack,
+            )),
+            (Some(memcache), None) => Some(downloaded(memcache, "memcache")),
+            (None, remote) => remote,
+        };
+        let remote = if self.offline {
+            remote.map(|_| Arc::new(OfflineStore) as BoxedReadStore<Key, Entry>)
+        } else {
+            remote
+        };
+        let in_flight = self.in_flight;
+        let remote = remote.map(|remote| {
+            Arc::new(SingleFlightStore::with_in_flight(remote, in_flight))
+                as BoxedReadStore<Key, Entry>
+        });
+
+        let remote = match (self.lfs, remote) {
+            (Some(lfs), Some(remote)) => Some(fallback(
+                Arc::new(LegacyDatastore(lfs)),
+                remote,
+                Arc::new(EmptyStore),
+                WritePolicy::NoWrite,
+            )),
+            (Some(lfs), None) => Some(Arc::new(LegacyDatastore(lfs)) as BoxedReadStore<Key, Entry>),
+            (None, remote) => remote,
+        };
+
+        let cache: BoxedReadStore<Key, Entry> = match self.history {
+            // Corrupt entries can only be quarantined if they can be fetched again.
+            Some(history) => Arc::new(VerifyingStore {
+                store: shared.clone(),
+                history,
+                quarantine: remote.as_ref().map(|_| shared.clone()),
+            }),
+            None => shared,
+        };
+        let write_policy = if getpack {
+            WritePolicy::NoWrite
+        } else {
+            WritePolicy::WriteThrough
+        };
+        let shared = match remote {
+            Some(remote) => fallback(cache, remote, write_store, write_policy),
+            None => cache,
+        };
+
+        let store = match self.local {
+            Some(local) => fallback(local, shared, Arc::new(EmptyStore), WritePolicy::NoWrite),
+            None => shared,
+        };
+        let store = match progress {
+            Some(progress) => Arc::new(ProgressStore {
+                store,
+                progress,
+                source: None,
+            }),
+            None => store,
+        };
+        if self.cancel.is_some() || self.timeout.is_some() || self.interruptible {
+            Arc::new(CancellableStore {
+                store,
+                token: self.cancel,
+                timeout: self.timeout,
+                interruptible: self.interruptible,
+            })
+        } else {
+            store
+        }
+    }
+
+    /// Build a store for the aux data of files, cached in `aux`. Aux data which isn't cached is
+    /// computed from the content available locally, or fetched from the aux data remote, and
+    /// written to `aux`. File content is never fetched remotely to compute aux data.
+    pub fn build_aux(self, aux: Arc<IndexedLogAuxStore>) -> BoxedReadStore<Key, FileAuxData> {
+        let fetch_options = self.fetch_options.clone();
+        let aux_remote = if self.offline {
+            None
+        } else {
+            self.aux_remote.clone()
+        };
+        let local: BoxedReadStore<Key, FileAuxData> = Arc::new(ComputedAuxStore {
+            store: ScmStoreBuilder {
+                remote: None,
+                memcache: None,
+                ..self
+            }
+            .build(),
+        });
+        let fallback = match aux_remote {
+            Some(remote) => Arc::new(FallbackStore {
+                preferred: local,
+                fallback: remote,
+                write_store: aux.clone(),
+                write_policy: WritePolicy::NoWrite,
+                write_error_policy: WriteErrorPolicy::Log,
+                fetch_options: fetch_options.clone(),
+            }) as BoxedReadStore<Key, FileAuxData>,
+            None => local,
+        };
+        Arc::new(FallbackStore {
+            preferred: aux.clone(),
+            fallback,
+            write_store: aux,
+            write_policy: WritePolicy::WriteThrough,
+            write_error_policy: WriteErrorPolicy::Log,
+            fetch_options,
+        })
+    }
+}
+
+/// A store which contains nothing and discards all writes, used to fill the unused slots of
+/// `FallbackStore`.
+struct EmptyStore;
+
+#[async_trait]
+impl ReadStore<Key, Entry> for EmptyStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+        Box::pin(keys.map(|key| Err(FetchError::not_found(key))))
+    }
+}
+
+#[async_trait]
+impl WriteStore<Key, Entry> for EmptyStore {
+    async fn write_stream(self: Arc<Self>, values: WriteStream<Entry>) -> WriteResults<Key> {
+        Box::pin(values.map(|value| Ok(value.key().clone())))
+    }
+}
+
+/// The bottom of the stack in offline mode, which fails every key with `RequiresNetwork`.
+struct OfflineStore;
+
+#[async_trait]
+impl ReadStore<Key, Entry> for OfflineStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+        Box::pin(keys.map(|key| {
+            let keys = vec![StoreKey::hgid(key.clone())];
+            Err(FetchError::with_key(key, RequiresNetwork { keys }))
+        }))
+    }
+}
@@ -0,0 +1,341 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A combinator which coalesces concurrent fetches of the same key.
+//!
+//! This is mostly useful in front of remote stores, when several fetches race for the same
+//! keys, for instance `hg status` and a background prefetch. Only the first fetch of a key is
+//! sent to the underlying store, and every other fetch of that key waits for its result.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::format_err;
+use async_trait::async_trait;
+use futures::{
+    channel::{mpsc::unbounded, oneshot},
+    future::{self, BoxFuture},
+    stream, StreamExt,
+};
+use parking_lot::Mutex;
+
+use types::Key;
+
+use crate::{
+    indexedlogdatastore::Entry,
+    newstore::{
+        metrics::FetchMetrics, BoxedReadStore, FetchError, FetchStream, KeyStream, ReadStore,
+    },
+};
+
+/// Result shared with the fetches waiting on a key. `None` means the key wasn't found.
+type SharedResult = Result<Entry, Option<String>>;
+
+type Waiters = Vec<oneshot::Sender<SharedResult>>;
+
+/// Maximum number of keys a single fetch waits on at once.
+const MAX_WAITING: usize = 10000;
+
+const METRICS: FetchMetrics = FetchMetrics::new("singleflight");
+
+/// The keys currently being fetched, along with the fetches waiting for them. Cloning an
+/// `InFlight` shares the underlying map, so that several `SingleFlightStore`s in front of the
+/// same remote store can coalesce each other's fetches.
+#[derive(Clone, Default)]
+pub struct InFlight(Arc<Mutex<HashMap<Key, Waiters>>>);
+
+impl InFlight {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Send the result for `key` to all the fetches waiting for it.
+    fn complete(&self, key: &Key, res: &Result<Entry, FetchError<Key>>) {
+        let waiters = match self.0.lock().remove(key) {
+            Some(waiters) => waiters,
+            None => return,
+        };
+        for waiter in waiters {
+            let shared = match res {
+                Ok(entry) => Ok(entry.clone()),
+                Err(FetchError::NotFound(_)) => Err(None),
+                Err(e) => Err(Some(format!("{}", e))),
+            };
+            let _ = waiter.send(shared);
+        }
+    }
+}
+
+pub struct SingleFlightStore {
+    store: BoxedReadStore<Key, Entry>,
+    in_flight: InFlight,
+}
+
+impl SingleFlightStore {
+    pub fn new(store: BoxedReadStore<Key, Entry>) -> Self {
+        Self::with_in_flight(store, InFlight::new())
+    }
+
+    /// Coalesce fetches with the other stores sharing `in_flight`.
+    pub fn with_in_flight(store: BoxedReadStore<Key, Entry>, in_flight: InFlight) -> Self {
+        SingleFlightStore { store, in_flight }
+    }
+}
+
+/// The keys a single fetch sent to the underlying store. Once the underlying store's stream
+/// ends, the keys it didn't return are reported to the fetches waiting for them, either as not
+/// found or with the error the stream failed with. If the fetch is dropped before then, the
+/// waiting fetches retry the keys themselves.
+struct OwnedKeys {
+    in_flight: InFlight,
+    keys: Mutex<HashSet<Key>>,
+    /// The last error the underlying store returned without a key.
+    error: Mutex<Option<String>>,
+}
+
+impl OwnedKeys {
+    /// Remove the remaining keys from the `InFlight` map and send `res` to their waiters. The
+    /// waiters are dropped instead when `res` is `None`.
+    fn release(&self, res: Option<SharedResult>) {
+        let mut in_flight = self.in_flight.0.lock();
+        for key in self.keys.lock().drain() {
+            let waiters = in_flight.remove(&key).unwrap_or_default();
+            if let Some(res) = &res {
+                for waiter in waiters {
+                    let _ = waiter.send(res.clone());
+                }
+            }
+        }
+    }
+}
+
+impl Drop for OwnedKeys {
+    fn drop(&mut self) {
+        self.release(None);
+    }
+}
+
+#[async_trait]
+impl ReadStore<Key, Entry> for SingleFlightStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+        let (owned_sender, owned_receiver) = unbounded();
+        let owned = Arc::new(OwnedKeys {
+            in_flight: self.in_flight.clone(),
+            keys: Mutex::new(HashSet::new()),
+            error: Mutex::new(None),
+        });
+
+        // Keys the underlying store doesn't return are released once its stream ends.
+        let in_flight = self.in_flight.clone();
+        let (returned, released) = (owned.clone(), owned.clone());
+        let fetched = self
+            .store
+            .clone()
+            .fetch_stream(Box::pin(owned_receiver))
+            .await
+            .map(move |res| {
+                let key = match &res {
+                    Ok(entry) => Some(entry.key()),
+                    Err(FetchError::NotFound(key)) | Err(FetchError::KeyedError(key, _)) => {
+                        Some(key)
+                    }
+                    Err(FetchError::Other(e)) => {
+                        *returned.error.lock() = Some(format!("{}", e));
+                        None
+                    }
+                };
+                if let Some(key) = key {
+                    returned.keys.lock().remove(key);
+                    in_flight.complete(key, &res);
+                }
+                Some(res)
+            })
+            .chain(stream::once(async move {
+                let error = released.error.lock().take();
+                released.release(Some(Err(error)));
+                None
+            }))
+            .filter_map(future::ready);
+
+        // Keys which aren't in flight yet are sent to the underlying store, the others wait for
+        // the fetch which owns them. The sender is dropped once the keys are exhausted, which
+        // lets the underlying store's stream end.
+        let owned_sender = Arc::new(Mutex::new(Some(owned_sender)));
+        let in_flight = self.in_flight.clone();
+        let store = self.clone();
+        let waiting = keys
+            .map(Some)
+            .chain(stream::once(future::ready(None)))
+            .filter_map(move |key| {
+                let key = match key {
+                    Some(key) => key,
+                    None => {
+                        owned_sender.lock().take();
+                        return future::ready(None);
+                    }
+                };
+                METRICS.requested(1);
+
+                let mut in_flight = in_flight.0.lock();
+                if let Some(waiters) = in_flight.get_mut(&key) {
+                    METRICS.hit(1);
+                    let (sender, receiver) = oneshot::channel();
+                    waiters.push(sender);
+                    let store = store.clone();
+                    let waiter: BoxFuture<'static, Vec<_>> = Box::pin(async move {
+                        match receiver.await {
+                            Ok(Ok(entry)) => vec![Ok(entry)],
+                            Ok(Err(Some(e))) => {
+                                vec![Err(FetchError::with_key(key, format_err!("{}", e)))]
+                            }
+                            Ok(Err(None)) => vec![Err(FetchError::not_found(key))],
+                            // The owning fetch was dropped before it completed.
+                            Err(oneshot::Canceled) => {
+                                store
+                                    .fetch_stream(Box::pin(stream::once(future::ready(key))))
+                                    .await
+                                    .collect()
+                                    .await
+                            }
+                        }
+                    });
+                    return future::ready(Some(waiter));
+                }
+
+                METRICS.miss(1);
+                in_flight.insert(key.clone(), Vec::new());
+                owned.keys.lock().insert(key.clone());
+                if let Some(sender) = owned_sender.lock().as_ref() {
+                    let _ = sender.unbounded_send(key);
+                }
+                future::ready(None)
+            })
+            .buffer_unordered(MAX_WAITING)
+            .map(stream::iter)
+            .flatten();
+
+        Box::pin(stream::select(fetched, waiting))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use minibytes::Bytes;
+
+    use async_runtime::block_on_future as block_on;
+    use types::testutil::*;
+
+    /// Returns every key after a delay, counting the keys it was asked for.
+    struct SlowStore {
+        fetched: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ReadStore<Key, Entry> for SlowStore {
+        async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+            Box::pin(keys.then(move |key| {
+                self.fetched.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    if key.path.as_str() == "missing" {
+                        Err(FetchError::not_found(key))
+                    } else {
+                        Ok(Entry::new(
+                            key,
+                            Bytes::from(&[1, 2, 3][..]),
+                            Default::default(),
+                        ))
+                    }
+                }
+            }))
+        }
+    }
+
+    /// Fails every fetch after a delay, without reporting which keys failed.
+    struct FailingStore;
+
+    #[async_trait]
+    impl ReadStore<Key, Entry> for FailingStore {
+        async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+            Box::pin(keys.then(|_| async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Err(FetchError::from(format_err!("server unavailable")))
+            }))
+        }
+    }
+
+    #[test]
+    fn test_single_flight() {
+        let underlying = Arc::new(SlowStore {
+            fetched: AtomicUsize::new(0),
+        });
+        let store = Arc::new(SingleFlightStore::new(underlying.clone()));
+
+        let keys = vec![key("a", "1"), key("missing", "2"), key("a", "1")];
+        let fetch = |keys: Vec<Key>| {
+            let store = store.clone();
+            async move {
+                store
+                    .fetch_stream(Box::pin(stream::iter(keys)))
+                    .await
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+        let (first, second) = block_on(future::join(fetch(keys.clone()), fetch(keys)));
+
+        for results in &[first, second] {
+            assert_eq!(results.len(), 3);
+            assert_eq!(results.iter().filter(|res| res.is_ok()).count(), 2);
+            assert!(results.iter().any(|res| match res {
+                Err(FetchError::NotFound(key)) => key.path.as_str() == "missing",
+                _ => false,
+            }));
+        }
+        assert_eq!(underlying.fetched.load(Ordering::SeqCst), 2);
+        assert!(store.in_flight.0.lock().is_empty());
+    }
+
+    #[test]
+    fn test_single_flight_error() {
+        let store = Arc::new(SingleFlightStore::new(Arc::new(FailingStore)));
+        let fetch = || {
+            let store = store.clone();
+            async move {
+                store
+                    .fetch_stream(Box::pin(stream::once(future::ready(key("a", "1")))))
+                    .await
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+        let (first, second) = block_on(future::join(fetch(), fetch()));
+
+        // The waiting fetch gets the owner's error rather than a spurious not found.
+        let mut results = first.into_iter().chain(second).collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        results.sort_by_key(|res| res.as_ref().err().and_then(|e| e.key()).is_some());
+        assert!(matches!(&results[0], Err(FetchError::Other(_))));
+        match &results[1] {
+            Err(FetchError::KeyedError(key, e)) => {
+                assert_eq!(key.path.as_str(), "a");
+                assert!(e.to_string().contains("server unavailable"));
+            }
+            res => panic!("unexpected result {:?}", res.as_ref().map(|_| ())),
+        }
+        assert!(store.in_flight.0.lock().is_empty());
+    }
+}
@@ -0,0 +1,384 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A combinator which verifies the content of fetched entries against their hg node.
+//!
+//! The node of a file or tree is the SHA-1 of its parents and content, so a corrupt cache entry
+//! can be detected as long as its parents are known. Without verification, a corrupt entry is
+//! silently served, and may end up in a commit.
+
+use std::{collections::HashSet, sync::Arc};
+
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use futures::{FutureExt, StreamExt};
+use tokio::task::spawn_blocking;
+use tracing::error;
+
+use types::{HgId, Key, Parents};
+
+use crate::{
+    error::HashMismatch,
+    historystore::HgIdHistoryStore,
+    indexedlogdatastore::{Entry, IndexedLogHgIdDataStore},
+    newstore::{
+        metrics::FetchMetrics, BoxedReadStore, FetchError, FetchStream, KeyStream, ReadStore,
+    },
+    redacted::is_redacted,
+    repack::ToKeys,
+};
+
+const METRICS: FetchMetrics = FetchMetrics::new("verify");
+
+pub struct VerifyingStore {
+    /// The store whose entries are verified.
+    pub store: BoxedReadStore<Key, Entry>,
+
+    /// Where the parents of the fetched entries are looked up. Entries whose parents aren't
+    /// found can't be verified, and are returned as is.
+    pub history: Arc<dyn HgIdHistoryStore>,
+
+    /// The indexedlog store `store` reads from, if any. Corrupt entries are quarantined in it
+    /// and reported as not found, so that a `FallbackStore` above this one fetches them again
+    /// and writes them back. Otherwise, corrupt entries are reported as `HashMismatch` errors.
+    pub quarantine: Option<Arc<IndexedLogHgIdDataStore>>,
+}
+
+/// The outcome of checking an entry against its node.
+#[derive(Debug)]
+pub enum Verified {
+    Valid,
+
+    /// LFS pointers, redacted content and entries whose parents aren't known can't be checked.
+    Unverifiable,
+
+    Corrupt(HashMismatch),
+}
+
+/// Check the content of `entry` against its node, looking up its parents in `history`.
+pub fn verify_entry(entry: &mut Entry, history: &dyn HgIdHistoryStore) -> Result<Verified> {
+    let key = entry.key().clone();
+    // LFS pointers are hashed with the full content, which isn't available here, and
+    // redacted content never matches its node.
+    if entry.metadata().is_lfs() {
+        return Ok(Verified::Unverifiable);
+    }
+    let content = entry.content()?;
+    if is_redacted(&content) {
+        return Ok(Verified::Unverifiable);
+    }
+
+    let parents = match history.get_node_info(&key)? {
+        Some(info) => Parents::new(info.parents[0].hgid, info.parents[1].hgid),
+        None => return Ok(Verified::Unverifiable),
+    };
+
+    let computed = HgId::from_content(&content, parents);
+    if computed == key.hgid {
+        Ok(Verified::Valid)
+    } else {
+        Ok(Verified::Corrupt(HashMismatch { key, computed }))
+    }
+}
+
+impl VerifyingStore {
+    fn verify(&self, mut entry: Entry) -> Result<Entry, FetchError<Key>> {
+        let key = entry.key().clone();
+        match verify_entry(&mut entry, self.history.as_ref()) {
+            Ok(Verified::Valid) => {
+                METRICS.hit(1);
+                Ok(entry)
+            }
+            Ok(Verified::Unverifiable) => {
+                METRICS.miss(1);
+                Ok(entry)
+            }
+            Ok(Verified::Corrupt(mismatch)) => {
+                METRICS.error(1);
+                match self.quarantine.as_ref() {
+                    Some(quarantine) => {
+                        error!({ error = %mismatch }, "quarantining corrupt entry");
+                        if let Err(e) = quarantine.quarantine(key.clone()) {
+                            error!({ error = %e }, "failed to record quarantine");
+                        }
+                        Err(FetchError::not_found(key))
+                    }
+                    None => Err(FetchError::with_key(key, mismatch)),
+                }
+            }
+            Err(e) => {
+                METRICS.error(1);
+                Err(FetchError::with_key(key, e))
+            }
+        }
+    }
+}
+
+/// The result of `scan_store`.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// Number of keys checked.
+    pub checked: usize,
+
+    /// Number of entries which couldn't be checked, see `Verified::Unverifiable`.
+    pub unverifiable: usize,
+
+    /// The corrupt entries, which were quarantined, and what is wrong with them.
+    pub corrupt: Vec<(Key, Error)>,
+
+    /// Number of log entries too damaged to tell which key they hold.
+    pub unreadable: usize,
+}
+
+/// Check every entry of `store` against its node, and quarantine the entries whose content is
+/// corrupt or can't be read, so that they are fetched again rather than served.
+///
+/// Quarantine only lasts as long as `store` is open. Corrupt entries are only replaced on disk
+/// once a new entry is written for them.
+pub fn scan_store(
+    store: &IndexedLogHgIdDataStore,
+    history: &dyn HgIdHistoryStore,
+) -> Result<ScanReport> {
+    let mut report = ScanReport::default();
+    let mut seen = HashSet::new();
+    for key in store.to_keys() {
+        let key = match key {
+            Ok(key) => key,
+            Err(_) => {
+                report.unreadable += 1;
+                continue;
+            }
+        };
+        // Only the latest entry of a key is ever read.
+        if !seen.insert(key.clone()) {
+            continue;
+        }
+        report.checked += 1;
+        let verified = store.entry(&key).and_then(|entry| match entry {
+            Some(mut entry) => verify_entry(&mut entry, history),
+            None => Ok(Verified::Unverifiable),
+        });
+        let error = match verified {
+            Ok(Verified::Valid) => continue,
+            Ok(Verified::Unverifiable) => {
+                report.unverifiable += 1;
+                continue;
+            }
+            Ok(Verified::Corrupt(mismatch)) => Error::from(mismatch),
+            Err(e) => e,
+        };
+        store.quarantine(key.clone())?;
+        report.corrupt.push((key, error));
+    }
+    Ok(report)
+}
+
+#[async_trait]
+impl ReadStore<Key, Entry> for VerifyingStore {
+    async fn fetch_stream(self: Arc<Self>, keys: KeyStream<Key>) -> FetchStream<Key, Entry> {
+        Box::pin(
+            self.store
+                .clone()
+                .fetch_stream(keys)
+                .await
+                .then(move |res| {
+                    let self_ = self.clone();
+                    async move {
+                        let entry = res?;
+                        METRICS.requested(1);
+                        let key = entry.key().clone();
+                        spawn_blocking(move || self_.verify(entry))
+                            .map(move |spawn_res| match spawn_res {
+                                Ok(res) => res,
+                                Err(e) => Err(FetchError::with_key(key, e)),
+                            })
+                            .await
+                    }
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+    use futures::stream;
+    use minibytes::Bytes;
+    use tempfile::TempDir;
+
+    use async_runtime::{block_on_future as block_on, stream_to_iter as block_on_stream};
+    use configparser::config::ConfigSet;
+    use types::{testutil::*, NodeInfo};
+
+    use crate::{
+        datastore::{Delta, HgIdDataStore, HgIdMutableDeltaStore, StoreResult},
+        historystore::HgIdMutableHistoryStore,
+        indexedlogdatastore::IndexedLogDataStoreType,
+        indexedloghistorystore::{IndexedLogHgIdHistoryStore, IndexedLogHistoryStoreType},
+        localstore::ExtStoredPolicy,
+        newstore::fallback::{FallbackFetchOptions, FallbackStore, WriteErrorPolicy, WritePolicy},
+        types::StoreKey,
+    };
+
+    #[test]
+    fn test_verify_quarantine() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let config = ConfigSet::new();
+        let cache = Arc::new(IndexedLogHgIdDataStore::new(
+            tempdir.path().join("cache"),
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?);
+        let remote = Arc::new(IndexedLogHgIdDataStore::new(
+            tempdir.path().join("remote"),
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?);
+        let history = Arc::new(IndexedLogHgIdHistoryStore::new(
+            tempdir.path().join("history"),
+            &config,
+            IndexedLogHistoryStoreType::Shared,
+        )?);
+
+        let content = Bytes::from(&b"content"[..]);
+        let parents = Parents::new(HgId::null_id().clone(), HgId::null_id().clone());
+        let k = Key::new(
+            key("a", "1").path,
+            HgId::from_content(&content, parents.clone()),
+        );
+        history.add(
+            &k,
+            &NodeInfo {
+                parents: [
+                    Key::new(k.path.clone(), HgId::null_id().clone()),
+                    Key::new(k.path.clone(), HgId::null_id().clone()),
+                ],
+                linknode: HgId::null_id().clone(),
+            },
+        )?;
+
+        let delta = |data: &Bytes| Delta {
+            data: data.clone(),
+            base: None,
+            key: k.clone(),
+        };
+        cache.add(&delta(&Bytes::from(&b"corrupt"[..])), &Default::default())?;
+        remote.add(&delta(&content), &Default::default())?;
+
+        // Without quarantine, the corrupt entry is rejected.
+        let verifying = Arc::new(VerifyingStore {
+            store: cache.clone(),
+            history: history.clone(),
+            quarantine: None,
+        });
+        let fetched: Vec<_> = block_on_stream(block_on(
+            verifying.fetch_stream(Box::pin(stream::iter(vec![k.clone()]))),
+        ))
+        .collect();
+        match &fetched[..] {
+            [Err(FetchError::KeyedError(key, e))] => {
+                assert_eq!(key, &k);
+                assert!(e.downcast_ref::<HashMismatch>().is_some());
+            }
+            _ => panic!("expected a hash mismatch"),
+        }
+
+        // With quarantine, the entry is fetched again and the cache is repaired.
+        let verifying = Arc::new(VerifyingStore {
+            store: cache.clone(),
+            history,
+            quarantine: Some(cache.clone()),
+        });
+        let fallback = Arc::new(FallbackStore {
+            preferred: verifying,
+            fallback: remote,
+            write_store: cache.clone(),
+            write_policy: WritePolicy::WriteThrough,
+            write_error_policy: WriteErrorPolicy::Log,
+            fetch_options: FallbackFetchOptions::default(),
+        });
+        let fetched: Vec<_> = block_on_stream(block_on(
+            fallback.fetch_stream(Box::pin(stream::iter(vec![k.clone()]))),
+        ))
+        .collect();
+        assert_eq!(fetched.len(), 1);
+        assert_eq!(fetched[0].as_ref().unwrap().clone().content()?, content);
+        assert_eq!(
+            cache.get(StoreKey::hgid(k))?,
+            StoreResult::Found(content.as_ref().to_vec())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_store() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let config = ConfigSet::new();
+        let store = IndexedLogHgIdDataStore::new(
+            tempdir.path().join("store"),
+            ExtStoredPolicy::Use,
+            &config,
+            IndexedLogDataStoreType::Shared,
+        )?;
+        let history = IndexedLogHgIdHistoryStore::new(
+            tempdir.path().join("history"),
+            &config,
+            IndexedLogHistoryStoreType::Shared,
+        )?;
+
+        let null = HgId::null_id().clone();
+        let add = |path: &str, content: &[u8], node_content: &[u8], known: bool| -> Result<Key> {
+            let k = Key::new(
+                key(path, "1").path,
+                HgId::from_content(node_content, Parents::new(null, null)),
+            );
+            if known {
+                let parent = Key::new(k.path.clone(), null);
+                history.add(
+                    &k,
+                    &NodeInfo {
+                        parents: [parent.clone(), parent],
+                        linknode: null,
+                    },
+                )?;
+            }
+            let delta = Delta {
+                data: Bytes::copy_from_slice(content),
+                base: None,
+                key: k.clone(),
+            };
+            store.add(&delta, &Default::default())?;
+            Ok(k)
+        };
+        let valid = add("a", b"a", b"a", true)?;
+        let corrupt = add("b", b"corrupt", b"b", true)?;
+        add("c", b"corrupt", b"c", false)?;
+
+        let report = scan_store(&store, &history)?;
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.unverifiable, 1);
+        assert_eq!(report.unreadable, 0);
+        assert_eq!(report.corrupt.len(), 1);
+        assert_eq!(report.corrupt[0].0, corrupt);
+        assert!(report.corrupt[0].1.downcast_ref::<HashMismatch>().is_some());
+
+        // The corrupt entry is quarantined.
+        assert_eq!(
+            store.get(StoreKey::hgid(corrupt.clone()))?,
+            StoreResult::NotFound(StoreKey::hgid(corrupt))
+        );
+        assert_eq!(
+            store.get(StoreKey::hgid(valid))?,
+            StoreResult::Found(b"a".to_vec())
+        );
+        Ok(())
+    }
+}
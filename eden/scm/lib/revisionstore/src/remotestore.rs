@@ -7,9 +7,16 @@
 
 use std::sync::Arc;
 
+use anyhow::{Error, Result};
+
+use types::{Key, NodeInfo};
+
 use crate::{
-    datastore::{HgIdMutableDeltaStore, RemoteDataStore},
-    historystore::{HgIdMutableHistoryStore, RemoteHistoryStore},
+    datastore::{HgIdDataStore, HgIdMutableDeltaStore, Metadata, RemoteDataStore, StoreResult},
+    error::RequiresNetwork,
+    historystore::{HgIdHistoryStore, HgIdMutableHistoryStore, RemoteHistoryStore},
+    localstore::LocalStore,
+    types::StoreKey,
 };
 
 pub trait HgIdRemoteStore: Send + Sync {
@@ -22,3 +29,88 @@ pub trait HgIdRemoteStore: Send + Sync {
         store: Arc<dyn HgIdMutableHistoryStore>,
     ) -> Arc<dyn RemoteHistoryStore>;
 }
+
+/// The remote store used in offline mode (`scmstore.offline`). Nothing is fetched, and every key
+/// it is asked for is reported as requiring network access.
+pub struct OfflineRemoteStore;
+
+impl HgIdRemoteStore for OfflineRemoteStore {
+    fn datastore(
+        self: Arc<Self>,
+        _store: Arc<dyn HgIdMutableDeltaStore>,
+    ) -> Arc<dyn RemoteDataStore> {
+        self
+    }
+
+    fn historystore(
+        self: Arc<Self>,
+        _store: Arc<dyn HgIdMutableHistoryStore>,
+    ) -> Arc<dyn RemoteHistoryStore> {
+        self
+    }
+}
+
+fn requires_network(keys: &[StoreKey]) -> Error {
+    RequiresNetwork {
+        keys: keys.to_vec(),
+    }
+    .into()
+}
+
+impl LocalStore for OfflineRemoteStore {
+    fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        Ok(keys.to_vec())
+    }
+}
+
+impl HgIdDataStore for OfflineRemoteStore {
+    fn get(&self, key: StoreKey) -> Result<StoreResult<Vec<u8>>> {
+        Err(requires_network(&[key]))
+    }
+
+    fn get_meta(&self, key: StoreKey) -> Result<StoreResult<Metadata>> {
+        Err(requires_network(&[key]))
+    }
+
+    fn refresh(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl RemoteDataStore for OfflineRemoteStore {
+    fn prefetch(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        if keys.is_empty() {
+            Ok(vec![])
+        } else {
+            Err(requires_network(keys))
+        }
+    }
+
+    fn upload(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        if keys.is_empty() {
+            Ok(vec![])
+        } else {
+            Err(requires_network(keys))
+        }
+    }
+}
+
+impl HgIdHistoryStore for OfflineRemoteStore {
+    fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>> {
+        Err(requires_network(&[StoreKey::hgid(key.clone())]))
+    }
+
+    fn refresh(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl RemoteHistoryStore for OfflineRemoteStore {
+    fn prefetch(&self, keys: &[StoreKey]) -> Result<()> {
+        if keys.is_empty() {
+            Ok(())
+        } else {
+            Err(requires_network(keys))
+        }
+    }
+}
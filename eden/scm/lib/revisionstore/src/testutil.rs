@@ -5,7 +5,15 @@
  * GNU General Public License version 2.
  */
 
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::{Error, Result};
 use async_trait::async_trait;
@@ -16,8 +24,8 @@ use configparser::config::ConfigSet;
 use edenapi::{EdenApi, EdenApiError, Fetch, ProgressCallback, ResponseMeta, Stats};
 use edenapi_types::{
     CloneData, CommitHashToLocationResponse, CommitLocationToHashRequest,
-    CommitLocationToHashResponse, CommitRevlogData, EdenApiServerError, FileEntry, HistoryEntry,
-    TreeAttributes, TreeEntry,
+    CommitLocationToHashResponse, CommitRevlogData, EdenApiServerError, FileAuxData, FileEntry,
+    HistoryEntry, Sha256, SparseProfileResponse, TreeAttributes, TreeEntry,
 };
 use types::{HgId, Key, NodeInfo, Parents, RepoPathBuf};
 
@@ -27,8 +35,9 @@ use crate::{
     },
     historystore::{HgIdHistoryStore, HgIdMutableHistoryStore, RemoteHistoryStore},
     localstore::LocalStore,
+    newstore::auxdata::content_blake3,
     remotestore::HgIdRemoteStore,
-    types::StoreKey,
+    types::{ContentHash, StoreKey},
 };
 
 pub fn delta(data: &str, base: Option<Key>, key: Key) -> Delta {
@@ -193,6 +202,9 @@ pub struct FakeEdenApi {
     files: HashMap<Key, Bytes>,
     trees: HashMap<Key, Bytes>,
     history: HashMap<Key, NodeInfo>,
+    throttled: AtomicUsize,
+    requests: AtomicUsize,
+    aux_requests: AtomicUsize,
 }
 
 impl FakeEdenApi {
@@ -212,10 +224,44 @@ impl FakeEdenApi {
         Self { history, ..self }
     }
 
+    /// Reject the next `requests` file and tree requests with a 429 response.
+    pub fn throttled(self, requests: usize) -> Self {
+        Self {
+            throttled: AtomicUsize::new(requests),
+            ..self
+        }
+    }
+
     pub fn into_arc(self) -> Arc<Self> {
         Arc::new(self)
     }
 
+    /// Number of file and tree requests received, including throttled ones.
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// Number of file aux data requests received.
+    pub fn aux_requests(&self) -> usize {
+        self.aux_requests.load(Ordering::SeqCst)
+    }
+
+    fn throttle(&self) -> Result<(), EdenApiError> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let throttled = self
+            .throttled
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if throttled {
+            return Err(EdenApiError::HttpError {
+                status: http::StatusCode::TOO_MANY_REQUESTS,
+                message: "throttled".to_string(),
+                retry_after: Some(Duration::from_millis(10)),
+            });
+        }
+        Ok(())
+    }
+
     fn get_files(
         map: &HashMap<Key, Bytes>,
         keys: Vec<Key>,
@@ -275,9 +321,38 @@ impl EdenApi for FakeEdenApi {
         keys: Vec<Key>,
         _progress: Option<ProgressCallback>,
     ) -> Result<Fetch<FileEntry>, EdenApiError> {
+        self.throttle()?;
         Self::get_files(&self.files, keys)
     }
 
+    async fn files_aux(
+        &self,
+        _repo: String,
+        keys: Vec<Key>,
+        _progress: Option<ProgressCallback>,
+    ) -> Result<Fetch<FileAuxData>, EdenApiError> {
+        self.aux_requests.fetch_add(1, Ordering::SeqCst);
+        let entries = keys
+            .into_iter()
+            .filter_map(|key| {
+                let data = self.files.get(&key)?;
+                let sha256 = ContentHash::sha256(data).unwrap_sha256();
+                Some(Ok(FileAuxData {
+                    key,
+                    total_size: data.len() as u64,
+                    content_sha256: Sha256(sha256.into_inner()),
+                    content_blake3: content_blake3(data),
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Fetch {
+            meta: vec![ResponseMeta::default()],
+            entries: Box::pin(stream::iter(entries)),
+            stats: Box::pin(future::ok(Stats::default())),
+        })
+    }
+
     async fn history(
         &self,
         _repo: String,
@@ -307,6 +382,7 @@ impl EdenApi for FakeEdenApi {
         _attrs: Option<TreeAttributes>,
         _progress: Option<ProgressCallback>,
     ) -> Result<Fetch<Result<TreeEntry, EdenApiServerError>>, EdenApiError> {
+        self.throttle()?;
         Self::get_trees(&self.trees, keys)
     }
 
@@ -347,6 +423,15 @@ impl EdenApi for FakeEdenApi {
         unimplemented!()
     }
 
+    async fn pull_fast_forward_master(
+        &self,
+        _repo: String,
+        _old_master: HgId,
+        _new_master: HgId,
+    ) -> Result<CloneData<HgId>, EdenApiError> {
+        unimplemented!()
+    }
+
     async fn commit_location_to_hash(
         &self,
         _repo: String,
@@ -365,6 +450,10 @@ impl EdenApi for FakeEdenApi {
     ) -> Result<Fetch<CommitHashToLocationResponse>, EdenApiError> {
         unimplemented!()
     }
+
+    async fn sparse_profile(&self, _repo: String) -> Result<SparseProfileResponse, EdenApiError> {
+        unimplemented!()
+    }
 }
 
 pub fn make_config(dir: impl AsRef<Path>) -> ConfigSet {
@@ -5,6 +5,8 @@
  * GNU General Public License version 2.
  */
 
+use std::fmt;
+
 use minibytes::Bytes;
 use serde_derive::{Deserialize, Serialize};
 use sha2::Digest;
@@ -55,6 +57,14 @@ impl ContentHash {
     }
 }
 
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContentHash::Sha256(hash) => write!(f, "sha256:{}", hash),
+        }
+    }
+}
+
 impl StoreKey {
     pub fn hgid(key: Key) -> Self {
         StoreKey::HgId(key)
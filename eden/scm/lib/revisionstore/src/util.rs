@@ -83,6 +83,13 @@ pub fn get_indexedlogdatastore_path(path: impl AsRef<Path>) -> Result<PathBuf> {
     Ok(path)
 }
 
+pub fn get_negativecache_path(path: impl AsRef<Path>) -> Result<PathBuf> {
+    let mut path = path.as_ref().to_owned();
+    path.push("negativecache");
+    create_shared_dir(&path)?;
+    Ok(path)
+}
+
 pub fn get_indexedloghistorystore_path(path: impl AsRef<Path>) -> Result<PathBuf> {
     let mut path = path.as_ref().to_owned();
     path.push("indexedloghistorystore");
@@ -90,6 +90,8 @@ Show debug commands if there are no other candidates
   debugbindag
   debugbuilddag
   debugbundle
+  debugcachegc
+  debugcachestats
   debugcapabilities
   debugcauserusterror
   debugchangelog
@@ -127,6 +129,7 @@ Show debug commands if there are no other candidates
   debugignore
   debugindex
   debugindexdot
+  debugindexedlogstats
   debuginitgit
   debuginstall
   debugknown
@@ -156,10 +159,12 @@ Show debug commands if there are no other candidates
   debugrebuilddirstate
   debugrebuildfncache
   debugrename
+  debugrepairstore
   debugrevlog
   debugrevspec
   debugrunshell
   debugsegmentclone
+  debugsegmentpull
   debugsendunbundle
   debugsetparents
   debugshell
@@ -209,6 +214,7 @@ Show the global options
   --help
   --hidden
   --noninteractive
+  --offline
   --outputencoding
   --pager
   --profile
@@ -247,6 +253,7 @@ Show the options for the "serve" command
   --ipv6
   --name
   --noninteractive
+  --offline
   --outputencoding
   --pager
   --pid-file
@@ -380,6 +387,8 @@ Show all commands + options
   debugbindag: rev, output
   debugbuilddag: mergeable-file, overwritten-file, new-file
   debugbundle: all, part-type, spec
+  debugcachegc: 
+  debugcachestats: reset
   debugcapabilities: 
   debugcauserusterror: 
   debugchangelog: migrate
@@ -416,6 +425,7 @@ Show all commands + options
   debugignore: 
   debugindex: changelog, manifest, dir, format
   debugindexdot: changelog, manifest, dir
+  debugindexedlogstats: history, local
   debuginitgit: git-dir
   debuginstall: template
   debugknown: 
@@ -429,7 +439,7 @@ Show all commands + options
   debugmutation: rev, successors, time-range
   debugmutationfromobsmarkers: 
   debugnamecomplete: 
-  debugnewstore: 
+  debugnewstore: path, node, depth
   debugobsolete: flags, record-parents, rev, exclusive, index, delete, date, user, template
   debugpathcomplete: full, normal, added, removed
   debugpickmergetool: rev, changedelete, include, exclude, tool
@@ -445,10 +455,12 @@ Show all commands + options
   debugrebuilddirstate: rev, minimal
   debugrebuildfncache: 
   debugrename: rev
+  debugrepairstore: refetch
   debugrevlog: changelog, manifest, dir, dump
   debugrevspec: optimize, show-revs, show-set, show-stage, no-optimized, verify-optimized
   debugrunshell: cmd
   debugsegmentclone: 
+  debugsegmentpull: 
   debugsendunbundle: 
   debugsetparents: 
   debugshell: command
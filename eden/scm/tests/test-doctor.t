@@ -75,8 +75,16 @@ Test that 'hg doctor' can fix them:
   hgcommits/v1: repaired
   metalog: repaired
   allheads: repaired
-  revisionstore: repaired
-  revisionstore: repaired
+  $TESTTMP/hgcache/master/indexedlogdatastore: repaired
+    indexedlogdatastore: cannot read 'latest': invalid digit found in string
+    Index "node" failed integrity check
+    Rebuilt index "node"
+    Reset latest to 0
+  $TESTTMP/hgcache/master/manifests/indexedlogdatastore: repaired
+    indexedlogdatastore: cannot read 'latest': invalid digit found in string
+    Index "node" failed integrity check
+    Rebuilt index "node"
+    Reset latest to 0
   checking commit references
 
 Check the repo is usable again:
@@ -83,6 +83,20 @@
   $ hg cat -r 1 x
   x2
 
+# prefetch new revisions with the daemon
+
+  $ clearcache
+  $ hg debugprefetchdaemon -r 0::1 --once
+  prefetched 2 revisions
+  4 files fetched over 1 fetches - (4 misses, 0.00% hit ratio) over *s (glob) (?)
+  $ hg debugprefetchdaemon -r 0::1 --once --config remotefilelog.prefetchdaemon.maxrevs=1
+  prefetched 1 revisions
+
+  $ hg debugprefetchdaemon --stop
+  asked the prefetch daemon to stop
+  $ hg debugprefetchdaemon -r 0::1 --once
+  prefetched 2 revisions
+
 # prefetch certain files
 
   $ clearcache
@@ -0,0 +1,91 @@
+#chg-compatible
+
+  $ . "$TESTDIR/library.sh"
+  $ setconfig treemanifest.flatcompat=False
+
+Setup the server
+
+  $ hginit master
+  $ cd master
+  $ cat >> .hg/hgrc <<EOF
+  > [extensions]
+  > treemanifest=
+  > [treemanifest]
+  > server=True
+  > treeonly=True
+  > [remotefilelog]
+  > server=True
+  > shallowtrees=True
+  > EOF
+
+Setup two clients, one of which asks for prefetch hints
+
+  $ cd ..
+  $ for name in hints nohints; do
+  >   hgcloneshallow ssh://user@dummy/master $name -q --config treemanifest.treeonly=True
+  >   cat >> $name/.hg/hgrc <<EOF
+  > [extensions]
+  > treemanifest=
+  > [treemanifest]
+  > sendtrees=True
+  > treeonly=True
+  > [remotefilelog]
+  > reponame=treeonlyrepo
+  > EOF
+  > done
+  $ cat >> hints/.hg/hgrc <<EOF
+  > [treemanifest]
+  > pullprefetchhints=True
+  > EOF
+
+Make some commits
+
+  $ cd master
+  $ mkdir dir1 dir2
+  $ echo a > dir1/a
+  $ echo b > dir2/b
+  $ hg commit -Aqm 'add dir1/a and dir2/b'
+  $ echo a >> dir1/a
+  $ hg commit -Aqm 'modify dir1/a'
+
+Without hints there is nothing to prefetch
+
+  $ cd ../hints
+  $ hg debugprefetchtreehints --debug
+  no tree prefetch hints
+
+Pulling saves the hinted trees, which are then prefetched in the background
+
+  $ hg pull -q
+  $ for i in $(seq 1 100); do
+  >   test -f .hg/store/treeprefetchhints || break
+  >   sleep 0.1
+  > done
+  $ test -f .hg/store/treeprefetchhints
+  [1]
+
+So checking out the pulled head doesn't fetch any tree
+
+  $ hg up tip
+  2 files updated, 0 files merged, 0 files removed, 0 files unresolved
+  2 files fetched over * (glob) (?)
+
+Hinted trees that are already present are not fetched again
+
+  $ echo "$(hg log -r tip -T '{manifest}') " > .hg/store/treeprefetchhints
+  $ hg debugprefetchtreehints --debug
+  prefetched 0 of 1 hinted trees
+  $ test -f .hg/store/treeprefetchhints
+  [1]
+
+The client without hints fetches the trees on checkout instead
+
+  $ cd ../nohints
+  $ hg pull -q
+  $ test -f .hg/store/treeprefetchhints
+  [1]
+  $ hg up tip
+  fetching tree '' *, found via * (glob)
+  3 trees fetched over * (glob)
+  2 files updated, 0 files merged, 0 files removed, 0 files unresolved
+  2 files fetched over * (glob) (?)
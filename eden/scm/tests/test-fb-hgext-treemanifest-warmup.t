@@ -0,0 +1,70 @@
+#chg-compatible
+
+  $ . "$TESTDIR/library.sh"
+  $ setconfig treemanifest.flatcompat=False
+
+Setup the server
+
+  $ hginit master
+  $ cd master
+  $ cat >> .hg/hgrc <<EOF
+  > [extensions]
+  > treemanifest=
+  > [treemanifest]
+  > server=True
+  > treeonly=True
+  > [remotefilelog]
+  > server=True
+  > shallowtrees=True
+  > EOF
+  $ mkdir -p dir1 dir2/sub
+  $ echo a > dir1/a
+  $ echo b > dir2/sub/b
+  $ hg commit -Aqm 'add dir1/a and dir2/sub/b'
+
+Setup the client, and check out the commit
+
+  $ cd ..
+  $ hgcloneshallow ssh://user@dummy/master client -q --config treemanifest.treeonly=True
+  $ cd client
+  $ cat >> .hg/hgrc <<EOF
+  > [extensions]
+  > treemanifest=
+  > [treemanifest]
+  > sendtrees=True
+  > treeonly=True
+  > [remotefilelog]
+  > reponame=treeonlyrepo
+  > EOF
+  $ hg up -q tip
+
+With an empty cache, warming up to depth 0 only fetches the root tree
+
+  $ clearcache
+  $ hg pull -q --debug --config treemanifest.warmup=True --config treemanifest.warmupdepth=0 2>&1 | grep "warmed up"
+  warmed up 1 trees of *: fetched 1 in 1 batches (glob)
+
+Without a depth, every tree of the working copy parent is walked
+
+  $ hg pull -q --debug --config treemanifest.warmup=True 2>&1 | grep "warmed up"
+  warmed up 4 trees of *: fetched * in * batches (glob)
+
+Trees already in the store are not fetched again
+
+  $ hg pull -q --debug --config treemanifest.warmup=True 2>&1 | grep "warmed up"
+  warmed up 4 trees of *: fetched 0 in 0 batches (glob)
+
+so walking the working copy doesn't fetch any tree
+
+  $ hg files -r .
+  dir1/a
+  dir2/sub/b
+
+while it does once the cache is gone
+
+  $ clearcache
+  $ hg files -r .
+  fetching tree '' *, found via * (glob)
+  * trees fetched over * (glob)
+  dir1/a
+  dir2/sub/b
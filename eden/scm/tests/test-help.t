@@ -485,6 +485,7 @@ Verbose help for add
       --version               output version information and exit
    -h --help                  display help and exit
       --hidden                consider hidden changesets
+      --offline               do not fetch file or tree data from the server
       --pager TYPE            when to paginate (boolean, always, auto, or never)
                               (default: auto)
 
@@ -935,6 +936,10 @@ Test list of internal help commands
                  builds a repo with a given DAG from scratch in the current
                  empty repo
    debugbundle   lists the contents of a bundle
+   debugcachegc
+                 garbage collect the shared indexedlog cache
+   debugcachestats
+                 report the hit ratios of the stores of the shared cache
    debugcapabilities
                  lists the capabilities of a remote peer
    debugcauserusterror
@@ -1003,6 +1008,8 @@ Test list of internal help commands
    debugindex    dump the contents of an index file
    debugindexdot
                  dump an index DAG as a graphviz dot file
+   debugindexedlogstats
+                 report statistics about indexedlog stores
    debuginitgit  init a repo from a git backend
    debuginstall  test Mercurial installation
    debugknown    test whether node ids are known to a repo
@@ -1033,6 +1040,9 @@ Test list of internal help commands
                  complete part or all of a tracked path
    debugpickmergetool
                  examine which merge tool is chosen for specified file
+   debugprefetchtreehints
+                 prefetch the trees hinted at by the server during the last
+                 pull
    debugpreviewbindag
                  print dag generated by debugbindag
    debugprocesstree
@@ -1053,12 +1063,16 @@ Test list of internal help commands
    debugrebuildfncache
                  rebuild the fncache file
    debugrename   dump rename information
+   debugrepairstore
+                 check the shared file and tree stores for corrupt entries
    debugrevlog   show data and statistics about a revlog
    debugrevspec  parse and apply a revision specification
    debugrunshell
                  run a shell command
    debugsegmentclone
                  clone a repository using segmented changelog
+   debugsegmentpull
+                 pull new master commits into a segmented changelog
    debugsendunbundle
                  Send unbundle wireproto command to a given server
    debugsetparents
@@ -502,5 +502,6 @@ Confirm that --help works (it didn't when we used an alias)
       --version               output version information and exit
    -h --help                  display help and exit
       --hidden                consider hidden changesets
+      --offline               do not fetch file or tree data from the server
       --pager TYPE            when to paginate (boolean, always, auto, or never)
                               (default: auto)
@@ -180,6 +180,7 @@
       --version               output version information and exit
    -h --help                  display help and exit
       --hidden                consider hidden changesets
+      --offline               do not fetch file or tree data from the server
       --pager TYPE            when to paginate (boolean, always, auto, or never)
                               (default: auto)
 
@@ -220,6 +221,7 @@
       --version               output version information and exit
    -h --help                  display help and exit
       --hidden                consider hidden changesets
+      --offline               do not fetch file or tree data from the server
       --pager TYPE            when to paginate (boolean, always, auto, or never)
                               (default: auto)
   